@@ -1,5 +1,8 @@
 use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration as TimeDuration, Instant};
 
 use borsh::BorshSerialize;
@@ -11,7 +14,9 @@ use rand::seq::SliceRandom;
 use rand::SeedableRng;
 
 use crate::error::{Error, ErrorKind, LogTransientStorageError};
-use crate::lightclient::get_epoch_block_producers_view;
+use crate::lightclient::{
+    get_epoch_block_producers_view, get_epoch_info_block_producers_view, light_client_block_hash,
+};
 use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode};
 use crate::types::{
     AcceptedBlock, ApplyTransactionResult, Block, BlockEconomicsConfig, BlockHeader,
@@ -28,30 +33,35 @@ use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
     MaybeEncodedShardChunk, SlashedValidator,
 };
+use near_primitives::epoch_manager::{BlockInfo, EpochSyncData};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{
     combine_hash, merklize, verify_path, Direction, MerklePath, MerklePathItem,
 };
-use near_primitives::receipt::Receipt;
+use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::sharding::{
-    ChunkHash, ChunkHashHeight, ReceiptList, ReceiptProof, ShardChunk, ShardChunkHeader, ShardInfo,
-    ShardProof, StateSyncInfo,
+    ChunkHash, ChunkHashHeight, CongestionInfo, ReceiptList, ReceiptProof, ShardChunk,
+    ShardChunkHeader, ShardInfo, ShardProof, StateSyncInfo,
 };
 use near_primitives::syncing::{
-    get_num_state_parts, ReceiptProofResponse, ReceiptResponse, RootProof,
-    ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1, ShardStateSyncResponseHeaderV2,
-    StateHeaderKey, StatePartKey,
+    get_num_state_parts, EpochSyncFinalizationResponse, ReceiptProofResponse, ReceiptResponse,
+    RootProof, ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1,
+    ShardStateSyncResponseHeaderV2, StateHeaderKey, StatePartKey,
 };
-use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
+use near_primitives::transaction::{Action, ExecutionOutcomeWithIdAndProof};
 use near_primitives::types::{
     AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, ChunkExtra, EpochId, MerkleHash,
-    NumBlocks, ShardId, ValidatorStake,
+    NumBlocks, NumShards, ShardId, StateChangeCause, StateChangesRequest, StateRoot, StoreKey,
+    ValidatorStake,
 };
 use near_primitives::unwrap_or_return;
+use near_primitives::utils::system_account;
 use near_primitives::views::{
     ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
     FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
-    SignedTransactionView,
+    ReceiptDelayReasonView, ReceiptExecutionTraceView, ReceiptInputDataView, ReceiptRefundView,
+    RefundKindView, SignedTransactionView, ValidatorStakeView,
 };
 use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
 
@@ -79,6 +89,10 @@ pub const NUM_EPOCHS_TO_KEEP_STORE_DATA: u64 = 5;
 /// Maximum number of height to go through at each step when cleaning forks during garbage collection.
 const GC_FORK_CLEAN_STEP: u64 = 1000;
 
+/// Maximum number of headers from the last final block of an epoch to the first block of the
+/// next epoch that are served to epoch sync.
+const MAX_EPOCH_SYNC_HEADERS: usize = 100;
+
 enum ApplyChunksMode {
     ThisEpoch,
     NextEpoch,
@@ -186,6 +200,17 @@ pub struct Chain {
     /// Block economics, relevant to changes when new block must be produced.
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
+    /// Builds of the states of the shards split from the shards of the given epoch, running in
+    /// the background.
+    split_state_builds: HashMap<(EpochId, ShardId), SplitStateBuild>,
+}
+
+/// A build of the states of the shards split from a shard, from the state of the shard after the
+/// block `block_hash`, running in a separate thread.
+struct SplitStateBuild {
+    block_hash: CryptoHash,
+    is_done: Arc<AtomicBool>,
+    handle: JoinHandle<Result<HashMap<ShardId, StateRoot>, Error>>,
 }
 
 impl Chain {
@@ -198,7 +223,7 @@ impl Chain {
         let store = ChainStore::new(store, chain_genesis.height);
         let genesis_chunks = genesis_chunks(
             state_roots.clone(),
-            runtime_adapter.num_shards(),
+            runtime_adapter.num_shards(&EpochId::default())?,
             chain_genesis.gas_limit,
             chain_genesis.height,
             chain_genesis.protocol_version,
@@ -222,6 +247,7 @@ impl Chain {
             epoch_length: chain_genesis.epoch_length,
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
+            split_state_builds: HashMap::new(),
         })
     }
 
@@ -235,7 +261,7 @@ impl Chain {
         let mut store = ChainStore::new(store, chain_genesis.height);
         let genesis_chunks = genesis_chunks(
             state_roots.clone(),
-            runtime_adapter.num_shards(),
+            runtime_adapter.num_shards(&EpochId::default())?,
             chain_genesis.gas_limit,
             chain_genesis.height,
             chain_genesis.protocol_version,
@@ -334,6 +360,7 @@ impl Chain {
             epoch_length: chain_genesis.epoch_length,
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
+            split_state_builds: HashMap::new(),
         })
     }
 
@@ -635,11 +662,17 @@ impl Chain {
         genesis_block: &Block,
         block: &Block,
     ) -> Result<(), Error> {
-        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+        for chunk_header in block.chunks().iter() {
             if chunk_header.height_created() == genesis_block.header().height() {
                 // Special case: genesis chunks can be in non-genesis blocks and don't have a signature
                 // We must verify that content matches and signature is empty.
-                let genesis_chunk = &genesis_block.chunks()[shard_id];
+                // After the shards are split, a genesis chunk of the parent shard can be the
+                // previous chunk of a split shard, so it's looked up by its own shard id.
+                let genesis_chunk = genesis_block
+                    .chunks()
+                    .get(chunk_header.shard_id() as usize)
+                    .cloned()
+                    .ok_or_else(|| Error::from(ErrorKind::InvalidChunk))?;
                 if genesis_chunk.chunk_hash() != chunk_header.chunk_hash()
                     || genesis_chunk.signature() != chunk_header.signature()
                 {
@@ -958,8 +991,8 @@ impl Chain {
         block: &Block,
     ) -> Result<(), Error> {
         let prev_hash = *block.header().prev_hash();
-        let shards_to_dl = self.get_shards_to_dl_state(me, &prev_hash);
-        let prev_block = self.get_block(&prev_hash)?;
+        let shards_to_dl = self.get_shards_to_dl_state(me, &prev_hash)?;
+        let prev_block = self.get_block(&prev_hash)?.clone();
 
         debug!(target: "chain", "Downloading state for {:?}, I'm {:?}", shards_to_dl, me);
 
@@ -1009,6 +1042,8 @@ impl Chain {
                 chain_update.chain_store_update.save_block_height_processed(block_height);
                 chain_update.commit()?;
 
+                self.split_states(&block)?;
+
                 if needs_to_start_fetching_state {
                     debug!(target: "chain", "Downloading state for block {}", block.hash());
                     self.start_downloading_state(me, &block)?;
@@ -1123,22 +1158,190 @@ impl Chain {
         &self,
         me: &Option<AccountId>,
         parent_hash: &CryptoHash,
-    ) -> Vec<ShardId> {
-        (0..self.runtime_adapter.num_shards())
-            .filter(|shard_id| {
-                self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
-                    parent_hash,
-                    *shard_id,
-                    true,
-                ) && !self.runtime_adapter.cares_about_shard(
-                    me.as_ref(),
-                    parent_hash,
-                    *shard_id,
-                    true,
-                )
-            })
-            .collect()
+    ) -> Result<Vec<ShardId>, Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
+        let mut shards_to_dl = vec![];
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
+            if will_care_about_shard_or_split_shards(
+                &*self.runtime_adapter,
+                me,
+                parent_hash,
+                shard_id,
+            )? && !self.runtime_adapter.cares_about_shard(
+                me.as_ref(),
+                parent_hash,
+                shard_id,
+                true,
+            ) {
+                shards_to_dl.push(shard_id);
+            }
+        }
+        Ok(shards_to_dl)
+    }
+
+    /// Keeps the states of the shards split from the shards the block was applied for up to date
+    /// in the epoch before the shards are split. They are built in the background from the state
+    /// after a final block of the epoch, and then updated with the state changes of every block
+    /// since, so that they are ready after the last block of the epoch.
+    fn split_states(&mut self, block: &Block) -> Result<(), Error> {
+        let header = block.header();
+        if !will_shards_split(&*self.runtime_adapter, header)? {
+            return Ok(());
+        }
+        let is_last_block = self.runtime_adapter.is_next_block_epoch_start(header.hash())?;
+        for shard_id in 0..block.chunks().len() as ShardId {
+            if self.store.get_chunk_extra(header.hash(), shard_id).is_err()
+                || self.store.get_split_state_roots(header.hash(), shard_id).is_ok()
+            {
+                continue;
+            }
+            let key = (header.epoch_id().clone(), shard_id);
+            self.finish_split_state_build(&key, is_last_block)?;
+            if self.split_state_builds.contains_key(&key) {
+                continue;
+            }
+            match self.get_blocks_without_split_states(header, shard_id)? {
+                Some(block_hashes) => self.update_split_states(&block_hashes, shard_id)?,
+                None if is_last_block => {
+                    let state_root =
+                        self.store.get_chunk_extra(header.hash(), shard_id)?.state_root;
+                    let state_roots = self.runtime_adapter.build_split_states(
+                        header.hash(),
+                        shard_id,
+                        &state_root,
+                    )?;
+                    let mut chain_store_update = self.store.store_update();
+                    chain_store_update.save_split_state_roots(header.hash(), shard_id, state_roots);
+                    chain_store_update.commit()?;
+                }
+                None => self.start_split_state_build(header, shard_id)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts building the states of the shards split from the shard from its state after the
+    /// last final block, if it's in the same epoch as the given block.
+    fn start_split_state_build(
+        &mut self,
+        header: &BlockHeader,
+        shard_id: ShardId,
+    ) -> Result<(), Error> {
+        let block_hash = *header.last_final_block();
+        if block_hash == CryptoHash::default()
+            || self.store.get_block_header(&block_hash)?.epoch_id() != header.epoch_id()
+        {
+            return Ok(());
+        }
+        let state_root = match self.store.get_chunk_extra(&block_hash, shard_id) {
+            Ok(chunk_extra) => chunk_extra.state_root,
+            Err(_) => return Ok(()),
+        };
+        debug!(target: "chain", "Building split states of shard {} from block {}", shard_id, block_hash);
+        let runtime_adapter = self.runtime_adapter.clone();
+        let is_done = Arc::new(AtomicBool::new(false));
+        let is_build_done = is_done.clone();
+        let handle = thread::spawn(move || {
+            let result = runtime_adapter.build_split_states(&block_hash, shard_id, &state_root);
+            is_build_done.store(true, Ordering::SeqCst);
+            result
+        });
+        self.split_state_builds.insert(
+            (header.epoch_id().clone(), shard_id),
+            SplitStateBuild { block_hash, is_done, handle },
+        );
+        Ok(())
+    }
+
+    /// Saves the split state roots of the build running in the background, if it's done or if
+    /// `wait` is set. A failed build is dropped, the states are then built synchronously after
+    /// the last block of the epoch.
+    fn finish_split_state_build(
+        &mut self,
+        key: &(EpochId, ShardId),
+        wait: bool,
+    ) -> Result<(), Error> {
+        match self.split_state_builds.get(key) {
+            Some(build) if wait || build.is_done.load(Ordering::SeqCst) => {}
+            _ => return Ok(()),
+        }
+        let build = self.split_state_builds.remove(key).unwrap();
+        let result = build.handle.join().unwrap_or_else(|_| {
+            Err(ErrorKind::Other("Building split states panicked".to_string()).into())
+        });
+        match result {
+            Ok(state_roots) => {
+                let mut chain_store_update = self.store.store_update();
+                chain_store_update.save_split_state_roots(&build.block_hash, key.1, state_roots);
+                chain_store_update.commit()?;
+            }
+            Err(err) => {
+                error!(target: "chain", "Failed to build split states of shard {}: {}", key.1, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the blocks from the given block back to the first block of the same epoch whose
+    /// previous block has split states of the shard, or `None` if there is no such block.
+    fn get_blocks_without_split_states(
+        &mut self,
+        header: &BlockHeader,
+        shard_id: ShardId,
+    ) -> Result<Option<Vec<CryptoHash>>, Error> {
+        let mut block_hashes = vec![*header.hash()];
+        loop {
+            let prev_hash = *self.store.get_block_header(block_hashes.last().unwrap())?.prev_hash();
+            if self.store.get_block_header(&prev_hash)?.epoch_id() != header.epoch_id() {
+                return Ok(None);
+            }
+            if self.store.get_split_state_roots(&prev_hash, shard_id).is_ok() {
+                return Ok(Some(block_hashes));
+            }
+            block_hashes.push(prev_hash);
+        }
+    }
+
+    /// Applies the state changes of the shard in the given blocks, from the newest, to the split
+    /// states after their previous blocks. The trie changes are saved to be garbage collected with
+    /// the blocks.
+    fn update_split_states(
+        &mut self,
+        block_hashes: &[CryptoHash],
+        shard_id: ShardId,
+    ) -> Result<(), Error> {
+        let tries = self.runtime_adapter.get_tries();
+        for block_hash in block_hashes.iter().rev() {
+            let prev_hash = *self.store.get_block_header(block_hash)?.prev_hash();
+            let split_state_roots = self.store.get_split_state_roots(&prev_hash, shard_id)?.clone();
+            let state_root = self.store.get_chunk_extra(block_hash, shard_id)?.state_root;
+            let changes = self.store.get_state_changes_for_split_states(block_hash, shard_id)?;
+            let trie_changes = self.runtime_adapter.apply_state_changes_to_split_states(
+                block_hash,
+                shard_id,
+                &state_root,
+                &split_state_roots,
+                &changes,
+            )?;
+            let mut store_update = self.store.owned_store().store_update();
+            for (split_shard_id, split_trie_changes) in trie_changes.iter() {
+                tries
+                    .apply_insertions(split_trie_changes, *split_shard_id, &mut store_update)
+                    .map_err(|err| ErrorKind::Other(err.to_string()))?;
+            }
+            let state_roots = trie_changes
+                .iter()
+                .map(|(split_shard_id, split_trie_changes)| {
+                    (*split_shard_id, split_trie_changes.new_root)
+                })
+                .collect();
+            let mut chain_store_update = self.store.store_update();
+            chain_store_update.merge(store_update);
+            chain_store_update.save_split_state_roots(block_hash, shard_id, state_roots);
+            chain_store_update.save_split_state_trie_changes(block_hash, shard_id, trie_changes);
+            chain_store_update.commit()?;
+        }
+        Ok(())
     }
 
     /// Check if any block with missing chunk is ready to be processed
@@ -1148,7 +1351,7 @@ impl Chain {
         prev_hash: CryptoHash,
         block_accepted: F,
         block_misses_chunks: F2,
-        on_challenge: F3,
+        mut on_challenge: F3,
     ) where
         F: Copy + FnMut(AcceptedBlock) -> (),
         F2: Copy + FnMut(Vec<ShardChunkHeader>) -> (),
@@ -1171,8 +1374,11 @@ impl Chain {
                         debug!(target: "chain", "Block with missing chunks is accepted; me: {:?}", me);
                         new_blocks_accepted.push(block_hash);
                     }
-                    Err(_) => {
+                    Err(err) => {
                         debug!(target: "chain", "Block with missing chunks is declined; me: {:?}", me);
+                        if let Some(challenge_body) = err.challenge_body() {
+                            on_challenge(challenge_body);
+                        }
                     }
                 }
             }
@@ -1196,7 +1402,7 @@ impl Chain {
         prev_hash: CryptoHash,
         block_accepted: F,
         block_misses_chunks: F2,
-        on_challenge: F3,
+        mut on_challenge: F3,
     ) -> Option<Tip>
     where
         F: Copy + FnMut(AcceptedBlock) -> (),
@@ -1231,8 +1437,11 @@ impl Chain {
                             maybe_new_head = maybe_tip;
                             queue.push(block_hash);
                         }
-                        Err(_) => {
+                        Err(err) => {
                             debug!(target: "chain", "Orphan declined");
+                            if let Some(challenge_body) = err.challenge_body() {
+                                on_challenge(challenge_body);
+                            }
                         }
                     }
                 }
@@ -1258,7 +1467,260 @@ impl Chain {
         shard_id: ShardId,
         last_height_included: BlockHeight,
     ) -> Result<ReceiptResponse, Error> {
-        self.store.get_outgoing_receipts_for_shard(prev_block_hash, shard_id, last_height_included)
+        get_outgoing_receipts_for_shard(
+            &mut self.store,
+            &*self.runtime_adapter,
+            &prev_block_hash,
+            shard_id,
+            last_height_included,
+        )
+    }
+
+    /// Returns the headers and epoch manager records that a node bootstrapping with epoch sync
+    /// needs to continue syncing from the first block of the given epoch.
+    pub fn get_epoch_sync_finalization(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochSyncFinalizationResponse, Error> {
+        let head = self.head()?;
+        let block_in_epoch = if &head.epoch_id == epoch_id {
+            head.last_block_hash
+        } else {
+            light_client_block_hash(self.store.get_epoch_light_client_block(&epoch_id.0)?)
+        };
+        let epoch_start_height = self.runtime_adapter.get_epoch_start_height(&block_in_epoch)?;
+        let epoch_first_header = self.get_header_by_height(epoch_start_height)?.clone();
+        let prev_header = self.get_block_header(epoch_first_header.prev_hash())?.clone();
+        let prev_epoch_id = prev_header.epoch_id().clone();
+        if epoch_first_header.epoch_id() != epoch_id || &prev_epoch_id == epoch_id {
+            return Err(ErrorKind::Other(format!(
+                "Can't find the first block of epoch {:?}",
+                epoch_id
+            ))
+            .into());
+        }
+        let next_block_hash = *self.store.get_next_block_hash(epoch_first_header.hash())?;
+        let next_header = self.get_block_header(&next_block_hash)?.clone();
+        let epoch_sync_data =
+            self.runtime_adapter.get_epoch_sync_data(prev_header.hash())?.ok_or_else(|| {
+                ErrorKind::Other(format!("No epoch sync data for epoch {:?}", epoch_id))
+            })?;
+
+        let final_block_hash =
+            light_client_block_hash(self.store.get_epoch_light_client_block(&prev_epoch_id.0)?);
+        let mut headers = vec![next_header, epoch_first_header];
+        let mut header = prev_header;
+        loop {
+            let (hash, prev_hash) = (*header.hash(), *header.prev_hash());
+            headers.push(header);
+            if hash == final_block_hash {
+                break;
+            }
+            if headers.len() >= MAX_EPOCH_SYNC_HEADERS {
+                return Err(ErrorKind::Other(format!(
+                    "Too many headers after the last final block of epoch {:?}",
+                    prev_epoch_id
+                ))
+                .into());
+            }
+            header = self.get_block_header(&prev_hash)?.clone();
+        }
+        headers.reverse();
+
+        let first_block_merkle_tree = self.store.get_block_merkle_tree(&final_block_hash)?.clone();
+        Ok(EpochSyncFinalizationResponse { headers, first_block_merkle_tree, epoch_sync_data })
+    }
+
+    /// Validates the epoch sync finalization response for the given epoch and saves the headers
+    /// and epoch manager records from it, so that header sync and state sync can continue from
+    /// the first block of the epoch.
+    ///
+    /// # Arguments
+    ///  * `prev_block_producers` - verified ordered block producers of the previous epoch
+    ///  * `light_client_block` - verified light client block of the previous epoch, which proves
+    ///                   the block producers of the given epoch
+    ///
+    /// The headers are linked to the last final block of the previous epoch by their hashes and
+    /// each of them must be approved by more than 2/3 of the stake of its approvers, the last one
+    /// approving the first block of the epoch. The epoch manager records must match the hash
+    /// committed in the first block of the epoch and the block infos must match the headers.
+    pub fn apply_epoch_sync_finalization(
+        &mut self,
+        epoch_id: &EpochId,
+        prev_block_producers: &[ValidatorStakeView],
+        light_client_block: &LightClientBlockView,
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<(), Error> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::InvalidEpochSyncResponse(reason.to_string()).into()
+        };
+        let EpochSyncFinalizationResponse { mut headers, first_block_merkle_tree, epoch_sync_data } =
+            response;
+        let block_producers = light_client_block.next_bps.clone().unwrap_or_default();
+
+        if headers.len() < 3 {
+            return Err(invalid("not enough headers"));
+        }
+        let final_block_hash = light_client_block_hash(light_client_block);
+        if headers[0].hash() != &final_block_hash
+            || headers[1].hash()
+                != &combine_hash(light_client_block.next_block_inner_hash, final_block_hash)
+        {
+            return Err(invalid(
+                "headers don't start at the last final block of the previous epoch",
+            ));
+        }
+        if headers.windows(2).any(|pair| pair[1].prev_hash() != pair[0].hash()) {
+            return Err(invalid("headers are not consecutive"));
+        }
+        let prev_epoch_id = headers[0].epoch_id().clone();
+        let next_header = headers.pop().unwrap();
+        let (epoch_first_header, prev_epoch_headers) = headers.split_last().unwrap();
+        if epoch_first_header.epoch_id() != epoch_id
+            || next_header.epoch_id() != epoch_id
+            || &prev_epoch_id == epoch_id
+            || prev_epoch_headers.iter().any(|header| header.epoch_id() != &prev_epoch_id)
+        {
+            return Err(invalid("headers don't end at the first block of the epoch"));
+        }
+        for header in headers.iter().skip(2).chain(iter::once(&next_header)) {
+            let producers = if header.epoch_id() == epoch_id {
+                &block_producers[..]
+            } else {
+                prev_block_producers
+            };
+            if !producers
+                .iter()
+                .any(|bp| header.signature().verify(header.hash().as_ref(), &bp.public_key))
+            {
+                return Err(ErrorKind::InvalidSignature.into());
+            }
+        }
+        if &first_block_merkle_tree.root() != headers[0].block_merkle_root() {
+            return Err(ErrorKind::InvalidBlockMerkleRoot.into());
+        }
+        if epoch_first_header.epoch_sync_data_hash() != Some(epoch_sync_data.hash()) {
+            return Err(ErrorKind::InvalidEpochSyncDataHash.into());
+        }
+
+        let EpochSyncData {
+            prev_epoch_block_infos,
+            prev_epoch_first_block_info,
+            prev_epoch_info,
+            epoch_info,
+            next_epoch_info,
+        } = epoch_sync_data;
+        if get_epoch_info_block_producers_view(&prev_epoch_info) != prev_block_producers
+            || get_epoch_info_block_producers_view(&epoch_info) != block_producers
+        {
+            return Err(invalid("epoch infos don't match the verified block producers"));
+        }
+        let next_block_producers = get_epoch_info_block_producers_view(&next_epoch_info);
+        if &Chain::compute_bp_hash_inner(
+            next_block_producers.into_iter().map(Into::into).collect(),
+        )? != epoch_first_header.next_bp_hash()
+        {
+            return Err(ErrorKind::InvalidNextBPHash.into());
+        }
+
+        // Block infos don't have hashes, they are linked through their previous hashes from the
+        // last block of the previous epoch.
+        let mut block_infos = Vec::with_capacity(prev_epoch_block_infos.len() + 1);
+        let mut block_hash = *epoch_first_header.prev_hash();
+        for block_info in prev_epoch_block_infos.into_iter().rev() {
+            let prev_hash = block_info.prev_hash;
+            block_infos.push((block_hash, block_info));
+            block_hash = prev_hash;
+        }
+        block_infos.reverse();
+        let headers_by_hash =
+            headers.iter().map(|header| (*header.hash(), header)).collect::<HashMap<_, _>>();
+        let block_info_by_hash =
+            block_infos.iter().map(|(hash, info)| (*hash, info)).collect::<HashMap<_, _>>();
+        let prev_epoch_first_block = prev_epoch_first_block_info.epoch_first_block;
+        if prev_epoch_first_block_info.epoch_id != prev_epoch_id
+            || prev_epoch_first_block_info.height > headers[0].height()
+        {
+            return Err(invalid("block info of the epoch first block doesn't match the headers"));
+        }
+        for header in prev_epoch_headers.iter() {
+            let block_info = block_info_by_hash
+                .get(header.hash())
+                .ok_or_else(|| invalid("missing block info"))?;
+            let last_finalized_height_matches =
+                match headers_by_hash.get(&block_info.last_final_block_hash) {
+                    Some(final_header) => final_header.height() == block_info.last_finalized_height,
+                    None => block_info.last_finalized_height < headers[0].height(),
+                };
+            let slashed_match = match block_info_by_hash.get(header.prev_hash()) {
+                Some(prev_block_info) if block_info.epoch_first_block != *header.hash() => {
+                    slashed_match_parent(block_info, prev_block_info, header)
+                }
+                _ => true,
+            };
+            if !block_info_matches_header(block_info, header)
+                || !last_finalized_height_matches
+                || !slashed_match
+                || block_info.epoch_first_block != prev_epoch_first_block
+            {
+                return Err(invalid("block infos don't match the headers"));
+            }
+        }
+        let last_finalized_height = block_info_by_hash
+            .get(epoch_first_header.last_final_block())
+            .ok_or_else(|| invalid("missing block info of the last final block"))?
+            .height;
+
+        block_infos.push((prev_epoch_first_block, prev_epoch_first_block_info));
+        self.runtime_adapter.save_epoch_sync_data(
+            block_infos,
+            vec![
+                (prev_epoch_id, prev_epoch_info),
+                (epoch_id.clone(), epoch_info),
+                (epoch_first_header.next_epoch_id().clone(), next_epoch_info),
+            ],
+        )?;
+        self.runtime_adapter.add_validator_proposals(BlockHeaderInfo::new(
+            epoch_first_header,
+            last_finalized_height,
+        ))?;
+
+        // With the epoch manager records in place, the approvals of each header are checked the
+        // same way as in `validate_header`.
+        for (prev_header, header) in
+            headers.iter().zip(headers.iter().skip(1).chain(iter::once(&next_header)))
+        {
+            if !self.runtime_adapter.verify_approval(
+                prev_header.hash(),
+                prev_header.height(),
+                header.height(),
+                header.approvals(),
+            )? {
+                return Err(ErrorKind::InvalidApprovals.into());
+            }
+            let stakes = self
+                .runtime_adapter
+                .get_epoch_block_approvers_ordered(prev_header.hash())?
+                .iter()
+                .map(|x| (x.stake_this_epoch, x.stake_next_epoch))
+                .collect();
+            if !Doomslug::can_approved_block_be_produced(
+                DoomslugThresholdMode::TwoThirds,
+                header.approvals(),
+                &stakes,
+            ) {
+                return Err(ErrorKind::NotEnoughApprovals.into());
+            }
+        }
+
+        let tip = Tip::from_header(epoch_first_header);
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.save_epoch_sync_headers(headers, first_block_merkle_tree)?;
+        chain_store_update.commit()?;
+
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.save_header_head_if_not_challenged(&tip)?;
+        chain_store_update.commit()
     }
 
     pub fn get_state_response_header(
@@ -1319,9 +1781,12 @@ impl Chain {
             self.get_header_on_chain_by_height(&sync_hash, chunk_header.height_included())?.clone();
 
         // Collecting the `prev` state.
-        let (prev_chunk_header, prev_chunk_proof, prev_chunk_height_included) = match self
-            .get_block(block_header.prev_hash())
-        {
+        let (
+            prev_chunk_header,
+            prev_chunk_proof,
+            prev_chunk_height_included,
+            prev_block_chunk_headers,
+        ) = match self.get_block(block_header.prev_hash()) {
             Ok(prev_block) => {
                 if shard_id as usize >= prev_block.chunks().len() {
                     return Err(
@@ -1343,12 +1808,17 @@ impl Chain {
                 let prev_chunk_proof = prev_chunk_proofs[shard_id as usize].clone();
                 let prev_chunk_height_included = prev_chunk_header.height_included();
 
-                (Some(prev_chunk_header), Some(prev_chunk_proof), prev_chunk_height_included)
+                (
+                    Some(prev_chunk_header),
+                    Some(prev_chunk_proof),
+                    prev_chunk_height_included,
+                    prev_block.chunks().iter().cloned().collect(),
+                )
             }
             Err(e) => match e.kind() {
                 ErrorKind::DBNotFoundErr(_) => {
                     if block_header.prev_hash() == &CryptoHash::default() {
-                        (None, None, 0)
+                        (None, None, 0, vec![])
                     } else {
                         return Err(e);
                     }
@@ -1407,7 +1877,7 @@ impl Chain {
                 let prev_chunk_header =
                     prev_chunk_header.and_then(|prev_header| match prev_header {
                         ShardChunkHeader::V1(header) => Some(header),
-                        ShardChunkHeader::V2(_) => None,
+                        ShardChunkHeader::V2(_) | ShardChunkHeader::V3(_) => None,
                     });
                 ShardStateSyncResponseHeader::V1(ShardStateSyncResponseHeaderV1 {
                     chunk,
@@ -1426,6 +1896,7 @@ impl Chain {
                     chunk_proof,
                     prev_chunk_header,
                     prev_chunk_proof,
+                    prev_block_chunk_headers,
                     incoming_receipts_proofs,
                     root_proofs,
                     state_root_node,
@@ -1509,7 +1980,7 @@ impl Chain {
         let prev_chunk_header = shard_state_header.cloned_prev_chunk_header();
 
         // 1-2. Checking chunk validity
-        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter) {
+        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter)? {
             byzantine_assert!(false);
             return Err(ErrorKind::Other(
                 "set_shard_state failed: chunk header proofs are invalid".into(),
@@ -1565,6 +2036,24 @@ impl Chain {
             _ =>
                 return Err(ErrorKind::Other("set_shard_state failed: `prev_chunk_header` and `prev_chunk_proof` must either both be present or both absent".into()).into())
         };
+        // 3c. Checking that `prev_block_chunk_headers` are the chunks of the block at height before
+        // chunk.height_included - the congestion `chunk` is applied with is derived from them
+        if let Some(prev_block_chunk_headers) = shard_state_header.prev_block_chunk_headers() {
+            let is_valid = if prev_chunk_header.is_some() {
+                let prev_block_header = self.get_block_header(block_header.prev_hash())?;
+                Block::compute_chunk_headers_root(prev_block_chunk_headers.iter()).0
+                    == *prev_block_header.chunk_headers_root()
+            } else {
+                prev_block_chunk_headers.is_empty()
+            };
+            if !is_valid {
+                byzantine_assert!(false);
+                return Err(ErrorKind::Other(
+                    "set_shard_state failed: prev block chunk headers are invalid".into(),
+                )
+                .into());
+            }
+        }
 
         // 4. Proving incoming receipts validity
         // 4a. Checking len of proofs
@@ -1706,6 +2195,23 @@ impl Chain {
         Ok(())
     }
 
+    /// Returns whether each state part of the shard was already downloaded and validated. The
+    /// parts are kept across restarts, so the interrupted state sync downloads only the others.
+    pub fn get_downloaded_state_parts(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+    ) -> Result<Vec<bool>, Error> {
+        let store = self.store.owned_store();
+        (0..num_parts)
+            .map(|part_id| {
+                let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+                Ok(store.exists(ColStateParts, &key)?)
+            })
+            .collect()
+    }
+
     pub fn set_state_finalize(
         &mut self,
         shard_id: ShardId,
@@ -1742,6 +2248,14 @@ impl Chain {
                 break;
             }
         }
+
+        // If the shards are split at the start of the epoch of `sync_hash`, the synced states
+        // are of the parent shards, so the states of the split shards are built from them.
+        let prev_hash = *self.get_block_header(&sync_hash)?.prev_hash();
+        if get_split_shard_layout(&*self.runtime_adapter, &prev_hash)?.is_some() {
+            let prev_block = self.get_block(&prev_hash)?.clone();
+            self.split_states(&prev_block)?;
+        }
         Ok(())
     }
 
@@ -1781,6 +2295,7 @@ impl Chain {
         let mut chain_update = self.chain_update();
         chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::NextEpoch)?;
         chain_update.commit()?;
+        self.split_states(&block)?;
 
         affected_blocks.insert(*block.header().hash());
 
@@ -1806,6 +2321,7 @@ impl Chain {
                 chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::NextEpoch)?;
 
                 chain_update.commit()?;
+                self.split_states(&block)?;
 
                 affected_blocks.insert(*block.header().hash());
                 queue.push(next_block_hash);
@@ -1937,6 +2453,217 @@ impl Chain {
         Ok(FinalExecutionOutcomeWithReceiptView { final_outcome, receipts })
     }
 
+    /// Explains the execution of every receipt of the transaction: the blocks it was created and
+    /// executed in, why it was delayed, which data it waited for, which refunds it generated and
+    /// which state changes it caused. The trace is reconstructed from the stored outcomes,
+    /// receipts and state changes, so it is only complete on nodes that still keep them.
+    pub fn get_receipts_execution_trace(
+        &mut self,
+        final_outcome: &FinalExecutionOutcomeView,
+    ) -> Result<Vec<ReceiptExecutionTraceView>, Error> {
+        let mut created_in = HashMap::new();
+        for outcome in std::iter::once(&final_outcome.transaction_outcome)
+            .chain(final_outcome.receipts_outcome.iter())
+        {
+            for receipt_id in outcome.outcome.receipt_ids.iter() {
+                created_in.insert(*receipt_id, outcome.block_hash);
+            }
+        }
+        let mut executed_in = HashMap::new();
+        let mut receipts = HashMap::new();
+        // Maps a data id to the receipt whose result is sent as this data.
+        let mut data_producers = HashMap::new();
+        for outcome in final_outcome.receipts_outcome.iter() {
+            executed_in.insert(outcome.id, outcome.block_hash);
+            if let Some(receipt) = self.store.get_receipt(&outcome.id)?.cloned() {
+                if let ReceiptEnum::Action(action_receipt) = &receipt.receipt {
+                    for data_receiver in action_receipt.output_data_receivers.iter() {
+                        data_producers.insert(data_receiver.data_id, outcome.id);
+                    }
+                }
+                receipts.insert(outcome.id, receipt);
+            }
+        }
+
+        final_outcome
+            .receipts_outcome
+            .iter()
+            .map(|outcome| {
+                self.get_receipt_execution_trace(
+                    outcome,
+                    &created_in,
+                    &executed_in,
+                    &receipts,
+                    &data_producers,
+                )
+            })
+            .collect()
+    }
+
+    fn get_receipt_execution_trace(
+        &mut self,
+        outcome: &ExecutionOutcomeWithIdView,
+        created_in: &HashMap<CryptoHash, CryptoHash>,
+        executed_in: &HashMap<CryptoHash, CryptoHash>,
+        receipts: &HashMap<CryptoHash, Receipt>,
+        data_producers: &HashMap<CryptoHash, CryptoHash>,
+    ) -> Result<ReceiptExecutionTraceView, Error> {
+        let receipt_id = outcome.id;
+        let executed_in_block_hash = outcome.block_hash;
+        let executed_at_block_height = self.get_block_header(&executed_in_block_hash)?.height();
+        let created_in_block_hash =
+            created_in.get(&receipt_id).cloned().unwrap_or(executed_in_block_hash);
+        let created_at_block_height = self.get_block_header(&created_in_block_hash)?.height();
+
+        let input_data: Vec<_> = match receipts.get(&receipt_id).map(|receipt| &receipt.receipt) {
+            Some(ReceiptEnum::Action(action_receipt)) => action_receipt
+                .input_data_ids
+                .iter()
+                .map(|data_id| ReceiptInputDataView {
+                    data_id: *data_id,
+                    producer_receipt_id: data_producers.get(data_id).cloned(),
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        // A receipt with input data can't be executed before all of its inputs are produced.
+        let mut ready_at_block_height = created_at_block_height;
+        for producer_receipt_id in input_data.iter().filter_map(|data| data.producer_receipt_id) {
+            if let Some(block_hash) = executed_in.get(&producer_receipt_id) {
+                let height = self.get_block_header(block_hash)?.height();
+                ready_at_block_height = std::cmp::max(ready_at_block_height, height);
+            }
+        }
+        let mut delay_reasons = vec![];
+        let waiting_blocks = ready_at_block_height - created_at_block_height;
+        if waiting_blocks > 0 {
+            delay_reasons
+                .push(ReceiptDelayReasonView::WaitingForInputData { blocks: waiting_blocks });
+        }
+
+        // Once the receipt is ready it is expected to be executed in the next block, so every
+        // block in between is either skipped, has no chunk for the receiver's shard, or had a
+        // chunk that used up its gas limit. A chunk that had gas left processed the whole delayed
+        // receipts queue, so the delay in such blocks can't be explained from the chain.
+        let expected_blocks = executed_at_block_height.saturating_sub(ready_at_block_height + 1);
+        let mut missing_chunks = 0;
+        let mut delayed = 0;
+        let mut unknown = 0;
+        let mut prev_hash = *self.get_block_header(&executed_in_block_hash)?.prev_hash();
+        let mut remaining_blocks = expected_blocks;
+        while remaining_blocks > 0 {
+            let header = self.get_block_header(&prev_hash)?.clone();
+            if header.height() <= ready_at_block_height {
+                break;
+            }
+            let shard_id = self
+                .runtime_adapter
+                .account_id_to_shard_id(&outcome.outcome.executor_id, header.epoch_id())?;
+            if header.chunk_mask().get(shard_id as usize).cloned().unwrap_or(false) {
+                match self.store.get_chunk_extra(header.hash(), shard_id) {
+                    Ok(chunk_extra) if chunk_extra.gas_used >= chunk_extra.gas_limit => {
+                        delayed += 1
+                    }
+                    Ok(_) => unknown += 1,
+                    // The node doesn't track the shard of the receiver.
+                    Err(e) => match e.kind() {
+                        ErrorKind::DBNotFoundErr(_) => unknown += 1,
+                        _ => return Err(e),
+                    },
+                }
+            } else {
+                missing_chunks += 1;
+            }
+            prev_hash = *header.prev_hash();
+            remaining_blocks -= 1;
+        }
+        let skipped = expected_blocks - missing_chunks - delayed - unknown;
+        if skipped > 0 {
+            delay_reasons.push(ReceiptDelayReasonView::SkippedBlocks { blocks: skipped });
+        }
+        if missing_chunks > 0 {
+            delay_reasons.push(ReceiptDelayReasonView::MissingChunks { blocks: missing_chunks });
+        }
+        if delayed > 0 {
+            delay_reasons.push(ReceiptDelayReasonView::DelayedReceiptsQueue { blocks: delayed });
+        }
+        if unknown > 0 {
+            delay_reasons.push(ReceiptDelayReasonView::Unknown { blocks: unknown });
+        }
+
+        let mut refunds = vec![];
+        for child_receipt_id in outcome.outcome.receipt_ids.iter() {
+            let child_receipt = match self.store.get_receipt(child_receipt_id)? {
+                Some(receipt) if receipt.predecessor_id == system_account() => receipt,
+                _ => continue,
+            };
+            if let ReceiptEnum::Action(action_receipt) = &child_receipt.receipt {
+                // Gas refunds keep the original signer to restore the access key allowance.
+                let kind = if action_receipt.signer_id == system_account() {
+                    RefundKindView::Balance
+                } else {
+                    RefundKindView::Gas
+                };
+                let amount = action_receipt
+                    .actions
+                    .iter()
+                    .map(|action| match action {
+                        Action::Transfer(transfer) => transfer.deposit,
+                        _ => 0,
+                    })
+                    .sum();
+                refunds.push(ReceiptRefundView {
+                    receipt_id: *child_receipt_id,
+                    receiver_id: child_receipt.receiver_id.clone(),
+                    amount,
+                    kind,
+                });
+            }
+        }
+
+        let account_ids = vec![outcome.outcome.executor_id.clone()];
+        let state_changes_requests = vec![
+            StateChangesRequest::AccountChanges { account_ids: account_ids.clone() },
+            StateChangesRequest::AllAccessKeyChanges { account_ids: account_ids.clone() },
+            StateChangesRequest::ContractCodeChanges { account_ids: account_ids.clone() },
+            StateChangesRequest::DataChanges { account_ids, key_prefix: StoreKey::from(vec![]) },
+        ];
+        let mut state_changes = vec![];
+        for state_changes_request in state_changes_requests.iter() {
+            state_changes.extend(
+                self.store
+                    .get_state_changes(&executed_in_block_hash, state_changes_request)?
+                    .into_iter()
+                    .filter(|state_change| match &state_change.cause {
+                        StateChangeCause::ActionReceiptProcessingStarted { receipt_hash }
+                        | StateChangeCause::ActionReceiptGasReward { receipt_hash }
+                        | StateChangeCause::ReceiptProcessing { receipt_hash }
+                        | StateChangeCause::PostponedReceipt { receipt_hash } => {
+                            *receipt_hash == receipt_id
+                        }
+                        _ => false,
+                    })
+                    .map(Into::into),
+            );
+        }
+
+        Ok(ReceiptExecutionTraceView {
+            receipt_id,
+            created_in_block_hash,
+            created_at_block_height,
+            executed_in_block_hash,
+            executed_at_block_height,
+            delay_blocks: waiting_blocks + expected_blocks,
+            delay_reasons,
+            input_data,
+            gas_burnt: outcome.outcome.gas_burnt,
+            tokens_burnt: outcome.outcome.tokens_burnt,
+            refunds,
+            state_changes,
+        })
+    }
+
     /// Find a validator to forward transactions to
     pub fn find_chunk_producer_for_forwarding(
         &self,
@@ -2228,6 +2955,16 @@ impl Chain {
         self.store.get_chunk(chunk_hash)
     }
 
+    /// Gets the sizes of the delayed receipts queues advertised by the chunks of the given block,
+    /// which the chunks of the next block are applied with.
+    pub fn get_congestion_info(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<CongestionInfo, Error> {
+        let block = self.get_block(block_hash)?.clone();
+        get_congestion_info(&*self.runtime_adapter, &block)
+    }
+
     /// Gets a chunk from header.
     #[inline]
     pub fn get_chunk_clone_from_header(
@@ -2491,14 +3228,15 @@ impl<'a> ChainUpdate<'a> {
         me: &Option<AccountId>,
         parent_hash: CryptoHash,
     ) -> Result<bool, Error> {
-        for shard_id in 0..self.runtime_adapter.num_shards() {
-            if self.runtime_adapter.cares_about_shard(me.as_ref(), &parent_hash, shard_id, true)
-                || self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
-                    &parent_hash,
-                    shard_id,
-                    true,
-                )
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&parent_hash)?;
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
+            if self.runtime_adapter.cares_about_shard(me.as_ref(), &parent_hash, shard_id, true) {
+                return Ok(true);
+            }
+        }
+        let next_epoch_id = self.runtime_adapter.get_next_epoch_id_from_prev_block(&parent_hash)?;
+        for shard_id in 0..self.runtime_adapter.num_shards(&next_epoch_id)? {
+            if self.runtime_adapter.will_care_about_shard(me.as_ref(), &parent_hash, shard_id, true)
             {
                 return Ok(true);
             }
@@ -2610,21 +3348,23 @@ impl<'a> ChainUpdate<'a> {
         chunk_header: &ShardChunkHeader,
     ) -> Result<ChunkState, Error> {
         let chunk_shard_id = chunk_header.shard_id();
-        let prev_chunk_header = &prev_block.chunks()[chunk_shard_id as usize];
+        let prev_chunk_header =
+            get_prev_chunk_header(&*self.runtime_adapter, prev_block, chunk_shard_id)?;
+        let prev_chunk_shard_id = prev_chunk_header.shard_id();
         let prev_merkle_proofs = Block::compute_chunk_headers_root(prev_block.chunks().iter()).1;
         let merkle_proofs = Block::compute_chunk_headers_root(block.chunks().iter()).1;
         let prev_chunk = self
             .chain_store_update
             .get_chain_store()
-            .get_chunk_clone_from_header(&prev_block.chunks()[chunk_shard_id as usize].clone())
+            .get_chunk_clone_from_header(&prev_chunk_header)
             .unwrap();
-        let receipt_proof_response: Vec<ReceiptProofResponse> =
-            self.chain_store_update.get_incoming_receipts_for_shard(
-                chunk_shard_id,
-                *prev_block.hash(),
-                prev_chunk_header.height_included(),
-            )?;
-        let receipts = collect_receipts_from_response(&receipt_proof_response);
+        let receipts = get_incoming_receipts_for_shard(
+            &mut self.chain_store_update,
+            &*self.runtime_adapter,
+            prev_chunk_shard_id,
+            prev_block.hash(),
+            prev_chunk_header.height_included(),
+        )?;
 
         let challenges_result = self.verify_challenges(
             block.challenges(),
@@ -2633,10 +3373,13 @@ impl<'a> ChainUpdate<'a> {
             Some(&block.hash()),
         )?;
         let prev_chunk_inner = prev_chunk.cloned_header().take_inner();
+        let prev_prev_block =
+            self.chain_store_update.get_block(prev_block.header().prev_hash())?.clone();
+        let congestion_info = get_congestion_info(&*self.runtime_adapter, &prev_prev_block)?;
         let apply_result = self
             .runtime_adapter
             .apply_transactions_with_optional_storage_proof(
-                chunk_shard_id,
+                prev_chunk_shard_id,
                 &prev_chunk_inner.prev_state_root,
                 prev_chunk.height_included(),
                 prev_block.header().raw_timestamp(),
@@ -2649,6 +3392,7 @@ impl<'a> ChainUpdate<'a> {
                 prev_chunk_inner.gas_limit,
                 &challenges_result,
                 *block.header().random_value(),
+                &congestion_info,
                 true,
             )
             .unwrap();
@@ -2656,11 +3400,13 @@ impl<'a> ChainUpdate<'a> {
         Ok(ChunkState {
             prev_block_header: prev_block.header().try_to_vec()?,
             block_header: block.header().try_to_vec()?,
-            prev_merkle_proof: prev_merkle_proofs[chunk_shard_id as usize].clone(),
+            prev_merkle_proof: prev_merkle_proofs[prev_chunk_shard_id as usize].clone(),
             merkle_proof: merkle_proofs[chunk_shard_id as usize].clone(),
             prev_chunk,
             chunk_header: chunk_header.clone(),
             partial_state,
+            prev_prev_block_header: prev_prev_block.header().try_to_vec()?,
+            prev_prev_block_chunk_headers: prev_prev_block.chunks().iter().cloned().collect(),
         })
     }
 
@@ -2678,11 +3424,13 @@ impl<'a> ChainUpdate<'a> {
             Some(&block.hash()),
         )?;
         self.chain_store_update.save_block_extra(&block.hash(), BlockExtra { challenges_result });
+        let congestion_info = get_congestion_info(&*self.runtime_adapter, prev_block)?;
+        let shards_will_split = will_shards_split(&*self.runtime_adapter, block.header())?;
 
-        for (shard_id, (chunk_header, prev_chunk_header)) in
-            (block.chunks().iter().zip(prev_block.chunks().iter())).enumerate()
-        {
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
             let shard_id = shard_id as ShardId;
+            let prev_chunk_header =
+                get_prev_chunk_header(&*self.runtime_adapter, prev_block, shard_id)?;
             let care_about_shard = match mode {
                 ApplyChunksMode::ThisEpoch => self.runtime_adapter.cares_about_shard(
                     me.as_ref(),
@@ -2691,12 +3439,12 @@ impl<'a> ChainUpdate<'a> {
                     true,
                 ),
                 ApplyChunksMode::NextEpoch => {
-                    self.runtime_adapter.will_care_about_shard(
-                        me.as_ref(),
+                    will_care_about_shard_or_split_shards(
+                        &*self.runtime_adapter,
+                        me,
                         &block.header().prev_hash(),
                         shard_id,
-                        true,
-                    ) && !self.runtime_adapter.cares_about_shard(
+                    )? && !self.runtime_adapter.cares_about_shard(
                         me.as_ref(),
                         &block.header().prev_hash(),
                         shard_id,
@@ -2707,10 +3455,12 @@ impl<'a> ChainUpdate<'a> {
             if care_about_shard {
                 if chunk_header.height_included() == block.header().height() {
                     // Validate state root.
-                    let prev_chunk_extra = self
-                        .chain_store_update
-                        .get_chunk_extra(&block.header().prev_hash(), shard_id)?
-                        .clone();
+                    let prev_chunk_extra = get_prev_chunk_extra(
+                        &mut self.chain_store_update,
+                        &*self.runtime_adapter,
+                        &block.header().prev_hash(),
+                        shard_id,
+                    )?;
 
                     // Validate that all next chunk information matches previous chunk extra.
                     validate_chunk_with_chunk_extra(
@@ -2720,7 +3470,7 @@ impl<'a> ChainUpdate<'a> {
                         &*self.runtime_adapter,
                         &block.header().prev_hash(),
                         &prev_chunk_extra,
-                        prev_chunk_header,
+                        &prev_chunk_header,
                         chunk_header,
                     )
                     .map_err(|e| {
@@ -2735,13 +3485,13 @@ impl<'a> ChainUpdate<'a> {
                         }
                     })?;
 
-                    let receipt_proof_response: Vec<ReceiptProofResponse> =
-                        self.chain_store_update.get_incoming_receipts_for_shard(
-                            shard_id,
-                            *block.hash(),
-                            prev_chunk_header.height_included(),
-                        )?;
-                    let receipts = collect_receipts_from_response(&receipt_proof_response);
+                    let receipts = get_incoming_receipts_for_shard(
+                        &mut self.chain_store_update,
+                        &*self.runtime_adapter,
+                        shard_id,
+                        block.hash(),
+                        prev_chunk_header.height_included(),
+                    )?;
 
                     let chunk = self
                         .chain_store_update
@@ -2780,12 +3530,20 @@ impl<'a> ChainUpdate<'a> {
                             gas_limit,
                             &block.header().challenges_result(),
                             *block.header().random_value(),
+                            &congestion_info,
                         )
                         .map_err(|e| ErrorKind::Other(e.to_string()))?;
 
                     let (outcome_root, outcome_paths) =
                         ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
 
+                    if shards_will_split {
+                        self.chain_store_update.save_state_changes_for_split_states(
+                            &block.hash(),
+                            shard_id,
+                            apply_result.trie_changes.state_changes().to_vec(),
+                        );
+                    }
                     self.chain_store_update.save_trie_changes(apply_result.trie_changes);
                     // Save state root after applying transactions.
                     self.chain_store_update.save_chunk_extra(
@@ -2813,10 +3571,12 @@ impl<'a> ChainUpdate<'a> {
                         outcome_paths,
                     );
                 } else {
-                    let mut new_extra = self
-                        .chain_store_update
-                        .get_chunk_extra(&prev_block.hash(), shard_id)?
-                        .clone();
+                    let mut new_extra = get_prev_chunk_extra(
+                        &mut self.chain_store_update,
+                        &*self.runtime_adapter,
+                        &prev_block.hash(),
+                        shard_id,
+                    )?;
 
                     let apply_result = self
                         .runtime_adapter
@@ -2834,9 +3594,17 @@ impl<'a> ChainUpdate<'a> {
                             new_extra.gas_limit,
                             &block.header().challenges_result(),
                             *block.header().random_value(),
+                            &CongestionInfo::missing_chunk(),
                         )
                         .map_err(|e| ErrorKind::Other(e.to_string()))?;
 
+                    if shards_will_split {
+                        self.chain_store_update.save_state_changes_for_split_states(
+                            &block.hash(),
+                            shard_id,
+                            apply_result.trie_changes.state_changes().to_vec(),
+                        );
+                    }
                     self.chain_store_update.save_trie_changes(apply_result.trie_changes);
                     new_extra.state_root = apply_result.new_root;
 
@@ -2863,10 +3631,6 @@ impl<'a> ChainUpdate<'a> {
     {
         debug!(target: "chain", "Process block {} at {}, approvals: {}, me: {:?}", block.hash(), block.header().height(), block.header().num_approvals(), me);
 
-        if block.chunks().len() != self.runtime_adapter.num_shards() as usize {
-            return Err(ErrorKind::IncorrectNumberOfChunkHeaders.into());
-        }
-
         // Check if we have already processed this block previously.
         self.check_known(block.header().hash())?;
 
@@ -2880,6 +3644,12 @@ impl<'a> ChainUpdate<'a> {
             return Err(ErrorKind::EpochOutOfBounds.into());
         }
 
+        if block.chunks().len() as NumShards
+            != self.runtime_adapter.num_shards(block.header().epoch_id())?
+        {
+            return Err(ErrorKind::IncorrectNumberOfChunkHeaders.into());
+        }
+
         // A heuristic to prevent block height to jump too fast towards BlockHeight::max and cause
         // overflow-related problems
         let block_height = block.header().height();
@@ -2974,15 +3744,18 @@ impl<'a> ChainUpdate<'a> {
         self.save_incoming_receipts_from_block(me, &block)?;
 
         // Do basic validation of chunks before applying the transactions
-        for (chunk_header, prev_chunk_header) in
-            block.chunks().iter().zip(prev_block.chunks().iter())
-        {
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
             if chunk_header.height_included() == block.header().height() {
                 if &chunk_header.prev_block_hash() != block.header().prev_hash() {
                     return Err(ErrorKind::InvalidChunk.into());
                 }
             } else {
-                if prev_chunk_header != chunk_header {
+                let prev_chunk_header = get_prev_chunk_header(
+                    &*self.runtime_adapter,
+                    &prev_block,
+                    shard_id as ShardId,
+                )?;
+                if &prev_chunk_header != chunk_header {
                     return Err(ErrorKind::InvalidChunk.into());
                 }
             }
@@ -3177,7 +3950,19 @@ impl<'a> ChainUpdate<'a> {
             }
         }
 
-        if header.chunk_mask().len() as u64 != self.runtime_adapter.num_shards() {
+        if let BlockHeader::BlockHeaderV3(_) = header {
+            let epoch_sync_data_hash = self
+                .runtime_adapter
+                .get_epoch_sync_data(header.prev_hash())?
+                .map(|epoch_sync_data| epoch_sync_data.hash());
+            if header.epoch_sync_data_hash() != epoch_sync_data_hash {
+                return Err(ErrorKind::InvalidEpochSyncDataHash.into());
+            }
+        }
+
+        if header.chunk_mask().len() as NumShards
+            != self.runtime_adapter.num_shards(header.epoch_id())?
+        {
             return Err(ErrorKind::InvalidChunkMask.into());
         }
 
@@ -3429,14 +4214,17 @@ impl<'a> ChainUpdate<'a> {
         sync_hash: CryptoHash,
         shard_state_header: ShardStateSyncResponseHeader,
     ) -> Result<(), Error> {
-        let (chunk, incoming_receipts_proofs) = match shard_state_header {
+        let (chunk, incoming_receipts_proofs, prev_block_chunk_headers) = match shard_state_header {
             ShardStateSyncResponseHeader::V1(shard_state_header) => (
                 ShardChunk::V1(shard_state_header.chunk),
                 shard_state_header.incoming_receipts_proofs,
+                None,
+            ),
+            ShardStateSyncResponseHeader::V2(shard_state_header) => (
+                shard_state_header.chunk,
+                shard_state_header.incoming_receipts_proofs,
+                Some(shard_state_header.prev_block_chunk_headers),
             ),
-            ShardStateSyncResponseHeader::V2(shard_state_header) => {
-                (shard_state_header.chunk, shard_state_header.incoming_receipts_proofs)
-            }
         };
 
         let block_header = self
@@ -3463,6 +4251,24 @@ impl<'a> ChainUpdate<'a> {
 
         let chunk_header = chunk.cloned_header();
         let gas_limit = chunk_header.gas_limit();
+        // The previous block is unknown during state sync, the congestion is derived from its chunk
+        // headers sent and validated with the state header instead.
+        let congestion_info = match prev_block_chunk_headers {
+            // The state headers that predate congestion.
+            None => CongestionInfo::default(),
+            Some(_) if block_header.height() == self.chain_store_update.get_genesis_height() => {
+                CongestionInfo::default()
+            }
+            Some(prev_block_chunk_headers) => {
+                let prev_block_header =
+                    self.chain_store_update.get_block_header(block_header.prev_hash())?.clone();
+                get_congestion_info_from_chunk_headers(
+                    &*self.runtime_adapter,
+                    &prev_block_header,
+                    prev_block_chunk_headers.iter(),
+                )?
+            }
+        };
         let apply_result = self.runtime_adapter.apply_transactions(
             shard_id,
             &chunk_header.prev_state_root(),
@@ -3477,6 +4283,7 @@ impl<'a> ChainUpdate<'a> {
             gas_limit,
             &block_header.challenges_result(),
             *block_header.random_value(),
+            &congestion_info,
         )?;
 
         let (outcome_root, outcome_proofs) =
@@ -3555,6 +4362,7 @@ impl<'a> ChainUpdate<'a> {
             chunk_extra.gas_limit,
             &block_header.challenges_result(),
             *block_header.random_value(),
+            &CongestionInfo::missing_chunk(),
         )?;
 
         self.chain_store_update.save_trie_changes(apply_result.trie_changes);
@@ -3618,6 +4426,36 @@ impl<'a> ChainUpdate<'a> {
     }
 }
 
+/// Whether the epoch manager block info has the fields that `add_validator_proposals` takes from
+/// the header.
+fn block_info_matches_header(block_info: &BlockInfo, header: &BlockHeader) -> bool {
+    block_info.height == header.height()
+        && &block_info.prev_hash == header.prev_hash()
+        && &block_info.last_final_block_hash == header.last_final_block()
+        && &block_info.epoch_id == header.epoch_id()
+        && block_info.proposals.as_slice() == header.validator_proposals()
+        && block_info.chunk_mask.as_slice() == header.chunk_mask()
+        && block_info.total_supply == header.total_supply()
+        && block_info.latest_protocol_version == header.latest_protocol_version()
+}
+
+/// Whether the validators slashed in the block info are the ones slashed in the block info of its
+/// parent from the same epoch, plus the ones slashed by the challenges of the block.
+fn slashed_match_parent(
+    block_info: &BlockInfo,
+    prev_block_info: &BlockInfo,
+    header: &BlockHeader,
+) -> bool {
+    prev_block_info.slashed.keys().all(|account_id| block_info.slashed.contains_key(account_id))
+        && block_info.slashed.keys().all(|account_id| {
+            prev_block_info.slashed.contains_key(account_id)
+                || header
+                    .challenges_result()
+                    .iter()
+                    .any(|slashed| &slashed.account_id == account_id)
+        })
+}
+
 pub fn collect_receipts<'a, T>(receipt_proofs: T) -> Vec<Receipt>
 where
     T: IntoIterator<Item = &'a ReceiptProof>,
@@ -3632,3 +4470,186 @@ pub fn collect_receipts_from_response(
         receipt_proof_response.iter().flat_map(|ReceiptProofResponse(_, proofs)| proofs),
     )
 }
+
+/// Returns the shard layout of the epoch of the block after the given block, if the shards are
+/// split at the start of that epoch.
+pub fn get_split_shard_layout(
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block_hash: &CryptoHash,
+) -> Result<Option<ShardLayout>, Error> {
+    if runtime_adapter.is_next_block_epoch_start(prev_block_hash)?
+        && runtime_adapter.will_shard_layout_change(prev_block_hash)?
+    {
+        let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+        Ok(Some(runtime_adapter.get_shard_layout(&epoch_id)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns whether the shards of the epoch of the block are split at the start of the next epoch.
+pub fn will_shards_split(
+    runtime_adapter: &dyn RuntimeAdapter,
+    header: &BlockHeader,
+) -> Result<bool, Error> {
+    Ok(runtime_adapter.get_shard_layout(header.epoch_id())?
+        != runtime_adapter.get_shard_layout(header.next_epoch_id())?)
+}
+
+/// Returns whether the shard of the epoch of the block after `parent_hash`, or any of the shards
+/// split from it if the shards are split at the start of the next epoch, will be cared about in
+/// the next epoch.
+fn will_care_about_shard_or_split_shards(
+    runtime_adapter: &dyn RuntimeAdapter,
+    me: &Option<AccountId>,
+    parent_hash: &CryptoHash,
+    shard_id: ShardId,
+) -> Result<bool, Error> {
+    let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
+    let next_epoch_id = runtime_adapter.get_next_epoch_id_from_prev_block(parent_hash)?;
+    let next_shard_layout = runtime_adapter.get_shard_layout(&next_epoch_id)?;
+    let shard_ids = if runtime_adapter.get_shard_layout(&epoch_id)? == next_shard_layout {
+        vec![shard_id]
+    } else {
+        next_shard_layout.get_split_shard_ids(shard_id)
+    };
+    Ok(shard_ids.into_iter().any(|shard_id| {
+        runtime_adapter.will_care_about_shard(me.as_ref(), parent_hash, shard_id, true)
+    }))
+}
+
+/// Returns the header of the previous chunk of the given shard in the block after `prev_block`.
+/// If the shards are split after `prev_block`, it's the last chunk of the parent shard.
+pub fn get_prev_chunk_header(
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block: &Block,
+    shard_id: ShardId,
+) -> Result<ShardChunkHeader, Error> {
+    let prev_shard_id = match get_split_shard_layout(runtime_adapter, prev_block.hash())? {
+        Some(shard_layout) => shard_layout.get_parent_shard_id(shard_id),
+        None => shard_id,
+    };
+    prev_block
+        .chunks()
+        .get(prev_shard_id as usize)
+        .cloned()
+        .ok_or_else(|| ErrorKind::InvalidShardId(shard_id).into())
+}
+
+/// Returns the chunk extra the next chunk of the given shard is built on. If the shards are
+/// split after the given block, the first split shard takes over the chunk extra of the parent
+/// shard, and the other split shards start from an empty one, all with the split states.
+pub fn get_prev_chunk_extra<T: ChainStoreAccess>(
+    chain_store: &mut T,
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block_hash: &CryptoHash,
+    shard_id: ShardId,
+) -> Result<ChunkExtra, Error> {
+    let shard_layout = match get_split_shard_layout(runtime_adapter, prev_block_hash)? {
+        Some(shard_layout) => shard_layout,
+        None => return Ok(chain_store.get_chunk_extra(prev_block_hash, shard_id)?.clone()),
+    };
+    let parent_shard_id = shard_layout.get_parent_shard_id(shard_id);
+    let chunk_extra = chain_store.get_chunk_extra(prev_block_hash, parent_shard_id)?.clone();
+    let state_root = *chain_store
+        .get_split_state_roots(prev_block_hash, parent_shard_id)?
+        .get(&shard_id)
+        .ok_or_else(|| ErrorKind::InvalidShardId(shard_id))?;
+    if shard_layout.get_split_shard_ids(parent_shard_id).first() == Some(&shard_id) {
+        Ok(ChunkExtra { state_root, ..chunk_extra })
+    } else {
+        Ok(ChunkExtra::new(&state_root, CryptoHash::default(), vec![], 0, chunk_extra.gas_limit, 0))
+    }
+}
+
+/// Returns the outgoing receipts of the previous chunk of the given shard, which the next chunk of
+/// the shard carries. If the shards were split since the previous chunk, the first split shard
+/// takes over the outgoing receipts of the parent shard.
+pub fn get_outgoing_receipts_for_shard(
+    chain_store: &mut ChainStore,
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block_hash: &CryptoHash,
+    shard_id: ShardId,
+    last_height_included: BlockHeight,
+) -> Result<ReceiptResponse, Error> {
+    let ReceiptResponse(block_hash, receipts) = chain_store.get_outgoing_receipts_for_shard(
+        *prev_block_hash,
+        shard_id,
+        last_height_included,
+    )?;
+    let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+    let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
+    let prev_epoch_id = chain_store.get_block_header(&block_hash)?.epoch_id().clone();
+    if runtime_adapter.get_shard_layout(&prev_epoch_id)? == shard_layout {
+        return Ok(ReceiptResponse(block_hash, receipts));
+    }
+    let parent_shard_id = shard_layout.get_parent_shard_id(shard_id);
+    let receipts = if shard_layout.get_split_shard_ids(parent_shard_id).first() == Some(&shard_id) {
+        chain_store.get_outgoing_receipts(&block_hash, parent_shard_id).cloned().unwrap_or_default()
+    } else {
+        vec![]
+    };
+    Ok(ReceiptResponse(block_hash, receipts))
+}
+
+/// Returns the receipts the chunk of the given shard in the given block is applied with, i.e.
+/// the receipts sent to the shard since its previous chunk included at `last_chunk_height_included`.
+/// Receipts that were sent to the parent shard before the shards were split are filtered by
+/// their receivers.
+pub fn get_incoming_receipts_for_shard(
+    chain_store_update: &mut ChainStoreUpdate,
+    runtime_adapter: &dyn RuntimeAdapter,
+    shard_id: ShardId,
+    block_hash: &CryptoHash,
+    last_chunk_height_included: BlockHeight,
+) -> Result<Vec<Receipt>, Error> {
+    let epoch_id = chain_store_update.get_block_header(block_hash)?.epoch_id().clone();
+    let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
+    let parent_shard_id = shard_layout.get_parent_shard_id(shard_id);
+    let mut receipts = vec![];
+    let mut block_hash = *block_hash;
+    loop {
+        let header = chain_store_update.get_block_header(&block_hash)?.clone();
+        if header.height() <= last_chunk_height_included {
+            break;
+        }
+        let is_split = runtime_adapter.get_shard_layout(header.epoch_id())? != shard_layout;
+        let receipt_shard_id = if is_split { parent_shard_id } else { shard_id };
+        if let Ok(receipt_proofs) =
+            chain_store_update.get_incoming_receipts(&block_hash, receipt_shard_id)
+        {
+            receipts.extend(collect_receipts(receipt_proofs).into_iter().filter(|receipt| {
+                !is_split || shard_layout.account_id_to_shard_id(&receipt.receiver_id) == shard_id
+            }));
+        }
+        block_hash = *header.prev_hash();
+    }
+    Ok(receipts)
+}
+
+/// Returns the congestion of the shards the chunks of the block after `prev_block` are applied
+/// with. Congestion isn't known right after the shards are split.
+pub fn get_congestion_info(
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block: &Block,
+) -> Result<CongestionInfo, Error> {
+    get_congestion_info_from_chunk_headers(
+        runtime_adapter,
+        prev_block.header(),
+        prev_block.chunks().iter(),
+    )
+}
+
+/// Same as `get_congestion_info`, from the header and the chunk headers of `prev_block` for the
+/// replays without the block itself, e.g. state sync or challenge validation.
+pub fn get_congestion_info_from_chunk_headers<'a, T: IntoIterator<Item = &'a ShardChunkHeader>>(
+    runtime_adapter: &dyn RuntimeAdapter,
+    prev_block_header: &BlockHeader,
+    chunk_headers: T,
+) -> Result<CongestionInfo, Error> {
+    if get_split_shard_layout(runtime_adapter, prev_block_header.hash())?.is_some() {
+        return Ok(CongestionInfo::default());
+    }
+    let shard_layout = runtime_adapter.get_shard_layout(prev_block_header.epoch_id())?;
+    Ok(CongestionInfo::from_chunk_headers(chunk_headers, shard_layout))
+}
@@ -3,12 +3,16 @@ use std::convert::TryFrom;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use log::warn;
+
 use near_crypto::Signature;
 use near_primitives::block::{Approval, ApprovalInner};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta};
 use near_primitives::validator_signer::ValidatorSigner;
 
+use crate::slashing_protection::SlashingProtection;
+
 /// Have that many iterations in the timer instead of `loop` to prevent potential bugs from blocking
 /// the node
 const MAX_TIMER_ITERS: usize = 20;
@@ -101,6 +105,8 @@ pub struct Doomslug {
     /// Information to track the timer (see `start_timer` routine in the paper)
     timer: DoomslugTimer,
     signer: Option<Arc<dyn ValidatorSigner>>,
+    /// If set, every approval is checked against and recorded in it before signing
+    slashing_protection: Option<SlashingProtection>,
     /// How many approvals to have before producing a block. In production should be always `HalfStake`,
     ///    but for many tests we use `NoApprovals` to invoke more forkfulness
     threshold_mode: DoomslugThresholdMode,
@@ -300,10 +306,19 @@ impl Doomslug {
                 max_delay,
             },
             signer,
+            slashing_protection: None,
             threshold_mode,
         }
     }
 
+    pub fn with_slashing_protection(
+        mut self,
+        slashing_protection: Option<SlashingProtection>,
+    ) -> Self {
+        self.slashing_protection = slashing_protection;
+        self
+    }
+
     #[cfg(feature = "adversarial")]
     pub fn adv_disable(&mut self) {
         self.threshold_mode = DoomslugThresholdMode::NoApprovals
@@ -403,9 +418,15 @@ impl Doomslug {
     }
 
     pub fn create_approval(&self, target_height: BlockHeight) -> Option<Approval> {
-        self.signer.as_ref().map(|signer| {
-            Approval::new(self.tip.block_hash, self.tip.height, target_height, &**signer)
-        })
+        let signer = self.signer.as_ref()?;
+        if let Some(slashing_protection) = self.slashing_protection.as_ref() {
+            let inner = ApprovalInner::new(&self.tip.block_hash, self.tip.height, target_height);
+            if let Err(err) = slashing_protection.check_and_record_approval(&inner, target_height) {
+                warn!(target: "doomslug", "Not sending approval for target height {}: {}", target_height, err);
+                return None;
+            }
+        }
+        Some(Approval::new(self.tip.block_hash, self.tip.height, target_height, &**signer))
     }
 
     /// Determines whether a block has enough approvals to be produced.
@@ -6,7 +6,7 @@ use failure::{Backtrace, Context, Fail};
 use log::error;
 
 use near_primitives::block::BlockValidityError;
-use near_primitives::challenge::{ChunkProofs, ChunkState};
+use near_primitives::challenge::{ChallengeBody, ChunkProofs, ChunkState};
 use near_primitives::errors::{EpochError, StorageError};
 use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::to_base;
@@ -134,12 +134,21 @@ pub enum ErrorKind {
     /// Invalid Balance Burnt
     #[fail(display = "Invalid Balance Burnt")]
     InvalidBalanceBurnt,
+    /// Invalid Delayed Receipts Count
+    #[fail(display = "Invalid Delayed Receipts Count")]
+    InvalidDelayedReceiptsCount,
     /// Invalid shard id
     #[fail(display = "Shard id {} does not exist", _0)]
     InvalidShardId(ShardId),
     /// Invalid shard id
     #[fail(display = "Invalid state request: {}", _0)]
     InvalidStateRequest(String),
+    /// Invalid epoch sync response
+    #[fail(display = "Invalid epoch sync response: {}", _0)]
+    InvalidEpochSyncResponse(String),
+    /// Invalid hash of the epoch sync data in the header
+    #[fail(display = "Invalid Epoch Sync Data Hash")]
+    InvalidEpochSyncDataHash,
     /// Invalid VRF proof, or incorrect random_output in the header
     #[fail(display = "Invalid Randomness Beacon Output")]
     InvalidRandomnessBeaconOutput,
@@ -170,6 +179,9 @@ pub enum ErrorKind {
     /// GC error.
     #[fail(display = "GC Error: {}", _0)]
     GCError(String),
+    /// Signing could conflict with what the validator signed before.
+    #[fail(display = "Slashing Protection: {}", _0)]
+    SlashingProtection(String),
     /// Anything else
     #[fail(display = "Other Error: {}", _0)]
     Other(String),
@@ -233,6 +245,7 @@ impl Error {
             | ErrorKind::ChallengedBlockOnChain
             | ErrorKind::StorageError(_)
             | ErrorKind::GCError(_)
+            | ErrorKind::SlashingProtection(_)
             | ErrorKind::DBNotFoundErr(_) => false,
             ErrorKind::InvalidBlockPastTime(_, _)
             | ErrorKind::InvalidBlockFutureTime(_)
@@ -265,8 +278,11 @@ impl Error {
             | ErrorKind::InvalidGasPrice
             | ErrorKind::InvalidGasUsed
             | ErrorKind::InvalidBalanceBurnt
+            | ErrorKind::InvalidDelayedReceiptsCount
             | ErrorKind::InvalidShardId(_)
             | ErrorKind::InvalidStateRequest(_)
+            | ErrorKind::InvalidEpochSyncResponse(_)
+            | ErrorKind::InvalidEpochSyncDataHash
             | ErrorKind::InvalidRandomnessBeaconOutput
             | ErrorKind::InvalidBlockMerkleRoot
             | ErrorKind::NotAValidator
@@ -274,6 +290,19 @@ impl Error {
         }
     }
 
+    /// Challenge that proves the chunk which caused this error to be invalid, if there is one.
+    pub fn challenge_body(&self) -> Option<ChallengeBody> {
+        match self.kind() {
+            ErrorKind::InvalidChunkProofs(chunk_proofs) => {
+                Some(ChallengeBody::ChunkProofs(*chunk_proofs))
+            }
+            ErrorKind::InvalidChunkState(chunk_state) => {
+                Some(ChallengeBody::ChunkState(*chunk_state))
+            }
+            _ => None,
+        }
+    }
+
     pub fn is_error(&self) -> bool {
         match self.kind() {
             ErrorKind::IOErr(_) | ErrorKind::Other(_) | ErrorKind::DBNotFoundErr(_) => true,
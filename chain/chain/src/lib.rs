@@ -4,7 +4,10 @@ extern crate lazy_static;
 pub use chain::{collect_receipts, Chain, MAX_ORPHAN_SIZE};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
 pub use error::{Error, ErrorKind};
-pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
+pub use lightclient::{
+    create_light_client_block_view, get_epoch_block_producers_view,
+    get_epoch_info_block_producers_view, light_client_block_hash, validate_light_client_block,
+};
 pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
 pub use store_validator::{ErrorMessage, StoreValidator};
 pub use types::{
@@ -16,6 +19,7 @@ mod doomslug;
 mod error;
 mod lightclient;
 mod metrics;
+pub mod slashing_protection;
 mod store;
 pub mod store_validator;
 pub mod test_utils;
@@ -1,10 +1,16 @@
-use near_primitives::block::BlockHeader;
+use std::collections::HashSet;
+
+use borsh::BorshSerialize;
+
+use near_primitives::block::{Approval, ApprovalInner, BlockHeader, BlockHeaderInnerLite};
+use near_primitives::epoch_manager::EpochInfo;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::combine_hash;
 use near_primitives::types::EpochId;
 use near_primitives::views::{BlockHeaderInnerLiteView, LightClientBlockView, ValidatorStakeView};
 
-use crate::error::Error;
-use crate::{ChainStoreAccess, RuntimeAdapter};
+use crate::error::{Error, ErrorKind};
+use crate::{Chain, ChainStoreAccess, RuntimeAdapter};
 
 pub fn get_epoch_block_producers_view(
     epoch_id: &EpochId,
@@ -18,6 +24,66 @@ pub fn get_epoch_block_producers_view(
         .collect::<Vec<_>>())
 }
 
+/// Block producers of the epoch, in the same order as `get_epoch_block_producers_view` returns them.
+pub fn get_epoch_info_block_producers_view(epoch_info: &EpochInfo) -> Vec<ValidatorStakeView> {
+    let mut seen = HashSet::new();
+    epoch_info
+        .block_producers_settlement
+        .iter()
+        .map(|validator_id| &epoch_info.validators[*validator_id as usize])
+        .filter(|validator| seen.insert(validator.account_id.clone()))
+        .map(|validator| validator.clone().into())
+        .collect()
+}
+
+/// Hash of the block the light client block is created for.
+pub fn light_client_block_hash(block: &LightClientBlockView) -> CryptoHash {
+    let inner_lite: BlockHeaderInnerLite = block.inner_lite.clone().into();
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    combine_hash(combine_hash(inner_lite_hash, block.inner_rest_hash), block.prev_block_hash)
+}
+
+/// Validates the light client block given the ordered block producers of its epoch: the block
+/// after it must be endorsed by more than 2/3 of their stake and `next_bps` must match the
+/// `next_bp_hash` of the block.
+pub fn validate_light_client_block(
+    epoch_id: &EpochId,
+    block_producers: &[ValidatorStakeView],
+    block: &LightClientBlockView,
+) -> Result<(), Error> {
+    if block.inner_lite.epoch_id != epoch_id.0 {
+        return Err(ErrorKind::InvalidEpochHash.into());
+    }
+
+    let next_block_hash = combine_hash(block.next_block_inner_hash, light_client_block_hash(block));
+    let approval_data = Approval::get_data_for_sig(
+        &ApprovalInner::Endorsement(next_block_hash),
+        block.inner_lite.height + 2,
+    );
+
+    let total_stake: u128 = block_producers.iter().map(|bp| bp.stake).sum();
+    let mut approved_stake = 0;
+    for (approval, block_producer) in block.approvals_after_next.iter().zip(block_producers) {
+        if let Some(signature) = approval {
+            if !signature.verify(&approval_data, &block_producer.public_key) {
+                return Err(ErrorKind::InvalidApprovals.into());
+            }
+            approved_stake += block_producer.stake;
+        }
+    }
+    if approved_stake * 3 <= total_stake * 2 {
+        return Err(ErrorKind::NotEnoughApprovals.into());
+    }
+
+    let next_bps = block.next_bps.as_ref().ok_or(ErrorKind::InvalidNextBPHash)?;
+    let next_bp_hash =
+        Chain::compute_bp_hash_inner(next_bps.iter().cloned().map(Into::into).collect())?;
+    if next_bp_hash != block.inner_lite.next_bp_hash {
+        return Err(ErrorKind::InvalidNextBPHash.into());
+    }
+    Ok(())
+}
+
 /// Creates the `LightClientBlock` from the information in the chain store for a given block.
 ///
 /// # Arguments
@@ -69,3 +135,160 @@ pub fn create_light_client_block_view(
         approvals_after_next,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{KeyType, Signature};
+    use near_primitives::block::{Approval, ApprovalInner};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::merkle::combine_hash;
+    use near_primitives::types::EpochId;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+    use near_primitives::views::{
+        BlockHeaderInnerLiteView, LightClientBlockView, ValidatorStakeView,
+    };
+
+    use crate::error::ErrorKind;
+    use crate::lightclient::{light_client_block_hash, validate_light_client_block};
+    use crate::Chain;
+
+    fn signers(names: &[&str]) -> Vec<InMemoryValidatorSigner> {
+        names
+            .iter()
+            .map(|name| InMemoryValidatorSigner::from_seed(name, KeyType::ED25519, name))
+            .collect()
+    }
+
+    fn block_producers(signers: &[InMemoryValidatorSigner]) -> Vec<ValidatorStakeView> {
+        signers
+            .iter()
+            .map(|signer| ValidatorStakeView {
+                account_id: signer.validator_id().clone(),
+                public_key: signer.public_key(),
+                stake: 100,
+            })
+            .collect()
+    }
+
+    /// Light client block of `epoch_id` with `next_bps`, approved by the given signers.
+    fn light_client_block(
+        epoch_id: &EpochId,
+        next_bps: Vec<ValidatorStakeView>,
+        approvers: &[Option<&InMemoryValidatorSigner>],
+    ) -> LightClientBlockView {
+        let next_bp_hash =
+            Chain::compute_bp_hash_inner(next_bps.iter().cloned().map(Into::into).collect())
+                .unwrap();
+        let mut block = LightClientBlockView {
+            prev_block_hash: hash(&[1]),
+            next_block_inner_hash: hash(&[2]),
+            inner_lite: BlockHeaderInnerLiteView {
+                height: 10,
+                epoch_id: epoch_id.0,
+                next_epoch_id: hash(&[3]),
+                prev_state_root: CryptoHash::default(),
+                outcome_root: CryptoHash::default(),
+                timestamp: 0,
+                timestamp_nanosec: 0,
+                next_bp_hash,
+                block_merkle_root: CryptoHash::default(),
+            },
+            inner_rest_hash: hash(&[4]),
+            next_bps: Some(next_bps),
+            approvals_after_next: vec![],
+        };
+        let next_block_hash =
+            combine_hash(block.next_block_inner_hash, light_client_block_hash(&block));
+        block.approvals_after_next = approvers
+            .iter()
+            .map(|approver| {
+                approver.map(|signer| {
+                    signer.sign_approval(&ApprovalInner::Endorsement(next_block_hash), 12)
+                })
+            })
+            .collect();
+        block
+    }
+
+    #[test]
+    fn test_validate_light_client_block() {
+        let epoch_id = EpochId(hash(&[5]));
+        let signers = signers(&["test1", "test2", "test3"]);
+        let bps = block_producers(&signers);
+        let next_bps = block_producers(&self::signers(&["test4"]));
+
+        let block = light_client_block(
+            &epoch_id,
+            next_bps.clone(),
+            &[Some(&signers[0]), Some(&signers[1]), Some(&signers[2])],
+        );
+        validate_light_client_block(&epoch_id, &bps, &block).unwrap();
+
+        // Approvals of 2/3 of the stake are not enough.
+        let block = light_client_block(
+            &epoch_id,
+            next_bps.clone(),
+            &[Some(&signers[0]), None, Some(&signers[2])],
+        );
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::NotEnoughApprovals
+        );
+
+        // The block belongs to another epoch.
+        assert_eq!(
+            validate_light_client_block(&EpochId(hash(&[6])), &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidEpochHash
+        );
+    }
+
+    #[test]
+    fn test_validate_light_client_block_forged() {
+        let epoch_id = EpochId(hash(&[5]));
+        let signers = signers(&["test1", "test2", "test3"]);
+        let bps = block_producers(&signers);
+        let next_bps = block_producers(&self::signers(&["test4"]));
+        let all_approvers = [Some(&signers[0]), Some(&signers[1]), Some(&signers[2])];
+
+        // An approval signed by someone else than the block producer.
+        let outsider = &self::signers(&["test4"])[0];
+        let block = light_client_block(
+            &epoch_id,
+            next_bps.clone(),
+            &[Some(&signers[0]), Some(outsider), Some(&signers[2])],
+        );
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidApprovals
+        );
+
+        // A garbage signature.
+        let mut block = light_client_block(&epoch_id, next_bps.clone(), &all_approvers);
+        block.approvals_after_next[1] = Some(Signature::empty(KeyType::ED25519));
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidApprovals
+        );
+
+        // The approvals don't cover a block changed after signing.
+        let mut block = light_client_block(&epoch_id, next_bps.clone(), &all_approvers);
+        block.inner_lite.height += 1;
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidApprovals
+        );
+
+        // The next block producers don't match the hash in the approved block.
+        let mut block = light_client_block(&epoch_id, next_bps, &all_approvers);
+        block.next_bps = Some(bps.clone());
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidNextBPHash
+        );
+        block.next_bps = None;
+        assert_eq!(
+            validate_light_client_block(&epoch_id, &bps, &block).unwrap_err().kind(),
+            ErrorKind::InvalidNextBPHash
+        );
+    }
+}
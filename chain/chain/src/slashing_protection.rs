@@ -0,0 +1,327 @@
+//! Slashing protection keeps track of the blocks, chunks and approvals signed by the validator
+//! key of this node, and refuses to sign anything that could conflict with them. This protects a
+//! validator that is accidentally run on two machines, or restored from an old backup, from
+//! double signing, as long as the records are moved together with the key.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use near_primitives::block::ApprovalInner;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+use crate::error::{Error, ErrorKind};
+
+const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
+
+/// Number of the latest approval target heights for which the signed approval is kept.
+const NUM_APPROVALS_TO_KEEP: usize = 1_000;
+
+/// Everything signed by one validator that later signatures must not conflict with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SlashingProtectionRecord {
+    /// Largest height of a signed block.
+    pub block_height: BlockHeight,
+    /// Largest height of a signed chunk per shard.
+    pub chunk_heights: BTreeMap<ShardId, BlockHeight>,
+    /// Largest target height of a signed endorsement.
+    pub endorsement_target_height: BlockHeight,
+    /// Largest target height of a signed approval, endorsement or skip.
+    pub approval_target_height: BlockHeight,
+    /// Signed approvals for the latest target heights.
+    pub approvals: BTreeMap<BlockHeight, ApprovalInner>,
+}
+
+impl SlashingProtectionRecord {
+    /// Block at `height` may be signed if no block was signed at this height or above.
+    pub fn can_sign_block(&self, height: BlockHeight) -> bool {
+        height > self.block_height
+    }
+
+    /// Chunk of `shard_id` at `height` may be signed if no chunk of the shard was signed at this
+    /// height or above.
+    pub fn can_sign_chunk(&self, shard_id: ShardId, height: BlockHeight) -> bool {
+        self.chunk_heights.get(&shard_id).map_or(true, |chunk_height| height > *chunk_height)
+    }
+
+    /// Same rules as in Doomslug: an endorsement is only sent for a target above all the
+    /// approvals sent before, while a skip is only sent for a target above all the endorsements.
+    /// Signing again exactly the same approval is always allowed.
+    pub fn can_sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> bool {
+        if self.approvals.get(&target_height) == Some(inner) {
+            return true;
+        }
+        match inner {
+            ApprovalInner::Endorsement(_) => target_height > self.approval_target_height,
+            ApprovalInner::Skip(_) => target_height > self.endorsement_target_height,
+        }
+    }
+
+    fn record_approval(&mut self, inner: &ApprovalInner, target_height: BlockHeight) {
+        if let ApprovalInner::Endorsement(_) = inner {
+            self.endorsement_target_height =
+                std::cmp::max(self.endorsement_target_height, target_height);
+        }
+        self.approval_target_height = std::cmp::max(self.approval_target_height, target_height);
+        self.approvals.insert(target_height, inner.clone());
+        while self.approvals.len() > NUM_APPROVALS_TO_KEEP {
+            let lowest_target_height = *self.approvals.keys().next().unwrap();
+            self.approvals.remove(&lowest_target_height);
+        }
+    }
+
+    /// Combines the records of the same validator from two machines, so that nothing that
+    /// conflicts with either of them can be signed. Approvals for the same target keep the ones
+    /// from `self`.
+    pub fn merge(&mut self, other: &SlashingProtectionRecord) {
+        self.block_height = std::cmp::max(self.block_height, other.block_height);
+        for (shard_id, height) in other.chunk_heights.iter() {
+            let chunk_height = self.chunk_heights.entry(*shard_id).or_default();
+            *chunk_height = std::cmp::max(*chunk_height, *height);
+        }
+        self.endorsement_target_height =
+            std::cmp::max(self.endorsement_target_height, other.endorsement_target_height);
+        self.approval_target_height =
+            std::cmp::max(self.approval_target_height, other.approval_target_height);
+        for (target_height, inner) in other.approvals.iter() {
+            self.approvals.entry(*target_height).or_insert_with(|| inner.clone());
+        }
+        while self.approvals.len() > NUM_APPROVALS_TO_KEEP {
+            let lowest_target_height = *self.approvals.keys().next().unwrap();
+            self.approvals.remove(&lowest_target_height);
+        }
+    }
+}
+
+/// Checks and records signatures of one validator in the slashing protection file. The file is
+/// kept next to the validator key instead of in the store, so that it survives restoring the data
+/// directory from a backup. Every record is written to disk before the check returns, so the
+/// signature can only be sent out after it is persisted.
+#[derive(Clone)]
+pub struct SlashingProtection {
+    path: PathBuf,
+    account_id: AccountId,
+    /// Records of all the validators in the file, shared by the clones in the client and Doomslug.
+    records: Arc<Mutex<BTreeMap<AccountId, SlashingProtectionRecord>>>,
+}
+
+impl SlashingProtection {
+    pub fn new(path: &Path, account_id: AccountId) -> Result<Self, Error> {
+        let records = get_slashing_protection_records(path)?;
+        Ok(SlashingProtection {
+            path: path.to_path_buf(),
+            account_id,
+            records: Arc::new(Mutex::new(records)),
+        })
+    }
+
+    pub fn get_record(&self) -> SlashingProtectionRecord {
+        let records = self.records.lock().expect(POISONED_LOCK_ERR);
+        records.get(&self.account_id).cloned().unwrap_or_default()
+    }
+
+    /// Applies `update` to the record of the validator and writes the file. Nothing is changed if
+    /// `update` or writing the file fails.
+    fn check_and_record<F>(&self, update: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut SlashingProtectionRecord) -> Result<(), Error>,
+    {
+        let mut records = self.records.lock().expect(POISONED_LOCK_ERR);
+        let mut new_records = records.clone();
+        update(new_records.entry(self.account_id.clone()).or_default())?;
+        write_slashing_protection_records(&self.path, &new_records)?;
+        *records = new_records;
+        Ok(())
+    }
+
+    /// Records the block at `height` or fails if signing it could be a double sign.
+    pub fn check_and_record_block(&self, height: BlockHeight) -> Result<(), Error> {
+        self.check_and_record(|record| {
+            if !record.can_sign_block(height) {
+                return Err(ErrorKind::SlashingProtection(format!(
+                    "block at height {} is not above the last signed block at height {}",
+                    height, record.block_height
+                ))
+                .into());
+            }
+            record.block_height = height;
+            Ok(())
+        })
+    }
+
+    /// Records the chunk of `shard_id` at `height` or fails if signing it could be a double sign.
+    pub fn check_and_record_chunk(
+        &self,
+        shard_id: ShardId,
+        height: BlockHeight,
+    ) -> Result<(), Error> {
+        self.check_and_record(|record| {
+            if !record.can_sign_chunk(shard_id, height) {
+                return Err(ErrorKind::SlashingProtection(format!(
+                    "chunk of shard {} at height {} is not above the last signed chunk at height {}",
+                    shard_id, height, record.chunk_heights[&shard_id]
+                ))
+                .into());
+            }
+            record.chunk_heights.insert(shard_id, height);
+            Ok(())
+        })
+    }
+
+    /// Records the approval or fails if it conflicts with an approval signed before.
+    pub fn check_and_record_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<(), Error> {
+        self.check_and_record(|record| {
+            if !record.can_sign_approval(inner, target_height) {
+                return Err(ErrorKind::SlashingProtection(format!(
+                    "approval {:?} for target height {} conflicts with the approvals signed up to target height {}",
+                    inner, target_height, record.approval_target_height
+                ))
+                .into());
+            }
+            record.record_approval(inner, target_height);
+            Ok(())
+        })
+    }
+}
+
+/// Returns the records of all the validators in the slashing protection file at `path`. There are
+/// no records if the file doesn't exist yet.
+pub fn get_slashing_protection_records(
+    path: &Path,
+) -> Result<BTreeMap<AccountId, SlashingProtectionRecord>, Error> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|err| {
+            ErrorKind::Other(format!(
+                "Failed to parse slashing protection file {}: {}",
+                path.display(),
+                err
+            ))
+            .into()
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Merges records, e.g. exported from another machine, into the slashing protection file at
+/// `path`.
+pub fn merge_slashing_protection_records(
+    path: &Path,
+    records: &BTreeMap<AccountId, SlashingProtectionRecord>,
+) -> Result<(), Error> {
+    let mut new_records = get_slashing_protection_records(path)?;
+    for (account_id, other) in records.iter() {
+        new_records.entry(account_id.clone()).or_default().merge(other);
+    }
+    write_slashing_protection_records(path, &new_records)
+}
+
+/// Replaces the slashing protection file at `path` with `records`. The records are written to a
+/// temporary file which is synced and renamed over the old one, so a crash leaves either the old
+/// or the new records on disk.
+fn write_slashing_protection_records(
+    path: &Path,
+    records: &BTreeMap<AccountId, SlashingProtectionRecord>,
+) -> Result<(), Error> {
+    let content = serde_json::to_string_pretty(records)
+        .map_err(|err| ErrorKind::Other(format!("Failed to serialize records: {}", err)))?;
+    let tmp_path = path.with_extension("json.tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    fn slashing_protection_file(dir: &tempfile::TempDir) -> PathBuf {
+        dir.path().join("slashing_protection.json")
+    }
+
+    #[test]
+    fn test_refuse_blocks_and_chunks_at_or_below_signed_height() {
+        let dir = tempfile::Builder::new().prefix("slashing_protection").tempdir().unwrap();
+        let path = slashing_protection_file(&dir);
+        let protection = SlashingProtection::new(&path, "test0".to_string()).unwrap();
+        protection.check_and_record_block(5).unwrap();
+        assert!(protection.check_and_record_block(5).is_err());
+        assert!(protection.check_and_record_block(4).is_err());
+        protection.check_and_record_block(6).unwrap();
+
+        protection.check_and_record_chunk(0, 5).unwrap();
+        assert!(protection.check_and_record_chunk(0, 5).is_err());
+        protection.check_and_record_chunk(1, 5).unwrap();
+
+        // The records are read back from the file after a restart.
+        let protection = SlashingProtection::new(&path, "test0".to_string()).unwrap();
+        assert!(protection.check_and_record_block(6).is_err());
+        assert!(protection.check_and_record_chunk(1, 5).is_err());
+        protection.check_and_record_block(7).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_conflicting_approvals() {
+        let dir = tempfile::Builder::new().prefix("slashing_protection").tempdir().unwrap();
+        let protection =
+            SlashingProtection::new(&slashing_protection_file(&dir), "test0".to_string()).unwrap();
+        let endorsement = ApprovalInner::Endorsement(hash(&[1]));
+        protection.check_and_record_approval(&endorsement, 2).unwrap();
+        // Sending the same approval again is fine.
+        protection.check_and_record_approval(&endorsement, 2).unwrap();
+        assert!(protection
+            .check_and_record_approval(&ApprovalInner::Endorsement(hash(&[2])), 2)
+            .is_err());
+        assert!(protection.check_and_record_approval(&ApprovalInner::Skip(1), 2).is_err());
+        // Skips above the last endorsement are fine even for targets skipped before.
+        protection.check_and_record_approval(&ApprovalInner::Skip(1), 4).unwrap();
+        protection.check_and_record_approval(&ApprovalInner::Skip(2), 4).unwrap();
+        // But an endorsement for a skipped target is not.
+        assert!(protection
+            .check_and_record_approval(&ApprovalInner::Endorsement(hash(&[3])), 4)
+            .is_err());
+        protection.check_and_record_approval(&ApprovalInner::Endorsement(hash(&[3])), 5).unwrap();
+    }
+
+    #[test]
+    fn test_merge_records() {
+        let dir = tempfile::Builder::new().prefix("slashing_protection").tempdir().unwrap();
+        let path = slashing_protection_file(&dir);
+        let protection = SlashingProtection::new(&path, "test0".to_string()).unwrap();
+        protection.check_and_record_block(5).unwrap();
+
+        let other_dir = tempfile::Builder::new().prefix("slashing_protection").tempdir().unwrap();
+        let other_path = slashing_protection_file(&other_dir);
+        let other = SlashingProtection::new(&other_path, "test0".to_string()).unwrap();
+        other.check_and_record_block(3).unwrap();
+        other.check_and_record_chunk(0, 7).unwrap();
+        other.check_and_record_approval(&ApprovalInner::Endorsement(hash(&[1])), 8).unwrap();
+
+        let records = get_slashing_protection_records(&other_path).unwrap();
+        merge_slashing_protection_records(&path, &records).unwrap();
+        let record = SlashingProtection::new(&path, "test0".to_string()).unwrap().get_record();
+        assert_eq!(record.block_height, 5);
+        assert!(!record.can_sign_chunk(0, 7));
+        assert!(!record.can_sign_approval(&ApprovalInner::Skip(7), 8));
+        assert!(record.can_sign_approval(&ApprovalInner::Skip(7), 9));
+    }
+}
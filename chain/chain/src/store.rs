@@ -27,8 +27,9 @@ use near_primitives::transaction::{
 };
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{
-    AccountId, BlockExtra, BlockHeight, ChunkExtra, EpochId, GCCount, NumBlocks, ShardId,
-    StateChanges, StateChangesExt, StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
+    AccountId, BlockExtra, BlockHeight, ChunkExtra, EpochId, GCCount, NumBlocks,
+    RawStateChangesWithTrieKey, ShardId, StateChanges, StateChangesExt, StateChangesKinds,
+    StateChangesKindsExt, StateChangesRequest, StateRoot,
 };
 use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
 use near_primitives::views::LightClientBlockView;
@@ -39,8 +40,9 @@ use near_store::{
     ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount, ColIncomingReceipts,
     ColInvalidChunks, ColLastBlockWithNewChunk, ColNextBlockHashes, ColNextBlockWithNewChunk,
     ColOutcomeIds, ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights,
-    ColReceiptIdToShardId, ColReceipts, ColState, ColStateChanges, ColStateDlInfos,
-    ColStateHeaders, ColStateParts, ColTransactionResult, ColTransactions, ColTrieChanges, DBCol,
+    ColReceiptIdToShardId, ColReceipts, ColSplitStateRoots, ColSplitStateTrieChanges, ColState,
+    ColStateChanges, ColStateChangesForSplitStates, ColStateDlInfos, ColStateHeaders,
+    ColStateParts, ColTransactionResult, ColTransactions, ColTrieChanges, DBCol,
     KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
     CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
     LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC, TAIL_KEY,
@@ -247,6 +249,22 @@ pub trait ChainStoreAccess {
         block_hash: CryptoHash,
     ) -> Result<ShardStateSyncResponseHeader, Error>;
 
+    /// Returns the state roots of the shards split from the given shard after the given block of
+    /// the epoch before the shard layout changes.
+    fn get_split_state_roots(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error>;
+
+    /// Returns the changes of the state of the given shard in the given block of the epoch before
+    /// the shard layout changes, which are applied to the states of the shards split from it.
+    fn get_state_changes_for_split_states(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Vec<RawStateChangesWithTrieKey>, Error>;
+
     /// Returns latest known height and time it was seen.
     fn get_latest_known(&mut self) -> Result<LatestKnown, Error>;
 
@@ -871,6 +889,29 @@ impl ChainStoreAccess for ChainStore {
         }
     }
 
+    fn get_split_state_roots(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error> {
+        option_to_not_found(
+            self.store.get_ser(ColSplitStateRoots, &get_block_shard_id(block_hash, shard_id)),
+            &format!("SPLIT STATE ROOTS: {}:{}", block_hash, shard_id),
+        )
+    }
+
+    fn get_state_changes_for_split_states(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Vec<RawStateChangesWithTrieKey>, Error> {
+        option_to_not_found(
+            self.store
+                .get_ser(ColStateChangesForSplitStates, &get_block_shard_id(block_hash, shard_id)),
+            &format!("STATE CHANGES FOR SPLIT STATES: {}:{}", block_hash, shard_id),
+        )
+    }
+
     fn get_latest_known(&mut self) -> Result<LatestKnown, Error> {
         if self.latest_known.is_none() {
             self.latest_known = Some(option_to_not_found(
@@ -1135,6 +1176,9 @@ struct ChainStoreCacheUpdate {
     headers: HashMap<CryptoHash, BlockHeader>,
     block_extras: HashMap<CryptoHash, BlockExtra>,
     chunk_extras: HashMap<(CryptoHash, ShardId), ChunkExtra>,
+    split_state_roots: HashMap<(CryptoHash, ShardId), HashMap<ShardId, StateRoot>>,
+    state_changes_for_split_states: HashMap<(CryptoHash, ShardId), Vec<RawStateChangesWithTrieKey>>,
+    split_state_trie_changes: HashMap<(CryptoHash, ShardId), HashMap<ShardId, TrieChanges>>,
     chunks: HashMap<ChunkHash, ShardChunk>,
     partial_chunks: HashMap<ChunkHash, PartialEncodedChunk>,
     block_hash_per_height: HashMap<BlockHeight, HashMap<EpochId, HashSet<CryptoHash>>>,
@@ -1533,6 +1577,36 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
         self.chain_store.get_state_header(shard_id, block_hash)
     }
 
+    fn get_split_state_roots(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error> {
+        if let Some(state_roots) =
+            self.chain_store_cache_update.split_state_roots.get(&(*block_hash, shard_id))
+        {
+            Ok(state_roots.clone())
+        } else {
+            self.chain_store.get_split_state_roots(block_hash, shard_id)
+        }
+    }
+
+    fn get_state_changes_for_split_states(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Vec<RawStateChangesWithTrieKey>, Error> {
+        if let Some(changes) = self
+            .chain_store_cache_update
+            .state_changes_for_split_states
+            .get(&(*block_hash, shard_id))
+        {
+            Ok(changes.clone())
+        } else {
+            self.chain_store.get_state_changes_for_split_states(block_hash, shard_id)
+        }
+    }
+
     fn get_latest_known(&mut self) -> Result<LatestKnown, Error> {
         self.chain_store.get_latest_known()
     }
@@ -1791,6 +1865,45 @@ impl<'a> ChainStoreUpdate<'a> {
         self.chain_store_cache_update.chunk_extras.insert((*block_hash, shard_id), chunk_extra);
     }
 
+    /// Save the state roots of the shards split from the given shard after the block.
+    pub fn save_split_state_roots(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_roots: HashMap<ShardId, StateRoot>,
+    ) {
+        self.chain_store_cache_update
+            .split_state_roots
+            .insert((*block_hash, shard_id), state_roots);
+    }
+
+    /// Save the changes of the state of the given shard in the block, which are applied to the
+    /// states of the shards split from it.
+    pub fn save_state_changes_for_split_states(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        changes: Vec<RawStateChangesWithTrieKey>,
+    ) {
+        self.chain_store_cache_update
+            .state_changes_for_split_states
+            .insert((*block_hash, shard_id), changes);
+    }
+
+    /// Save the trie changes of the states of the shards split from the given shard in the block,
+    /// so they are reverted or applied when the block is garbage collected. The trie nodes they
+    /// insert are saved by the caller.
+    pub fn save_split_state_trie_changes(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        trie_changes: HashMap<ShardId, TrieChanges>,
+    ) {
+        self.chain_store_cache_update
+            .split_state_trie_changes
+            .insert((*block_hash, shard_id), trie_changes);
+    }
+
     pub fn save_chunk(&mut self, chunk: ShardChunk) {
         for transaction in chunk.transactions() {
             self.chain_store_cache_update.transactions.insert(transaction.clone());
@@ -1836,6 +1949,31 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(())
     }
 
+    /// Saves the consecutive headers downloaded by epoch sync. The ancestors of the first header
+    /// are not known, so its block merkle tree is given explicitly and the height index is
+    /// written directly instead of walking back to a known block.
+    pub fn save_epoch_sync_headers(
+        &mut self,
+        headers: Vec<BlockHeader>,
+        first_block_merkle_tree: PartialMerkleTree,
+    ) -> Result<(), Error> {
+        for (i, header) in headers.into_iter().enumerate() {
+            if i == 0 {
+                self.save_block_merkle_tree(*header.hash(), first_block_merkle_tree.clone());
+            } else {
+                self.update_and_save_block_merkle_tree(&header)?;
+                self.chain_store_cache_update
+                    .next_block_hashes
+                    .insert(*header.prev_hash(), *header.hash());
+            }
+            self.chain_store_cache_update
+                .height_to_hashes
+                .insert(header.height(), Some(*header.hash()));
+            self.chain_store_cache_update.headers.insert(*header.hash(), header);
+        }
+        Ok(())
+    }
+
     pub fn save_next_block_hash(&mut self, hash: &CryptoHash, next_hash: CryptoHash) {
         self.chain_store_cache_update.next_block_hashes.insert(hash.clone(), next_hash);
     }
@@ -2068,6 +2206,21 @@ impl<'a> ChainStoreUpdate<'a> {
                                 .map_err(|err| ErrorKind::Other(err.to_string()))
                         })
                         .unwrap_or(Ok(()))?;
+                    // The same for the states of the shards split from this shard
+                    let block_shard_id = get_block_shard_id(&block_hash, shard_id);
+                    if let Some(split_trie_changes) =
+                        self.store().get_ser::<HashMap<ShardId, TrieChanges>>(
+                            ColSplitStateTrieChanges,
+                            &block_shard_id,
+                        )?
+                    {
+                        for (split_shard_id, trie_changes) in split_trie_changes.iter() {
+                            tries
+                                .revert_insertions(trie_changes, *split_shard_id, &mut store_update)
+                                .map_err(|err| ErrorKind::Other(err.to_string()))?;
+                        }
+                        self.gc_col(ColSplitStateTrieChanges, &block_shard_id);
+                    }
                 }
             }
             GCMode::Canonical(tries) => {
@@ -2088,6 +2241,21 @@ impl<'a> ChainStoreUpdate<'a> {
                                 .map_err(|err| ErrorKind::Other(err.to_string()))
                         })
                         .unwrap_or(Ok(()))?;
+                    // The same for the states of the shards split from this shard
+                    let block_shard_id = get_block_shard_id(&block_hash, shard_id);
+                    if let Some(split_trie_changes) =
+                        self.store().get_ser::<HashMap<ShardId, TrieChanges>>(
+                            ColSplitStateTrieChanges,
+                            &block_shard_id,
+                        )?
+                    {
+                        for (split_shard_id, trie_changes) in split_trie_changes.iter() {
+                            tries
+                                .apply_deletions(trie_changes, *split_shard_id, &mut store_update)
+                                .map_err(|err| ErrorKind::Other(err.to_string()))?;
+                        }
+                        self.gc_col(ColSplitStateTrieChanges, &block_shard_id);
+                    }
                 }
                 // Set `block_hash` on previous one
                 block_hash = *self.get_block_header(&block_hash)?.prev_hash();
@@ -2096,6 +2264,10 @@ impl<'a> ChainStoreUpdate<'a> {
                 // Not apply the data from ColTrieChanges
                 for shard_id in 0..header.chunk_mask().len() as ShardId {
                     self.gc_col(ColTrieChanges, &get_block_shard_id(&block_hash, shard_id));
+                    self.gc_col(
+                        ColSplitStateTrieChanges,
+                        &get_block_shard_id(&block_hash, shard_id),
+                    );
                 }
             }
         }
@@ -2114,6 +2286,8 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_col(ColChunkPerHeightShard, &block_shard_id);
             self.gc_col(ColNextBlockWithNewChunk, &block_shard_id);
             self.gc_col(ColChunkExtra, &block_shard_id);
+            self.gc_col(ColSplitStateRoots, &block_shard_id);
+            self.gc_col(ColStateChangesForSplitStates, &block_shard_id);
 
             // For incoming State Parts it's done in chain.clear_downloaded_parts()
             // The following code is mostly for outgoing State Parts.
@@ -2366,6 +2540,11 @@ impl<'a> ChainStoreUpdate<'a> {
                 store_update.delete(col, key);
                 self.chain_store.chunk_extras.cache_remove(key);
             }
+            DBCol::ColSplitStateRoots
+            | DBCol::ColStateChangesForSplitStates
+            | DBCol::ColSplitStateTrieChanges => {
+                store_update.delete(col, key);
+            }
             DBCol::ColPartialChunks => {
                 store_update.delete(col, key);
                 self.chain_store.partial_chunks.cache_remove(key);
@@ -2426,7 +2605,8 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::ColEpochStart
             | DBCol::ColBlockOrdinal
             | DBCol::_ColTransactionRefCount
-            | DBCol::ColCachedContractCode => {
+            | DBCol::ColCachedContractCode
+            | DBCol::ColEpochValidatorStats => {
                 unreachable!();
             }
         }
@@ -2494,6 +2674,33 @@ impl<'a> ChainStoreUpdate<'a> {
                 chunk_extra,
             )?;
         }
+        for ((block_hash, shard_id), state_roots) in
+            self.chain_store_cache_update.split_state_roots.iter()
+        {
+            store_update.set_ser(
+                ColSplitStateRoots,
+                &get_block_shard_id(block_hash, *shard_id),
+                state_roots,
+            )?;
+        }
+        for ((block_hash, shard_id), changes) in
+            self.chain_store_cache_update.state_changes_for_split_states.iter()
+        {
+            store_update.set_ser(
+                ColStateChangesForSplitStates,
+                &get_block_shard_id(block_hash, *shard_id),
+                changes,
+            )?;
+        }
+        for ((block_hash, shard_id), trie_changes) in
+            self.chain_store_cache_update.split_state_trie_changes.iter()
+        {
+            store_update.set_ser(
+                ColSplitStateTrieChanges,
+                &get_block_shard_id(block_hash, *shard_id),
+                trie_changes,
+            )?;
+        }
         for (block_hash, block_extra) in self.chain_store_cache_update.block_extras.iter() {
             store_update.set_ser(ColBlockExtra, block_hash.as_ref(), block_extra)?;
         }
@@ -3164,6 +3371,8 @@ mod tests {
             DBCol::ColBlockRefCount,
             DBCol::ColOutcomeIds,
             DBCol::ColChunkExtra,
+            DBCol::ColSplitStateRoots,
+            DBCol::ColStateChangesForSplitStates,
         ];
         for col in DBCol::iter() {
             println!("current column is {:?}", col);
@@ -13,24 +13,27 @@ use near_crypto::{KeyType, PublicKey, SecretKey, Signature};
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::challenge::ChallengesResult;
+use near_primitives::epoch_manager::{BlockInfo, EpochInfo, EpochSyncData};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::serialize::to_base;
-use near_primitives::sharding::ChunkHash;
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::sharding::{ChunkHash, CongestionInfo};
 use near_primitives::transaction::{
     Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, SignedTransaction,
     TransferAction,
 };
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, EpochId, Gas, Nonce, NumBlocks, NumShards,
-    ShardId, StateRoot, StateRootNode, ValidatorStake,
+    RawStateChangesWithTrieKey, ShardId, StateRoot, StateRootNode, ValidatorStake,
 };
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
-    AccessKeyInfoView, AccessKeyList, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewStateResult,
+    AccessKeyInfoView, AccessKeyList, CallResult, EpochValidatorHistoryView, EpochValidatorInfo,
+    QueryError, QueryRequest, QueryResponse, QueryResponseKind, StorageStakingView,
+    StorageUsageView, ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
@@ -77,7 +80,7 @@ pub struct KeyValueRuntime {
 }
 
 pub fn account_id_to_shard_id(account_id: &AccountId, num_shards: NumShards) -> ShardId {
-    u64::from((hash(&account_id.clone().into_bytes()).0).0[0]) % num_shards
+    ShardLayout::v0(num_shards, 0).account_id_to_shard_id(account_id)
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize)]
@@ -259,7 +262,7 @@ impl KeyValueRuntime {
 
 impl RuntimeAdapter for KeyValueRuntime {
     fn genesis_state(&self) -> (Arc<Store>, Vec<StateRoot>) {
-        (self.store.clone(), ((0..self.num_shards()).map(|_| StateRoot::default()).collect()))
+        (self.store.clone(), ((0..self.num_shards).map(|_| StateRoot::default()).collect()))
     }
 
     fn get_tries(&self) -> ShardTries {
@@ -373,17 +376,17 @@ impl RuntimeAdapter for KeyValueRuntime {
         shard_id: ShardId,
     ) -> Result<AccountId, Error> {
         let validators = &self.validators[self.get_valset_for_epoch(epoch_id)?];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         let delta = ((shard_id + height + 1) % validators_per_shard) as usize;
         Ok(validators[offset + delta].account_id.clone())
     }
 
-    fn num_shards(&self) -> ShardId {
-        self.num_shards
+    fn num_shards(&self, _epoch_id: &EpochId) -> Result<NumShards, Error> {
+        Ok(self.num_shards)
     }
 
     fn num_total_parts(&self) -> usize {
@@ -400,8 +403,20 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
     }
 
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId {
-        account_id_to_shard_id(account_id, self.num_shards())
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        _epoch_id: &EpochId,
+    ) -> Result<ShardId, Error> {
+        Ok(account_id_to_shard_id(account_id, self.num_shards))
+    }
+
+    fn get_shard_layout(&self, _epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        Ok(ShardLayout::v0(self.num_shards, 0))
+    }
+
+    fn will_shard_layout_change(&self, _parent_hash: &CryptoHash) -> Result<bool, Error> {
+        Ok(false)
     }
 
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<String, Error> {
@@ -424,10 +439,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
         let validators = &self.validators[epoch_valset.1];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         assert!(offset + validators_per_shard as usize <= validators.len());
         if let Some(account_id) = account_id {
@@ -452,10 +467,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
         let validators = &self.validators[(epoch_valset.1 + 1) % self.validators.len()];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         if let Some(account_id) = account_id {
             for validator in validators[offset..offset + (validators_per_shard as usize)].iter() {
@@ -470,22 +485,36 @@ impl RuntimeAdapter for KeyValueRuntime {
     fn validate_tx(
         &self,
         _gas_price: Balance,
-        _state_update: Option<StateRoot>,
+        _shard_state_root: Option<(ShardId, StateRoot)>,
         _transaction: &SignedTransaction,
         _verify_signature: bool,
+        _block_height: BlockHeight,
+        _congestion_info: &CongestionInfo,
         _current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error> {
         Ok(None)
     }
 
+    fn get_actions_fee_gas(
+        &self,
+        _signer_id: &AccountId,
+        _receiver_id: &AccountId,
+        _actions: &[Action],
+        _current_protocol_version: ProtocolVersion,
+    ) -> Result<Gas, Error> {
+        Ok(0)
+    }
+
     fn prepare_transactions(
         &self,
         _gas_price: Balance,
         _gas_limit: Gas,
         _shard_id: ShardId,
         _state_root: StateRoot,
+        _next_block_height: BlockHeight,
         transactions: &mut dyn PoolIterator,
         _chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        _congestion_info: &CongestionInfo,
         _current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let mut res = vec![];
@@ -495,6 +524,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(res)
     }
 
+    fn get_delayed_receipts_count(
+        &self,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+    ) -> Result<u64, Error> {
+        Ok(0)
+    }
+
     fn add_validator_proposals(&self, _block_header_info: BlockHeaderInfo) -> Result<(), Error> {
         Ok(())
     }
@@ -514,6 +551,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _gas_limit: Gas,
         _challenges: &ChallengesResult,
         _random_seed: CryptoHash,
+        _congestion_info: &CongestionInfo,
         generate_storage_proof: bool,
     ) -> Result<ApplyTransactionResult, Error> {
         assert!(!generate_storage_proof);
@@ -525,7 +563,7 @@ impl RuntimeAdapter for KeyValueRuntime {
 
         for receipt in receipts.iter() {
             if let ReceiptEnum::Action(action) = &receipt.receipt {
-                assert_eq!(self.account_id_to_shard_id(&receipt.receiver_id), shard_id);
+                assert_eq!(account_id_to_shard_id(&receipt.receiver_id, self.num_shards), shard_id);
                 if !state.receipt_nonces.contains(&receipt.receipt_id) {
                     state.receipt_nonces.insert(receipt.receipt_id);
                     if let Action::Transfer(TransferAction { deposit }) = action.actions[0] {
@@ -546,7 +584,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
 
         for transaction in transactions {
-            assert_eq!(self.account_id_to_shard_id(&transaction.transaction.signer_id), shard_id);
+            assert_eq!(
+                account_id_to_shard_id(&transaction.transaction.signer_id, self.num_shards),
+                shard_id
+            );
             if transaction.transaction.actions.is_empty() {
                 continue;
             }
@@ -586,7 +627,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         for (hash, from, to, amount, nonce) in balance_transfers {
             let mut good_to_go = false;
 
-            if self.account_id_to_shard_id(&from) != shard_id {
+            if account_id_to_shard_id(&from, self.num_shards) != shard_id {
                 // This is a receipt, was already debited
                 good_to_go = true;
             } else if let Some(balance) = state.amounts.get(&from) {
@@ -598,7 +639,8 @@ impl RuntimeAdapter for KeyValueRuntime {
             }
 
             if good_to_go {
-                let new_receipt_hashes = if self.account_id_to_shard_id(&to) == shard_id {
+                let new_receipt_hashes = if account_id_to_shard_id(&to, self.num_shards) == shard_id
+                {
                     state.amounts.insert(to.clone(), state.amounts.get(&to).unwrap_or(&0) + amount);
                     vec![]
                 } else {
@@ -618,7 +660,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                     };
                     let receipt_hash = receipt.get_hash();
                     new_receipts
-                        .entry(self.account_id_to_shard_id(&receipt.receiver_id))
+                        .entry(account_id_to_shard_id(&receipt.receiver_id, self.num_shards))
                         .or_insert_with(|| vec![])
                         .push(receipt);
                     vec![receipt_hash]
@@ -693,6 +735,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _gas_limit: Gas,
         _challenges: &ChallengesResult,
         _random_value: CryptoHash,
+        _congestion_info: &CongestionInfo,
     ) -> Result<ApplyTransactionResult, Error> {
         unimplemented!();
     }
@@ -755,6 +798,52 @@ impl RuntimeAdapter for KeyValueRuntime {
                 block_height,
                 block_hash: *block_hash,
             }),
+            QueryRequest::ViewStorageStaking { account_id } => {
+                let amount = self
+                    .state
+                    .read()
+                    .unwrap()
+                    .get(&state_root)
+                    .map_or(0, |state| *state.amounts.get(account_id).unwrap_or(&0));
+                Ok(QueryResponse {
+                    kind: QueryResponseKind::StorageStaking(StorageStakingView {
+                        storage_usage: 0,
+                        storage_byte_cost: 0,
+                        storage_stake: 0,
+                        amount,
+                        locked: 0,
+                        locked_for_storage: 0,
+                        free_balance: amount,
+                    }),
+                    block_height,
+                    block_hash: *block_hash,
+                })
+            }
+            QueryRequest::ViewStorageUsage { .. } => Ok(QueryResponse {
+                kind: QueryResponseKind::StorageUsage(StorageUsageView {
+                    storage_usage: 0,
+                    account: 0,
+                    code: 0,
+                    abi: 0,
+                    num_access_keys: 0,
+                    access_keys: 0,
+                    data: vec![],
+                    truncated: false,
+                }),
+                block_height,
+                block_hash: *block_hash,
+            }),
+            QueryRequest::ViewContractAbi { account_id } => Ok(QueryResponse {
+                kind: QueryResponseKind::Error(QueryError {
+                    error: format!(
+                        "contract ABI for account {} does not exist while viewing",
+                        account_id
+                    ),
+                    logs: vec![],
+                }),
+                block_height,
+                block_hash: *block_hash,
+            }),
         }
     }
 
@@ -789,6 +878,26 @@ impl RuntimeAdapter for KeyValueRuntime {
         true
     }
 
+    fn build_split_states(
+        &self,
+        _block_hash: &CryptoHash,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error> {
+        Err(ErrorKind::Other("Resharding is not supported by KeyValueRuntime".to_string()).into())
+    }
+
+    fn apply_state_changes_to_split_states(
+        &self,
+        _block_hash: &CryptoHash,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+        _split_state_roots: &HashMap<ShardId, StateRoot>,
+        _changes: &[RawStateChangesWithTrieKey],
+    ) -> Result<HashMap<ShardId, TrieChanges>, Error> {
+        Err(ErrorKind::Other("Resharding is not supported by KeyValueRuntime".to_string()).into())
+    }
+
     fn confirm_state(
         &self,
         _shard_id: ShardId,
@@ -883,10 +992,32 @@ impl RuntimeAdapter for KeyValueRuntime {
         self.hash_to_valset.write().unwrap().contains_key(epoch_id)
     }
 
+    fn get_epoch_sync_data(
+        &self,
+        _prev_block_hash: &CryptoHash,
+    ) -> Result<Option<EpochSyncData>, Error> {
+        Ok(None)
+    }
+
+    fn save_epoch_sync_data(
+        &self,
+        _block_infos: Vec<(CryptoHash, BlockInfo)>,
+        _epoch_infos: Vec<(EpochId, EpochInfo)>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn get_epoch_minted_amount(&self, _epoch_id: &EpochId) -> Result<Balance, Error> {
         Ok(0)
     }
 
+    fn get_validator_rewards(
+        &self,
+        _last_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Balance>, Error> {
+        Ok(HashMap::new())
+    }
+
     fn get_epoch_protocol_version(&self, _epoch_id: &EpochId) -> Result<ProtocolVersion, Error> {
         Ok(PROTOCOL_VERSION)
     }
@@ -903,6 +1034,15 @@ impl RuntimeAdapter for KeyValueRuntime {
         })
     }
 
+    fn get_validator_history(
+        &self,
+        _block_hash: &CryptoHash,
+        _num_epochs: u64,
+        _account_id: Option<&AccountId>,
+    ) -> Result<Vec<EpochValidatorHistoryView>, Error> {
+        Ok(vec![])
+    }
+
     fn compare_epoch_id(
         &self,
         epoch_id: &EpochId,
@@ -1148,11 +1288,12 @@ impl ChainGenesis {
 
 #[cfg(test)]
 mod test {
-    use super::KeyValueRuntime;
+    use super::{account_id_to_shard_id, KeyValueRuntime};
     use crate::RuntimeAdapter;
     use borsh::BorshSerialize;
     use near_primitives::hash::{hash, CryptoHash};
     use near_primitives::receipt::Receipt;
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::sharding::ReceiptList;
     use near_primitives::types::NumShards;
     use near_store::test_utils::create_test_store;
@@ -1162,11 +1303,11 @@ mod test {
     impl KeyValueRuntime {
         fn naive_build_receipt_hashes(&self, receipts: &[Receipt]) -> Vec<CryptoHash> {
             let mut receipts_hashes = vec![];
-            for shard_id in 0..self.num_shards() {
+            for shard_id in 0..self.num_shards {
                 let shard_receipts: Vec<Receipt> = receipts
                     .iter()
                     .filter(|&receipt| {
-                        self.account_id_to_shard_id(&receipt.receiver_id) == shard_id
+                        account_id_to_shard_id(&receipt.receiver_id, self.num_shards) == shard_id
                     })
                     .cloned()
                     .collect();
@@ -1199,7 +1340,8 @@ mod test {
         let naive_result = runtime_adapter.naive_build_receipt_hashes(&receipts);
         let naive_duration = start.elapsed();
         let start = Instant::now();
-        let prod_result = runtime_adapter.build_receipts_hashes(&receipts);
+        let prod_result =
+            runtime_adapter.build_receipts_hashes(&receipts, &ShardLayout::v0(num_shards, 0));
         let prod_duration = start.elapsed();
         assert_eq!(naive_result, prod_result);
         // production implementation is at least 50% faster
@@ -9,22 +9,27 @@ use near_crypto::Signature;
 use near_pool::types::PoolIterator;
 pub use near_primitives::block::{Block, BlockHeader, Tip};
 use near_primitives::challenge::{ChallengesResult, SlashedValidator};
+use near_primitives::epoch_manager::{BlockInfo, EpochInfo, EpochSyncData};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
-use near_primitives::sharding::{ChunkHash, ReceiptList, ShardChunkHeader};
-use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::sharding::{ChunkHash, CongestionInfo, ReceiptList, ShardChunkHeader};
+use near_primitives::transaction::{Action, ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
-    NumBlocks, ShardId, StateRoot, StateRootNode, ValidatorStake,
+    NumBlocks, NumShards, RawStateChangesWithTrieKey, ShardId, StateRoot, StateRootNode,
+    ValidatorStake,
 };
 use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
-use near_store::{PartialStorage, ShardTries, Store, Trie, WrappedTrieChanges};
+use near_primitives::views::{
+    EpochValidatorHistoryView, EpochValidatorInfo, QueryRequest, QueryResponse,
+};
+use near_store::{PartialStorage, ShardTries, Store, Trie, TrieChanges, WrappedTrieChanges};
 
 use crate::error::Error;
 use chrono::{DateTime, Utc};
@@ -244,8 +249,12 @@ pub trait RuntimeAdapter: Send + Sync {
     ) -> Result<(), Error>;
 
     /// Validates a given signed transaction.
-    /// If the state root is given, then the verification will use the account. Otherwise it will
-    /// only validate the transaction math, limits and signatures.
+    /// If the state root of the signer's shard is given, then the verification will use the
+    /// account. Otherwise it will only validate the transaction math, limits and signatures.
+    /// Transactions whose receiver belongs to a shard congested according to `congestion_info`
+    /// are rejected.
+    /// `block_height` is the height of the block the transaction is going to be included in, it's
+    /// used to check the expiration of the access key.
     /// Returns an option of `InvalidTxError`, it contains `Some(InvalidTxError)` if there is
     /// a validation error, or `None` in case the transaction succeeded.
     /// Throws an `Error` with `ErrorKind::StorageError` in case the runtime throws
@@ -253,17 +262,31 @@ pub trait RuntimeAdapter: Send + Sync {
     fn validate_tx(
         &self,
         gas_price: Balance,
-        state_root: Option<StateRoot>,
+        shard_state_root: Option<(ShardId, StateRoot)>,
         transaction: &SignedTransaction,
         verify_signature: bool,
+        block_height: BlockHeight,
+        congestion_info: &CongestionInfo,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error>;
 
+    /// Gas burnt to create and execute a receipt with the given actions sent by `signer_id` to
+    /// `receiver_id`. Gas attached to function calls is not included.
+    fn get_actions_fee_gas(
+        &self,
+        signer_id: &AccountId,
+        receiver_id: &AccountId,
+        actions: &[Action],
+        current_protocol_version: ProtocolVersion,
+    ) -> Result<Gas, Error>;
+
     /// Returns an ordered list of valid transactions from the pool up the given limits.
     /// Pulls transactions from the given pool iterators one by one. Validates each transaction
     /// against the given `chain_validate` closure and runtime's transaction verifier.
     /// If the transaction is valid for both, it's added to the result and the temporary state
     /// update is preserved for validation of next transactions.
+    /// `next_block_height` is used to filter out transactions signed with expired access keys.
+    /// `congestion_info` is used to filter out transactions sent to congested shards.
     /// Throws an `Error` with `ErrorKind::StorageError` in case the runtime throws
     /// `RuntimeError::StorageError`.
     fn prepare_transactions(
@@ -272,11 +295,20 @@ pub trait RuntimeAdapter: Send + Sync {
         gas_limit: Gas,
         shard_id: ShardId,
         state_root: StateRoot,
+        next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
         chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        congestion_info: &CongestionInfo,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<SignedTransaction>, Error>;
 
+    /// Returns the number of receipts in the delayed receipts queue of the given shard state.
+    fn get_delayed_receipts_count(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+    ) -> Result<u64, Error>;
+
     /// Verify validator signature for the given epoch.
     /// Note: doesnt't account for slashed accounts within given epoch. USE WITH CAUTION.
     fn verify_validator_signature(
@@ -372,15 +404,26 @@ pub trait RuntimeAdapter: Send + Sync {
         account_id: &AccountId,
     ) -> Result<(ValidatorStake, bool), Error>;
 
-    /// Get current number of shards.
-    fn num_shards(&self) -> ShardId;
+    /// Get the number of shards in the epoch.
+    fn num_shards(&self, epoch_id: &EpochId) -> Result<NumShards, Error>;
 
     fn num_total_parts(&self) -> usize;
 
     fn num_data_parts(&self) -> usize;
 
-    /// Account Id to Shard Id mapping, given current number of shards.
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId;
+    /// Account Id to Shard Id mapping in the epoch.
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        epoch_id: &EpochId,
+    ) -> Result<ShardId, Error>;
+
+    /// Assignment of accounts to shards in the epoch.
+    fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error>;
+
+    /// Returns true if the epoch after the epoch of the given block has a different shard layout,
+    /// i.e. the shards are split at the end of the epoch.
+    fn will_shard_layout_change(&self, parent_hash: &CryptoHash) -> Result<bool, Error>;
 
     /// Returns `account_id` that suppose to have the `part_id` of all chunks given previous block hash.
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<AccountId, Error>;
@@ -432,9 +475,31 @@ pub trait RuntimeAdapter: Send + Sync {
     /// Check if epoch exists.
     fn epoch_exists(&self, epoch_id: &EpochId) -> bool;
 
+    /// Epoch manager records that a node bootstrapping with epoch sync needs to process the
+    /// blocks from the block after the given one on, if that block starts a new epoch.
+    /// Their hash is committed in the header of that block.
+    fn get_epoch_sync_data(
+        &self,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<Option<EpochSyncData>, Error>;
+
+    /// Saves the epoch manager records downloaded by epoch sync.
+    fn save_epoch_sync_data(
+        &self,
+        block_infos: Vec<(CryptoHash, BlockInfo)>,
+        epoch_infos: Vec<(EpochId, EpochInfo)>,
+    ) -> Result<(), Error>;
+
     /// Amount of tokens minted in given epoch.
     fn get_epoch_minted_amount(&self, epoch_id: &EpochId) -> Result<Balance, Error>;
 
+    /// Rewards paid to validators in the block that follows `last_block_hash`, which must be the
+    /// last block of an epoch.
+    fn get_validator_rewards(
+        &self,
+        last_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Balance>, Error>;
+
     /// Epoch active protocol version.
     fn get_epoch_protocol_version(&self, epoch_id: &EpochId) -> Result<ProtocolVersion, Error>;
 
@@ -443,6 +508,7 @@ pub trait RuntimeAdapter: Send + Sync {
 
     /// Apply transactions to given state root and return store update and new state root.
     /// Also returns transaction result for each transaction and new receipts.
+    /// Outgoing receipts to shards congested according to `congestion_info` are throttled.
     fn apply_transactions(
         &self,
         shard_id: ShardId,
@@ -458,6 +524,7 @@ pub trait RuntimeAdapter: Send + Sync {
         gas_limit: Gas,
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
+        congestion_info: &CongestionInfo,
     ) -> Result<ApplyTransactionResult, Error> {
         self.apply_transactions_with_optional_storage_proof(
             shard_id,
@@ -473,6 +540,7 @@ pub trait RuntimeAdapter: Send + Sync {
             gas_limit,
             challenges_result,
             random_seed,
+            congestion_info,
             false,
         )
     }
@@ -492,6 +560,7 @@ pub trait RuntimeAdapter: Send + Sync {
         gas_limit: Gas,
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
+        congestion_info: &CongestionInfo,
         generate_storage_proof: bool,
     ) -> Result<ApplyTransactionResult, Error>;
 
@@ -511,6 +580,7 @@ pub trait RuntimeAdapter: Send + Sync {
         gas_limit: Gas,
         challenges_result: &ChallengesResult,
         random_value: CryptoHash,
+        congestion_info: &CongestionInfo,
     ) -> Result<ApplyTransactionResult, Error>;
 
     /// Query runtime with given `path` and `data`.
@@ -527,6 +597,15 @@ pub trait RuntimeAdapter: Send + Sync {
 
     fn get_validator_info(&self, block_hash: &CryptoHash) -> Result<EpochValidatorInfo, Error>;
 
+    /// Performance of the validators in up to `num_epochs` finished epochs before the epoch of
+    /// the given block, latest first. Only includes the given validator if `account_id` is set.
+    fn get_validator_history(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+        account_id: Option<&AccountId>,
+    ) -> Result<Vec<EpochValidatorHistoryView>, Error>;
+
     /// Get the part of the state from given state root.
     fn obtain_state_part(
         &self,
@@ -546,6 +625,30 @@ pub trait RuntimeAdapter: Send + Sync {
         data: &Vec<u8>,
     ) -> bool;
 
+    /// Builds the states of the shards of the next epoch that are split from the given shard,
+    /// from its state after the given block. The states are saved to the store while they are
+    /// built, so it can take a while for large states.
+    /// Returns the state roots of the split shards.
+    fn build_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error>;
+
+    /// Applies the changes of the state of the given shard in the given block to the states of
+    /// the shards of the next epoch split from it, which are at `split_state_roots` before the
+    /// block. `state_root` is the state root of the shard after the block.
+    /// Returns the trie changes of the states of the split shards.
+    fn apply_state_changes_to_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        split_state_roots: &HashMap<ShardId, StateRoot>,
+        changes: &[RawStateChangesWithTrieKey],
+    ) -> Result<HashMap<ShardId, TrieChanges>, Error>;
+
     /// Should be executed after accepting all the parts to set up a new state.
     fn confirm_state(
         &self,
@@ -585,17 +688,22 @@ pub trait RuntimeAdapter: Send + Sync {
     /// Build receipts hashes.
     // Due to borsh serialization constraints, we have to use `&Vec<Receipt>` instead of `&[Receipt]`
     // here.
-    fn build_receipts_hashes(&self, receipts: &Vec<Receipt>) -> Vec<CryptoHash> {
-        if self.num_shards() == 1 {
+    fn build_receipts_hashes(
+        &self,
+        receipts: &Vec<Receipt>,
+        shard_layout: &ShardLayout,
+    ) -> Vec<CryptoHash> {
+        if shard_layout.num_shards() == 1 {
             return vec![hash(&ReceiptList(0, receipts).try_to_vec().unwrap())];
         }
         let mut account_id_to_shard_id = HashMap::new();
-        let mut shard_receipts: Vec<_> = (0..self.num_shards()).map(|i| (i, Vec::new())).collect();
+        let mut shard_receipts: Vec<_> =
+            shard_layout.shard_ids().map(|i| (i, Vec::new())).collect();
         for receipt in receipts.iter() {
             let shard_id = match account_id_to_shard_id.get(&receipt.receiver_id) {
                 Some(id) => *id,
                 None => {
-                    let id = self.account_id_to_shard_id(&receipt.receiver_id);
+                    let id = shard_layout.account_id_to_shard_id(&receipt.receiver_id);
                     account_id_to_shard_id.insert(receipt.receiver_id.clone(), id);
                     id
                 }
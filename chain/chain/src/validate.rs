@@ -11,13 +11,14 @@ use near_primitives::challenge::{
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::merklize;
 use near_primitives::sharding::{
-    ShardChunk, ShardChunkHeader, ShardChunkHeaderV1, ShardChunkHeaderV2,
+    ShardChunk, ShardChunkHeader, ShardChunkHeaderV1, ShardChunkHeaderV2, ShardChunkHeaderV3,
 };
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, ChunkExtra, EpochId, Nonce};
 use near_store::PartialStorage;
 
 use crate::byzantine_assert;
+use crate::chain::{get_congestion_info_from_chunk_headers, get_outgoing_receipts_for_shard};
 use crate::types::ApplyTransactionResult;
 use crate::{ChainStore, Error, ErrorKind, RuntimeAdapter};
 
@@ -25,12 +26,16 @@ use crate::{ChainStore, Error, ErrorKind, RuntimeAdapter};
 const GAS_LIMIT_ADJUSTMENT_FACTOR: u64 = 1000;
 
 /// Verifies that chunk's proofs in the header match the body.
-pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAdapter) -> bool {
+pub fn validate_chunk_proofs(
+    chunk: &ShardChunk,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> Result<bool, Error> {
     let correct_chunk_hash = match chunk {
         ShardChunk::V1(chunk) => ShardChunkHeaderV1::compute_hash(&chunk.header.inner),
         ShardChunk::V2(chunk) => match &chunk.header {
             ShardChunkHeader::V1(header) => ShardChunkHeaderV1::compute_hash(&header.inner),
             ShardChunkHeader::V2(header) => ShardChunkHeaderV2::compute_hash(&header.inner),
+            ShardChunkHeader::V3(header) => ShardChunkHeaderV3::compute_hash(&header.inner),
         },
     };
 
@@ -42,21 +47,18 @@ pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAd
     // 1. Checking chunk.header.hash
     if header_hash != correct_chunk_hash {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
 
     // 2. Checking that chunk body is valid
     // 2a. Checking chunk hash
     if chunk.chunk_hash() != correct_chunk_hash {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
-    let header_inner = match chunk {
-        ShardChunk::V1(chunk) => &chunk.header.inner,
-        ShardChunk::V2(chunk) => match &chunk.header {
-            ShardChunkHeader::V1(header) => &header.inner,
-            ShardChunkHeader::V2(header) => &header.inner,
-        },
+    let header_tx_root = match chunk {
+        ShardChunk::V1(chunk) => chunk.header.inner.tx_root,
+        ShardChunk::V2(chunk) => chunk.header.tx_root(),
     };
     let height_created = chunk.height_created();
     let outgoing_receipts_root = chunk.outgoing_receipts_root();
@@ -64,22 +66,25 @@ pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAd
 
     // 2b. Checking that chunk transactions are valid
     let (tx_root, _) = merklize(transactions);
-    if tx_root != header_inner.tx_root {
+    if tx_root != header_tx_root {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
     // 2c. Checking that chunk receipts are valid
     if height_created == 0 {
-        return receipts.len() == 0 && outgoing_receipts_root == CryptoHash::default();
+        return Ok(receipts.len() == 0 && outgoing_receipts_root == CryptoHash::default());
     } else {
-        let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(receipts);
+        let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(&chunk.prev_block_hash())?;
+        let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
+        let outgoing_receipts_hashes =
+            runtime_adapter.build_receipts_hashes(receipts, &shard_layout);
         let (receipts_root, _) = merklize(&outgoing_receipts_hashes);
         if receipts_root != outgoing_receipts_root {
             byzantine_assert!(false);
-            return false;
+            return Ok(false);
         }
     }
-    true
+    Ok(true)
 }
 
 /// Validates that the given transactions are in proper valid order.
@@ -148,12 +153,25 @@ pub fn validate_chunk_with_chunk_extra(
         return Err(ErrorKind::InvalidBalanceBurnt.into());
     }
 
-    let receipt_response = chain_store.get_outgoing_receipts_for_shard(
-        *prev_block_hash,
+    if let ShardChunkHeader::V3(_) = chunk_header {
+        let delayed_receipts_count = runtime_adapter
+            .get_delayed_receipts_count(chunk_header.shard_id(), &prev_chunk_extra.state_root)?;
+        if delayed_receipts_count != chunk_header.delayed_receipts_count() {
+            return Err(ErrorKind::InvalidDelayedReceiptsCount.into());
+        }
+    }
+
+    let receipt_response = get_outgoing_receipts_for_shard(
+        chain_store,
+        runtime_adapter,
+        prev_block_hash,
         chunk_header.shard_id(),
         prev_chunk_header.height_included(),
     )?;
-    let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(&receipt_response.1);
+    let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+    let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
+    let outgoing_receipts_hashes =
+        runtime_adapter.build_receipts_hashes(&receipt_response.1, &shard_layout);
     let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
 
     if outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
@@ -275,7 +293,7 @@ fn validate_chunk_proofs_challenge(
         MaybeEncodedShardChunk::Decoded(chunk) => chunk,
     };
 
-    if !validate_chunk_proofs(chunk_ref, &*runtime_adapter) {
+    if !validate_chunk_proofs(chunk_ref, &*runtime_adapter)? {
         // Chunk proofs are invalid. Good challenge.
         return account_to_slash_for_valid_challenge;
     }
@@ -319,6 +337,20 @@ fn validate_chunk_state_challenge(
         return Err(ErrorKind::MaliciousChallenge.into());
     }
 
+    // Validate the chunk headers the congestion prev chunk is applied with are derived from.
+    let prev_prev_block_header = BlockHeader::try_from_slice(&chunk_state.prev_prev_block_header)?;
+    if prev_prev_block_header.hash() != prev_block_header.prev_hash()
+        || Block::compute_chunk_headers_root(chunk_state.prev_prev_block_chunk_headers.iter()).0
+            != *prev_prev_block_header.chunk_headers_root()
+    {
+        return Err(ErrorKind::MaliciousChallenge.into());
+    }
+    let congestion_info = get_congestion_info_from_chunk_headers(
+        runtime_adapter,
+        &prev_prev_block_header,
+        chunk_state.prev_prev_block_chunk_headers.iter(),
+    )?;
+
     // Apply state transition and check that the result state and other data doesn't match.
     let partial_storage = PartialStorage { nodes: chunk_state.partial_state.clone() };
     let result = runtime_adapter
@@ -337,6 +369,7 @@ fn validate_chunk_state_challenge(
             prev_chunk_header.gas_limit(),
             &ChallengesResult::default(),
             *block_header.random_value(),
+            &congestion_info,
         )
         .map_err(|_| Error::from(ErrorKind::MaliciousChallenge))?;
     let outcome_root = ApplyTransactionResult::compute_outcomes_proof(&result.outcomes).0;
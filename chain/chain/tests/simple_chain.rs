@@ -55,6 +55,7 @@ fn build_chain_with_orhpans() {
         &*signer,
         last_block.header().next_bp_hash().clone(),
         CryptoHash::default(),
+        None,
     );
     assert_eq!(
         chain
@@ -25,6 +25,7 @@ use near_primitives::block::{BlockHeader, Tip};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
 use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart,
     PartialEncodedChunkV1, PartialEncodedChunkV2, ReceiptList, ReceiptProof, ReedSolomonWrapper,
@@ -510,7 +511,7 @@ impl ShardsManager {
 
         let shards_to_fetch_receipts =
         // TODO: only keep shards for which we don't have receipts yet
-            if request_full { HashSet::new() } else { self.get_tracking_shards(&parent_hash) };
+            if request_full { HashSet::new() } else { self.get_tracking_shards(&parent_hash)? };
 
         // The loop below will be sending PartialEncodedChunkRequestMsg to various block producers.
         // We need to send such a message to the original chunk producer if we do not have the receipts
@@ -580,8 +581,12 @@ impl ShardsManager {
         })
     }
 
-    fn get_tracking_shards(&self, parent_hash: &CryptoHash) -> HashSet<ShardId> {
-        (0..self.runtime_adapter.num_shards())
+    fn get_tracking_shards(
+        &self,
+        parent_hash: &CryptoHash,
+    ) -> Result<HashSet<ShardId>, near_chain::Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
+        Ok((0..self.runtime_adapter.num_shards(&epoch_id)?)
             .filter(|chunk_shard_id| {
                 self.cares_about_shard_this_or_next_epoch(
                     self.me.as_ref(),
@@ -590,7 +595,7 @@ impl ShardsManager {
                     true,
                 )
             })
-            .collect::<HashSet<_>>()
+            .collect::<HashSet<_>>())
     }
 
     fn request_chunk_single(
@@ -796,10 +801,11 @@ impl ShardsManager {
     pub fn group_receipts_by_shard(
         &self,
         receipts: Vec<Receipt>,
+        shard_layout: &ShardLayout,
     ) -> HashMap<ShardId, Vec<Receipt>> {
-        let mut result = HashMap::with_capacity(self.runtime_adapter.num_shards() as usize);
+        let mut result = HashMap::with_capacity(shard_layout.num_shards() as usize);
         for receipt in receipts {
-            let shard_id = self.runtime_adapter.account_id_to_shard_id(&receipt.receiver_id);
+            let shard_id = shard_layout.account_id_to_shard_id(&receipt.receiver_id);
             let entry = result.entry(shard_id).or_insert_with(Vec::new);
             entry.push(receipt)
         }
@@ -1388,6 +1394,16 @@ impl ShardsManager {
         )
     }
 
+    /// Returns the shard layout the outgoing receipts of the chunks built on the given block are
+    /// grouped with.
+    fn get_shard_layout_from_prev_block(
+        &self,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<ShardLayout, Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+        Ok(self.runtime_adapter.get_shard_layout(&epoch_id)?)
+    }
+
     fn need_part(&self, prev_block_hash: &CryptoHash, part_ord: u64) -> Result<bool, Error> {
         Ok(Some(self.runtime_adapter.get_part_owner(prev_block_hash, part_ord)?) == self.me)
     }
@@ -1397,8 +1413,8 @@ impl ShardsManager {
         prev_block_hash: &CryptoHash,
         chunk_entry: &EncodedChunksCacheEntry,
     ) -> Result<bool, Error> {
-        for shard_id in 0..self.runtime_adapter.num_shards() {
-            let shard_id = shard_id as ShardId;
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
             if !chunk_entry.receipts.contains_key(&shard_id) {
                 if self.need_receipt(&prev_block_hash, shard_id) {
                     return Ok(false);
@@ -1434,6 +1450,7 @@ impl ShardsManager {
         gas_used: Gas,
         gas_limit: Gas,
         balance_burnt: Balance,
+        delayed_receipts_count: u64,
         validator_proposals: Vec<ValidatorStake>,
         transactions: Vec<SignedTransaction>,
         outgoing_receipts: &Vec<Receipt>,
@@ -1453,6 +1470,7 @@ impl ShardsManager {
             gas_used,
             gas_limit,
             balance_burnt,
+            delayed_receipts_count,
             tx_root,
             validator_proposals,
             transactions,
@@ -1511,7 +1529,7 @@ impl ShardsManager {
             ShardChunkHeader::V1(header) => {
                 PartialEncodedChunk::V1(PartialEncodedChunkV1 { header, parts, receipts })
             }
-            header @ ShardChunkHeader::V2(_) => {
+            header @ ShardChunkHeader::V2(_) | header @ ShardChunkHeader::V3(_) => {
                 PartialEncodedChunk::V2(PartialEncodedChunkV2 { header, parts, receipts })
             }
         };
@@ -1532,7 +1550,7 @@ impl ShardsManager {
             .decode_chunk(self.runtime_adapter.num_data_parts())
             .map_err(|err| Error::from(err))
             .and_then(|shard_chunk| {
-                if !validate_chunk_proofs(&shard_chunk, &*self.runtime_adapter) {
+                if !validate_chunk_proofs(&shard_chunk, &*self.runtime_adapter)? {
                     return Err(Error::InvalidChunk);
                 }
                 Ok(shard_chunk)
@@ -1545,7 +1563,7 @@ impl ShardsManager {
                 merkle_paths,
                 shard_chunk.receipts().clone(),
                 &mut store_update,
-            );
+            )?;
 
             // Decoded a valid chunk, store it in the permanent store
             store_update.save_chunk(shard_chunk);
@@ -1571,17 +1589,18 @@ impl ShardsManager {
         merkle_paths: Vec<MerklePath>,
         outgoing_receipts: Vec<Receipt>,
         store_update: &mut ChainStoreUpdate<'_>,
-    ) {
+    ) -> Result<(), Error> {
         let header = encoded_chunk.cloned_header();
         let shard_id = header.shard_id();
+        let shard_layout = self.get_shard_layout_from_prev_block(&header.prev_block_hash())?;
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, &shard_layout);
         let (outgoing_receipts_root, outgoing_receipts_proofs) =
             merklize(&outgoing_receipts_hashes);
         assert_eq!(header.outgoing_receipts_root(), outgoing_receipts_root);
 
         // Save this chunk into encoded_chunks & process encoded chunk to add to the store.
-        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts);
+        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts, &shard_layout);
         let receipts = outgoing_receipts_proofs
             .into_iter()
             .enumerate()
@@ -1615,6 +1634,7 @@ impl ShardsManager {
 
         // Save this chunk into encoded_chunks.
         self.encoded_chunks.insert(cache_entry.header.chunk_hash(), cache_entry);
+        Ok(())
     }
 
     pub fn distribute_encoded_chunk(
@@ -1628,8 +1648,9 @@ impl ShardsManager {
         let chunk_header = encoded_chunk.cloned_header();
         let prev_block_hash = chunk_header.prev_block_hash();
         let shard_id = chunk_header.shard_id();
+        let shard_layout = self.get_shard_layout_from_prev_block(&prev_block_hash)?;
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, &shard_layout);
         let (outgoing_receipts_root, outgoing_receipts_proofs) =
             merklize(&outgoing_receipts_hashes);
         assert_eq!(chunk_header.outgoing_receipts_root(), outgoing_receipts_root);
@@ -1644,7 +1665,7 @@ impl ShardsManager {
             entry.push(part_ord);
         }
 
-        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts);
+        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts, &shard_layout);
         let receipt_proofs: Vec<_> = outgoing_receipts_proofs
             .into_iter()
             .enumerate()
@@ -1722,7 +1743,8 @@ mod test {
     use {
         crate::ACCEPTING_SEAL_PERIOD_MS, near_chain::ChainStore, near_chain::RuntimeAdapter,
         near_crypto::KeyType, near_logger_utils::init_test_logger,
-        near_primitives::merkle::merklize, near_primitives::sharding::ReedSolomonWrapper,
+        near_primitives::merkle::merklize, near_primitives::shard_layout::ShardLayout,
+        near_primitives::sharding::ReedSolomonWrapper,
         near_primitives::validator_signer::InMemoryValidatorSigner,
     };
 
@@ -1799,10 +1821,11 @@ mod test {
                 0,
                 0,
                 0,
+                0,
                 vec![],
                 vec![],
                 &vec![],
-                merklize(&runtime_adapter.build_receipts_hashes(&vec![])).0,
+                merklize(&runtime_adapter.build_receipts_hashes(&vec![], &ShardLayout::v0(1, 0))).0,
                 CryptoHash::default(),
                 &signer,
                 &mut rs,
@@ -206,7 +206,8 @@ impl Default for ChunkForwardingTestFixture {
             mock_network.clone(),
         );
         let receipts = Vec::new();
-        let receipts_hashes = mock_runtime.build_receipts_hashes(&receipts);
+        let shard_layout = mock_runtime.get_shard_layout(&mock_epoch_id).unwrap();
+        let receipts_hashes = mock_runtime.build_receipts_hashes(&receipts, &shard_layout);
         let (receipts_root, _) = merkle::merklize(&receipts_hashes);
         let (mock_chunk, mock_merkles) = producer_shard_manager
             .create_encoded_shard_chunk(
@@ -218,6 +219,7 @@ impl Default for ChunkForwardingTestFixture {
                 0,
                 1000,
                 0,
+                0,
                 Vec::new(),
                 Vec::new(),
                 &receipts,
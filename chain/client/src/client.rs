@@ -10,7 +10,8 @@ use cached::{Cached, SizedCache};
 use chrono::Utc;
 use log::{debug, error, info, warn};
 
-use near_chain::chain::TX_ROUTING_HEIGHT_HORIZON;
+use near_chain::chain::{get_prev_chunk_extra, get_prev_chunk_header, TX_ROUTING_HEIGHT_HORIZON};
+use near_chain::slashing_protection::SlashingProtection;
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, LatestKnown};
 use near_chain::{
@@ -37,7 +38,7 @@ use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::ValidatorSigner;
 
 use crate::metrics;
-use crate::sync::{BlockSync, HeaderSync, StateSync, StateSyncResult};
+use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
 use crate::types::{Error, ShardSyncDownload};
 use crate::SyncStatus;
 use near_primitives::block_header::ApprovalType;
@@ -67,11 +68,15 @@ pub struct Client {
     network_adapter: Arc<dyn NetworkAdapter>,
     /// Signer for block producer (if present).
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
+    /// Record of the blocks, chunks and approvals signed with `validator_signer`.
+    slashing_protection: Option<SlashingProtection>,
     /// Approvals for which we do not have the block yet
     pending_approvals: SizedCache<ApprovalInner, HashMap<AccountId, (Approval, ApprovalType)>>,
     /// A mapping from a block for which a state sync is underway for the next epoch, and the object
     /// storing the current status of the state sync
     pub catchup_state_syncs: HashMap<CryptoHash, (StateSync, HashMap<u64, ShardSyncDownload>)>,
+    /// Keeps track of epoch sync.
+    pub epoch_sync: EpochSync,
     /// Keeps track of syncing headers.
     pub header_sync: HeaderSync,
     /// Keeps track of syncing block.
@@ -119,11 +124,20 @@ impl Client {
         );
         let block_sync =
             BlockSync::new(network_adapter.clone(), config.block_fetch_horizon, config.archive);
-        let state_sync = StateSync::new(network_adapter.clone());
+        let state_sync = StateSync::new(network_adapter.clone(), config.state_parts_source.clone());
+        let epoch_sync = EpochSync::new(network_adapter.clone(), config.epoch_sync_enabled);
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = runtime_adapter.num_data_parts();
         let parity_parts = runtime_adapter.num_total_parts() - data_parts;
 
+        let slashing_protection =
+            match (validator_signer.as_ref(), config.slashing_protection_file.as_ref()) {
+                (Some(validator_signer), Some(path)) => {
+                    Some(SlashingProtection::new(path, validator_signer.validator_id().clone())?)
+                }
+                _ => None,
+            };
+
         let doomslug = Doomslug::new(
             chain.store().largest_target_height()?,
             config.min_block_production_delay,
@@ -132,7 +146,8 @@ impl Client {
             config.max_block_wait_delay,
             validator_signer.clone(),
             doomslug_threshold_mode,
-        );
+        )
+        .with_slashing_protection(slashing_protection.clone());
 
         Ok(Self {
             #[cfg(feature = "adversarial")]
@@ -147,8 +162,10 @@ impl Client {
             shards_mgr,
             network_adapter,
             validator_signer,
+            slashing_protection,
             pending_approvals: SizedCache::with_size(num_block_producer_seats),
             catchup_state_syncs: HashMap::new(),
+            epoch_sync,
             header_sync,
             block_sync,
             state_sync,
@@ -402,8 +419,12 @@ impl Client {
         block_merkle_tree.insert(prev_hash);
         let block_merkle_root = block_merkle_tree.root();
         let prev_block_extra = self.chain.get_block_extra(&prev_hash)?.clone();
-        let prev_block = self.chain.get_block(&prev_hash)?;
-        let mut chunks: Vec<_> = prev_block.chunks().iter().cloned().collect();
+        let prev_block = self.chain.get_block(&prev_hash)?.clone();
+        // Shards without a new chunk repeat their previous chunk, which is the chunk of the parent
+        // shard if the shards are split.
+        let mut chunks = (0..self.runtime_adapter.num_shards(&epoch_id)?)
+            .map(|shard_id| get_prev_chunk_header(&*self.runtime_adapter, &prev_block, shard_id))
+            .collect::<Result<Vec<_>, _>>()?;
 
         // Collect new chunks.
         for (shard_id, mut chunk_header) in new_chunks {
@@ -423,11 +444,20 @@ impl Client {
                 None
             };
 
+        let epoch_sync_data_hash = self
+            .runtime_adapter
+            .get_epoch_sync_data(&prev_hash)?
+            .map(|epoch_sync_data| epoch_sync_data.hash());
+
         // Get all the current challenges.
         // TODO(2445): Enable challenges when they are working correctly.
         // let challenges = self.challenges.drain().map(|(_, challenge)| challenge).collect();
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
+        if let Some(slashing_protection) = self.slashing_protection.as_ref() {
+            slashing_protection.check_and_record_block(next_height)?;
+        }
+
         let block = Block::produce(
             protocol_version,
             &prev_header,
@@ -445,6 +475,7 @@ impl Client {
             &*validator_signer,
             next_bp_hash,
             block_merkle_root,
+            epoch_sync_data_hash,
         );
 
         // Update latest known even before returning block out, to prevent race conditions.
@@ -497,14 +528,17 @@ impl Client {
             validator_signer.validator_id()
         );
 
-        let chunk_extra = self
-            .chain
-            .get_chunk_extra(&prev_block_hash, shard_id)
-            .map_err(|err| Error::ChunkProducer(format!("No chunk extra available: {}", err)))?
-            .clone();
+        let chunk_extra = get_prev_chunk_extra(
+            self.chain.mut_store(),
+            &*self.runtime_adapter,
+            &prev_block_hash,
+            shard_id,
+        )
+        .map_err(|err| Error::ChunkProducer(format!("No chunk extra available: {}", err)))?;
 
         let prev_block_header = self.chain.get_block_header(&prev_block_hash)?.clone();
-        let transactions = self.prepare_transactions(shard_id, &chunk_extra, &prev_block_header)?;
+        let transactions =
+            self.prepare_transactions(shard_id, &chunk_extra, &prev_block_header, next_height)?;
         let num_filtered_transactions = transactions.len();
         let (tx_root, _) = merklize(&transactions);
         let ReceiptResponse(_, outgoing_receipts) = self.chain.get_outgoing_receipts_for_shard(
@@ -525,11 +559,17 @@ impl Client {
         // 2. anyone who just asks for one's incoming receipts
         // will receive a piece of incoming receipts only
         // with merkle receipts proofs which can be checked locally
+        let shard_layout = self.runtime_adapter.get_shard_layout(epoch_id)?;
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, &shard_layout);
         let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(epoch_id)?;
+        let delayed_receipts_count =
+            self.runtime_adapter.get_delayed_receipts_count(shard_id, &chunk_extra.state_root)?;
+        if let Some(slashing_protection) = self.slashing_protection.as_ref() {
+            slashing_protection.check_and_record_chunk(shard_id, next_height)?;
+        }
         let (encoded_chunk, merkle_paths) = self.shards_mgr.create_encoded_shard_chunk(
             prev_block_hash,
             chunk_extra.state_root,
@@ -539,6 +579,7 @@ impl Client {
             chunk_extra.gas_used,
             chunk_extra.gas_limit,
             chunk_extra.balance_burnt,
+            delayed_receipts_count,
             chunk_extra.validator_proposals,
             transactions,
             &outgoing_receipts,
@@ -570,12 +611,14 @@ impl Client {
         shard_id: ShardId,
         chunk_extra: &ChunkExtra,
         prev_block_header: &BlockHeader,
+        next_height: BlockHeight,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let Self { chain, shards_mgr, runtime_adapter, .. } = self;
 
         let next_epoch_id =
             runtime_adapter.get_epoch_id_from_prev_block(&prev_block_header.hash())?;
         let protocol_version = runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
+        let congestion_info = chain.get_congestion_info(prev_block_header.hash())?;
 
         let transactions = if let Some(mut iter) = shards_mgr.get_pool_iterator(shard_id) {
             let transaction_validity_period = chain.transaction_validity_period;
@@ -584,6 +627,7 @@ impl Client {
                 chunk_extra.gas_limit,
                 shard_id,
                 chunk_extra.state_root.clone(),
+                next_height,
                 &mut iter,
                 &mut |tx: &SignedTransaction| -> bool {
                     chain
@@ -595,6 +639,7 @@ impl Client {
                         )
                         .is_ok()
                 },
+                &congestion_info,
                 protocol_version,
             )?
         } else {
@@ -609,7 +654,14 @@ impl Client {
     pub fn send_challenges(&mut self, challenges: Arc<RwLock<Vec<ChallengeBody>>>) {
         if let Some(validator_signer) = self.validator_signer.as_ref() {
             for body in challenges.write().unwrap().drain(..) {
+                let kind = match &body {
+                    ChallengeBody::BlockDoubleSign(_) => "block_double_sign",
+                    ChallengeBody::ChunkProofs(_) => "chunk_proofs",
+                    ChallengeBody::ChunkState(_) => "chunk_state",
+                };
                 let challenge = Challenge::produce(body, &**validator_signer);
+                info!(target: "client", "Sending {} challenge {} signed by {}", kind, challenge.hash, validator_signer.validator_id());
+                near_metrics::inc_counter_vec(&metrics::CHALLENGES_PRODUCED_TOTAL, &[kind]);
                 self.challenges.insert(challenge.hash, challenge.clone());
                 self.network_adapter.do_send(NetworkRequests::Challenge(challenge));
             }
@@ -663,35 +715,16 @@ impl Client {
             )
         };
 
-        // Send out challenges that accumulated via on_challenge.
-        self.send_challenges(challenges);
-
         // Send out challenge if the block was found to be invalid.
-        if let Some(validator_signer) = self.validator_signer.as_ref() {
-            match &result {
-                Err(e) => match e.kind() {
-                    near_chain::ErrorKind::InvalidChunkProofs(chunk_proofs) => {
-                        self.network_adapter.do_send(NetworkRequests::Challenge(
-                            Challenge::produce(
-                                ChallengeBody::ChunkProofs(*chunk_proofs),
-                                &**validator_signer,
-                            ),
-                        ));
-                    }
-                    near_chain::ErrorKind::InvalidChunkState(chunk_state) => {
-                        self.network_adapter.do_send(NetworkRequests::Challenge(
-                            Challenge::produce(
-                                ChallengeBody::ChunkState(*chunk_state),
-                                &**validator_signer,
-                            ),
-                        ));
-                    }
-                    _ => {}
-                },
-                _ => {}
+        if let Err(err) = &result {
+            if let Some(challenge_body) = err.challenge_body() {
+                challenges.write().unwrap().push(challenge_body);
             }
         }
 
+        // Send out challenges that accumulated via on_challenge.
+        self.send_challenges(challenges);
+
         if let Ok(Some(_)) = result {
             self.last_time_head_progress_made = Instant::now();
         }
@@ -1009,23 +1042,30 @@ impl Client {
                 }
             };
 
-            if provenance != Provenance::SYNC && !self.sync_status.is_syncing() {
+            // Fishermen only watch the chain and never produce chunks.
+            if provenance != Provenance::SYNC
+                && !self.sync_status.is_syncing()
+                && !self.config.fisherman
+            {
                 // Produce new chunks
-                for shard_id in 0..self.runtime_adapter.num_shards() {
-                    let epoch_id = self
-                        .runtime_adapter
-                        .get_epoch_id_from_prev_block(&block.header().hash())
-                        .unwrap();
+                let epoch_id = self
+                    .runtime_adapter
+                    .get_epoch_id_from_prev_block(&block.header().hash())
+                    .unwrap();
+                for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id).unwrap() {
                     let chunk_proposer = self
                         .runtime_adapter
                         .get_chunk_producer(&epoch_id, block.header().height() + 1, shard_id)
                         .unwrap();
 
                     if chunk_proposer == *validator_signer.validator_id() {
+                        let last_header =
+                            get_prev_chunk_header(&*self.runtime_adapter, &block, shard_id)
+                                .unwrap();
                         match self.produce_chunk(
                             *block.hash(),
                             &epoch_id,
-                            block.chunks()[shard_id as usize].clone(),
+                            last_header,
                             block.header().height() + 1,
                             shard_id,
                         ) {
@@ -1271,7 +1311,8 @@ impl Client {
 
     /// Forwards given transaction to upcoming validators.
     fn forward_tx(&self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id);
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, epoch_id)?;
         let head = self.chain.head()?;
         let maybe_next_epoch_id = self.get_next_epoch_id_if_at_boundary(&head)?;
 
@@ -1363,7 +1404,6 @@ impl Client {
     ) -> Result<NetworkClientResponses, Error> {
         let head = self.chain.head()?;
         let me = self.validator_signer.as_ref().map(|vs| vs.validator_id());
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id);
         let cur_block_header = self.chain.head_header()?.clone();
         let transaction_validity_period = self.chain.transaction_validity_period;
         // here it is fine to use `cur_block_header` as it is a best effort estimate. If the transaction
@@ -1379,12 +1419,23 @@ impl Client {
         }
         let gas_price = cur_block_header.gas_price();
         let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, &epoch_id)?;
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
+        let congestion_info = self.chain.get_congestion_info(&head.last_block_hash)?;
 
         if let Some(err) = self
             .runtime_adapter
-            .validate_tx(gas_price, None, &tx, true, protocol_version)
+            .validate_tx(
+                gas_price,
+                None,
+                &tx,
+                true,
+                head.height + 1,
+                &congestion_info,
+                protocol_version,
+            )
             .expect("no storage errors")
         {
             debug!(target: "client", "Invalid tx during basic validation: {:?}", err);
@@ -1394,7 +1445,12 @@ impl Client {
         if self.runtime_adapter.cares_about_shard(me, &head.last_block_hash, shard_id, true)
             || self.runtime_adapter.will_care_about_shard(me, &head.last_block_hash, shard_id, true)
         {
-            let state_root = match self.chain.get_chunk_extra(&head.last_block_hash, shard_id) {
+            let state_root = match get_prev_chunk_extra(
+                self.chain.mut_store(),
+                &*self.runtime_adapter,
+                &head.last_block_hash,
+                shard_id,
+            ) {
                 Ok(chunk_extra) => chunk_extra.state_root,
                 Err(_) => {
                     // Not being able to fetch a state root most likely implies that we haven't
@@ -1411,7 +1467,15 @@ impl Client {
             };
             if let Some(err) = self
                 .runtime_adapter
-                .validate_tx(gas_price, Some(state_root), &tx, false, protocol_version)
+                .validate_tx(
+                    gas_price,
+                    Some((shard_id, state_root)),
+                    &tx,
+                    false,
+                    head.height + 1,
+                    &congestion_info,
+                    protocol_version,
+                )
                 .expect("no storage errors")
             {
                 debug!(target: "client", "Invalid tx: {:?}", err);
@@ -1493,11 +1557,12 @@ impl Client {
         for (sync_hash, state_sync_info) in self.chain.store().iterate_state_sync_infos() {
             assert_eq!(sync_hash, state_sync_info.epoch_tail_hash);
             let network_adapter1 = self.network_adapter.clone();
+            let state_parts_source = self.config.state_parts_source.clone();
 
-            let (state_sync, new_shard_sync) = self
-                .catchup_state_syncs
-                .entry(sync_hash)
-                .or_insert_with(|| (StateSync::new(network_adapter1), HashMap::new()));
+            let (state_sync, new_shard_sync) =
+                self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
+                    (StateSync::new(network_adapter1, state_parts_source), HashMap::new())
+                });
 
             debug!(
                 target: "client",
@@ -1693,6 +1758,10 @@ mod test {
                 header.inner.prev_block_hash = hash(b"some_prev_block");
                 header.init();
             }
+            ShardChunkHeader::V3(ref mut header) => {
+                header.inner.prev_block_hash = hash(b"some_prev_block");
+                header.init();
+            }
         }
 
         #[cfg(feature = "protocol_feature_forward_chunk_parts")]
@@ -12,6 +12,7 @@ use log::{debug, error, info, trace, warn};
 
 #[cfg(feature = "delay_detector")]
 use delay_detector::DelayDetector;
+use near_chain::chain::get_split_shard_layout;
 use near_chain::test_utils::format_hash;
 use near_chain::types::AcceptedBlock;
 #[cfg(feature = "adversarial")]
@@ -32,6 +33,7 @@ use near_network::types::{NetworkInfo, ReasonForBan};
 use near_network::{
     NetworkAdapter, NetworkClientMessages, NetworkClientResponses, NetworkRequests,
 };
+use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::{BlockHeight, EpochId};
@@ -46,6 +48,7 @@ use near_telemetry::TelemetryActor;
 
 use crate::client::Client;
 use crate::info::{InfoHelper, ValidatorInfoHelper};
+use crate::metrics;
 use crate::sync::{highest_height_peer, StateSync, StateSyncResult};
 use crate::types::{
     Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
@@ -86,6 +89,8 @@ pub struct ClientActor {
     doomslug_timer_next_attempt: DateTime<Utc>,
     chunk_request_retry_next_attempt: DateTime<Utc>,
     sync_started: bool,
+    /// Whether the warning that the validator key isn't a fisherman was logged.
+    fisherman_warning_logged: bool,
 }
 
 /// Blocks the program until given genesis time arrives.
@@ -124,6 +129,17 @@ impl ClientActor {
         if let Some(vs) = &validator_signer {
             info!(target: "client", "Starting validator node: {}", vs.validator_id());
         }
+        if config.fisherman {
+            match &validator_signer {
+                Some(vs) => info!(target: "client", "Starting fisherman: {}", vs.validator_id()),
+                None => {
+                    warn!(target: "client", "Fisherman mode without a validator key, challenges will not be sent")
+                }
+            }
+            if config.tracked_shards.is_empty() && config.tracked_accounts.is_empty() {
+                warn!(target: "client", "Fisherman mode without tracked shards or accounts, no chunks will be validated");
+            }
+        }
         let info_helper = InfoHelper::new(telemetry_actor, &config, validator_signer.clone());
         let client = Client::new(
             config,
@@ -159,6 +175,7 @@ impl ClientActor {
             doomslug_timer_next_attempt: now,
             chunk_request_retry_next_attempt: now,
             sync_started: false,
+            fisherman_warning_logged: false,
         })
     }
 }
@@ -170,8 +187,8 @@ impl Actor for ClientActor {
         // Start syncing job.
         self.start_sync(ctx);
 
-        // Start block production tracking if have block producer info.
-        if self.client.validator_signer.is_some() {
+        // Start block production tracking if have block producer info and are not a fisherman.
+        if self.client.validator_signer.is_some() && !self.client.config.fisherman {
             self.block_production_started = true;
         }
 
@@ -292,6 +309,7 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 self.client.process_tx(transaction, is_forwarded, check_only)
             }
             NetworkClientMessages::Block(block, peer_id, was_requested) => {
+                self.client.block_sync.received_block(block.hash(), &peer_id);
                 let blocks_at_height = self
                     .client
                     .chain
@@ -339,6 +357,34 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     NetworkClientResponses::Ban { ban_reason: ReasonForBan::BadBlockHeader }
                 }
             }
+            NetworkClientMessages::EpochSyncResponse(peer_id, response) => {
+                if let Err(err) =
+                    self.client.epoch_sync.on_response(&mut self.client.chain, &peer_id, *response)
+                {
+                    error!(target: "sync", "Epoch sync: failed to process response from {}: {}", peer_id, err);
+                    if err.is_bad_data() {
+                        return NetworkClientResponses::Ban {
+                            ban_reason: ReasonForBan::BadBlockHeader,
+                        };
+                    }
+                }
+                NetworkClientResponses::NoResponse
+            }
+            NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response) => {
+                if let Err(err) = self.client.epoch_sync.on_finalization_response(
+                    &mut self.client.chain,
+                    &peer_id,
+                    *response,
+                ) {
+                    error!(target: "sync", "Epoch sync: failed to process finalization from {}: {}", peer_id, err);
+                    if err.is_bad_data() {
+                        return NetworkClientResponses::Ban {
+                            ban_reason: ReasonForBan::BadBlockHeader,
+                        };
+                    }
+                }
+                NetworkClientResponses::NoResponse
+            }
             NetworkClientMessages::BlockApproval(approval, peer_id) => {
                 self.client.collect_block_approval(&approval, ApprovalType::PeerApproval(peer_id));
                 NetworkClientResponses::NoResponse
@@ -706,8 +752,8 @@ impl ClientActor {
                 == Some(&next_block_producer_account)
             {
                 let num_chunks = self.client.shards_mgr.num_chunks_for_block(&head.last_block_hash);
-                let have_all_chunks =
-                    head.height == 0 || num_chunks == self.client.runtime_adapter.num_shards();
+                let have_all_chunks = head.height == 0
+                    || num_chunks == self.client.runtime_adapter.num_shards(&epoch_id)?;
 
                 if self.client.doomslug.ready_to_produce_block(
                     Instant::now(),
@@ -812,8 +858,10 @@ impl ClientActor {
         match chain_store_update.commit() {
             Ok(_) => {
                 let head = unwrap_or_return!(self.client.chain.head());
-                if self.client.is_validator(&head.epoch_id, &head.last_block_hash)
-                    || self.client.is_validator(&head.next_epoch_id, &head.last_block_hash)
+                // Fishermen only watch the chain and never approve blocks.
+                if !self.client.config.fisherman
+                    && (self.client.is_validator(&head.epoch_id, &head.last_block_hash)
+                        || self.client.is_validator(&head.next_epoch_id, &head.last_block_hash))
                 {
                     for approval in approvals {
                         if let Err(e) =
@@ -1004,6 +1052,7 @@ impl ClientActor {
 
     fn receive_headers(&mut self, headers: Vec<BlockHeader>, peer_id: PeerId) -> bool {
         info!(target: "client", "Received {} block headers from {}", headers.len(), peer_id);
+        self.client.header_sync.received_headers(&peer_id, &headers);
         if headers.len() == 0 {
             return true;
         }
@@ -1211,6 +1260,12 @@ impl ClientActor {
                 self.check_send_announce_account(head.prev_block_hash);
             }
             wait_period = self.client.config.sync_check_period;
+        } else if !unwrap_or_run_later!(self.client.epoch_sync.run(
+            &mut self.client.sync_status,
+            &mut self.client.chain,
+            &self.network_info.highest_height_peers
+        )) {
+            // Wait for epoch sync to reach the current epoch before syncing headers.
         } else {
             // Run each step of syncing separately.
             unwrap_or_run_later!(self.client.header_sync.run(
@@ -1251,7 +1306,13 @@ impl ClientActor {
                     };
 
                 let me = self.client.validator_signer.as_ref().map(|x| x.validator_id().clone());
-                let shards_to_sync = (0..self.client.runtime_adapter.num_shards())
+                let epoch_id = unwrap_or_run_later!(self
+                    .client
+                    .runtime_adapter
+                    .get_epoch_id_from_prev_block(&sync_hash));
+                let num_shards =
+                    unwrap_or_run_later!(self.client.runtime_adapter.num_shards(&epoch_id));
+                let shards_to_sync: Vec<_> = (0..num_shards)
                     .filter(|x| {
                         self.client.shards_mgr.cares_about_shard_this_or_next_epoch(
                             me.as_ref(),
@@ -1261,6 +1322,25 @@ impl ClientActor {
                         )
                     })
                     .collect();
+                // The synced states are the ones after the previous block of `sync_hash`, so if
+                // the shards are split after it, the states of their parent shards are synced.
+                let prev_hash =
+                    *unwrap_or_run_later!(self.client.chain.get_block_header(&sync_hash))
+                        .prev_hash();
+                let shards_to_sync = match unwrap_or_run_later!(get_split_shard_layout(
+                    &*self.client.runtime_adapter,
+                    &prev_hash
+                )) {
+                    Some(shard_layout) => {
+                        let mut parent_shard_ids: Vec<_> = shards_to_sync
+                            .into_iter()
+                            .map(|shard_id| shard_layout.get_parent_shard_id(shard_id))
+                            .collect();
+                        parent_shard_ids.dedup();
+                        parent_shard_ids
+                    }
+                    None => shards_to_sync,
+                };
 
                 if !self.client.config.archive && just_enter_state_sync {
                     unwrap_or_run_later!(self.client.chain.reset_data_pre_state_sync(sync_hash));
@@ -1345,6 +1425,31 @@ impl ClientActor {
         });
     }
 
+    /// Checks that the account of the validator key is a fisherman in the current epoch. The
+    /// warning that it isn't is only logged once.
+    fn log_fisherman_status(&mut self, head: &Tip) {
+        let is_fisherman = match self.client.validator_signer.as_ref() {
+            Some(validator_signer) => {
+                match self.client.runtime_adapter.get_fisherman_by_account_id(
+                    &head.epoch_id,
+                    &head.last_block_hash,
+                    validator_signer.validator_id(),
+                ) {
+                    Ok((_, is_slashed)) => !is_slashed,
+                    Err(_) => {
+                        if !self.fisherman_warning_logged {
+                            warn!(target: "client", "{} is not a fisherman in epoch {:?}, challenges will be rejected", validator_signer.validator_id(), head.epoch_id);
+                            self.fisherman_warning_logged = true;
+                        }
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+        near_metrics::set_gauge(&metrics::IS_FISHERMAN, is_fisherman as i64);
+    }
+
     /// Periodically log summary.
     fn log_summary(&self, ctx: &mut Context<Self>) {
         ctx.run_later(self.client.config.log_summary_period, move |act, ctx| {
@@ -1377,6 +1482,9 @@ impl ClientActor {
             } else {
                 None
             };
+            if act.client.config.fisherman {
+                act.log_fisherman_status(&head);
+            }
 
             act.info_helper.info(
                 act.client.chain.store().get_genesis_height(),
@@ -177,6 +177,9 @@ fn display_sync_status(
     match sync_status {
         SyncStatus::AwaitingPeers => format!("#{:>8} Waiting for peers", head.height),
         SyncStatus::NoSync => format!("#{:>8} {:>44}", head.height, head.last_block_hash),
+        SyncStatus::EpochSync { verified_epochs } => {
+            format!("#{:>8} Epoch sync: {} epochs verified", head.height, verified_epochs)
+        }
         SyncStatus::HeaderSync { current_height, highest_height } => {
             let percent = if *highest_height <= genesis_height {
                 0
@@ -205,7 +208,15 @@ fn display_sync_status(
                         shard_id,
                         match shard_status.status {
                             ShardSyncStatus::StateDownloadHeader => format!("header"),
-                            ShardSyncStatus::StateDownloadParts => format!("parts"),
+                            ShardSyncStatus::StateDownloadParts => format!(
+                                "parts {}/{}",
+                                shard_status
+                                    .downloads
+                                    .iter()
+                                    .filter(|download| download.done)
+                                    .count(),
+                                shard_status.downloads.len()
+                            ),
                             ShardSyncStatus::StateDownloadFinalize => format!("finalization"),
                             ShardSyncStatus::StateDownloadComplete => format!("done"),
                         }
@@ -4,11 +4,12 @@ extern crate lazy_static;
 pub use crate::client::Client;
 pub use crate::client_actor::{start_client, ClientActor};
 pub use crate::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetReceipt, GetStateChanges, GetStateChangesInBlock,
-    GetValidatorInfo, GetValidatorOrdered, Query, Status, StatusResponse, SyncStatus, TxStatus,
-    TxStatusError,
+    Error, EstimateFee, GetAccountBalanceChanges, GetBlock, GetBlockProof, GetBlockProofResponse,
+    GetBlockWithMerkleTree, GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse,
+    GetExecutionOutcomesForBlock, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetReceipt,
+    GetStateChanges, GetStateChangesInBlock, GetValidatorHistory, GetValidatorInfo,
+    GetValidatorOrdered, Query, ShardSyncDownload, ShardSyncStatus, Status, StatusResponse,
+    SyncStatus, TxStatus, TxStatusError,
 };
 #[cfg(feature = "adversarial")]
 pub use crate::view_client::AdversarialControls;
@@ -1,6 +1,6 @@
 use near_metrics::{
-    try_create_histogram, try_create_int_counter, try_create_int_gauge, Histogram, IntCounter,
-    IntGauge,
+    try_create_histogram, try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge,
+    try_create_int_gauge_vec, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 
 lazy_static! {
@@ -26,4 +26,26 @@ lazy_static! {
         try_create_int_gauge("near_memory_usage_bytes", "Amount of RAM memory usage");
     pub static ref GC_TIME: near_metrics::Result<Histogram> =
         try_create_histogram("near_gc_time", "Time taken to do garbage collection");
+    pub static ref SYNC_PEER_ITEMS_PER_SECOND: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_sync_peer_items_per_second",
+            "Number of headers or blocks per second received from the fastest peer during sync",
+            &["kind"]
+        );
+    pub static ref SYNC_PEER_REQUEST_TIMEOUTS_TOTAL: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_sync_peer_request_timeouts_total",
+            "Number of header or block requests to peers that timed out during sync",
+            &["kind"]
+        );
+    pub static ref CHALLENGES_PRODUCED_TOTAL: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_challenges_produced_total",
+            "Number of challenges signed and broadcast by this node",
+            &["kind"]
+        );
+    pub static ref IS_FISHERMAN: near_metrics::Result<IntGauge> = try_create_int_gauge(
+        "near_is_fisherman",
+        "Bool to denote if this node runs in fisherman mode with a fisherman account"
+    );
 }
@@ -1,8 +1,8 @@
 use near_chain::{ChainStoreAccess, Error};
 use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::{ops::Add, time::Duration as TimeDuration};
 
 use ansi_term::Color::{Purple, Yellow};
@@ -12,15 +12,24 @@ use log::{debug, error, info, warn};
 use rand::seq::{IteratorRandom, SliceRandom};
 use rand::{thread_rng, Rng};
 
-use near_chain::{Chain, RuntimeAdapter};
+use near_chain::chain::get_split_shard_layout;
+use near_chain::{
+    get_epoch_block_producers_view, validate_light_client_block, Chain, RuntimeAdapter,
+};
+use near_chain_configs::StatePartsSource;
 use near_network::types::{AccountOrPeerIdOrHash, NetworkResponses, ReasonForBan};
 use near_network::{FullPeerInfo, NetworkAdapter, NetworkRequests};
-use near_primitives::block::Tip;
+use near_primitives::block::{BlockHeader, Tip};
 use near_primitives::hash::CryptoHash;
-use near_primitives::syncing::get_num_state_parts;
-use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, ShardId};
+use near_primitives::network::PeerId;
+use near_primitives::syncing::{
+    get_num_state_parts, get_state_part_file_path, EpochSyncFinalizationResponse, EpochSyncResponse,
+};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, EpochId, ShardId};
 use near_primitives::utils::to_timestamp;
+use near_primitives::views::{LightClientBlockView, ValidatorStakeView};
 
+use crate::metrics;
 use crate::types::{DownloadStatus, ShardSyncDownload, ShardSyncStatus, SyncStatus};
 use cached::{Cached, SizedCache};
 
@@ -42,6 +51,30 @@ pub const MAX_PENDING_PART: u64 = MAX_STATE_PART_REQUEST * 10000;
 
 pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
+/// Number of the fastest peers the consecutive header ranges are requested from in turn.
+pub const MAX_HEADER_SYNC_PEERS: usize = 3;
+
+/// Maximum number of blocks requested from a single peer at the same time during block sync.
+pub const MAX_PENDING_BLOCK_REQUESTS_PER_PEER: usize = 4;
+
+/// Maximum number of heights after the head that block sync looks at to find blocks to request.
+pub const BLOCK_SYNC_WINDOW: u64 = 64;
+
+/// Maximum number of state parts fetched from the state parts source at the same time.
+pub const MAX_PENDING_EXTERNAL_PART: usize = 16;
+
+/// Maximum size of a state part fetched from the state parts source.
+const MAX_STATE_PART_SIZE: usize = 100 * 1024 * 1024;
+
+/// Epoch sync request timeout in seconds.
+pub const EPOCH_SYNC_REQUEST_TIMEOUT: i64 = 10;
+
+/// Number of distinct peers that must report an epoch as the current one before epoch sync
+/// stops walking the epochs, unless there are no other peers to ask.
+pub const EPOCH_SYNC_CURRENT_EPOCH_CONFIRMATIONS: usize = 2;
+
+const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
+
 /// Get random peer from the hightest height peers.
 pub fn highest_height_peer(highest_height_peers: &Vec<FullPeerInfo>) -> Option<FullPeerInfo> {
     if highest_height_peers.len() == 0 {
@@ -54,13 +87,305 @@ pub fn highest_height_peer(highest_height_peers: &Vec<FullPeerInfo>) -> Option<F
     }
 }
 
+/// Statistics of the responses of a peer to the sync requests.
+#[derive(Default)]
+struct SyncPeerStats {
+    /// Number of headers or blocks received from the peer.
+    received: u64,
+    /// Total time it took the peer to respond, in milliseconds.
+    total_latency_ms: i64,
+    /// Number of requests to the peer that timed out.
+    timed_out: u64,
+}
+
+impl SyncPeerStats {
+    fn items_per_second(&self) -> f64 {
+        self.received as f64 * 1000.0 / std::cmp::max(self.total_latency_ms, 1) as f64
+    }
+}
+
+/// Tracks the throughput of the peers serving sync requests, so that the fastest peers are used.
+struct SyncPeerTracker {
+    /// Kind of the requested data, either "headers" or "blocks", used as the metrics label.
+    kind: &'static str,
+    peers: HashMap<PeerId, SyncPeerStats>,
+}
+
+impl SyncPeerTracker {
+    fn new(kind: &'static str) -> Self {
+        SyncPeerTracker { kind, peers: HashMap::new() }
+    }
+
+    fn response_received(&mut self, peer_id: &PeerId, num_items: u64, latency: Duration) {
+        let stats = self.peers.entry(peer_id.clone()).or_default();
+        stats.received += num_items;
+        stats.total_latency_ms += latency.num_milliseconds();
+        let best_items_per_second =
+            self.peers.values().map(SyncPeerStats::items_per_second).fold(0.0, f64::max);
+        near_metrics::set_gauge_vec(
+            &metrics::SYNC_PEER_ITEMS_PER_SECOND,
+            &[self.kind],
+            best_items_per_second as i64,
+        );
+    }
+
+    fn request_timed_out(&mut self, peer_id: &PeerId) {
+        self.peers.entry(peer_id.clone()).or_default().timed_out += 1;
+        near_metrics::inc_counter_vec(&metrics::SYNC_PEER_REQUEST_TIMEOUTS_TOTAL, &[self.kind]);
+    }
+
+    /// Throughput of the peer discounted by its timeouts. The peers that didn't respond yet come
+    /// first, so that every peer gets measured.
+    fn score(&self, peer_id: &PeerId) -> f64 {
+        match self.peers.get(peer_id) {
+            Some(stats) if stats.received > 0 => {
+                stats.items_per_second() / (1 + stats.timed_out) as f64
+            }
+            Some(stats) if stats.timed_out > 0 => 0.0,
+            _ => std::f64::INFINITY,
+        }
+    }
+
+    /// Returns at most `limit` peers with the best score, in random order among equal scores.
+    fn best_peers(&self, mut peers: Vec<FullPeerInfo>, limit: usize) -> Vec<FullPeerInfo> {
+        peers.shuffle(&mut thread_rng());
+        peers.sort_by(|a, b| {
+            self.score(&b.peer_info.id)
+                .partial_cmp(&self.score(&a.peer_info.id))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        peers.truncate(limit);
+        peers
+    }
+}
+
+/// Helper to bootstrap a new node with epoch sync.
+/// Walks the epochs from genesis one light client block at a time: the light client block of an
+/// epoch proves the block producers of the next epoch with the approvals of the already verified
+/// block producers of the epoch. Once the current epoch is reached, the headers around its first
+/// block and the matching epoch infos are downloaded, and header sync and state sync continue
+/// from the first block of the current epoch instead of genesis.
+/// Each request goes to a random peer that hasn't failed a request yet, and an epoch is taken as
+/// the current one only after several peers report that it is not finished.
+pub struct EpochSync {
+    network_adapter: Arc<dyn NetworkAdapter>,
+    /// Last epoch with verified block producers.
+    epoch_id: EpochId,
+    /// Verified ordered block producers of `epoch_id`.
+    block_producers: Vec<ValidatorStakeView>,
+    /// Verified light client block of the epoch before `epoch_id` and the block producers of
+    /// that epoch. `None` while `epoch_id` is the genesis epoch.
+    prev_epoch: Option<(LightClientBlockView, Vec<ValidatorStakeView>)>,
+    verified_epochs: u64,
+    /// Peers that reported that `epoch_id` is the current epoch.
+    current_epoch_confirmations: HashSet<PeerId>,
+    /// Set once enough peers reported that `epoch_id` is the current epoch.
+    reached_current_epoch: bool,
+    /// Peers that timed out or sent an invalid response, they are not asked again.
+    failed_peers: HashSet<PeerId>,
+    /// Peer of the request in flight and when it was sent.
+    pending_request: Option<(PeerId, DateTime<Utc>)>,
+    done: bool,
+}
+
+impl EpochSync {
+    pub fn new(network_adapter: Arc<dyn NetworkAdapter>, enabled: bool) -> Self {
+        EpochSync {
+            network_adapter,
+            epoch_id: EpochId::default(),
+            block_producers: vec![],
+            prev_epoch: None,
+            verified_epochs: 0,
+            current_epoch_confirmations: HashSet::new(),
+            reached_current_epoch: false,
+            failed_peers: HashSet::new(),
+            pending_request: None,
+            done: !enabled,
+        }
+    }
+
+    /// Sends the next epoch sync request if needed. Returns true once epoch sync is finished
+    /// and the other sync steps can run.
+    pub fn run(
+        &mut self,
+        sync_status: &mut SyncStatus,
+        chain: &mut Chain,
+        highest_height_peers: &Vec<FullPeerInfo>,
+    ) -> Result<bool, near_chain::Error> {
+        if self.done {
+            return Ok(true);
+        }
+        // The genesis block producers are not known yet, so this is the first run.
+        if self.block_producers.is_empty() {
+            // Only a node without any headers after genesis bootstraps with epoch sync.
+            if chain.header_head()?.height > chain.genesis().height() {
+                self.done = true;
+                return Ok(true);
+            }
+            self.block_producers = get_epoch_block_producers_view(
+                &self.epoch_id,
+                chain.genesis().hash(),
+                &*chain.runtime_adapter,
+            )?;
+        }
+
+        let now = Utc::now();
+        if let Some((peer_id, requested_at)) = self.pending_request.take() {
+            if now - requested_at < Duration::seconds(EPOCH_SYNC_REQUEST_TIMEOUT) {
+                self.pending_request = Some((peer_id, requested_at));
+                return Ok(false);
+            }
+            debug!(target: "sync", "Epoch sync: request to {} timed out", peer_id);
+            self.failed_peers.insert(peer_id);
+        }
+
+        *sync_status = SyncStatus::EpochSync { verified_epochs: self.verified_epochs };
+        let peer = highest_height_peers
+            .iter()
+            .filter(|peer| {
+                !self.failed_peers.contains(&peer.peer_info.id)
+                    && (self.reached_current_epoch
+                        || !self.current_epoch_confirmations.contains(&peer.peer_info.id))
+            })
+            .choose(&mut thread_rng());
+        let peer_id = match peer {
+            Some(peer) => peer.peer_info.id.clone(),
+            None => {
+                if !self.reached_current_epoch && !self.current_epoch_confirmations.is_empty() {
+                    // No other peer can confirm that the epoch is not finished.
+                    self.on_current_epoch_reached();
+                } else {
+                    // Every peer failed, give them another chance.
+                    self.failed_peers.clear();
+                }
+                return Ok(false);
+            }
+        };
+        let epoch_id = self.epoch_id.clone();
+        let request = if self.reached_current_epoch {
+            NetworkRequests::EpochSyncFinalizationRequest { epoch_id, peer_id: peer_id.clone() }
+        } else {
+            NetworkRequests::EpochSyncRequest { epoch_id, peer_id: peer_id.clone() }
+        };
+        self.network_adapter.do_send(request);
+        self.pending_request = Some((peer_id, now));
+        Ok(false)
+    }
+
+    fn on_current_epoch_reached(&mut self) {
+        if self.verified_epochs == 0 {
+            // The chain is still in its first epoch, there is nothing to skip.
+            self.done = true;
+        } else {
+            self.reached_current_epoch = true;
+        }
+    }
+
+    fn take_pending_request(&mut self, peer_id: &PeerId) -> bool {
+        match &self.pending_request {
+            Some((pending_peer_id, _)) if pending_peer_id == peer_id => {
+                self.pending_request = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Verifies the light client block of the current epoch and moves on to the next epoch.
+    pub fn on_response(
+        &mut self,
+        chain: &mut Chain,
+        peer_id: &PeerId,
+        response: EpochSyncResponse,
+    ) -> Result<(), near_chain::Error> {
+        if self.done
+            || self.reached_current_epoch
+            || response.epoch_id != self.epoch_id
+            || !self.take_pending_request(peer_id)
+        {
+            debug!(target: "sync", "Epoch sync: ignoring unrequested response from {}", peer_id);
+            return Ok(());
+        }
+
+        let light_client_block = match response.light_client_block {
+            Some(light_client_block) => light_client_block,
+            None => {
+                self.current_epoch_confirmations.insert(peer_id.clone());
+                if self.current_epoch_confirmations.len() >= EPOCH_SYNC_CURRENT_EPOCH_CONFIRMATIONS
+                {
+                    self.on_current_epoch_reached();
+                }
+                return Ok(());
+            }
+        };
+        if let Err(err) =
+            validate_light_client_block(&self.epoch_id, &self.block_producers, &light_client_block)
+        {
+            self.failed_peers.insert(peer_id.clone());
+            return Err(err);
+        }
+        // The peers that reported the epoch as the current one are behind.
+        self.failed_peers.extend(self.current_epoch_confirmations.drain());
+
+        // Keep the verified light client blocks to serve epoch sync to other nodes.
+        let mut chain_store_update = chain.mut_store().store_update();
+        chain_store_update
+            .save_epoch_light_client_block(&self.epoch_id.0, light_client_block.clone());
+        chain_store_update.commit()?;
+
+        let next_block_producers = light_client_block.next_bps.clone().unwrap_or_default();
+        let block_producers = std::mem::replace(&mut self.block_producers, next_block_producers);
+        self.epoch_id = EpochId(light_client_block.inner_lite.next_epoch_id);
+        self.prev_epoch = Some((light_client_block, block_producers));
+        self.verified_epochs += 1;
+        debug!(target: "sync", "Epoch sync: verified {} epochs, next epoch {:?}", self.verified_epochs, self.epoch_id);
+        Ok(())
+    }
+
+    /// Saves the headers and epoch infos at the start of the current epoch and finishes epoch sync.
+    pub fn on_finalization_response(
+        &mut self,
+        chain: &mut Chain,
+        peer_id: &PeerId,
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<(), near_chain::Error> {
+        if self.done || !self.reached_current_epoch || !self.take_pending_request(peer_id) {
+            debug!(target: "sync", "Epoch sync: ignoring unrequested finalization from {}", peer_id);
+            return Ok(());
+        }
+        let (light_client_block, prev_block_producers) = match &self.prev_epoch {
+            Some(prev_epoch) => prev_epoch,
+            None => return Ok(()),
+        };
+        if let Err(err) = chain.apply_epoch_sync_finalization(
+            &self.epoch_id,
+            prev_block_producers,
+            light_client_block,
+            response,
+        ) {
+            self.failed_peers.insert(peer_id.clone());
+            return Err(err);
+        }
+        info!(target: "sync", "Epoch sync: done after verifying {} epochs, syncing from epoch {:?}", self.verified_epochs, self.epoch_id);
+        self.done = true;
+        Ok(())
+    }
+}
+
 /// Helper to keep track of sync headers.
 /// Handles major re-orgs by finding closest header that matches and re-downloading headers from that point.
+/// Each header range is requested from a single peer. Once a peer returns a full range, the next
+/// range, starting after its last header, is requested right away from another of the fastest
+/// peers, while the received headers are processed.
 pub struct HeaderSync {
     network_adapter: Arc<dyn NetworkAdapter>,
     history_locator: Vec<(BlockHeight, CryptoHash)>,
     prev_header_sync: (DateTime<Utc>, BlockHeight, BlockHeight, BlockHeight),
-    syncing_peer: Option<FullPeerInfo>,
+    /// The fastest peers the header ranges are requested from.
+    syncing_peers: Vec<FullPeerInfo>,
+    /// When the pending header range request was sent to the peer.
+    pending_requests: HashMap<PeerId, DateTime<Utc>>,
+    peer_tracker: SyncPeerTracker,
     stalling_ts: Option<DateTime<Utc>>,
 
     initial_timeout: Duration,
@@ -81,7 +406,9 @@ impl HeaderSync {
             network_adapter,
             history_locator: vec![],
             prev_header_sync: (Utc::now(), 0, 0, 0),
-            syncing_peer: None,
+            syncing_peers: vec![],
+            pending_requests: HashMap::new(),
+            peer_tracker: SyncPeerTracker::new("headers"),
             stalling_ts: None,
             initial_timeout: Duration::from_std(initial_timeout).unwrap(),
             progress_timeout: Duration::from_std(progress_timeout).unwrap(),
@@ -106,7 +433,7 @@ impl HeaderSync {
             SyncStatus::HeaderSync { .. }
             | SyncStatus::BodySync { .. }
             | SyncStatus::StateSyncDone => true,
-            SyncStatus::NoSync | SyncStatus::AwaitingPeers => {
+            SyncStatus::NoSync | SyncStatus::AwaitingPeers | SyncStatus::EpochSync { .. } => {
                 debug!(target: "sync", "Sync: initial transition to Header sync. Header head {} at {}",
                     header_head.last_block_hash, header_head.height,
                 );
@@ -119,17 +446,54 @@ impl HeaderSync {
         if enable_header_sync {
             *sync_status =
                 SyncStatus::HeaderSync { current_height: header_head.height, highest_height };
-            self.syncing_peer = None;
-            if let Some(peer) = highest_height_peer(&highest_height_peers) {
-                if peer.chain_info.height > header_head.height {
-                    self.syncing_peer = self.request_headers(chain, peer);
-                }
-            }
+            let peers = highest_height_peers
+                .iter()
+                .filter(|peer| peer.chain_info.height > header_head.height)
+                .cloned()
+                .collect();
+            let peers = self.peer_tracker.best_peers(peers, MAX_HEADER_SYNC_PEERS);
+            self.syncing_peers = self.request_headers(chain, peers);
         }
 
         Ok(())
     }
 
+    /// Records the response of the peer to the headers request and requests the next range of
+    /// headers if the peer returned a full range.
+    pub fn received_headers(&mut self, peer_id: &PeerId, headers: &[BlockHeader]) {
+        let requested_at = match self.pending_requests.remove(peer_id) {
+            Some(requested_at) => requested_at,
+            None => return,
+        };
+        self.peer_tracker.response_received(
+            peer_id,
+            headers.len() as u64,
+            Utc::now() - requested_at,
+        );
+        let last_header = match headers.last() {
+            Some(last_header) if headers.len() >= MAX_BLOCK_HEADERS as usize => last_header,
+            _ => return,
+        };
+        // The next range goes to another peer, the same one is used only if no other peer has it.
+        let peer = self
+            .syncing_peers
+            .iter()
+            .filter(|peer| {
+                peer.chain_info.height > last_header.height()
+                    && !self.pending_requests.contains_key(&peer.peer_info.id)
+            })
+            .min_by_key(|peer| &peer.peer_info.id == peer_id)
+            .cloned();
+        if let Some(peer) = peer {
+            debug!(target: "sync", "Sync: request headers: asking {} for headers after {}", peer.peer_info.id, last_header.height());
+            self.network_adapter.do_send(NetworkRequests::BlockHeadersRequest {
+                hashes: vec![*last_header.hash()],
+                peer_id: peer.peer_info.id.clone(),
+            });
+            self.pending_requests.insert(peer.peer_info.id, Utc::now());
+        }
+    }
+
     fn compute_expected_height(
         &self,
         old_height: BlockHeight,
@@ -151,20 +515,27 @@ impl HeaderSync {
         let (timeout, old_expected_height, prev_height, prev_highest_height) =
             self.prev_header_sync;
 
-        // Received all necessary header, can request more.
-        let all_headers_received =
-            header_head.height >= min(prev_height + MAX_BLOCK_HEADERS - 4, prev_highest_height);
+        // Received all necessary header, can request more. The next range may be already requested.
+        let all_headers_received = header_head.height
+            >= min(prev_height + MAX_BLOCK_HEADERS - 4, prev_highest_height)
+            && self.pending_requests.is_empty();
 
         // Did we receive as many headers as we expected from the peer? Request more or ban peer.
         let stalling = header_head.height <= old_expected_height && now > timeout;
 
         // Always enable header sync on initial state transition from NoSync / NoSyncFewBlocksBehind / AwaitingPeers.
         let force_sync = match sync_status {
-            SyncStatus::NoSync | SyncStatus::AwaitingPeers => true,
+            SyncStatus::NoSync | SyncStatus::AwaitingPeers | SyncStatus::EpochSync { .. } => true,
             _ => false,
         };
 
         if force_sync || all_headers_received || stalling {
+            // The peers that didn't respond in time lose their priority.
+            for (peer_id, requested_at) in self.pending_requests.drain() {
+                if now - requested_at > self.initial_timeout {
+                    self.peer_tracker.request_timed_out(&peer_id);
+                }
+            }
             self.prev_header_sync = (
                 now + self.initial_timeout,
                 self.compute_expected_height(header_head.height, self.initial_timeout),
@@ -184,30 +555,31 @@ impl HeaderSync {
                 self.stalling_ts = None;
             } else {
                 if let Some(ref stalling_ts) = self.stalling_ts {
-                    if let Some(ref peer) = self.syncing_peer {
-                        match sync_status {
-                            SyncStatus::HeaderSync { highest_height, .. } => {
-                                if now > *stalling_ts + self.stall_ban_timeout
-                                    && *highest_height == peer.chain_info.height
-                                {
+                    if let SyncStatus::HeaderSync { highest_height, .. } = sync_status {
+                        if now > *stalling_ts + self.stall_ban_timeout {
+                            let mut banned = false;
+                            for peer in self.syncing_peers.iter() {
+                                if *highest_height == peer.chain_info.height {
                                     warn!(target: "sync", "Sync: ban a fraudulent peer: {}, claimed height: {}",
                                         peer.peer_info, peer.chain_info.height);
                                     self.network_adapter.do_send(NetworkRequests::BanPeer {
                                         peer_id: peer.peer_info.id.clone(),
                                         ban_reason: ReasonForBan::HeightFraud,
                                     });
-                                    // This peer is fraudulent, let's skip this beat and wait for
-                                    // the next one when this peer is not in the list anymore.
-                                    self.syncing_peer = None;
-                                    return false;
+                                    banned = true;
                                 }
                             }
-                            _ => (),
+                            if banned {
+                                // These peers are fraudulent, let's skip this beat and wait for
+                                // the next one when these peers are not in the list anymore.
+                                self.syncing_peers.clear();
+                                return false;
+                            }
                         }
                     }
                 }
             }
-            self.syncing_peer = None;
+            self.syncing_peers.clear();
             true
         } else {
             // Resetting the timeout as long as we make progress.
@@ -230,17 +602,27 @@ impl HeaderSync {
         }
     }
 
-    /// Request headers from a given peer to advance the chain.
-    fn request_headers(&mut self, chain: &mut Chain, peer: FullPeerInfo) -> Option<FullPeerInfo> {
+    /// Request headers from the best of the given peers to advance the chain. The other peers
+    /// serve the following ranges.
+    fn request_headers(
+        &mut self,
+        chain: &mut Chain,
+        peers: Vec<FullPeerInfo>,
+    ) -> Vec<FullPeerInfo> {
+        let peer = match peers.first() {
+            Some(peer) => peer,
+            None => return vec![],
+        };
         if let Ok(locator) = self.get_locator(chain) {
             debug!(target: "sync", "Sync: request headers: asking {} for headers, {:?}", peer.peer_info.id, locator);
             self.network_adapter.do_send(NetworkRequests::BlockHeadersRequest {
                 hashes: locator,
                 peer_id: peer.peer_info.id.clone(),
             });
-            return Some(peer);
+            self.pending_requests.insert(peer.peer_info.id.clone(), Utc::now());
+            return peers;
         }
-        None
+        vec![]
     }
 
     fn get_locator(&mut self, chain: &mut Chain) -> Result<Vec<CryptoHash>, near_chain::Error> {
@@ -316,14 +698,18 @@ fn get_locator_heights(height: u64) -> Vec<u64> {
 
 pub struct BlockSyncRequest {
     height: BlockHeight,
-    hash: CryptoHash,
+    peer_id: PeerId,
     when: DateTime<Utc>,
 }
 
 /// Helper to track block syncing.
+/// The blocks after the head are requested from several peers at once, the fastest peers are
+/// preferred and the timed out requests are sent to other peers.
 pub struct BlockSync {
     network_adapter: Arc<dyn NetworkAdapter>,
-    last_request: Option<BlockSyncRequest>,
+    /// Requested blocks that weren't received yet.
+    pending_requests: HashMap<CryptoHash, BlockSyncRequest>,
+    peer_tracker: SyncPeerTracker,
     /// How far to fetch blocks vs fetch state.
     block_fetch_horizon: BlockHeightDelta,
     /// Whether to enforce block sync
@@ -336,7 +722,13 @@ impl BlockSync {
         block_fetch_horizon: BlockHeightDelta,
         archive: bool,
     ) -> Self {
-        BlockSync { network_adapter, last_request: None, block_fetch_horizon, archive }
+        BlockSync {
+            network_adapter,
+            pending_requests: HashMap::new(),
+            peer_tracker: SyncPeerTracker::new("blocks"),
+            block_fetch_horizon,
+            archive,
+        }
     }
 
     /// Runs check if block sync is needed, if it's needed and it's too far - sync state is started instead (returning true).
@@ -348,11 +740,9 @@ impl BlockSync {
         highest_height: BlockHeight,
         highest_height_peers: &[FullPeerInfo],
     ) -> Result<bool, near_chain::Error> {
-        if self.block_sync_due(chain)? {
-            if self.block_sync(chain, highest_height_peers)? {
-                debug!(target: "sync", "Sync: transition to State Sync.");
-                return Ok(true);
-            }
+        if self.block_sync(chain, highest_height_peers)? {
+            debug!(target: "sync", "Sync: transition to State Sync.");
+            return Ok(true);
         }
 
         let head = chain.head()?;
@@ -384,8 +774,14 @@ impl BlockSync {
         Ok(false)
     }
 
+    /// Whether the block was received, even if it can't be processed yet.
+    fn block_received(chain: &Chain, hash: &CryptoHash) -> Result<bool, near_chain::Error> {
+        Ok(chain.is_orphan(hash) || chain.is_chunk_orphan(hash) || chain.block_exists(hash)?)
+    }
+
     /// Returns true if state download is required (last known block is too far).
-    /// Otherwise request recent blocks from peers round robin.
+    /// Otherwise request the blocks after the head from the peers, splitting them between the
+    /// peers with the best throughput.
     pub fn block_sync(
         &mut self,
         chain: &mut Chain,
@@ -395,59 +791,106 @@ impl BlockSync {
             return Ok(true);
         }
 
-        let reference_hash = match &self.last_request {
-            Some(request) if chain.is_chunk_orphan(&request.hash) => request.hash,
-            _ => chain.head()?.last_block_hash,
-        };
-
-        let next_hash = match chain.mut_store().get_next_block_hash(&reference_hash) {
-            Ok(hash) => *hash,
-            Err(e) => match e.kind() {
-                near_chain::ErrorKind::DBNotFoundErr(_) => {
-                    return Ok(false);
-                }
-                _ => return Err(e),
-            },
-        };
-        let next_height = chain.get_block_header(&next_hash)?.height();
-
-        let request = BlockSyncRequest { height: next_height, hash: next_hash, when: Utc::now() };
-
+        let now = Utc::now();
         let head = chain.head()?;
         let header_head = chain.header_head()?;
 
-        debug!(target: "sync", "Block sync: {}/{} requesting block {} from {} peers", head.height, header_head.height, next_hash, highest_height_peers.len());
+        // Forget the requests of the received blocks and collect the timed out ones.
+        let mut to_request = vec![];
+        for (hash, request) in std::mem::take(&mut self.pending_requests) {
+            if request.height <= head.height || Self::block_received(chain, &hash)? {
+                continue;
+            }
+            if now - request.when > Duration::seconds(BLOCK_REQUEST_TIMEOUT) {
+                self.peer_tracker.request_timed_out(&request.peer_id);
+                to_request.push((hash, request.height, Some(request.peer_id)));
+            } else {
+                self.pending_requests.insert(hash, request);
+            }
+        }
 
-        let gc_stop_height = chain.runtime_adapter.get_gc_stop_height(&header_head.last_block_hash);
+        // Find the next blocks that were neither received nor requested.
+        let max_pending_requests = highest_height_peers.len() * MAX_PENDING_BLOCK_REQUESTS_PER_PEER;
+        let mut hash = head.last_block_hash;
+        for _ in 0..BLOCK_SYNC_WINDOW {
+            if self.pending_requests.len() + to_request.len() >= max_pending_requests {
+                break;
+            }
+            hash = match chain.mut_store().get_next_block_hash(&hash) {
+                Ok(hash) => *hash,
+                Err(e) => match e.kind() {
+                    near_chain::ErrorKind::DBNotFoundErr(_) => break,
+                    _ => return Err(e),
+                },
+            };
+            if self.pending_requests.contains_key(&hash)
+                || to_request.iter().any(|(requested_hash, _, _)| requested_hash == &hash)
+                || Self::block_received(chain, &hash)?
+            {
+                continue;
+            }
+            to_request.push((hash, chain.get_block_header(&hash)?.height(), None));
+        }
+        if to_request.is_empty() {
+            return Ok(false);
+        }
 
-        let request_from_archival = self.archive && request.height < gc_stop_height;
-        let peer = if request_from_archival {
-            let archival_peer_iter = highest_height_peers.iter().filter(|p| p.chain_info.archival);
-            archival_peer_iter.choose(&mut rand::thread_rng())
-        } else {
-            let peer_iter = highest_height_peers.iter();
-            peer_iter.choose(&mut rand::thread_rng())
-        };
+        debug!(target: "sync", "Block sync: {}/{} requesting {} blocks from {} peers", head.height, header_head.height, to_request.len(), highest_height_peers.len());
 
-        if let Some(peer) = peer {
-            self.network_adapter.do_send(NetworkRequests::BlockRequest {
-                hash: request.hash,
-                peer_id: peer.peer_info.id.clone(),
-            });
+        let gc_stop_height = chain.runtime_adapter.get_gc_stop_height(&header_head.last_block_hash);
+        // The garbage collected blocks are served only by the archival peers, and the blocks after
+        // them can't be processed before them, so nothing is requested without an archival peer.
+        if self.archive
+            && to_request.iter().any(|(_, height, _)| *height < gc_stop_height)
+            && !highest_height_peers.iter().any(|peer| peer.chain_info.archival)
+        {
+            debug!(target: "sync", "Block sync: no archival peers to request the blocks below {} from", gc_stop_height);
+            return Ok(false);
+        }
+        let mut num_pending_by_peer: HashMap<PeerId, usize> = HashMap::new();
+        for request in self.pending_requests.values() {
+            *num_pending_by_peer.entry(request.peer_id.clone()).or_default() += 1;
         }
 
-        self.last_request = Some(request);
+        for (hash, height, timed_out_peer_id) in to_request {
+            let request_from_archival = self.archive && height < gc_stop_height;
+            let mut peers = highest_height_peers
+                .iter()
+                .filter(|peer| !request_from_archival || peer.chain_info.archival)
+                .filter(|peer| {
+                    num_pending_by_peer.get(&peer.peer_info.id).cloned().unwrap_or_default()
+                        < MAX_PENDING_BLOCK_REQUESTS_PER_PEER
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            // Send the timed out request to another peer if there is one.
+            if peers.len() > 1 {
+                peers.retain(|peer| Some(&peer.peer_info.id) != timed_out_peer_id.as_ref());
+            }
+            let peer =
+                self.peer_tracker.best_peers(peers, usize::max_value()).into_iter().min_by_key(
+                    |peer| num_pending_by_peer.get(&peer.peer_info.id).cloned().unwrap_or_default(),
+                );
+            if let Some(peer) = peer {
+                let peer_id = peer.peer_info.id;
+                self.network_adapter
+                    .do_send(NetworkRequests::BlockRequest { hash, peer_id: peer_id.clone() });
+                *num_pending_by_peer.entry(peer_id.clone()).or_default() += 1;
+                self.pending_requests.insert(hash, BlockSyncRequest { height, peer_id, when: now });
+            }
+        }
 
         Ok(false)
     }
 
-    /// Check if we should run block body sync and ask for more full blocks.
-    fn block_sync_due(&mut self, chain: &Chain) -> Result<bool, near_chain::Error> {
-        match &self.last_request {
-            None => Ok(true),
-            Some(request) => Ok(chain.head()?.height >= request.height
-                || chain.is_chunk_orphan(&request.hash)
-                || Utc::now() - request.when > Duration::seconds(BLOCK_REQUEST_TIMEOUT)),
+    /// Records the response of the peer to the block request.
+    pub fn received_block(&mut self, hash: &CryptoHash, peer_id: &PeerId) {
+        if let Some(request) = self.pending_requests.get(hash) {
+            if &request.peer_id == peer_id {
+                let latency = Utc::now() - request.when;
+                self.peer_tracker.response_received(peer_id, 1, latency);
+                self.pending_requests.remove(hash);
+            }
         }
     }
 }
@@ -479,9 +922,21 @@ impl PendingRequestStatus {
     }
 }
 
+/// State part fetched from the state parts source, not yet validated.
+struct FetchedStatePart {
+    sync_hash: CryptoHash,
+    shard_id: ShardId,
+    part_id: u64,
+    data: Result<Vec<u8>, String>,
+}
+
 /// Helper to track state sync.
 pub struct StateSync {
     network_adapter: Arc<dyn NetworkAdapter>,
+    /// If set, the state parts are fetched from this location instead of the peers.
+    parts_source: Option<StatePartsSource>,
+    /// The state parts fetched from `parts_source` since the previous step.
+    fetched_parts: Arc<Mutex<Vec<FetchedStatePart>>>,
 
     state_sync_time: HashMap<ShardId, DateTime<Utc>>,
     last_time_block_requested: Option<DateTime<Utc>>,
@@ -492,9 +947,14 @@ pub struct StateSync {
 }
 
 impl StateSync {
-    pub fn new(network_adapter: Arc<dyn NetworkAdapter>) -> Self {
+    pub fn new(
+        network_adapter: Arc<dyn NetworkAdapter>,
+        parts_source: Option<StatePartsSource>,
+    ) -> Self {
         StateSync {
             network_adapter,
+            parts_source,
+            fetched_parts: Default::default(),
             state_sync_time: Default::default(),
             last_time_block_requested: None,
             last_part_id_requested: Default::default(),
@@ -558,14 +1018,25 @@ impl StateSync {
             ],
             status: ShardSyncStatus::StateDownloadHeader,
         };
+        let fetched_parts =
+            std::mem::take(&mut *self.fetched_parts.lock().expect(POISONED_LOCK_ERR));
 
         for shard_id in tracking_shards {
             let mut download_timeout = false;
             let mut need_shard = false;
-            let shard_sync_download = new_shard_sync.entry(shard_id).or_insert_with(|| {
+            if !new_shard_sync.contains_key(&shard_id) {
                 need_shard = true;
-                init_sync_download.clone()
-            });
+                let shard_sync_download =
+                    match Self::resume_shard_sync_download(chain, shard_id, sync_hash, now)? {
+                        Some(shard_sync_download) => {
+                            update_sync_status = true;
+                            shard_sync_download
+                        }
+                        None => init_sync_download.clone(),
+                    };
+                new_shard_sync.insert(shard_id, shard_sync_download);
+            }
+            let shard_sync_download = new_shard_sync.get_mut(&shard_id).unwrap();
             let mut this_done = false;
             match shard_sync_download.status {
                 ShardSyncStatus::StateDownloadHeader => {
@@ -604,6 +1075,30 @@ impl StateSync {
                     }
                 }
                 ShardSyncStatus::StateDownloadParts => {
+                    let num_parts = shard_sync_download.downloads.len() as u64;
+                    for part in fetched_parts.iter().filter(|part| {
+                        part.sync_hash == sync_hash
+                            && part.shard_id == shard_id
+                            && part.part_id < num_parts
+                    }) {
+                        let download = &mut shard_sync_download.downloads[part.part_id as usize];
+                        if download.done {
+                            continue;
+                        }
+                        let result = match &part.data {
+                            Ok(data) => chain
+                                .set_state_part(shard_id, sync_hash, part.part_id, num_parts, data)
+                                .map_err(|err| err.to_string()),
+                            Err(err) => Err(err.clone()),
+                        };
+                        match result {
+                            Ok(()) => download.done = true,
+                            Err(err) => {
+                                error!(target: "sync", "State sync can't get part from the state parts source, shard = {}, part = {}, hash = {}: {}", shard_id, part.part_id, sync_hash, err);
+                                download.error = true;
+                            }
+                        }
+                    }
                     let mut parts_done = true;
                     for part_download in shard_sync_download.downloads.iter_mut() {
                         if !part_download.done {
@@ -708,6 +1203,47 @@ impl StateSync {
         Ok((update_sync_status, all_done))
     }
 
+    /// Restores the progress of the state sync of the shard from the state header and the parts
+    /// that were saved before the node restarted. Returns `None` if there is no header saved.
+    fn resume_shard_sync_download(
+        chain: &mut Chain,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ShardSyncDownload>, near_chain::Error> {
+        let shard_state_header = match chain.get_state_header(shard_id, sync_hash) {
+            Ok(shard_state_header) => shard_state_header,
+            Err(_) => return Ok(None),
+        };
+        let state_num_parts =
+            get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+        let downloaded_parts =
+            chain.get_downloaded_state_parts(shard_id, sync_hash, state_num_parts)?;
+        let num_downloaded_parts = downloaded_parts.iter().filter(|done| **done).count();
+        info!(target: "sync", "State sync: resuming shard {} for hash {} with {}/{} parts downloaded", shard_id, sync_hash, num_downloaded_parts, state_num_parts);
+        if num_downloaded_parts == downloaded_parts.len() {
+            return Ok(Some(ShardSyncDownload {
+                downloads: vec![],
+                status: ShardSyncStatus::StateDownloadFinalize,
+            }));
+        }
+        Ok(Some(ShardSyncDownload {
+            downloads: downloaded_parts
+                .into_iter()
+                .map(|done| DownloadStatus {
+                    start_time: now,
+                    prev_update_time: now,
+                    run_me: Arc::new(AtomicBool::new(!done)),
+                    error: false,
+                    done,
+                    state_requests_count: 0,
+                    last_target: None,
+                })
+                .collect(),
+            status: ShardSyncStatus::StateDownloadParts,
+        }))
+    }
+
     /// Find the hash of the first block on the same epoch (and chain) of block with hash `sync_hash`.
     pub fn get_epoch_start_sync_hash(
         chain: &mut Chain,
@@ -783,19 +1319,27 @@ impl StateSync {
         // Remove candidates from pending list if request expired due to timeout
         self.last_part_id_requested.retain(|_, request| !request.expired());
 
-        let prev_block_hash = chain.get_block_header(&sync_hash)?.prev_hash();
+        let prev_block_hash = *chain.get_block_header(&sync_hash)?.prev_hash();
         let epoch_hash = runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash)?;
+        // If the shards are split after the previous block of `sync_hash`, the state of the parent
+        // shard is kept by the nodes that track any of the split shards.
+        let shard_ids = match get_split_shard_layout(&**runtime_adapter, &prev_block_hash)? {
+            Some(shard_layout) => shard_layout.get_split_shard_ids(shard_id),
+            None => vec![shard_id],
+        };
 
         Ok(runtime_adapter
             .get_epoch_block_producers_ordered(&epoch_hash, &sync_hash)?
             .iter()
             .filter_map(|(validator_stake, _slashed)| {
-                if runtime_adapter.cares_about_shard(
-                    Some(&validator_stake.account_id),
-                    &prev_block_hash,
-                    shard_id,
-                    false,
-                ) {
+                if shard_ids.iter().any(|shard_id| {
+                    runtime_adapter.cares_about_shard(
+                        Some(&validator_stake.account_id),
+                        &prev_block_hash,
+                        *shard_id,
+                        false,
+                    )
+                }) {
                     if me.as_ref().map(|me| me != &validator_stake.account_id).unwrap_or(true) {
                         Some(AccountOrPeerIdOrHash::AccountId(validator_stake.account_id.clone()))
                     } else {
@@ -806,7 +1350,9 @@ impl StateSync {
                 }
             })
             .chain(highest_height_peers.iter().filter_map(|peer| {
-                if peer.chain_info.tracked_shards.contains(&shard_id) {
+                if peer.chain_info.tracked_shards.iter().any(|tracked_shard_id| {
+                    *tracked_shard_id == shard_id || shard_ids.contains(tracked_shard_id)
+                }) {
                     Some(AccountOrPeerIdOrHash::PeerId(peer.peer_info.id.clone()))
                 } else {
                     None
@@ -829,6 +1375,17 @@ impl StateSync {
         shard_sync_download: ShardSyncDownload,
         highest_height_peers: &Vec<FullPeerInfo>,
     ) -> Result<ShardSyncDownload, near_chain::Error> {
+        if let ShardSyncStatus::StateDownloadParts = shard_sync_download.status {
+            if let Some(parts_source) = &self.parts_source {
+                return Ok(self.fetch_parts_from_source(
+                    parts_source.clone(),
+                    shard_id,
+                    sync_hash,
+                    shard_sync_download,
+                ));
+            }
+        }
+
         let possible_targets = self.possible_targets(
             me,
             shard_id,
@@ -910,6 +1467,49 @@ impl StateSync {
         Ok(new_shard_sync_download)
     }
 
+    /// Fetches the parts that need to be requested from the state parts source. The fetched parts
+    /// are validated and saved on the next step.
+    fn fetch_parts_from_source(
+        &mut self,
+        parts_source: StatePartsSource,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        shard_sync_download: ShardSyncDownload,
+    ) -> ShardSyncDownload {
+        let mut new_shard_sync_download = shard_sync_download;
+        let num_parts = new_shard_sync_download.downloads.len() as u64;
+        let num_pending = new_shard_sync_download
+            .downloads
+            .iter()
+            .filter(|download| !download.done && !download.run_me.load(Ordering::SeqCst))
+            .count();
+
+        for (part_id, download) in new_shard_sync_download
+            .downloads
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, download)| download.run_me.load(Ordering::SeqCst))
+            .take(MAX_PENDING_EXTERNAL_PART.saturating_sub(num_pending))
+        {
+            download.run_me.store(false, Ordering::SeqCst);
+            download.state_requests_count += 1;
+            let part_id = part_id as u64;
+            let path = get_state_part_file_path(&sync_hash, shard_id, part_id, num_parts);
+            let fetched_parts = self.fetched_parts.clone();
+            actix::spawn(fetch_state_part(parts_source.clone(), path).then(move |data| {
+                fetched_parts.lock().expect(POISONED_LOCK_ERR).push(FetchedStatePart {
+                    sync_hash,
+                    shard_id,
+                    part_id,
+                    data,
+                });
+                future::ready(())
+            }));
+        }
+
+        new_shard_sync_download
+    }
+
     pub fn run(
         &mut self,
         me: &Option<AccountId>,
@@ -960,6 +1560,33 @@ impl StateSync {
     }
 }
 
+/// Reads the state part at `path` relative to the root of the state parts source.
+async fn fetch_state_part(parts_source: StatePartsSource, path: String) -> Result<Vec<u8>, String> {
+    match parts_source {
+        StatePartsSource::Filesystem { root_dir } => {
+            // The parts are large, so they are read on the thread pool to not block the arbiter.
+            actix_web::web::block(move || std::fs::read(root_dir.join(&path)))
+                .await
+                .map_err(|err| err.to_string())
+        }
+        StatePartsSource::Http { url } => {
+            let url = format!("{}/{}", url.trim_end_matches('/'), path);
+            let mut response = actix_web::client::Client::new()
+                .get(&url)
+                .timeout(TimeDuration::from_secs(STATE_SYNC_TIMEOUT as u64))
+                .send()
+                .await
+                .map_err(|err| err.to_string())?;
+            if !response.status().is_success() {
+                return Err(format!("{} responded with {}", url, response.status()));
+            }
+            let body =
+                response.body().limit(MAX_STATE_PART_SIZE).await.map_err(|err| err.to_string())?;
+            Ok(body.to_vec())
+        }
+    }
+}
+
 /// Create an abstract collection of elements to be shuffled.
 /// Each element will appear in the shuffled output exactly `limit` times.
 /// Use it as an iterator to access the shuffled collection.
@@ -1124,6 +1751,57 @@ mod test {
         );
     }
 
+    /// Checks that the consecutive header ranges are requested from different peers.
+    #[test]
+    fn test_sync_headers_split_ranges() {
+        let mock_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut header_sync = HeaderSync::new(
+            mock_adapter.clone(),
+            TimeDuration::from_secs(10),
+            TimeDuration::from_secs(2),
+            TimeDuration::from_secs(120),
+            1_000_000_000,
+        );
+        let (mut chain, _, _) = setup();
+        let highest_height = 10_000;
+        let peers: Vec<_> = (0..2)
+            .map(|_| {
+                let mut peer = FullPeerInfo {
+                    peer_info: PeerInfo::random(),
+                    chain_info: Default::default(),
+                    edge_info: Default::default(),
+                };
+                peer.chain_info.height = highest_height;
+                peer
+            })
+            .collect();
+        let mut sync_status = SyncStatus::NoSync;
+        header_sync.run(&mut sync_status, &mut chain, highest_height, &peers).unwrap();
+
+        // The first range is requested from a single peer.
+        let first_peer_id = match mock_adapter.pop().unwrap() {
+            NetworkRequests::BlockHeadersRequest { peer_id, .. } => peer_id,
+            request => panic!("unexpected network request {:?}", request),
+        };
+        assert!(mock_adapter.pop().is_none());
+
+        // Once a full range is received, the range after it is requested from the other peer.
+        let headers = vec![chain.genesis().clone(); MAX_BLOCK_HEADERS as usize];
+        header_sync.received_headers(&first_peer_id, &headers);
+        let second_peer_id = match mock_adapter.pop().unwrap() {
+            NetworkRequests::BlockHeadersRequest { hashes, peer_id } => {
+                assert_eq!(hashes, vec![*chain.genesis().hash()]);
+                peer_id
+            }
+            request => panic!("unexpected network request {:?}", request),
+        };
+        assert_ne!(second_peer_id, first_peer_id);
+
+        // A partial range means the peer has no more headers.
+        header_sync.received_headers(&second_peer_id, &headers[..1]);
+        assert!(mock_adapter.pop().is_none());
+    }
+
     /// Sets up `HeaderSync` with particular tolerance for slowness, and makes sure that a peer that
     /// sends headers below the threshold gets banned, and the peer that sends them faster doesn't get
     /// banned.
@@ -1145,7 +1823,7 @@ mod test {
         );
 
         let set_syncing_peer = |header_sync: &mut HeaderSync| {
-            header_sync.syncing_peer = Some(FullPeerInfo {
+            header_sync.syncing_peers = vec![FullPeerInfo {
                 peer_info: PeerInfo {
                     id: PeerId::new(PublicKey::empty(KeyType::ED25519)),
                     addr: None,
@@ -1153,8 +1831,8 @@ mod test {
                 },
                 chain_info: Default::default(),
                 edge_info: Default::default(),
-            });
-            header_sync.syncing_peer.as_mut().unwrap().chain_info.height = highest_height;
+            }];
+            header_sync.syncing_peers[0].chain_info.height = highest_height;
         };
         set_syncing_peer(&mut header_sync);
 
@@ -1221,6 +1899,7 @@ mod test {
                 &*signers[3],
                 last_block.header().next_bp_hash().clone(),
                 block_merkle_tree.root(),
+                None,
             );
             block_merkle_tree.insert(*block.hash());
 
@@ -1274,27 +1953,36 @@ mod test {
     }
 
     /// Helper function for block sync tests
-    fn collect_hashes_from_network_adapter(
+    fn collect_block_requests_from_network_adapter(
         network_adapter: Arc<MockNetworkAdapter>,
-    ) -> HashSet<CryptoHash> {
-        let mut requested_block_hashes = HashSet::new();
+    ) -> HashMap<CryptoHash, PeerId> {
+        let mut block_requests = HashMap::new();
         let mut network_request = network_adapter.requests.write().unwrap();
         while let Some(request) = network_request.pop_back() {
             match request {
-                NetworkRequests::BlockRequest { hash, .. } => {
-                    requested_block_hashes.insert(hash);
+                NetworkRequests::BlockRequest { hash, peer_id } => {
+                    block_requests.insert(hash, peer_id);
                 }
                 _ => panic!("unexpected network request {:?}", request),
             }
         }
-        requested_block_hashes
+        block_requests
+    }
+
+    fn collect_hashes_from_network_adapter(
+        network_adapter: Arc<MockNetworkAdapter>,
+    ) -> HashSet<CryptoHash> {
+        collect_block_requests_from_network_adapter(network_adapter)
+            .into_iter()
+            .map(|x| x.0)
+            .collect()
     }
 
     fn create_peer_infos(num_peers: usize) -> Vec<FullPeerInfo> {
         (0..num_peers)
-            .map(|_| FullPeerInfo {
+            .map(|i| FullPeerInfo {
                 peer_info: PeerInfo {
-                    id: PeerId::new(PublicKey::empty(KeyType::ED25519)),
+                    id: PeerId::new(PublicKey::from_seed(KeyType::ED25519, &format!("peer{}", i))),
                     addr: None,
                     account_id: None,
                 },
@@ -1322,23 +2010,36 @@ mod test {
         let peer_infos = create_peer_infos(2);
         env.clients[1].chain.sync_block_headers(block_headers, |_| unreachable!()).unwrap();
 
-        for block in blocks.iter().take(5) {
+        // The next blocks are split evenly between the peers.
+        let max_pending_requests = peer_infos.len() * MAX_PENDING_BLOCK_REQUESTS_PER_PEER;
+        for batch in blocks[..2 * max_pending_requests].chunks(max_pending_requests) {
             let is_state_sync =
                 block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
             assert!(!is_state_sync);
 
-            let requested_block_hashes =
-                collect_hashes_from_network_adapter(network_adapter.clone());
+            let block_requests =
+                collect_block_requests_from_network_adapter(network_adapter.clone());
             assert_eq!(
-                requested_block_hashes,
-                [block].iter().map(|x| *x.hash()).collect::<HashSet<_>>()
+                block_requests.keys().cloned().collect::<HashSet<_>>(),
+                batch.iter().map(|x| *x.hash()).collect::<HashSet<_>>()
             );
+            for peer in peer_infos.iter() {
+                assert_eq!(
+                    block_requests
+                        .values()
+                        .filter(|peer_id| *peer_id == &peer.peer_info.id)
+                        .count(),
+                    MAX_PENDING_BLOCK_REQUESTS_PER_PEER
+                );
+            }
 
-            env.process_block(1, block.clone(), Provenance::NONE);
+            for block in batch {
+                env.process_block(1, block.clone(), Provenance::NONE);
+            }
         }
 
         // Receive all blocks. Should not request more.
-        for i in 5..21 {
+        for i in 2 * max_pending_requests + 1..21 {
             env.process_block(1, blocks[i - 1].clone(), Provenance::NONE);
         }
         block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
@@ -1369,6 +2070,7 @@ mod test {
         // We don't have archival peers, and thus cannot request any blocks
         assert_eq!(requested_block_hashes, HashSet::new());
 
+        let mut block_sync = BlockSync::new(network_adapter.clone(), block_fetch_horizon, true);
         let mut peer_infos = create_peer_infos(2);
         for peer in peer_infos.iter_mut() {
             peer.chain_info.archival = true;
@@ -1378,7 +2080,188 @@ mod test {
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter.clone());
         assert_eq!(
             requested_block_hashes,
-            blocks.iter().take(1).map(|b| *b.hash()).collect::<HashSet<_>>()
+            blocks
+                .iter()
+                .take(peer_infos.len() * MAX_PENDING_BLOCK_REQUESTS_PER_PEER)
+                .map(|b| *b.hash())
+                .collect::<HashSet<_>>()
         );
     }
+
+    #[test]
+    fn test_block_sync_rerequest_timed_out() {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut block_sync = BlockSync::new(network_adapter.clone(), 10, false);
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.epoch_length = 100;
+        let mut env = TestEnv::new(chain_genesis, 2, 1);
+        let mut blocks = vec![];
+        for i in 1..4 {
+            let block = env.clients[0].produce_block(i).unwrap().unwrap();
+            blocks.push(block.clone());
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+        let block_headers = blocks.iter().map(|b| b.header().clone()).collect::<Vec<_>>();
+        let peer_infos = create_peer_infos(2);
+        env.clients[1].chain.sync_block_headers(block_headers, |_| unreachable!()).unwrap();
+
+        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let block_requests = collect_block_requests_from_network_adapter(network_adapter.clone());
+        assert_eq!(block_requests.len(), blocks.len());
+
+        // Nothing is requested again until the requests time out.
+        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        assert!(collect_hashes_from_network_adapter(network_adapter.clone()).is_empty());
+
+        for request in block_sync.pending_requests.values_mut() {
+            request.when = request.when - Duration::seconds(BLOCK_REQUEST_TIMEOUT + 1);
+        }
+        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let new_block_requests =
+            collect_block_requests_from_network_adapter(network_adapter.clone());
+        assert_eq!(new_block_requests.len(), blocks.len());
+        for (hash, peer_id) in new_block_requests {
+            assert_ne!(block_requests[&hash], peer_id);
+        }
+    }
+
+    /// Returns the epoch sync requests sent since the last call as (peer, is finalization).
+    fn collect_epoch_sync_requests(network_adapter: &MockNetworkAdapter) -> Vec<(PeerId, bool)> {
+        let mut requests = vec![];
+        let mut network_requests = network_adapter.requests.write().unwrap();
+        while let Some(request) = network_requests.pop_front() {
+            match request {
+                NetworkRequests::EpochSyncRequest { peer_id, .. } => {
+                    requests.push((peer_id, false))
+                }
+                NetworkRequests::EpochSyncFinalizationRequest { peer_id, .. } => {
+                    requests.push((peer_id, true))
+                }
+                _ => panic!("unexpected network request {:?}", request),
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn test_epoch_sync_current_epoch_confirmed_by_another_peer() {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut epoch_sync = EpochSync::new(network_adapter.clone(), true);
+        let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+        let chain = &mut env.clients[0].chain;
+        let peer_infos = create_peer_infos(3);
+        let mut sync_status = SyncStatus::NoSync;
+
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let requests = collect_epoch_sync_requests(&network_adapter);
+        assert_eq!(requests.len(), 1);
+        let (first_peer, _) = requests[0].clone();
+        // A second run doesn't send another request while the first one is pending.
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        assert!(collect_epoch_sync_requests(&network_adapter).is_empty());
+
+        // A response from a peer that wasn't asked is ignored.
+        let other_peer = peer_infos
+            .iter()
+            .map(|peer| peer.peer_info.id.clone())
+            .find(|peer_id| peer_id != &first_peer)
+            .unwrap();
+        let response = EpochSyncResponse { epoch_id: EpochId::default(), light_client_block: None };
+        epoch_sync.on_response(chain, &other_peer, response.clone()).unwrap();
+        assert!(epoch_sync.current_epoch_confirmations.is_empty());
+
+        // A single peer saying that the genesis epoch is not finished is not enough.
+        epoch_sync.on_response(chain, &first_peer, response.clone()).unwrap();
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let requests = collect_epoch_sync_requests(&network_adapter);
+        assert_eq!(requests.len(), 1);
+        let (second_peer, is_finalization) = requests[0].clone();
+        assert_ne!(second_peer, first_peer);
+        assert!(!is_finalization);
+
+        epoch_sync.on_response(chain, &second_peer, response).unwrap();
+        assert!(epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        assert!(collect_epoch_sync_requests(&network_adapter).is_empty());
+    }
+
+    #[test]
+    fn test_epoch_sync_single_peer() {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut epoch_sync = EpochSync::new(network_adapter.clone(), true);
+        let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+        let chain = &mut env.clients[0].chain;
+        let peer_infos = create_peer_infos(1);
+        let mut sync_status = SyncStatus::NoSync;
+
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let (peer_id, _) = collect_epoch_sync_requests(&network_adapter).pop().unwrap();
+        let response = EpochSyncResponse { epoch_id: EpochId::default(), light_client_block: None };
+        epoch_sync.on_response(chain, &peer_id, response).unwrap();
+        // There is no other peer to confirm the response with.
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        assert!(epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        assert!(collect_epoch_sync_requests(&network_adapter).is_empty());
+    }
+
+    #[test]
+    fn test_epoch_sync_invalid_response_and_timeout() {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut epoch_sync = EpochSync::new(network_adapter.clone(), true);
+        let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+        let chain = &mut env.clients[0].chain;
+        let peer_infos = create_peer_infos(3);
+        let mut sync_status = SyncStatus::NoSync;
+
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let (first_peer, _) = collect_epoch_sync_requests(&network_adapter).pop().unwrap();
+
+        // A light client block without approvals.
+        let genesis_header = chain.genesis().clone();
+        let garbage = LightClientBlockView {
+            prev_block_hash: *genesis_header.prev_hash(),
+            next_block_inner_hash: CryptoHash::default(),
+            inner_lite: genesis_header.into(),
+            inner_rest_hash: CryptoHash::default(),
+            next_bps: None,
+            approvals_after_next: vec![],
+        };
+        let response =
+            EpochSyncResponse { epoch_id: EpochId::default(), light_client_block: Some(garbage) };
+        assert!(epoch_sync.on_response(chain, &first_peer, response).is_err());
+        assert_eq!(epoch_sync.verified_epochs, 0);
+
+        // The peer that sent the invalid response is not asked again.
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let (second_peer, _) = collect_epoch_sync_requests(&network_adapter).pop().unwrap();
+        assert_ne!(second_peer, first_peer);
+
+        // Neither is the peer that timed out.
+        let (_, requested_at) = epoch_sync.pending_request.as_mut().unwrap();
+        *requested_at = *requested_at - Duration::seconds(EPOCH_SYNC_REQUEST_TIMEOUT + 1);
+        assert!(!epoch_sync.run(&mut sync_status, chain, &peer_infos).unwrap());
+        let (third_peer, _) = collect_epoch_sync_requests(&network_adapter).pop().unwrap();
+        assert_ne!(third_peer, first_peer);
+        assert_ne!(third_peer, second_peer);
+
+        // The late response of the peer that timed out is ignored.
+        let response = EpochSyncResponse { epoch_id: EpochId::default(), light_client_block: None };
+        epoch_sync.on_response(chain, &second_peer, response).unwrap();
+        assert!(epoch_sync.current_epoch_confirmations.is_empty());
+    }
+
+    #[test]
+    fn test_fetch_state_part_from_filesystem() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root_dir = tmp_dir.path().to_path_buf();
+        let sync_hash = CryptoHash::default();
+        let path = get_state_part_file_path(&sync_hash, 1, 2, 3);
+        std::fs::create_dir_all(root_dir.join(&path).parent().unwrap()).unwrap();
+        std::fs::write(root_dir.join(&path), &[1, 2, 3]).unwrap();
+        let parts_source = StatePartsSource::Filesystem { root_dir: root_dir.clone() };
+
+        let part = futures::executor::block_on(fetch_state_part(parts_source.clone(), path));
+        assert_eq!(part, Ok(vec![1, 2, 3]));
+        let missing_path = get_state_part_file_path(&sync_hash, 1, 0, 3);
+        assert!(futures::executor::block_on(fetch_state_part(parts_source, missing_path)).is_err());
+    }
 }
@@ -801,6 +801,8 @@ pub fn setup_mock_all_validators(
                             };
                         }
                         NetworkRequests::ForwardTx(_, _)
+                        | NetworkRequests::EpochSyncRequest { .. }
+                        | NetworkRequests::EpochSyncFinalizationRequest { .. }
                         | NetworkRequests::Sync { .. }
                         | NetworkRequests::FetchRoutingTable
                         | NetworkRequests::PingTo(_, _)
@@ -1156,6 +1158,7 @@ pub fn create_chunk(
             header.gas_used(),
             header.gas_limit(),
             header.balance_burnt(),
+            header.delayed_receipts_count(),
             tx_root,
             header.validator_proposals().iter().cloned().collect(),
             transactions,
@@ -1198,6 +1201,7 @@ pub fn create_chunk(
         &*client.validator_signer.as_ref().unwrap().clone(),
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
+        None,
     );
     (chunk, merkle_paths, receipts, block)
 }
@@ -14,15 +14,17 @@ use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
+use near_primitives::transaction::Action;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, MaybeBlockId, ShardId, TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockHeightDelta, BlockReference, MaybeBlockId, ShardId,
+    TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView, ValidatorStakeView,
+    AccountBalanceChangeView, BlockView, ChunkView, EpochValidatorHistoryView, EpochValidatorInfo,
+    ExecutionOutcomeWithIdView, FeeEstimationView, FinalExecutionOutcomeViewEnum, GasPriceView,
+    LightClientBlockLiteView, LightClientBlockView, QueryRequest, QueryResponse, ReceiptView,
+    StateChangesKindsView, StateChangesRequestView, StateChangesView, ValidatorStakeView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -122,6 +124,8 @@ pub enum SyncStatus {
     AwaitingPeers,
     /// Not syncing / Done syncing.
     NoSync,
+    /// Verifying the block producers of every epoch to bootstrap without the headers.
+    EpochSync { verified_epochs: u64 },
     /// Downloading block headers for fast sync.
     HeaderSync { current_height: BlockHeight, highest_height: BlockHeight },
     /// State sync, with different states of state sync for different shards.
@@ -250,6 +254,8 @@ pub struct TxStatus {
     pub tx_hash: CryptoHash,
     pub signer_account_id: AccountId,
     pub fetch_receipt: bool,
+    /// Also explain how each receipt was executed.
+    pub fetch_trace: bool,
 }
 
 #[derive(Debug)]
@@ -287,6 +293,19 @@ impl Message for GetValidatorInfo {
     type Result = Result<EpochValidatorInfo, String>;
 }
 
+/// Performance of the validators in the finished epochs before the epoch of the given block.
+pub struct GetValidatorHistory {
+    pub block_id: MaybeBlockId,
+    /// Only include the stats of this validator.
+    pub account_id: Option<AccountId>,
+    /// Number of finished epochs to return.
+    pub num_epochs: Option<u64>,
+}
+
+impl Message for GetValidatorHistory {
+    type Result = Result<Vec<EpochValidatorHistoryView>, String>;
+}
+
 pub struct GetValidatorOrdered {
     pub block_id: MaybeBlockId,
 }
@@ -312,6 +331,30 @@ impl Message for GetStateChangesInBlock {
     type Result = Result<StateChangesKindsView, String>;
 }
 
+/// Changes of the account balance in the given range of blocks, each attributed to its cause.
+pub struct GetAccountBalanceChanges {
+    pub account_id: AccountId,
+    pub from_block_height: BlockHeight,
+    pub to_block_height: BlockHeight,
+}
+
+impl Message for GetAccountBalanceChanges {
+    type Result = Result<Vec<AccountBalanceChangeView>, String>;
+}
+
+/// Estimates the gas and the cost of a transaction with the given actions based on recent blocks.
+pub struct EstimateFee {
+    pub signer_id: AccountId,
+    pub receiver_id: AccountId,
+    pub actions: Vec<Action>,
+    /// Number of blocks to project the gas price over.
+    pub num_blocks: Option<BlockHeightDelta>,
+}
+
+impl Message for EstimateFee {
+    type Result = Result<FeeEstimationView, String>;
+}
+
 pub struct GetExecutionOutcome {
     pub id: TransactionOrReceiptId,
 }
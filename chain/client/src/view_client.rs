@@ -1,9 +1,10 @@
 //! Readonly view of the chain and state of the database.
 //! Useful for querying from RPC.
 
-use std::cmp::Ordering;
+use std::cmp::{max, Ordering};
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
@@ -27,30 +28,37 @@ use near_primitives::block::{Block, BlockHeader, GenesisId, Tip};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, PartialMerkleTree};
 use near_primitives::network::AnnounceAccount;
+use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::sharding::ShardChunk;
 use near_primitives::syncing::{
-    ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
-    ShardStateSyncResponseV2,
+    EpochSyncResponse, ShardStateSyncResponse, ShardStateSyncResponseHeader,
+    ShardStateSyncResponseV1, ShardStateSyncResponseV2,
 };
+use near_primitives::transaction::{Action, ExecutionOutcomeWithId, FunctionCallAction};
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockId, BlockReference, Finality, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, Balance, BlockHeight, BlockHeightDelta, BlockId, BlockReference, Finality, Gas,
+    MaybeBlockId, ShardId, StateChangeCause, StateChangeValue, StateChangeWithCause,
+    StateChangesRequest, TransactionOrReceiptId,
 };
+use near_primitives::utils::system_account;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView, ValidatorStakeView,
+    AccountBalanceChangeCauseView, AccountBalanceChangeView, BlockView, ChunkView,
+    EpochValidatorHistoryView, EpochValidatorInfo, ExecutionOutcomeWithIdView, FeeEstimationView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionOutcomeWithTraceView,
+    FinalExecutionStatus, FunctionCallGasUsageView, GasPriceView, LightClientBlockView,
+    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesView,
+    ValidatorStakeView,
 };
+use near_store::{get_account, TrieUpdate};
 
 use crate::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
-    GetExecutionOutcome, GetExecutionOutcomesForBlock, GetGasPrice, GetReceipt, Query, TxStatus,
-    TxStatusError,
+    Error, EstimateFee, GetAccountBalanceChanges, GetBlock, GetBlockProof, GetBlockProofResponse,
+    GetBlockWithMerkleTree, GetExecutionOutcome, GetExecutionOutcomesForBlock, GetGasPrice,
+    GetReceipt, Query, TxStatus, TxStatusError,
 };
 use crate::{
     sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    GetStateChangesInBlock, GetValidatorHistory, GetValidatorInfo, GetValidatorOrdered,
 };
 
 /// Max number of queries that we keep.
@@ -59,6 +67,21 @@ const QUERY_REQUEST_LIMIT: usize = 500;
 const REQUEST_WAIT_TIME: u64 = 1000;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
+/// Max number of blocks that can be scanned for the changes of the account balance at once.
+const MAX_ACCOUNT_BALANCE_CHANGES_BLOCKS: BlockHeightDelta = 1000;
+/// Max number of blocks the gas price can be projected over by the fee estimation.
+const MAX_FEE_ESTIMATION_BLOCKS: BlockHeightDelta = 100;
+/// Number of blocks the gas price is projected over if the request doesn't specify it.
+const DEFAULT_FEE_ESTIMATION_BLOCKS: BlockHeightDelta = 10;
+/// Number of recent blocks the block utilization and the gas used by methods are taken from.
+const FEE_ESTIMATION_HISTORY_BLOCKS: usize = 100;
+/// Extra gas, in percent of the highest recent usage of a method, recommended to attach to a call
+/// so that it doesn't run out of gas when it takes a slightly more expensive path.
+const RECOMMENDED_ATTACHED_GAS_MARGIN_PERCENT: u128 = 20;
+/// Max number of epochs the validator history can be requested for at once.
+const MAX_VALIDATOR_HISTORY_EPOCHS: u64 = 100;
+/// Number of epochs of the validator history returned if the request doesn't specify it.
+const DEFAULT_VALIDATOR_HISTORY_EPOCHS: u64 = 10;
 
 /// Request and response manager across all instances of ViewClientActor.
 pub struct ViewClientRequestManager {
@@ -72,6 +95,19 @@ pub struct ViewClientRequestManager {
     pub query_responses: SizedCache<String, Result<QueryResponse, String>>,
     /// Receipt outcome requests
     pub receipt_outcome_requests: SizedCache<CryptoHash, Instant>,
+    /// Gas usage of recent blocks, so that fee estimations don't have to scan them every time.
+    pub fee_history_blocks: SizedCache<CryptoHash, FeeHistoryBlock>,
+}
+
+/// Gas usage of a block that the fee estimation is based on.
+#[derive(Clone)]
+pub struct FeeHistoryBlock {
+    gas_used: Gas,
+    gas_limit: Gas,
+    height: BlockHeight,
+    prev_hash: CryptoHash,
+    /// Receiver, method name and gas used of every function call executed in the block.
+    function_calls: Vec<(AccountId, String, Gas)>,
 }
 
 #[cfg(feature = "adversarial")]
@@ -104,6 +140,7 @@ impl ViewClientRequestManager {
             query_requests: SizedCache::with_size(QUERY_REQUEST_LIMIT),
             query_responses: SizedCache::with_size(QUERY_REQUEST_LIMIT),
             receipt_outcome_requests: SizedCache::with_size(QUERY_REQUEST_LIMIT),
+            fee_history_blocks: SizedCache::with_size(2 * FEE_ESTIMATION_HISTORY_BLOCKS),
         }
     }
 }
@@ -223,8 +260,14 @@ impl ViewClientActor {
             QueryRequest::ViewAccessKey { account_id, .. } => account_id,
             QueryRequest::ViewAccessKeyList { account_id, .. } => account_id,
             QueryRequest::CallFunction { account_id, .. } => account_id,
+            QueryRequest::ViewStorageUsage { account_id, .. } => account_id,
+            QueryRequest::ViewStorageStaking { account_id } => account_id,
+            QueryRequest::ViewContractAbi { account_id } => account_id,
         };
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(account_id);
+        let shard_id = self
+            .runtime_adapter
+            .account_id_to_shard_id(account_id, header.epoch_id())
+            .map_err(|e| e.to_string())?;
 
         // If we have state for the shard that we query return query result directly.
         // Otherwise route query to peers.
@@ -271,6 +314,353 @@ impl ViewClientActor {
         }
     }
 
+    /// Returns the amount and the locked balance of the account after the given block.
+    fn get_account_balance(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        account_id: &AccountId,
+    ) -> Result<(Balance, Balance), String> {
+        let state_root =
+            self.chain.get_chunk_extra(block_hash, shard_id).map_err(|e| e.to_string())?.state_root;
+        let trie = self.runtime_adapter.get_view_trie_for_shard(shard_id);
+        let state_update = TrieUpdate::new(Rc::new(trie), state_root);
+        Ok(get_account(&state_update, account_id)
+            .map_err(|e| e.to_string())?
+            .map_or((0, 0), |account| (account.amount, account.locked)))
+    }
+
+    fn get_account_balance_changes(
+        &mut self,
+        msg: GetAccountBalanceChanges,
+    ) -> Result<Vec<AccountBalanceChangeView>, String> {
+        let GetAccountBalanceChanges { account_id, from_block_height, to_block_height } = msg;
+        if from_block_height > to_block_height {
+            return Err("from_block_height must not be greater than to_block_height".to_string());
+        }
+        if to_block_height - from_block_height >= MAX_ACCOUNT_BALANCE_CHANGES_BLOCKS {
+            return Err(format!(
+                "At most {} blocks can be queried at once",
+                MAX_ACCOUNT_BALANCE_CHANGES_BLOCKS
+            ));
+        }
+        // The genesis block has no previous state to compare with.
+        let from_block_height = max(from_block_height, self.chain.genesis().height() + 1);
+
+        let mut changes = vec![];
+        let mut balance = None;
+        for height in from_block_height..=to_block_height {
+            let header = match self.chain.get_header_by_height(height) {
+                Ok(header) => header.clone(),
+                Err(e) => match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => continue,
+                    _ => return Err(e.to_string()),
+                },
+            };
+            let prev_header =
+                self.chain.get_block_header(header.prev_hash()).map_err(|e| e.to_string())?.clone();
+            let (mut amount, mut locked) = match balance {
+                Some(balance) => balance,
+                None => {
+                    let shard_id = self
+                        .runtime_adapter
+                        .account_id_to_shard_id(&account_id, prev_header.epoch_id())
+                        .map_err(|e| e.to_string())?;
+                    self.get_account_balance(prev_header.hash(), shard_id, &account_id)?
+                }
+            };
+            let state_changes = self
+                .chain
+                .store()
+                .get_state_changes(
+                    header.hash(),
+                    &StateChangesRequest::AccountChanges { account_ids: vec![account_id.clone()] },
+                )
+                .map_err(|e| e.to_string())?;
+            for StateChangeWithCause { cause, value } in state_changes {
+                let (amount_after, locked_after) = match value {
+                    StateChangeValue::AccountUpdate { account, .. } => {
+                        (account.amount, account.locked)
+                    }
+                    StateChangeValue::AccountDeletion { .. } => (0, 0),
+                    _ => continue,
+                };
+                if (amount_after, locked_after) != (amount, locked) {
+                    let cause = match cause {
+                        StateChangeCause::TransactionProcessing { tx_hash } => {
+                            AccountBalanceChangeCauseView::Transaction { tx_hash }
+                        }
+                        StateChangeCause::ActionReceiptGasReward { receipt_hash } => {
+                            AccountBalanceChangeCauseView::ContractReward { receipt_hash }
+                        }
+                        StateChangeCause::ActionReceiptProcessingStarted { receipt_hash }
+                        | StateChangeCause::ReceiptProcessing { receipt_hash } => {
+                            let is_refund = self
+                                .chain
+                                .mut_store()
+                                .get_receipt(&receipt_hash)
+                                .map_err(|e| e.to_string())?
+                                .map_or(false, |receipt| {
+                                    receipt.predecessor_id == system_account()
+                                });
+                            if is_refund {
+                                AccountBalanceChangeCauseView::Refund { receipt_hash }
+                            } else if locked_after > locked {
+                                AccountBalanceChangeCauseView::StakeLock { receipt_hash }
+                            } else {
+                                AccountBalanceChangeCauseView::Receipt { receipt_hash }
+                            }
+                        }
+                        StateChangeCause::ValidatorAccountsUpdate => {
+                            // Rewards are only paid in the first block of an epoch; at other
+                            // heights the update can only be slashing.
+                            let validator_reward = if header.epoch_id() != prev_header.epoch_id() {
+                                self.runtime_adapter
+                                    .get_validator_rewards(prev_header.hash())
+                                    .map_err(|e| e.to_string())?
+                                    .get(&account_id)
+                                    .cloned()
+                                    .unwrap_or(0)
+                            } else {
+                                0
+                            };
+                            AccountBalanceChangeCauseView::ValidatorAccountsUpdate {
+                                validator_reward,
+                            }
+                        }
+                        cause => AccountBalanceChangeCauseView::Other { cause: cause.into() },
+                    };
+                    changes.push(AccountBalanceChangeView {
+                        block_hash: *header.hash(),
+                        block_height: header.height(),
+                        cause,
+                        amount_before: amount,
+                        amount_after,
+                        locked_before: locked,
+                        locked_after,
+                    });
+                }
+                amount = amount_after;
+                locked = locked_after;
+            }
+            balance = Some((amount, locked));
+        }
+        Ok(changes)
+    }
+
+    /// Returns the gas usage of the given block, or `None` if the block was garbage collected.
+    /// Blocks never change, so the result is cached for the following fee estimations.
+    fn get_fee_history_block(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<FeeHistoryBlock>, String> {
+        if let Some(history_block) = self
+            .request_manager
+            .write()
+            .expect(POISONED_LOCK_ERR)
+            .fee_history_blocks
+            .cache_get(block_hash)
+        {
+            return Ok(Some(history_block.clone()));
+        }
+        let (gas_used, gas_limit, height, prev_hash) = match self.chain.get_block(block_hash) {
+            Ok(block) => (
+                Block::compute_gas_used(block.chunks().iter(), block.header().height()),
+                Block::compute_gas_limit(block.chunks().iter(), block.header().height()),
+                block.header().height(),
+                *block.header().prev_hash(),
+            ),
+            Err(e) => match e.kind() {
+                ErrorKind::DBNotFoundErr(_) => return Ok(None),
+                _ => return Err(e.to_string()),
+            },
+        };
+        let mut function_calls = vec![];
+        let outcomes =
+            self.chain.get_block_execution_outcomes(block_hash).map_err(|e| e.to_string())?;
+        for outcome in outcomes.into_iter().flat_map(|(_, outcomes)| outcomes) {
+            let ExecutionOutcomeWithId { id, outcome } = outcome.outcome_with_id;
+            // Outcomes of transactions don't have a receipt and are skipped here.
+            let (receiver_id, method_name) =
+                match self.chain.mut_store().get_receipt(&id).map_err(|e| e.to_string())? {
+                    Some(Receipt {
+                        receiver_id,
+                        receipt: ReceiptEnum::Action(ActionReceipt { actions, .. }),
+                        ..
+                    }) => match actions.iter().find_map(|action| match action {
+                        Action::FunctionCall(FunctionCallAction { method_name, .. }) => {
+                            Some(method_name.clone())
+                        }
+                        _ => None,
+                    }) {
+                        Some(method_name) => (receiver_id.clone(), method_name),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+            // Gas attached to the created receipts had to be attached to the call as well.
+            let mut gas_used = outcome.gas_burnt;
+            for receipt_id in outcome.receipt_ids.iter() {
+                if let Some(Receipt {
+                    receipt: ReceiptEnum::Action(ActionReceipt { actions, .. }),
+                    ..
+                }) = self.chain.mut_store().get_receipt(receipt_id).map_err(|e| e.to_string())?
+                {
+                    gas_used = actions
+                        .iter()
+                        .fold(gas_used, |acc, action| acc.saturating_add(action.get_prepaid_gas()));
+                }
+            }
+            function_calls.push((receiver_id, method_name, gas_used));
+        }
+        let history_block =
+            FeeHistoryBlock { gas_used, gas_limit, height, prev_hash, function_calls };
+        self.request_manager
+            .write()
+            .expect(POISONED_LOCK_ERR)
+            .fee_history_blocks
+            .cache_set(*block_hash, history_block.clone());
+        Ok(Some(history_block))
+    }
+
+    fn estimate_fee(&mut self, msg: EstimateFee) -> Result<FeeEstimationView, String> {
+        let EstimateFee { signer_id, receiver_id, actions, num_blocks } = msg;
+        let num_blocks = num_blocks.unwrap_or(DEFAULT_FEE_ESTIMATION_BLOCKS);
+        if num_blocks > MAX_FEE_ESTIMATION_BLOCKS {
+            return Err(format!(
+                "Gas price can be projected over at most {} blocks",
+                MAX_FEE_ESTIMATION_BLOCKS
+            ));
+        }
+        let head_header = self.chain.head_header().map_err(|e| e.to_string())?.clone();
+        let protocol_version = self
+            .runtime_adapter
+            .get_epoch_protocol_version(head_header.epoch_id())
+            .map_err(|e| e.to_string())?;
+        let fees_gas = self
+            .runtime_adapter
+            .get_actions_fee_gas(&signer_id, &receiver_id, &actions, protocol_version)
+            .map_err(|e| e.to_string())?;
+        let method_names: Vec<String> = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::FunctionCall(FunctionCallAction { method_name, .. }) => {
+                    Some(method_name.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        // Walk back from the head to collect the utilization of recent blocks and the gas used by
+        // the called methods.
+        let mut samples: HashMap<String, Vec<Gas>> = HashMap::new();
+        let mut num_history_blocks: usize = 0;
+        let mut total_gas_used: u128 = 0;
+        let mut total_gas_limit: u128 = 0;
+        let mut block_hash = *head_header.hash();
+        while num_history_blocks < FEE_ESTIMATION_HISTORY_BLOCKS {
+            // Older blocks may be garbage collected.
+            let history_block = match self.get_fee_history_block(&block_hash)? {
+                Some(history_block) => history_block,
+                None => break,
+            };
+            num_history_blocks += 1;
+            total_gas_used += u128::from(history_block.gas_used);
+            total_gas_limit += u128::from(history_block.gas_limit);
+            for (call_receiver_id, method_name, gas_used) in history_block.function_calls {
+                if call_receiver_id == receiver_id && method_names.contains(&method_name) {
+                    samples.entry(method_name).or_insert_with(Vec::new).push(gas_used);
+                }
+            }
+            if history_block.height == self.chain.genesis().height() {
+                break;
+            }
+            block_hash = history_block.prev_hash;
+        }
+
+        let economics_config = &self.chain.block_economics_config;
+        let gas_price_adjustment_rate =
+            economics_config.gas_price_adjustment_rate(protocol_version);
+        let min_gas_price = economics_config.min_gas_price(protocol_version);
+        let max_gas_price = economics_config.max_gas_price(protocol_version);
+        // Averages fit into `Gas`, since each of the summands does.
+        let avg_gas_limit = (total_gas_limit / max(num_history_blocks, 1) as u128) as Gas;
+        let avg_gas_used = (total_gas_used / max(num_history_blocks, 1) as u128) as Gas;
+        // Without any chunks in the recent blocks only the ratio of the full block matters.
+        let full_gas_limit = max(avg_gas_limit, 1);
+        let mut expected_gas_price = head_header.gas_price();
+        let mut projected_max_gas_price = head_header.gas_price();
+        for _ in 0..num_blocks {
+            expected_gas_price = Block::compute_new_gas_price(
+                expected_gas_price,
+                avg_gas_used,
+                avg_gas_limit,
+                gas_price_adjustment_rate,
+                min_gas_price,
+                max_gas_price,
+            );
+            projected_max_gas_price = Block::compute_new_gas_price(
+                projected_max_gas_price,
+                full_gas_limit,
+                full_gas_limit,
+                gas_price_adjustment_rate,
+                min_gas_price,
+                max_gas_price,
+            );
+        }
+
+        let mut function_calls = vec![];
+        let mut recommended_attached_gas: Gas = 0;
+        for action in actions.iter() {
+            if let Action::FunctionCall(FunctionCallAction { method_name, gas, .. }) = action {
+                let method_samples = samples.get(method_name).cloned().unwrap_or_default();
+                let max_gas_used = method_samples.iter().cloned().max().unwrap_or(0);
+                let avg_gas_used = if method_samples.is_empty() {
+                    0
+                } else {
+                    (method_samples.iter().map(|gas| u128::from(*gas)).sum::<u128>()
+                        / method_samples.len() as u128) as Gas
+                };
+                // Without any history the gas attached in the request is kept.
+                let attached_gas = if method_samples.is_empty() {
+                    *gas
+                } else {
+                    // The margin is a fraction of a `Gas` value, so it fits into `Gas`.
+                    let margin = (u128::from(max_gas_used)
+                        * RECOMMENDED_ATTACHED_GAS_MARGIN_PERCENT
+                        / 100) as Gas;
+                    max_gas_used.saturating_add(margin)
+                };
+                recommended_attached_gas = recommended_attached_gas.saturating_add(attached_gas);
+                function_calls.push(FunctionCallGasUsageView {
+                    method_name: method_name.clone(),
+                    num_samples: method_samples.len() as u64,
+                    max_gas_used,
+                    avg_gas_used,
+                });
+            }
+        }
+
+        let total_deposit = actions
+            .iter()
+            .fold(0 as Balance, |acc, action| acc.saturating_add(action.get_deposit_balance()));
+        let max_cost = u128::from(fees_gas.saturating_add(recommended_attached_gas))
+            .saturating_mul(projected_max_gas_price)
+            .saturating_add(total_deposit);
+        Ok(FeeEstimationView {
+            block_hash: *head_header.hash(),
+            block_height: head_header.height(),
+            gas_price: head_header.gas_price(),
+            expected_gas_price,
+            max_gas_price: projected_max_gas_price,
+            num_blocks,
+            fees_gas,
+            function_calls,
+            recommended_attached_gas,
+            max_cost,
+        })
+    }
+
     fn request_receipt_outcome(
         &mut self,
         receipt_id: CryptoHash,
@@ -298,6 +688,7 @@ impl ViewClientActor {
         tx_hash: CryptoHash,
         signer_account_id: AccountId,
         fetch_receipt: bool,
+        fetch_trace: bool,
     ) -> Result<Option<FinalExecutionOutcomeViewEnum>, TxStatusError> {
         {
             let mut request_manager = self.request_manager.write().expect(POISONED_LOCK_ERR);
@@ -308,7 +699,13 @@ impl ViewClientActor {
         }
 
         let head = self.chain.head().map_err(|e| TxStatusError::ChainError(e))?;
-        let target_shard_id = self.runtime_adapter.account_id_to_shard_id(&signer_account_id);
+        let target_shard_id = self
+            .runtime_adapter
+            .get_epoch_id_from_prev_block(&head.last_block_hash)
+            .and_then(|epoch_id| {
+                self.runtime_adapter.account_id_to_shard_id(&signer_account_id, &epoch_id)
+            })
+            .map_err(|e| TxStatusError::ChainError(e))?;
         // Check if we are tracking this shard.
         if self.runtime_adapter.cares_about_shard(
             self.validator_account_id.as_ref(),
@@ -330,6 +727,30 @@ impl ViewClientActor {
                         FinalExecutionStatus::SuccessValue(_)
                         | FinalExecutionStatus::Failure(_) => {}
                     }
+                    if fetch_trace {
+                        let receipts_trace = self
+                            .chain
+                            .get_receipts_execution_trace(&tx_result)
+                            .map_err(|e| TxStatusError::ChainError(e))?;
+                        let (final_outcome, receipts) = if fetch_receipt {
+                            let final_result = self
+                                .chain
+                                .get_final_transaction_result_with_receipt(tx_result)
+                                .map_err(|e| TxStatusError::ChainError(e))?;
+                            (final_result.final_outcome, Some(final_result.receipts))
+                        } else {
+                            (tx_result, None)
+                        };
+                        return Ok(Some(
+                            FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithTrace(
+                                FinalExecutionOutcomeWithTraceView {
+                                    final_outcome,
+                                    receipts,
+                                    receipts_trace,
+                                },
+                            ),
+                        ));
+                    }
                     if fetch_receipt {
                         let final_result = self
                             .chain
@@ -366,8 +787,6 @@ impl ViewClientActor {
         } else {
             let mut request_manager = self.request_manager.write().expect(POISONED_LOCK_ERR);
             if Self::need_request(tx_hash, &mut request_manager.tx_status_requests) {
-                let target_shard_id =
-                    self.runtime_adapter.account_id_to_shard_id(&signer_account_id);
                 let validator = self
                     .chain
                     .find_validator_for_forwarding(target_shard_id)
@@ -550,7 +969,7 @@ impl Handler<TxStatus> for ViewClientActor {
     type Result = Result<Option<FinalExecutionOutcomeViewEnum>, TxStatusError>;
 
     fn handle(&mut self, msg: TxStatus, _: &mut Self::Context) -> Self::Result {
-        self.get_tx_status(msg.tx_hash, msg.signer_account_id, msg.fetch_receipt)
+        self.get_tx_status(msg.tx_hash, msg.signer_account_id, msg.fetch_receipt, msg.fetch_trace)
     }
 }
 
@@ -564,6 +983,29 @@ impl Handler<GetValidatorInfo> for ViewClientActor {
     }
 }
 
+impl Handler<GetValidatorHistory> for ViewClientActor {
+    type Result = Result<Vec<EpochValidatorHistoryView>, String>;
+
+    fn handle(&mut self, msg: GetValidatorHistory, _: &mut Self::Context) -> Self::Result {
+        let num_epochs = msg.num_epochs.unwrap_or(DEFAULT_VALIDATOR_HISTORY_EPOCHS);
+        if num_epochs > MAX_VALIDATOR_HISTORY_EPOCHS {
+            return Err(format!(
+                "Validator history can be requested for at most {} epochs",
+                MAX_VALIDATOR_HISTORY_EPOCHS
+            ));
+        }
+        self.maybe_block_id_to_block_hash(msg.block_id)
+            .and_then(|block_hash| {
+                self.runtime_adapter.get_validator_history(
+                    &block_hash,
+                    num_epochs,
+                    msg.account_id.as_ref(),
+                )
+            })
+            .map_err(|err| err.to_string())
+    }
+}
+
 impl Handler<GetValidatorOrdered> for ViewClientActor {
     type Result = Result<Vec<ValidatorStakeView>, String>;
 
@@ -593,6 +1035,24 @@ impl Handler<GetStateChangesInBlock> for ViewClientActor {
     }
 }
 
+/// Returns changes of the account balance with their causes for the given range of blocks.
+impl Handler<GetAccountBalanceChanges> for ViewClientActor {
+    type Result = Result<Vec<AccountBalanceChangeView>, String>;
+
+    fn handle(&mut self, msg: GetAccountBalanceChanges, _: &mut Self::Context) -> Self::Result {
+        self.get_account_balance_changes(msg)
+    }
+}
+
+/// Estimates the fees of a transaction with the given actions from the recent blocks.
+impl Handler<EstimateFee> for ViewClientActor {
+    type Result = Result<FeeEstimationView, String>;
+
+    fn handle(&mut self, msg: EstimateFee, _: &mut Self::Context) -> Self::Result {
+        self.estimate_fee(msg)
+    }
+}
+
 /// Returns a list of changes in a store for a given block filtering by the state changes request.
 impl Handler<GetStateChanges> for ViewClientActor {
     type Result = Result<StateChangesView, String>;
@@ -661,17 +1121,27 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
     type Result = Result<GetExecutionOutcomeResponse, String>;
 
     fn handle(&mut self, msg: GetExecutionOutcome, _: &mut Self::Context) -> Self::Result {
-        let (id, target_shard_id) = match msg.id {
+        let (id, account_id) = match msg.id {
             TransactionOrReceiptId::Transaction { transaction_hash, sender_id } => {
-                (transaction_hash, self.runtime_adapter.account_id_to_shard_id(&sender_id))
+                (transaction_hash, sender_id)
             }
             TransactionOrReceiptId::Receipt { receipt_id, receiver_id } => {
-                (receipt_id, self.runtime_adapter.account_id_to_shard_id(&receiver_id))
+                (receipt_id, receiver_id)
             }
         };
         match self.chain.get_execution_outcome(&id) {
             Ok(outcome) => {
                 let mut outcome_proof = outcome.clone();
+                let epoch_id = self
+                    .chain
+                    .get_block_header(&outcome_proof.block_hash)
+                    .map_err(|e| e.to_string())?
+                    .epoch_id()
+                    .clone();
+                let target_shard_id = self
+                    .runtime_adapter
+                    .account_id_to_shard_id(&account_id, &epoch_id)
+                    .map_err(|e| e.to_string())?;
                 let next_block_hash = self
                     .chain
                     .get_next_block_hash_with_new_chunk(&outcome_proof.block_hash, target_shard_id)
@@ -706,6 +1176,13 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
             Err(e) => match e.kind() {
                 ErrorKind::DBNotFoundErr(_) => {
                     let head = self.chain.head().map_err(|e| TxStatusError::ChainError(e))?;
+                    let target_shard_id = self
+                        .runtime_adapter
+                        .get_epoch_id_from_prev_block(&head.last_block_hash)
+                        .and_then(|epoch_id| {
+                            self.runtime_adapter.account_id_to_shard_id(&account_id, &epoch_id)
+                        })
+                        .map_err(|e| e.to_string())?;
                     if self.runtime_adapter.cares_about_shard(
                         self.validator_account_id.as_ref(),
                         &head.last_block_hash,
@@ -808,13 +1285,18 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                 }
             }
             NetworkViewClientMessages::TxStatus { tx_hash, signer_account_id } => {
-                if let Ok(Some(result)) = self.get_tx_status(tx_hash, signer_account_id, false) {
+                if let Ok(Some(result)) =
+                    self.get_tx_status(tx_hash, signer_account_id, false, false)
+                {
                     // TODO: remove this legacy support in #3204
                     let result = match result {
                         FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(outcome) => outcome,
                         FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(
                             outcome,
                         ) => outcome.into(),
+                        FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithTrace(outcome) => {
+                            outcome.into()
+                        }
                     };
                     NetworkViewClientResponses::TxStatus(Box::new(result))
                 } else {
@@ -916,6 +1398,32 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                     NetworkViewClientResponses::NoResponse
                 }
             }
+            NetworkViewClientMessages::EpochSyncRequest { epoch_id } => {
+                let light_client_block =
+                    match self.chain.mut_store().get_epoch_light_client_block(&epoch_id.0) {
+                        Ok(light_client_block) => Some(light_client_block.clone()),
+                        Err(_) => match self.chain.head() {
+                            // The epoch is not finished yet, so the requester reached the current epoch.
+                            Ok(head) if head.epoch_id == epoch_id => None,
+                            _ => return NetworkViewClientResponses::NoResponse,
+                        },
+                    };
+                NetworkViewClientResponses::EpochSyncResponse(Box::new(EpochSyncResponse {
+                    epoch_id,
+                    light_client_block,
+                }))
+            }
+            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id } => {
+                match self.chain.get_epoch_sync_finalization(&epoch_id) {
+                    Ok(response) => NetworkViewClientResponses::EpochSyncFinalizationResponse(
+                        Box::new(response),
+                    ),
+                    Err(err) => {
+                        debug!(target: "sync", "Failed to build epoch sync finalization for {:?}: {}", epoch_id, err);
+                        NetworkViewClientResponses::NoResponse
+                    }
+                }
+            }
             NetworkViewClientMessages::GetChainInfo => match self.chain.head() {
                 Ok(head) => {
                     let height = self.get_height(&head);
@@ -62,6 +62,7 @@ fn test_verify_block_double_sign_challenge() {
         &signer,
         b1.header().next_bp_hash().clone(),
         block_merkle_tree.root(),
+        None,
     );
     let epoch_id = b1.header().epoch_id().clone();
     let valid_challenge = Challenge::produce(
@@ -308,6 +309,7 @@ fn test_verify_chunk_invalid_state_challenge() {
             0,
             1_000,
             0,
+            0,
             vec![],
             vec![],
             &vec![],
@@ -360,6 +362,7 @@ fn test_verify_chunk_invalid_state_challenge() {
         &validator_signer,
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
+        None,
     );
 
     let challenge_body = {
@@ -407,9 +410,33 @@ fn test_verify_chunk_invalid_state_challenge() {
             ],
         );
     }
+    let runtime_adapter = client.chain.runtime_adapter.clone();
+    // The chunk headers the congestion of the prev chunk is derived from must be the ones of the
+    // block before the prev block.
+    let prev_prev_block = client.chain.get_block(last_block.header().prev_hash()).unwrap().clone();
+    assert_eq!(
+        challenge_body.prev_prev_block_chunk_headers,
+        prev_prev_block.chunks().iter().cloned().collect::<Vec<_>>()
+    );
+    let mut forged_challenge_body = challenge_body.clone();
+    forged_challenge_body.prev_prev_block_chunk_headers =
+        last_block.chunks().iter().cloned().collect();
+    let forged_challenge =
+        Challenge::produce(ChallengeBody::ChunkState(forged_challenge_body), &validator_signer);
+    assert_eq!(
+        validate_challenge(
+            &*runtime_adapter,
+            &block.header().epoch_id(),
+            &block.header().prev_hash(),
+            &forged_challenge,
+        )
+        .unwrap_err()
+        .kind(),
+        ErrorKind::MaliciousChallenge
+    );
+
     let challenge =
         Challenge::produce(ChallengeBody::ChunkState(challenge_body), &validator_signer);
-    let runtime_adapter = client.chain.runtime_adapter.clone();
     assert_eq!(
         validate_challenge(
             &*runtime_adapter,
@@ -460,9 +487,13 @@ fn test_receive_invalid_chunk_as_chunk_producer() {
     assert!(result.is_err());
     assert_eq!(client.chain.head().unwrap().height, 1);
     // But everyone who doesn't track this shard have accepted.
-    let receipts_hashes = env.clients[0].runtime_adapter.build_receipts_hashes(&receipts);
+    let shard_layout =
+        env.clients[0].runtime_adapter.get_shard_layout(block.header().epoch_id()).unwrap();
+    let receipts_hashes =
+        env.clients[0].runtime_adapter.build_receipts_hashes(&receipts, &shard_layout);
     let (_receipts_root, receipts_proofs) = merklize(&receipts_hashes);
-    let receipts_by_shard = env.clients[0].shards_mgr.group_receipts_by_shard(receipts.clone());
+    let receipts_by_shard =
+        env.clients[0].shards_mgr.group_receipts_by_shard(receipts.clone(), &shard_layout);
     let one_part_receipt_proofs = env.clients[0].shards_mgr.receipts_recipient_filter(
         0,
         Vec::default(),
@@ -678,3 +709,94 @@ fn test_challenge_in_different_epoch() {
         }
     }
 }
+
+/// A client in fisherman mode keeps accepting blocks but does not produce chunks, even when its
+/// account is the chunk producer.
+#[test]
+fn test_fisherman_does_not_produce_chunks() {
+    let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+    env.clients[0].config.fisherman = true;
+    for height in 1..4 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(head.height, 3);
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+    assert_eq!(block.chunks()[0].height_included(), 0);
+}
+
+/// Returns the number of challenges of the given kind produced by this process so far.
+fn challenges_produced(kind: &str) -> f64 {
+    near_metrics::gather()
+        .iter()
+        .filter(|family| family.get_name() == "near_challenges_produced_total")
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| metric.get_label().iter().any(|label| label.get_value() == kind))
+        .map(|metric| metric.get_counter().get_value())
+        .sum()
+}
+
+/// Pops the requests sent to the network until a chunk proofs challenge.
+fn pop_chunk_proofs_challenge(network_adapter: &MockNetworkAdapter) -> Option<ChunkProofs> {
+    while let Some(request) = network_adapter.pop() {
+        if let NetworkRequests::Challenge(Challenge {
+            body: ChallengeBody::ChunkProofs(chunk_proofs),
+            ..
+        }) = request
+        {
+            return Some(chunk_proofs);
+        }
+    }
+    None
+}
+
+/// A block with an invalid chunk that was waiting for its chunks produces a challenge once the
+/// chunk is known.
+#[test]
+fn test_challenge_from_block_with_missing_chunks() {
+    init_test_logger();
+    let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+    env.produce_block(0, 1);
+    let block1_hash = *env.clients[0].chain.get_block_by_height(1).unwrap().hash();
+    let (chunk, _merkle_paths, _receipts, block) = create_invalid_proofs_chunk(&mut env.clients[0]);
+    let client = &mut env.clients[0];
+    let (_, result) = client.process_block(block, Provenance::NONE);
+    assert!(matches!(result.unwrap_err().kind(), ErrorKind::ChunksMissing(_)));
+
+    let challenges_before = challenges_produced("chunk_proofs");
+    let mut chain_store_update = client.chain.mut_store().store_update();
+    chain_store_update.save_invalid_chunk(chunk.clone());
+    chain_store_update.commit().unwrap();
+    client.process_blocks_with_missing_chunks(block1_hash, PROTOCOL_VERSION);
+
+    let chunk_proofs = pop_chunk_proofs_challenge(&env.network_adapters[0]).unwrap();
+    assert_eq!(chunk_proofs.chunk, MaybeEncodedShardChunk::Encoded(chunk));
+    assert!(challenges_produced("chunk_proofs") > challenges_before);
+}
+
+/// An orphan with an invalid chunk produces a challenge once its previous block arrives.
+#[test]
+fn test_challenge_from_orphan() {
+    init_test_logger();
+    let chain_genesis = ChainGenesis::test();
+    let mut env = TestEnv::new(chain_genesis.clone(), 1, 1);
+    let mut orphan_env = TestEnv::new(chain_genesis, 1, 1);
+    env.produce_block(0, 1);
+    let block1 = env.clients[0].chain.get_block_by_height(1).unwrap().clone();
+    let (chunk, _merkle_paths, _receipts, block) = create_invalid_proofs_chunk(&mut env.clients[0]);
+
+    let client = &mut orphan_env.clients[0];
+    let mut chain_store_update = client.chain.mut_store().store_update();
+    chain_store_update.save_invalid_chunk(chunk.clone());
+    chain_store_update.commit().unwrap();
+    let (_, result) = client.process_block(block, Provenance::NONE);
+    assert!(matches!(result.unwrap_err().kind(), ErrorKind::Orphan));
+
+    let challenges_before = challenges_produced("chunk_proofs");
+    let (_, result) = client.process_block(block1, Provenance::NONE);
+    assert!(result.is_ok());
+
+    let chunk_proofs = pop_chunk_proofs_challenge(&orphan_env.network_adapters[0]).unwrap();
+    assert_eq!(chunk_proofs.chunk, MaybeEncodedShardChunk::Encoded(chunk));
+    assert!(challenges_produced("chunk_proofs") > challenges_before);
+}
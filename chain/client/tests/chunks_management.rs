@@ -308,6 +308,10 @@ fn update_chunk_hash(chunk: PartialEncodedChunkV2, new_hash: ChunkHash) -> Parti
             header.hash = new_hash;
             ShardChunkHeader::V2(header)
         }
+        ShardChunkHeader::V3(mut header) => {
+            header.hash = new_hash;
+            ShardChunkHeader::V3(header)
+        }
     };
     PartialEncodedChunkV2 { header: new_header, parts: chunk.parts, receipts: chunk.receipts }
 }
@@ -325,6 +329,10 @@ fn update_chunk_height_created(
             header.inner.height_created = new_height;
             ShardChunkHeader::V2(header)
         }
+        ShardChunkHeader::V3(mut header) => {
+            header.inner.height_created = new_height;
+            ShardChunkHeader::V3(header)
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
 use std::path::Path;
@@ -6,10 +6,12 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 
 use actix::System;
+use chrono::Utc;
 use futures::{future, FutureExt};
 use num_rational::Rational;
 
 use near_chain::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA;
+use near_chain::test_utils::KeyValueRuntime;
 use near_chain::types::LatestKnown;
 use near_chain::validate::validate_chunk_with_chunk_extra;
 use near_chain::{
@@ -17,9 +19,12 @@ use near_chain::{
 };
 use near_chain_configs::{ClientConfig, Genesis};
 use near_chunks::{ChunkStatus, ShardsManager};
+use near_client::sync::StateSync;
 use near_client::test_utils::{create_chunk_on_height, setup_mock_all_validators};
 use near_client::test_utils::{setup_client, setup_mock, TestEnv};
-use near_client::{Client, GetBlock, GetBlockWithMerkleTree};
+use near_client::{
+    Client, GetBlock, GetBlockWithMerkleTree, ShardSyncDownload, ShardSyncStatus, SyncStatus,
+};
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
 use near_logger_utils::init_test_logger;
 #[cfg(feature = "metric_recorder")]
@@ -36,7 +41,7 @@ use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash, Digest};
 use near_primitives::merkle::verify_hash;
 use near_primitives::sharding::{
-    EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader, ShardChunkHeaderV2,
+    EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader, ShardChunkHeaderV2, ShardChunkHeaderV3,
 };
 use near_primitives::syncing::{get_num_state_parts, ShardStateSyncResponseHeader};
 use near_primitives::transaction::{
@@ -46,7 +51,7 @@ use near_primitives::types::{AccountId, BlockHeight, EpochId, NumBlocks, Validat
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::{QueryRequest, QueryResponseKind};
+use near_primitives::views::{QueryRequest, QueryResponseKind, ReceiptDelayReasonView};
 use near_store::test_utils::create_test_store;
 use neard::config::{GenesisExt, TESTING_INIT_BALANCE, TESTING_INIT_STAKE};
 use neard::NEAR_BASE;
@@ -210,6 +215,7 @@ fn receive_network_block() {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
+                None,
             );
             client.do_send(NetworkClientMessages::Block(block, PeerInfo::random().id, false));
             future::ready(())
@@ -280,6 +286,7 @@ fn produce_block_with_approvals() {
                 &signer1,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
+                None,
             );
             client.do_send(NetworkClientMessages::Block(
                 block.clone(),
@@ -442,6 +449,7 @@ fn invalid_blocks_common(is_requested: bool) {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
+                None,
             );
             // Send block with invalid chunk mask
             let mut block = valid_block.clone();
@@ -464,6 +472,9 @@ fn invalid_blocks_common(is_requested: bool) {
                 ShardChunkHeader::V2(chunk) => {
                     chunk.signature = some_signature;
                 }
+                ShardChunkHeader::V3(chunk) => {
+                    chunk.signature = some_signature;
+                }
             };
             block.set_chunks(chunks);
             client.do_send(NetworkClientMessages::Block(
@@ -552,6 +563,7 @@ fn invalid_blocks() {
                 &signer,
                 last_block.header.next_bp_hash,
                 CryptoHash::default(),
+                None,
             );
             block.mut_header().get_mut().inner_rest.chunk_mask = vec![];
             client.do_send(NetworkClientMessages::Block(
@@ -583,6 +595,7 @@ fn invalid_blocks() {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
+                None,
             );
             client.do_send(NetworkClientMessages::Block(block2, PeerInfo::random().id, false));
             future::ready(())
@@ -1034,7 +1047,7 @@ fn test_bad_orphan() {
         // Orphan block with unknown epoch
         let mut block = env.clients[0].produce_block(6).unwrap().unwrap();
         block.mut_header().get_mut().inner_lite.epoch_id = EpochId(CryptoHash(Digest([1; 32])));
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([1; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([1; 32]));
         block.mut_header().resign(&*signer);
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
         assert_eq!(res.as_ref().unwrap_err().kind(), ErrorKind::EpochOutOfBounds);
@@ -1042,7 +1055,7 @@ fn test_bad_orphan() {
     {
         // Orphan block with invalid signature
         let mut block = env.clients[0].produce_block(7).unwrap().unwrap();
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([1; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([1; 32]));
         block.mut_header().get_mut().init();
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
         assert_eq!(res.as_ref().unwrap_err().kind(), ErrorKind::InvalidSignature);
@@ -1056,14 +1069,19 @@ fn test_bad_orphan() {
                 Block::BlockV1(_) => unreachable!(),
                 Block::BlockV2(body) => body.as_mut(),
             };
-            let chunk = match &mut body.chunks[0] {
+            match &mut body.chunks[0] {
                 ShardChunkHeader::V1(_) => unreachable!(),
-                ShardChunkHeader::V2(chunk) => chunk,
-            };
-            chunk.inner.outcome_root = CryptoHash(Digest([1; 32]));
-            chunk.hash = ShardChunkHeaderV2::compute_hash(&chunk.inner);
+                ShardChunkHeader::V2(chunk) => {
+                    chunk.inner.outcome_root = CryptoHash(Digest([1; 32]));
+                    chunk.hash = ShardChunkHeaderV2::compute_hash(&chunk.inner);
+                }
+                ShardChunkHeader::V3(chunk) => {
+                    chunk.inner.outcome_root = CryptoHash(Digest([1; 32]));
+                    chunk.hash = ShardChunkHeaderV3::compute_hash(&chunk.inner);
+                }
+            }
         }
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
         block.mut_header().resign(&*signer);
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
         assert_eq!(res.as_ref().unwrap_err().kind(), ErrorKind::InvalidChunkHeadersRoot);
@@ -1073,7 +1091,7 @@ fn test_bad_orphan() {
         let mut block = env.clients[0].produce_block(9).unwrap().unwrap();
         let some_signature = Signature::from_parts(KeyType::ED25519, &[1; 64]).unwrap();
         block.mut_header().get_mut().inner_rest.approvals = vec![Some(some_signature)];
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
         block.mut_header().resign(&*signer);
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
         assert_eq!(res.as_ref().unwrap_err().kind(), ErrorKind::Orphan);
@@ -1087,14 +1105,19 @@ fn test_bad_orphan() {
                 Block::BlockV1(_) => unreachable!(),
                 Block::BlockV2(body) => body.as_mut(),
             };
-            let chunk = match &mut body.chunks[0] {
+            match &mut body.chunks[0] {
                 ShardChunkHeader::V1(_) => unreachable!(),
-                ShardChunkHeader::V2(chunk) => chunk,
-            };
-            chunk.signature = some_signature;
-            chunk.hash = ShardChunkHeaderV2::compute_hash(&chunk.inner);
+                ShardChunkHeader::V2(chunk) => {
+                    chunk.signature = some_signature;
+                    chunk.hash = ShardChunkHeaderV2::compute_hash(&chunk.inner);
+                }
+                ShardChunkHeader::V3(chunk) => {
+                    chunk.signature = some_signature;
+                    chunk.hash = ShardChunkHeaderV3::compute_hash(&chunk.inner);
+                }
+            }
         }
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([4; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([4; 32]));
         block.mut_header().resign(&*signer);
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
         assert_eq!(res.as_ref().unwrap_err().kind(), ErrorKind::Orphan);
@@ -1102,7 +1125,7 @@ fn test_bad_orphan() {
     {
         // Orphan block that's too far ahead: 20 * epoch_length
         let mut block = block.clone();
-        block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
+        *block.mut_header().get_mut().prev_hash = CryptoHash(Digest([3; 32]));
         block.mut_header().get_mut().inner_lite.height += 2000;
         block.mut_header().resign(&*signer);
         let (_, res) = env.clients[0].process_block(block, Provenance::NONE);
@@ -1895,7 +1918,7 @@ fn test_block_height_processed_orphan() {
     let block = env.clients[0].produce_block(1).unwrap().unwrap();
     let mut orphan_block = block.clone();
     let validator_signer = InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0");
-    orphan_block.mut_header().get_mut().prev_hash = hash(&[1]);
+    *orphan_block.mut_header().get_mut().prev_hash = hash(&[1]);
     orphan_block.mut_header().resign(&validator_signer);
     let block_height = orphan_block.header().height();
     let (_, tip) = env.clients[0].process_block(orphan_block, Provenance::NONE);
@@ -2025,6 +2048,54 @@ fn test_validate_chunk_extra() {
     .is_ok());
 }
 
+/// A chunk header must advertise the size of the delayed receipts queue after applying the
+/// previous chunk.
+#[test]
+fn test_validate_chunk_delayed_receipts_count() {
+    let mut env = TestEnv::new(ChainGenesis::test(), 1, 1);
+    for i in 1..3 {
+        env.produce_block(0, i);
+    }
+    let last_block = env.clients[0].chain.get_block_by_height(2).unwrap().clone();
+    let chunk_extra = env.clients[0].chain.get_chunk_extra(last_block.hash(), 0).unwrap().clone();
+    let validator_signer = InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0");
+    let make_chunk_header = |delayed_receipts_count| {
+        ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+            *last_block.hash(),
+            chunk_extra.state_root,
+            chunk_extra.outcome_root,
+            CryptoHash::default(),
+            0,
+            3,
+            0,
+            chunk_extra.gas_used,
+            chunk_extra.gas_limit,
+            chunk_extra.balance_burnt,
+            CryptoHash::default(),
+            CryptoHash::default(),
+            chunk_extra.validator_proposals.clone(),
+            delayed_receipts_count,
+            &validator_signer,
+        ))
+    };
+    let mut chain_store = ChainStore::new(env.clients[0].chain.store().owned_store(), 0);
+    let mut validate = |chunk_header: &ShardChunkHeader| {
+        validate_chunk_with_chunk_extra(
+            &mut chain_store,
+            &*env.clients[0].runtime_adapter,
+            last_block.hash(),
+            &chunk_extra,
+            &last_block.chunks()[0],
+            chunk_header,
+        )
+        .map_err(|e| e.kind())
+    };
+
+    // The delayed receipts queue of the test runtime is always empty.
+    assert_ne!(validate(&make_chunk_header(0)), Err(ErrorKind::InvalidDelayedReceiptsCount));
+    assert_eq!(validate(&make_chunk_header(1)), Err(ErrorKind::InvalidDelayedReceiptsCount));
+}
+
 /// Change protocol version back and forth and make sure that we do not produce invalid blocks
 #[test]
 fn test_gas_price_change_no_chunk() {
@@ -2128,6 +2199,172 @@ fn test_catchup_gas_price_change() {
     assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
 }
 
+/// A node that restarts in the middle of state sync resumes it from the state header and the
+/// state parts it has already stored instead of downloading them again.
+#[cfg(feature = "protocol_feature_epoch_sync")]
+#[test]
+fn test_epoch_sync_finalization() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 2, 1, create_nightshade_runtimes(&genesis, 2));
+    for i in 1..=3 * epoch_length + 3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    let epoch_id = head.epoch_id.clone();
+    let epoch_start_height =
+        env.clients[0].runtime_adapter.get_epoch_start_height(&head.last_block_hash).unwrap();
+    let epoch_first_header =
+        env.clients[0].chain.get_header_by_height(epoch_start_height).unwrap().clone();
+    let prev_epoch_id = env.clients[0]
+        .chain
+        .get_block_header(epoch_first_header.prev_hash())
+        .unwrap()
+        .epoch_id()
+        .clone();
+    let light_client_block = env.clients[0]
+        .chain
+        .mut_store()
+        .get_epoch_light_client_block(&prev_epoch_id.0)
+        .unwrap()
+        .clone();
+    let prev_block_producers = near_chain::get_epoch_block_producers_view(
+        &prev_epoch_id,
+        epoch_first_header.prev_hash(),
+        &*env.clients[0].runtime_adapter,
+    )
+    .unwrap();
+
+    let response = env.clients[0].chain.get_epoch_sync_finalization(&epoch_id).unwrap();
+    let num_headers = response.headers.len();
+    assert_eq!(response.headers[num_headers - 2].hash(), epoch_first_header.hash());
+    assert_eq!(epoch_first_header.epoch_sync_data_hash(), Some(response.epoch_sync_data.hash()));
+
+    let apply = |env: &mut TestEnv, response| {
+        env.clients[1].chain.apply_epoch_sync_finalization(
+            &epoch_id,
+            &prev_block_producers,
+            &light_client_block,
+            response,
+        )
+    };
+
+    // Epoch manager records that are not committed in the first block of the epoch.
+    let mut forged = response.clone();
+    forged.epoch_sync_data.next_epoch_info.epoch_height += 1;
+    assert_eq!(apply(&mut env, forged).unwrap_err().kind(), ErrorKind::InvalidEpochSyncDataHash);
+    let mut forged = response.clone();
+    forged.epoch_sync_data.prev_epoch_block_infos.last_mut().unwrap().total_supply += 1;
+    assert_eq!(apply(&mut env, forged).unwrap_err().kind(), ErrorKind::InvalidEpochSyncDataHash);
+
+    // The first block of the epoch must be approved by the block after it.
+    let mut forged = response.clone();
+    forged.headers.pop();
+    match apply(&mut env, forged).unwrap_err().kind() {
+        ErrorKind::InvalidEpochSyncResponse(_) => {}
+        kind => panic!("unexpected error {:?}", kind),
+    }
+    let mut forged = response.clone();
+    let signer = InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0");
+    let next_header = forged.headers.last_mut().unwrap();
+    next_header.get_mut().inner_rest.approvals = vec![None];
+    next_header.resign(&signer);
+    assert_eq!(apply(&mut env, forged).unwrap_err().kind(), ErrorKind::NotEnoughApprovals);
+    assert_eq!(env.clients[1].chain.header_head().unwrap().height, 0);
+
+    apply(&mut env, response).unwrap();
+    assert_eq!(
+        &env.clients[1].chain.header_head().unwrap().last_block_hash,
+        epoch_first_header.hash()
+    );
+
+    // Header sync continues from the first block of the epoch.
+    let headers = (epoch_start_height + 1..=head.height)
+        .map(|height| env.clients[0].chain.get_header_by_height(height).unwrap().clone())
+        .collect();
+    env.clients[1].chain.sync_block_headers(headers, |_| panic!("unexpected challenge")).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().last_block_hash, head.last_block_hash);
+}
+
+#[test]
+fn test_state_sync_resume_after_restart() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 2, 1, create_nightshade_runtimes(&genesis, 2));
+    let mut blocks = vec![];
+    for i in 1..=6 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    assert!(env.clients[1].chain.get_chunk_extra(blocks[4].hash(), 0).is_err());
+
+    let sync_hash = *blocks[5].hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    let state_sync_header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    let num_parts = get_num_state_parts(state_sync_header.state_root_node().memory_usage);
+    let state_sync_parts = (0..num_parts)
+        .map(|i| env.clients[0].chain.get_state_response_part(0, i, sync_hash).unwrap())
+        .collect::<Vec<_>>();
+
+    // Every call starts from a fresh `StateSync` without any progress, as after a restart.
+    let restart_state_sync = |env: &mut TestEnv| -> ShardSyncDownload {
+        let mut state_sync = StateSync::new(env.network_adapters[1].clone(), None);
+        let mut new_shard_sync = HashMap::new();
+        let runtime_adapter = env.clients[1].runtime_adapter.clone();
+        state_sync
+            .sync_shards_status(
+                &None,
+                sync_hash,
+                &mut new_shard_sync,
+                &mut env.clients[1].chain,
+                &runtime_adapter,
+                &vec![],
+                vec![0],
+                Utc::now(),
+            )
+            .unwrap();
+        new_shard_sync.remove(&0).unwrap()
+    };
+
+    // Nothing is stored yet, so the sync starts from the header.
+    let shard_sync = restart_state_sync(&mut env);
+    assert!(matches!(shard_sync.status, ShardSyncStatus::StateDownloadHeader));
+
+    // Only the header is stored, so the sync resumes with downloading all the parts.
+    env.clients[1].chain.set_state_header(0, sync_hash, state_sync_header).unwrap();
+    let shard_sync = restart_state_sync(&mut env);
+    assert!(matches!(shard_sync.status, ShardSyncStatus::StateDownloadParts));
+    assert_eq!(shard_sync.downloads.len(), num_parts as usize);
+    assert!(shard_sync.downloads.iter().all(|download| !download.done));
+
+    // All the parts are stored, so the sync resumes with finalizing the state.
+    for i in 0..num_parts {
+        env.clients[1]
+            .chain
+            .set_state_part(0, sync_hash, i, num_parts, &state_sync_parts[i as usize])
+            .unwrap();
+    }
+    let shard_sync = restart_state_sync(&mut env);
+    assert!(matches!(shard_sync.status, ShardSyncStatus::StateDownloadComplete));
+    let chunk_extra_after_sync =
+        env.clients[1].chain.get_chunk_extra(blocks[4].hash(), 0).unwrap().clone();
+    let expected_chunk_extra =
+        env.clients[0].chain.get_chunk_extra(blocks[4].hash(), 0).unwrap().clone();
+    assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
+}
+
 #[test]
 fn test_block_execution_outcomes() {
     let epoch_length = 5;
@@ -2209,6 +2446,60 @@ fn test_block_execution_outcomes() {
     assert!(execution_outcomes_from_block[0].outcome_with_id.id == delayed_receipt_id[0]);
 }
 
+/// Test that the receipts that don't fit into the chunks because of the gas limit are traced as
+/// delayed by the delayed receipts queue.
+#[test]
+fn test_receipts_execution_trace_delayed_receipts() {
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    genesis.config.epoch_length = 100;
+    genesis.config.gas_limit = 1000000000000;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let signer = InMemorySigner::from_seed("test0", KeyType::ED25519, "test0");
+    let mut tx_hashes = vec![];
+    // The transactions alone use up the gas limit of the chunk they are included in, so all the
+    // local receipts become delayed and need several chunks to be executed.
+    for i in 0..10 {
+        let tx = SignedTransaction::send_money(
+            i + 1,
+            "test0".to_string(),
+            "test0".to_string(),
+            &signer,
+            1,
+            *genesis_block.hash(),
+        );
+        tx_hashes.push(tx.get_hash());
+        env.clients[0].process_tx(tx, false, false);
+    }
+    for i in 1..12 {
+        env.produce_block(0, i);
+    }
+
+    let mut max_delay_blocks = 0;
+    for tx_hash in tx_hashes {
+        let chain = &mut env.clients[0].chain;
+        let final_outcome = chain.get_final_transaction_result(&tx_hash).unwrap();
+        for trace in chain.get_receipts_execution_trace(&final_outcome).unwrap() {
+            // Blocks are produced at every height and every block has a chunk, so the only
+            // reason for the delay is the delayed receipts queue.
+            if trace.delay_blocks == 0 {
+                assert!(trace.delay_reasons.is_empty());
+            } else {
+                assert_eq!(
+                    trace.delay_reasons,
+                    vec![ReceiptDelayReasonView::DelayedReceiptsQueue {
+                        blocks: trace.delay_blocks
+                    }]
+                );
+            }
+            max_delay_blocks = std::cmp::max(max_delay_blocks, trace.delay_blocks);
+        }
+    }
+    assert!(max_delay_blocks > 0);
+}
+
 #[test]
 fn test_epoch_protocol_version_change() {
     init_test_logger();
@@ -2436,3 +2727,51 @@ fn test_fork_execution_outcome() {
         env.clients[0].chain.store().get_outcomes_by_id(&receipt_id).unwrap();
     assert!(receipt_execution_outcomes.is_empty());
 }
+
+/// A validator restarted with an empty data directory, e.g. restored from an old backup, still
+/// refuses to sign blocks at or below the height recorded in its slashing protection file.
+#[test]
+fn test_slashing_protection_after_restart() {
+    let dir = tempfile::Builder::new().prefix("slashing_protection").tempdir().unwrap();
+    let chain_genesis = ChainGenesis::test();
+    let create_client = || {
+        let runtime_adapter = Arc::new(KeyValueRuntime::new_with_validators(
+            create_test_store(),
+            vec![vec!["test0".to_string()]],
+            1,
+            1,
+            chain_genesis.epoch_length,
+        ));
+        let mut config = ClientConfig::test(true, 10, 20, 1, false);
+        config.epoch_length = chain_genesis.epoch_length;
+        config.slashing_protection_file = Some(dir.path().join("slashing_protection.json"));
+        let validator_signer =
+            Arc::new(InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0"))
+                as Arc<dyn ValidatorSigner>;
+        let mut client = Client::new(
+            config,
+            chain_genesis.clone(),
+            runtime_adapter,
+            Arc::new(MockNetworkAdapter::default()),
+            Some(validator_signer),
+            false,
+        )
+        .unwrap();
+        client.sync_status = SyncStatus::NoSync;
+        client
+    };
+
+    let mut client = create_client();
+    for height in 1..=3 {
+        let block = client.produce_block(height).unwrap().unwrap();
+        let (_, result) = client.process_block(block, Provenance::PRODUCED);
+        result.unwrap();
+    }
+
+    let mut client = create_client();
+    assert_eq!(client.chain.head().unwrap().height, 0);
+    for height in 1..=3 {
+        assert!(client.produce_block(height).is_err());
+    }
+    assert!(client.produce_block(4).unwrap().is_some());
+}
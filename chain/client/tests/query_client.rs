@@ -72,6 +72,7 @@ fn query_status_not_crash() {
                 &signer,
                 block.header.next_bp_hash,
                 block_merkle_tree.root(),
+                None,
             );
             next_block.mut_header().get_mut().inner_lite.timestamp =
                 to_timestamp(next_block.header().timestamp() + chrono::Duration::seconds(60));
@@ -134,6 +135,7 @@ fn test_execution_outcome_for_chunk() {
                     tx_hash,
                     signer_account_id: "test".to_string(),
                     fetch_receipt: false,
+                    fetch_trace: false,
                 })
                 .await
                 .unwrap()
@@ -144,6 +146,9 @@ fn test_execution_outcome_for_chunk() {
                 FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(outcome) => {
                     outcome.into()
                 }
+                FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithTrace(outcome) => {
+                    outcome.into()
+                }
             };
 
             let mut execution_outcomes_in_block = view_client
@@ -0,0 +1,216 @@
+#![cfg(feature = "protocol_feature_resharding")]
+use std::path::Path;
+use std::sync::Arc;
+
+use near_chain::{ChainGenesis, RuntimeAdapter};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_crypto::{InMemorySigner, KeyType};
+use near_logger_utils::init_test_logger;
+use near_primitives::receipt::DelayedReceiptIndices;
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::transaction::{
+    Action, DeployContractAction, FunctionCallAction, SignedTransaction,
+};
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{Balance, ShardId};
+use near_primitives::version::{ProtocolFeature, PROTOCOL_FEATURES_TO_VERSION_MAPPING};
+use near_primitives::views::{QueryRequest, QueryResponseKind};
+use near_store::get;
+use near_store::test_utils::create_test_store;
+use neard::config::GenesisExt;
+
+fn create_env(genesis: &Genesis) -> TestEnv {
+    let runtime: Arc<dyn RuntimeAdapter> = Arc::new(neard::NightshadeRuntime::new(
+        Path::new("."),
+        create_test_store(),
+        genesis,
+        vec![],
+        vec![],
+    ));
+    TestEnv::new_with_runtime(ChainGenesis::from(genesis), 1, 1, vec![runtime])
+}
+
+/// Returns the balance of the account in the given shard after the head, or None if the account
+/// isn't in the state of the shard.
+fn query_balance(env: &mut TestEnv, shard_id: ShardId, account_id: &str) -> Option<Balance> {
+    let client = &mut env.clients[0];
+    let head = client.chain.head().unwrap();
+    let block = client.chain.get_block(&head.last_block_hash).unwrap().clone();
+    let state_root =
+        client.chain.get_chunk_extra(&head.last_block_hash, shard_id).unwrap().state_root;
+    let response = client
+        .runtime_adapter
+        .query(
+            shard_id,
+            &state_root,
+            head.height,
+            block.header().raw_timestamp(),
+            &head.last_block_hash,
+            &head.epoch_id,
+            &QueryRequest::ViewAccount { account_id: account_id.to_string() },
+        )
+        .ok()?;
+    match response.kind {
+        QueryResponseKind::ViewAccount(account) => Some(account.amount),
+        _ => panic!("Wrong return value"),
+    }
+}
+
+/// The shard is split into two at the epoch boundary after the protocol upgrade, while money is
+/// sent to an account that moves to the new shard.
+#[test]
+fn test_split_shard_at_protocol_upgrade() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    genesis.config.epoch_length = epoch_length;
+    genesis.config.protocol_version =
+        PROTOCOL_FEATURES_TO_VERSION_MAPPING[&ProtocolFeature::Resharding] - 1;
+    let shard_layout = ShardLayout::v1(vec!["test1".to_string()], Some(vec![0, 0]), 1);
+    genesis.config.resharding_shard_layout = Some(shard_layout.clone());
+    let mut env = create_env(&genesis);
+    let initial_balance = query_balance(&mut env, 0, "test1").unwrap();
+
+    let signer = InMemorySigner::from_seed("test0", KeyType::ED25519, "test0");
+    let num_transfers = 4 * epoch_length;
+    for height in 1..=num_transfers + 3 {
+        if height <= num_transfers {
+            let last_block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+            let tx = SignedTransaction::send_money(
+                height,
+                "test0".to_string(),
+                "test1".to_string(),
+                &signer,
+                1,
+                last_block_hash,
+            );
+            env.clients[0].process_tx(tx, false, false);
+        }
+        env.produce_block(0, height);
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(
+        env.clients[0].runtime_adapter.get_shard_layout(&head.epoch_id).unwrap(),
+        shard_layout
+    );
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap().clone();
+    assert_eq!(block.chunks().len(), 2);
+    assert!(block.chunks().iter().all(|chunk| chunk.height_included() == head.height));
+
+    let account_shard_id = |account_id: &str| {
+        env.clients[0]
+            .runtime_adapter
+            .account_id_to_shard_id(&account_id.to_string(), &head.epoch_id)
+            .unwrap()
+    };
+    assert_eq!(account_shard_id("test0"), 0);
+    assert_eq!(account_shard_id("test1"), 1);
+    assert!(query_balance(&mut env, 0, "test0").is_some());
+    assert!(query_balance(&mut env, 0, "test1").is_none());
+    assert!(query_balance(&mut env, 1, "test0").is_none());
+    // All the transfers arrived, including the ones in flight when the shard was split.
+    assert_eq!(
+        query_balance(&mut env, 1, "test1").unwrap(),
+        initial_balance + num_transfers as Balance
+    );
+}
+
+/// Transfers to the account that moves to the new shard are delayed behind function calls that
+/// burn the whole gas limit of the chunks, so they are in the delayed receipts queue of the shard
+/// when it's split, and are executed from the queue of the new shard.
+#[test]
+fn test_split_shard_with_delayed_receipts() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    genesis.config.epoch_length = epoch_length;
+    genesis.config.gas_limit = 200_000_000_000_000;
+    genesis.config.protocol_version =
+        PROTOCOL_FEATURES_TO_VERSION_MAPPING[&ProtocolFeature::Resharding] - 1;
+    let shard_layout = ShardLayout::v1(vec!["test1".to_string()], Some(vec![0, 0]), 1);
+    genesis.config.resharding_shard_layout = Some(shard_layout.clone());
+    let mut env = create_env(&genesis);
+    let initial_balance = query_balance(&mut env, 0, "test1").unwrap();
+
+    let signer = InMemorySigner::from_seed("test0", KeyType::ED25519, "test0");
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let tx = SignedTransaction::from_actions(
+        1,
+        "test0".to_string(),
+        "test0".to_string(),
+        &signer,
+        vec![Action::DeployContract(DeployContractAction {
+            code: include_bytes!("../../../runtime/near-vm-runner/tests/res/test_contract_rs.wasm")
+                .to_vec(),
+        })],
+        genesis_hash,
+    );
+    env.clients[0].process_tx(tx, false, false);
+    env.produce_block(0, 1);
+
+    let num_transfers = 4 * epoch_length;
+    let mut nonce = 1;
+    for height in 2..=num_transfers + 6 {
+        if height <= num_transfers + 1 {
+            let last_block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+            for _ in 0..3 {
+                nonce += 1;
+                let tx = SignedTransaction::from_actions(
+                    nonce,
+                    "test0".to_string(),
+                    "test0".to_string(),
+                    &signer,
+                    vec![Action::FunctionCall(FunctionCallAction {
+                        method_name: "sum_n".to_string(),
+                        args: u64::MAX.to_le_bytes().to_vec(),
+                        gas: 100_000_000_000_000,
+                        deposit: 0,
+                    })],
+                    last_block_hash,
+                );
+                env.clients[0].process_tx(tx, false, false);
+            }
+            nonce += 1;
+            let tx = SignedTransaction::send_money(
+                nonce,
+                "test0".to_string(),
+                "test1".to_string(),
+                &signer,
+                1,
+                last_block_hash,
+            );
+            env.clients[0].process_tx(tx, false, false);
+        }
+        env.produce_block(0, height);
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(
+        env.clients[0].runtime_adapter.get_shard_layout(&head.epoch_id).unwrap(),
+        shard_layout
+    );
+
+    // The delayed receipts queue isn't empty after the last block before the shard is split.
+    let client = &mut env.clients[0];
+    let first_block_hash = (1..=head.height)
+        .map(|height| *client.chain.get_block_by_height(height).unwrap().hash())
+        .find(|block_hash| {
+            let epoch_id = client.chain.get_block_header(block_hash).unwrap().epoch_id().clone();
+            client.runtime_adapter.get_shard_layout(&epoch_id).unwrap() == shard_layout
+        })
+        .unwrap();
+    let last_block_hash = *client.chain.get_block_header(&first_block_hash).unwrap().prev_hash();
+    let state_root = client.chain.get_chunk_extra(&last_block_hash, 0).unwrap().state_root;
+    let state_update = client.runtime_adapter.get_tries().new_trie_update(0, state_root);
+    let indices: DelayedReceiptIndices =
+        get(&state_update, &TrieKey::DelayedReceiptIndices).unwrap().unwrap();
+    assert!(indices.next_available_index > indices.first_index);
+
+    // All the transfers arrived, including the delayed ones when the shard was split.
+    assert_eq!(
+        query_balance(&mut env, 1, "test1").unwrap(),
+        initial_balance + num_transfers as Balance
+    );
+}
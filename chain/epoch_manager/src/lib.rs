@@ -6,11 +6,14 @@ use cached::{Cached, SizedCache};
 use log::{debug, warn};
 use primitive_types::U256;
 
+use near_primitives::checked_feature;
 use near_primitives::epoch_manager::{
-    BlockInfo, EpochConfig, EpochInfo, EpochSummary, SlashState, AGGREGATOR_KEY,
+    BlockInfo, EpochConfig, EpochInfo, EpochSummary, EpochSyncData, EpochValidatorStats,
+    SlashState, ValidatorEpochStats, AGGREGATOR_KEY,
 };
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockChunkValidatorStats, BlockHeight, EpochId, ShardId,
     ValidatorId, ValidatorKickoutReason, ValidatorStake, ValidatorStats,
@@ -19,7 +22,9 @@ use near_primitives::version::{ProtocolVersion, UPGRADABILITY_FIX_PROTOCOL_VERSI
 use near_primitives::views::{
     CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
 };
-use near_store::{ColBlockInfo, ColEpochInfo, ColEpochStart, Store, StoreUpdate};
+use near_store::{
+    ColBlockInfo, ColEpochInfo, ColEpochStart, ColEpochValidatorStats, Store, StoreUpdate,
+};
 
 use crate::proposals::proposals_to_epoch_info;
 pub use crate::reward_calculator::RewardCalculator;
@@ -42,6 +47,8 @@ pub struct EpochManager {
     /// Current epoch config.
     /// TODO: must be dynamically changing over time, so there should be a way to change it.
     config: EpochConfig,
+    /// Config of the epochs since `ProtocolFeature::Resharding`, if the shards are split.
+    resharding_config: Option<EpochConfig>,
     reward_calculator: RewardCalculator,
     /// Genesis protocol version. Useful when there are protocol upgrades.
     genesis_protocol_version: ProtocolVersion,
@@ -72,6 +79,7 @@ impl EpochManager {
         let mut epoch_manager = EpochManager {
             store,
             config,
+            resharding_config: None,
             reward_calculator,
             genesis_protocol_version,
             epochs_info: SizedCache::with_size(EPOCH_CACHE_SIZE),
@@ -107,6 +115,28 @@ impl EpochManager {
         Ok(epoch_manager)
     }
 
+    /// Splits the shards into the given shard layout at the first epoch with
+    /// `ProtocolFeature::Resharding`.
+    pub fn with_resharding_shard_layout(mut self, shard_layout: ShardLayout) -> Self {
+        self.resharding_config = Some(self.config.for_shard_layout(shard_layout));
+        self
+    }
+
+    fn get_epoch_config(&self, protocol_version: ProtocolVersion) -> &EpochConfig {
+        match &self.resharding_config {
+            Some(config)
+                if checked_feature!(
+                    "protocol_feature_resharding",
+                    Resharding,
+                    protocol_version
+                ) =>
+            {
+                config
+            }
+            _ => &self.config,
+        }
+    }
+
     /// # Parameters
     /// epoch_info
     /// block_validator_tracker
@@ -298,12 +328,36 @@ impl EpochManager {
             proposals, validator_kickout, block_validator_tracker, chunk_validator_tracker
         );
 
+        let all_validator_block_chunk_stats = epoch_info
+            .validators
+            .iter()
+            .enumerate()
+            .map(|(validator_id, validator)| {
+                let block_stats = block_validator_tracker
+                    .get(&(validator_id as u64))
+                    .cloned()
+                    .unwrap_or_default();
+                let mut chunk_stats = ValidatorStats::default();
+                for tracker in chunk_validator_tracker.values() {
+                    if let Some(stats) = tracker.get(&(validator_id as u64)) {
+                        chunk_stats.produced += stats.produced;
+                        chunk_stats.expected += stats.expected;
+                    }
+                }
+                (
+                    validator.account_id.clone(),
+                    BlockChunkValidatorStats { block_stats, chunk_stats },
+                )
+            })
+            .collect();
+
         Ok(EpochSummary {
             prev_epoch_last_block_hash,
             all_proposals: proposals,
             validator_kickout,
             validator_block_chunk_stats,
             next_version,
+            all_validator_block_chunk_stats,
         })
     }
 
@@ -321,6 +375,7 @@ impl EpochManager {
             validator_kickout,
             validator_block_chunk_stats,
             next_version,
+            all_validator_block_chunk_stats,
         } = self.collect_blocks_info(&block_info, last_block_hash)?;
         let epoch_info = self.get_epoch_info(&block_info.epoch_id)?;
         let epoch_protocol_version = epoch_info.protocol_version;
@@ -339,8 +394,10 @@ impl EpochManager {
             epoch_protocol_version,
             self.genesis_protocol_version,
         );
+        let stats_kickout = validator_kickout.clone();
+        let stats_reward = validator_reward.clone();
         let next_next_epoch_info = match proposals_to_epoch_info(
-            &self.config,
+            self.get_epoch_config(next_version),
             rng_seed,
             &next_epoch_info,
             all_proposals,
@@ -358,6 +415,17 @@ impl EpochManager {
             }
             Err(err) => return Err(err),
         };
+        let epoch_validator_stats = self.compute_epoch_validator_stats(
+            block_info,
+            last_block_hash,
+            all_validator_block_chunk_stats,
+            &stats_kickout,
+            &stats_reward,
+            &next_next_epoch_info.stake_change,
+        )?;
+        store_update
+            .set_ser(ColEpochValidatorStats, last_block_hash.as_ref(), &epoch_validator_stats)
+            .map_err(EpochError::from)?;
         // This epoch info is computed for the epoch after next (T+2),
         // where epoch_id of it is the hash of last block in this epoch (T).
         self.save_epoch_info(store_update, &EpochId(*last_block_hash), next_next_epoch_info)?;
@@ -365,6 +433,119 @@ impl EpochManager {
         Ok(EpochId(prev_epoch_last_block_hash))
     }
 
+    /// Computes the performance of the validators of the epoch (T) that ends with the given block.
+    fn compute_epoch_validator_stats(
+        &mut self,
+        block_info: &BlockInfo,
+        last_block_hash: &CryptoHash,
+        mut validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
+        validator_kickout: &HashMap<AccountId, ValidatorKickoutReason>,
+        validator_reward: &HashMap<AccountId, Balance>,
+        stake_change: &BTreeMap<AccountId, Balance>,
+    ) -> Result<EpochValidatorStats, EpochError> {
+        let epoch_start_height = self.get_block_info(&block_info.epoch_first_block)?.height;
+        let epoch_info = self.get_epoch_info(&block_info.epoch_id)?;
+        let validators = epoch_info
+            .validators
+            .iter()
+            .map(|validator| {
+                let stats = validator_block_chunk_stats
+                    .remove(&validator.account_id)
+                    .unwrap_or_else(|| BlockChunkValidatorStats {
+                        block_stats: ValidatorStats::default(),
+                        chunk_stats: ValidatorStats::default(),
+                    });
+                ValidatorEpochStats {
+                    account_id: validator.account_id.clone(),
+                    public_key: validator.public_key.clone(),
+                    stake: validator.stake,
+                    block_stats: stats.block_stats,
+                    chunk_stats: stats.chunk_stats,
+                    kickout_reason: validator_kickout.get(&validator.account_id).cloned(),
+                    reward: validator_reward.get(&validator.account_id).cloned().unwrap_or(0),
+                    next_stake: stake_change.get(&validator.account_id).cloned(),
+                }
+            })
+            .collect();
+        Ok(EpochValidatorStats {
+            epoch_id: block_info.epoch_id.clone(),
+            epoch_height: epoch_info.epoch_height,
+            epoch_start_height,
+            last_block_hash: *last_block_hash,
+            validators,
+        })
+    }
+
+    /// Returns the records that epoch sync needs to process the blocks from the block after
+    /// `prev_hash` on, if that block is the first block of an epoch after the first one.
+    pub fn get_epoch_sync_data(
+        &mut self,
+        prev_hash: &CryptoHash,
+    ) -> Result<Option<EpochSyncData>, EpochError> {
+        let prev_block_info = self.get_block_info(prev_hash)?.clone();
+        if prev_block_info.prev_hash == CryptoHash::default()
+            || !self.is_next_block_in_next_epoch(&prev_block_info)?
+        {
+            return Ok(None);
+        }
+        // The light client block of the epoch is created for a block after the last final block
+        // of the last final block of the last block in the epoch, see `create_light_client_block`.
+        let first_block_hash = if prev_block_info.last_final_block_hash == CryptoHash::default() {
+            CryptoHash::default()
+        } else {
+            self.get_block_info(&prev_block_info.last_final_block_hash)?.last_final_block_hash
+        };
+        let mut prev_epoch_block_infos = vec![];
+        let mut block_hash = *prev_hash;
+        loop {
+            let block_info = self.get_block_info(&block_hash)?.clone();
+            let next_hash = block_info.prev_hash;
+            prev_epoch_block_infos.push(block_info);
+            if block_hash == first_block_hash || next_hash == CryptoHash::default() {
+                break;
+            }
+            block_hash = next_hash;
+        }
+        prev_epoch_block_infos.reverse();
+
+        let prev_epoch_first_block_info =
+            self.get_block_info(&prev_block_info.epoch_first_block)?.clone();
+        let epoch_id = self.get_next_epoch_id_from_info(&prev_block_info)?;
+        let prev_epoch_info = self.get_epoch_info(&prev_block_info.epoch_id)?.clone();
+        let epoch_info = self.get_epoch_info(&epoch_id)?.clone();
+        let next_epoch_info = self.get_epoch_info(&EpochId(*prev_hash))?.clone();
+        Ok(Some(EpochSyncData {
+            prev_epoch_block_infos,
+            prev_epoch_first_block_info,
+            prev_epoch_info,
+            epoch_info,
+            next_epoch_info,
+        }))
+    }
+
+    /// Saves the block and epoch infos downloaded by epoch sync, together with the epoch starts
+    /// of the blocks that are the first blocks of their epochs.
+    pub fn save_epoch_sync_data(
+        &mut self,
+        block_infos: Vec<(CryptoHash, BlockInfo)>,
+        epoch_infos: Vec<(EpochId, EpochInfo)>,
+    ) -> Result<StoreUpdate, EpochError> {
+        let mut store_update = self.store.store_update();
+        for (epoch_id, epoch_info) in epoch_infos {
+            self.save_epoch_info(&mut store_update, &epoch_id, epoch_info)?;
+        }
+        for (block_hash, block_info) in block_infos {
+            if block_info.epoch_first_block == block_hash {
+                self.save_epoch_start(&mut store_update, &block_info.epoch_id, block_info.height)?;
+            }
+            if block_info.last_finalized_height > self.largest_final_height {
+                self.largest_final_height = block_info.last_finalized_height;
+            }
+            self.save_block_info(&mut store_update, &block_hash, block_info)?;
+        }
+        Ok(store_update)
+    }
+
     pub fn record_block_info(
         &mut self,
         current_hash: &CryptoHash,
@@ -662,6 +843,21 @@ impl EpochManager {
         self.get_epoch_info(&epoch_id)
     }
 
+    pub fn get_shard_layout(&mut self, epoch_id: &EpochId) -> Result<ShardLayout, EpochError> {
+        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version;
+        Ok(self.get_epoch_config(protocol_version).shard_layout.clone())
+    }
+
+    /// Returns true if the epoch after the epoch of the given block has a different shard layout.
+    pub fn will_shard_layout_change(
+        &mut self,
+        parent_hash: &CryptoHash,
+    ) -> Result<bool, EpochError> {
+        let epoch_id = self.get_epoch_id(parent_hash)?;
+        let next_epoch_id = self.get_next_epoch_id(parent_hash)?;
+        Ok(self.get_shard_layout(&epoch_id)? != self.get_shard_layout(&next_epoch_id)?)
+    }
+
     pub fn cares_about_shard_from_prev_block(
         &mut self,
         parent_hash: &CryptoHash,
@@ -932,6 +1128,48 @@ impl EpochManager {
         })
     }
 
+    /// Returns the performance of the validators in up to `num_epochs` finished epochs before the
+    /// epoch of the given block, latest first. Stops early at genesis and at the first epoch
+    /// without saved stats, i.e. one finalized before the stats were saved or skipped by sync.
+    /// If `account_id` is given, only its stats are kept, so an epoch in which it isn't a
+    /// validator has no validators.
+    pub fn get_validator_history(
+        &mut self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+        account_id: Option<&AccountId>,
+    ) -> Result<Vec<EpochValidatorStats>, EpochError> {
+        let mut history = vec![];
+        let mut epoch_first_block = self.get_block_info(block_hash)?.epoch_first_block;
+        while (history.len() as u64) < num_epochs {
+            let last_block_hash = match self.get_block_info(&epoch_first_block) {
+                Ok(block_info) => block_info.prev_hash,
+                Err(EpochError::MissingBlock(_)) => break,
+                Err(err) => return Err(err),
+            };
+            if last_block_hash == CryptoHash::default() {
+                break;
+            }
+            match self
+                .store
+                .get_ser::<EpochValidatorStats>(ColEpochValidatorStats, last_block_hash.as_ref())
+                .map_err(EpochError::from)?
+            {
+                Some(mut epoch_validator_stats) => {
+                    if let Some(account_id) = account_id {
+                        epoch_validator_stats
+                            .validators
+                            .retain(|validator| &validator.account_id == account_id);
+                    }
+                    history.push(epoch_validator_stats)
+                }
+                None => break,
+            }
+            epoch_first_block = self.get_block_info(&last_block_hash)?.epoch_first_block;
+        }
+        Ok(history)
+    }
+
     /// Compare two epoch ids based on their start height. This works because finality gadget
     /// guarantees that we cannot have two different epochs on two forks
     pub fn compare_epoch_id(
@@ -1905,6 +2143,152 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validator_history() {
+        let stake_amount = 1_000_000;
+        let validators = vec![("test1", stake_amount), ("test2", stake_amount)];
+        let total_supply = stake_amount * validators.len() as u128;
+        let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 2, 0, 90, 60);
+        let rng_seed = [0; 32];
+        let h = hash_range(5);
+        epoch_manager
+            .record_block_info(
+                &h[0],
+                block_info(
+                    0,
+                    0,
+                    Default::default(),
+                    Default::default(),
+                    h[0],
+                    vec![true],
+                    total_supply,
+                ),
+                rng_seed,
+            )
+            .unwrap();
+        epoch_manager
+            .record_block_info(
+                &h[1],
+                block_info(1, 1, h[0], h[0], h[1], vec![true], total_supply),
+                rng_seed,
+            )
+            .unwrap();
+        epoch_manager
+            .record_block_info(
+                &h[2],
+                block_info(2, 2, h[1], h[1], h[1], vec![true], total_supply),
+                rng_seed,
+            )
+            .unwrap();
+        epoch_manager
+            .record_block_info(
+                &h[3],
+                block_info(3, 3, h[2], h[2], h[3], vec![true], total_supply),
+                rng_seed,
+            )
+            .unwrap();
+
+        // Nothing is finished before the first epoch ends.
+        assert!(epoch_manager.get_validator_history(&h[2], 10, None).unwrap().is_empty());
+
+        let history = epoch_manager.get_validator_history(&h[3], 10, None).unwrap();
+        assert_eq!(history.len(), 1);
+        let epoch_stats = &history[0];
+        assert_eq!(epoch_stats.epoch_id, EpochId::default());
+        assert_eq!(epoch_stats.epoch_start_height, 1);
+        assert_eq!(epoch_stats.last_block_hash, h[2]);
+        let next_next_epoch_info = epoch_manager.get_epoch_info(&EpochId(h[2])).unwrap().clone();
+        let mut num_produced_blocks = 0;
+        for (validator, account_id) in epoch_stats.validators.iter().zip(["test1", "test2"].iter())
+        {
+            assert_eq!(&validator.account_id, account_id);
+            assert_eq!(validator.stake, stake_amount);
+            assert_eq!(validator.block_stats.produced, validator.block_stats.expected);
+            assert_eq!(validator.kickout_reason, None);
+            assert_eq!(
+                validator.reward,
+                next_next_epoch_info
+                    .validator_reward
+                    .get(&validator.account_id)
+                    .cloned()
+                    .unwrap_or(0)
+            );
+            assert_eq!(
+                validator.next_stake,
+                next_next_epoch_info.stake_change.get(&validator.account_id).cloned()
+            );
+            num_produced_blocks += validator.block_stats.produced;
+        }
+        assert_eq!(num_produced_blocks, 2);
+        assert_eq!(epoch_manager.get_validator_history(&h[3], 0, None).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_validator_history_kickout() {
+        let amount_staked = 1_000_000;
+        let validators = vec![("test1", amount_staked), ("test2", amount_staked)];
+        let mut epoch_manager = setup_default_epoch_manager(validators, 4, 1, 2, 0, 90, 60);
+        let h = hash_range(15);
+
+        // test2 misses its block at height 2 and is kicked out of the epoch starting at height 10.
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        record_block(&mut epoch_manager, h[0], h[1], 1, vec![]);
+        record_block(&mut epoch_manager, h[1], h[3], 3, vec![]);
+        record_block(&mut epoch_manager, h[3], h[4], 4, vec![]);
+        record_block(&mut epoch_manager, h[4], h[6], 6, vec![]);
+        record_block(&mut epoch_manager, h[6], h[8], 8, vec![]);
+        record_block(&mut epoch_manager, h[8], h[9], 9, vec![]);
+        record_block(&mut epoch_manager, h[9], h[10], 10, vec![]);
+        record_block(&mut epoch_manager, h[10], h[11], 11, vec![]);
+        record_block(&mut epoch_manager, h[11], h[12], 12, vec![]);
+        record_block(&mut epoch_manager, h[12], h[13], 13, vec![]);
+        record_block(&mut epoch_manager, h[13], h[14], 14, vec![]);
+
+        let history = epoch_manager.get_validator_history(&h[14], 10, None).unwrap();
+        assert_eq!(
+            history.iter().map(|epoch| epoch.last_block_hash).collect::<Vec<_>>(),
+            vec![h[13], h[9], h[4]]
+        );
+        let account_ids = |epoch: &EpochValidatorStats| {
+            epoch.validators.iter().map(|v| v.account_id.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(account_ids(&history[0]), vec!["test1".to_string()]);
+        assert_eq!(account_ids(&history[2]), vec!["test1".to_string(), "test2".to_string()]);
+        let kicked_out = &history[2].validators[1];
+        assert_eq!(
+            kicked_out.kickout_reason,
+            Some(ValidatorKickoutReason::NotEnoughBlocks { produced: 1, expected: 2 })
+        );
+        assert_eq!((kicked_out.block_stats.produced, kicked_out.block_stats.expected), (1, 2));
+        assert_eq!(history[2].validators[0].kickout_reason, None);
+
+        // Filtering by the kicked out validator keeps the epochs, with no stats for the epoch in
+        // which it isn't a validator anymore.
+        let test2_history =
+            epoch_manager.get_validator_history(&h[14], 10, Some(&"test2".to_string())).unwrap();
+        assert_eq!(test2_history.len(), history.len());
+        assert!(test2_history[0].validators.is_empty());
+        for (test2_epoch, epoch) in test2_history.iter().zip(history.iter()) {
+            assert_eq!(test2_epoch.last_block_hash, epoch.last_block_hash);
+            assert_eq!(
+                test2_epoch.validators,
+                epoch
+                    .validators
+                    .iter()
+                    .filter(|validator| validator.account_id == "test2")
+                    .cloned()
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(test2_history[2].validators, vec![kicked_out.clone()]);
+
+        // The history of an unknown account has the epochs without any validators.
+        let unknown_history =
+            epoch_manager.get_validator_history(&h[14], 2, Some(&"test3".to_string())).unwrap();
+        assert_eq!(unknown_history.len(), 2);
+        assert!(unknown_history.iter().all(|epoch| epoch.validators.is_empty()));
+    }
+
     #[test]
     fn test_validator_reward_weight_by_stake() {
         let stake_amount1 = 1_000_000;
@@ -2901,6 +3285,44 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "protocol_feature_resharding")]
+    fn test_shard_layout_switch() {
+        use near_primitives::version::{ProtocolFeature, PROTOCOL_FEATURES_TO_VERSION_MAPPING};
+
+        let resharding_protocol_version =
+            PROTOCOL_FEATURES_TO_VERSION_MAPPING[&ProtocolFeature::Resharding];
+        let store = create_test_store();
+        let config = epoch_config(2, 1, 2, 0, 90, 60, 0);
+        let amount_staked = 1_000_000;
+        let validators = vec![stake("test1", amount_staked), stake("test2", amount_staked)];
+        let shard_layout = ShardLayout::v1(vec!["test2".to_string()], Some(vec![0, 0]), 1);
+        let mut epoch_manager = EpochManager::new(
+            store.clone(),
+            config.clone(),
+            resharding_protocol_version - 1,
+            default_reward_calculator(),
+            validators.clone(),
+        )
+        .unwrap()
+        .with_resharding_shard_layout(shard_layout.clone());
+        let h = hash_range(8);
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        let mut block_info1 = block_info(1, 1, h[0], h[0], h[0], vec![], DEFAULT_TOTAL_SUPPLY);
+        block_info1.latest_protocol_version = resharding_protocol_version - 1;
+        epoch_manager.record_block_info(&h[1], block_info1, [0; 32]).unwrap();
+        for i in 2..6 {
+            record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
+        }
+        assert_eq!(epoch_manager.get_shard_layout(&EpochId(h[2])).unwrap(), ShardLayout::v0(1, 0));
+        assert_eq!(epoch_manager.get_shard_layout(&EpochId(h[4])).unwrap(), shard_layout);
+        // The epoch of block 5 is followed by the epoch that starts with the new shard layout.
+        assert!(!epoch_manager.will_shard_layout_change(&h[3]).unwrap());
+        assert!(epoch_manager.will_shard_layout_change(&h[5]).unwrap());
+        let epoch_info = epoch_manager.get_epoch_info(&EpochId(h[4])).unwrap();
+        assert_eq!(epoch_info.chunk_producers_settlement.len(), 2);
+    }
+
     #[test]
     fn test_protocol_version_switch_with_many_seats() {
         let store = create_test_store();
@@ -216,6 +216,7 @@ pub fn proposals_to_epoch_info(
 mod tests {
     use num_rational::Rational;
 
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::version::PROTOCOL_VERSION;
 
     use crate::test_utils::{
@@ -265,7 +266,7 @@ mod tests {
             proposals_to_epoch_info(
                 &EpochConfig {
                     epoch_length: 2,
-                    num_shards: 5,
+                    shard_layout: ShardLayout::v0(5, 0),
                     num_block_producer_seats: 6,
                     num_block_producer_seats_per_shard: vec![6, 2, 2, 2, 2],
                     avg_hidden_validator_seats_per_shard: vec![6, 2, 2, 2, 2],
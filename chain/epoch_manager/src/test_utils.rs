@@ -6,6 +6,7 @@ use near_crypto::{KeyType, SecretKey};
 use near_primitives::challenge::SlashedValidator;
 use near_primitives::epoch_manager::{EpochConfig, EpochInfo, ValidatorWeight};
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::{
     AccountId, Balance, BlockHeight, BlockHeightDelta, EpochHeight, NumSeats, NumShards,
     ValidatorId, ValidatorKickoutReason, ValidatorStake,
@@ -127,7 +128,7 @@ pub fn epoch_config(
 ) -> EpochConfig {
     EpochConfig {
         epoch_length,
-        num_shards,
+        shard_layout: ShardLayout::v0(num_shards, 0),
         num_block_producer_seats,
         num_block_producer_seats_per_shard: get_num_seats_per_shard(
             num_shards,
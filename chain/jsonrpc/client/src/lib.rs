@@ -7,12 +7,15 @@ use serde::Serialize;
 
 use near_primitives::hash::CryptoHash;
 use near_primitives::rpc::{
-    RpcQueryRequest, RpcStateChangesRequest, RpcStateChangesResponse, RpcValidatorsOrderedRequest,
+    RpcAccountBalanceChangesRequest, RpcAccountBalanceChangesResponse, RpcEstimateFeeRequest,
+    RpcQueryRequest, RpcStateChangesRequest, RpcStateChangesResponse, RpcTransactionStatusOptions,
+    RpcValidatorsHistoryRequest, RpcValidatorsOrderedRequest,
 };
 use near_primitives::types::{BlockId, BlockReference, MaybeBlockId, ShardId};
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, FinalExecutionOutcomeView, GasPriceView,
-    QueryResponse, StatusResponse, ValidatorStakeView,
+    BlockView, ChunkView, EpochValidatorHistoryView, EpochValidatorInfo, FeeEstimationView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeWithTraceView, GasPriceView, QueryResponse,
+    StatusResponse, ValidatorStakeView,
 };
 
 use crate::message::{from_slice, Message, RpcError};
@@ -212,6 +215,27 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "block", request)
     }
 
+    /// Same as `tx`, but also explains how each receipt of the transaction was executed.
+    pub fn tx_with_trace(
+        &self,
+        hash: String,
+        account_id: String,
+    ) -> RpcRequest<FinalExecutionOutcomeWithTraceView> {
+        let options = RpcTransactionStatusOptions { verbose: true };
+        call_method(&self.client, &self.server_addr, "tx", (hash, account_id, options))
+    }
+
+    pub fn account_balance_changes(
+        &self,
+        request: RpcAccountBalanceChangesRequest,
+    ) -> RpcRequest<RpcAccountBalanceChangesResponse> {
+        call_method(&self.client, &self.server_addr, "account_balance_changes", request)
+    }
+
+    pub fn estimate_fee(&self, request: RpcEstimateFeeRequest) -> RpcRequest<FeeEstimationView> {
+        call_method(&self.client, &self.server_addr, "estimate_fee", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_changes(
         &self,
@@ -227,6 +251,14 @@ impl JsonRpcClient {
     ) -> RpcRequest<Vec<ValidatorStakeView>> {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_validators_history(
+        &self,
+        request: RpcValidatorsHistoryRequest,
+    ) -> RpcRequest<Vec<EpochValidatorHistoryView>> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_history", request)
+    }
 }
 
 fn create_client() -> Client {
@@ -51,6 +51,19 @@ enum RpcQueryRequest {
         method_name: String,
         args_base64: String,
     },
+    ViewStorageUsage {
+        finality: Finality,
+        account_id: String,
+        prefix_len: Option<u32>,
+    },
+    ViewStorageStaking {
+        finality: Finality,
+        account_id: String,
+    },
+    ViewContractAbi {
+        finality: Finality,
+        account_id: String,
+    },
 }
 
 #[derive(Debug, arbitrary::Arbitrary, serde::Serialize)]
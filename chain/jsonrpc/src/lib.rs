@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fmt::Display;
 use std::string::FromUtf8Error;
 use std::time::Duration;
@@ -16,9 +17,10 @@ use tokio::time::{delay_for, timeout};
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetStateChanges, GetStateChangesInBlock,
-    GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus, TxStatusError, ViewClientActor,
+    ClientActor, EstimateFee, GetAccountBalanceChanges, GetBlock, GetBlockProof, GetChunk,
+    GetExecutionOutcome, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetStateChanges,
+    GetStateChangesInBlock, GetValidatorHistory, GetValidatorInfo, GetValidatorOrdered, Query,
+    Status, TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_client::message::{Message, Request, RpcError};
@@ -30,13 +32,15 @@ use near_network::{NetworkClientMessages, NetworkClientResponses};
 use near_primitives::errors::{InvalidTxError, TxExecutionError};
 use near_primitives::hash::CryptoHash;
 use near_primitives::rpc::{
-    RpcBroadcastTxSyncResponse, RpcLightClientExecutionProofRequest,
+    RpcAccountBalanceChangesRequest, RpcAccountBalanceChangesResponse, RpcBroadcastTxSyncResponse,
+    RpcEstimateFeeRequest, RpcLightClientExecutionProofRequest,
     RpcLightClientExecutionProofResponse, RpcQueryRequest, RpcStateChangesInBlockRequest,
     RpcStateChangesInBlockResponse, RpcStateChangesRequest, RpcStateChangesResponse,
-    RpcValidatorsOrderedRequest, TransactionInfo,
+    RpcTransactionStatusOptions, RpcValidatorsHistoryRequest, RpcValidatorsOrderedRequest,
+    TransactionInfo,
 };
 use near_primitives::serialize::{from_base, from_base64, BaseEncode};
-use near_primitives::transaction::SignedTransaction;
+use near_primitives::transaction::{Action, SignedTransaction};
 use near_primitives::types::{AccountId, BlockId, BlockReference, MaybeBlockId};
 use near_primitives::utils::is_valid_account_id;
 use near_primitives::views::{
@@ -130,11 +134,22 @@ fn jsonify<T: serde::Serialize>(
 
 fn parse_tx(params: Option<Value>) -> Result<SignedTransaction, RpcError> {
     let (encoded,) = parse_params::<(String,)>(params)?;
+    decode_tx(encoded)
+}
+
+fn decode_tx(encoded: String) -> Result<SignedTransaction, RpcError> {
     let bytes = from_base64_or_parse_err(encoded)?;
     SignedTransaction::try_from_slice(&bytes)
         .map_err(|e| RpcError::invalid_params(format!("Failed to decode transaction: {}", e)))
 }
 
+fn parse_tx_id(hash: CryptoHash, account_id: String) -> Result<TransactionInfo, RpcError> {
+    if !is_valid_account_id(&account_id) {
+        return Err(RpcError::invalid_params(format!("Invalid account id: {}", account_id)));
+    }
+    Ok(TransactionInfo::TransactionId { hash, account_id })
+}
+
 /// A general Server Error
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, near_rpc_error_macro::RpcError)]
 pub enum ServerError {
@@ -233,6 +248,7 @@ impl JsonRpcHandler {
             "EXPERIMENTAL_check_tx" => self.check_tx(request.params).await,
             "validators" => self.validators(request.params).await,
             "EXPERIMENTAL_validators_ordered" => self.validators_ordered(request.params).await,
+            "EXPERIMENTAL_validators_history" => self.validators_history(request.params).await,
             "query" => self.query(request.params).await,
             "health" => self.health().await,
             "status" => self.status().await,
@@ -243,6 +259,8 @@ impl JsonRpcHandler {
             "chunk" => self.chunk(request.params).await,
             "EXPERIMENTAL_changes" => self.changes_in_block_by_type(request.params).await,
             "EXPERIMENTAL_changes_in_block" => self.changes_in_block(request.params).await,
+            "account_balance_changes" => self.account_balance_changes(request.params).await,
+            "estimate_fee" => self.estimate_fee(request.params).await,
             "next_light_client_block" => self.next_light_client_block(request.params).await,
             "EXPERIMENTAL_light_client_proof" => {
                 self.light_client_execution_outcome_proof(request.params).await
@@ -289,6 +307,7 @@ impl JsonRpcHandler {
                         tx_hash,
                         signer_account_id: signer_account_id.clone(),
                         fetch_receipt: false,
+                        fetch_trace: false,
                     })
                     .await
                 {
@@ -315,6 +334,7 @@ impl JsonRpcHandler {
         &self,
         tx_info: TransactionInfo,
         fetch_receipt: bool,
+        fetch_trace: bool,
     ) -> Result<FinalExecutionOutcomeViewEnum, TxStatusError> {
         let (tx_hash, account_id) = match &tx_info {
             TransactionInfo::Transaction(tx) => (tx.get_hash(), tx.transaction.signer_id.clone()),
@@ -328,6 +348,7 @@ impl JsonRpcHandler {
                         tx_hash,
                         signer_account_id: account_id.clone(),
                         fetch_receipt,
+                        fetch_trace,
                     })
                     .await;
                 match tx_status_result {
@@ -359,7 +380,7 @@ impl JsonRpcHandler {
     async fn tx_polling(&self, tx_info: TransactionInfo) -> Result<Value, RpcError> {
         timeout(self.polling_config.polling_timeout, async {
             loop {
-                match self.tx_status_fetch(tx_info.clone(), false).await {
+                match self.tx_status_fetch(tx_info.clone(), false, false).await {
                     Ok(tx_status) => break jsonify(Ok(Ok(tx_status))),
                     // If transaction is missing, keep polling.
                     Err(TxStatusError::MissingTransaction(_)) => {}
@@ -466,7 +487,7 @@ impl JsonRpcHandler {
 
     async fn send_tx_commit(&self, params: Option<Value>) -> Result<Value, RpcError> {
         let tx = parse_tx(params)?;
-        match self.tx_status_fetch(TransactionInfo::Transaction(tx.clone()), false).await {
+        match self.tx_status_fetch(TransactionInfo::Transaction(tx.clone()), false, false).await {
             Ok(outcome) => {
                 return jsonify(Ok(Ok(outcome)));
             }
@@ -548,11 +569,22 @@ impl JsonRpcHandler {
                     },
                 },
                 "contract" => QueryRequest::ViewState { account_id, prefix: data.into() },
+                "storage_staking" => QueryRequest::ViewStorageStaking { account_id },
+                "contract_abi" => QueryRequest::ViewContractAbi { account_id },
+                "storage_usage" => QueryRequest::ViewStorageUsage {
+                    account_id,
+                    prefix_len: maybe_extra_arg
+                        .map(|len| len.parse())
+                        .transpose()
+                        .map_err(|_| RpcError::server_error(Some("Invalid prefix length")))?,
+                },
                 "call" => match maybe_extra_arg {
                     Some(method_name) => QueryRequest::CallFunction {
                         account_id,
                         method_name: method_name.to_string(),
                         args: data.into(),
+                        state_overrides: None,
+                        caller: None,
                     },
                     None => {
                         return Err(RpcError::server_error(Some(
@@ -599,22 +631,24 @@ impl JsonRpcHandler {
         params: Option<Value>,
         fetch_receipt: bool,
     ) -> Result<Value, RpcError> {
-        let tx_status_request =
+        let (tx_status_request, options) =
             if let Ok((hash, account_id)) = parse_params::<(CryptoHash, String)>(params.clone()) {
-                if !is_valid_account_id(&account_id) {
-                    return Err(RpcError::invalid_params(format!(
-                        "Invalid account id: {}",
-                        account_id
-                    )));
-                }
-                TransactionInfo::TransactionId { hash, account_id }
+                (parse_tx_id(hash, account_id)?, RpcTransactionStatusOptions::default())
+            } else if let Ok((hash, account_id, options)) =
+                parse_params::<(CryptoHash, String, RpcTransactionStatusOptions)>(params.clone())
+            {
+                (parse_tx_id(hash, account_id)?, options)
+            } else if let Ok((encoded, options)) =
+                parse_params::<(String, RpcTransactionStatusOptions)>(params.clone())
+            {
+                (TransactionInfo::Transaction(decode_tx(encoded)?), options)
             } else {
                 let tx = parse_tx(params)?;
-                TransactionInfo::Transaction(tx)
+                (TransactionInfo::Transaction(tx), RpcTransactionStatusOptions::default())
             };
 
         jsonify(Ok(self
-            .tx_status_fetch(tx_status_request, fetch_receipt)
+            .tx_status_fetch(tx_status_request, fetch_receipt, options.verbose)
             .await
             .map_err(|err| err.into())))
     }
@@ -685,6 +719,47 @@ impl JsonRpcHandler {
         )
     }
 
+    async fn account_balance_changes(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcAccountBalanceChangesRequest { account_id, from_block_height, to_block_height } =
+            parse_params(params)?;
+        if !is_valid_account_id(&account_id) {
+            return Err(RpcError::invalid_params(format!("Invalid account id: {}", account_id)));
+        }
+        jsonify(
+            self.view_client_addr
+                .send(GetAccountBalanceChanges {
+                    account_id: account_id.clone(),
+                    from_block_height,
+                    to_block_height,
+                })
+                .await
+                .map(|v| v.map(|changes| RpcAccountBalanceChangesResponse { account_id, changes })),
+        )
+    }
+
+    async fn estimate_fee(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcEstimateFeeRequest { signer_id, receiver_id, actions, num_blocks } =
+            parse_params(params)?;
+        for account_id in [&signer_id, &receiver_id].iter() {
+            if !is_valid_account_id(account_id) {
+                return Err(RpcError::invalid_params(format!(
+                    "Invalid account id: {}",
+                    account_id
+                )));
+            }
+        }
+        let actions = actions
+            .into_iter()
+            .map(Action::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| RpcError::invalid_params(format!("Invalid action: {}", err)))?;
+        jsonify(
+            self.view_client_addr
+                .send(EstimateFee { signer_id, receiver_id, actions, num_blocks })
+                .await,
+        )
+    }
+
     async fn next_light_client_block(&self, params: Option<Value>) -> Result<Value, RpcError> {
         let (last_block_hash,) = parse_params::<(CryptoHash,)>(params)?;
         jsonify(self.view_client_addr.send(GetNextLightClientBlock { last_block_hash }).await)
@@ -741,6 +816,26 @@ impl JsonRpcHandler {
         jsonify(self.view_client_addr.send(GetValidatorInfo { block_id }).await)
     }
 
+    /// Returns the blocks and chunks produced and expected, the kickouts, the rewards and the stake
+    /// changes of the validators in the finished epochs.
+    async fn validators_history(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcValidatorsHistoryRequest { block_id, account_id, num_epochs } =
+            parse_params(params)?;
+        if let Some(account_id) = &account_id {
+            if !is_valid_account_id(account_id) {
+                return Err(RpcError::invalid_params(format!(
+                    "Invalid account id: {}",
+                    account_id
+                )));
+            }
+        }
+        jsonify(
+            self.view_client_addr
+                .send(GetValidatorHistory { block_id, account_id, num_epochs })
+                .await,
+        )
+    }
+
     /// Returns the current epoch validators ordered in the block producer order with repetition.
     /// This endpoint is solely used for bridge currently and is not intended for other external use
     /// cases.
@@ -10,10 +10,12 @@ use near_logger_utils::init_test_logger;
 use near_network::test_utils::WaitOrTimeout;
 use near_primitives::account::{AccessKey, AccessKeyPermission};
 use near_primitives::hash::CryptoHash;
+use near_primitives::rpc::RpcEstimateFeeRequest;
 use near_primitives::rpc::RpcQueryRequest;
+use near_primitives::rpc::RpcValidatorsHistoryRequest;
 use near_primitives::rpc::RpcValidatorsOrderedRequest;
 use near_primitives::types::{BlockId, BlockReference, ShardId, SyncCheckpoint};
-use near_primitives::views::{QueryRequest, QueryResponseKind};
+use near_primitives::views::{ActionView, QueryRequest, QueryResponseKind};
 
 #[macro_use]
 pub mod test_utils;
@@ -325,6 +327,8 @@ fn test_query_call_function() {
                     account_id: "test".to_string(),
                     method_name: "method".to_string(),
                     args: vec![].into(),
+                    state_overrides: None,
+                    caller: None,
                 },
             })
             .await
@@ -491,6 +495,71 @@ fn test_gas_price() {
     });
 }
 
+/// Estimate fees of a function call that has no history in the recent blocks.
+#[test]
+fn test_estimate_fee() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let estimation = client
+            .estimate_fee(RpcEstimateFeeRequest {
+                signer_id: "test1".to_string(),
+                receiver_id: "test2".to_string(),
+                actions: vec![ActionView::FunctionCall {
+                    method_name: "method".to_string(),
+                    args: String::new(),
+                    gas: 1_000_000,
+                    deposit: 5,
+                }],
+                num_blocks: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(estimation.block_height, 0);
+        assert_eq!(estimation.num_blocks, 10);
+        assert!(estimation.gas_price > 0);
+        assert!(estimation.expected_gas_price <= estimation.max_gas_price);
+        assert_eq!(estimation.function_calls.len(), 1);
+        assert_eq!(estimation.function_calls[0].num_samples, 0);
+        assert_eq!(estimation.recommended_attached_gas, 1_000_000);
+        assert!(estimation.max_cost >= 1_000_000 * estimation.max_gas_price + 5);
+
+        let too_many_blocks = client
+            .estimate_fee(RpcEstimateFeeRequest {
+                signer_id: "test1".to_string(),
+                receiver_id: "test2".to_string(),
+                actions: vec![],
+                num_blocks: Some(1_000),
+            })
+            .await;
+        assert!(too_many_blocks.is_err());
+    });
+}
+
+/// Retrieve the validator history via json rpc.
+#[test]
+fn test_validators_history() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let history = client
+            .EXPERIMENTAL_validators_history(RpcValidatorsHistoryRequest {
+                block_id: None,
+                account_id: Some("test1".to_string()),
+                num_epochs: None,
+            })
+            .await
+            .unwrap();
+        // No epoch is finished at genesis.
+        assert!(history.is_empty());
+
+        let too_many_epochs = client
+            .EXPERIMENTAL_validators_history(RpcValidatorsHistoryRequest {
+                block_id: Some(BlockId::Height(0)),
+                account_id: None,
+                num_epochs: Some(1_000),
+            })
+            .await;
+        assert!(too_many_epochs.is_err());
+    });
+}
+
 #[test]
 fn test_invalid_methods() {
     test_with_client!(test_utils::NodeType::NonValidator, client, async move {
@@ -587,6 +656,8 @@ fn test_call_function_non_existing_account_method_name() {
                             .to_string(),
                     args: vec![].into(),
                     account_id: "\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}".to_string(),
+                    state_overrides: None,
+                    caller: None,
                 },
             })
             .await
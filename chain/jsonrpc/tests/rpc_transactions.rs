@@ -9,10 +9,13 @@ use near_jsonrpc::client::new_client;
 use near_logger_utils::{init_integration_logger, init_test_logger};
 use near_network::test_utils::WaitOrTimeout;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::rpc::RpcAccountBalanceChangesRequest;
 use near_primitives::serialize::{to_base, to_base64};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::BlockReference;
-use near_primitives::views::FinalExecutionStatus;
+use near_primitives::views::{
+    AccountBalanceChangeCauseView, FinalExecutionStatus, ReceiptDelayReasonView,
+};
 
 #[macro_use]
 pub mod test_utils;
@@ -97,6 +100,98 @@ fn test_send_tx_commit() {
     });
 }
 
+/// Test that the verbose `tx` explains the execution of every receipt.
+#[test]
+fn test_tx_status_with_trace() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1", KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".to_string(),
+            "test2".to_string(),
+            &signer,
+            100,
+            block_hash,
+        );
+        let bytes = tx.try_to_vec().unwrap();
+        client.broadcast_tx_commit(to_base64(&bytes)).await.unwrap();
+        let result = client.tx(to_base(&tx.get_hash()), "test1".to_string()).await.unwrap();
+        let traced =
+            client.tx_with_trace(to_base(&tx.get_hash()), "test1".to_string()).await.unwrap();
+        assert_eq!(traced.final_outcome, result);
+        assert!(traced.receipts.is_none());
+        assert_eq!(traced.receipts_trace.len(), result.receipts_outcome.len());
+        for (trace, outcome) in traced.receipts_trace.iter().zip(result.receipts_outcome.iter()) {
+            assert_eq!(trace.receipt_id, outcome.id);
+            assert_eq!(trace.executed_in_block_hash, outcome.block_hash);
+            assert!(trace.executed_at_block_height >= trace.created_at_block_height);
+            let explained_blocks: u64 = trace
+                .delay_reasons
+                .iter()
+                .map(|reason| match reason {
+                    ReceiptDelayReasonView::WaitingForInputData { blocks }
+                    | ReceiptDelayReasonView::SkippedBlocks { blocks }
+                    | ReceiptDelayReasonView::MissingChunks { blocks }
+                    | ReceiptDelayReasonView::DelayedReceiptsQueue { blocks }
+                    | ReceiptDelayReasonView::Unknown { blocks } => *blocks,
+                })
+                .sum();
+            assert_eq!(explained_blocks, trace.delay_blocks);
+        }
+        let transfer_trace = &traced.receipts_trace[0];
+        assert!(transfer_trace.input_data.is_empty());
+        assert!(!transfer_trace.state_changes.is_empty());
+    });
+}
+
+/// Test that a transfer shows up in the balance changes of both the sender and the receiver.
+#[test]
+fn test_account_balance_changes() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1", KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".to_string(),
+            "test2".to_string(),
+            &signer,
+            100,
+            block_hash,
+        );
+        let bytes = tx.try_to_vec().unwrap();
+        let result = client.broadcast_tx_commit(to_base64(&bytes)).await.unwrap();
+        let to_block_height = client.block(BlockReference::latest()).await.unwrap().header.height;
+
+        let sender_changes = client
+            .account_balance_changes(RpcAccountBalanceChangesRequest {
+                account_id: "test1".to_string(),
+                from_block_height: 0,
+                to_block_height,
+            })
+            .await
+            .unwrap();
+        assert!(sender_changes.changes.iter().any(|change| {
+            change.cause == AccountBalanceChangeCauseView::Transaction { tx_hash: tx.get_hash() }
+                && change.amount_before - change.amount_after >= 100
+        }));
+
+        let receiver_changes = client
+            .account_balance_changes(RpcAccountBalanceChangesRequest {
+                account_id: "test2".to_string(),
+                from_block_height: 0,
+                to_block_height,
+            })
+            .await
+            .unwrap();
+        let receipt_hash = result.transaction_outcome.outcome.receipt_ids[0];
+        assert!(receiver_changes.changes.iter().any(|change| {
+            change.cause == AccountBalanceChangeCauseView::Receipt { receipt_hash }
+                && change.amount_after - change.amount_before == 100
+        }));
+    });
+}
+
 /// Test that expired transaction should be rejected
 #[test]
 fn test_expired_tx() {
@@ -393,6 +393,12 @@ impl Peer {
             PeerMessage::BlockHeadersRequest(hashes) => {
                 NetworkViewClientMessages::BlockHeadersRequest(hashes)
             }
+            PeerMessage::EpochSyncRequest(epoch_id) => {
+                NetworkViewClientMessages::EpochSyncRequest { epoch_id }
+            }
+            PeerMessage::EpochSyncFinalizationRequest(epoch_id) => {
+                NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id }
+            }
             peer_message => {
                 error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", peer_message);
                 return;
@@ -440,6 +446,12 @@ impl Peer {
                     Ok(NetworkViewClientResponses::BlockHeaders(headers)) => {
                         act.send_message(PeerMessage::BlockHeaders(headers))
                     }
+                    Ok(NetworkViewClientResponses::EpochSyncResponse(response)) => {
+                        act.send_message(PeerMessage::EpochSyncResponse(response))
+                    }
+                    Ok(NetworkViewClientResponses::EpochSyncFinalizationResponse(response)) => {
+                        act.send_message(PeerMessage::EpochSyncFinalizationResponse(response))
+                    }
                     Err(err) => {
                         error!(
                             target: "network",
@@ -480,6 +492,12 @@ impl Peer {
             PeerMessage::BlockHeaders(headers) => {
                 NetworkClientMessages::BlockHeaders(headers, peer_id)
             }
+            PeerMessage::EpochSyncResponse(response) => {
+                NetworkClientMessages::EpochSyncResponse(peer_id, response)
+            }
+            PeerMessage::EpochSyncFinalizationResponse(response) => {
+                NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response)
+            }
             // All Routed messages received at this point are for us.
             PeerMessage::Routed(routed_message) => {
                 let msg_hash = routed_message.hash();
@@ -547,7 +565,9 @@ impl Peer {
             | PeerMessage::RequestUpdateNonce(_)
             | PeerMessage::ResponseUpdateNonce(_)
             | PeerMessage::BlockRequest(_)
-            | PeerMessage::BlockHeadersRequest(_) => {
+            | PeerMessage::BlockHeadersRequest(_)
+            | PeerMessage::EpochSyncRequest(_)
+            | PeerMessage::EpochSyncFinalizationRequest(_) => {
                 error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
                 return;
             }
@@ -26,7 +26,10 @@ use near_primitives::sharding::{
     ChunkHash, PartialEncodedChunk, PartialEncodedChunkPart, PartialEncodedChunkV1,
     PartialEncodedChunkWithArcReceipts, ReceiptProof, ShardChunkHeader,
 };
-use near_primitives::syncing::{ShardStateSyncResponse, ShardStateSyncResponseV1};
+use near_primitives::syncing::{
+    EpochSyncFinalizationResponse, EpochSyncResponse, ShardStateSyncResponse,
+    ShardStateSyncResponseV1,
+};
 use near_primitives::transaction::{ExecutionOutcomeWithIdAndProof, SignedTransaction};
 use near_primitives::types::{AccountId, BlockHeight, BlockReference, EpochId, ShardId};
 use near_primitives::utils::{from_timestamp, to_timestamp};
@@ -759,6 +762,11 @@ pub enum PeerMessage {
     Disconnect,
     Challenge(Challenge),
     HandshakeV2(HandshakeV2),
+
+    EpochSyncRequest(EpochId),
+    EpochSyncResponse(Box<EpochSyncResponse>),
+    EpochSyncFinalizationRequest(EpochId),
+    EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
 }
 
 impl fmt::Display for PeerMessage {
@@ -782,7 +790,9 @@ impl PeerMessage {
             PeerMessage::Block(_)
             | PeerMessage::BlockHeaders(_)
             | PeerMessage::Transaction(_)
-            | PeerMessage::Challenge(_) => true,
+            | PeerMessage::Challenge(_)
+            | PeerMessage::EpochSyncResponse(_)
+            | PeerMessage::EpochSyncFinalizationResponse(_) => true,
             PeerMessage::Routed(r) => match r.body {
                 RoutedMessageBody::BlockApproval(_)
                 | RoutedMessageBody::ForwardTx(_)
@@ -815,6 +825,8 @@ impl PeerMessage {
             },
             PeerMessage::BlockHeadersRequest(_) => true,
             PeerMessage::BlockRequest(_) => true,
+            PeerMessage::EpochSyncRequest(_) => true,
+            PeerMessage::EpochSyncFinalizationRequest(_) => true,
             _ => false,
         }
     }
@@ -1166,6 +1178,16 @@ pub enum NetworkRequests {
         hashes: Vec<CryptoHash>,
         peer_id: PeerId,
     },
+    /// Request the light client block proving the block producers of the epoch after the given one.
+    EpochSyncRequest {
+        epoch_id: EpochId,
+        peer_id: PeerId,
+    },
+    /// Request the headers and epoch infos needed to finish epoch sync at the given epoch.
+    EpochSyncFinalizationRequest {
+        epoch_id: EpochId,
+        peer_id: PeerId,
+    },
     /// Request state header for given shard at given state root.
     StateRequestHeader {
         shard_id: ShardId,
@@ -1404,6 +1426,10 @@ pub enum NetworkClientMessages {
     Block(Block, PeerId, bool),
     /// Received list of headers for syncing.
     BlockHeaders(Vec<BlockHeader>, PeerId),
+    /// Epoch sync response.
+    EpochSyncResponse(PeerId, Box<EpochSyncResponse>),
+    /// Epoch sync finalization response.
+    EpochSyncFinalizationResponse(PeerId, Box<EpochSyncFinalizationResponse>),
     /// Block approval.
     BlockApproval(Approval, PeerId),
     /// State response.
@@ -1484,6 +1510,10 @@ pub enum NetworkViewClientMessages {
     BlockRequest(CryptoHash),
     /// Request headers.
     BlockHeadersRequest(Vec<CryptoHash>),
+    /// Epoch sync request.
+    EpochSyncRequest { epoch_id: EpochId },
+    /// Epoch sync finalization request.
+    EpochSyncFinalizationRequest { epoch_id: EpochId },
     /// State request header.
     StateRequestHeader { shard_id: ShardId, sync_hash: CryptoHash },
     /// State request part.
@@ -1507,6 +1537,10 @@ pub enum NetworkViewClientResponses {
     Block(Box<Block>),
     /// Headers response.
     BlockHeaders(Vec<BlockHeader>),
+    /// Epoch sync response.
+    EpochSyncResponse(Box<EpochSyncResponse>),
+    /// Epoch sync finalization response.
+    EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
     /// Chain information.
     ChainInfo {
         genesis_id: GenesisId,
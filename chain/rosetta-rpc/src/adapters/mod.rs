@@ -182,6 +182,15 @@ fn convert_block_changes_to_transactions(
             StateChangeCauseView::UpdatedDelayedReceipts => {
                 format!("block-delayed-receipts:{}", block_hash)
             }
+            StateChangeCauseView::ReleasedScheduledReceipts => {
+                format!("block-scheduled-receipts:{}", block_hash)
+            }
+            StateChangeCauseView::UpdatedBufferedReceipts => {
+                format!("block-buffered-receipts:{}", block_hash)
+            }
+            StateChangeCauseView::DeletedAccountsCleanup => {
+                format!("block-deleted-accounts-cleanup:{}", block_hash)
+            }
             StateChangeCauseView::NotWritableToDisk => {
                 return Err(crate::errors::ErrorKind::InternalInvariantError(
                     "State Change 'NotWritableToDisk' should never be observed".to_string(),
@@ -611,6 +620,79 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                     operations.push(deploy_contract_operation);
                 }
+
+                // Upgrade is represented as a deploy followed by a call of the migration method.
+                near_primitives::transaction::Action::UpgradeContract(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::DeployContractOperation {
+                            account: receiver_account_identifier.clone(),
+                            code: action.code,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+
+                    let initiate_function_call_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateFunctionCallOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_function_call_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::FunctionCallOperation {
+                            account: receiver_account_identifier.clone(),
+                            method_name: action.method_name,
+                            args: action.args,
+                            attached_gas: action.gas,
+                            attached_amount: 0,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_function_call_operation_id],
+                        ),
+                    );
+                }
+
+                // Delegated actions are executed by a separate receipt on behalf of their sender,
+                // so they don't affect the balances of the transaction accounts directly.
+                near_primitives::transaction::Action::Delegate(_) => {}
+
+                // The ABI doesn't affect balances, so it's represented as a plain deploy.
+                near_primitives::transaction::Action::DeployContractWithAbi(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::DeployContractOperation {
+                            account: receiver_account_identifier.clone(),
+                            code: action.code,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+                }
             }
         }
         operations
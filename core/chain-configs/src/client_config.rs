@@ -1,5 +1,6 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -7,6 +8,15 @@ use serde::{Deserialize, Serialize};
 use near_primitives::types::{AccountId, BlockHeightDelta, NumBlocks, NumSeats, ShardId};
 use near_primitives::version::Version;
 
+/// Location of the state parts dumped for the epoch sync hashes, see `get_state_part_file_path`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum StatePartsSource {
+    /// Directory on the local filesystem.
+    Filesystem { root_dir: PathBuf },
+    /// Plain HTTP file server with the same layout as the directory.
+    Http { url: String },
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// Version of the binary.
@@ -77,6 +87,17 @@ pub struct ClientConfig {
     pub archive: bool,
     /// Number of threads for ViewClientActor pool.
     pub view_client_threads: usize,
+    /// If set, state sync fetches the state parts from this location instead of the peers.
+    pub state_parts_source: Option<StatePartsSource>,
+    /// Bootstrap a new node by verifying one light client block per epoch instead of
+    /// downloading all the headers from genesis.
+    pub epoch_sync_enabled: bool,
+    /// Run as a fisherman: re-execute the chunks of the tracked shards and send challenges for
+    /// invalid ones, without producing blocks or chunks.
+    pub fisherman: bool,
+    /// If set, refuse to sign blocks, chunks and approvals that conflict with the ones recorded
+    /// as signed by the validator key in this file.
+    pub slashing_protection_file: Option<PathBuf>,
 }
 
 impl ClientConfig {
@@ -128,6 +149,10 @@ impl ClientConfig {
             tracked_shards: vec![],
             archive,
             view_client_threads: 1,
+            state_parts_source: None,
+            epoch_sync_enabled: false,
+            fisherman: false,
+            slashing_protection_file: None,
         }
     }
 }
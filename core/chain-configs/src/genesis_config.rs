@@ -15,10 +15,11 @@ use smart_default::SmartDefault;
 
 use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::{u128_dec_format, u128_dec_format_compatible};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
     AccountId, AccountInfo, Balance, BlockHeight, BlockHeightDelta, EpochHeight, Gas, NumBlocks,
-    NumSeats,
+    NumSeats, NumShards,
 };
 use near_primitives::version::ProtocolVersion;
 use near_runtime_configs::RuntimeConfig;
@@ -118,6 +119,11 @@ pub struct GenesisConfig {
     #[serde(default = "default_minimum_stake_divisor")]
     #[default(10)]
     pub minimum_stake_divisor: u64,
+    /// Shard layout the shards of the genesis layout are split into at the first epoch with
+    /// `ProtocolFeature::Resharding`. The genesis layout assigns accounts by hash, so it can
+    /// only be split if it has a single shard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resharding_shard_layout: Option<ShardLayout>,
 }
 
 /// Records in storage at genesis (get split into shards at genesis creation).
@@ -175,6 +181,11 @@ impl GenesisConfig {
         )
         .expect("Failed to create / write a genesis config file.");
     }
+
+    /// Shard layout at genesis, the number of shards is defined by the block producer seats.
+    pub fn shard_layout(&self) -> ShardLayout {
+        ShardLayout::v0(self.num_block_producer_seats_per_shard.len() as NumShards, 0)
+    }
 }
 
 impl GenesisRecords {
@@ -1,5 +1,5 @@
 mod client_config;
 mod genesis_config;
 
-pub use client_config::ClientConfig;
+pub use client_config::{ClientConfig, StatePartsSource};
 pub use genesis_config::{Genesis, GenesisConfig, GenesisRecords};
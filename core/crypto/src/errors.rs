@@ -16,3 +16,19 @@ pub enum ParseKeyError {
     #[error("invalid key data: {0}")]
     InvalidData(String),
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyFileError {
+    #[error("failed to read or write the key file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse the key file: {0}")]
+    InvalidFormat(#[from] serde_json::Error),
+    #[error("key file is encrypted and requires a passphrase")]
+    PassphraseRequired,
+    #[error("wrong passphrase or corrupted key file")]
+    WrongPassphrase,
+    #[error("failed to derive the encryption key: {0}")]
+    KeyDerivation(String),
+    #[error("invalid encrypted key file: {0}")]
+    InvalidData(String),
+}
@@ -3,10 +3,16 @@ use std::io::{Read, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
 use serde::{Deserialize, Serialize};
 
+use crate::errors::KeyFileError;
 use crate::{PublicKey, SecretKey};
 
+const SALT_LEN: usize = 16;
+const ENCRYPTION_KEY_LEN: u32 = 32;
+
 #[derive(Serialize, Deserialize)]
 pub struct KeyFile {
     pub account_id: String,
@@ -14,23 +20,265 @@ pub struct KeyFile {
     pub secret_key: SecretKey,
 }
 
+/// Parameters of the Argon2id key derivation, stored in the key file so that they can be changed
+/// without breaking the files written before.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KdfParams {
+    /// Memory usage in KiB.
+    pub mem_cost: u32,
+    /// Number of passes over the memory.
+    pub time_cost: u32,
+    pub lanes: u32,
+    pub salt: String,
+}
+
+impl KdfParams {
+    fn new(mem_cost: u32, time_cost: u32) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        KdfParams { mem_cost, time_cost, lanes: 1, salt: bs58::encode(salt).into_string() }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> Result<LessSafeKey, KeyFileError> {
+        let salt = decode_bs58(&self.salt)?;
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            version: argon2::Version::Version13,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            thread_mode: argon2::ThreadMode::Sequential,
+            secret: &[],
+            ad: &[],
+            hash_length: ENCRYPTION_KEY_LEN,
+        };
+        let key = argon2::hash_raw(passphrase.as_bytes(), &salt, &config)
+            .map_err(|err| KeyFileError::KeyDerivation(err.to_string()))?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| KeyFileError::KeyDerivation("invalid key length".to_string()))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+impl Default for KdfParams {
+    /// 64 MiB and 3 passes take about a second, which is only paid once when the node starts.
+    fn default() -> Self {
+        KdfParams::new(64 * 1024, 3)
+    }
+}
+
+/// Key file with the secret key encrypted by ChaCha20-Poly1305 under a key derived from a
+/// passphrase. The account id and the public key stay readable and are authenticated together
+/// with the secret key, so they can't be swapped without the passphrase.
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedKeyFile {
+    pub account_id: String,
+    pub public_key: PublicKey,
+    pub kdf: KdfParams,
+    pub nonce: String,
+    pub encrypted_secret_key: String,
+}
+
+impl EncryptedKeyFile {
+    pub fn encrypt(key_file: &KeyFile, passphrase: &str) -> Result<Self, KeyFileError> {
+        Self::encrypt_with_params(key_file, passphrase, KdfParams::default())
+    }
+
+    fn encrypt_with_params(
+        key_file: &KeyFile,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, KeyFileError> {
+        let key = kdf.derive_key(passphrase)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let mut in_out = key_file.secret_key.to_string().into_bytes();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(associated_data(&key_file.account_id, &key_file.public_key)),
+            &mut in_out,
+        )
+        .map_err(|_| KeyFileError::InvalidData("failed to encrypt the secret key".to_string()))?;
+        Ok(EncryptedKeyFile {
+            account_id: key_file.account_id.clone(),
+            public_key: key_file.public_key.clone(),
+            kdf,
+            nonce: bs58::encode(nonce).into_string(),
+            encrypted_secret_key: bs58::encode(in_out).into_string(),
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<KeyFile, KeyFileError> {
+        let key = self.kdf.derive_key(passphrase)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        let nonce_bytes = decode_bs58(&self.nonce)?;
+        if nonce_bytes.len() != NONCE_LEN {
+            return Err(KeyFileError::InvalidData(format!(
+                "invalid nonce length: {}",
+                nonce_bytes.len()
+            )));
+        }
+        nonce.copy_from_slice(&nonce_bytes);
+        let mut in_out = decode_bs58(&self.encrypted_secret_key)?;
+        let secret_key = key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(associated_data(&self.account_id, &self.public_key)),
+                &mut in_out,
+            )
+            .map_err(|_| KeyFileError::WrongPassphrase)?;
+        let secret_key: SecretKey = std::str::from_utf8(secret_key)
+            .map_err(|err| KeyFileError::InvalidData(err.to_string()))?
+            .parse()
+            .map_err(|err: crate::ParseKeyError| KeyFileError::InvalidData(err.to_string()))?;
+        if secret_key.public_key() != self.public_key {
+            return Err(KeyFileError::InvalidData(
+                "secret key doesn't match the public key".to_string(),
+            ));
+        }
+        Ok(KeyFile {
+            account_id: self.account_id.clone(),
+            public_key: self.public_key.clone(),
+            secret_key,
+        })
+    }
+}
+
+/// Key files on disk are either plain or encrypted, told apart by their fields.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyFileContent {
+    Plain(KeyFile),
+    Encrypted(EncryptedKeyFile),
+}
+
+fn associated_data(account_id: &str, public_key: &PublicKey) -> Vec<u8> {
+    format!("{}:{}", account_id, public_key).into_bytes()
+}
+
+fn decode_bs58(data: &str) -> Result<Vec<u8>, KeyFileError> {
+    bs58::decode(data).into_vec().map_err(|err| KeyFileError::InvalidData(err.to_string()))
+}
+
+fn write_file(path: &Path, content: &str) -> Result<(), KeyFileError> {
+    let mut file = File::create(path)?;
+    let mut perm = file.metadata()?.permissions();
+    perm.set_mode(u32::from(libc::S_IWUSR | libc::S_IRUSR));
+    file.set_permissions(perm)?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<KeyFileContent, KeyFileError> {
+    let mut file = File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
 impl KeyFile {
     pub fn write_to_file(&self, path: &Path) {
-        let mut file = File::create(path).expect("Failed to create / write a key file.");
-        let mut perm =
-            file.metadata().expect("Failed to retrieve key file metadata.").permissions();
-        perm.set_mode(u32::from(libc::S_IWUSR | libc::S_IRUSR));
-        file.set_permissions(perm).expect("Failed to set permissions for a key file.");
         let str = serde_json::to_string_pretty(self).expect("Error serializing the key file.");
-        if let Err(err) = file.write_all(str.as_bytes()) {
+        if let Err(err) = write_file(path, &str) {
             panic!("Failed to write a key file {}", err);
         }
     }
 
+    /// Writes the key file with the secret key encrypted with the given passphrase.
+    pub fn write_encrypted_to_file(
+        &self,
+        path: &Path,
+        passphrase: &str,
+    ) -> Result<(), KeyFileError> {
+        let encrypted = EncryptedKeyFile::encrypt(self, passphrase)?;
+        write_file(path, &serde_json::to_string_pretty(&encrypted)?)
+    }
+
     pub fn from_file(path: &Path) -> Self {
-        let mut file = File::open(path).expect("Could not open key file.");
-        let mut content = String::new();
-        file.read_to_string(&mut content).expect("Could not read from key file.");
-        serde_json::from_str(&content).expect("Failed to deserialize KeyFile")
+        Self::from_file_with_passphrase(path, None)
+    }
+
+    /// Reads a plain or an encrypted key file. The passphrase is only used for encrypted ones.
+    pub fn from_file_with_passphrase(path: &Path, passphrase: Option<&str>) -> Self {
+        Self::try_from_file(path, passphrase)
+            .unwrap_or_else(|err| panic!("Failed to read key file {}: {}", path.display(), err))
+    }
+
+    pub fn try_from_file(path: &Path, passphrase: Option<&str>) -> Result<Self, KeyFileError> {
+        match read_file(path)? {
+            KeyFileContent::Plain(key_file) => Ok(key_file),
+            KeyFileContent::Encrypted(encrypted) => {
+                encrypted.decrypt(passphrase.ok_or(KeyFileError::PassphraseRequired)?)
+            }
+        }
+    }
+
+    /// Whether the key file at `path` is encrypted and needs a passphrase to be read.
+    pub fn is_encrypted(path: &Path) -> Result<bool, KeyFileError> {
+        Ok(matches!(read_file(path)?, KeyFileContent::Encrypted(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{KeyType, SecretKey};
+
+    use super::*;
+
+    fn test_key_file() -> KeyFile {
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, "test");
+        KeyFile { account_id: "test".to_string(), public_key: secret_key.public_key(), secret_key }
+    }
+
+    #[test]
+    fn test_encrypted_key_file_roundtrip() {
+        let key_file = test_key_file();
+        let encrypted =
+            EncryptedKeyFile::encrypt_with_params(&key_file, "passphrase", KdfParams::new(8, 1))
+                .unwrap();
+        let str = serde_json::to_string(&encrypted).unwrap();
+        assert!(!str.contains(&key_file.secret_key.to_string()));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("key.json");
+        write_file(&path, &str).unwrap();
+        assert!(KeyFile::is_encrypted(&path).unwrap());
+        match KeyFile::try_from_file(&path, None) {
+            Err(KeyFileError::PassphraseRequired) => {}
+            _ => panic!("expected a passphrase to be required"),
+        }
+        match KeyFile::try_from_file(&path, Some("wrong")) {
+            Err(KeyFileError::WrongPassphrase) => {}
+            _ => panic!("expected a wrong passphrase"),
+        }
+        let decrypted = KeyFile::try_from_file(&path, Some("passphrase")).unwrap();
+        assert_eq!(decrypted.account_id, key_file.account_id);
+        assert_eq!(decrypted.secret_key, key_file.secret_key);
+    }
+
+    #[test]
+    fn test_plain_key_file_ignores_passphrase() {
+        let key_file = test_key_file();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("key.json");
+        key_file.write_to_file(&path);
+        assert!(!KeyFile::is_encrypted(&path).unwrap());
+        let read = KeyFile::from_file_with_passphrase(&path, Some("passphrase"));
+        assert_eq!(read.secret_key, key_file.secret_key);
+    }
+
+    #[test]
+    fn test_swapped_account_id_is_rejected() {
+        let key_file = test_key_file();
+        let mut encrypted =
+            EncryptedKeyFile::encrypt_with_params(&key_file, "passphrase", KdfParams::new(8, 1))
+                .unwrap();
+        encrypted.account_id = "other".to_string();
+        match encrypted.decrypt("passphrase") {
+            Err(KeyFileError::WrongPassphrase) => {}
+            _ => panic!("expected the changed account id to fail authentication"),
+        }
     }
 }
@@ -1,5 +1,5 @@
-pub use errors::{ParseKeyError, ParseSignatureError, TryFromSliceError};
-pub use key_file::KeyFile;
+pub use errors::{KeyFileError, ParseKeyError, ParseSignatureError, TryFromSliceError};
+pub use key_file::{EncryptedKeyFile, KeyFile};
 pub use signature::{
     ED25519PublicKey, KeyType, PublicKey, Secp256K1PublicKey, SecretKey, Signature,
 };
@@ -60,6 +60,10 @@ impl InMemorySigner {
     pub fn from_file(path: &Path) -> Self {
         KeyFile::from_file(path).into()
     }
+
+    pub fn from_file_with_passphrase(path: &Path, passphrase: Option<&str>) -> Self {
+        KeyFile::from_file_with_passphrase(path, passphrase).into()
+    }
 }
 
 impl Signer for InMemorySigner {
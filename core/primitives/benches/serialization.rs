@@ -63,6 +63,7 @@ fn create_block() -> Block {
         &signer,
         CryptoHash::default(),
         CryptoHash::default(),
+        None,
     )
 }
 
@@ -1,9 +1,11 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
+use near_crypto::PublicKey;
+
 use crate::hash::CryptoHash;
 use crate::serialize::{option_u128_dec_format, u128_dec_format_compatible};
-use crate::types::{AccountId, Balance, Nonce, StorageUsage};
+use crate::types::{AccountId, Balance, BlockHeight, Nonce, StorageUsage};
 
 /// Per account information stored in the state.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -55,6 +57,10 @@ pub enum AccessKeyPermission {
     /// Grants full access to the account.
     /// NOTE: It's used to replace account-level public keys.
     FullAccess,
+
+    /// Grants permission to make function calls to a set of receivers, optionally with an
+    /// attached deposit and until a given block height.
+    ExtendedFunctionCall(ExtendedFunctionCallPermission),
 }
 
 /// Grants limited permission to make transactions with FunctionCallActions
@@ -83,6 +89,54 @@ pub struct FunctionCallPermission {
     pub method_names: Vec<String>,
 }
 
+/// Grants limited permission to make transactions with FunctionCallActions, similar to
+/// `FunctionCallPermission`, but allows several receivers, attached deposits and expiration.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug,
+)]
+pub struct ExtendedFunctionCallPermission {
+    /// Allowance is a balance limit to use by this access key to pay for function call gas and
+    /// transaction fees. Works the same way as `FunctionCallPermission::allowance`.
+    /// `None` means unlimited allowance.
+    #[serde(with = "option_u128_dec_format")]
+    pub allowance: Option<Balance>,
+
+    /// The access key only allows transactions with one of the given receivers' account ids.
+    pub receiver_ids: Vec<AccountId>,
+
+    /// A list of method names that can be used. The access key only allows transactions with the
+    /// function call of one of the given method names.
+    /// Empty list means any method name can be used.
+    pub method_names: Vec<String>,
+
+    /// The maximum deposit that can be attached to a single function call.
+    /// `None` means no deposit can be attached.
+    #[serde(with = "option_u128_dec_format")]
+    pub max_deposit_per_call: Option<Balance>,
+
+    /// Total balance that can be attached as deposits with this access key. When this access key
+    /// is used, the deposit allowance is decreased by the attached deposits.
+    /// `None` means the total is only limited by `max_deposit_per_call`.
+    #[serde(with = "option_u128_dec_format")]
+    pub deposit_allowance: Option<Balance>,
+
+    /// The block height after which the access key can no longer be used.
+    /// `None` means the access key never expires.
+    pub expires_at: Option<BlockHeight>,
+}
+
+/// A deposit charged from the deposit allowance of an extended function call access key, stored
+/// in the state of the signer account until the deposit is refunded.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ChargedDeposit {
+    /// The ID of the receipt that will refund the deposit if the function call fails.
+    pub refund_receipt_id: CryptoHash,
+    /// The access key that was charged for the deposit.
+    pub public_key: PublicKey,
+    /// The charged deposit.
+    pub amount: Balance,
+}
+
 #[cfg(test)]
 mod tests {
     use borsh::BorshSerialize;
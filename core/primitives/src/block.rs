@@ -95,6 +95,7 @@ pub fn genesis_chunks(
                 0,
                 initial_gas_limit,
                 0,
+                0,
                 CryptoHash::default(),
                 vec![],
                 vec![],
@@ -125,7 +126,7 @@ impl Block {
                 .into_iter()
                 .map(|chunk| match chunk {
                     ShardChunkHeader::V1(header) => header,
-                    ShardChunkHeader::V2(_) => panic!(
+                    ShardChunkHeader::V2(_) | ShardChunkHeader::V3(_) => panic!(
                         "Attempted to include VersionedShardChunkHeaderV2 in old protocol version"
                     ),
                 })
@@ -202,6 +203,7 @@ impl Block {
         signer: &dyn ValidatorSigner,
         next_bp_hash: CryptoHash,
         block_merkle_root: CryptoHash,
+        epoch_sync_data_hash: Option<CryptoHash>,
     ) -> Self {
         // Collect aggregate of validators and gas usage/limits from chunks.
         let mut validator_proposals = vec![];
@@ -273,6 +275,7 @@ impl Block {
             approvals,
             next_bp_hash,
             block_merkle_root,
+            epoch_sync_data_hash,
         );
 
         Self::block_from_protocol_version(
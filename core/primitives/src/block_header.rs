@@ -1,6 +1,6 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use near_crypto::{KeyType, PublicKey, Signature};
 
@@ -11,8 +11,20 @@ use crate::network::PeerId;
 use crate::types::{AccountId, Balance, BlockHeight, EpochId, MerkleHash, ValidatorStake};
 use crate::utils::{from_timestamp, to_timestamp};
 use crate::validator_signer::ValidatorSigner;
+#[cfg(feature = "protocol_feature_epoch_sync")]
+use crate::version::{ProtocolFeature, PROTOCOL_FEATURES_TO_VERSION_MAPPING};
 use crate::version::{ProtocolVersion, PROTOCOL_VERSION};
 
+/// First protocol version with `BlockHeaderV3`, the one of `ProtocolFeature::EpochSyncDataHash`.
+pub fn epoch_sync_data_hash_protocol_version() -> ProtocolVersion {
+    #[cfg(feature = "protocol_feature_epoch_sync")]
+    let version =
+        PROTOCOL_FEATURES_TO_VERSION_MAPPING.get(&ProtocolFeature::EpochSyncDataHash).copied();
+    #[cfg(not(feature = "protocol_feature_epoch_sync"))]
+    let version = None;
+    version.unwrap_or(ProtocolVersion::MAX)
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
 pub struct BlockHeaderInnerLite {
     /// Height of this block.
@@ -107,7 +119,9 @@ pub struct BlockHeaderInnerRestV2 {
 }
 
 /// The part of the block approval that is different for endorsements and skips
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash,
+)]
 pub enum ApprovalInner {
     Endorsement(CryptoHash),
     Skip(BlockHeight),
@@ -230,12 +244,51 @@ impl BlockHeaderV2 {
     }
 }
 
+/// V2 -> V3: Add `epoch_sync_data_hash`, which is hashed together with `inner_rest`
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+#[borsh_init(init)]
+pub struct BlockHeaderV3 {
+    pub prev_hash: CryptoHash,
+
+    /// Inner part of the block header that gets hashed, split into two parts, one that is sent
+    ///    to light clients, and the rest
+    pub inner_lite: BlockHeaderInnerLite,
+    pub inner_rest: BlockHeaderInnerRestV2,
+    /// Hash of the epoch manager records needed to process the blocks from this one on, see
+    /// `EpochSyncData`. Only set in the first block of an epoch.
+    pub epoch_sync_data_hash: Option<CryptoHash>,
+
+    /// Signature of the block producer.
+    pub signature: Signature,
+
+    /// Cached value of hash for this block.
+    #[borsh_skip]
+    pub hash: CryptoHash,
+}
+
+impl BlockHeaderV3 {
+    pub fn init(&mut self) {
+        self.hash = BlockHeader::compute_hash(
+            self.prev_hash,
+            &self.inner_lite.try_to_vec().expect("Failed to serialize"),
+            &self.inner_rest_bytes(),
+        );
+    }
+
+    pub fn inner_rest_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.inner_rest.try_to_vec().expect("Failed to serialize");
+        self.epoch_sync_data_hash.serialize(&mut bytes).expect("Failed to serialize");
+        bytes
+    }
+}
+
 /// Versioned BlockHeader data structure.
 /// For each next version, document what are the changes between versions.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
 pub enum BlockHeader {
     BlockHeaderV1(Box<BlockHeaderV1>),
     BlockHeaderV2(Box<BlockHeaderV2>),
+    BlockHeaderV3(Box<BlockHeaderV3>),
 }
 
 impl BlockHeader {
@@ -276,6 +329,7 @@ impl BlockHeader {
         approvals: Vec<Option<Signature>>,
         next_bp_hash: CryptoHash,
         block_merkle_root: CryptoHash,
+        epoch_sync_data_hash: Option<CryptoHash>,
     ) -> Self {
         let inner_lite = BlockHeaderInnerLite {
             height,
@@ -335,18 +389,37 @@ impl BlockHeader {
                 approvals,
                 latest_protocol_version: PROTOCOL_VERSION,
             };
-            let (hash, signature) = signer.sign_block_header_parts(
-                prev_hash,
-                &inner_lite.try_to_vec().expect("Failed to serialize"),
-                &inner_rest.try_to_vec().expect("Failed to serialize"),
-            );
-            Self::BlockHeaderV2(Box::new(BlockHeaderV2 {
-                prev_hash,
-                inner_lite,
-                inner_rest,
-                signature,
-                hash,
-            }))
+            if protocol_version < epoch_sync_data_hash_protocol_version() {
+                let (hash, signature) = signer.sign_block_header_parts(
+                    prev_hash,
+                    &inner_lite.try_to_vec().expect("Failed to serialize"),
+                    &inner_rest.try_to_vec().expect("Failed to serialize"),
+                );
+                Self::BlockHeaderV2(Box::new(BlockHeaderV2 {
+                    prev_hash,
+                    inner_lite,
+                    inner_rest,
+                    signature,
+                    hash,
+                }))
+            } else {
+                let mut header = BlockHeaderV3 {
+                    prev_hash,
+                    inner_lite,
+                    inner_rest,
+                    epoch_sync_data_hash,
+                    signature: Signature::empty(KeyType::ED25519),
+                    hash: CryptoHash::default(),
+                };
+                let (hash, signature) = signer.sign_block_header_parts(
+                    prev_hash,
+                    &header.inner_lite.try_to_vec().expect("Failed to serialize"),
+                    &header.inner_rest_bytes(),
+                );
+                header.hash = hash;
+                header.signature = signature;
+                Self::BlockHeaderV3(Box::new(header))
+            }
         }
     }
 
@@ -422,18 +495,31 @@ impl BlockHeader {
                 approvals: vec![],
                 latest_protocol_version: genesis_protocol_version,
             };
-            let hash = BlockHeader::compute_hash(
-                CryptoHash::default(),
-                &inner_lite.try_to_vec().expect("Failed to serialize"),
-                &inner_rest.try_to_vec().expect("Failed to serialize"),
-            );
-            Self::BlockHeaderV2(Box::new(BlockHeaderV2 {
-                prev_hash: CryptoHash::default(),
-                inner_lite,
-                inner_rest,
-                signature: Signature::empty(KeyType::ED25519),
-                hash,
-            }))
+            if genesis_protocol_version < epoch_sync_data_hash_protocol_version() {
+                let hash = BlockHeader::compute_hash(
+                    CryptoHash::default(),
+                    &inner_lite.try_to_vec().expect("Failed to serialize"),
+                    &inner_rest.try_to_vec().expect("Failed to serialize"),
+                );
+                Self::BlockHeaderV2(Box::new(BlockHeaderV2 {
+                    prev_hash: CryptoHash::default(),
+                    inner_lite,
+                    inner_rest,
+                    signature: Signature::empty(KeyType::ED25519),
+                    hash,
+                }))
+            } else {
+                let mut header = BlockHeaderV3 {
+                    prev_hash: CryptoHash::default(),
+                    inner_lite,
+                    inner_rest,
+                    epoch_sync_data_hash: None,
+                    signature: Signature::empty(KeyType::ED25519),
+                    hash: CryptoHash::default(),
+                };
+                header.init();
+                Self::BlockHeaderV3(Box::new(header))
+            }
         }
     }
 
@@ -442,6 +528,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.hash,
             BlockHeader::BlockHeaderV2(header) => &header.hash,
+            BlockHeader::BlockHeaderV3(header) => &header.hash,
         }
     }
 
@@ -450,6 +537,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.prev_hash,
             BlockHeader::BlockHeaderV2(header) => &header.prev_hash,
+            BlockHeader::BlockHeaderV3(header) => &header.prev_hash,
         }
     }
 
@@ -458,6 +546,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.signature,
             BlockHeader::BlockHeaderV2(header) => &header.signature,
+            BlockHeader::BlockHeaderV3(header) => &header.signature,
         }
     }
 
@@ -466,6 +555,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => header.inner_lite.height,
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.height,
+            BlockHeader::BlockHeaderV3(header) => header.inner_lite.height,
         }
     }
 
@@ -474,6 +564,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.epoch_id,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.epoch_id,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.epoch_id,
         }
     }
 
@@ -482,6 +573,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.next_epoch_id,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_epoch_id,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_epoch_id,
         }
     }
 
@@ -490,6 +582,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.prev_state_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.prev_state_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.prev_state_root,
         }
     }
 
@@ -498,6 +591,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_receipts_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_receipts_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_receipts_root,
         }
     }
 
@@ -506,6 +600,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_headers_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_headers_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_headers_root,
         }
     }
 
@@ -514,6 +609,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_tx_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_tx_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_tx_root,
         }
     }
 
@@ -523,6 +619,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => {
                 header.inner_rest.chunk_mask.iter().map(|&x| u64::from(x)).sum::<u64>()
             }
+            BlockHeader::BlockHeaderV3(header) => {
+                header.inner_rest.chunk_mask.iter().map(|&x| u64::from(x)).sum::<u64>()
+            }
         }
     }
 
@@ -531,6 +630,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.challenges_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_root,
         }
     }
 
@@ -539,6 +639,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.outcome_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.outcome_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.outcome_root,
         }
     }
 
@@ -547,6 +648,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => header.inner_lite.timestamp,
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.timestamp,
+            BlockHeader::BlockHeaderV3(header) => header.inner_lite.timestamp,
         }
     }
 
@@ -555,6 +657,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.validator_proposals,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.validator_proposals,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.validator_proposals,
         }
     }
 
@@ -563,6 +666,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_mask,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_mask,
         }
     }
 
@@ -571,6 +675,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.gas_price,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.gas_price,
+            BlockHeader::BlockHeaderV3(header) => header.inner_rest.gas_price,
         }
     }
 
@@ -579,6 +684,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.total_supply,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.total_supply,
+            BlockHeader::BlockHeaderV3(header) => header.inner_rest.total_supply,
         }
     }
 
@@ -587,6 +693,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.random_value,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.random_value,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.random_value,
         }
     }
 
@@ -595,6 +702,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.last_final_block,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_final_block,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_final_block,
         }
     }
 
@@ -603,6 +711,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.last_ds_final_block,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_ds_final_block,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_ds_final_block,
         }
     }
 
@@ -611,6 +720,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.challenges_result,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_result,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_result,
         }
     }
 
@@ -619,6 +729,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.next_bp_hash,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_bp_hash,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_bp_hash,
         }
     }
 
@@ -627,6 +738,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.block_merkle_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.block_merkle_root,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_lite.block_merkle_root,
         }
     }
 
@@ -635,6 +747,17 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.approvals,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.approvals,
+            BlockHeader::BlockHeaderV3(header) => &header.inner_rest.approvals,
+        }
+    }
+
+    /// Hash of the epoch manager records committed in the first block of an epoch. `None` in
+    /// the other blocks and in the headers before `BlockHeaderV3`.
+    #[inline]
+    pub fn epoch_sync_data_hash(&self) -> Option<CryptoHash> {
+        match self {
+            BlockHeader::BlockHeaderV1(_) | BlockHeader::BlockHeaderV2(_) => None,
+            BlockHeader::BlockHeaderV3(header) => header.epoch_sync_data_hash,
         }
     }
 
@@ -657,7 +780,7 @@ impl BlockHeader {
                 header.inner_rest.chunk_mask.iter().map(|&x| u64::from(x)).sum::<u64>()
                     == header.inner_rest.chunks_included
             }
-            BlockHeader::BlockHeaderV2(_header) => true,
+            BlockHeader::BlockHeaderV2(_) | BlockHeader::BlockHeaderV3(_) => true,
         }
     }
 
@@ -666,6 +789,7 @@ impl BlockHeader {
         match self {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.latest_protocol_version,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.latest_protocol_version,
+            BlockHeader::BlockHeaderV3(header) => header.inner_rest.latest_protocol_version,
         }
     }
 
@@ -677,6 +801,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => {
                 header.inner_lite.try_to_vec().expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV3(header) => {
+                header.inner_lite.try_to_vec().expect("Failed to serialize")
+            }
         }
     }
 
@@ -688,6 +815,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => {
                 header.inner_rest.try_to_vec().expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV3(header) => header.inner_rest_bytes(),
         }
     }
 }
@@ -66,6 +66,10 @@ pub struct ChunkState {
     pub chunk_header: ShardChunkHeader,
     /// Partial state that was affected by transactions of given chunk.
     pub partial_state: PartialState,
+    /// Encoded header of the block before prev block.
+    pub prev_prev_block_header: Vec<u8>,
+    /// Chunk headers of the block before prev block, the congestion prev chunk is applied with.
+    pub prev_prev_block_chunk_headers: Vec<ShardChunkHeader>,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, PartialEq, Eq, Clone, Debug)]
@@ -1,15 +1,17 @@
 use std::collections::{BTreeMap, HashMap};
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use near_crypto::PublicKey;
 use num_rational::Rational;
 use serde::Serialize;
 use smart_default::SmartDefault;
 
 use crate::challenge::SlashedValidator;
-use crate::hash::CryptoHash;
+use crate::hash::{hash, CryptoHash};
+use crate::shard_layout::ShardLayout;
 use crate::types::{
     AccountId, Balance, BlockChunkValidatorStats, BlockHeight, BlockHeightDelta, EpochHeight,
-    EpochId, NumSeats, NumShards, ValidatorId, ValidatorKickoutReason, ValidatorStake,
+    EpochId, NumSeats, ValidatorId, ValidatorKickoutReason, ValidatorStake, ValidatorStats,
 };
 use crate::version::{ProtocolVersion, PROTOCOL_VERSION};
 
@@ -23,8 +25,8 @@ pub const AGGREGATOR_KEY: &[u8] = b"AGGREGATOR";
 pub struct EpochConfig {
     /// Epoch length in block heights.
     pub epoch_length: BlockHeightDelta,
-    /// Number of shards currently.
-    pub num_shards: NumShards,
+    /// Assignment of accounts to shards.
+    pub shard_layout: ShardLayout,
     /// Number of seats for block producers.
     pub num_block_producer_seats: NumSeats,
     /// Number of seats of block producers per each shard.
@@ -49,8 +51,35 @@ pub struct EpochConfig {
     pub protocol_upgrade_num_epochs: EpochHeight,
 }
 
+impl EpochConfig {
+    /// Returns the config for the epochs with the given shard layout, which is split from the
+    /// shard layout of this config. The split shards get the seats of their parent shard.
+    pub fn for_shard_layout(&self, shard_layout: ShardLayout) -> EpochConfig {
+        let seats_of_parent = |seats_per_shard: &Vec<NumSeats>| -> Vec<NumSeats> {
+            shard_layout
+                .shard_ids()
+                .filter_map(|shard_id| {
+                    seats_per_shard
+                        .get(shard_layout.get_parent_shard_id(shard_id) as usize)
+                        .cloned()
+                })
+                .collect()
+        };
+        EpochConfig {
+            num_block_producer_seats_per_shard: seats_of_parent(
+                &self.num_block_producer_seats_per_shard,
+            ),
+            avg_hidden_validator_seats_per_shard: seats_of_parent(
+                &self.avg_hidden_validator_seats_per_shard,
+            ),
+            shard_layout,
+            ..self.clone()
+        }
+    }
+}
+
 /// Information per each block.
-#[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug)]
+#[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct BlockInfo {
     pub height: BlockHeight,
     pub last_finalized_height: BlockHeight,
@@ -106,6 +135,28 @@ impl BlockInfo {
 #[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct ValidatorWeight(ValidatorId, u64);
 
+/// Epoch manager records needed to process the blocks from the first block of an epoch on
+/// without knowing the blocks before it. The hash of the records is committed in the first
+/// block of the epoch, so that epoch sync can verify them.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct EpochSyncData {
+    /// Block infos of the consecutive blocks ending with the last block of the previous epoch.
+    /// They start with the last final block of the last final block of the last block, which
+    /// is at or before the block of the light client block of the previous epoch.
+    pub prev_epoch_block_infos: Vec<BlockInfo>,
+    /// Block info of the first block of the previous epoch.
+    pub prev_epoch_first_block_info: BlockInfo,
+    pub prev_epoch_info: EpochInfo,
+    pub epoch_info: EpochInfo,
+    pub next_epoch_info: EpochInfo,
+}
+
+impl EpochSyncData {
+    pub fn hash(&self) -> CryptoHash {
+        hash(&self.try_to_vec().expect("Failed to serialize"))
+    }
+}
+
 /// Information per epoch.
 #[derive(SmartDefault, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct EpochInfo {
@@ -151,6 +202,37 @@ pub struct EpochSummary {
     pub validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
     /// Protocol version for next epoch.
     pub next_version: ProtocolVersion,
+    /// Blocks and chunks produced and expected per validator of the epoch, including the ones
+    /// that are kicked out.
+    pub all_validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
+}
+
+/// Performance of a validator during a finished epoch.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorEpochStats {
+    pub account_id: AccountId,
+    pub public_key: PublicKey,
+    /// Stake of the validator during the epoch.
+    pub stake: Balance,
+    pub block_stats: ValidatorStats,
+    pub chunk_stats: ValidatorStats,
+    /// Reason the validator is kicked out of the epoch after next, if it is.
+    pub kickout_reason: Option<ValidatorKickoutReason>,
+    /// Reward of the validator for the epoch.
+    pub reward: Balance,
+    /// Stake of the validator in the epoch after next, if it changes.
+    pub next_stake: Option<Balance>,
+}
+
+/// Performance of the validators of a finished epoch, saved when the epoch is finalized.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct EpochValidatorStats {
+    pub epoch_id: EpochId,
+    pub epoch_height: EpochHeight,
+    pub epoch_start_height: BlockHeight,
+    /// Last block of the epoch, the stats are stored under its hash.
+    pub last_block_hash: CryptoHash,
+    pub validators: Vec<ValidatorEpochStats>,
 }
 
 /// State that a slashed validator can be in.
@@ -1,11 +1,12 @@
 use crate::serialize::u128_dec_format;
-use crate::types::{AccountId, Balance, EpochId, Gas, Nonce};
+use crate::types::{AccountId, Balance, BlockHeight, EpochId, Gas, Nonce, ShardId};
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::PublicKey;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display};
 
 use crate::hash::CryptoHash;
+use crate::version::ProtocolVersion;
 use near_rpc_error_macro::RpcError;
 use near_vm_errors::{FunctionCallError, VMLogicError};
 
@@ -141,6 +142,8 @@ pub enum InvalidTxError {
     Expired,
     /// An error occurred while validating actions of a Transaction.
     ActionsValidation(ActionsValidationError),
+    /// The shard of the transaction receiver has too many delayed receipts to accept new ones.
+    ReceiverShardCongested { shard_id: ShardId, delayed_receipts_count: u64 },
 }
 
 #[derive(
@@ -166,6 +169,26 @@ pub enum InvalidAccessKeyError {
     },
     /// Having a deposit with a function call action is not allowed with a function call access key.
     DepositWithFunctionCall,
+    /// Transaction `receiver_id` isn't one of the access key receiver_ids
+    ReceiverNotAllowed { tx_receiver: AccountId },
+    /// The deposit attached to the function call exceeds the per call limit of the access key
+    DepositExceedsLimit {
+        #[serde(with = "u128_dec_format")]
+        deposit: Balance,
+        #[serde(with = "u128_dec_format")]
+        limit: Balance,
+    },
+    /// Access Key does not have enough deposit allowance to cover the attached deposit
+    NotEnoughDepositAllowance {
+        account_id: AccountId,
+        public_key: PublicKey,
+        #[serde(with = "u128_dec_format")]
+        deposit_allowance: Balance,
+        #[serde(with = "u128_dec_format")]
+        deposit: Balance,
+    },
+    /// The access key has expired at the given block height
+    AccessKeyExpired { expires_at: BlockHeight, block_height: BlockHeight },
 }
 
 /// Describes the error for validating a list of actions.
@@ -197,6 +220,12 @@ pub enum ActionsValidationError {
     UnsuitableStakingKey { public_key: PublicKey },
     /// The attached amount of gas in a FunctionCall action has to be a positive number.
     FunctionCallZeroAttachedGas,
+    /// The action is not supported by the current protocol version.
+    UnsupportedProtocolFeature { protocol_feature: String, version: ProtocolVersion },
+    /// A delegate action can't contain other delegate actions.
+    NestedDelegateAction,
+    /// The size of the contract ABI exceeded the limit in a DeployContractWithAbi action.
+    ContractAbiSizeExceeded { size: u64, limit: u64 },
 }
 
 /// Describes the error for validating a receipt.
@@ -311,6 +340,20 @@ impl Display for ActionsValidationError {
                 f,
                 "The attached amount of gas in a FunctionCall action has to be a positive number",
             ),
+            ActionsValidationError::UnsupportedProtocolFeature { protocol_feature, version } => write!(
+                f,
+                "{} is not supported by the protocol version {}",
+                protocol_feature, version
+            ),
+            ActionsValidationError::NestedDelegateAction => write!(
+                f,
+                "A delegate action can't contain other delegate actions",
+            ),
+            ActionsValidationError::ContractAbiSizeExceeded { size, limit } => write!(
+                f,
+                "The size of the contract ABI {} exceeds the maximum allowed size {} in a DeployContractWithAbi action",
+                size, limit
+            ),
         }
     }
 }
@@ -387,6 +430,27 @@ pub enum ActionErrorKind {
     /// Error occurs when a `CreateAccount` action is called on hex-characters account of length 64.
     /// See implicit account creation NEP: https://github.com/nearprotocol/NEPs/pull/71
     OnlyImplicitAccountCreationAllowed { account_id: AccountId },
+    /// Signature of the delegate action doesn't match its `public_key`.
+    DelegateActionInvalidSignature,
+    /// The `sender_id` of the delegate action is not the receiver of the transaction.
+    DelegateActionSenderDoesNotMatchTxReceiver { sender_id: AccountId, receiver_id: AccountId },
+    /// The delegate action is applied after its `max_block_height`.
+    DelegateActionExpired,
+    /// The access key used to sign the delegate action is missing or can't be used for its actions.
+    DelegateActionAccessKeyError(InvalidAccessKeyError),
+    /// The nonce of the delegate action is not greater than the nonce of the access key.
+    DelegateActionInvalidNonce { delegate_nonce: Nonce, ak_nonce: Nonce },
+    /// The sender of the delegate action doesn't have enough balance for the attached deposits.
+    DelegateActionNotEnoughBalance {
+        sender_id: AccountId,
+        #[serde(with = "u128_dec_format")]
+        balance: Balance,
+        #[serde(with = "u128_dec_format")]
+        cost: Balance,
+    },
+    /// The account can't be created, because the contract data of the previously deleted account
+    /// with the same account_id is still being removed from the state.
+    DeletedAccountCleanupInProgress { account_id: AccountId },
 }
 
 impl From<ActionErrorKind> for ActionError {
@@ -436,6 +500,11 @@ impl Display for InvalidTxError {
             InvalidTxError::ActionsValidation(error) => {
                 write!(f, "Transaction actions validation error: {}", error)
             }
+            InvalidTxError::ReceiverShardCongested { shard_id, delayed_receipts_count } => write!(
+                f,
+                "Shard {} of the transaction receiver is congested with {} delayed receipts",
+                shard_id, delayed_receipts_count
+            ),
         }
     }
 }
@@ -483,6 +552,31 @@ impl Display for InvalidAccessKeyError {
             InvalidAccessKeyError::DepositWithFunctionCall => {
                 write!(f, "Having a deposit with a function call action is not allowed with a function call access key.")
             }
+            InvalidAccessKeyError::ReceiverNotAllowed { tx_receiver } => write!(
+                f,
+                "Transaction receiver_id {:?} isn't allowed by the access key",
+                tx_receiver
+            ),
+            InvalidAccessKeyError::DepositExceedsLimit { deposit, limit } => write!(
+                f,
+                "Attached deposit {} exceeds the access key limit {} per function call",
+                deposit, limit
+            ),
+            InvalidAccessKeyError::NotEnoughDepositAllowance {
+                account_id,
+                public_key,
+                deposit_allowance,
+                deposit,
+            } => write!(
+                f,
+                "Access Key {:?}:{} does not have enough deposit allowance {} for attached deposit {}",
+                account_id, public_key, deposit_allowance, deposit
+            ),
+            InvalidAccessKeyError::AccessKeyExpired { expires_at, block_height } => write!(
+                f,
+                "Access key expired at block height {}, current block height is {}",
+                expires_at, block_height
+            ),
         }
     }
 }
@@ -503,6 +597,8 @@ pub struct BalanceMismatchError {
     pub processed_delayed_receipts_balance: Balance,
     #[serde(with = "u128_dec_format")]
     pub initial_postponed_receipts_balance: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub released_scheduled_receipts_balance: Balance,
     // Output balances
     #[serde(with = "u128_dec_format")]
     pub final_accounts_balance: Balance,
@@ -513,6 +609,8 @@ pub struct BalanceMismatchError {
     #[serde(with = "u128_dec_format")]
     pub final_postponed_receipts_balance: Balance,
     #[serde(with = "u128_dec_format")]
+    pub new_scheduled_receipts_balance: Balance,
+    #[serde(with = "u128_dec_format")]
     pub tx_burnt_amount: Balance,
     #[serde(with = "u128_dec_format")]
     pub slashed_burnt_amount: Balance,
@@ -528,12 +626,14 @@ impl Display for BalanceMismatchError {
             .saturating_add(self.initial_accounts_balance)
             .saturating_add(self.incoming_receipts_balance)
             .saturating_add(self.processed_delayed_receipts_balance)
-            .saturating_add(self.initial_postponed_receipts_balance);
+            .saturating_add(self.initial_postponed_receipts_balance)
+            .saturating_add(self.released_scheduled_receipts_balance);
         let final_balance = self
             .final_accounts_balance
             .saturating_add(self.outgoing_receipts_balance)
             .saturating_add(self.new_delayed_receipts_balance)
             .saturating_add(self.final_postponed_receipts_balance)
+            .saturating_add(self.new_scheduled_receipts_balance)
             .saturating_add(self.tx_burnt_amount)
             .saturating_add(self.slashed_burnt_amount)
             .saturating_add(self.other_burnt_amount);
@@ -546,11 +646,13 @@ impl Display for BalanceMismatchError {
              \tIncoming receipts balance sum: {}\n\
              \tProcessed delayed receipts balance sum: {}\n\
              \tInitial postponed receipts balance sum: {}\n\
+             \tReleased scheduled receipts balance sum: {}\n\
              Outputs:\n\
              \tFinal accounts balance sum: {}\n\
              \tOutgoing receipts balance sum: {}\n\
              \tNew delayed receipts balance sum: {}\n\
              \tFinal postponed receipts balance sum: {}\n\
+             \tNew scheduled receipts balance sum: {}\n\
              \tTx fees burnt amount: {}\n\
              \tSlashed amount: {}\n\
              \tOther burnt amount: {}",
@@ -561,10 +663,12 @@ impl Display for BalanceMismatchError {
             self.incoming_receipts_balance,
             self.processed_delayed_receipts_balance,
             self.initial_postponed_receipts_balance,
+            self.released_scheduled_receipts_balance,
             self.final_accounts_balance,
             self.outgoing_receipts_balance,
             self.new_delayed_receipts_balance,
             self.final_postponed_receipts_balance,
+            self.new_scheduled_receipts_balance,
             self.tx_burnt_amount,
             self.slashed_burnt_amount,
             self.other_burnt_amount,
@@ -674,7 +778,14 @@ impl Display for ActionErrorKind {
                 write!(f, "An new action receipt created during a FunctionCall is not valid: {}", e)
             }
             ActionErrorKind::InsufficientStake { account_id, stake, minimum_stake } => write!(f, "Account {} tries to stake {} but minimum required stake is {}", account_id, stake, minimum_stake),
-            ActionErrorKind::OnlyImplicitAccountCreationAllowed { account_id } => write!(f, "CreateAccount action is called on hex-characters account of length 64 {}", account_id)
+            ActionErrorKind::OnlyImplicitAccountCreationAllowed { account_id } => write!(f, "CreateAccount action is called on hex-characters account of length 64 {}", account_id),
+            ActionErrorKind::DelegateActionInvalidSignature => write!(f, "The signature of the delegate action is not valid"),
+            ActionErrorKind::DelegateActionSenderDoesNotMatchTxReceiver { sender_id, receiver_id } => write!(f, "Delegate action sender {} doesn't match the transaction receiver {}", sender_id, receiver_id),
+            ActionErrorKind::DelegateActionExpired => write!(f, "Delegate action has expired"),
+            ActionErrorKind::DelegateActionAccessKeyError(access_key_error) => Display::fmt(&access_key_error, f),
+            ActionErrorKind::DelegateActionInvalidNonce { delegate_nonce, ak_nonce } => write!(f, "Delegate action nonce {} must be larger than the access key nonce {}", delegate_nonce, ak_nonce),
+            ActionErrorKind::DelegateActionNotEnoughBalance { sender_id, balance, cost } => write!(f, "Sender {} does not have enough balance {} for the delegated deposits {}", sender_id, balance, cost),
+            ActionErrorKind::DeletedAccountCleanupInProgress { account_id } => write!(f, "Account {} can't be created until the state of the deleted account with the same name is removed", account_id),
         }
     }
 }
@@ -19,8 +19,10 @@ pub mod logging;
 pub mod merkle;
 pub mod network;
 pub mod receipt;
+pub mod remote_signer;
 pub mod rpc;
 pub mod serialize;
+pub mod shard_layout;
 pub mod sharding;
 pub mod state_record;
 pub mod syncing;
@@ -10,7 +10,7 @@ use crate::hash::CryptoHash;
 use crate::logging;
 use crate::serialize::{option_base64_format, u128_dec_format_compatible};
 use crate::transaction::{Action, TransferAction};
-use crate::types::{AccountId, Balance};
+use crate::types::{AccountId, Balance, BlockHeight};
 use crate::utils::system_account;
 
 /// Receipts are used for a cross-shard communication.
@@ -60,6 +60,43 @@ impl Receipt {
         }
     }
 
+    /// Generates a receipt with a transfer from system for a given deposit without a receipt_id.
+    /// Unlike `new_balance_refund` it contains the `signer_public_key` of the access key that
+    /// attached the deposit. The execution of this receipt will try to refund the deposit
+    /// allowance of this access key, the same best effort way as `new_gas_refund`.
+    pub fn new_deposit_refund(
+        receiver_id: &AccountId,
+        refund: Balance,
+        signer_public_key: PublicKey,
+    ) -> Self {
+        Receipt {
+            predecessor_id: system_account(),
+            receiver_id: receiver_id.clone(),
+            receipt_id: CryptoHash::default(),
+
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: system_account(),
+                signer_public_key,
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::Transfer(TransferAction { deposit: refund })],
+            }),
+        }
+    }
+
+    /// Returns whether the receipt was generated by `new_deposit_refund`.
+    pub fn is_deposit_refund(&self) -> bool {
+        match &self.receipt {
+            ReceiptEnum::Action(action_receipt) => {
+                self.predecessor_id == system_account()
+                    && action_receipt.signer_id == system_account()
+                    && action_receipt.signer_public_key != PublicKey::empty(KeyType::ED25519)
+            }
+            ReceiptEnum::Data(_) => false,
+        }
+    }
+
     /// Generates a receipt with a transfer action from system for a given balance without a
     /// receipt_id. It contains `signer_id` and `signer_public_key` to indicate this is a gas
     /// refund. The execution of this receipt will try to refund the allowance of the
@@ -171,3 +208,34 @@ pub struct DelayedReceiptIndices {
     // Exclusive end index of the queue
     pub next_available_index: u64,
 }
+
+/// Describes when a scheduled receipt becomes due. Scheduled receipts are stored in the state of
+/// the shard of the account that scheduled them, and are sent to their receivers by the first
+/// chunk applied at or after the given block height or block timestamp.
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    Serialize,
+    Deserialize,
+    Hash,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub enum ReceiptSchedule {
+    BlockHeight(BlockHeight),
+    /// Block timestamp in nanoseconds.
+    BlockTimestamp(u64),
+}
+
+impl ReceiptSchedule {
+    /// Returns whether the schedule is due at the given block height and block timestamp.
+    pub fn is_due(&self, block_height: BlockHeight, block_timestamp: u64) -> bool {
+        match self {
+            ReceiptSchedule::BlockHeight(height) => *height <= block_height,
+            ReceiptSchedule::BlockTimestamp(timestamp) => *timestamp <= block_timestamp,
+        }
+    }
+}
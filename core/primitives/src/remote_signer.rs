@@ -0,0 +1,439 @@
+//! Validator signer that keeps the secret key in a separate signer process and sends it every
+//! signing request over a Unix socket. The signer process gets the same data as the
+//! `ValidatorSigner` methods and computes the signed hashes itself. The reference signer in
+//! `tools/remote-signer` signs every request it gets; checking for double signing is left to the
+//! slashing protection of the node.
+//!
+//! Every message is a little endian `u32` length followed by the borsh serialized
+//! `RemoteSignerRequest` or `RemoteSignerResponse`.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_crypto::{PublicKey, Signature};
+
+use crate::block::ApprovalInner;
+use crate::challenge::ChallengeBody;
+use crate::hash::CryptoHash;
+use crate::network::PeerId;
+use crate::sharding::ChunkHash;
+use crate::telemetry::TelemetryInfo;
+use crate::types::{AccountId, BlockHeight, EpochId};
+use crate::validator_signer::ValidatorSigner;
+
+/// Largest message accepted from the other side of the socket.
+const MAX_MESSAGE_SIZE: usize = 512 * 1024 * 1024;
+
+/// How long the node waits for the signer process to accept or answer a request.
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request sent by the node to the signer process.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub enum RemoteSignerRequest {
+    /// Account id and public key of the validator.
+    ValidatorKey,
+    /// Telemetry info serialized to JSON.
+    Telemetry(String),
+    BlockHeaderParts {
+        prev_hash: CryptoHash,
+        inner_lite: Vec<u8>,
+        inner_rest: Vec<u8>,
+    },
+    ChunkHash(ChunkHash),
+    Approval {
+        inner: ApprovalInner,
+        target_height: BlockHeight,
+    },
+    Challenge(ChallengeBody),
+    AccountAnnounce {
+        account_id: AccountId,
+        peer_id: PeerId,
+        epoch_id: EpochId,
+    },
+    VrfWithProof(Vec<u8>),
+}
+
+/// Response of the signer process to a `RemoteSignerRequest`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum RemoteSignerResponse {
+    ValidatorKey {
+        account_id: AccountId,
+        public_key: PublicKey,
+    },
+    /// Signed telemetry info serialized to JSON.
+    Telemetry(String),
+    Signature(Signature),
+    HashAndSignature(CryptoHash, Signature),
+    VrfWithProof(near_crypto::vrf::Value, near_crypto::vrf::Proof),
+    /// The signer refused or failed to handle the request.
+    Error(String),
+}
+
+pub fn write_message<T: BorshSerialize>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+    let bytes = message.try_to_vec()?;
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message is too large"))?;
+    stream.write_all(&len.to_le_bytes())?;
+    stream.write_all(&bytes)?;
+    stream.flush()
+}
+
+pub fn read_message<T: BorshDeserialize>(stream: &mut impl Read) -> io::Result<T> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message is too large"));
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes)?;
+    T::try_from_slice(&bytes)
+}
+
+/// Handles one request in the signer process with the signer that holds the secret key.
+pub fn handle_remote_signer_request(
+    signer: &dyn ValidatorSigner,
+    request: RemoteSignerRequest,
+) -> RemoteSignerResponse {
+    match request {
+        RemoteSignerRequest::ValidatorKey => RemoteSignerResponse::ValidatorKey {
+            account_id: signer.validator_id().clone(),
+            public_key: signer.public_key(),
+        },
+        RemoteSignerRequest::Telemetry(content) => {
+            match serde_json::from_str::<TelemetryInfo>(&content) {
+                Ok(info) => {
+                    RemoteSignerResponse::Telemetry(signer.sign_telemetry(&info).to_string())
+                }
+                Err(err) => RemoteSignerResponse::Error(format!("Invalid telemetry info: {}", err)),
+            }
+        }
+        RemoteSignerRequest::BlockHeaderParts { prev_hash, inner_lite, inner_rest } => {
+            let (hash, signature) =
+                signer.sign_block_header_parts(prev_hash, &inner_lite, &inner_rest);
+            RemoteSignerResponse::HashAndSignature(hash, signature)
+        }
+        RemoteSignerRequest::ChunkHash(chunk_hash) => {
+            RemoteSignerResponse::Signature(signer.sign_chunk_hash(&chunk_hash))
+        }
+        RemoteSignerRequest::Approval { inner, target_height } => {
+            RemoteSignerResponse::Signature(signer.sign_approval(&inner, target_height))
+        }
+        RemoteSignerRequest::Challenge(challenge_body) => {
+            let (hash, signature) = signer.sign_challenge(&challenge_body);
+            RemoteSignerResponse::HashAndSignature(hash, signature)
+        }
+        RemoteSignerRequest::AccountAnnounce { account_id, peer_id, epoch_id } => {
+            RemoteSignerResponse::Signature(signer.sign_account_announce(
+                &account_id,
+                &peer_id,
+                &epoch_id,
+            ))
+        }
+        RemoteSignerRequest::VrfWithProof(data) => {
+            let (value, proof) = signer.compute_vrf_with_proof(&data);
+            RemoteSignerResponse::VrfWithProof(value, proof)
+        }
+    }
+}
+
+/// Answers the requests of one node connection until it is closed.
+pub fn serve_remote_signer_connection(
+    signer: &dyn ValidatorSigner,
+    mut stream: UnixStream,
+) -> io::Result<()> {
+    loop {
+        let request = match read_message::<RemoteSignerRequest>(&mut stream) {
+            Ok(request) => request,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        write_message(&mut stream, &handle_remote_signer_request(signer, request))?;
+    }
+}
+
+/// Signer that forwards every signing request to a signer process listening on a Unix socket.
+/// The connection is re-established once if a request fails. As `ValidatorSigner` methods can't
+/// fail, the node panics if the signer can't be reached or refuses to sign.
+pub struct RemoteValidatorSigner {
+    socket_path: PathBuf,
+    account_id: AccountId,
+    public_key: PublicKey,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+/// Connects to the signer process, with timeouts so that a stuck signer fails the request
+/// instead of blocking the node forever.
+fn connect_with_timeouts(socket_path: &Path) -> io::Result<UnixStream> {
+    let stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+    stream.set_write_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+    Ok(stream)
+}
+
+impl RemoteValidatorSigner {
+    /// Connects to the signer process and fetches the account id and public key of the validator.
+    pub fn connect(socket_path: &Path) -> io::Result<Self> {
+        let mut stream = connect_with_timeouts(socket_path)?;
+        write_message(&mut stream, &RemoteSignerRequest::ValidatorKey)?;
+        match read_message(&mut stream)? {
+            RemoteSignerResponse::ValidatorKey { account_id, public_key } => Ok(Self {
+                socket_path: socket_path.to_path_buf(),
+                account_id,
+                public_key,
+                stream: Mutex::new(Some(stream)),
+            }),
+            RemoteSignerResponse::Error(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response")),
+        }
+    }
+
+    fn try_request(
+        &self,
+        stream: &mut Option<UnixStream>,
+        request: &RemoteSignerRequest,
+    ) -> io::Result<RemoteSignerResponse> {
+        if stream.is_none() {
+            *stream = Some(connect_with_timeouts(&self.socket_path)?);
+        }
+        let result = stream
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "Not connected"))
+            .and_then(|stream| {
+                write_message(stream, request)?;
+                read_message(stream)
+            });
+        if result.is_err() {
+            *stream = None;
+        }
+        result
+    }
+
+    fn request(&self, request: RemoteSignerRequest) -> RemoteSignerResponse {
+        let mut stream = self.stream.lock().expect("Remote signer connection lock is poisoned");
+        let response = self
+            .try_request(&mut *stream, &request)
+            .or_else(|_| self.try_request(&mut *stream, &request))
+            .unwrap_or_else(|err| {
+                panic!("Remote signer at {} failed: {}", self.socket_path.display(), err)
+            });
+        if let RemoteSignerResponse::Error(err) = &response {
+            panic!("Remote signer refused to sign {:?}: {}", request, err);
+        }
+        response
+    }
+}
+
+fn unexpected_response(request: &str) -> ! {
+    panic!("Remote signer sent an unexpected response to {} request", request)
+}
+
+impl ValidatorSigner for RemoteValidatorSigner {
+    fn validator_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value {
+        let content = serde_json::to_string(info).expect("Telemetry must serialize to JSON");
+        match self.request(RemoteSignerRequest::Telemetry(content)) {
+            RemoteSignerResponse::Telemetry(signed) => {
+                serde_json::from_str(&signed).expect("Signed telemetry must be JSON")
+            }
+            _ => unexpected_response("telemetry"),
+        }
+    }
+
+    fn sign_block_header_parts(
+        &self,
+        prev_hash: CryptoHash,
+        inner_lite: &[u8],
+        inner_rest: &[u8],
+    ) -> (CryptoHash, Signature) {
+        match self.request(RemoteSignerRequest::BlockHeaderParts {
+            prev_hash,
+            inner_lite: inner_lite.to_vec(),
+            inner_rest: inner_rest.to_vec(),
+        }) {
+            RemoteSignerResponse::HashAndSignature(hash, signature) => (hash, signature),
+            _ => unexpected_response("block header"),
+        }
+    }
+
+    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature {
+        match self.request(RemoteSignerRequest::ChunkHash(chunk_hash.clone())) {
+            RemoteSignerResponse::Signature(signature) => signature,
+            _ => unexpected_response("chunk hash"),
+        }
+    }
+
+    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature {
+        match self.request(RemoteSignerRequest::Approval { inner: inner.clone(), target_height }) {
+            RemoteSignerResponse::Signature(signature) => signature,
+            _ => unexpected_response("approval"),
+        }
+    }
+
+    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+        match self.request(RemoteSignerRequest::Challenge(challenge_body.clone())) {
+            RemoteSignerResponse::HashAndSignature(hash, signature) => (hash, signature),
+            _ => unexpected_response("challenge"),
+        }
+    }
+
+    fn sign_account_announce(
+        &self,
+        account_id: &AccountId,
+        peer_id: &PeerId,
+        epoch_id: &EpochId,
+    ) -> Signature {
+        match self.request(RemoteSignerRequest::AccountAnnounce {
+            account_id: account_id.clone(),
+            peer_id: peer_id.clone(),
+            epoch_id: epoch_id.clone(),
+        }) {
+            RemoteSignerResponse::Signature(signature) => signature,
+            _ => unexpected_response("account announce"),
+        }
+    }
+
+    fn compute_vrf_with_proof(
+        &self,
+        data: &[u8],
+    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
+        match self.request(RemoteSignerRequest::VrfWithProof(data.to_vec())) {
+            RemoteSignerResponse::VrfWithProof(value, proof) => (value, proof),
+            _ => unexpected_response("VRF"),
+        }
+    }
+
+    fn write_to_file(&self, path: &Path) {
+        panic!(
+            "Can't write the validator key to {}: the secret key of a remote signer is only known to the signer process at {}",
+            path.display(),
+            self.socket_path.display()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    use near_crypto::KeyType;
+
+    use crate::hash::hash;
+    use crate::validator_signer::InMemoryValidatorSigner;
+
+    use super::*;
+
+    fn test_signer() -> Arc<InMemoryValidatorSigner> {
+        Arc::new(InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0"))
+    }
+
+    /// Starts a signer process that answers the given number of requests on each connection it
+    /// accepts, in order, and then closes it.
+    fn start_signer(
+        listener: UnixListener,
+        requests_per_connection: Vec<usize>,
+        handler: impl Fn(RemoteSignerRequest) -> RemoteSignerResponse + Send + 'static,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            for num_requests in requests_per_connection {
+                let (mut stream, _) = listener.accept().unwrap();
+                for _ in 0..num_requests {
+                    let request = read_message(&mut stream).unwrap();
+                    write_message(&mut stream, &handler(request)).unwrap();
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_remote_signer_matches_in_memory_signer() {
+        let dir = tempfile::Builder::new().prefix("remote_signer").tempdir().unwrap();
+        let socket_path = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let signer = test_signer();
+        let server_signer = signer.clone();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve_remote_signer_connection(&*server_signer, stream).unwrap();
+        });
+
+        let remote_signer = RemoteValidatorSigner::connect(&socket_path).unwrap();
+        assert_eq!(remote_signer.validator_id(), signer.validator_id());
+        assert_eq!(ValidatorSigner::public_key(&remote_signer), signer.public_key());
+        let inner = ApprovalInner::Endorsement(hash(&[1]));
+        assert_eq!(remote_signer.sign_approval(&inner, 2), signer.sign_approval(&inner, 2));
+        assert_eq!(
+            remote_signer.sign_block_header_parts(hash(&[1]), &[2], &[3]),
+            signer.sign_block_header_parts(hash(&[1]), &[2], &[3])
+        );
+        let (value, _) = remote_signer.compute_vrf_with_proof(&[1, 2, 3]);
+        assert!(value == signer.compute_vrf_with_proof(&[1, 2, 3]).0);
+
+        drop(remote_signer);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_remote_signer_reconnects() {
+        let dir = tempfile::Builder::new().prefix("remote_signer").tempdir().unwrap();
+        let socket_path = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let signer = test_signer();
+        let server_signer = signer.clone();
+        // The first connection is closed right after the validator key is sent.
+        let server = start_signer(listener, vec![1, 1], move |request| {
+            handle_remote_signer_request(&*server_signer, request)
+        });
+
+        let remote_signer = RemoteValidatorSigner::connect(&socket_path).unwrap();
+        let inner = ApprovalInner::Skip(1);
+        assert_eq!(remote_signer.sign_approval(&inner, 2), signer.sign_approval(&inner, 2));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_remote_signer_error_response() {
+        let dir = tempfile::Builder::new().prefix("remote_signer").tempdir().unwrap();
+        let socket_path = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = start_signer(listener, vec![1], |_| {
+            RemoteSignerResponse::Error("validator key is locked".to_string())
+        });
+
+        let err = RemoteValidatorSigner::connect(&socket_path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(err.to_string(), "validator key is locked");
+        server.join().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Remote signer refused to sign")]
+    fn test_remote_signer_panics_on_refused_request() {
+        let dir = tempfile::Builder::new().prefix("remote_signer").tempdir().unwrap();
+        let socket_path = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let signer = test_signer();
+        let _server = start_signer(listener, vec![2], move |request| match request {
+            RemoteSignerRequest::ValidatorKey => handle_remote_signer_request(&*signer, request),
+            _ => RemoteSignerResponse::Error("conflicting approval".to_string()),
+        });
+
+        let remote_signer = RemoteValidatorSigner::connect(&socket_path).unwrap();
+        remote_signer.sign_approval(&ApprovalInner::Skip(1), 2);
+    }
+}
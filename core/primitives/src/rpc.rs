@@ -7,10 +7,12 @@ use serde::{Deserialize, Serialize};
 use crate::hash::CryptoHash;
 use crate::merkle::MerklePath;
 use crate::transaction::SignedTransaction;
-use crate::types::{AccountId, BlockReference, MaybeBlockId, TransactionOrReceiptId};
+use crate::types::{
+    AccountId, BlockHeight, BlockHeightDelta, BlockReference, MaybeBlockId, TransactionOrReceiptId,
+};
 use crate::views::{
-    ExecutionOutcomeWithIdView, LightClientBlockLiteView, QueryRequest, StateChangeWithCauseView,
-    StateChangesKindsView, StateChangesRequestView,
+    AccountBalanceChangeView, ActionView, ExecutionOutcomeWithIdView, LightClientBlockLiteView,
+    QueryRequest, StateChangeWithCauseView, StateChangesKindsView, StateChangesRequestView,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -47,6 +49,39 @@ pub struct RpcStateChangesInBlockResponse {
     pub changes: StateChangesKindsView,
 }
 
+/// Options of the `tx` and `EXPERIMENTAL_tx_status` methods passed after the transaction.
+#[derive(Serialize, Deserialize, Default)]
+pub struct RpcTransactionStatusOptions {
+    /// Include the execution trace of every receipt.
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcAccountBalanceChangesRequest {
+    pub account_id: AccountId,
+    /// First block of the range, inclusive.
+    pub from_block_height: BlockHeight,
+    /// Last block of the range, inclusive.
+    pub to_block_height: BlockHeight,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcAccountBalanceChangesResponse {
+    pub account_id: AccountId,
+    pub changes: Vec<AccountBalanceChangeView>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcEstimateFeeRequest {
+    pub signer_id: AccountId,
+    pub receiver_id: AccountId,
+    pub actions: Vec<ActionView>,
+    /// Number of blocks to project the gas price over.
+    #[serde(default)]
+    pub num_blocks: Option<BlockHeightDelta>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RpcBroadcastTxSyncResponse {
     pub transaction_hash: String,
@@ -78,3 +113,16 @@ pub enum TransactionInfo {
 pub struct RpcValidatorsOrderedRequest {
     pub block_id: MaybeBlockId,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcValidatorsHistoryRequest {
+    /// The history ends with the epoch before the epoch of this block, the latest block if empty.
+    #[serde(default)]
+    pub block_id: MaybeBlockId,
+    /// Only include the stats of this validator.
+    #[serde(default)]
+    pub account_id: Option<AccountId>,
+    /// Number of finished epochs to return.
+    #[serde(default)]
+    pub num_epochs: Option<u64>,
+}
@@ -0,0 +1,167 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{AccountId, NumShards, ShardId};
+use crate::utils::account_id_to_shard_id;
+
+/// Version of the shard layout. It's increased every time the shards are split.
+pub type ShardVersion = u32;
+
+/// Describes how accounts are assigned to shards.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ShardLayout {
+    /// Accounts are assigned to shards by the hash of the account id.
+    V0 { num_shards: NumShards, version: ShardVersion },
+    /// Accounts are assigned to shards by ranges. Shard `i` contains the accounts that are
+    /// greater or equal to `boundary_accounts[i - 1]` and less than `boundary_accounts[i]`.
+    V1 {
+        boundary_accounts: Vec<AccountId>,
+        /// Parent shard in the previous shard layout of every shard, if the shards were split
+        /// from the previous layout.
+        to_parent_shard_map: Option<Vec<ShardId>>,
+        version: ShardVersion,
+    },
+}
+
+impl Default for ShardLayout {
+    fn default() -> Self {
+        ShardLayout::v0(1, 0)
+    }
+}
+
+impl ShardLayout {
+    pub fn v0(num_shards: NumShards, version: ShardVersion) -> Self {
+        ShardLayout::V0 { num_shards, version }
+    }
+
+    pub fn v1(
+        boundary_accounts: Vec<AccountId>,
+        to_parent_shard_map: Option<Vec<ShardId>>,
+        version: ShardVersion,
+    ) -> Self {
+        ShardLayout::V1 { boundary_accounts, to_parent_shard_map, version }
+    }
+
+    pub fn num_shards(&self) -> NumShards {
+        match self {
+            ShardLayout::V0 { num_shards, .. } => *num_shards,
+            ShardLayout::V1 { boundary_accounts, .. } => boundary_accounts.len() as NumShards + 1,
+        }
+    }
+
+    pub fn version(&self) -> ShardVersion {
+        match self {
+            ShardLayout::V0 { version, .. } | ShardLayout::V1 { version, .. } => *version,
+        }
+    }
+
+    pub fn shard_ids(&self) -> impl Iterator<Item = ShardId> {
+        0..self.num_shards()
+    }
+
+    /// Maps the given account to the shard it belongs to.
+    pub fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId {
+        match self {
+            ShardLayout::V0 { num_shards, .. } => account_id_to_shard_id(account_id, *num_shards),
+            ShardLayout::V1 { boundary_accounts, .. } => boundary_accounts
+                .iter()
+                .take_while(|boundary_account| *boundary_account <= account_id)
+                .count() as ShardId,
+        }
+    }
+
+    /// Returns the shard of the previous shard layout the given shard was split from, or the
+    /// shard itself if it wasn't split.
+    pub fn get_parent_shard_id(&self, shard_id: ShardId) -> ShardId {
+        match self {
+            ShardLayout::V1 { to_parent_shard_map: Some(to_parent_shard_map), .. } => {
+                to_parent_shard_map[shard_id as usize]
+            }
+            _ => shard_id,
+        }
+    }
+
+    /// Returns the shards of this layout that were split from the given shard of the previous
+    /// shard layout, in increasing order.
+    pub fn get_split_shard_ids(&self, parent_shard_id: ShardId) -> Vec<ShardId> {
+        self.shard_ids()
+            .filter(|shard_id| self.get_parent_shard_id(*shard_id) == parent_shard_id)
+            .collect()
+    }
+
+    /// Checks that every shard of this layout contains only the accounts of its parent shard in
+    /// the given previous layout, so the state of the parent shard can be split between them.
+    pub fn is_split_of(&self, parent: &ShardLayout) -> bool {
+        let (boundary_accounts, to_parent_shard_map) = match self {
+            ShardLayout::V1 { boundary_accounts, to_parent_shard_map: Some(map), .. } => {
+                (boundary_accounts, map)
+            }
+            _ => return false,
+        };
+        if to_parent_shard_map.len() as NumShards != self.num_shards()
+            || self.version() <= parent.version()
+            || !boundary_accounts.windows(2).all(|w| w[0] < w[1])
+        {
+            return false;
+        }
+        match parent {
+            // Hash based layout can only be split if all the accounts are in the same shard.
+            ShardLayout::V0 { num_shards, .. } => {
+                *num_shards == 1 && to_parent_shard_map.iter().all(|shard_id| *shard_id == 0)
+            }
+            ShardLayout::V1 { boundary_accounts: parent_boundary_accounts, .. } => {
+                parent_boundary_accounts
+                    .iter()
+                    .all(|account_id| boundary_accounts.contains(account_id))
+                    && self.shard_ids().all(|shard_id| {
+                        let first_account = if shard_id == 0 {
+                            AccountId::new()
+                        } else {
+                            boundary_accounts[shard_id as usize - 1].clone()
+                        };
+                        parent.account_id_to_shard_id(&first_account)
+                            == to_parent_shard_map[shard_id as usize]
+                    })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardLayout;
+
+    #[test]
+    fn test_account_id_to_shard_id_v1() {
+        let layout = ShardLayout::v1(vec!["ccc".to_string(), "mmm".to_string()], None, 1);
+        assert_eq!(layout.num_shards(), 3);
+        assert_eq!(layout.account_id_to_shard_id(&"aaa".to_string()), 0);
+        assert_eq!(layout.account_id_to_shard_id(&"ccc".to_string()), 1);
+        assert_eq!(layout.account_id_to_shard_id(&"ccc.near".to_string()), 1);
+        assert_eq!(layout.account_id_to_shard_id(&"mmm".to_string()), 2);
+        assert_eq!(layout.account_id_to_shard_id(&"zzz".to_string()), 2);
+    }
+
+    #[test]
+    fn test_split_shards() {
+        let genesis_layout = ShardLayout::v0(1, 0);
+        let layout = ShardLayout::v1(vec!["mmm".to_string()], Some(vec![0, 0]), 1);
+        assert!(layout.is_split_of(&genesis_layout));
+        assert_eq!(layout.get_split_shard_ids(0), vec![0, 1]);
+        assert_eq!(layout.get_parent_shard_id(1), 0);
+
+        let next_layout =
+            ShardLayout::v1(vec!["ccc".to_string(), "mmm".to_string()], Some(vec![0, 0, 1]), 2);
+        assert!(next_layout.is_split_of(&layout));
+        assert_eq!(next_layout.get_split_shard_ids(0), vec![0, 1]);
+        assert_eq!(next_layout.get_split_shard_ids(1), vec![2]);
+
+        // The boundary of the parent layout must be kept.
+        let bad_layout = ShardLayout::v1(vec!["ccc".to_string()], Some(vec![0, 0]), 2);
+        assert!(!bad_layout.is_split_of(&layout));
+        // Shards of a hash based layout are not ranges of accounts.
+        assert!(!layout.is_split_of(&ShardLayout::v0(2, 0)));
+        // Split must be to a newer version.
+        assert!(!next_layout.is_split_of(&ShardLayout::v1(vec!["mmm".to_string()], None, 2)));
+    }
+}
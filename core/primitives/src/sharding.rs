@@ -7,13 +7,26 @@ use near_crypto::Signature;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::{combine_hash, merklize, MerklePath};
 use crate::receipt::Receipt;
+use crate::shard_layout::ShardLayout;
 use crate::transaction::SignedTransaction;
 use crate::types::{Balance, BlockHeight, Gas, MerkleHash, ShardId, StateRoot, ValidatorStake};
 use crate::validator_signer::ValidatorSigner;
+#[cfg(feature = "protocol_feature_congestion_control")]
+use crate::version::{ProtocolFeature, PROTOCOL_FEATURES_TO_VERSION_MAPPING};
 use crate::version::{ProtocolVersion, ProtocolVersionRange, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
 use reed_solomon_erasure::ReconstructShard;
 use std::sync::Arc;
 
+/// First protocol version with `ShardChunkHeaderV3`, the one of `ProtocolFeature::CongestionControl`.
+fn congestion_control_protocol_version() -> ProtocolVersion {
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    let version =
+        PROTOCOL_FEATURES_TO_VERSION_MAPPING.get(&ProtocolFeature::CongestionControl).copied();
+    #[cfg(not(feature = "protocol_feature_congestion_control"))]
+    let version = None;
+    version.unwrap_or(ProtocolVersion::MAX)
+}
+
 #[derive(
     BorshSerialize,
     BorshDeserialize,
@@ -162,10 +175,125 @@ impl ShardChunkHeaderV2 {
     }
 }
 
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct ShardChunkHeaderInnerV2 {
+    /// Previous block hash.
+    pub prev_block_hash: CryptoHash,
+    pub prev_state_root: StateRoot,
+    /// Root of the outcomes from execution transactions and results.
+    pub outcome_root: CryptoHash,
+    pub encoded_merkle_root: CryptoHash,
+    pub encoded_length: u64,
+    pub height_created: BlockHeight,
+    /// Shard index.
+    pub shard_id: ShardId,
+    /// Gas used in this chunk.
+    pub gas_used: Gas,
+    /// Gas limit voted by validators.
+    pub gas_limit: Gas,
+    /// Total balance burnt in previous chunk
+    pub balance_burnt: Balance,
+    /// Outgoing receipts merkle root.
+    pub outgoing_receipts_root: CryptoHash,
+    /// Tx merkle root.
+    pub tx_root: CryptoHash,
+    /// Validator proposals.
+    pub validator_proposals: Vec<ValidatorStake>,
+    /// Number of receipts in the delayed receipts queue of the shard after the previous chunk.
+    /// Other shards throttle the receipts they send to the shard when the queue is too long.
+    pub delayed_receipts_count: u64,
+}
+
+impl From<ShardChunkHeaderInnerV2> for ShardChunkHeaderInner {
+    fn from(inner: ShardChunkHeaderInnerV2) -> Self {
+        Self {
+            prev_block_hash: inner.prev_block_hash,
+            prev_state_root: inner.prev_state_root,
+            outcome_root: inner.outcome_root,
+            encoded_merkle_root: inner.encoded_merkle_root,
+            encoded_length: inner.encoded_length,
+            height_created: inner.height_created,
+            shard_id: inner.shard_id,
+            gas_used: inner.gas_used,
+            gas_limit: inner.gas_limit,
+            balance_burnt: inner.balance_burnt,
+            outgoing_receipts_root: inner.outgoing_receipts_root,
+            tx_root: inner.tx_root,
+            validator_proposals: inner.validator_proposals,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[borsh_init(init)]
+pub struct ShardChunkHeaderV3 {
+    pub inner: ShardChunkHeaderInnerV2,
+
+    pub height_included: BlockHeight,
+
+    /// Signature of the chunk producer.
+    pub signature: Signature,
+
+    #[borsh_skip]
+    pub hash: ChunkHash,
+}
+
+impl ShardChunkHeaderV3 {
+    pub fn init(&mut self) {
+        self.hash = Self::compute_hash(&self.inner);
+    }
+
+    pub fn compute_hash(inner: &ShardChunkHeaderInnerV2) -> ChunkHash {
+        let inner_bytes = inner.try_to_vec().expect("Failed to serialize");
+        let inner_hash = hash(&inner_bytes);
+
+        ChunkHash(combine_hash(inner_hash, inner.encoded_merkle_root))
+    }
+
+    pub fn new(
+        prev_block_hash: CryptoHash,
+        prev_state_root: StateRoot,
+        outcome_root: CryptoHash,
+        encoded_merkle_root: CryptoHash,
+        encoded_length: u64,
+        height: BlockHeight,
+        shard_id: ShardId,
+        gas_used: Gas,
+        gas_limit: Gas,
+        balance_burnt: Balance,
+        outgoing_receipts_root: CryptoHash,
+        tx_root: CryptoHash,
+        validator_proposals: Vec<ValidatorStake>,
+        delayed_receipts_count: u64,
+        signer: &dyn ValidatorSigner,
+    ) -> Self {
+        let inner = ShardChunkHeaderInnerV2 {
+            prev_block_hash,
+            prev_state_root,
+            outcome_root,
+            encoded_merkle_root,
+            encoded_length,
+            height_created: height,
+            shard_id,
+            gas_used,
+            gas_limit,
+            balance_burnt,
+            outgoing_receipts_root,
+            tx_root,
+            validator_proposals,
+            delayed_receipts_count,
+        };
+        let hash = Self::compute_hash(&inner);
+        let signature = signer.sign_chunk_hash(&hash);
+        Self { inner, height_included: 0, signature, hash }
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, PartialEq, Eq, Debug)]
 pub enum ShardChunkHeader {
     V1(ShardChunkHeaderV1),
     V2(ShardChunkHeaderV2),
+    V3(ShardChunkHeaderV3),
 }
 
 impl ShardChunkHeader {
@@ -174,15 +302,17 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner,
             Self::V2(header) => header.inner,
+            Self::V3(header) => header.inner.into(),
         }
     }
 
     pub fn inner_header_hash(&self) -> CryptoHash {
-        let inner = match self {
-            Self::V1(header) => &header.inner,
-            Self::V2(header) => &header.inner,
-        };
-        let inner_bytes = inner.try_to_vec().expect("Failed to serialize");
+        let inner_bytes = match self {
+            Self::V1(header) => header.inner.try_to_vec(),
+            Self::V2(header) => header.inner.try_to_vec(),
+            Self::V3(header) => header.inner.try_to_vec(),
+        }
+        .expect("Failed to serialize");
         hash(&inner_bytes)
     }
 
@@ -191,6 +321,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.height_created,
             Self::V2(header) => header.inner.height_created,
+            Self::V3(header) => header.inner.height_created,
         }
     }
 
@@ -199,6 +330,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => &header.signature,
             Self::V2(header) => &header.signature,
+            Self::V3(header) => &header.signature,
         }
     }
 
@@ -207,6 +339,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.height_included,
             Self::V2(header) => header.height_included,
+            Self::V3(header) => header.height_included,
         }
     }
 
@@ -215,6 +348,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => &mut header.height_included,
             Self::V2(header) => &mut header.height_included,
+            Self::V3(header) => &mut header.height_included,
         }
     }
 
@@ -223,6 +357,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => &header.inner.validator_proposals,
             Self::V2(header) => &header.inner.validator_proposals,
+            Self::V3(header) => &header.inner.validator_proposals,
         }
     }
 
@@ -231,6 +366,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.prev_state_root,
             Self::V2(header) => header.inner.prev_state_root,
+            Self::V3(header) => header.inner.prev_state_root,
         }
     }
 
@@ -239,6 +375,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.prev_block_hash,
             Self::V2(header) => header.inner.prev_block_hash,
+            Self::V3(header) => header.inner.prev_block_hash,
         }
     }
 
@@ -247,6 +384,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.encoded_merkle_root,
             Self::V2(header) => header.inner.encoded_merkle_root,
+            Self::V3(header) => header.inner.encoded_merkle_root,
         }
     }
 
@@ -255,6 +393,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.shard_id,
             Self::V2(header) => header.inner.shard_id,
+            Self::V3(header) => header.inner.shard_id,
         }
     }
 
@@ -263,6 +402,7 @@ impl ShardChunkHeader {
         match self {
             Self::V1(header) => header.inner.encoded_length,
             Self::V2(header) => header.inner.encoded_length,
+            Self::V3(header) => header.inner.encoded_length,
         }
     }
 
@@ -271,6 +411,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.gas_used,
             ShardChunkHeader::V2(header) => header.inner.gas_used,
+            ShardChunkHeader::V3(header) => header.inner.gas_used,
         }
     }
 
@@ -279,6 +420,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.gas_limit,
             ShardChunkHeader::V2(header) => header.inner.gas_limit,
+            ShardChunkHeader::V3(header) => header.inner.gas_limit,
         }
     }
 
@@ -287,6 +429,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.balance_burnt,
             ShardChunkHeader::V2(header) => header.inner.balance_burnt,
+            ShardChunkHeader::V3(header) => header.inner.balance_burnt,
         }
     }
 
@@ -295,6 +438,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.outgoing_receipts_root,
             ShardChunkHeader::V2(header) => header.inner.outgoing_receipts_root,
+            ShardChunkHeader::V3(header) => header.inner.outgoing_receipts_root,
         }
     }
 
@@ -303,6 +447,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.outcome_root,
             ShardChunkHeader::V2(header) => header.inner.outcome_root,
+            ShardChunkHeader::V3(header) => header.inner.outcome_root,
         }
     }
 
@@ -311,6 +456,7 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.inner.tx_root,
             ShardChunkHeader::V2(header) => header.inner.tx_root,
+            ShardChunkHeader::V3(header) => header.inner.tx_root,
         }
     }
 
@@ -319,6 +465,17 @@ impl ShardChunkHeader {
         match &self {
             ShardChunkHeader::V1(header) => header.hash.clone(),
             ShardChunkHeader::V2(header) => header.hash.clone(),
+            ShardChunkHeader::V3(header) => header.hash.clone(),
+        }
+    }
+
+    /// Number of receipts in the delayed receipts queue of the shard after the previous chunk.
+    /// Headers before `ProtocolFeature::CongestionControl` don't advertise it.
+    #[inline]
+    pub fn delayed_receipts_count(&self) -> u64 {
+        match &self {
+            ShardChunkHeader::V1(_) | ShardChunkHeader::V2(_) => 0,
+            ShardChunkHeader::V3(header) => header.inner.delayed_receipts_count,
         }
     }
 
@@ -327,8 +484,12 @@ impl ShardChunkHeader {
             ShardChunkHeader::V1(_) => {
                 ProtocolVersionRange::new(0, Some(SHARD_CHUNK_HEADER_UPGRADE_VERSION))
             }
-            ShardChunkHeader::V2(_) => {
-                ProtocolVersionRange::new(SHARD_CHUNK_HEADER_UPGRADE_VERSION, None)
+            ShardChunkHeader::V2(_) => ProtocolVersionRange::new(
+                SHARD_CHUNK_HEADER_UPGRADE_VERSION,
+                Some(congestion_control_protocol_version()),
+            ),
+            ShardChunkHeader::V3(_) => {
+                ProtocolVersionRange::new(congestion_control_protocol_version(), None)
             }
         }
     }
@@ -339,6 +500,43 @@ impl ShardChunkHeader {
 )]
 pub struct ChunkHashHeight(pub ChunkHash, pub BlockHeight);
 
+/// Sizes of the delayed receipts queues of all shards, as advertised by the chunk headers of a
+/// block. It's used to throttle receipts and transactions sent to congested shards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CongestionInfo {
+    /// Delayed receipts count of each shard, indexed by shard id.
+    pub delayed_receipts_counts: Vec<u64>,
+    /// Shard layout the shard ids refer to.
+    pub shard_layout: ShardLayout,
+}
+
+impl CongestionInfo {
+    pub fn from_chunk_headers<'a, T: IntoIterator<Item = &'a ShardChunkHeader>>(
+        chunks: T,
+        shard_layout: ShardLayout,
+    ) -> Self {
+        Self {
+            delayed_receipts_counts: chunks
+                .into_iter()
+                .map(|chunk| chunk.delayed_receipts_count())
+                .collect(),
+            shard_layout,
+        }
+    }
+
+    /// Congestion a shard is applied with in a block without a new chunk for it. No receipts are
+    /// sent without a chunk, so nothing is throttled and the buffered receipts stay in the state.
+    /// The replays of such blocks must use it as well to get the same state root.
+    pub fn missing_chunk() -> Self {
+        Self::default()
+    }
+
+    /// Returns 0 for shards without information.
+    pub fn delayed_receipts_count(&self, shard_id: ShardId) -> u64 {
+        self.delayed_receipts_counts.get(shard_id as usize).cloned().unwrap_or(0)
+    }
+}
+
 impl ShardChunkHeaderV1 {
     pub fn init(&mut self) {
         self.hash = Self::compute_hash(&self.inner);
@@ -408,7 +606,7 @@ impl PartialEncodedChunk {
             ShardChunkHeader::V1(header) => {
                 Self::V1(PartialEncodedChunkV1 { header, parts, receipts })
             }
-            header @ ShardChunkHeader::V2(_) => {
+            header @ ShardChunkHeader::V2(_) | header @ ShardChunkHeader::V3(_) => {
                 Self::V2(PartialEncodedChunkV2 { header, parts, receipts })
             }
         }
@@ -452,6 +650,7 @@ impl PartialEncodedChunk {
                 ShardChunkHeader::V1(header) => &header.inner.prev_block_hash,
 
                 ShardChunkHeader::V2(header) => &header.inner.prev_block_hash,
+                ShardChunkHeader::V3(header) => &header.inner.prev_block_hash,
             },
         }
     }
@@ -564,7 +763,7 @@ impl ShardChunk {
                     transactions: chunk.transactions,
                     receipts: chunk.receipts,
                 })),
-                ShardChunkHeader::V2(_) => None,
+                ShardChunkHeader::V2(_) | ShardChunkHeader::V3(_) => None,
             },
             Self::V2(chunk) => Some(ShardChunk::V2(ShardChunkV2 {
                 chunk_hash: header.chunk_hash(),
@@ -598,6 +797,14 @@ impl ShardChunk {
         }
     }
 
+    #[inline]
+    pub fn prev_block_hash(&self) -> CryptoHash {
+        match self {
+            Self::V1(chunk) => chunk.header.inner.prev_block_hash,
+            Self::V2(chunk) => chunk.header.prev_block_hash(),
+        }
+    }
+
     #[inline]
     pub fn prev_state_root(&self) -> StateRoot {
         match self {
@@ -819,6 +1026,7 @@ impl EncodedShardChunk {
         gas_used: Gas,
         gas_limit: Gas,
         balance_burnt: Balance,
+        delayed_receipts_count: u64,
 
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStake>,
@@ -863,6 +1071,7 @@ impl EncodedShardChunk {
             outgoing_receipts_root,
             tx_root,
             validator_proposals,
+            delayed_receipts_count,
             encoded_length as u64,
             parts,
             rs,
@@ -884,6 +1093,7 @@ impl EncodedShardChunk {
         outgoing_receipts_root: CryptoHash,
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStake>,
+        delayed_receipts_count: u64,
 
         encoded_length: u64,
         parts: Vec<Option<Box<[u8]>>>,
@@ -916,7 +1126,7 @@ impl EncodedShardChunk {
             );
             let chunk = EncodedShardChunkV1 { header, content };
             (Self::V1(chunk), merkle_paths)
-        } else {
+        } else if protocol_version < congestion_control_protocol_version() {
             let header = ShardChunkHeaderV2::new(
                 prev_block_hash,
                 prev_state_root,
@@ -935,6 +1145,26 @@ impl EncodedShardChunk {
             );
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V2(header), content };
             (Self::V2(chunk), merkle_paths)
+        } else {
+            let header = ShardChunkHeaderV3::new(
+                prev_block_hash,
+                prev_state_root,
+                outcome_root,
+                encoded_merkle_root,
+                encoded_length,
+                height,
+                shard_id,
+                gas_used,
+                gas_limit,
+                balance_burnt,
+                outgoing_receipts_root,
+                tx_root,
+                validator_proposals,
+                delayed_receipts_count,
+                signer,
+            );
+            let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V3(header), content };
+            (Self::V2(chunk), merkle_paths)
         }
     }
 
@@ -6,15 +6,17 @@ use near_crypto::PublicKey;
 
 use crate::account::{AccessKey, Account};
 use crate::hash::{hash, CryptoHash};
-use crate::receipt::{Receipt, ReceivedData};
+use crate::receipt::{Receipt, ReceiptSchedule, ReceivedData};
 use crate::serialize::{base64_format, option_base64_format};
 use crate::trie_key::col;
 use crate::trie_key::trie_key_parsers::{
     parse_account_id_from_access_key_key, parse_account_id_from_account_key,
-    parse_account_id_from_contract_code_key, parse_account_id_from_contract_data_key,
-    parse_account_id_from_received_data_key, parse_data_id_from_received_data_key,
-    parse_data_key_from_contract_data_key, parse_public_key_from_access_key_key,
+    parse_account_id_from_contract_abi_key, parse_account_id_from_contract_code_key,
+    parse_account_id_from_contract_data_key, parse_account_id_from_received_data_key,
+    parse_data_id_from_received_data_key, parse_data_key_from_contract_data_key,
+    parse_public_key_from_access_key_key, parse_trie_key_scheduled_receipt_from_raw_key,
 };
+use crate::trie_key::TrieKey;
 use crate::types::AccountId;
 
 /// Record in the state storage.
@@ -50,6 +52,15 @@ pub enum StateRecord {
     /// Delayed Receipt.
     /// The receipt was delayed because the shard was overwhelmed.
     DelayedReceipt(Box<Receipt>),
+    /// Scheduled Receipt.
+    /// The receipt is stored with the account that scheduled it until the schedule is due.
+    ScheduledReceipt { schedule: ReceiptSchedule, receipt: Box<Receipt> },
+    /// ABI of the contract deployed on the account, encoded in base64.
+    ContractAbi {
+        account_id: AccountId,
+        #[serde(with = "base64_format")]
+        abi: Vec<u8>,
+    },
 }
 
 impl StateRecord {
@@ -96,6 +107,28 @@ impl StateRecord {
                 Some(StateRecord::DelayedReceipt(Box::new(receipt)))
             }
             col::DELAYED_RECEIPT_INDICES => None,
+            col::SCHEDULED_RECEIPT_INDEX => None,
+            col::SCHEDULED_RECEIPT => {
+                let schedule = match parse_trie_key_scheduled_receipt_from_raw_key(&key).unwrap() {
+                    TrieKey::ScheduledReceipt { schedule, .. } => schedule,
+                    _ => unreachable!(),
+                };
+                let receipt = Receipt::try_from_slice(&value).unwrap();
+                Some(StateRecord::ScheduledReceipt { schedule, receipt: Box::new(receipt) })
+            }
+            col::CONTRACT_ABI => Some(StateRecord::ContractAbi {
+                account_id: parse_account_id_from_contract_abi_key(&key).unwrap(),
+                abi: value,
+            }),
+            col::BUFFERED_RECEIPT_INDICES => None,
+            col::DELETED_ACCOUNT => None,
+            col::CHARGED_DEPOSITS => None,
+            // Receipts buffered for a congested shard are dumped as delayed receipts of the
+            // receiver shard.
+            col::BUFFERED_RECEIPT => {
+                let receipt = Receipt::try_from_slice(&value).unwrap();
+                Some(StateRecord::DelayedReceipt(Box::new(receipt)))
+            }
             _ => unreachable!(),
         }
     }
@@ -129,6 +162,12 @@ impl Display for StateRecord {
             ),
             StateRecord::PostponedReceipt(receipt) => write!(f, "Postponed receipt {:?}", receipt),
             StateRecord::DelayedReceipt(receipt) => write!(f, "Delayed receipt {:?}", receipt),
+            StateRecord::ScheduledReceipt { schedule, receipt } => {
+                write!(f, "Scheduled receipt {:?}: {:?}", schedule, receipt)
+            }
+            StateRecord::ContractAbi { account_id, abi } => {
+                write!(f, "ABI for {:?}: {}", account_id, to_printable(&abi))
+            }
         }
     }
 }
@@ -1,13 +1,16 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::Serialize;
 
+use crate::block_header::BlockHeader;
+use crate::epoch_manager::EpochSyncData;
 use crate::hash::CryptoHash;
-use crate::merkle::MerklePath;
+use crate::merkle::{MerklePath, PartialMerkleTree};
 use crate::receipt::Receipt;
 use crate::sharding::{
     ReceiptProof, ShardChunk, ShardChunkHeader, ShardChunkHeaderV1, ShardChunkV1,
 };
-use crate::types::{BlockHeight, ShardId, StateRoot, StateRootNode};
+use crate::types::{BlockHeight, EpochId, ShardId, StateRoot, StateRootNode};
+use crate::views::LightClientBlockView;
 
 #[derive(PartialEq, Eq, Clone, Debug, BorshSerialize, BorshDeserialize, Serialize)]
 pub struct ReceiptResponse(pub CryptoHash, pub Vec<Receipt>);
@@ -41,6 +44,9 @@ pub struct ShardStateSyncResponseHeaderV2 {
     pub chunk_proof: MerklePath,
     pub prev_chunk_header: Option<ShardChunkHeader>,
     pub prev_chunk_proof: Option<MerklePath>,
+    /// Chunk headers of the block before the one including `chunk`. The delayed receipts counts
+    /// they advertise are the congestion `chunk` is applied with.
+    pub prev_block_chunk_headers: Vec<ShardChunkHeader>,
     pub incoming_receipts_proofs: Vec<ReceiptProofResponse>,
     pub root_proofs: Vec<Vec<RootProof>>,
     pub state_root_node: StateRootNode,
@@ -109,6 +115,15 @@ impl ShardStateSyncResponseHeader {
         }
     }
 
+    /// Chunk headers of the previous block, unknown for the headers that predate congestion.
+    #[inline]
+    pub fn prev_block_chunk_headers(&self) -> Option<&Vec<ShardChunkHeader>> {
+        match self {
+            Self::V1(_) => None,
+            Self::V2(header) => Some(&header.prev_block_chunk_headers),
+        }
+    }
+
     #[inline]
     pub fn incoming_receipts_proofs(&self) -> &Vec<ReceiptProofResponse> {
         match self {
@@ -188,6 +203,29 @@ impl ShardStateSyncResponseV1 {
     }
 }
 
+/// Response to the epoch sync request for a given epoch.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize)]
+pub struct EpochSyncResponse {
+    pub epoch_id: EpochId,
+    /// Light client block of the last final block of the requested epoch, which proves the
+    /// block producers of the next epoch. `None` if the requested epoch is not finished yet.
+    pub light_client_block: Option<LightClientBlockView>,
+}
+
+/// Data needed to start syncing headers and state from the first block of an epoch without
+/// knowing the headers before it.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize)]
+pub struct EpochSyncFinalizationResponse {
+    /// Headers from the last final block of the previous epoch to the first block of the
+    /// requested epoch and the block after it, which approves the first block, in increasing
+    /// height order.
+    pub headers: Vec<BlockHeader>,
+    /// Block merkle tree of the first of `headers`.
+    pub first_block_merkle_tree: PartialMerkleTree,
+    /// Epoch manager records committed in the first block of the requested epoch.
+    pub epoch_sync_data: EpochSyncData,
+}
+
 pub fn get_num_state_parts(memory_usage: u64) -> u64 {
     // We assume that 1 Mb is a good limit for state part size.
     // On the other side, it's important to divide any state into
@@ -195,3 +233,13 @@ pub fn get_num_state_parts(memory_usage: u64) -> u64 {
     // TODO #1708
     memory_usage / (1024 * 1024) + 3
 }
+
+/// Path of the dumped state part relative to the root of the state parts location.
+pub fn get_state_part_file_path(
+    sync_hash: &CryptoHash,
+    shard_id: ShardId,
+    part_id: u64,
+    num_parts: u64,
+) -> String {
+    format!("{}/shard_{}/state_part_{:06}_of_{:06}", sync_hash, shard_id, part_id, num_parts)
+}
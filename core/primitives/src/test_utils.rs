@@ -1,8 +1,10 @@
+use borsh::BorshSerialize;
+
 use near_crypto::{EmptySigner, PublicKey, Signature, Signer};
 
 use crate::account::{AccessKey, AccessKeyPermission, Account};
 use crate::block::Block;
-use crate::block_header::{BlockHeader, BlockHeaderV2};
+use crate::block_header::{BlockHeader, BlockHeaderInnerLite, BlockHeaderInnerRestV2};
 use crate::errors::EpochError;
 use crate::hash::CryptoHash;
 use crate::merkle::PartialMerkleTree;
@@ -243,11 +245,52 @@ impl SignedTransaction {
     }
 }
 
+/// Mutable references to the fields of a `BlockHeaderV2` or a `BlockHeaderV3`.
+pub struct BlockHeaderMut<'a> {
+    pub prev_hash: &'a mut CryptoHash,
+    pub inner_lite: &'a mut BlockHeaderInnerLite,
+    pub inner_rest: &'a mut BlockHeaderInnerRestV2,
+    /// `None` for `BlockHeaderV2`.
+    pub epoch_sync_data_hash: Option<&'a mut Option<CryptoHash>>,
+    pub signature: &'a mut Signature,
+    pub hash: &'a mut CryptoHash,
+}
+
+impl BlockHeaderMut<'_> {
+    /// Recomputes the hash of the header.
+    pub fn init(&mut self) {
+        let mut inner_rest = self.inner_rest.try_to_vec().expect("Failed to serialize");
+        if let Some(epoch_sync_data_hash) = &self.epoch_sync_data_hash {
+            epoch_sync_data_hash.serialize(&mut inner_rest).expect("Failed to serialize");
+        }
+        *self.hash = BlockHeader::compute_hash(
+            *self.prev_hash,
+            &self.inner_lite.try_to_vec().expect("Failed to serialize"),
+            &inner_rest,
+        );
+    }
+}
+
 impl BlockHeader {
-    pub fn get_mut(&mut self) -> &mut BlockHeaderV2 {
+    pub fn get_mut(&mut self) -> BlockHeaderMut<'_> {
         match self {
             BlockHeader::BlockHeaderV1(_) => panic!("old header should not appear in tests"),
-            BlockHeader::BlockHeaderV2(header) => header,
+            BlockHeader::BlockHeaderV2(header) => BlockHeaderMut {
+                prev_hash: &mut header.prev_hash,
+                inner_lite: &mut header.inner_lite,
+                inner_rest: &mut header.inner_rest,
+                epoch_sync_data_hash: None,
+                signature: &mut header.signature,
+                hash: &mut header.hash,
+            },
+            BlockHeader::BlockHeaderV3(header) => BlockHeaderMut {
+                prev_hash: &mut header.prev_hash,
+                inner_lite: &mut header.inner_lite,
+                inner_rest: &mut header.inner_rest,
+                epoch_sync_data_hash: Some(&mut header.epoch_sync_data_hash),
+                signature: &mut header.signature,
+                hash: &mut header.hash,
+            },
         }
     }
 
@@ -257,9 +300,9 @@ impl BlockHeader {
             &self.inner_lite_bytes(),
             &self.inner_rest_bytes(),
         );
-        let mut header = self.get_mut();
-        header.hash = hash;
-        header.signature = signature;
+        let header = self.get_mut();
+        *header.hash = hash;
+        *header.signature = signature;
     }
 }
 
@@ -278,7 +321,7 @@ impl Block {
                     .into_iter()
                     .map(|chunk| match chunk {
                         ShardChunkHeader::V1(header) => header,
-                        ShardChunkHeader::V2(_) => {
+                        ShardChunkHeader::V2(_) | ShardChunkHeader::V3(_) => {
                             panic!("Attempted to set V1 block chunks with V2")
                         }
                     })
@@ -393,6 +436,7 @@ impl Block {
             signer,
             next_bp_hash,
             block_merkle_root,
+            None,
         )
     }
 }
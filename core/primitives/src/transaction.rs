@@ -13,7 +13,7 @@ use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
 use crate::serialize::{base64_format, u128_dec_format, u128_dec_format_compatible};
-use crate::types::{AccountId, Balance, Gas, Nonce};
+use crate::types::{AccountId, Balance, BlockHeight, Gas, Nonce};
 
 pub type LogEntry = String;
 
@@ -56,12 +56,29 @@ pub enum Action {
     AddKey(AddKeyAction),
     DeleteKey(DeleteKeyAction),
     DeleteAccount(DeleteAccountAction),
+    /// Sets a Wasm code to a receiver_id and calls a migration method of the new code in the same
+    /// action. If the migration fails, the old code and state are kept.
+    UpgradeContract(UpgradeContractAction),
+    /// Actions signed by `sender_id` that are executed on its behalf, while the gas is paid by the
+    /// signer of the transaction (the relayer).
+    Delegate(SignedDelegateAction),
+    /// Sets a Wasm code to a receiver_id together with the ABI describing the contract methods.
+    DeployContractWithAbi(DeployContractWithAbiAction),
 }
 
 impl Action {
     pub fn get_prepaid_gas(&self) -> Gas {
         match self {
             Action::FunctionCall(a) => a.gas,
+            Action::UpgradeContract(a) => a.gas,
+            // The inner actions aren't validated yet when estimating fees, so the sum saturates
+            // instead of overflowing.
+            Action::Delegate(a) => a
+                .delegate_action
+                .actions
+                .iter()
+                .try_fold(0 as Gas, |acc, action| acc.checked_add(action.get_prepaid_gas()))
+                .unwrap_or(Gas::max_value()),
             _ => 0,
         }
     }
@@ -106,6 +123,64 @@ impl fmt::Debug for DeployContractAction {
     }
 }
 
+/// Deploy contract action that also stores the contract ABI. The ABI is an opaque blob for the
+/// runtime, by convention it's a JSON document that lists the contract methods, see
+/// `views::ContractAbiView`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeployContractWithAbiAction {
+    /// WebAssembly binary
+    #[serde(with = "base64_format")]
+    pub code: Vec<u8>,
+    #[serde(with = "base64_format")]
+    pub abi: Vec<u8>,
+}
+
+impl From<DeployContractWithAbiAction> for Action {
+    fn from(deploy_contract_with_abi_action: DeployContractWithAbiAction) -> Self {
+        Self::DeployContractWithAbi(deploy_contract_with_abi_action)
+    }
+}
+
+impl fmt::Debug for DeployContractWithAbiAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeployContractWithAbiAction")
+            .field("code", &format_args!("{}", logging::pretty_utf8(&self.code)))
+            .field("abi", &format_args!("{}", logging::pretty_utf8(&self.abi)))
+            .finish()
+    }
+}
+
+/// Upgrade contract action: deploys the code and runs a migration method of the new code.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct UpgradeContractAction {
+    /// WebAssembly binary
+    #[serde(with = "base64_format")]
+    pub code: Vec<u8>,
+    /// Method of the new code that migrates the existing contract state.
+    pub method_name: String,
+    #[serde(with = "base64_format")]
+    pub args: Vec<u8>,
+    /// Gas attached to the migration method call.
+    pub gas: Gas,
+}
+
+impl From<UpgradeContractAction> for Action {
+    fn from(upgrade_contract_action: UpgradeContractAction) -> Self {
+        Self::UpgradeContract(upgrade_contract_action)
+    }
+}
+
+impl fmt::Debug for UpgradeContractAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpgradeContractAction")
+            .field("code", &format_args!("{}", logging::pretty_utf8(&self.code)))
+            .field("method_name", &format_args!("{}", &self.method_name))
+            .field("args", &format_args!("{}", logging::pretty_utf8(&self.args)))
+            .field("gas", &format_args!("{}", &self.gas))
+            .finish()
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct FunctionCallAction {
     pub method_name: String,
@@ -198,6 +273,61 @@ impl From<DeleteAccountAction> for Action {
     }
 }
 
+/// Actions that `sender_id` wants to execute on `receiver_id` without paying for gas.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct DelegateAction {
+    /// Account on whose behalf the actions are executed.
+    pub sender_id: AccountId,
+    /// Receiver of the actions.
+    pub receiver_id: AccountId,
+    /// Actions to execute. Can't contain other delegate actions.
+    pub actions: Vec<Action>,
+    /// Nonce of the `sender_id` access key, works the same way as the transaction nonce.
+    pub nonce: Nonce,
+    /// The actions can't be executed after this block height.
+    pub max_block_height: BlockHeight,
+    /// Public key of the `sender_id` access key used to sign the delegate action.
+    pub public_key: PublicKey,
+}
+
+/// Prefix of the signed delegate action bytes. Borsh-serialized transactions start with the
+/// length of the signer id, which can't be that large, so a signature of a delegate action can't
+/// be replayed as a signature of a transaction and vice versa.
+pub const DELEGATE_ACTION_SIGNATURE_PREFIX: u32 = (1 << 30) + 366;
+
+impl DelegateAction {
+    /// Computes a hash of the delegate action for signing.
+    pub fn get_hash(&self) -> CryptoHash {
+        let mut bytes = DELEGATE_ACTION_SIGNATURE_PREFIX.try_to_vec().expect("Failed to serialize");
+        self.serialize(&mut bytes).expect("Failed to serialize");
+        hash(&bytes)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SignedDelegateAction {
+    pub delegate_action: DelegateAction,
+    pub signature: Signature,
+}
+
+impl SignedDelegateAction {
+    pub fn new(signature: Signature, delegate_action: DelegateAction) -> Self {
+        Self { delegate_action, signature }
+    }
+
+    /// Checks that the delegate action is signed with its `public_key`.
+    pub fn verify(&self) -> bool {
+        self.signature
+            .verify(self.delegate_action.get_hash().as_ref(), &self.delegate_action.public_key)
+    }
+}
+
+impl From<SignedDelegateAction> for Action {
+    fn from(signed_delegate_action: SignedDelegateAction) -> Self {
+        Self::Delegate(signed_delegate_action)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Eq, Debug, Clone)]
 #[borsh_init(init)]
 pub struct SignedTransaction {
@@ -490,6 +620,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_delegate_action_signature_is_domain_separated() {
+        let signer = InMemorySigner::from_seed("test.near", KeyType::ED25519, "test.near");
+        let delegate_action = DelegateAction {
+            sender_id: "test.near".to_string(),
+            receiver_id: "123".to_string(),
+            actions: vec![Action::Transfer(TransferAction { deposit: 1 })],
+            nonce: 1,
+            max_block_height: 100,
+            public_key: signer.public_key(),
+        };
+        let plain_hash = hash(&delegate_action.try_to_vec().unwrap());
+        assert_ne!(delegate_action.get_hash(), plain_hash);
+
+        // A signature of the plain serialization doesn't authorize the delegate action.
+        let signature = signer.sign(plain_hash.as_ref());
+        assert!(!SignedDelegateAction::new(signature, delegate_action.clone()).verify());
+        let signature = signer.sign(delegate_action.get_hash().as_ref());
+        assert!(SignedDelegateAction::new(signature, delegate_action).verify());
+    }
+
+    #[test]
+    fn test_delegate_action_prepaid_gas_saturates() {
+        let function_call = |gas| {
+            Action::FunctionCall(FunctionCallAction {
+                method_name: "f".to_string(),
+                args: vec![],
+                gas,
+                deposit: 0,
+            })
+        };
+        let delegate = |actions| {
+            Action::Delegate(SignedDelegateAction::new(
+                Signature::empty(KeyType::ED25519),
+                DelegateAction {
+                    sender_id: "test.near".to_string(),
+                    receiver_id: "123".to_string(),
+                    actions,
+                    nonce: 1,
+                    max_block_height: 100,
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                },
+            ))
+        };
+        assert_eq!(delegate(vec![function_call(1), function_call(2)]).get_prepaid_gas(), 3);
+        assert_eq!(
+            delegate(vec![function_call(Gas::max_value()), function_call(1)]).get_prepaid_gas(),
+            Gas::max_value()
+        );
+    }
+
     #[test]
     fn test_outcome_to_hashes() {
         let outcome = ExecutionOutcome {
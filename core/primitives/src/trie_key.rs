@@ -1,5 +1,6 @@
 use crate::hash::CryptoHash;
-use crate::types::AccountId;
+use crate::receipt::ReceiptSchedule;
+use crate::types::{AccountId, ShardId};
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::PublicKey;
 use std::mem::size_of;
@@ -35,8 +36,31 @@ pub(crate) mod col {
     pub const DELAYED_RECEIPT: &[u8] = &[8];
     /// This column id is used when storing Key-Value data from a contract on an `account_id`.
     pub const CONTRACT_DATA: &[u8] = &[9];
+    /// This column id is used when storing `primitives::receipt::ReceiptSchedule` of a scheduled
+    /// receipt for a given `account_id` that scheduled it and a given `receipt_id`.
+    pub const SCHEDULED_RECEIPT_INDEX: &[u8] = &[10];
+    /// This column id is used when storing scheduled receipts (`primitives::receipt::Receipt`)
+    /// ordered by the block height or the block timestamp when they are due.
+    pub const SCHEDULED_RECEIPT: &[u8] = &[11];
+    /// This column id is used when storing the ABI blob of the contract deployed on a given
+    /// `account_id`.
+    pub const CONTRACT_ABI: &[u8] = &[12];
+    /// This column id is used when storing the indices of the queue of receipts buffered for
+    /// a given receiver shard, because the receiver shard is congested.
+    pub const BUFFERED_RECEIPT_INDICES: &[u8] = &[13];
+    /// This column id is used when storing receipts buffered for a given receiver shard.
+    pub const BUFFERED_RECEIPT: &[u8] = &[14];
+    /// This column id is used when marking deleted accounts whose contract data is not removed
+    /// from the state yet.
+    pub const DELETED_ACCOUNT: &[u8] = &[15];
+    /// This column id is used when storing the deposits charged from the deposit allowances of
+    /// the access keys of a given `account_id`, which can still be refunded.
+    pub const CHARGED_DEPOSITS: &[u8] = &[16];
 }
 
+/// Size of the serialized kind of a `ReceiptSchedule` followed by the big-endian due value.
+const RECEIPT_SCHEDULE_LEN: usize = 1 + size_of::<u64>();
+
 /// Describes the key of a specific key-value record in a state trie.
 #[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
 pub enum TrieKey {
@@ -72,6 +96,28 @@ pub enum TrieKey {
     /// Used to store a key-value record `Vec<u8>` within a contract deployed on a given `AccountId`
     /// and a given key.
     ContractData { account_id: AccountId, key: Vec<u8> },
+    /// Used to store the `primitives::receipt::ReceiptSchedule` of a scheduled receipt for a given
+    /// `AccountId` that scheduled it and a given `receipt_id` of the receipt. It allows to find
+    /// the receipt when it's cancelled.
+    ScheduledReceiptIndex { account_id: AccountId, receipt_id: CryptoHash },
+    /// Used to store a scheduled receipt `primitives::receipt::Receipt` for a given schedule and
+    /// a given `receipt_id` of the receipt. Keys are ordered by the schedule kind and then by the
+    /// block height or timestamp, so due receipts can be found by iterating from the beginning.
+    ScheduledReceipt { schedule: ReceiptSchedule, receipt_id: CryptoHash },
+    /// Used to store `Vec<u8>` ABI of the contract deployed on a given `AccountId`.
+    ContractAbi { account_id: AccountId },
+    /// Used to store indices (`primitives::receipt::DelayedReceiptIndices`) of the queue of
+    /// outgoing receipts buffered for a given receiver `ShardId`.
+    BufferedReceiptIndices { receiver_shard_id: ShardId },
+    /// Used to store an outgoing receipt `primitives::receipt::Receipt` for a given index `u64`
+    /// in the buffered receipts queue of a given receiver `ShardId`.
+    BufferedReceipt { receiver_shard_id: ShardId, index: u64 },
+    /// Used to mark a deleted `AccountId` whose contract data is still being removed. Stores the
+    /// `BlockHeight` at which the account was deleted.
+    DeletedAccount { account_id: AccountId },
+    /// Used to store `Vec<primitives::account::ChargedDeposit>` of the deposits charged from the
+    /// deposit allowances of the access keys of a given `AccountId`.
+    ChargedDeposits { account_id: AccountId },
 }
 
 impl TrieKey {
@@ -114,6 +160,26 @@ impl TrieKey {
                     + ACCOUNT_DATA_SEPARATOR.len()
                     + key.len()
             }
+            TrieKey::ScheduledReceiptIndex { account_id, receipt_id } => {
+                col::SCHEDULED_RECEIPT_INDEX.len()
+                    + account_id.len()
+                    + ACCOUNT_DATA_SEPARATOR.len()
+                    + receipt_id.as_ref().len()
+            }
+            TrieKey::ScheduledReceipt { receipt_id, .. } => {
+                col::SCHEDULED_RECEIPT.len() + RECEIPT_SCHEDULE_LEN + receipt_id.as_ref().len()
+            }
+            TrieKey::ContractAbi { account_id } => col::CONTRACT_ABI.len() + account_id.len(),
+            TrieKey::BufferedReceiptIndices { .. } => {
+                col::BUFFERED_RECEIPT_INDICES.len() + size_of::<ShardId>()
+            }
+            TrieKey::BufferedReceipt { .. } => {
+                col::BUFFERED_RECEIPT.len() + size_of::<ShardId>() + size_of::<u64>()
+            }
+            TrieKey::DeletedAccount { account_id } => col::DELETED_ACCOUNT.len() + account_id.len(),
+            TrieKey::ChargedDeposits { account_id } => {
+                col::CHARGED_DEPOSITS.len() + account_id.len()
+            }
         }
     }
 
@@ -172,12 +238,51 @@ impl TrieKey {
                 res.extend(ACCOUNT_DATA_SEPARATOR);
                 res.extend(key);
             }
+            TrieKey::ScheduledReceiptIndex { account_id, receipt_id } => {
+                res.extend(col::SCHEDULED_RECEIPT_INDEX);
+                res.extend(account_id.as_bytes());
+                res.extend(ACCOUNT_DATA_SEPARATOR);
+                res.extend(receipt_id.as_ref());
+            }
+            TrieKey::ScheduledReceipt { schedule, receipt_id } => {
+                res.extend(trie_key_parsers::get_raw_prefix_for_scheduled_receipts(schedule));
+                res.extend(&schedule_due_value(schedule).to_be_bytes());
+                res.extend(receipt_id.as_ref());
+            }
+            TrieKey::ContractAbi { account_id } => {
+                res.extend(col::CONTRACT_ABI);
+                res.extend(account_id.as_bytes());
+            }
+            TrieKey::BufferedReceiptIndices { receiver_shard_id } => {
+                res.extend(col::BUFFERED_RECEIPT_INDICES);
+                res.extend(&receiver_shard_id.to_le_bytes());
+            }
+            TrieKey::BufferedReceipt { receiver_shard_id, index } => {
+                res.extend(col::BUFFERED_RECEIPT);
+                res.extend(&receiver_shard_id.to_le_bytes());
+                res.extend(&index.to_le_bytes());
+            }
+            TrieKey::DeletedAccount { account_id } => {
+                res.extend(col::DELETED_ACCOUNT);
+                res.extend(account_id.as_bytes());
+            }
+            TrieKey::ChargedDeposits { account_id } => {
+                res.extend(col::CHARGED_DEPOSITS);
+                res.extend(account_id.as_bytes());
+            }
         };
         debug_assert_eq!(res.len(), expected_len);
         res
     }
 }
 
+fn schedule_due_value(schedule: &ReceiptSchedule) -> u64 {
+    match schedule {
+        ReceiptSchedule::BlockHeight(block_height) => *block_height,
+        ReceiptSchedule::BlockTimestamp(block_timestamp) => *block_timestamp,
+    }
+}
+
 // TODO: Remove once we switch to non-raw keys everywhere.
 pub mod trie_key_parsers {
     use super::*;
@@ -302,6 +407,93 @@ pub mod trie_key_parsers {
         })?))
     }
 
+    pub fn parse_account_id_from_contract_abi_key(
+        raw_key: &[u8],
+    ) -> Result<AccountId, std::io::Error> {
+        let account_id = parse_account_id_prefix(col::CONTRACT_ABI, raw_key)?;
+        Ok(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId to be TrieKey::ContractAbi",
+            )
+        })?))
+    }
+
+    pub fn parse_account_id_from_deleted_account_key(
+        raw_key: &[u8],
+    ) -> Result<AccountId, std::io::Error> {
+        let account_id = parse_account_id_prefix(col::DELETED_ACCOUNT, raw_key)?;
+        Ok(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId to be TrieKey::DeletedAccount",
+            )
+        })?))
+    }
+
+    pub fn parse_account_id_from_charged_deposits_key(
+        raw_key: &[u8],
+    ) -> Result<AccountId, std::io::Error> {
+        let account_id = parse_account_id_prefix(col::CHARGED_DEPOSITS, raw_key)?;
+        Ok(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId to be TrieKey::ChargedDeposits",
+            )
+        })?))
+    }
+
+    /// Returns the account the record with the given raw key belongs to, or `None` if the record
+    /// belongs to the whole shard, like the queues of delayed, buffered and scheduled receipts.
+    pub fn parse_account_id_from_raw_key(
+        raw_key: &[u8],
+    ) -> Result<Option<AccountId>, std::io::Error> {
+        let (column, rest) = raw_key.split_first().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "raw key is empty")
+        })?;
+        let is_column = |columns: &[&[u8]]| columns.iter().any(|c| c[0] == *column);
+        let separator = if is_column(&[
+            col::ACCOUNT,
+            col::CONTRACT_CODE,
+            col::CONTRACT_ABI,
+            col::DELETED_ACCOUNT,
+            col::CHARGED_DEPOSITS,
+        ]) {
+            None
+        } else if is_column(&[col::ACCESS_KEY]) {
+            Some(col::ACCESS_KEY[0])
+        } else if is_column(&[
+            col::RECEIVED_DATA,
+            col::POSTPONED_RECEIPT_ID,
+            col::PENDING_DATA_COUNT,
+            col::POSTPONED_RECEIPT,
+            col::CONTRACT_DATA,
+            col::SCHEDULED_RECEIPT_INDEX,
+        ]) {
+            Some(ACCOUNT_DATA_SEPARATOR[0])
+        } else {
+            return Ok(None);
+        };
+        let account_id = match separator {
+            None => rest,
+            Some(separator) => match rest.iter().position(|c| *c == separator) {
+                Some(position) => &rest[..position],
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "raw key does not have a separator after the AccountId",
+                    ))
+                }
+            },
+        };
+        Ok(Some(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId",
+            )
+        })?)))
+    }
+
     pub fn parse_trie_key_access_key_from_raw_key(
         raw_key: &[u8],
     ) -> Result<TrieKey, std::io::Error> {
@@ -378,10 +570,90 @@ pub mod trie_key_parsers {
         res.extend(prefix);
         res
     }
+
+    pub fn get_raw_prefix_for_deleted_accounts() -> Vec<u8> {
+        col::DELETED_ACCOUNT.to_vec()
+    }
+
+    /// Returns the common prefix of the keys of all scheduled receipts that have the same kind of
+    /// schedule as the given one, regardless of the block height or timestamp it's due at.
+    pub fn get_raw_prefix_for_scheduled_receipts(schedule: &ReceiptSchedule) -> Vec<u8> {
+        let mut res = Vec::with_capacity(col::SCHEDULED_RECEIPT.len() + 1);
+        res.extend(col::SCHEDULED_RECEIPT);
+        res.push(match schedule {
+            ReceiptSchedule::BlockHeight(_) => 0,
+            ReceiptSchedule::BlockTimestamp(_) => 1,
+        });
+        res
+    }
+
+    pub fn get_raw_prefix_for_scheduled_receipt_index(account_id: &AccountId) -> Vec<u8> {
+        let mut res = Vec::with_capacity(
+            col::SCHEDULED_RECEIPT_INDEX.len() + account_id.len() + ACCOUNT_DATA_SEPARATOR.len(),
+        );
+        res.extend(col::SCHEDULED_RECEIPT_INDEX);
+        res.extend(account_id.as_bytes());
+        res.extend(ACCOUNT_DATA_SEPARATOR);
+        res
+    }
+
+    pub fn parse_receipt_id_from_scheduled_receipt_index_key(
+        raw_key: &[u8],
+        account_id: &AccountId,
+    ) -> Result<CryptoHash, std::io::Error> {
+        let prefix_len =
+            col::SCHEDULED_RECEIPT_INDEX.len() + account_id.len() + ACCOUNT_DATA_SEPARATOR.len();
+        if raw_key.len() < prefix_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key is too short for TrieKey::ScheduledReceiptIndex",
+            ));
+        }
+        CryptoHash::try_from(&raw_key[prefix_len..]).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Can't parse CryptoHash for TrieKey::ScheduledReceiptIndex",
+            )
+        })
+    }
+
+    pub fn parse_trie_key_scheduled_receipt_from_raw_key(
+        raw_key: &[u8],
+    ) -> Result<TrieKey, std::io::Error> {
+        let schedule_and_id = parse_account_id_prefix(col::SCHEDULED_RECEIPT, raw_key)?;
+        if schedule_and_id.len() != RECEIPT_SCHEDULE_LEN + size_of::<CryptoHash>() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key has invalid length for TrieKey::ScheduledReceipt",
+            ));
+        }
+        let mut due = [0u8; size_of::<u64>()];
+        due.copy_from_slice(&schedule_and_id[1..RECEIPT_SCHEDULE_LEN]);
+        let due = u64::from_be_bytes(due);
+        let schedule = match schedule_and_id[0] {
+            0 => ReceiptSchedule::BlockHeight(due),
+            1 => ReceiptSchedule::BlockTimestamp(due),
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "raw key has unknown schedule kind for TrieKey::ScheduledReceipt",
+                ))
+            }
+        };
+        let receipt_id =
+            CryptoHash::try_from(&schedule_and_id[RECEIPT_SCHEDULE_LEN..]).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Can't parse CryptoHash for TrieKey::ScheduledReceipt",
+                )
+            })?;
+        Ok(TrieKey::ScheduledReceipt { schedule, receipt_id })
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::hash::hash;
     use near_crypto::KeyType;
 
     use super::*;
@@ -469,6 +741,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_for_scheduled_receipt_index_consistency() {
+        let receipt_id = hash(b"receipt");
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::ScheduledReceiptIndex { account_id: account_id.clone(), receipt_id };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert!(raw_key.starts_with(
+                &trie_key_parsers::get_raw_prefix_for_scheduled_receipt_index(&account_id)
+            ));
+            assert_eq!(
+                trie_key_parsers::parse_receipt_id_from_scheduled_receipt_index_key(
+                    &raw_key,
+                    &account_id
+                )
+                .unwrap(),
+                receipt_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_for_scheduled_receipt_consistency() {
+        let receipt_id = CryptoHash::default();
+        for schedule in vec![
+            ReceiptSchedule::BlockHeight(0),
+            ReceiptSchedule::BlockHeight(u64::max_value()),
+            ReceiptSchedule::BlockTimestamp(1_600_000_000_000_000_000),
+        ] {
+            let key = TrieKey::ScheduledReceipt { schedule, receipt_id };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert!(raw_key
+                .starts_with(&trie_key_parsers::get_raw_prefix_for_scheduled_receipts(&schedule)));
+            assert_eq!(
+                trie_key_parsers::parse_trie_key_scheduled_receipt_from_raw_key(&raw_key).unwrap(),
+                key
+            );
+        }
+        let earlier = TrieKey::ScheduledReceipt {
+            schedule: ReceiptSchedule::BlockHeight(255),
+            receipt_id: CryptoHash::default(),
+        };
+        let later =
+            TrieKey::ScheduledReceipt { schedule: ReceiptSchedule::BlockHeight(256), receipt_id };
+        assert!(earlier.to_vec() < later.to_vec());
+    }
+
     #[test]
     fn test_key_for_code_consistency() {
         for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
@@ -481,4 +801,97 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_key_for_contract_abi_consistency() {
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::ContractAbi { account_id: account_id.clone() };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_contract_abi_key(&raw_key).unwrap(),
+                account_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_for_buffered_receipt_consistency() {
+        let indices_key = TrieKey::BufferedReceiptIndices { receiver_shard_id: 3 };
+        assert_eq!(indices_key.to_vec().len(), indices_key.len());
+        let key = TrieKey::BufferedReceipt { receiver_shard_id: 3, index: 7 };
+        let raw_key = key.to_vec();
+        assert_eq!(raw_key.len(), key.len());
+        assert_ne!(raw_key, TrieKey::BufferedReceipt { receiver_shard_id: 4, index: 7 }.to_vec());
+    }
+
+    #[test]
+    fn test_parse_account_id_from_raw_key() {
+        let hash = CryptoHash::default();
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            for key in vec![
+                TrieKey::Account { account_id: account_id.clone() },
+                TrieKey::ContractCode { account_id: account_id.clone() },
+                TrieKey::AccessKey {
+                    account_id: account_id.clone(),
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                },
+                TrieKey::ReceivedData { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PostponedReceiptId { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PendingDataCount { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::PostponedReceipt { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::ContractData { account_id: account_id.clone(), key: b",\x02".to_vec() },
+                TrieKey::ScheduledReceiptIndex { account_id: account_id.clone(), receipt_id: hash },
+                TrieKey::ContractAbi { account_id: account_id.clone() },
+                TrieKey::DeletedAccount { account_id: account_id.clone() },
+                TrieKey::ChargedDeposits { account_id: account_id.clone() },
+            ] {
+                assert_eq!(
+                    trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                    Some(account_id.clone())
+                );
+            }
+        }
+        for key in vec![
+            TrieKey::DelayedReceiptIndices,
+            TrieKey::DelayedReceipt { index: 1 },
+            TrieKey::ScheduledReceipt {
+                schedule: ReceiptSchedule::BlockHeight(1),
+                receipt_id: hash,
+            },
+            TrieKey::BufferedReceiptIndices { receiver_shard_id: 1 },
+            TrieKey::BufferedReceipt { receiver_shard_id: 1, index: 1 },
+        ] {
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_for_deleted_account_consistency() {
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::DeletedAccount { account_id: account_id.clone() };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_deleted_account_key(&raw_key).unwrap(),
+                account_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_for_charged_deposits_consistency() {
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::ChargedDeposits { account_id: account_id.clone() };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_charged_deposits_key(&raw_key).unwrap(),
+                account_id
+            );
+        }
+    }
 }
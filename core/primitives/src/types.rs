@@ -182,6 +182,13 @@ pub enum StateChangeCause {
     /// State change that happens when we update validator accounts. Not associated with with any
     /// specific transaction or receipt.
     ValidatorAccountsUpdate,
+    /// Scheduled receipts that became due were removed from the state and sent to the receivers.
+    ReleasedScheduledReceipts,
+    /// Updated queues of outgoing receipts buffered for congested receiver shards.
+    /// We either forwarded previously buffered receipts or buffered more outgoing receipts.
+    UpdatedBufferedReceipts,
+    /// Contract data of previously deleted accounts was removed from the state.
+    DeletedAccountsCleanup,
 }
 
 /// This represents the committed changes in the Trie with a change cause.
@@ -517,7 +524,7 @@ impl From<Finality> for BlockReference {
     }
 }
 
-#[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct ValidatorStats {
     pub produced: NumBlocks,
     pub expected: NumBlocks,
@@ -2,7 +2,7 @@ use std::cmp::max;
 use std::convert::{AsRef, TryFrom};
 use std::fmt;
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
@@ -70,6 +70,12 @@ pub fn create_receipt_id_from_receipt(
     create_hash_upgradable(protocol_version, &receipt.receipt_id, &block_hash, receipt_index as u64)
 }
 
+/// Creates the ID of the receipt refunding the deposit attached to a receipt with a given ID,
+/// so the refund can be matched with the deposit charged from the deposit allowance.
+pub fn create_deposit_refund_receipt_id(receipt_id: &CryptoHash) -> CryptoHash {
+    hash(&[b"deposit_refund".as_ref(), receipt_id.as_ref()].concat())
+}
+
 /// Creates a new action_hash from a given receipt, a block hash and an action index.
 /// This method is backward compatible, so it takes the current protocol version.
 pub fn create_action_hash(
@@ -151,6 +157,12 @@ fn create_nonce_with_nonce(base: &CryptoHash, salt: u64) -> CryptoHash {
     hash(&nonce)
 }
 
+/// Maps the given account to the shard it belongs to given the total number of shards.
+pub fn account_id_to_shard_id(account_id: &AccountId, num_shards: NumShards) -> ShardId {
+    let mut cursor = std::io::Cursor::new((hash(&account_id.clone().into_bytes()).0).0);
+    cursor.read_u64::<LittleEndian>().expect("Must not happened") % (num_shards)
+}
+
 pub fn index_to_bytes(index: u64) -> Vec<u8> {
     let mut bytes = vec![];
     bytes.write_u64::<LittleEndian>(index).expect("writing to bytes failed");
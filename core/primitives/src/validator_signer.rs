@@ -149,7 +149,11 @@ impl InMemoryValidatorSigner {
     }
 
     pub fn from_file(path: &Path) -> Self {
-        let signer = InMemorySigner::from_file(path);
+        Self::from_file_with_passphrase(path, None)
+    }
+
+    pub fn from_file_with_passphrase(path: &Path, passphrase: Option<&str>) -> Self {
+        let signer = InMemorySigner::from_file_with_passphrase(path, passphrase);
         Self { account_id: signer.account_id.clone(), signer: Arc::new(signer) }
     }
 }
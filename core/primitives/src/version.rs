@@ -75,6 +75,43 @@ impl ProtocolVersionRange {
 pub enum ProtocolFeature {
     #[cfg(feature = "protocol_feature_forward_chunk_parts")]
     ForwardChunkParts,
+    /// `Action::UpgradeContract`, which deploys a contract and runs its migration method
+    /// atomically.
+    #[cfg(feature = "protocol_feature_contract_upgrade")]
+    ContractUpgrade,
+    /// `Action::Delegate` for meta-transactions paid for by a relayer.
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    DelegateAction,
+    /// `AccessKeyPermission::ExtendedFunctionCall` with several receivers, attached deposits and
+    /// expiration.
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    ExtendedFunctionCallAccessKey,
+    /// Receipts that are stored in the state and sent at a given block height or timestamp.
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    ScheduledReceipts,
+    /// `storage_byte_cost`, `storage_locked_balance` and `storage_usage_delta` host functions.
+    #[cfg(feature = "protocol_feature_storage_staking_host_functions")]
+    StorageStakingHostFunctions,
+    /// `Action::DeployContractWithAbi` that stores the contract ABI next to the code. Deploying
+    /// a contract without ABI removes the previously stored one.
+    #[cfg(feature = "protocol_feature_contract_abi")]
+    ContractAbi,
+    /// Chunk headers advertise the size of the delayed receipts queue of their shard, and
+    /// receipts sent to shards with a long queue are buffered in the state of the sender shard.
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    CongestionControl,
+    /// Deleting an account keeps its contract data in the state and removes it over the
+    /// following chunks. The account can't be created again until all the data is removed.
+    #[cfg(feature = "protocol_feature_lazy_account_deletion")]
+    LazyAccountDeletion,
+    /// Switches to the resharding shard layout from the genesis config, if there is one. The
+    /// shards are split at the first epoch boundary with this feature.
+    #[cfg(feature = "protocol_feature_resharding")]
+    Resharding,
+    /// The first block of every epoch commits to the epoch manager records that a node needs
+    /// to process the blocks from it on, so that epoch sync can verify them.
+    #[cfg(feature = "protocol_feature_epoch_sync")]
+    EpochSyncDataHash,
 }
 
 /// Current latest stable version of the protocol.
@@ -83,7 +120,7 @@ pub const PROTOCOL_VERSION: ProtocolVersion = 40;
 
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 41;
+pub const PROTOCOL_VERSION: ProtocolVersion = 51;
 
 lazy_static! {
     static ref STABLE_PROTOCOL_FEATURES_TO_VERSION_MAPPING: HashMap<ProtocolFeature, ProtocolVersion> = vec![
@@ -103,10 +140,39 @@ lazy_static! {
 #[cfg(feature = "nightly_protocol")]
 lazy_static! {
     pub static ref PROTOCOL_FEATURES_TO_VERSION_MAPPING: HashMap<ProtocolFeature, ProtocolVersion> = {
-        let nightly_protocol_features_to_version_mapping: HashMap<
-            ProtocolFeature,
-            ProtocolVersion,
-        > = vec![(ProtocolFeature::ForwardChunkParts, 41)].into_iter().collect();
+        #[allow(unused_mut)]
+        let mut nightly_protocol_features_to_version_mapping = HashMap::new();
+        #[cfg(feature = "protocol_feature_forward_chunk_parts")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::ForwardChunkParts, 41);
+        #[cfg(feature = "protocol_feature_contract_upgrade")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::ContractUpgrade, 42);
+        #[cfg(feature = "protocol_feature_delegate_action")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::DelegateAction, 43);
+        #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+        nightly_protocol_features_to_version_mapping
+            .insert(ProtocolFeature::ExtendedFunctionCallAccessKey, 44);
+        // Host functions are gated in `near-vm-logic`, which can't depend on this crate.
+        #[cfg(feature = "protocol_feature_scheduled_receipts")]
+        nightly_protocol_features_to_version_mapping.insert(
+            ProtocolFeature::ScheduledReceipts,
+            near_vm_logic::types::SCHEDULED_RECEIPTS_PROTOCOL_VERSION,
+        );
+        #[cfg(feature = "protocol_feature_storage_staking_host_functions")]
+        nightly_protocol_features_to_version_mapping.insert(
+            ProtocolFeature::StorageStakingHostFunctions,
+            near_vm_logic::types::STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION,
+        );
+        #[cfg(feature = "protocol_feature_contract_abi")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::ContractAbi, 47);
+        #[cfg(feature = "protocol_feature_congestion_control")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::CongestionControl, 48);
+        #[cfg(feature = "protocol_feature_lazy_account_deletion")]
+        nightly_protocol_features_to_version_mapping
+            .insert(ProtocolFeature::LazyAccountDeletion, 49);
+        #[cfg(feature = "protocol_feature_resharding")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::Resharding, 50);
+        #[cfg(feature = "protocol_feature_epoch_sync")]
+        nightly_protocol_features_to_version_mapping.insert(ProtocolFeature::EpochSyncDataHash, 51);
         for (stable_protocol_feature, stable_protocol_version) in
             STABLE_PROTOCOL_FEATURES_TO_VERSION_MAPPING.iter()
         {
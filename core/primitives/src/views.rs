@@ -12,13 +12,16 @@ use serde::{Deserialize, Serialize};
 
 use near_crypto::{PublicKey, Signature};
 
-use crate::account::{AccessKey, AccessKeyPermission, Account, FunctionCallPermission};
+use crate::account::{
+    AccessKey, AccessKeyPermission, Account, ExtendedFunctionCallPermission, FunctionCallPermission,
+};
 use crate::block::{Block, BlockHeader};
 use crate::block_header::{
-    BlockHeaderInnerLite, BlockHeaderInnerRest, BlockHeaderInnerRestV2, BlockHeaderV1,
-    BlockHeaderV2,
+    epoch_sync_data_hash_protocol_version, BlockHeaderInnerLite, BlockHeaderInnerRest,
+    BlockHeaderInnerRestV2, BlockHeaderV1, BlockHeaderV2, BlockHeaderV3,
 };
 use crate::challenge::{Challenge, ChallengesResult};
+use crate::epoch_manager::{EpochValidatorStats, ValidatorEpochStats};
 use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
@@ -29,18 +32,20 @@ use crate::serialize::{
     u128_dec_format, u64_dec_format,
 };
 use crate::sharding::{
-    ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderV2,
+    ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderInnerV2,
+    ShardChunkHeaderV2, ShardChunkHeaderV3,
 };
 use crate::transaction::{
-    Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
-    DeployContractAction, ExecutionOutcome, ExecutionOutcomeWithIdAndProof, ExecutionStatus,
-    FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
+    Action, AddKeyAction, CreateAccountAction, DelegateAction, DeleteAccountAction,
+    DeleteKeyAction, DeployContractAction, DeployContractWithAbiAction, ExecutionOutcome,
+    ExecutionOutcomeWithIdAndProof, ExecutionStatus, FunctionCallAction, SignedDelegateAction,
+    SignedTransaction, StakeAction, TransferAction, UpgradeContractAction,
 };
 use crate::types::{
-    AccountId, AccountWithPublicKey, Balance, BlockHeight, EpochId, FunctionArgs, Gas, Nonce,
-    NumBlocks, ShardId, StateChangeCause, StateChangeKind, StateChangeValue, StateChangeWithCause,
-    StateChangesRequest, StateRoot, StorageUsage, StoreKey, StoreValue, ValidatorKickoutReason,
-    ValidatorStake,
+    AccountId, AccountWithPublicKey, Balance, BlockHeight, BlockHeightDelta, EpochHeight, EpochId,
+    FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
+    StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
+    StoreValue, ValidatorKickoutReason, ValidatorStake,
 };
 use crate::version::{ProtocolVersion, Version};
 
@@ -102,6 +107,17 @@ pub enum AccessKeyPermissionView {
         method_names: Vec<String>,
     },
     FullAccess,
+    ExtendedFunctionCall {
+        #[serde(with = "option_u128_dec_format")]
+        allowance: Option<Balance>,
+        receiver_ids: Vec<AccountId>,
+        method_names: Vec<String>,
+        #[serde(with = "option_u128_dec_format")]
+        max_deposit_per_call: Option<Balance>,
+        #[serde(with = "option_u128_dec_format")]
+        deposit_allowance: Option<Balance>,
+        expires_at: Option<BlockHeight>,
+    },
 }
 
 impl From<AccessKeyPermission> for AccessKeyPermissionView {
@@ -113,6 +129,16 @@ impl From<AccessKeyPermission> for AccessKeyPermissionView {
                 method_names: func_call.method_names,
             },
             AccessKeyPermission::FullAccess => AccessKeyPermissionView::FullAccess,
+            AccessKeyPermission::ExtendedFunctionCall(func_call) => {
+                AccessKeyPermissionView::ExtendedFunctionCall {
+                    allowance: func_call.allowance,
+                    receiver_ids: func_call.receiver_ids,
+                    method_names: func_call.method_names,
+                    max_deposit_per_call: func_call.max_deposit_per_call,
+                    deposit_allowance: func_call.deposit_allowance,
+                    expires_at: func_call.expires_at,
+                }
+            }
         }
     }
 }
@@ -128,6 +154,21 @@ impl From<AccessKeyPermissionView> for AccessKeyPermission {
                 })
             }
             AccessKeyPermissionView::FullAccess => AccessKeyPermission::FullAccess,
+            AccessKeyPermissionView::ExtendedFunctionCall {
+                allowance,
+                receiver_ids,
+                method_names,
+                max_deposit_per_call,
+                deposit_allowance,
+                expires_at,
+            } => AccessKeyPermission::ExtendedFunctionCall(ExtendedFunctionCallPermission {
+                allowance,
+                receiver_ids,
+                method_names,
+                max_deposit_per_call,
+                deposit_allowance,
+                expires_at,
+            }),
         }
     }
 }
@@ -167,12 +208,151 @@ pub struct ViewStateResult {
     pub proof: TrieProofPath,
 }
 
+/// Storage usage of the contract data keys that start with the same prefix.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StoragePrefixUsageView {
+    #[serde(rename = "prefix_base64", with = "base64_format")]
+    pub prefix: StoreKey,
+    pub num_keys: u64,
+    pub storage_usage: StorageUsage,
+}
+
+/// Breakdown of the storage usage of an account, computed with the same rules that are used to
+/// charge `Account::storage_usage`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StorageUsageView {
+    /// Storage usage recorded in the account.
+    pub storage_usage: StorageUsage,
+    /// Storage usage of the account record itself.
+    pub account: StorageUsage,
+    /// Size of the deployed contract code.
+    pub code: StorageUsage,
+    /// Size of the ABI of the deployed contract.
+    #[serde(default)]
+    pub abi: StorageUsage,
+    pub num_access_keys: u64,
+    pub access_keys: StorageUsage,
+    /// Contract data grouped by key prefix, sorted by prefix.
+    pub data: Vec<StoragePrefixUsageView>,
+    /// Whether the account has more records than a single query scans, in which case the access
+    /// keys and the contract data above are incomplete.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Split of the account balance between the storage stake and the tokens the account can spend.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StorageStakingView {
+    pub storage_usage: StorageUsage,
+    /// Amount of tokens required to store one byte.
+    #[serde(with = "u128_dec_format")]
+    pub storage_byte_cost: Balance,
+    /// Total amount of tokens required to pay for the storage of the account.
+    #[serde(with = "u128_dec_format")]
+    pub storage_stake: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub locked: Balance,
+    /// Part of `amount` locked for storage. The locked balance is used for storage first.
+    #[serde(with = "u128_dec_format")]
+    pub locked_for_storage: Balance,
+    /// Part of `amount` that can be spent without running out of the storage stake.
+    #[serde(with = "u128_dec_format")]
+    pub free_balance: Balance,
+}
+
+/// Kind of a contract method in the contract ABI.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractMethodKindView {
+    /// The method doesn't modify the state and can be called with a `call_function` query.
+    View,
+    /// The method has to be called with a `FunctionCall` action.
+    Change,
+}
+
+/// Description of a single contract method in the contract ABI.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ContractMethodAbiView {
+    pub name: String,
+    pub kind: ContractMethodKindView,
+    /// JSON schema of the method arguments, serialized as a string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args_schema: Option<String>,
+    /// JSON schema of the value returned by the method, serialized as a string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_schema: Option<String>,
+}
+
+/// ABI of the contract deployed on the account.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ContractAbiView {
+    /// The ABI blob as it's stored in the state, encoded in base64.
+    #[serde(rename = "abi_base64", with = "base64_format")]
+    pub abi: Vec<u8>,
+    /// Methods of the contract, if the ABI follows the standard JSON format:
+    /// `{"methods": [{"name": "...", "kind": "view" | "change", "args_schema": "..."}]}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub methods: Option<Vec<ContractMethodAbiView>>,
+}
+
+#[derive(Deserialize)]
+struct ContractAbiStandard {
+    methods: Vec<ContractMethodAbiView>,
+}
+
+impl ContractAbiView {
+    /// Creates the view of the stored ABI blob. Methods are decoded if the ABI is in the standard
+    /// JSON format, otherwise only the raw blob is returned.
+    pub fn new(abi: Vec<u8>) -> Self {
+        let methods = serde_json::from_slice::<ContractAbiStandard>(&abi)
+            .ok()
+            .map(|standard| standard.methods);
+        Self { abi, methods }
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct CallResult {
     pub result: Vec<u8>,
     pub logs: Vec<String>,
 }
 
+/// Value of a single contract storage key to use instead of the actual one.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StateItemOverride {
+    #[serde(rename = "key_base64", with = "base64_format")]
+    pub key: StoreKey,
+    /// `None` removes the key.
+    #[serde(rename = "value_base64", with = "option_base64_format")]
+    pub value: Option<Vec<u8>>,
+}
+
+/// Hypothetical changes of the called contract account that are applied on top of the actual
+/// state for the duration of a single `call_function` query. Nothing is written to the storage.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default,
+)]
+pub struct StateOverrides {
+    /// Contract code to run instead of the deployed one.
+    #[serde(rename = "code_base64", default, with = "option_base64_format")]
+    pub code: Option<Vec<u8>>,
+    #[serde(default, with = "option_u128_dec_format")]
+    pub balance: Option<Balance>,
+    #[serde(default)]
+    pub state: Vec<StateItemOverride>,
+}
+
+/// Context of the caller for a simulated function call. When it is given, the method is executed
+/// as a regular (non-view) function call whose effects are discarded afterwards.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct CallerContext {
+    pub predecessor_account_id: AccountId,
+    #[serde(default, with = "u128_dec_format")]
+    pub attached_deposit: Balance,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct QueryError {
     pub error: String,
@@ -205,6 +385,9 @@ pub enum QueryResponseKind {
     Error(QueryError),
     AccessKey(AccessKeyView),
     AccessKeyList(AccessKeyList),
+    StorageUsage(StorageUsageView),
+    StorageStaking(StorageStakingView),
+    ContractAbi(ContractAbiView),
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -230,6 +413,25 @@ pub enum QueryRequest {
         method_name: String,
         #[serde(rename = "args_base64", with = "base64_format")]
         args: FunctionArgs,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        state_overrides: Option<StateOverrides>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        caller: Option<CallerContext>,
+    },
+    /// Storage usage of the account with the contract data grouped by the first `prefix_len`
+    /// bytes of the keys.
+    ViewStorageUsage {
+        account_id: AccountId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix_len: Option<u32>,
+    },
+    /// Balance of the account locked for storage and the balance it can spend.
+    ViewStorageStaking {
+        account_id: AccountId,
+    },
+    /// ABI of the contract deployed on the account.
+    ViewContractAbi {
+        account_id: AccountId,
     },
 }
 
@@ -361,6 +563,8 @@ pub struct BlockHeaderView {
     pub approvals: Vec<Option<Signature>>,
     pub signature: Signature,
     pub latest_protocol_version: ProtocolVersion,
+    #[serde(default)]
+    pub epoch_sync_data_hash: Option<CryptoHash>,
 }
 
 impl From<BlockHeader> for BlockHeaderView {
@@ -399,6 +603,7 @@ impl From<BlockHeader> for BlockHeaderView {
             approvals: header.approvals().to_vec(),
             signature: header.signature().clone(),
             latest_protocol_version: header.latest_protocol_version(),
+            epoch_sync_data_hash: header.epoch_sync_data_hash(),
         }
     }
 }
@@ -446,39 +651,53 @@ impl From<BlockHeaderView> for BlockHeader {
             header.init();
             BlockHeader::BlockHeaderV1(Box::new(header))
         } else {
-            let mut header = BlockHeaderV2 {
-                prev_hash: view.prev_hash,
-                inner_lite,
-                inner_rest: BlockHeaderInnerRestV2 {
-                    chunk_receipts_root: view.chunk_receipts_root,
-                    chunk_headers_root: view.chunk_headers_root,
-                    chunk_tx_root: view.chunk_tx_root,
-                    challenges_root: view.challenges_root,
-                    random_value: view.random_value,
-                    validator_proposals: view
-                        .validator_proposals
-                        .into_iter()
-                        .map(|v| v.into())
-                        .collect(),
-                    chunk_mask: view.chunk_mask,
-                    gas_price: view.gas_price,
-                    total_supply: view.total_supply,
-                    challenges_result: view.challenges_result,
-                    last_final_block: view.last_final_block,
-                    last_ds_final_block: view.last_ds_final_block,
-                    approvals: view.approvals.clone(),
-                    latest_protocol_version: view.latest_protocol_version,
-                },
-                signature: view.signature,
-                hash: CryptoHash::default(),
+            let inner_rest = BlockHeaderInnerRestV2 {
+                chunk_receipts_root: view.chunk_receipts_root,
+                chunk_headers_root: view.chunk_headers_root,
+                chunk_tx_root: view.chunk_tx_root,
+                challenges_root: view.challenges_root,
+                random_value: view.random_value,
+                validator_proposals: view
+                    .validator_proposals
+                    .into_iter()
+                    .map(|v| v.into())
+                    .collect(),
+                chunk_mask: view.chunk_mask,
+                gas_price: view.gas_price,
+                total_supply: view.total_supply,
+                challenges_result: view.challenges_result,
+                last_final_block: view.last_final_block,
+                last_ds_final_block: view.last_ds_final_block,
+                approvals: view.approvals.clone(),
+                latest_protocol_version: view.latest_protocol_version,
             };
-            header.init();
-            BlockHeader::BlockHeaderV2(Box::new(header))
+            if view.latest_protocol_version < epoch_sync_data_hash_protocol_version() {
+                let mut header = BlockHeaderV2 {
+                    prev_hash: view.prev_hash,
+                    inner_lite,
+                    inner_rest,
+                    signature: view.signature,
+                    hash: CryptoHash::default(),
+                };
+                header.init();
+                BlockHeader::BlockHeaderV2(Box::new(header))
+            } else {
+                let mut header = BlockHeaderV3 {
+                    prev_hash: view.prev_hash,
+                    inner_lite,
+                    inner_rest,
+                    epoch_sync_data_hash: view.epoch_sync_data_hash,
+                    signature: view.signature,
+                    hash: CryptoHash::default(),
+                };
+                header.init();
+                BlockHeader::BlockHeaderV3(Box::new(header))
+            }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, BorshDeserialize, BorshSerialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
 pub struct BlockHeaderInnerLiteView {
     pub height: BlockHeight,
     pub epoch_id: CryptoHash,
@@ -518,6 +737,32 @@ impl From<BlockHeader> for BlockHeaderInnerLiteView {
                 next_bp_hash: header.inner_lite.next_bp_hash,
                 block_merkle_root: header.inner_lite.block_merkle_root,
             },
+            BlockHeader::BlockHeaderV3(header) => BlockHeaderInnerLiteView {
+                height: header.inner_lite.height,
+                epoch_id: header.inner_lite.epoch_id.0,
+                next_epoch_id: header.inner_lite.next_epoch_id.0,
+                prev_state_root: header.inner_lite.prev_state_root,
+                outcome_root: header.inner_lite.outcome_root,
+                timestamp: header.inner_lite.timestamp,
+                timestamp_nanosec: header.inner_lite.timestamp,
+                next_bp_hash: header.inner_lite.next_bp_hash,
+                block_merkle_root: header.inner_lite.block_merkle_root,
+            },
+        }
+    }
+}
+
+impl From<BlockHeaderInnerLiteView> for BlockHeaderInnerLite {
+    fn from(view: BlockHeaderInnerLiteView) -> Self {
+        BlockHeaderInnerLite {
+            height: view.height,
+            epoch_id: EpochId(view.epoch_id),
+            next_epoch_id: EpochId(view.next_epoch_id),
+            prev_state_root: view.prev_state_root,
+            outcome_root: view.outcome_root,
+            timestamp: view.timestamp_nanosec,
+            next_bp_hash: view.next_bp_hash,
+            block_merkle_root: view.block_merkle_root,
         }
     }
 }
@@ -547,6 +792,10 @@ pub struct ChunkHeaderView {
     pub tx_root: CryptoHash,
     pub validator_proposals: Vec<ValidatorStakeView>,
     pub signature: Signature,
+    /// Size of the delayed receipts queue of the shard after the previous chunk. Only advertised
+    /// since `ProtocolFeature::CongestionControl`.
+    #[serde(default)]
+    pub delayed_receipts_count: Option<u64>,
 }
 
 impl From<ShardChunkHeader> for ChunkHeaderView {
@@ -554,6 +803,10 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
         let hash = chunk.chunk_hash();
         let signature = chunk.signature().clone();
         let height_included = chunk.height_included();
+        let delayed_receipts_count = match &chunk {
+            ShardChunkHeader::V1(_) | ShardChunkHeader::V2(_) => None,
+            ShardChunkHeader::V3(header) => Some(header.inner.delayed_receipts_count),
+        };
         let inner = chunk.take_inner();
         ChunkHeaderView {
             chunk_hash: hash.0,
@@ -574,12 +827,42 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
             tx_root: inner.tx_root,
             validator_proposals: inner.validator_proposals.into_iter().map(Into::into).collect(),
             signature,
+            delayed_receipts_count,
         }
     }
 }
 
 impl From<ChunkHeaderView> for ShardChunkHeader {
     fn from(view: ChunkHeaderView) -> Self {
+        if let Some(delayed_receipts_count) = view.delayed_receipts_count {
+            let mut header = ShardChunkHeaderV3 {
+                inner: ShardChunkHeaderInnerV2 {
+                    prev_block_hash: view.prev_block_hash,
+                    prev_state_root: view.prev_state_root,
+                    outcome_root: view.outcome_root,
+                    encoded_merkle_root: view.encoded_merkle_root,
+                    encoded_length: view.encoded_length,
+                    height_created: view.height_created,
+                    shard_id: view.shard_id,
+                    gas_used: view.gas_used,
+                    gas_limit: view.gas_limit,
+                    balance_burnt: view.balance_burnt,
+                    outgoing_receipts_root: view.outgoing_receipts_root,
+                    tx_root: view.tx_root,
+                    validator_proposals: view
+                        .validator_proposals
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    delayed_receipts_count,
+                },
+                height_included: view.height_included,
+                signature: view.signature,
+                hash: ChunkHash::default(),
+            };
+            header.init();
+            return ShardChunkHeader::V3(header);
+        }
         let mut header = ShardChunkHeaderV2 {
             inner: ShardChunkHeaderInner {
                 prev_block_hash: view.prev_block_hash,
@@ -681,6 +964,20 @@ pub enum ActionView {
     DeleteAccount {
         beneficiary_id: AccountId,
     },
+    UpgradeContract {
+        code: String,
+        method_name: String,
+        args: String,
+        gas: Gas,
+    },
+    Delegate {
+        delegate_action: DelegateAction,
+        signature: Signature,
+    },
+    DeployContractWithAbi {
+        code: String,
+        abi: String,
+    },
 }
 
 impl From<Action> for ActionView {
@@ -708,6 +1005,20 @@ impl From<Action> for ActionView {
             Action::DeleteAccount(action) => {
                 ActionView::DeleteAccount { beneficiary_id: action.beneficiary_id }
             }
+            Action::UpgradeContract(action) => ActionView::UpgradeContract {
+                code: to_base64(&hash(&action.code)),
+                method_name: action.method_name,
+                args: to_base64(&action.args),
+                gas: action.gas,
+            },
+            Action::Delegate(action) => ActionView::Delegate {
+                delegate_action: action.delegate_action,
+                signature: action.signature,
+            },
+            Action::DeployContractWithAbi(action) => ActionView::DeployContractWithAbi {
+                code: to_base64(&hash(&action.code)),
+                abi: to_base64(&action.abi),
+            },
         }
     }
 }
@@ -742,6 +1053,23 @@ impl TryFrom<ActionView> for Action {
             ActionView::DeleteAccount { beneficiary_id } => {
                 Action::DeleteAccount(DeleteAccountAction { beneficiary_id })
             }
+            ActionView::UpgradeContract { code, method_name, args, gas } => {
+                Action::UpgradeContract(UpgradeContractAction {
+                    code: from_base64(&code)?,
+                    method_name,
+                    args: from_base64(&args)?,
+                    gas,
+                })
+            }
+            ActionView::Delegate { delegate_action, signature } => {
+                Action::Delegate(SignedDelegateAction { delegate_action, signature })
+            }
+            ActionView::DeployContractWithAbi { code, abi } => {
+                Action::DeployContractWithAbi(DeployContractWithAbiAction {
+                    code: from_base64(&code)?,
+                    abi: from_base64(&abi)?,
+                })
+            }
         })
     }
 }
@@ -915,6 +1243,7 @@ impl From<ExecutionOutcomeWithIdAndProof> for ExecutionOutcomeWithIdView {
 pub enum FinalExecutionOutcomeViewEnum {
     FinalExecutionOutcome(FinalExecutionOutcomeView),
     FinalExecutionOutcomeWithReceipt(FinalExecutionOutcomeWithReceiptView),
+    FinalExecutionOutcomeWithTrace(FinalExecutionOutcomeWithTraceView),
 }
 
 /// Final execution outcome of the transaction and all of subsequent the receipts.
@@ -961,6 +1290,93 @@ impl From<FinalExecutionOutcomeWithReceiptView> for FinalExecutionOutcomeView {
     }
 }
 
+/// Final execution outcome of the transaction together with the execution trace of every
+/// receipt. Receipts are only included if they were requested.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FinalExecutionOutcomeWithTraceView {
+    /// Final outcome view without receipts
+    #[serde(flatten)]
+    pub final_outcome: FinalExecutionOutcomeView,
+    /// Receipts generated from the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipts: Option<Vec<ReceiptView>>,
+    /// Execution trace of each receipt in the same order as `receipts_outcome`.
+    pub receipts_trace: Vec<ReceiptExecutionTraceView>,
+}
+
+impl From<FinalExecutionOutcomeWithTraceView> for FinalExecutionOutcomeView {
+    fn from(final_outcome_view: FinalExecutionOutcomeWithTraceView) -> Self {
+        final_outcome_view.final_outcome
+    }
+}
+
+/// Explains where, when and with which effects a receipt was executed.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ReceiptExecutionTraceView {
+    pub receipt_id: CryptoHash,
+    /// Block in which the transaction or receipt that created this receipt was executed.
+    pub created_in_block_hash: CryptoHash,
+    pub created_at_block_height: BlockHeight,
+    /// Block in which this receipt was executed.
+    pub executed_in_block_hash: CryptoHash,
+    pub executed_at_block_height: BlockHeight,
+    /// Number of blocks between the earliest block the receipt could have been executed in and
+    /// the block it was actually executed in.
+    pub delay_blocks: BlockHeightDelta,
+    /// Breakdown of `delay_blocks` by cause.
+    pub delay_reasons: Vec<ReceiptDelayReasonView>,
+    /// Data the receipt had to wait for before it could be executed.
+    pub input_data: Vec<ReceiptInputDataView>,
+    pub gas_burnt: Gas,
+    #[serde(with = "u128_dec_format")]
+    pub tokens_burnt: Balance,
+    /// Refund receipts generated by the execution of this receipt.
+    pub refunds: Vec<ReceiptRefundView>,
+    /// State changes caused by this receipt on the account of its receiver.
+    pub state_changes: Vec<StateChangeWithCauseView>,
+}
+
+/// Cause of a receipt not being executed right after it was created.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum ReceiptDelayReasonView {
+    /// The receipt was postponed until all of its input data arrived.
+    WaitingForInputData { blocks: BlockHeightDelta },
+    /// No block was produced at these heights.
+    SkippedBlocks { blocks: BlockHeightDelta },
+    /// The chunk of the receiver's shard was missing from these blocks.
+    MissingChunks { blocks: BlockHeightDelta },
+    /// The shard had a chunk in these blocks, but the receipt stayed in the delayed receipts
+    /// queue because the chunk used up its gas limit.
+    DelayedReceiptsQueue { blocks: BlockHeightDelta },
+    /// The shard had a chunk with gas left in these blocks, or the node doesn't track the shard
+    /// and can't tell whether its chunks used up their gas limit.
+    Unknown { blocks: BlockHeightDelta },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ReceiptInputDataView {
+    pub data_id: CryptoHash,
+    /// The receipt of this transaction whose result was sent as this data, if it is known.
+    pub producer_receipt_id: Option<CryptoHash>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum RefundKindView {
+    /// Unused deposit returned to the predecessor of a failed receipt.
+    Balance,
+    /// Unused prepaid gas returned to the signer.
+    Gas,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ReceiptRefundView {
+    pub receipt_id: CryptoHash,
+    pub receiver_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    pub kind: RefundKindView,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct ValidatorStakeView {
     pub account_id: AccountId,
@@ -1135,7 +1551,66 @@ pub struct NextEpochValidatorInfo {
     pub shards: Vec<ShardId>,
 }
 
-#[derive(Serialize, Debug, Clone, BorshDeserialize, BorshSerialize)]
+/// Performance of a validator during a finished epoch.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ValidatorEpochStatsView {
+    pub account_id: AccountId,
+    pub public_key: PublicKey,
+    #[serde(with = "u128_dec_format")]
+    pub stake: Balance,
+    pub num_produced_blocks: NumBlocks,
+    pub num_expected_blocks: NumBlocks,
+    pub num_produced_chunks: NumBlocks,
+    pub num_expected_chunks: NumBlocks,
+    /// Reason the validator is kicked out of the epoch after next, if it is.
+    pub kickout_reason: Option<ValidatorKickoutReason>,
+    #[serde(with = "u128_dec_format")]
+    pub reward: Balance,
+    /// Stake of the validator in the epoch after next, if it changes.
+    #[serde(with = "option_u128_dec_format")]
+    pub next_stake: Option<Balance>,
+}
+
+impl From<ValidatorEpochStats> for ValidatorEpochStatsView {
+    fn from(stats: ValidatorEpochStats) -> Self {
+        ValidatorEpochStatsView {
+            account_id: stats.account_id,
+            public_key: stats.public_key,
+            stake: stats.stake,
+            num_produced_blocks: stats.block_stats.produced,
+            num_expected_blocks: stats.block_stats.expected,
+            num_produced_chunks: stats.chunk_stats.produced,
+            num_expected_chunks: stats.chunk_stats.expected,
+            kickout_reason: stats.kickout_reason,
+            reward: stats.reward,
+            next_stake: stats.next_stake,
+        }
+    }
+}
+
+/// Performance of the validators of a finished epoch.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EpochValidatorHistoryView {
+    pub epoch_id: CryptoHash,
+    pub epoch_height: EpochHeight,
+    pub epoch_start_height: BlockHeight,
+    pub last_block_hash: CryptoHash,
+    pub validators: Vec<ValidatorEpochStatsView>,
+}
+
+impl From<EpochValidatorStats> for EpochValidatorHistoryView {
+    fn from(stats: EpochValidatorStats) -> Self {
+        EpochValidatorHistoryView {
+            epoch_id: stats.epoch_id.0,
+            epoch_height: stats.epoch_height,
+            epoch_start_height: stats.epoch_start_height,
+            last_block_hash: stats.last_block_hash,
+            validators: stats.validators.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
 pub struct LightClientBlockView {
     pub prev_block_hash: CryptoHash,
     pub next_block_inner_hash: CryptoHash,
@@ -1168,6 +1643,43 @@ pub struct GasPriceView {
     pub gas_price: Balance,
 }
 
+/// Gas used by recent receipts that called the given method of the receiver.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCallGasUsageView {
+    pub method_name: String,
+    /// Number of receipts the statistics are computed from.
+    pub num_samples: u64,
+    /// Gas burnt by the receipt plus gas attached to the receipts it created.
+    pub max_gas_used: Gas,
+    pub avg_gas_used: Gas,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeeEstimationView {
+    /// The head block the estimation is made at.
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    #[serde(with = "u128_dec_format")]
+    pub gas_price: Balance,
+    /// Gas price after `num_blocks` if blocks are as full as the recent ones on average.
+    #[serde(with = "u128_dec_format")]
+    pub expected_gas_price: Balance,
+    /// Gas price after `num_blocks` if all of them are full.
+    #[serde(with = "u128_dec_format")]
+    pub max_gas_price: Balance,
+    pub num_blocks: BlockHeightDelta,
+    /// Gas burnt for converting the actions into a receipt and executing them, not including the
+    /// execution of function calls.
+    pub fees_gas: Gas,
+    pub function_calls: Vec<FunctionCallGasUsageView>,
+    /// Gas to attach to the function calls: the highest recent usage of each method with a safety
+    /// margin on top, or the attached gas of the calls without any recent usage.
+    pub recommended_attached_gas: Gas,
+    /// Upper bound of the transaction cost at `max_gas_price`, including deposits.
+    #[serde(with = "u128_dec_format")]
+    pub max_cost: Balance,
+}
+
 /// It is a [serializable view] of [`StateChangesRequest`].
 ///
 /// [serializable view]: ./index.html
@@ -1247,7 +1759,7 @@ impl From<StateChangeKind> for StateChangeKindView {
 pub type StateChangesKindsView = Vec<StateChangeKindView>;
 
 /// See crate::types::StateChangeCause for details.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum StateChangeCauseView {
     NotWritableToDisk,
@@ -1259,6 +1771,9 @@ pub enum StateChangeCauseView {
     PostponedReceipt { receipt_hash: CryptoHash },
     UpdatedDelayedReceipts,
     ValidatorAccountsUpdate,
+    ReleasedScheduledReceipts,
+    UpdatedBufferedReceipts,
+    DeletedAccountsCleanup,
 }
 
 impl From<StateChangeCause> for StateChangeCauseView {
@@ -1283,11 +1798,14 @@ impl From<StateChangeCause> for StateChangeCauseView {
             }
             StateChangeCause::UpdatedDelayedReceipts => Self::UpdatedDelayedReceipts,
             StateChangeCause::ValidatorAccountsUpdate => Self::ValidatorAccountsUpdate,
+            StateChangeCause::ReleasedScheduledReceipts => Self::ReleasedScheduledReceipts,
+            StateChangeCause::UpdatedBufferedReceipts => Self::UpdatedBufferedReceipts,
+            StateChangeCause::DeletedAccountsCleanup => Self::DeletedAccountsCleanup,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(rename_all = "snake_case", tag = "type", content = "change")]
 pub enum StateChangeValueView {
     AccountUpdate {
@@ -1360,7 +1878,7 @@ impl From<StateChangeValue> for StateChangeValueView {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct StateChangeWithCauseView {
     pub cause: StateChangeCauseView,
     #[serde(flatten)]
@@ -1375,3 +1893,54 @@ impl From<StateChangeWithCause> for StateChangeWithCauseView {
 }
 
 pub type StateChangesView = Vec<StateChangeWithCauseView>;
+
+/// Reason of a change of the account balance.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum AccountBalanceChangeCauseView {
+    /// The signer paid for the gas and the deposits of the transaction.
+    Transaction {
+        tx_hash: CryptoHash,
+    },
+    /// Execution of an action receipt, e.g. a transfer or a storage payment.
+    Receipt {
+        receipt_hash: CryptoHash,
+    },
+    /// A refund of unused gas or of the deposit of a failed receipt.
+    Refund {
+        receipt_hash: CryptoHash,
+    },
+    /// The contract got its share of the gas burnt by the receipt.
+    ContractReward {
+        receipt_hash: CryptoHash,
+    },
+    /// A stake action locked a part of the balance.
+    StakeLock {
+        receipt_hash: CryptoHash,
+    },
+    /// Epoch boundary update: the validator reward was added, the stake that is no longer needed
+    /// was unlocked and the slashed stake was burnt.
+    ValidatorAccountsUpdate {
+        #[serde(with = "u128_dec_format")]
+        validator_reward: Balance,
+    },
+    Other {
+        cause: StateChangeCauseView,
+    },
+}
+
+/// A single change of the account balance with the balances before and after it.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AccountBalanceChangeView {
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    pub cause: AccountBalanceChangeCauseView,
+    #[serde(with = "u128_dec_format")]
+    pub amount_before: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub amount_after: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub locked_before: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub locked_after: Balance,
+}
@@ -7,6 +7,7 @@ use near_primitives::types::{AccountId, Balance};
 use near_primitives::version::ProtocolVersion;
 use near_runtime_fees::RuntimeFeesConfig;
 use near_vm_logic::VMConfig;
+use std::cmp::min;
 use std::sync::Arc;
 
 /// The structure that holds the parameters of the runtime, mostly economics.
@@ -88,11 +89,7 @@ pub fn get_insufficient_storage_stake(
     account: &Account,
     runtime_config: &RuntimeConfig,
 ) -> Result<Option<Balance>, String> {
-    let required_amount = Balance::from(account.storage_usage)
-        .checked_mul(runtime_config.storage_amount_per_byte)
-        .ok_or_else(|| {
-            format!("Account's storage_usage {} overflows multiplication", account.storage_usage)
-        })?;
+    let required_amount = get_storage_stake(account, runtime_config)?;
     let available_amount = account.amount.checked_add(account.locked).ok_or_else(|| {
         format!(
             "Account's amount {} and locked {} overflow addition",
@@ -106,6 +103,29 @@ pub fn get_insufficient_storage_stake(
     }
 }
 
+/// Returns the balance the account must hold to pay for its storage.
+pub fn get_storage_stake(
+    account: &Account,
+    runtime_config: &RuntimeConfig,
+) -> Result<Balance, String> {
+    Balance::from(account.storage_usage)
+        .checked_mul(runtime_config.storage_amount_per_byte)
+        .ok_or_else(|| {
+            format!("Account's storage_usage {} overflows multiplication", account.storage_usage)
+        })
+}
+
+/// Returns the part of the liquid balance of the account that is locked to pay for its storage.
+/// The locked (staked) balance counts towards the storage stake first, so it's zero for accounts
+/// that stake enough.
+pub fn get_storage_locked_balance(
+    account: &Account,
+    runtime_config: &RuntimeConfig,
+) -> Result<Balance, String> {
+    let required_amount = get_storage_stake(account, runtime_config)?;
+    Ok(min(required_amount.saturating_sub(account.locked), account.amount))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
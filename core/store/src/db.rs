@@ -114,10 +114,21 @@ pub enum DBCol {
     ColReceipts = 45,
     /// Precompiled machine code of the contract
     ColCachedContractCode = 46,
+    /// Validator performance of finished epochs by the hash of the last block of the epoch
+    ColEpochValidatorStats = 47,
+    /// State roots of the shards split from a shard after a block of the epoch before the
+    /// resharding, by block hash and shard id
+    ColSplitStateRoots = 48,
+    /// Changes of the state of a shard in a block of the epoch before a resharding, by block hash
+    /// and shard id. They are applied to the states of the shards split from the shard.
+    ColStateChangesForSplitStates = 49,
+    /// Trie changes of the states of the shards split from a shard in a block, by block hash and
+    /// the shard id of the parent shard
+    ColSplitStateTrieChanges = 50,
 }
 
 // Do not move this line from enum DBCol
-pub const NUM_COLS: usize = 47;
+pub const NUM_COLS: usize = 51;
 
 impl std::fmt::Display for DBCol {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -169,6 +180,10 @@ impl std::fmt::Display for DBCol {
             Self::ColProcessedBlockHeights => "processed block heights",
             Self::ColReceipts => "receipts",
             Self::ColCachedContractCode => "cached code",
+            Self::ColEpochValidatorStats => "epoch validator stats",
+            Self::ColSplitStateRoots => "state roots of split shards",
+            Self::ColStateChangesForSplitStates => "state changes for split states",
+            Self::ColSplitStateTrieChanges => "trie changes of split states",
         };
         write!(formatter, "{}", desc)
     }
@@ -200,6 +215,7 @@ lazy_static! {
         col_gc[DBCol::ColEpochInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColCachedContractCode as usize] = false;
+        col_gc[DBCol::ColEpochValidatorStats as usize] = false; // history of validator performance
         col_gc
     };
 }
@@ -213,6 +229,8 @@ lazy_static! {
         col_gc[DBCol::ColStateHeaders as usize] = true;
         // True until #2515
         col_gc[DBCol::ColStateParts as usize] = true;
+        // Only written in the epoch before a resharding
+        col_gc[DBCol::ColSplitStateTrieChanges as usize] = true;
         col_gc
     };
 }
@@ -19,14 +19,14 @@ pub use db::{
     LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
 };
 use near_crypto::PublicKey;
-use near_primitives::account::{AccessKey, Account};
+use near_primitives::account::{AccessKey, Account, ChargedDeposit};
 use near_primitives::contract::ContractCode;
 pub use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
-use near_primitives::receipt::{Receipt, ReceivedData};
+use near_primitives::receipt::{Receipt, ReceiptSchedule, ReceivedData};
 use near_primitives::serialize::to_base;
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
-use near_primitives::types::{AccountId, StateRoot};
+use near_primitives::types::{AccountId, BlockHeight, StateRoot};
 
 pub use crate::db::refcount::decode_value_with_rc;
 use crate::db::refcount::encode_value_with_rc;
@@ -34,9 +34,9 @@ use crate::db::{
     DBOp, DBTransaction, Database, RocksDB, GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY,
 };
 pub use crate::trie::{
-    iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateIterator,
-    update::TrieUpdateValuePtr, KeyForStateChanges, PartialStorage, ShardTries, Trie, TrieChanges,
-    WrappedTrieChanges,
+    iterator::TrieIterator, update::TrieKeyValueUpdate, update::TrieUpdate,
+    update::TrieUpdateIterator, update::TrieUpdateValuePtr, update::TrieUpdates,
+    KeyForStateChanges, PartialStorage, ShardTries, Trie, TrieChanges, WrappedTrieChanges,
 };
 
 mod db;
@@ -372,6 +372,82 @@ pub fn get_postponed_receipt(
     get(state_update, &TrieKey::PostponedReceipt { receiver_id: receiver_id.clone(), receipt_id })
 }
 
+/// Stores the scheduled receipt together with the index that allows to find it by the ID of the
+/// account that scheduled it (`receipt.predecessor_id`) and the receipt ID.
+pub fn set_scheduled_receipt(
+    state_update: &mut TrieUpdate,
+    schedule: ReceiptSchedule,
+    receipt: &Receipt,
+) {
+    set(
+        state_update,
+        TrieKey::ScheduledReceiptIndex {
+            account_id: receipt.predecessor_id.clone(),
+            receipt_id: receipt.receipt_id,
+        },
+        &schedule,
+    );
+    set(
+        state_update,
+        TrieKey::ScheduledReceipt { schedule, receipt_id: receipt.receipt_id },
+        receipt,
+    );
+}
+
+pub fn remove_scheduled_receipt(
+    state_update: &mut TrieUpdate,
+    schedule: ReceiptSchedule,
+    account_id: &AccountId,
+    receipt_id: CryptoHash,
+) {
+    state_update
+        .remove(TrieKey::ScheduledReceiptIndex { account_id: account_id.clone(), receipt_id });
+    state_update.remove(TrieKey::ScheduledReceipt { schedule, receipt_id });
+}
+
+/// Returns the IDs of all the receipts scheduled by the given account that are not yet released.
+pub fn get_scheduled_receipt_ids(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Vec<CryptoHash>, StorageError> {
+    state_update
+        .iter(&trie_key_parsers::get_raw_prefix_for_scheduled_receipt_index(account_id))?
+        .map(|raw_key| {
+            trie_key_parsers::parse_receipt_id_from_scheduled_receipt_index_key(
+                &raw_key?, account_id,
+            )
+            .map_err(|_e| {
+                StorageError::StorageInconsistentState(
+                    "Can't parse receipt ID from raw key for ScheduledReceiptIndex".to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Returns the scheduled receipt with the given ID that was scheduled by the given account.
+pub fn get_scheduled_receipt(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+    receipt_id: CryptoHash,
+) -> Result<Option<(ReceiptSchedule, Receipt)>, StorageError> {
+    let schedule: ReceiptSchedule = match get(
+        state_update,
+        &TrieKey::ScheduledReceiptIndex { account_id: account_id.clone(), receipt_id },
+    )? {
+        Some(schedule) => schedule,
+        None => return Ok(None),
+    };
+    let receipt = get(state_update, &TrieKey::ScheduledReceipt { schedule, receipt_id })?
+        .ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Scheduled receipt {} should be in the state",
+                receipt_id
+            ))
+        })?;
+    Ok(Some((schedule, receipt)))
+}
+
 pub fn set_access_key(
     state_update: &mut TrieUpdate,
     account_id: AccountId,
@@ -425,13 +501,41 @@ pub fn get_code(
         .map(|opt| opt.map(|code| ContractCode::new(code, code_hash)))
 }
 
-/// Removes account, code and all access keys associated to it.
+pub fn set_contract_abi(state_update: &mut TrieUpdate, account_id: AccountId, abi: Vec<u8>) {
+    state_update.set(TrieKey::ContractAbi { account_id }, abi);
+}
+
+pub fn get_contract_abi(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<Vec<u8>>, StorageError> {
+    state_update.get(&TrieKey::ContractAbi { account_id: account_id.clone() })
+}
+
+pub fn remove_contract_abi(state_update: &mut TrieUpdate, account_id: AccountId) {
+    state_update.remove(TrieKey::ContractAbi { account_id });
+}
+
+/// Removes account, code, contract ABI, all access keys and contract data associated to it.
 pub fn remove_account(
     state_update: &mut TrieUpdate,
     account_id: &AccountId,
+) -> Result<(), StorageError> {
+    remove_account_without_data(state_update, account_id)?;
+    remove_contract_data(state_update, account_id, |_| true)?;
+    Ok(())
+}
+
+/// Removes account, code, contract ABI, charged deposits and all access keys associated to it, but
+/// keeps the contract data.
+pub fn remove_account_without_data(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
 ) -> Result<(), StorageError> {
     state_update.remove(TrieKey::Account { account_id: account_id.clone() });
     state_update.remove(TrieKey::ContractCode { account_id: account_id.clone() });
+    state_update.remove(TrieKey::ContractAbi { account_id: account_id.clone() });
+    state_update.remove(TrieKey::ChargedDeposits { account_id: account_id.clone() });
 
     // Removing access keys
     let public_keys = state_update
@@ -449,24 +553,91 @@ pub fn remove_account(
     for public_key in public_keys {
         state_update.remove(TrieKey::AccessKey { account_id: account_id.clone(), public_key });
     }
+    Ok(())
+}
 
-    // Removing contract data
-    let data_keys = state_update
+/// Returns whether the given account has any contract data in the state.
+pub fn has_contract_data(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<bool, StorageError> {
+    Ok(state_update
         .iter(&trie_key_parsers::get_raw_prefix_for_contract_data(&account_id, &[]))?
-        .map(|raw_key| {
-            trie_key_parsers::parse_data_key_from_contract_data_key(&raw_key?, account_id)
-                .map_err(|_e| {
-                    StorageError::StorageInconsistentState(
-                        "Can't parse data key from raw key for ContractData".to_string(),
-                    )
-                })
-                .map(Vec::from)
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+        .next()
+        .is_some())
+}
+
+/// Removes the contract data records of the given account in the order of their keys, while
+/// `can_remove` allows to remove the next key. Returns whether no contract data is left.
+pub fn remove_contract_data(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+    mut can_remove: impl FnMut(&[u8]) -> bool,
+) -> Result<bool, StorageError> {
+    let mut data_keys = vec![];
+    let mut is_removed = true;
+    for raw_key in
+        state_update.iter(&trie_key_parsers::get_raw_prefix_for_contract_data(&account_id, &[]))?
+    {
+        let key = trie_key_parsers::parse_data_key_from_contract_data_key(&raw_key?, account_id)
+            .map_err(|_e| {
+                StorageError::StorageInconsistentState(
+                    "Can't parse data key from raw key for ContractData".to_string(),
+                )
+            })?
+            .to_vec();
+        if !can_remove(&key) {
+            is_removed = false;
+            break;
+        }
+        data_keys.push(key);
+    }
     for key in data_keys {
         state_update.remove(TrieKey::ContractData { account_id: account_id.clone(), key });
     }
-    Ok(())
+    Ok(is_removed)
+}
+
+/// Marks the account as deleted at the given block height while its contract data is removed.
+pub fn set_deleted_account(
+    state_update: &mut TrieUpdate,
+    account_id: AccountId,
+    block_height: BlockHeight,
+) {
+    set(state_update, TrieKey::DeletedAccount { account_id }, &block_height);
+}
+
+pub fn get_deleted_account(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<BlockHeight>, StorageError> {
+    get(state_update, &TrieKey::DeletedAccount { account_id: account_id.clone() })
+}
+
+pub fn remove_deleted_account(state_update: &mut TrieUpdate, account_id: AccountId) {
+    state_update.remove(TrieKey::DeletedAccount { account_id });
+}
+
+pub fn get_charged_deposits(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Vec<ChargedDeposit>, StorageError> {
+    Ok(get(state_update, &TrieKey::ChargedDeposits { account_id: account_id.clone() })?
+        .unwrap_or_default())
+}
+
+/// Stores the deposits charged from the access keys of the given account, removes the record if
+/// there are none.
+pub fn set_charged_deposits(
+    state_update: &mut TrieUpdate,
+    account_id: AccountId,
+    charged_deposits: Vec<ChargedDeposit>,
+) {
+    if charged_deposits.is_empty() {
+        state_update.remove(TrieKey::ChargedDeposits { account_id });
+    } else {
+        set(state_update, TrieKey::ChargedDeposits { account_id }, &charged_deposits);
+    }
 }
 
 pub fn get_genesis_state_roots(store: &Store) -> Result<Option<Vec<StateRoot>>, std::io::Error> {
@@ -179,6 +179,7 @@ pub fn migrate_9_to_10(path: &String, is_archival: bool) {
                 header.inner.gas_used,
                 header.inner.gas_limit,
                 header.inner.balance_burnt,
+                0,
                 header.inner.tx_root,
                 header.inner.validator_proposals.clone(),
                 transactions,
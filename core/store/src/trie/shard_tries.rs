@@ -231,6 +231,10 @@ impl WrappedTrieChanges {
         self.tries.apply_insertions(&self.trie_changes, self.shard_id, store_update)
     }
 
+    pub fn state_changes(&self) -> &[RawStateChangesWithTrieKey] {
+        &self.state_changes
+    }
+
     /// Save state changes into Store.
     ///
     /// NOTE: the changes are drained from `self`.
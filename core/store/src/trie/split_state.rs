@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptSchedule};
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::types::{RawStateChangesWithTrieKey, ShardId, StateRoot};
+
+use crate::{get, ShardTries, StorageError, StoreUpdate, Trie, TrieChanges, TrieUpdate};
+
+/// Records of the state of one shard, keyed by the raw trie key.
+type ShardRecords = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// Number of records added to the states of the split shards at once while they are built. The
+/// trie nodes of a batch are kept in memory until it's committed.
+const SPLIT_STATE_BATCH_SIZE: usize = 10_000;
+
+impl ShardTries {
+    /// Builds the states of the shards of `shard_layout` that are split from the given shard of
+    /// `prev_shard_layout`, from its state at `state_root`.
+    ///
+    /// The records of an account move to the shard of the account. The delayed receipts are
+    /// queued in the shard of their receiver and the scheduled receipts are kept in the shard of
+    /// the account that scheduled them, in the same order. The buffered outgoing receipts are
+    /// kept by the first split shard, because it takes over the outgoing receipts of the parent
+    /// shard, and are buffered for the new shard of their receiver.
+    ///
+    /// The records are added in batches that are committed to the store right away, so the state
+    /// is never kept in memory as a whole. The trie nodes replaced by a batch are removed, so
+    /// only the nodes of the returned states are left in the store.
+    pub fn build_split_states(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        prev_shard_layout: &ShardLayout,
+        shard_layout: &ShardLayout,
+    ) -> Result<HashMap<ShardId, StateRoot>, StorageError> {
+        self.build_split_states_in_batches(
+            shard_id,
+            state_root,
+            prev_shard_layout,
+            shard_layout,
+            SPLIT_STATE_BATCH_SIZE,
+        )
+    }
+
+    fn build_split_states_in_batches(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        prev_shard_layout: &ShardLayout,
+        shard_layout: &ShardLayout,
+        batch_size: usize,
+    ) -> Result<HashMap<ShardId, StateRoot>, StorageError> {
+        let mut state_roots: HashMap<ShardId, StateRoot> = shard_layout
+            .get_split_shard_ids(shard_id)
+            .into_iter()
+            .map(|split_shard_id| (split_shard_id, Trie::empty_root()))
+            .collect();
+        let mut records: HashMap<ShardId, ShardRecords> = HashMap::new();
+        let mut num_records = 0;
+        let trie = self.get_view_trie_for_shard(shard_id);
+        for item in trie.iter(state_root)? {
+            let (key, value) = item?;
+            // The queues of the delayed and buffered receipts are rebuilt below.
+            if let Some(split_shard_id) = get_split_shard_id(&key, &value, shard_layout)? {
+                records.entry(split_shard_id).or_default().push((key, Some(value)));
+                num_records += 1;
+                if num_records == batch_size {
+                    let batch = std::mem::take(&mut records);
+                    self.add_split_state_records(shard_id, &mut state_roots, batch)?;
+                    num_records = 0;
+                }
+            }
+        }
+        let state_update = self.new_trie_update_view(shard_id, *state_root);
+        for (split_shard_id, queue_records) in
+            get_queue_records(&state_update, shard_id, prev_shard_layout, shard_layout)?
+        {
+            records.entry(split_shard_id).or_default().extend(queue_records);
+        }
+        self.add_split_state_records(shard_id, &mut state_roots, records)?;
+        Ok(state_roots)
+    }
+
+    /// Adds the records to the states of the split shards and commits the new trie nodes,
+    /// removing the ones they replace.
+    fn add_split_state_records(
+        &self,
+        shard_id: ShardId,
+        state_roots: &mut HashMap<ShardId, StateRoot>,
+        records: HashMap<ShardId, ShardRecords>,
+    ) -> Result<(), StorageError> {
+        let mut store_update = StoreUpdate::new_with_tries(self.clone());
+        for (split_shard_id, shard_records) in records {
+            let state_root = state_roots
+                .get_mut(&split_shard_id)
+                .ok_or_else(|| foreign_record_error(split_shard_id, shard_id))?;
+            let trie_changes = self
+                .get_view_trie_for_shard(split_shard_id)
+                .update(state_root, shard_records.into_iter())?;
+            let (update, new_state_root) = self.apply_all(&trie_changes, split_shard_id)?;
+            store_update.merge(update);
+            *state_root = new_state_root;
+        }
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)
+    }
+
+    /// Applies the changes of the state of the given shard in a block to the states of the shards
+    /// split from it, which are at `split_state_roots` before the block. The queues of the delayed
+    /// and buffered receipts are rebuilt from the state of the shard after the block, at
+    /// `state_root`, if the block changed them.
+    ///
+    /// Returns the trie changes of the states of the split shards.
+    pub fn apply_state_changes_to_split_states(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        split_state_roots: &HashMap<ShardId, StateRoot>,
+        changes: &[RawStateChangesWithTrieKey],
+        prev_shard_layout: &ShardLayout,
+        shard_layout: &ShardLayout,
+    ) -> Result<HashMap<ShardId, TrieChanges>, StorageError> {
+        let mut records: HashMap<ShardId, ShardRecords> = HashMap::new();
+        let mut queues_changed = false;
+        for change in changes {
+            let key = change.trie_key.to_vec();
+            let value = change.changes.last().and_then(|change| change.data.clone());
+            match (&change.trie_key, value) {
+                (TrieKey::DelayedReceiptIndices, _)
+                | (TrieKey::DelayedReceipt { .. }, _)
+                | (TrieKey::BufferedReceiptIndices { .. }, _)
+                | (TrieKey::BufferedReceipt { .. }, _) => queues_changed = true,
+                (_, Some(value)) => {
+                    if let Some(split_shard_id) = get_split_shard_id(&key, &value, shard_layout)? {
+                        records.entry(split_shard_id).or_default().push((key, Some(value)));
+                    }
+                }
+                (_, None) => {
+                    // The shard of a removed scheduled receipt isn't known from its key, so the
+                    // removed records are looked up in the split states.
+                    for (split_shard_id, split_state_root) in split_state_roots {
+                        if self
+                            .get_view_trie_for_shard(*split_shard_id)
+                            .get(split_state_root, &key)?
+                            .is_some()
+                        {
+                            records.entry(*split_shard_id).or_default().push((key.clone(), None));
+                        }
+                    }
+                }
+            }
+        }
+        if queues_changed {
+            for (split_shard_id, split_state_root) in split_state_roots {
+                let split_state_update =
+                    self.new_trie_update_view(*split_shard_id, *split_state_root);
+                let shard_records = records.entry(*split_shard_id).or_default();
+                for key in get_queue_keys(&split_state_update, shard_layout)? {
+                    shard_records.push((key, None));
+                }
+            }
+            let state_update = self.new_trie_update_view(shard_id, *state_root);
+            for (split_shard_id, queue_records) in
+                get_queue_records(&state_update, shard_id, prev_shard_layout, shard_layout)?
+            {
+                records.entry(split_shard_id).or_default().extend(queue_records);
+            }
+        }
+
+        if let Some(split_shard_id) =
+            records.keys().find(|split_shard_id| !split_state_roots.contains_key(split_shard_id))
+        {
+            return Err(foreign_record_error(*split_shard_id, shard_id));
+        }
+        let mut trie_changes = HashMap::new();
+        for (split_shard_id, split_state_root) in split_state_roots {
+            let shard_trie_changes = match records.remove(split_shard_id) {
+                Some(shard_records) => self
+                    .get_trie_for_shard(*split_shard_id)
+                    .update(split_state_root, shard_records.into_iter())?,
+                None => TrieChanges::empty(*split_state_root),
+            };
+            trie_changes.insert(*split_shard_id, shard_trie_changes);
+        }
+        Ok(trie_changes)
+    }
+}
+
+fn foreign_record_error(split_shard_id: ShardId, shard_id: ShardId) -> StorageError {
+    StorageError::StorageInconsistentState(format!(
+        "Record of shard {} found in the state of shard {}",
+        split_shard_id, shard_id
+    ))
+}
+
+/// Returns the split shard a record belongs to: the shard of its account, or the shard of the
+/// account that scheduled the receipt for the scheduled receipts. The other records are the
+/// records of the queues of receipts, which are rebuilt instead, so `None` is returned for them.
+fn get_split_shard_id(
+    key: &[u8],
+    value: &[u8],
+    shard_layout: &ShardLayout,
+) -> Result<Option<ShardId>, StorageError> {
+    let account_id = trie_key_parsers::parse_account_id_from_raw_key(key).map_err(|err| {
+        StorageError::StorageInconsistentState(format!(
+            "Can't parse the account of a trie key: {}",
+            err
+        ))
+    })?;
+    if let Some(account_id) = account_id {
+        return Ok(Some(shard_layout.account_id_to_shard_id(&account_id)));
+    }
+    let scheduled_receipts_prefixes = [
+        trie_key_parsers::get_raw_prefix_for_scheduled_receipts(&ReceiptSchedule::BlockHeight(0)),
+        trie_key_parsers::get_raw_prefix_for_scheduled_receipts(&ReceiptSchedule::BlockTimestamp(
+            0,
+        )),
+    ];
+    if scheduled_receipts_prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+        let receipt = Receipt::try_from_slice(value).map_err(|_| {
+            StorageError::StorageInconsistentState(
+                "Can't deserialize a scheduled receipt".to_string(),
+            )
+        })?;
+        return Ok(Some(shard_layout.account_id_to_shard_id(&receipt.predecessor_id)));
+    }
+    Ok(None)
+}
+
+/// Returns the records of the queues of the delayed and buffered receipts of the shards split
+/// from the given shard, rebuilt from the queues in its state.
+fn get_queue_records(
+    state_update: &TrieUpdate,
+    shard_id: ShardId,
+    prev_shard_layout: &ShardLayout,
+    shard_layout: &ShardLayout,
+) -> Result<HashMap<ShardId, ShardRecords>, StorageError> {
+    let split_shard_ids = shard_layout.get_split_shard_ids(shard_id);
+    let first_split_shard_id = *split_shard_ids.first().ok_or_else(|| {
+        StorageError::StorageInconsistentState(format!(
+            "Shard {} is not split into any shard",
+            shard_id
+        ))
+    })?;
+    let mut records: HashMap<ShardId, ShardRecords> = HashMap::new();
+
+    let mut delayed_receipts: HashMap<ShardId, Vec<Receipt>> = HashMap::new();
+    let indices: DelayedReceiptIndices =
+        get(state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+    for index in indices.first_index..indices.next_available_index {
+        let receipt: Receipt =
+            get(state_update, &TrieKey::DelayedReceipt { index })?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed receipt #{} should be in the state",
+                    index
+                ))
+            })?;
+        delayed_receipts
+            .entry(shard_layout.account_id_to_shard_id(&receipt.receiver_id))
+            .or_default()
+            .push(receipt);
+    }
+    for (split_shard_id, receipts) in delayed_receipts {
+        if !split_shard_ids.contains(&split_shard_id) {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "Delayed receipt of shard {} found in the state of shard {}",
+                split_shard_id, shard_id
+            )));
+        }
+        push_queue(
+            records.entry(split_shard_id).or_default(),
+            receipts,
+            |index| TrieKey::DelayedReceipt { index },
+            TrieKey::DelayedReceiptIndices,
+        );
+    }
+
+    let mut buffered_receipts: HashMap<ShardId, Vec<Receipt>> = HashMap::new();
+    for receiver_shard_id in prev_shard_layout.shard_ids() {
+        let indices: DelayedReceiptIndices =
+            get(state_update, &TrieKey::BufferedReceiptIndices { receiver_shard_id })?
+                .unwrap_or_default();
+        for index in indices.first_index..indices.next_available_index {
+            let receipt: Receipt =
+                get(state_update, &TrieKey::BufferedReceipt { receiver_shard_id, index })?
+                    .ok_or_else(|| {
+                        StorageError::StorageInconsistentState(format!(
+                            "Buffered receipt #{} for shard {} should be in the state",
+                            index, receiver_shard_id
+                        ))
+                    })?;
+            buffered_receipts
+                .entry(shard_layout.account_id_to_shard_id(&receipt.receiver_id))
+                .or_default()
+                .push(receipt);
+        }
+    }
+    let shard_records = records.entry(first_split_shard_id).or_default();
+    for (receiver_shard_id, receipts) in buffered_receipts {
+        push_queue(
+            shard_records,
+            receipts,
+            |index| TrieKey::BufferedReceipt { receiver_shard_id, index },
+            TrieKey::BufferedReceiptIndices { receiver_shard_id },
+        );
+    }
+    Ok(records)
+}
+
+/// Returns the keys of the records of the queues of the delayed and buffered receipts in the
+/// state of a split shard.
+fn get_queue_keys(
+    state_update: &TrieUpdate,
+    shard_layout: &ShardLayout,
+) -> Result<Vec<Vec<u8>>, StorageError> {
+    let mut keys = vec![];
+    let indices: Option<DelayedReceiptIndices> =
+        get(state_update, &TrieKey::DelayedReceiptIndices)?;
+    if let Some(indices) = indices {
+        for index in indices.first_index..indices.next_available_index {
+            keys.push(TrieKey::DelayedReceipt { index }.to_vec());
+        }
+        keys.push(TrieKey::DelayedReceiptIndices.to_vec());
+    }
+    for receiver_shard_id in shard_layout.shard_ids() {
+        let indices: Option<DelayedReceiptIndices> =
+            get(state_update, &TrieKey::BufferedReceiptIndices { receiver_shard_id })?;
+        if let Some(indices) = indices {
+            for index in indices.first_index..indices.next_available_index {
+                keys.push(TrieKey::BufferedReceipt { receiver_shard_id, index }.to_vec());
+            }
+            keys.push(TrieKey::BufferedReceiptIndices { receiver_shard_id }.to_vec());
+        }
+    }
+    Ok(keys)
+}
+
+/// Adds the records of a queue of receipts that starts from index 0.
+fn push_queue(
+    records: &mut ShardRecords,
+    receipts: Vec<Receipt>,
+    receipt_key: impl Fn(u64) -> TrieKey,
+    indices_key: TrieKey,
+) {
+    let indices =
+        DelayedReceiptIndices { first_index: 0, next_available_index: receipts.len() as u64 };
+    for (index, receipt) in receipts.into_iter().enumerate() {
+        records.push((
+            receipt_key(index as u64).to_vec(),
+            Some(receipt.try_to_vec().expect("Borsh serializer is not expected to ever fail")),
+        ));
+    }
+    records.push((
+        indices_key.to_vec(),
+        Some(indices.try_to_vec().expect("Borsh serializer is not expected to ever fail")),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_primitives::account::Account;
+    use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptSchedule};
+    use near_primitives::shard_layout::ShardLayout;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::{StateChangeCause, StateRoot};
+
+    use crate::test_utils::create_test_store;
+    use crate::{
+        get, get_account, get_scheduled_receipt, set, set_account, set_scheduled_receipt, DBCol,
+        ShardTries, StoreUpdate, Trie, TrieUpdate,
+    };
+
+    fn set_test_account(state_update: &mut TrieUpdate, account_id: &str) {
+        let account =
+            Account { amount: 1, locked: 0, code_hash: Default::default(), storage_usage: 0 };
+        set_account(state_update, account_id.to_string(), &account);
+    }
+
+    fn set_test_queue(
+        state_update: &mut TrieUpdate,
+        receiver_ids: &[&str],
+        receipt_key: impl Fn(u64) -> TrieKey,
+        indices_key: TrieKey,
+    ) -> Vec<Receipt> {
+        let receipts = receiver_ids
+            .iter()
+            .map(|receiver_id| Receipt::new_balance_refund(&receiver_id.to_string(), 1))
+            .collect::<Vec<_>>();
+        for (index, receipt) in receipts.iter().enumerate() {
+            set(state_update, receipt_key(index as u64), receipt);
+        }
+        let indices =
+            DelayedReceiptIndices { first_index: 0, next_available_index: receipts.len() as u64 };
+        set(state_update, indices_key, &indices);
+        receipts
+    }
+
+    fn commit_state(tries: &ShardTries, mut state_update: TrieUpdate) -> StateRoot {
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize_genesis().unwrap();
+        let (store_update, state_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        state_root
+    }
+
+    #[test]
+    fn test_split_state() {
+        let tries = ShardTries::new(create_test_store(), 2);
+        let mut state_update = tries.new_trie_update(0, Default::default());
+        for account_id in &["alice", "bob", "zoe"] {
+            set_test_account(&mut state_update, account_id);
+        }
+        let receipts = set_test_queue(
+            &mut state_update,
+            &["zoe", "alice", "zoe"],
+            |index| TrieKey::DelayedReceipt { index },
+            TrieKey::DelayedReceiptIndices,
+        );
+        let mut scheduled_receipt = receipts[1].clone();
+        scheduled_receipt.predecessor_id = "bob".to_string();
+        set_scheduled_receipt(
+            &mut state_update,
+            ReceiptSchedule::BlockHeight(10),
+            &scheduled_receipt,
+        );
+        let state_root = commit_state(&tries, state_update);
+
+        let shard_layout = ShardLayout::v1(vec!["m".to_string()], Some(vec![0, 0]), 1);
+        let state_roots = tries
+            .build_split_states(0, &state_root, &ShardLayout::v0(1, 0), &shard_layout)
+            .unwrap();
+
+        let left = tries.new_trie_update(0, state_roots[&0]);
+        let right = tries.new_trie_update(1, state_roots[&1]);
+        assert!(get_account(&left, &"alice".to_string()).unwrap().is_some());
+        assert!(get_account(&left, &"bob".to_string()).unwrap().is_some());
+        assert!(get_account(&left, &"zoe".to_string()).unwrap().is_none());
+        assert!(get_account(&right, &"zoe".to_string()).unwrap().is_some());
+        assert!(get_scheduled_receipt(&left, &"bob".to_string(), scheduled_receipt.receipt_id)
+            .unwrap()
+            .is_some());
+
+        let left_indices: DelayedReceiptIndices =
+            get(&left, &TrieKey::DelayedReceiptIndices).unwrap().unwrap();
+        assert_eq!(left_indices.next_available_index, 1);
+        let right_indices: DelayedReceiptIndices =
+            get(&right, &TrieKey::DelayedReceiptIndices).unwrap().unwrap();
+        assert_eq!(right_indices.next_available_index, 2);
+        let receipt: Receipt = get(&right, &TrieKey::DelayedReceipt { index: 1 }).unwrap().unwrap();
+        assert_eq!(receipt, receipts[2]);
+    }
+
+    /// Building the split states in small batches leaves the same states in the store, without
+    /// the trie nodes of the intermediate states.
+    #[test]
+    fn test_build_split_states_in_batches() {
+        let shard_layout = ShardLayout::v1(vec!["m".to_string()], Some(vec![0, 0]), 1);
+        let build = |batch_size: usize| {
+            let tries = ShardTries::new(create_test_store(), 2);
+            let mut state_update = tries.new_trie_update(0, Default::default());
+            for index in 0..50 {
+                set_test_account(&mut state_update, &format!("account{}", index));
+                set_test_account(&mut state_update, &format!("near{}", index));
+            }
+            set_test_queue(
+                &mut state_update,
+                &["account1", "near1", "account2"],
+                |index| TrieKey::DelayedReceipt { index },
+                TrieKey::DelayedReceiptIndices,
+            );
+            let state_root = commit_state(&tries, state_update);
+            let state_roots = tries
+                .build_split_states_in_batches(
+                    0,
+                    &state_root,
+                    &ShardLayout::v0(1, 0),
+                    &shard_layout,
+                    batch_size,
+                )
+                .unwrap();
+            (state_roots, tries.get_store().iter(DBCol::ColState).count())
+        };
+        assert_eq!(build(1), build(usize::MAX));
+    }
+
+    /// Applying the changes of a block to the split states gives the same states as splitting the
+    /// state after the block, including the queues of delayed and buffered receipts.
+    #[test]
+    fn test_apply_state_changes_to_split_states() {
+        let tries = ShardTries::new(create_test_store(), 3);
+        let prev_shard_layout = ShardLayout::v1(vec!["m".to_string()], None, 1);
+        let shard_layout =
+            ShardLayout::v1(vec!["f".to_string(), "m".to_string()], Some(vec![0, 0, 1]), 2);
+        let mut state_update = tries.new_trie_update(0, Trie::empty_root());
+        for account_id in &["alice", "fred"] {
+            set_test_account(&mut state_update, account_id);
+        }
+        let delayed_receipts = set_test_queue(
+            &mut state_update,
+            &["fred", "alice"],
+            |index| TrieKey::DelayedReceipt { index },
+            TrieKey::DelayedReceiptIndices,
+        );
+        set_test_queue(
+            &mut state_update,
+            &["zoe", "zoe"],
+            |index| TrieKey::BufferedReceipt { receiver_shard_id: 1, index },
+            TrieKey::BufferedReceiptIndices { receiver_shard_id: 1 },
+        );
+        let state_root = commit_state(&tries, state_update);
+        let split_state_roots =
+            tries.build_split_states(0, &state_root, &prev_shard_layout, &shard_layout).unwrap();
+
+        // The block adds and removes accounts, processes a delayed receipt and delays another
+        // one, and forwards a buffered receipt.
+        let mut state_update = tries.new_trie_update(0, state_root);
+        set_test_account(&mut state_update, "bob");
+        state_update.remove(TrieKey::Account { account_id: "alice".to_string() });
+        state_update.remove(TrieKey::DelayedReceipt { index: 0 });
+        let receipt = Receipt::new_balance_refund(&"bob".to_string(), 1);
+        set(&mut state_update, TrieKey::DelayedReceipt { index: 2 }, &receipt);
+        set(
+            &mut state_update,
+            TrieKey::DelayedReceiptIndices,
+            &DelayedReceiptIndices { first_index: 1, next_available_index: 3 },
+        );
+        state_update.remove(TrieKey::BufferedReceipt { receiver_shard_id: 1, index: 0 });
+        set(
+            &mut state_update,
+            TrieKey::BufferedReceiptIndices { receiver_shard_id: 1 },
+            &DelayedReceiptIndices { first_index: 1, next_available_index: 2 },
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let (trie_changes, state_changes) = state_update.finalize().unwrap();
+        let (store_update, state_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let split_trie_changes = tries
+            .apply_state_changes_to_split_states(
+                0,
+                &state_root,
+                &split_state_roots,
+                &state_changes,
+                &prev_shard_layout,
+                &shard_layout,
+            )
+            .unwrap();
+        let mut store_update = StoreUpdate::new_with_tries(tries.clone());
+        let mut new_split_state_roots = HashMap::new();
+        for (split_shard_id, trie_changes) in split_trie_changes.iter() {
+            let (update, split_state_root) =
+                tries.apply_all(trie_changes, *split_shard_id).unwrap();
+            store_update.merge(update);
+            new_split_state_roots.insert(*split_shard_id, split_state_root);
+        }
+        store_update.commit().unwrap();
+
+        assert_eq!(
+            new_split_state_roots,
+            tries.build_split_states(0, &state_root, &prev_shard_layout, &shard_layout).unwrap()
+        );
+        let left = tries.new_trie_update(0, new_split_state_roots[&0]);
+        let right = tries.new_trie_update(1, new_split_state_roots[&1]);
+        assert!(get_account(&left, &"alice".to_string()).unwrap().is_none());
+        assert!(get_account(&left, &"bob".to_string()).unwrap().is_some());
+        assert!(get_account(&right, &"fred".to_string()).unwrap().is_some());
+        let left_receipt: Receipt =
+            get(&left, &TrieKey::DelayedReceipt { index: 0 }).unwrap().unwrap();
+        assert_eq!(left_receipt, delayed_receipts[1]);
+        let left_receipt: Receipt =
+            get(&left, &TrieKey::DelayedReceipt { index: 1 }).unwrap().unwrap();
+        assert_eq!(left_receipt, receipt);
+        assert!(get::<DelayedReceiptIndices>(&right, &TrieKey::DelayedReceiptIndices)
+            .unwrap()
+            .is_none());
+        let buffered_indices: DelayedReceiptIndices =
+            get(&left, &TrieKey::BufferedReceiptIndices { receiver_shard_id: 2 }).unwrap().unwrap();
+        assert_eq!(buffered_indices.next_available_index, 1);
+    }
+}
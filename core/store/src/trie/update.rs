@@ -14,6 +14,7 @@ use near_primitives::trie_key::TrieKey;
 use std::rc::Rc;
 
 /// Key-value update. Contains a TrieKey and a value.
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrieKeyValueUpdate {
     pub trie_key: TrieKey,
     pub value: Option<Vec<u8>>,
@@ -114,6 +115,17 @@ impl TrieUpdate {
         self.prospective.clear();
     }
 
+    /// Returns a copy of the changes that are not committed yet.
+    pub fn prospective(&self) -> TrieUpdates {
+        self.prospective.clone()
+    }
+
+    /// Replaces the uncommitted changes with the given ones, e.g. with the result of an earlier
+    /// call to `prospective`.
+    pub fn set_prospective(&mut self, prospective: TrieUpdates) {
+        self.prospective = prospective;
+    }
+
     pub fn finalize(self) -> Result<(TrieChanges, Vec<RawStateChangesWithTrieKey>), StorageError> {
         assert!(self.prospective.is_empty(), "Finalize cannot be called with uncommitted changes.");
         let TrieUpdate { trie, root, committed, .. } = self;
@@ -1,7 +1,7 @@
 use std::convert::TryInto;
 use std::fs;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
@@ -14,7 +14,7 @@ use num_rational::Rational;
 use serde::{Deserialize, Serialize};
 
 use lazy_static::lazy_static;
-use near_chain_configs::{ClientConfig, Genesis, GenesisConfig};
+use near_chain_configs::{ClientConfig, Genesis, GenesisConfig, StatePartsSource};
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 use near_jsonrpc::RpcConfig;
 use near_network::test_utils::open_port;
@@ -23,6 +23,7 @@ use near_network::utils::blacklist_from_iter;
 use near_network::NetworkConfig;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::CryptoHash;
+use near_primitives::remote_signer::RemoteValidatorSigner;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
     AccountId, AccountInfo, Balance, BlockHeightDelta, EpochHeight, Gas, NumBlocks, NumSeats,
@@ -35,6 +36,10 @@ use near_primitives::version::PROTOCOL_VERSION;
 use near_rosetta_rpc::RosettaRpcConfig;
 use near_runtime_configs::RuntimeConfig;
 use near_telemetry::TelemetryConfig;
+use near_vm_logic::VMKind;
+use node_runtime::is_vm_kind_supported;
+
+use crate::passphrase::{PassphraseSource, DEFAULT_PASSPHRASE_ENV};
 
 /// Initial balance used in tests.
 pub const TESTING_INIT_BALANCE: Balance = 1_000_000_000 * NEAR_BASE;
@@ -133,6 +138,7 @@ pub const CONFIG_FILENAME: &str = "config.json";
 pub const GENESIS_CONFIG_FILENAME: &str = "genesis.json";
 pub const NODE_KEY_FILE: &str = "node_key.json";
 pub const VALIDATOR_KEY_FILE: &str = "validator_key.json";
+pub const SLASHING_PROTECTION_FILE: &str = "slashing_protection.json";
 
 pub const MAINNET_TELEMETRY_URL: &str = "https://explorer.mainnet.near.org/api/nodes";
 pub const NETWORK_TELEMETRY_URL: &str = "https://explorer.{}.near.org/api/nodes";
@@ -305,6 +311,10 @@ fn default_view_client_threads() -> usize {
     4
 }
 
+fn default_slashing_protection() -> bool {
+    true
+}
+
 fn default_doomslug_step_period() -> Duration {
     Duration::from_millis(100)
 }
@@ -407,6 +417,28 @@ pub struct Config {
     pub gc_blocks_limit: NumBlocks,
     #[serde(default = "default_view_client_threads")]
     pub view_client_threads: usize,
+    /// Execute every function call on this VM as well and log differences with the default VM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub differential_vm: Option<VMKind>,
+    /// Fetch the state parts during state sync from a local directory or an HTTP file server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_parts_source: Option<StatePartsSource>,
+    /// Bootstrap a new node with epoch sync instead of syncing all the headers from genesis.
+    #[serde(default)]
+    pub epoch_sync_enabled: bool,
+    /// Watch the tracked shards and send challenges for invalid chunks and double signed blocks,
+    /// signed with the validator key of a fisherman account.
+    #[serde(default)]
+    pub fisherman: bool,
+    /// Refuse to sign anything that conflicts with what the validator key signed before, as
+    /// recorded in `slashing_protection.json` next to the validator key file. See
+    /// `export_slashing_protection` and `import_slashing_protection` to move the records.
+    #[serde(default = "default_slashing_protection")]
+    pub slashing_protection: bool,
+    /// Sign with the validator key held by a remote signer listening on this Unix socket instead
+    /// of loading `validator_key_file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer_socket: Option<String>,
 }
 
 impl Default for Config {
@@ -427,6 +459,12 @@ impl Default for Config {
             archive: false,
             gc_blocks_limit: default_gc_blocks_limit(),
             view_client_threads: 4,
+            differential_vm: None,
+            state_parts_source: None,
+            epoch_sync_enabled: false,
+            fisherman: false,
+            slashing_protection: default_slashing_protection(),
+            remote_signer_socket: None,
         }
     }
 }
@@ -446,11 +484,27 @@ impl Config {
             panic!("Failed to write a config file {}", err);
         }
     }
+
+    /// Path of the slashing protection file of the node in `dir`. It is kept next to the validator
+    /// key file rather than in the data directory, so that restoring or resetting the data
+    /// doesn't lose the record of what the key signed.
+    pub fn slashing_protection_file(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.validator_key_file).with_file_name(SLASHING_PROTECTION_FILE)
+    }
 }
 
 impl From<&str> for Config {
     fn from(content: &str) -> Self {
-        serde_json::from_str(content).expect("Failed to deserialize config")
+        let config: Config = serde_json::from_str(content).expect("Failed to deserialize config");
+        if let Some(vm_kind) = config.differential_vm {
+            if !is_vm_kind_supported(vm_kind) {
+                panic!(
+                    "differential_vm {:?} is not supported by this build, compile with '--features wasmtime_vm'",
+                    vm_kind
+                );
+            }
+        }
+        config
     }
 }
 
@@ -594,6 +648,10 @@ impl NearConfig {
                 archive: config.archive,
                 gc_blocks_limit: config.gc_blocks_limit,
                 view_client_threads: config.view_client_threads,
+                state_parts_source: config.state_parts_source,
+                epoch_sync_enabled: config.epoch_sync_enabled,
+                fisherman: config.fisherman,
+                slashing_protection_file: None,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -650,6 +708,17 @@ impl NearConfig {
 }
 
 impl NearConfig {
+    /// Connects to the remote signer if `remote_signer_socket` is set in the config of the node in
+    /// `dir`, and uses it as the validator signer.
+    pub fn connect_remote_signer(&mut self, dir: &Path) -> io::Result<()> {
+        if let Some(remote_signer_socket) = &self.config.remote_signer_socket {
+            let signer = RemoteValidatorSigner::connect(&dir.join(remote_signer_socket))?;
+            self.network_config.account_id = Some(signer.validator_id().clone());
+            self.validator_signer = Some(Arc::new(signer));
+        }
+        Ok(())
+    }
+
     /// Test tool to save configs back to the folder.
     /// Useful for dynamic creating testnet configs and then saving them in different folders.
     pub fn save_to_dir(&self, dir: &Path) {
@@ -657,7 +726,10 @@ impl NearConfig {
 
         self.config.write_to_file(&dir.join(CONFIG_FILENAME));
 
-        if let Some(validator_signer) = &self.validator_signer {
+        // The key of a remote signer stays with the signer process.
+        if let (Some(validator_signer), None) =
+            (&self.validator_signer, &self.config.remote_signer_socket)
+        {
             validator_signer.write_to_file(&dir.join(&self.config.validator_key_file));
         }
 
@@ -1005,23 +1077,59 @@ pub fn download_genesis(url: &String, path: &PathBuf) {
     });
 }
 
+/// Loads the configs from `dir` without ever prompting. The passphrase of encrypted key files is
+/// taken from `DEFAULT_PASSPHRASE_ENV`.
 pub fn load_config(dir: &Path) -> NearConfig {
+    load_config_with_passphrase(dir, &PassphraseSource::Env(DEFAULT_PASSPHRASE_ENV.to_string()))
+}
+
+/// Loads the configs from `dir`, reading the passphrase from `passphrase_source` once if any of
+/// the key files is encrypted. The same passphrase is used for the node and the validator keys.
+pub fn load_config_with_passphrase(dir: &Path, passphrase_source: &PassphraseSource) -> NearConfig {
+    let mut cached_passphrase = None;
+    let mut read_passphrase = |path: &Path| -> Option<String> {
+        if KeyFile::is_encrypted(path)
+            .unwrap_or_else(|err| panic!("Failed to read key file {}: {}", path.display(), err))
+            && cached_passphrase.is_none()
+        {
+            cached_passphrase = Some(
+                passphrase_source
+                    .read(&format!("Passphrase for the key files in {}: ", dir.display()))
+                    .expect("Failed to read the key file passphrase"),
+            );
+        }
+        cached_passphrase.clone()
+    };
     let config = Config::from_file(&dir.join(CONFIG_FILENAME));
     let genesis = if let Some(ref genesis_records_file) = config.genesis_records_file {
         Genesis::from_files(&dir.join(&config.genesis_file), &dir.join(genesis_records_file))
     } else {
         Genesis::from_file(&dir.join(&config.genesis_file))
     };
-    let validator_signer = if dir.join(&config.validator_key_file).exists() {
-        let signer =
-            Arc::new(InMemoryValidatorSigner::from_file(&dir.join(&config.validator_key_file)))
-                as Arc<dyn ValidatorSigner>;
+    // The remote signer is only connected to when the node starts, see
+    // `NearConfig::connect_remote_signer`.
+    let validator_signer = if config.remote_signer_socket.is_some() {
+        None
+    } else if dir.join(&config.validator_key_file).exists() {
+        let path = dir.join(&config.validator_key_file);
+        let passphrase = read_passphrase(&path);
+        let signer = Arc::new(InMemoryValidatorSigner::from_file_with_passphrase(
+            &path,
+            passphrase.as_deref(),
+        )) as Arc<dyn ValidatorSigner>;
         Some(signer)
     } else {
         None
     };
-    let network_signer = InMemorySigner::from_file(&dir.join(&config.node_key_file));
-    NearConfig::new(config, genesis, (&network_signer).into(), validator_signer)
+    let path = dir.join(&config.node_key_file);
+    let passphrase = read_passphrase(&path);
+    let network_signer = InMemorySigner::from_file_with_passphrase(&path, passphrase.as_deref());
+    let slashing_protection_file =
+        if config.slashing_protection { Some(config.slashing_protection_file(dir)) } else { None };
+    let mut near_config =
+        NearConfig::new(config, genesis, (&network_signer).into(), validator_signer);
+    near_config.client_config.slashing_protection_file = slashing_protection_file;
+    near_config
 }
 
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
@@ -1044,3 +1152,52 @@ pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
     };
     NearConfig::new(config, genesis, signer.into(), validator_signer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Initializes the configs of a validator in a temporary directory and encrypts both of its
+    /// key files with `passphrase`.
+    fn init_configs_with_encrypted_keys(passphrase: &str) -> tempfile::TempDir {
+        let dir = tempfile::Builder::new().prefix("encrypted_keys").tempdir().unwrap();
+        init_configs(
+            dir.path(),
+            Some("localnet"),
+            Some("test.near"),
+            Some("test"),
+            1,
+            false,
+            None,
+            false,
+            None,
+        );
+        let config = Config::from_file(&dir.path().join(CONFIG_FILENAME));
+        for key_file in [config.validator_key_file, config.node_key_file].iter() {
+            let path = dir.path().join(key_file);
+            KeyFile::from_file(&path).write_encrypted_to_file(&path, passphrase).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_load_config_with_encrypted_keys() {
+        let dir = init_configs_with_encrypted_keys("secret");
+        let name = "NEAR_TEST_LOAD_CONFIG_PASSPHRASE";
+        std::env::set_var(name, "secret");
+        let near_config =
+            load_config_with_passphrase(dir.path(), &PassphraseSource::Env(name.to_string()));
+        let signer = InMemoryValidatorSigner::from_seed("test.near", KeyType::ED25519, "test");
+        assert_eq!(near_config.validator_signer.unwrap().public_key(), signer.public_key());
+        // The passphrase is read once for both key files.
+        assert!(std::env::var_os(name).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to read the key file passphrase")]
+    fn test_load_config_does_not_prompt() {
+        let dir = init_configs_with_encrypted_keys("secret");
+        std::env::remove_var(DEFAULT_PASSPHRASE_ENV);
+        load_config(dir.path());
+    }
+}
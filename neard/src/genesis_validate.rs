@@ -1,5 +1,6 @@
 use near_chain_configs::Genesis;
 use near_crypto::key_conversion::is_valid_staking_key;
+use near_primitives::checked_feature;
 use near_primitives::state_record::StateRecord;
 use num_rational::Rational;
 use std::collections::{HashMap, HashSet};
@@ -31,6 +32,7 @@ pub fn validate_genesis(genesis: &Genesis) {
     let mut account_ids = HashSet::new();
     let mut access_key_account_ids = HashSet::new();
     let mut contract_account_ids = HashSet::new();
+    let mut contract_abi_account_ids = HashSet::new();
     for record in genesis.records.0.iter() {
         match record {
             StateRecord::Account { account_id, account } => {
@@ -52,6 +54,11 @@ pub fn validate_genesis(genesis: &Genesis) {
                 }
                 contract_account_ids.insert(account_id.clone());
             }
+            StateRecord::ContractAbi { account_id, .. } => {
+                if !contract_abi_account_ids.insert(account_id.clone()) {
+                    panic!("account {} has more than one contract ABI", account_id);
+                }
+            }
             _ => {}
         }
     }
@@ -71,6 +78,13 @@ pub fn validate_genesis(genesis: &Genesis) {
             account_id
         );
     }
+    for account_id in contract_abi_account_ids {
+        assert!(
+            contract_account_ids.contains(&account_id),
+            "contract ABI account {} does not have a contract",
+            account_id
+        );
+    }
     assert!(
         genesis.config.online_max_threshold > genesis.config.online_min_threshold,
         "Online max threshold smaller than min threshold"
@@ -99,6 +113,20 @@ pub fn validate_genesis(genesis: &Genesis) {
         genesis.config.gas_price_adjustment_rate < Rational::from_integer(1),
         "Gas price adjustment rate must be less than 1"
     );
+    if let Some(resharding_shard_layout) = &genesis.config.resharding_shard_layout {
+        assert!(
+            !checked_feature!(
+                "protocol_feature_resharding",
+                Resharding,
+                genesis.config.protocol_version
+            ),
+            "Resharding requires genesis protocol version before the resharding"
+        );
+        assert!(
+            resharding_shard_layout.is_split_of(&genesis.config.shard_layout()),
+            "Resharding shard layout is not a split of the genesis shard layout"
+        );
+    }
 }
 
 #[cfg(test)]
@@ -108,6 +136,7 @@ mod test {
     use near_chain_configs::GenesisRecords;
     use near_crypto::{KeyType, PublicKey};
     use near_primitives::account::{AccessKey, Account};
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::types::AccountInfo;
 
     const VALID_ED25519_RISTRETTO_KEY: &str = "ed25519:KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7";
@@ -237,4 +266,54 @@ mod test {
         ]);
         validate_genesis(&genesis);
     }
+
+    #[test]
+    #[should_panic(expected = "contract ABI account test does not have a contract")]
+    fn test_contract_abi_without_contract() {
+        let mut genesis = Genesis::default();
+        genesis.config.validators = vec![AccountInfo {
+            account_id: "test".to_string(),
+            public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+            amount: 10,
+        }];
+        genesis.config.total_supply = 110;
+        genesis.records = GenesisRecords(vec![
+            StateRecord::Account {
+                account_id: "test".to_string(),
+                account: Account {
+                    amount: 100,
+                    locked: 10,
+                    code_hash: Default::default(),
+                    storage_usage: 0,
+                },
+            },
+            StateRecord::ContractAbi { account_id: "test".to_string(), abi: b"{}".to_vec() },
+        ]);
+        validate_genesis(&genesis);
+    }
+
+    #[test]
+    #[should_panic(expected = "Resharding shard layout is not a split of the genesis shard layout")]
+    fn test_resharding_of_hash_based_shards() {
+        let mut genesis = Genesis::default();
+        genesis.config.validators = vec![AccountInfo {
+            account_id: "test".to_string(),
+            public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+            amount: 10,
+        }];
+        genesis.config.total_supply = 110;
+        genesis.config.num_block_producer_seats_per_shard = vec![1, 1];
+        genesis.config.resharding_shard_layout =
+            Some(ShardLayout::v1(vec!["test".to_string()], Some(vec![0, 0]), 1));
+        genesis.records = GenesisRecords(vec![StateRecord::Account {
+            account_id: "test".to_string(),
+            account: Account {
+                amount: 100,
+                locked: 10,
+                code_hash: Default::default(),
+                storage_usage: 0,
+            },
+        }]);
+        validate_genesis(&genesis);
+    }
 }
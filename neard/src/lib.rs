@@ -17,7 +17,9 @@ use near_rosetta_rpc::start_rosetta_rpc;
 use near_store::{create_store, Store};
 use near_telemetry::TelemetryActor;
 
-pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
+pub use crate::config::{
+    init_configs, load_config, load_config_with_passphrase, load_test_config, NearConfig, NEAR_BASE,
+};
 use crate::migrations::migrate_12_to_13;
 pub use crate::runtime::NightshadeRuntime;
 use near_store::migrations::{
@@ -29,6 +31,7 @@ use near_store::migrations::{
 pub mod config;
 pub mod genesis_validate;
 mod migrations;
+pub mod passphrase;
 mod runtime;
 mod shard_tracker;
 
@@ -194,18 +197,22 @@ pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Arc<
 
 pub fn start_with_config(
     home_dir: &Path,
-    config: NearConfig,
+    mut config: NearConfig,
 ) -> (Addr<ClientActor>, Addr<ViewClientActor>, Vec<Arbiter>) {
+    config.connect_remote_signer(home_dir).expect("Failed to connect to the remote signer");
     let store = init_and_migrate_store(home_dir, &config);
     near_actix_utils::init_stop_on_panic();
 
-    let runtime = Arc::new(NightshadeRuntime::new(
-        home_dir,
-        Arc::clone(&store),
-        &config.genesis,
-        config.client_config.tracked_accounts.clone(),
-        config.client_config.tracked_shards.clone(),
-    ));
+    let runtime = Arc::new(
+        NightshadeRuntime::new(
+            home_dir,
+            Arc::clone(&store),
+            &config.genesis,
+            config.client_config.tracked_accounts.clone(),
+            config.client_config.tracked_shards.clone(),
+        )
+        .with_differential_vm(config.config.differential_vm),
+    );
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
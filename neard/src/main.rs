@@ -2,10 +2,11 @@ use std::convert::TryInto;
 use std::env;
 use std::fs;
 use std::io;
+use std::os::unix::io::RawFd;
 use std::path::Path;
 
 use actix::System;
-use clap::{crate_version, App, AppSettings, Arg, SubCommand};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
 #[cfg(feature = "adversarial")]
 use log::error;
 use log::info;
@@ -13,10 +14,17 @@ use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::EnvFilter;
 
 use git_version::git_version;
+use near_chain::slashing_protection::{
+    get_slashing_protection_records, merge_slashing_protection_records,
+};
+use near_crypto::KeyFile;
 use near_primitives::version::{Version, PROTOCOL_VERSION};
-use neard::config::init_testnet_configs;
+use neard::config::{init_testnet_configs, Config, CONFIG_FILENAME};
 use neard::genesis_validate::validate_genesis;
-use neard::{get_default_home, get_store_path, init_configs, load_config, start_with_config};
+use neard::passphrase::PassphraseSource;
+use neard::{
+    get_default_home, get_store_path, init_configs, load_config_with_passphrase, start_with_config,
+};
 
 fn init_logging(verbose: Option<&str>, ansi: bool) {
     let mut env_filter = EnvFilter::new(
@@ -60,6 +68,53 @@ fn init_logging(verbose: Option<&str>, ansi: bool) {
         .init();
 }
 
+/// Passphrase source given by the `env_arg` or `fd_arg` command line arguments, if any.
+fn passphrase_source(args: &ArgMatches, env_arg: &str, fd_arg: &str) -> Option<PassphraseSource> {
+    if let Some(name) = args.value_of(env_arg) {
+        Some(PassphraseSource::Env(name.to_string()))
+    } else if let Some(fd) = args.value_of(fd_arg) {
+        let fd = fd.parse().expect("Failed to parse file descriptor");
+        // The descriptor is inherited from the parent and nothing else in the process owns it.
+        Some(unsafe { PassphraseSource::from_raw_fd(fd) })
+    } else {
+        None
+    }
+}
+
+/// File descriptor the passphrase given by the `env_arg` or `fd_arg` command line arguments is
+/// read from, with stdin for a passphrase that is prompted for. `None` for an environment
+/// variable.
+fn passphrase_input_fd(args: &ArgMatches, env_arg: &str, fd_arg: &str) -> Option<RawFd> {
+    if args.value_of(env_arg).is_some() {
+        None
+    } else if let Some(fd) = args.value_of(fd_arg) {
+        Some(fd.parse().expect("Failed to parse file descriptor"))
+    } else {
+        Some(libc::STDIN_FILENO)
+    }
+}
+
+/// Writes the encrypted key file next to `path` first and then moves it over, so that the key is
+/// not lost if writing fails halfway. The new file is synced before the rename and the directory
+/// after it, so a crash can't leave an empty or missing key file behind.
+fn write_encrypted_key_file(key_file: &KeyFile, path: &Path, passphrase: &str) {
+    let tmp_path = path.with_extension("tmp");
+    key_file
+        .write_encrypted_to_file(&tmp_path, passphrase)
+        .unwrap_or_else(|err| panic!("Failed to write the encrypted key file: {}", err));
+    fs::File::open(&tmp_path)
+        .and_then(|file| file.sync_all())
+        .expect("Failed to sync the encrypted key file");
+    fs::rename(&tmp_path, path).expect("Failed to replace the key file");
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .expect("Failed to sync the key file directory");
+}
+
 fn main() {
     // We use it to automatically search the for root certificates to perform HTTPS calls
     // (sending telemetry and downloading genesis)
@@ -106,6 +161,26 @@ fn main() {
             .arg(Arg::with_name("rpc-addr").long("rpc-addr").help("Customize RPC listening address (useful for running multiple nodes on the same machine)").takes_value(true))
             .arg(Arg::with_name("telemetry-url").long("telemetry-url").help("Customize telemetry url").takes_value(true))
             .arg(Arg::with_name("archive").long("archive").help("Keep old blocks in the storage (default false)").takes_value(false))
+            .arg(Arg::with_name("passphrase-env").long("passphrase-env").takes_value(true).help("Environment variable with the passphrase of encrypted key files (default NEAR_KEY_PASSPHRASE if set, otherwise prompt)"))
+            .arg(Arg::with_name("passphrase-fd").long("passphrase-fd").takes_value(true).conflicts_with("passphrase-env").help("File descriptor to read the passphrase of encrypted key files from"))
+        )
+        .subcommand(SubCommand::with_name("encrypt_key").about("Encrypts a plain key file in place with a passphrase")
+            .arg(Arg::with_name("file").long("file").takes_value(true).required(true).help("Key file to encrypt, e.g. ~/.near/validator_key.json"))
+            .arg(Arg::with_name("new-passphrase-env").long("new-passphrase-env").takes_value(true).help("Environment variable with the new passphrase (default prompt)"))
+            .arg(Arg::with_name("new-passphrase-fd").long("new-passphrase-fd").takes_value(true).conflicts_with("new-passphrase-env").help("File descriptor to read the new passphrase from"))
+        )
+        .subcommand(SubCommand::with_name("change_key_passphrase").about("Re-encrypts an encrypted key file with a new passphrase")
+            .arg(Arg::with_name("file").long("file").takes_value(true).required(true).help("Encrypted key file"))
+            .arg(Arg::with_name("passphrase-env").long("passphrase-env").takes_value(true).help("Environment variable with the current passphrase (default prompt)"))
+            .arg(Arg::with_name("passphrase-fd").long("passphrase-fd").takes_value(true).conflicts_with("passphrase-env").help("File descriptor to read the current passphrase from"))
+            .arg(Arg::with_name("new-passphrase-env").long("new-passphrase-env").takes_value(true).help("Environment variable with the new passphrase (default prompt)"))
+            .arg(Arg::with_name("new-passphrase-fd").long("new-passphrase-fd").takes_value(true).conflicts_with("new-passphrase-env").help("File descriptor to read the new passphrase from"))
+        )
+        .subcommand(SubCommand::with_name("export_slashing_protection").about("Writes the heights and approvals signed by the validator keys of this node to a file")
+            .arg(Arg::with_name("file").long("file").takes_value(true).required(true).help("File to write the records to"))
+        )
+        .subcommand(SubCommand::with_name("import_slashing_protection").about("Merges the heights and approvals signed on another node from a file, before running the validator key moved from there")
+            .arg(Arg::with_name("file").long("file").takes_value(true).required(true).help("File exported with export_slashing_protection on the other node"))
         )
         .subcommand(SubCommand::with_name("unsafe_reset_data").about("(unsafe) Remove all the data, effectively resetting node to genesis state (keeps genesis and config)"))
         .subcommand(SubCommand::with_name("unsafe_reset_all").about("(unsafe) Remove all the config, keys, data and effectively removing all information about the network"))
@@ -188,7 +263,9 @@ fn main() {
         }
         ("run", Some(args)) => {
             // Load configs from home.
-            let mut near_config = load_config(home_dir);
+            let passphrase_source = passphrase_source(args, "passphrase-env", "passphrase-fd")
+                .unwrap_or_else(PassphraseSource::env_or_prompt);
+            let mut near_config = load_config_with_passphrase(home_dir, &passphrase_source);
             validate_genesis(&near_config.genesis);
             // Set current version in client config.
             near_config.client_config.version = version;
@@ -237,6 +314,68 @@ fn main() {
             system.run().unwrap();
             arbiters.into_iter().for_each(|mut a| a.join().unwrap());
         }
+        ("export_slashing_protection", Some(args)) => {
+            let file = args.value_of("file").unwrap();
+            let config = Config::from_file(&home_dir.join(CONFIG_FILENAME));
+            let records =
+                get_slashing_protection_records(&config.slashing_protection_file(home_dir))
+                    .expect("Failed to read slashing protection records");
+            fs::write(file, serde_json::to_string_pretty(&records).unwrap())
+                .expect("Failed to write slashing protection file");
+            info!(target: "near", "Exported slashing protection records of {} validators to {}", records.len(), file);
+        }
+        ("import_slashing_protection", Some(args)) => {
+            let file = args.value_of("file").unwrap();
+            let content =
+                fs::read_to_string(file).expect("Failed to read slashing protection file");
+            let records =
+                serde_json::from_str(&content).expect("Failed to parse slashing protection file");
+            let config = Config::from_file(&home_dir.join(CONFIG_FILENAME));
+            merge_slashing_protection_records(&config.slashing_protection_file(home_dir), &records)
+                .expect("Failed to save slashing protection records");
+            info!(target: "near", "Imported slashing protection records of {} validators from {}", records.len(), file);
+        }
+        ("encrypt_key", Some(args)) => {
+            let path = Path::new(args.value_of("file").unwrap());
+            if KeyFile::is_encrypted(path).expect("Failed to read the key file") {
+                panic!("Key file {} is already encrypted", path.display());
+            }
+            let key_file = KeyFile::from_file(path);
+            let new_passphrase = passphrase_source(args, "new-passphrase-env", "new-passphrase-fd")
+                .unwrap_or(PassphraseSource::Prompt)
+                .read_new("New passphrase: ")
+                .expect("Failed to read the new passphrase");
+            write_encrypted_key_file(&key_file, path, &new_passphrase);
+            info!(target: "near", "Encrypted key file {}", path.display());
+        }
+        ("change_key_passphrase", Some(args)) => {
+            let path = Path::new(args.value_of("file").unwrap());
+            if !KeyFile::is_encrypted(path).expect("Failed to read the key file") {
+                panic!("Key file {} is not encrypted, use encrypt_key instead", path.display());
+            }
+            // Both passphrases may be prompted for on a terminal, but a descriptor is read until
+            // EOF, so nothing would be left for the other passphrase.
+            let prompts = !args.is_present("passphrase-env") && !args.is_present("passphrase-fd");
+            let new_prompts =
+                !args.is_present("new-passphrase-env") && !args.is_present("new-passphrase-fd");
+            let input_fd = passphrase_input_fd(args, "passphrase-env", "passphrase-fd");
+            let new_input_fd = passphrase_input_fd(args, "new-passphrase-env", "new-passphrase-fd");
+            if input_fd.is_some() && input_fd == new_input_fd && !(prompts && new_prompts) {
+                panic!("The current and the new passphrase can't be read from the same file descriptor or stdin");
+            }
+            let passphrase = passphrase_source(args, "passphrase-env", "passphrase-fd")
+                .unwrap_or(PassphraseSource::Prompt)
+                .read("Current passphrase: ")
+                .expect("Failed to read the passphrase");
+            let key_file = KeyFile::try_from_file(path, Some(&passphrase))
+                .unwrap_or_else(|err| panic!("Failed to decrypt the key file: {}", err));
+            let new_passphrase = passphrase_source(args, "new-passphrase-env", "new-passphrase-fd")
+                .unwrap_or(PassphraseSource::Prompt)
+                .read_new("New passphrase: ")
+                .expect("Failed to read the new passphrase");
+            write_encrypted_key_file(&key_file, path, &new_passphrase);
+            info!(target: "near", "Changed the passphrase of key file {}", path.display());
+        }
         ("unsafe_reset_data", Some(_args)) => {
             let store_path = get_store_path(home_dir);
             info!(target: "near", "Removing all data from {}", store_path);
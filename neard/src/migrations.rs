@@ -3,7 +3,7 @@ use borsh::BorshDeserialize;
 use near_chain::chain::collect_receipts_from_response;
 use near_chain::types::ApplyTransactionResult;
 use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
-use near_primitives::sharding::{ChunkHash, ShardChunkHeader, ShardChunkV1};
+use near_primitives::sharding::{ChunkHash, CongestionInfo, ShardChunkHeader, ShardChunkV1};
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::{BlockHeight, ShardId};
 use near_store::migrations::set_store_version;
@@ -60,6 +60,7 @@ fn apply_block_at_height(
             chunk_header.gas_limit(),
             &block.header().challenges_result(),
             *block.header().random_value(),
+            &CongestionInfo::default(),
         )
         .unwrap();
     let (_, outcome_paths) = ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
@@ -0,0 +1,163 @@
+//! Sources of the passphrase for encrypted key files.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+
+/// Environment variable with the passphrase, used when no source is given explicitly.
+pub const DEFAULT_PASSPHRASE_ENV: &str = "NEAR_KEY_PASSPHRASE";
+
+/// Where to take the passphrase of the encrypted key files from.
+#[derive(Debug)]
+pub enum PassphraseSource {
+    /// Ask on the terminal without echoing the input.
+    Prompt,
+    /// Read from the environment variable with the given name.
+    Env(String),
+    /// Read until EOF from an inherited file descriptor, e.g. `--passphrase-fd 3 3<file`. The
+    /// descriptor is closed when the source is dropped.
+    Fd(File),
+}
+
+impl PassphraseSource {
+    /// `DEFAULT_PASSPHRASE_ENV` if it is set, otherwise the terminal. Only meant for commands that
+    /// are run by hand, such as `run`.
+    pub fn env_or_prompt() -> Self {
+        if std::env::var_os(DEFAULT_PASSPHRASE_ENV).is_some() {
+            PassphraseSource::Env(DEFAULT_PASSPHRASE_ENV.to_string())
+        } else {
+            PassphraseSource::Prompt
+        }
+    }
+
+    /// Reads from the inherited file descriptor `fd`.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor has to be open and not owned by anything else, in particular by another
+    /// source, since it is closed when the source is dropped.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        PassphraseSource::Fd(File::from_raw_fd(fd))
+    }
+
+    /// Reads the passphrase of an existing key file. An environment variable is removed once it
+    /// is read, so that it isn't passed on to child processes. A file descriptor is read until
+    /// EOF, so reading it again returns an empty passphrase.
+    pub fn read(&self, prompt: &str) -> io::Result<String> {
+        match self {
+            PassphraseSource::Prompt => prompt_passphrase(prompt),
+            PassphraseSource::Env(name) => {
+                let passphrase = std::env::var(name).map_err(|err| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("{}: {}", name, err))
+                })?;
+                std::env::remove_var(name);
+                Ok(passphrase)
+            }
+            PassphraseSource::Fd(file) => {
+                let mut file: &File = file;
+                let mut passphrase = String::new();
+                file.read_to_string(&mut passphrase)?;
+                Ok(trim_newline(passphrase))
+            }
+        }
+    }
+
+    /// Reads a passphrase to encrypt a key file with. On the terminal it has to be typed twice.
+    pub fn read_new(&self, prompt: &str) -> io::Result<String> {
+        let passphrase = self.read(prompt)?;
+        if passphrase.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty passphrase"));
+        }
+        if let PassphraseSource::Prompt = self {
+            if prompt_passphrase("Repeat the passphrase: ")? != passphrase {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Passphrases don't match"));
+            }
+        }
+        Ok(passphrase)
+    }
+}
+
+fn trim_newline(mut line: String) -> String {
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+/// Disables echo on the terminal for as long as it is alive.
+struct EchoGuard {
+    termios: Option<libc::termios>,
+}
+
+impl EchoGuard {
+    fn new() -> Self {
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                // Not a terminal, e.g. the passphrase is piped in.
+                return EchoGuard { termios: None };
+            }
+            let mut silent = termios;
+            silent.c_lflag &= !libc::ECHO;
+            silent.c_lflag |= libc::ECHONL;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &silent);
+            EchoGuard { termios: Some(termios) }
+        }
+    }
+}
+
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        if let Some(termios) = &self.termios {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios);
+            }
+        }
+    }
+}
+
+fn prompt_passphrase(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let _guard = EchoGuard::new();
+    let mut passphrase = String::new();
+    BufReader::new(io::stdin()).read_line(&mut passphrase)?;
+    Ok(trim_newline(passphrase))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::io::IntoRawFd;
+
+    use super::*;
+
+    #[test]
+    fn test_env_passphrase_is_removed_after_reading() {
+        let name = "NEAR_TEST_ENV_PASSPHRASE";
+        std::env::set_var(name, "secret");
+        let source = PassphraseSource::Env(name.to_string());
+        assert_eq!(source.read("").unwrap(), "secret");
+        assert!(std::env::var_os(name).is_none());
+        assert_eq!(source.read("").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_fd_passphrase() {
+        let dir = tempfile::Builder::new().prefix("passphrase").tempdir().unwrap();
+        let path = dir.path().join("passphrase");
+        std::fs::write(&path, "secret\n").unwrap();
+        let fd = File::open(&path).unwrap().into_raw_fd();
+        let source = unsafe { PassphraseSource::from_raw_fd(fd) };
+        assert_eq!(source.read("").unwrap(), "secret");
+        // The descriptor stays open until the source is dropped.
+        assert_eq!(source.read("").unwrap(), "");
+    }
+
+    #[test]
+    fn test_empty_new_passphrase() {
+        let name = "NEAR_TEST_EMPTY_PASSPHRASE";
+        std::env::set_var(name, "");
+        let err = PassphraseSource::Env(name.to_string()).read_new("").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
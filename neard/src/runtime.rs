@@ -20,33 +20,40 @@ use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{Approval, ApprovalInner};
 use near_primitives::challenge::ChallengesResult;
-use near_primitives::epoch_manager::{BlockInfo, EpochConfig};
+use near_primitives::epoch_manager::{BlockInfo, EpochConfig, EpochInfo, EpochSyncData};
 use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::receipt::Receipt;
-use near_primitives::sharding::ChunkHash;
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt};
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::sharding::{ChunkHash, CongestionInfo};
 use near_primitives::state_record::StateRecord;
-use near_primitives::transaction::SignedTransaction;
-use near_primitives::trie_key::trie_key_parsers;
+use near_primitives::transaction::{Action, SignedTransaction};
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, Gas,
-    MerkleHash, NumShards, ShardId, StateChangeCause, StateRoot, StateRootNode, ValidatorStake,
+    MerkleHash, NumShards, RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+    StateRootNode, ValidatorStake,
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
-    AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryError, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewStateResult,
+    AccessKeyInfoView, CallResult, CallerContext, ContractAbiView, EpochValidatorHistoryView,
+    EpochValidatorInfo, QueryError, QueryRequest, QueryResponse, QueryResponseKind, StateOverrides,
+    StorageStakingView, StorageUsageView, ViewStateResult,
 };
 use near_store::{
-    get_access_key_raw, get_genesis_hash, get_genesis_state_roots, set_genesis_hash,
-    set_genesis_state_roots, ColState, PartialStorage, ShardTries, Store, Trie, WrappedTrieChanges,
+    get, get_access_key_raw, get_genesis_hash, get_genesis_state_roots, set_genesis_hash,
+    set_genesis_state_roots, ColState, PartialStorage, ShardTries, Store, Trie, TrieChanges,
+    WrappedTrieChanges,
 };
+use near_vm_logic::VMKind;
 use node_runtime::adapter::ViewRuntimeAdapter;
 use node_runtime::cache::StoreCompiledContractCache;
+use node_runtime::config::{total_prepaid_exec_fees, total_send_fees};
 use node_runtime::state_viewer::TrieViewer;
+use node_runtime::state_viewer::{DEFAULT_STORAGE_USAGE_PREFIX_LEN, MAX_STORAGE_USAGE_QUERY_KEYS};
 use node_runtime::{
-    validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
-    ValidatorAccountsUpdate,
+    check_receiver_congestion, is_vm_kind_supported, validate_transaction,
+    verify_and_charge_transaction, ApplyState, Runtime, ValidatorAccountsUpdate,
 };
 
 use crate::shard_tracker::{account_id_to_shard_id, ShardTracker};
@@ -125,6 +132,8 @@ pub struct NightshadeRuntime {
     epoch_manager: SafeEpochManager,
     shard_tracker: ShardTracker,
     genesis_state_roots: Vec<StateRoot>,
+    /// If set, function calls are additionally executed on this VM to compare the results.
+    differential_vm: Option<VMKind>,
 }
 
 impl NightshadeRuntime {
@@ -139,10 +148,11 @@ impl NightshadeRuntime {
         let trie_viewer = TrieViewer::new();
         let genesis_config = genesis.config.clone();
         let genesis_runtime_config = Arc::new(genesis_config.runtime_config.clone());
-        let num_shards = genesis.config.num_block_producer_seats_per_shard.len() as NumShards;
+        let shard_layout = genesis.config.shard_layout();
+        let num_shards = shard_layout.num_shards();
         let initial_epoch_config = EpochConfig {
             epoch_length: genesis.config.epoch_length,
-            num_shards,
+            shard_layout,
             num_block_producer_seats: genesis.config.num_block_producer_seats,
             num_block_producer_seats_per_shard: genesis
                 .config
@@ -172,32 +182,38 @@ impl NightshadeRuntime {
         };
         let state_roots =
             Self::initialize_genesis_state_if_needed(store.clone(), home_dir, genesis);
-        let tries = ShardTries::new(
+        // The tries must cover the shards of both layouts, since the states of the split shards
+        // are built before the switch.
+        let max_num_shards = genesis
+            .config
+            .resharding_shard_layout
+            .as_ref()
+            .map_or(num_shards, |shard_layout| shard_layout.num_shards().max(num_shards));
+        let tries = ShardTries::new(store.clone(), max_num_shards);
+        let mut epoch_manager = EpochManager::new(
             store.clone(),
-            genesis.config.num_block_producer_seats_per_shard.len() as NumShards,
-        );
-        let epoch_manager = Arc::new(RwLock::new(
-            EpochManager::new(
-                store.clone(),
-                initial_epoch_config,
-                genesis_config.protocol_version,
-                reward_calculator,
-                genesis_config
-                    .validators
-                    .iter()
-                    .map(|account_info| ValidatorStake {
-                        account_id: account_info.account_id.clone(),
-                        public_key: account_info
-                            .public_key
-                            .clone()
-                            .try_into()
-                            .expect("Failed to deserialize validator public key"),
-                        stake: account_info.amount,
-                    })
-                    .collect(),
-            )
-            .expect("Failed to start Epoch Manager"),
-        ));
+            initial_epoch_config,
+            genesis_config.protocol_version,
+            reward_calculator,
+            genesis_config
+                .validators
+                .iter()
+                .map(|account_info| ValidatorStake {
+                    account_id: account_info.account_id.clone(),
+                    public_key: account_info
+                        .public_key
+                        .clone()
+                        .try_into()
+                        .expect("Failed to deserialize validator public key"),
+                    stake: account_info.amount,
+                })
+                .collect(),
+        )
+        .expect("Failed to start Epoch Manager");
+        if let Some(shard_layout) = &genesis_config.resharding_shard_layout {
+            epoch_manager = epoch_manager.with_resharding_shard_layout(shard_layout.clone());
+        }
+        let epoch_manager = Arc::new(RwLock::new(epoch_manager));
         let shard_tracker = ShardTracker::new(
             initial_tracking_accounts,
             initial_tracking_shards,
@@ -215,7 +231,22 @@ impl NightshadeRuntime {
             epoch_manager: SafeEpochManager(epoch_manager),
             shard_tracker,
             genesis_state_roots: state_roots,
+            differential_vm: None,
+        }
+    }
+
+    /// Executes every function call on `vm_kind` in addition to the default VM and reports any
+    /// differences in the results. Does not affect the outcome of applying chunks.
+    pub fn with_differential_vm(mut self, vm_kind: Option<VMKind>) -> Self {
+        if let Some(vm_kind) = vm_kind {
+            assert!(
+                is_vm_kind_supported(vm_kind),
+                "Differential VM {:?} is not supported by this build, compile with '--features wasmtime_vm'",
+                vm_kind
+            );
         }
+        self.differential_vm = vm_kind;
+        self
     }
 
     fn get_epoch_height_from_prev_block(
@@ -361,7 +392,12 @@ impl NightshadeRuntime {
         gas_limit: Gas,
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
+        congestion_info: &CongestionInfo,
     ) -> Result<ApplyTransactionResult, Error> {
+        let shard_layout = {
+            let epoch_id = self.get_epoch_id_from_prev_block(prev_block_hash)?;
+            self.get_shard_layout(&epoch_id)?
+        };
         let validator_accounts_update = {
             let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
             debug!(target: "runtime",
@@ -373,7 +409,9 @@ impl NightshadeRuntime {
             let mut slashing_info: HashMap<_, _> = challenges_result
                 .iter()
                 .filter_map(|s| {
-                    if self.account_id_to_shard_id(&s.account_id) == shard_id && !s.is_double_sign {
+                    if shard_layout.account_id_to_shard_id(&s.account_id) == shard_id
+                        && !s.is_double_sign
+                    {
                         Some((s.account_id.clone(), None))
                     } else {
                         None
@@ -386,22 +424,28 @@ impl NightshadeRuntime {
                     epoch_manager.compute_stake_return_info(prev_block_hash)?;
                 let stake_info = stake_info
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        shard_layout.account_id_to_shard_id(account_id) == shard_id
+                    })
                     .collect();
                 let validator_rewards = validator_reward
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        shard_layout.account_id_to_shard_id(account_id) == shard_id
+                    })
                     .collect();
                 let last_proposals = last_validator_proposals
                     .iter()
-                    .filter(|v| self.account_id_to_shard_id(&v.account_id) == shard_id)
+                    .filter(|v| shard_layout.account_id_to_shard_id(&v.account_id) == shard_id)
                     .fold(HashMap::new(), |mut acc, v| {
                         acc.insert(v.account_id.clone(), v.stake);
                         acc
                     });
                 let double_sign_slashing_info: HashMap<_, _> = double_sign_slashing_info
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        shard_layout.account_id_to_shard_id(account_id) == shard_id
+                    })
                     .map(|(account_id, stake)| (account_id, Some(stake)))
                     .collect();
                 slashing_info.extend(double_sign_slashing_info);
@@ -412,7 +456,9 @@ impl NightshadeRuntime {
                     protocol_treasury_account_id: Some(
                         self.genesis_config.protocol_treasury_account.clone(),
                     )
-                    .filter(|account_id| self.account_id_to_shard_id(account_id) == shard_id),
+                    .filter(|account_id| {
+                        shard_layout.account_id_to_shard_id(account_id) == shard_id
+                    }),
                     slashing_info,
                 })
             } else if !challenges_result.is_empty() {
@@ -434,6 +480,7 @@ impl NightshadeRuntime {
 
         let apply_state = ApplyState {
             block_index: block_height,
+            shard_id,
             last_block_hash: *prev_block_hash,
             epoch_id,
             epoch_height,
@@ -447,6 +494,8 @@ impl NightshadeRuntime {
                 current_protocol_version,
             ),
             cache: Some(Arc::new(StoreCompiledContractCache { store: self.store.clone() })),
+            differential_vm: self.differential_vm,
+            congestion_info: congestion_info.clone(),
         };
 
         let apply_result = self
@@ -489,7 +538,7 @@ impl NightshadeRuntime {
         let mut receipt_result = HashMap::default();
         for receipt in apply_result.outgoing_receipts {
             receipt_result
-                .entry(self.account_id_to_shard_id(&receipt.receiver_id))
+                .entry(shard_layout.account_id_to_shard_id(&receipt.receiver_id))
                 .or_insert_with(|| vec![])
                 .push(receipt);
         }
@@ -513,6 +562,21 @@ impl NightshadeRuntime {
 
         Ok(result)
     }
+
+    /// Returns the shard layouts of the epoch of the given block and of the next epoch, whose
+    /// shards are split from the shards of the former.
+    fn get_split_shard_layouts(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<(ShardLayout, ShardLayout), Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        let epoch_id = epoch_manager.get_epoch_id(block_hash)?;
+        let next_epoch_id = epoch_manager.get_next_epoch_id(block_hash)?;
+        Ok((
+            epoch_manager.get_shard_layout(&epoch_id)?,
+            epoch_manager.get_shard_layout(&next_epoch_id)?,
+        ))
+    }
 }
 
 pub fn state_record_to_shard_id(state_record: &StateRecord, num_shards: NumShards) -> ShardId {
@@ -520,11 +584,15 @@ pub fn state_record_to_shard_id(state_record: &StateRecord, num_shards: NumShard
         StateRecord::Account { account_id, .. }
         | StateRecord::AccessKey { account_id, .. }
         | StateRecord::Contract { account_id, .. }
+        | StateRecord::ContractAbi { account_id, .. }
         | StateRecord::ReceivedData { account_id, .. }
         | StateRecord::Data { account_id, .. } => account_id_to_shard_id(account_id, num_shards),
         StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
             account_id_to_shard_id(&receipt.receiver_id, num_shards)
         }
+        StateRecord::ScheduledReceipt { receipt, .. } => {
+            account_id_to_shard_id(&receipt.predecessor_id, num_shards)
+        }
     }
 }
 
@@ -569,9 +637,11 @@ impl RuntimeAdapter for NightshadeRuntime {
     fn validate_tx(
         &self,
         gas_price: Balance,
-        state_root: Option<StateRoot>,
+        shard_state_root: Option<(ShardId, StateRoot)>,
         transaction: &SignedTransaction,
         verify_signature: bool,
+        block_height: BlockHeight,
+        congestion_info: &CongestionInfo,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error> {
         let runtime_config = RuntimeConfig::from_protocol_version(
@@ -579,8 +649,14 @@ impl RuntimeAdapter for NightshadeRuntime {
             current_protocol_version,
         );
 
-        if let Some(state_root) = state_root {
-            let shard_id = self.account_id_to_shard_id(&transaction.transaction.signer_id);
+        if let Err(err) =
+            check_receiver_congestion(transaction, congestion_info, current_protocol_version)
+        {
+            debug!(target: "runtime", "Tx {:?} validation failed: {:?}", transaction, err);
+            return Ok(Some(err));
+        }
+
+        if let Some((shard_id, state_root)) = shard_state_root {
             let mut state_update = self.get_tries().new_trie_update(shard_id, state_root);
 
             match verify_and_charge_transaction(
@@ -589,6 +665,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 gas_price,
                 &transaction,
                 verify_signature,
+                Some(block_height),
                 current_protocol_version,
             ) {
                 Ok(_) => Ok(None),
@@ -623,14 +700,50 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn get_actions_fee_gas(
+        &self,
+        signer_id: &AccountId,
+        receiver_id: &AccountId,
+        actions: &[Action],
+        current_protocol_version: ProtocolVersion,
+    ) -> Result<Gas, Error> {
+        let runtime_config = RuntimeConfig::from_protocol_version(
+            &self.genesis_runtime_config,
+            current_protocol_version,
+        );
+        let fees = &runtime_config.transaction_costs;
+        let sender_is_receiver = signer_id == receiver_id;
+        let overflow_err =
+            || Error::from(ErrorKind::Other("Integer overflow during fee computation".to_string()));
+        let send_fees = total_send_fees(
+            fees,
+            sender_is_receiver,
+            actions,
+            receiver_id,
+            current_protocol_version,
+        )
+        .map_err(|_| overflow_err())?;
+        let exec_fees =
+            total_prepaid_exec_fees(fees, actions, receiver_id, current_protocol_version)
+                .map_err(|_| overflow_err())?;
+        fees.action_receipt_creation_config
+            .send_fee(sender_is_receiver)
+            .checked_add(fees.action_receipt_creation_config.exec_fee())
+            .and_then(|gas| gas.checked_add(send_fees))
+            .and_then(|gas| gas.checked_add(exec_fees))
+            .ok_or_else(overflow_err)
+    }
+
     fn prepare_transactions(
         &self,
         gas_price: Balance,
         gas_limit: Gas,
         shard_id: ShardId,
         state_root: StateRoot,
+        next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
         chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        congestion_info: &CongestionInfo,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let mut state_update = self.get_tries().new_trie_update(shard_id, state_root);
@@ -651,8 +764,12 @@ impl RuntimeAdapter for NightshadeRuntime {
             if let Some(iter) = pool_iterator.next() {
                 while let Some(tx) = iter.next() {
                     num_checked_transactions += 1;
-                    // Verifying the transaction is on the same chain and hasn't expired yet.
-                    if chain_validate(&tx) {
+                    // Verifying the transaction is on the same chain and hasn't expired yet, and
+                    // that the receiver shard can accept it.
+                    if chain_validate(&tx)
+                        && check_receiver_congestion(&tx, congestion_info, current_protocol_version)
+                            .is_ok()
+                    {
                         // Verifying the validity of the transaction based on the current state.
                         match verify_and_charge_transaction(
                             &runtime_config,
@@ -660,6 +777,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                             gas_price,
                             &tx,
                             false,
+                            Some(next_block_height),
                             current_protocol_version,
                         ) {
                             Ok(verification_result) => {
@@ -686,6 +804,19 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(transactions)
     }
 
+    fn get_delayed_receipts_count(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+    ) -> Result<u64, Error> {
+        let state_update = self.get_tries().new_trie_update(shard_id, *state_root);
+        let delayed_receipts_indices: DelayedReceiptIndices =
+            get(&state_update, &TrieKey::DelayedReceiptIndices)
+                .map_err(|err| Error::from(ErrorKind::StorageError(err)))?
+                .unwrap_or_default();
+        Ok(delayed_receipts_indices.next_available_index - delayed_receipts_indices.first_index)
+    }
+
     fn verify_validator_signature(
         &self,
         epoch_id: &EpochId,
@@ -871,9 +1002,8 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
-    fn num_shards(&self) -> NumShards {
-        // TODO: should be dynamic.
-        self.genesis_config.num_block_producer_seats_per_shard.len() as NumShards
+    fn num_shards(&self, epoch_id: &EpochId) -> Result<NumShards, Error> {
+        Ok(self.get_shard_layout(epoch_id)?.num_shards())
     }
 
     fn num_total_parts(&self) -> usize {
@@ -894,8 +1024,22 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId {
-        account_id_to_shard_id(account_id, self.num_shards())
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        epoch_id: &EpochId,
+    ) -> Result<ShardId, Error> {
+        Ok(self.get_shard_layout(epoch_id)?.account_id_to_shard_id(account_id))
+    }
+
+    fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager.get_shard_layout(epoch_id).map_err(Error::from)
+    }
+
+    fn will_shard_layout_change(&self, parent_hash: &CryptoHash) -> Result<bool, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager.will_shard_layout_change(parent_hash).map_err(Error::from)
     }
 
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<String, Error> {
@@ -986,11 +1130,40 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_epoch_info(epoch_id).is_ok()
     }
 
+    fn get_epoch_sync_data(
+        &self,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<Option<EpochSyncData>, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        Ok(epoch_manager.get_epoch_sync_data(prev_block_hash)?)
+    }
+
+    fn save_epoch_sync_data(
+        &self,
+        block_infos: Vec<(CryptoHash, BlockInfo)>,
+        epoch_infos: Vec<(EpochId, EpochInfo)>,
+    ) -> Result<(), Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager
+            .save_epoch_sync_data(block_infos, epoch_infos)?
+            .commit()
+            .map_err(|err| err.into())
+    }
+
     fn get_epoch_minted_amount(&self, epoch_id: &EpochId) -> Result<Balance, Error> {
         let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
         Ok(epoch_manager.get_epoch_info(epoch_id)?.minted_amount)
     }
 
+    fn get_validator_rewards(
+        &self,
+        last_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Balance>, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        // Rewards for an epoch are stored in the info of the epoch identified by its last block.
+        Ok(epoch_manager.get_epoch_info(&EpochId(*last_block_hash))?.validator_reward.clone())
+    }
+
     fn get_epoch_protocol_version(&self, epoch_id: &EpochId) -> Result<ProtocolVersion, Error> {
         let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
         Ok(epoch_manager.get_epoch_info(epoch_id)?.protocol_version)
@@ -1040,6 +1213,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         gas_limit: Gas,
         challenges: &ChallengesResult,
         random_seed: CryptoHash,
+        congestion_info: &CongestionInfo,
         generate_storage_proof: bool,
     ) -> Result<ApplyTransactionResult, Error> {
         let trie = self.get_trie_for_shard(shard_id);
@@ -1059,6 +1233,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             gas_limit,
             challenges,
             random_seed,
+            congestion_info,
         ) {
             Ok(result) => Ok(result),
             Err(e) => match e.kind() {
@@ -1086,6 +1261,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         gas_limit: Gas,
         challenges: &ChallengesResult,
         random_value: CryptoHash,
+        congestion_info: &CongestionInfo,
     ) -> Result<ApplyTransactionResult, Error> {
         let trie = Trie::from_recorded_storage(partial_storage);
         self.process_state_update(
@@ -1103,6 +1279,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             gas_limit,
             challenges,
             random_value,
+            congestion_info,
         )
     }
 
@@ -1127,7 +1304,13 @@ impl RuntimeAdapter for NightshadeRuntime {
                     Err(e) => Err(e),
                 }
             }
-            QueryRequest::CallFunction { account_id, method_name, args } => {
+            QueryRequest::CallFunction {
+                account_id,
+                method_name,
+                args,
+                state_overrides,
+                caller,
+            } => {
                 let mut logs = vec![];
                 let (epoch_height, current_protocol_version) = {
                     let mut epoch_manager =
@@ -1147,6 +1330,8 @@ impl RuntimeAdapter for NightshadeRuntime {
                     account_id,
                     method_name,
                     args.as_ref(),
+                    state_overrides.as_ref().unwrap_or(&StateOverrides::default()),
+                    caller.as_ref(),
                     &mut logs,
                     &self.epoch_manager,
                     current_protocol_version,
@@ -1163,6 +1348,63 @@ impl RuntimeAdapter for NightshadeRuntime {
                     }),
                 }
             }
+            QueryRequest::ViewStorageUsage { account_id, prefix_len } => {
+                match self.view_storage_usage(
+                    shard_id,
+                    *state_root,
+                    account_id,
+                    prefix_len.map_or(DEFAULT_STORAGE_USAGE_PREFIX_LEN, |len| len as usize),
+                    Some(MAX_STORAGE_USAGE_QUERY_KEYS),
+                ) {
+                    Ok(result) => Ok(QueryResponse {
+                        kind: QueryResponseKind::StorageUsage(result),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                    Err(err) => Ok(QueryResponse {
+                        kind: QueryResponseKind::Error(QueryError {
+                            error: err.to_string(),
+                            logs: vec![],
+                        }),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                }
+            }
+            QueryRequest::ViewStorageStaking { account_id } => {
+                match self.view_storage_staking(shard_id, *state_root, account_id) {
+                    Ok(result) => Ok(QueryResponse {
+                        kind: QueryResponseKind::StorageStaking(result),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                    Err(err) => Ok(QueryResponse {
+                        kind: QueryResponseKind::Error(QueryError {
+                            error: err.to_string(),
+                            logs: vec![],
+                        }),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                }
+            }
+            QueryRequest::ViewContractAbi { account_id } => {
+                match self.view_contract_abi(shard_id, *state_root, account_id) {
+                    Ok(result) => Ok(QueryResponse {
+                        kind: QueryResponseKind::ContractAbi(result),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                    Err(err) => Ok(QueryResponse {
+                        kind: QueryResponseKind::Error(QueryError {
+                            error: err.to_string(),
+                            logs: vec![],
+                        }),
+                        block_height,
+                        block_hash: *block_hash,
+                    }),
+                }
+            }
             QueryRequest::ViewState { account_id, prefix } => {
                 match self.view_state(shard_id, *state_root, account_id, prefix.as_ref()) {
                     Ok(result) => Ok(QueryResponse {
@@ -1230,6 +1472,20 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_validator_info(block_hash).map_err(|e| e.into())
     }
 
+    fn get_validator_history(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+        account_id: Option<&AccountId>,
+    ) -> Result<Vec<EpochValidatorHistoryView>, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        Ok(epoch_manager
+            .get_validator_history(block_hash, num_epochs, account_id)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
     /// Returns StorageError when storage is inconsistent.
     /// This is possible with the used isolation level + running ViewClient in a separate thread
     fn obtain_state_part(
@@ -1280,6 +1536,39 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn build_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+    ) -> Result<HashMap<ShardId, StateRoot>, Error> {
+        let (prev_shard_layout, shard_layout) = self.get_split_shard_layouts(block_hash)?;
+        self.tries
+            .build_split_states(shard_id, state_root, &prev_shard_layout, &shard_layout)
+            .map_err(|err| Error::from(ErrorKind::StorageError(err)))
+    }
+
+    fn apply_state_changes_to_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        split_state_roots: &HashMap<ShardId, StateRoot>,
+        changes: &[RawStateChangesWithTrieKey],
+    ) -> Result<HashMap<ShardId, TrieChanges>, Error> {
+        let (prev_shard_layout, shard_layout) = self.get_split_shard_layouts(block_hash)?;
+        self.tries
+            .apply_state_changes_to_split_states(
+                shard_id,
+                state_root,
+                split_state_roots,
+                changes,
+                &prev_shard_layout,
+                &shard_layout,
+            )
+            .map_err(|err| Error::from(ErrorKind::StorageError(err)))
+    }
+
     fn confirm_state(
         &self,
         shard_id: ShardId,
@@ -1392,12 +1681,14 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        state_overrides: &StateOverrides,
+        caller: Option<&CallerContext>,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
-        self.trie_viewer.call_function(
+        self.trie_viewer.call_function_with_overrides(
             state_update,
             height,
             block_timestamp,
@@ -1407,9 +1698,12 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
             contract_id,
             method_name,
             args,
+            state_overrides,
+            caller,
             logs,
             epoch_info_provider,
             current_protocol_version,
+            &self.genesis_runtime_config,
         )
     }
 
@@ -1460,6 +1754,48 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_state(&state_update, account_id, prefix)
     }
+
+    fn view_storage_usage(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+        prefix_len: usize,
+        max_keys: Option<u64>,
+    ) -> Result<StorageUsageView, Box<dyn std::error::Error>> {
+        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        self.trie_viewer.view_storage_usage(
+            &state_update,
+            account_id,
+            prefix_len,
+            max_keys,
+            &self.genesis_runtime_config.transaction_costs.storage_usage_config,
+        )
+    }
+
+    fn view_storage_staking(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+    ) -> Result<StorageStakingView, Box<dyn std::error::Error>> {
+        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        self.trie_viewer.view_storage_staking(
+            &state_update,
+            account_id,
+            &self.genesis_runtime_config,
+        )
+    }
+
+    fn view_contract_abi(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+    ) -> Result<ContractAbiView, Box<dyn std::error::Error>> {
+        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        self.trie_viewer.view_contract_abi(&state_update, account_id)
+    }
 }
 
 #[cfg(test)]
@@ -1537,6 +1873,7 @@ mod test {
                     gas_limit,
                     challenges,
                     CryptoHash::default(),
+                    &CongestionInfo::default(),
                 )
                 .unwrap();
             let mut store_update = self.store.store_update();
@@ -1633,7 +1970,7 @@ mod test {
             challenges_result: ChallengesResult,
         ) {
             let new_hash = hash(&vec![(self.head.height + 1) as u8]);
-            let num_shards = self.runtime.num_shards();
+            let num_shards = self.runtime.num_shards(&self.head.epoch_id).unwrap();
             assert_eq!(transactions.len() as NumShards, num_shards);
             assert_eq!(chunk_mask.len() as NumShards, num_shards);
             let mut all_proposals = vec![];
@@ -1695,7 +2032,10 @@ mod test {
         }
 
         pub fn view_account(&self, account_id: &str) -> AccountView {
-            let shard_id = self.runtime.account_id_to_shard_id(&account_id.to_string());
+            let shard_id = self
+                .runtime
+                .account_id_to_shard_id(&account_id.to_string(), &self.head.epoch_id)
+                .unwrap();
             self.runtime
                 .view_account(
                     shard_id,
@@ -2146,7 +2486,8 @@ mod test {
             .collect();
         let signer = InMemorySigner::from_seed(&validators[0], KeyType::ED25519, &validators[0]);
         let staking_transaction = stake(1, &signer, &block_producers[0], TESTING_INIT_STAKE - 1);
-        let first_account_shard_id = env.runtime.account_id_to_shard_id(&"test1".to_string());
+        let first_account_shard_id =
+            env.runtime.account_id_to_shard_id(&"test1".to_string(), &env.head.epoch_id).unwrap();
         let transactions = if first_account_shard_id == 0 {
             vec![vec![staking_transaction], vec![]]
         } else {
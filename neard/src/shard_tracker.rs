@@ -1,22 +1,16 @@
 use std::collections::{HashMap, HashSet};
-use std::io::Cursor;
 use std::sync::{Arc, RwLock};
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use log::info;
 
 use near_epoch_manager::EpochManager;
 use near_primitives::errors::EpochError;
-use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, EpochId, NumShards, ShardId};
+pub use near_primitives::utils::account_id_to_shard_id;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
-pub fn account_id_to_shard_id(account_id: &AccountId, num_shards: NumShards) -> ShardId {
-    let mut cursor = Cursor::new((hash(&account_id.clone().into_bytes()).0).0);
-    cursor.read_u64::<LittleEndian>().expect("Must not happened") % (num_shards)
-}
-
 /// Tracker that tracks shard ids and accounts. It maintains two items: `tracked_accounts` and
 /// `tracked_shards`. The shards that are actually tracked are the union of shards that `tracked_accounts`
 /// are in and `tracked_shards`.
@@ -37,7 +31,7 @@ pub struct ShardTracker {
     current_epoch_id: EpochId,
     /// Epoch manager that for given block hash computes the epoch id.
     epoch_manager: Arc<RwLock<EpochManager>>,
-    /// Number of shards in the system.
+    /// Number of shards in the genesis shard layout.
     num_shards: NumShards,
 }
 
@@ -176,6 +170,27 @@ impl ShardTracker {
         Ok(())
     }
 
+    /// Maps a shard of the epoch after `parent_hash` (or of the epoch after it, if `next_epoch`)
+    /// to the shard it was split from. The tracked shards refer to the genesis shard layout, and
+    /// tracking a shard means tracking all the shards split from it.
+    fn get_tracked_shard_id(
+        &self,
+        parent_hash: &CryptoHash,
+        shard_id: ShardId,
+        next_epoch: bool,
+    ) -> ShardId {
+        let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
+        let epoch_id = if next_epoch {
+            epoch_manager.get_next_epoch_id_from_prev_block(parent_hash)
+        } else {
+            epoch_manager.get_epoch_id_from_prev_block(parent_hash)
+        };
+        epoch_id
+            .and_then(|epoch_id| epoch_manager.get_shard_layout(&epoch_id))
+            .map(|shard_layout| shard_layout.get_parent_shard_id(shard_id))
+            .unwrap_or(shard_id)
+    }
+
     pub fn care_about_shard(
         &self,
         account_id: Option<&AccountId>,
@@ -192,11 +207,15 @@ impl ShardTracker {
             };
             if !is_me {
                 return account_cares_about_shard;
+            } else if account_cares_about_shard {
+                return true;
             }
-            account_cares_about_shard || self.actual_tracked_shards.contains(&shard_id)
-        } else {
-            self.actual_tracked_shards.contains(&shard_id)
         }
+        self.actual_tracked_shards.contains(&self.get_tracked_shard_id(
+            parent_hash,
+            shard_id,
+            false,
+        ))
     }
 
     pub fn will_care_about_shard(
@@ -221,7 +240,11 @@ impl ShardTracker {
         }
         let mut tracker = self.clone();
         tracker.flush_pending();
-        tracker.actual_tracked_shards.contains(&shard_id)
+        tracker.actual_tracked_shards.contains(&self.get_tracked_shard_id(
+            parent_hash,
+            shard_id,
+            true,
+        ))
     }
 }
 
@@ -234,6 +257,7 @@ mod tests {
     use near_epoch_manager::{EpochManager, RewardCalculator};
     use near_primitives::epoch_manager::{BlockInfo, EpochConfig};
     use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::types::{BlockHeight, EpochId, NumShards, ValidatorStake};
     use near_store::test_utils::create_test_store;
 
@@ -247,7 +271,7 @@ mod tests {
         let store = create_test_store();
         let initial_epoch_config = EpochConfig {
             epoch_length: 1,
-            num_shards,
+            shard_layout: ShardLayout::v0(num_shards, 0),
             num_block_producer_seats: 1,
             num_block_producer_seats_per_shard: vec![1],
             avg_hidden_validator_seats_per_shard: vec![],
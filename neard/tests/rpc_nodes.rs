@@ -6,21 +6,24 @@ use borsh::BorshSerialize;
 use futures::future::join_all;
 use futures::{future, FutureExt, TryFutureExt};
 
-use near_client::{GetBlock, GetExecutionOutcome, TxStatus};
+use near_client::{EstimateFee, GetAccountBalanceChanges, GetBlock, GetExecutionOutcome, TxStatus};
 use near_crypto::{InMemorySigner, KeyType};
 use near_jsonrpc::client::new_client;
 use near_logger_utils::init_integration_logger;
 use near_network::test_utils::WaitOrTimeout;
+use near_network::NetworkClientMessages;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{compute_root_from_path_and_item, verify_path};
 use near_primitives::serialize::{from_base64, to_base64};
-use near_primitives::transaction::{PartialExecutionStatus, SignedTransaction};
+use near_primitives::transaction::{
+    Action, DeployContractAction, FunctionCallAction, PartialExecutionStatus, SignedTransaction,
+};
 use near_primitives::types::{BlockId, BlockReference, TransactionOrReceiptId};
 use near_primitives::views::{
-    ExecutionOutcomeView, ExecutionStatusView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus,
-    QueryResponseKind,
+    AccountBalanceChangeCauseView, ExecutionOutcomeView, ExecutionStatusView,
+    FinalExecutionOutcomeViewEnum, FinalExecutionStatus, QueryResponseKind,
 };
-use neard::config::TESTING_INIT_BALANCE;
+use neard::config::{TESTING_INIT_BALANCE, TESTING_INIT_STAKE};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::Arc;
@@ -95,6 +98,7 @@ fn test_tx_propagation() {
                             tx_hash,
                             signer_account_id: "near.1".to_string(),
                             fetch_receipt: false,
+                            fetch_trace: false,
                         })
                         .then(move |res| {
                             match &res {
@@ -620,3 +624,229 @@ fn test_get_execution_outcome_tx_success() {
 fn test_get_execution_outcome_tx_failure() {
     test_get_execution_outcome(false);
 }
+
+/// Starts a single validator, makes it stake more and send money to an account that does not
+/// exist, and checks that the refund, the stake and the validator reward all show up with their
+/// own causes in the balance changes of the validator.
+#[test]
+fn test_account_balance_changes_causes() {
+    init_integration_logger();
+    heavy_test(|| {
+        let system = System::new("NEAR");
+        let num_nodes = 1;
+        let dirs = (0..num_nodes)
+            .map(|i| {
+                tempfile::Builder::new().prefix(&format!("balance_changes{}", i)).tempdir().unwrap()
+            })
+            .collect::<Vec<_>>();
+        let (genesis, _, clients) = start_nodes(1, &dirs, 1, 0, 10, 0);
+        let client = clients[0].0.clone();
+        let view_client = clients[0].1.clone();
+
+        let genesis_hash = *genesis_block(&genesis).hash();
+        let signer = InMemorySigner::from_seed("near.0", KeyType::ED25519, "near.0");
+        let transactions = vec![
+            SignedTransaction::stake(
+                1,
+                "near.0".to_string(),
+                &signer,
+                TESTING_INIT_STAKE * 2,
+                signer.public_key.clone(),
+                genesis_hash,
+            ),
+            SignedTransaction::send_money(
+                2,
+                "near.0".to_string(),
+                "near.nonexistent".to_string(),
+                &signer,
+                10000,
+                genesis_hash,
+            ),
+        ];
+        let sent = Arc::new(AtomicBool::new(false));
+
+        WaitOrTimeout::new(
+            Box::new(move |_ctx| {
+                let client = client.clone();
+                let view_client1 = view_client.clone();
+                let transactions = transactions.clone();
+                let sent = sent.clone();
+                actix::spawn(view_client.send(GetBlock::latest()).then(move |res| {
+                    let height = res.unwrap().unwrap().header.height;
+                    if height > 1 && !sent.swap(true, SeqCst) {
+                        for transaction in transactions {
+                            client.do_send(NetworkClientMessages::Transaction {
+                                transaction,
+                                is_forwarded: false,
+                                check_only: false,
+                            });
+                        }
+                    }
+                    // Rewards for the first epoch are paid at the start of the third one.
+                    if height <= 21 {
+                        return future::ready(());
+                    }
+                    actix::spawn(
+                        view_client1
+                            .send(GetAccountBalanceChanges {
+                                account_id: "near.0".to_string(),
+                                from_block_height: 0,
+                                to_block_height: height,
+                            })
+                            .then(|res| {
+                                let changes = res.unwrap().unwrap();
+                                assert!(changes.iter().any(|change| match change.cause {
+                                    AccountBalanceChangeCauseView::Refund { .. } => {
+                                        change.amount_after - change.amount_before >= 10000
+                                    }
+                                    _ => false,
+                                }));
+                                assert!(changes.iter().any(|change| match change.cause {
+                                    AccountBalanceChangeCauseView::StakeLock { .. } => {
+                                        change.locked_after == TESTING_INIT_STAKE * 2
+                                            && change.locked_before == TESTING_INIT_STAKE
+                                    }
+                                    _ => false,
+                                }));
+                                assert!(changes.iter().any(|change| match change.cause {
+                                    AccountBalanceChangeCauseView::ValidatorAccountsUpdate {
+                                        validator_reward,
+                                    } => {
+                                        validator_reward > 0
+                                            && change.amount_after + change.locked_after
+                                                == change.amount_before
+                                                    + change.locked_before
+                                                    + validator_reward
+                                    }
+                                    _ => false,
+                                }));
+                                System::current().stop();
+                                future::ready(())
+                            }),
+                    );
+                    future::ready(())
+                }));
+            }),
+            100,
+            60000,
+        )
+        .start();
+
+        system.run().unwrap();
+    });
+}
+
+/// Calls a contract a few times and checks that the fee estimation recommends attaching the
+/// highest recent gas usage of the method with a safety margin on top.
+#[test]
+fn test_estimate_fee_with_history() {
+    init_integration_logger();
+    heavy_test(|| {
+        let system = System::new("NEAR");
+        let num_nodes = 2;
+        let dirs = (0..num_nodes)
+            .map(|i| {
+                tempfile::Builder::new().prefix(&format!("estimate_fee{}", i)).tempdir().unwrap()
+            })
+            .collect::<Vec<_>>();
+        let (genesis, _, clients) = start_nodes(1, &dirs, 1, 1, 1000, 0);
+        let client = clients[0].0.clone();
+        let view_client = clients[0].1.clone();
+
+        let genesis_hash = *genesis_block(&genesis).hash();
+        let signer0 = InMemorySigner::from_seed("near.0", KeyType::ED25519, "near.0");
+        let signer1 = InMemorySigner::from_seed("near.1", KeyType::ED25519, "near.1");
+        let function_call = Action::FunctionCall(FunctionCallAction {
+            method_name: "log_something".to_string(),
+            args: vec![],
+            gas: 100_000_000_000_000,
+            deposit: 0,
+        });
+        let deploy_tx = SignedTransaction::from_actions(
+            1,
+            "near.1".to_string(),
+            "near.1".to_string(),
+            &signer1,
+            vec![Action::DeployContract(DeployContractAction {
+                code: include_bytes!(
+                    "../../runtime/near-vm-runner/tests/res/test_contract_rs.wasm"
+                )
+                .to_vec(),
+            })],
+            genesis_hash,
+        );
+        let num_calls = 3;
+        let call_txs = (1..=num_calls)
+            .map(|nonce| {
+                SignedTransaction::from_actions(
+                    nonce,
+                    "near.0".to_string(),
+                    "near.1".to_string(),
+                    &signer0,
+                    vec![function_call.clone()],
+                    genesis_hash,
+                )
+            })
+            .collect::<Vec<_>>();
+        let deployed = Arc::new(AtomicBool::new(false));
+        let called = Arc::new(AtomicBool::new(false));
+
+        WaitOrTimeout::new(
+            Box::new(move |_ctx| {
+                let client = client.clone();
+                let view_client1 = view_client.clone();
+                let deploy_tx = deploy_tx.clone();
+                let call_txs = call_txs.clone();
+                let function_call = function_call.clone();
+                let deployed = deployed.clone();
+                let called = called.clone();
+                actix::spawn(view_client.send(GetBlock::latest()).then(move |res| {
+                    let height = res.unwrap().unwrap().header.height;
+                    let send_tx = |transaction| {
+                        client.do_send(NetworkClientMessages::Transaction {
+                            transaction,
+                            is_forwarded: false,
+                            check_only: false,
+                        })
+                    };
+                    if height > 1 && !deployed.swap(true, SeqCst) {
+                        send_tx(deploy_tx);
+                    } else if height > 4 && !called.swap(true, SeqCst) {
+                        call_txs.into_iter().for_each(send_tx);
+                    } else if height > 8 {
+                        let estimate_fee = EstimateFee {
+                            signer_id: "near.0".to_string(),
+                            receiver_id: "near.1".to_string(),
+                            actions: vec![function_call],
+                            num_blocks: None,
+                        };
+                        actix::spawn(view_client1.send(estimate_fee).then(|res| {
+                            let estimation = res.unwrap().unwrap();
+                            let function_call = &estimation.function_calls[0];
+                            if function_call.num_samples < num_calls {
+                                return future::ready(());
+                            }
+                            assert_eq!(function_call.num_samples, num_calls);
+                            assert!(function_call.avg_gas_used > 0);
+                            assert!(function_call.avg_gas_used <= function_call.max_gas_used);
+                            assert!(function_call.max_gas_used < 100_000_000_000_000);
+                            assert_eq!(
+                                estimation.recommended_attached_gas,
+                                function_call.max_gas_used + function_call.max_gas_used / 5
+                            );
+                            assert!(estimation.expected_gas_price <= estimation.max_gas_price);
+                            System::current().stop();
+                            future::ready(())
+                        }));
+                    }
+                    future::ready(())
+                }));
+            }),
+            100,
+            30000,
+        )
+        .start();
+
+        system.run().unwrap();
+    });
+}
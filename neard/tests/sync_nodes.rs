@@ -76,6 +76,7 @@ fn add_blocks(
             }])
             .unwrap(),
             block_merkle_tree.root(),
+            None,
         );
         block_merkle_tree.insert(*block.hash());
         let _ = client.do_send(NetworkClientMessages::Block(
@@ -113,6 +113,15 @@ pub struct ActionCreationConfig {
 
     /// Base cost of deleting an account.
     pub delete_account_cost: Fee,
+
+    /// Base cost of a delegate action, excluding the cost of the delegated actions.
+    #[serde(default = "default_delegate_cost")]
+    pub delegate_cost: Fee,
+}
+
+/// Cost of a delegate action for configs that were created before it was introduced.
+fn default_delegate_cost() -> Fee {
+    Fee { send_sir: 200_000_000_000, send_not_sir: 200_000_000_000, execution: 200_000_000_000 }
 }
 
 /// Describes the cost of creating an access key.
@@ -219,6 +228,7 @@ impl Default for RuntimeFeesConfig {
                     send_not_sir: 147489000000,
                     execution: 147489000000,
                 },
+                delegate_cost: default_delegate_cost(),
             },
             storage_usage_config: StorageUsageConfig {
                 // See Account in core/primitives/src/account.rs for the data structure.
@@ -255,7 +265,8 @@ impl RuntimeFeesConfig {
                     function_call_cost_per_byte: free.clone(),
                 },
                 delete_key_cost: free.clone(),
-                delete_account_cost: free,
+                delete_account_cost: free.clone(),
+                delegate_cost: free,
             },
             storage_usage_config: StorageUsageConfig {
                 num_bytes_account: 0,
@@ -177,6 +177,12 @@ pub enum HostError {
     ContractSizeExceeded { size: u64, limit: u64 },
     /// The host function was deprecated.
     Deprecated { method_name: String },
+    /// Returning scheduled promise is prohibited
+    CannotReturnScheduledPromise,
+    /// VM Logic provided an invalid receipt id
+    InvalidReceiptId,
+    /// The host function is not available in the current protocol version.
+    HostFunctionNotAvailable { method_name: String },
 }
 
 #[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Deserialize, Serialize)]
@@ -344,6 +350,9 @@ impl std::fmt::Display for HostError {
             ReturnedValueLengthExceeded { length, limit } => write!(f, "The length of a returned value {} exceeds the limit {}", length, limit),
             ContractSizeExceeded { size, limit } => write!(f, "The size of a contract code in DeployContract action {} exceeds the limit {}", size, limit),
             Deprecated {method_name}=> write!(f, "Attempted to call deprecated host function {}", method_name),
+            CannotReturnScheduledPromise => write!(f, "Returning scheduled promise is prohibited."),
+            InvalidReceiptId => write!(f, "VM Logic provided an invalid receipt id"),
+            HostFunctionNotAvailable { method_name } => write!(f, "Host function {} is not available in the current protocol version", method_name),
         }
     }
 }
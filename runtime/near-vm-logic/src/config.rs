@@ -315,12 +315,31 @@ pub struct ExtCostsConfig {
     pub validator_stake_base: Gas,
     /// Cost of calling `validator_total_stake`.
     pub validator_total_stake_base: Gas,
+
+    // #######################
+    // # Storage staking API #
+    // #######################
+    /// Cost of calling `storage_usage_delta`.
+    #[serde(default = "default_storage_staking_host_function_base")]
+    pub storage_usage_delta_base: Gas,
+    /// Cost of calling `storage_byte_cost`.
+    #[serde(default = "default_storage_staking_host_function_base")]
+    pub storage_byte_cost_base: Gas,
+    /// Cost of calling `storage_locked_balance`.
+    #[serde(default = "default_storage_staking_host_function_base")]
+    pub storage_locked_balance_base: Gas,
 }
 
 // We multiply the actual computed costs by the fixed factor to ensure we
 // have certain reserve for further gas price variation.
 const SAFETY_MULTIPLIER: u64 = 3;
 
+/// Genesis configs created before the storage staking host functions were added don't have their
+/// costs, so they are priced like the other host functions that only read the execution context.
+fn default_storage_staking_host_function_base() -> Gas {
+    SAFETY_MULTIPLIER * 88256037
+}
+
 impl Default for ExtCostsConfig {
     fn default() -> ExtCostsConfig {
         ExtCostsConfig {
@@ -373,6 +392,9 @@ impl Default for ExtCostsConfig {
             promise_return: SAFETY_MULTIPLIER * 186717462,
             validator_stake_base: SAFETY_MULTIPLIER * 303944908800,
             validator_total_stake_base: SAFETY_MULTIPLIER * 303944908800,
+            storage_usage_delta_base: default_storage_staking_host_function_base(),
+            storage_byte_cost_base: default_storage_staking_host_function_base(),
+            storage_locked_balance_base: default_storage_staking_host_function_base(),
         }
     }
 }
@@ -429,6 +451,9 @@ impl ExtCostsConfig {
             promise_return: 0,
             validator_stake_base: 0,
             validator_total_stake_base: 0,
+            storage_usage_delta_base: 0,
+            storage_byte_cost_base: 0,
+            storage_locked_balance_base: 0,
         }
     }
 }
@@ -486,6 +511,9 @@ pub enum ExtCosts {
     promise_return,
     validator_stake_base,
     validator_total_stake_base,
+    storage_usage_delta_base,
+    storage_byte_cost_base,
+    storage_locked_balance_base,
 }
 
 // Type of an action, used in fees logic.
@@ -590,11 +618,14 @@ impl ExtCosts {
             promise_return => config.promise_return,
             validator_stake_base => config.validator_stake_base,
             validator_total_stake_base => config.validator_total_stake_base,
+            storage_usage_delta_base => config.storage_usage_delta_base,
+            storage_byte_cost_base => config.storage_byte_cost_base,
+            storage_locked_balance_base => config.storage_locked_balance_base,
         }
     }
 
     pub const fn count() -> usize {
-        ExtCosts::validator_total_stake_base as usize + 1
+        ExtCosts::storage_locked_balance_base as usize + 1
     }
 
     pub fn name_of(index: usize) -> &'static str {
@@ -648,6 +679,9 @@ impl ExtCosts {
             "promise_return",
             "validator_stake_base",
             "validator_total_stake_base",
+            "storage_usage_delta_base",
+            "storage_byte_cost_base",
+            "storage_locked_balance_base",
         ][index]
     }
 }
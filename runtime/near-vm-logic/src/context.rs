@@ -39,6 +39,9 @@ pub struct VMContext {
     pub account_locked_balance: Balance,
     /// The account's storage usage before the contract execution
     pub storage_usage: StorageUsage,
+    /// The amount of tokens that has to be staked for every byte of storage the account uses.
+    #[serde(default, with = "crate::serde_with::u128_dec_format_compatible")]
+    pub storage_byte_cost: Balance,
     /// The balance that was attached to the call that will be immediately deposited before the
     /// contract execution starts.
     #[serde(with = "crate::serde_with::u128_dec_format_compatible")]
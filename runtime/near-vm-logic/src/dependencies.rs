@@ -1,6 +1,6 @@
 //! External dependencies of the near-vm-logic.
 
-use crate::types::{AccountId, Balance, Gas, PublicKey, ReceiptIndex};
+use crate::types::{AccountId, Balance, Gas, PublicKey, ReceiptIndex, ReceiptSchedule};
 use near_vm_errors::VMLogicError;
 
 /// An abstraction over the memory of the smart contract.
@@ -167,6 +167,50 @@ pub trait External {
         receiver_id: AccountId,
     ) -> Result<ReceiptIndex>;
 
+    /// Creates a receipt which is stored in the state until the given schedule is due, and
+    /// returns its index together with the receipt ID which can be used to cancel it.
+    ///
+    /// # Arguments
+    ///
+    /// * `receiver_id` - the account the receipt is sent to once it's due
+    /// * `schedule` - the block height or the block timestamp after which the receipt is sent
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::MockedExternal;
+    /// # use near_vm_logic::types::ReceiptSchedule;
+    /// # use near_vm_logic::External;
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// let (receipt_index, receipt_id) = external
+    ///     .create_scheduled_receipt("charli.near".to_owned(), ReceiptSchedule::BlockHeight(100))
+    ///     .unwrap();
+    ///
+    /// ```
+    fn create_scheduled_receipt(
+        &mut self,
+        receiver_id: AccountId,
+        schedule: ReceiptSchedule,
+    ) -> Result<(ReceiptIndex, Vec<u8>)>;
+
+    /// Cancels a receipt that was scheduled by the current account and is not yet due.
+    /// Returns `false` if there is no such receipt or it's already due.
+    ///
+    /// # Arguments
+    ///
+    /// * `receipt_id` - the ID of the scheduled receipt
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::MockedExternal;
+    /// # use near_vm_logic::External;
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// assert_eq!(external.cancel_scheduled_receipt(&[0; 32]), Ok(false));
+    ///
+    /// ```
+    fn cancel_scheduled_receipt(&mut self, receipt_id: &[u8]) -> Result<bool>;
+
     /// Attaches an `Action::CreateAccount` action to an existing receipt
     ///
     /// # Arguments
@@ -6,7 +6,8 @@ use crate::dependencies::{External, MemoryLike};
 use crate::gas_counter::GasCounter;
 use crate::types::{
     AccountId, Balance, EpochHeight, Gas, ProfileData, PromiseIndex, PromiseResult,
-    ProtocolVersion, ReceiptIndex, ReturnData, StorageUsage,
+    ProtocolVersion, ReceiptIndex, ReceiptSchedule, ReturnData, StorageUsage,
+    SCHEDULED_RECEIPTS_PROTOCOL_VERSION, STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION,
 };
 use crate::utils::split_method_names;
 use crate::{ExtCosts, HostError, VMLogicError, ValuePtr};
@@ -60,6 +61,8 @@ pub struct VMLogic<'a> {
     promises: Vec<Promise>,
     /// Record the accounts towards which the receipts are directed.
     receipt_to_account: HashMap<ReceiptIndex, AccountId>,
+    /// Receipt IDs of the scheduled receipts created during this execution.
+    scheduled_receipts: HashMap<ReceiptIndex, Vec<u8>>,
 
     /// Tracks the total log length. The sum of length of all logs.
     total_log_length: u64,
@@ -142,6 +145,7 @@ impl<'a> VMLogic<'a> {
             registers: HashMap::new(),
             promises: vec![],
             receipt_to_account: HashMap::new(),
+            scheduled_receipts: HashMap::new(),
             total_log_length: 0,
             current_protocol_version,
         }
@@ -692,6 +696,84 @@ impl<'a> VMLogic<'a> {
         Ok(self.current_storage_usage)
     }
 
+    /// The change of the storage usage of the current account caused by the current call so far.
+    /// Writes the change into the register as a little-endian `i64`, which is negative when the
+    /// call released more storage than it allocated.
+    ///
+    /// # Errors
+    ///
+    /// * If called before the storage staking host functions are enabled returns
+    ///   `HostFunctionNotAvailable`.
+    /// * If the total number of registers exceeds `max_number_registers` returns
+    ///   `MemoryAccessViolation`.
+    ///
+    /// # Cost
+    ///
+    /// `base + write_register_base + write_register_byte * 8 + storage_usage_delta_base`
+    pub fn storage_usage_delta(&mut self, register_id: u64) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        self.check_storage_staking_available("storage_usage_delta")?;
+        self.gas_counter.pay_base(storage_usage_delta_base)?;
+        // The difference of the two `u64` fits into `i64` unless the storage usage changed by
+        // more than 2^63 bytes.
+        let delta = self.current_storage_usage.wrapping_sub(self.context.storage_usage) as i64;
+        self.internal_write_register(register_id, delta.to_le_bytes().to_vec())
+    }
+
+    /// The amount of tokens that has to be staked for every byte of storage. Writes the value
+    /// into the `u128` variable pointed by `balance_ptr`.
+    ///
+    /// # Errors
+    ///
+    /// If called before the storage staking host functions are enabled returns
+    /// `HostFunctionNotAvailable`.
+    ///
+    /// # Cost
+    ///
+    /// `base + memory_write_base + memory_write_size * 16 + storage_byte_cost_base`
+    pub fn storage_byte_cost(&mut self, balance_ptr: u64) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        self.check_storage_staking_available("storage_byte_cost")?;
+        self.gas_counter.pay_base(storage_byte_cost_base)?;
+        self.memory_set_u128(balance_ptr, self.context.storage_byte_cost)
+    }
+
+    /// The part of the current account balance that is reserved to cover the current storage
+    /// usage and can not be transferred. Tokens locked due to staking count towards the storage
+    /// stake first. Writes the value into the `u128` variable pointed by `balance_ptr`.
+    ///
+    /// # Errors
+    ///
+    /// If called before the storage staking host functions are enabled returns
+    /// `HostFunctionNotAvailable`.
+    ///
+    /// # Cost
+    ///
+    /// `base + memory_write_base + memory_write_size * 16 + storage_locked_balance_base`
+    pub fn storage_locked_balance(&mut self, balance_ptr: u64) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        self.check_storage_staking_available("storage_locked_balance")?;
+        self.gas_counter.pay_base(storage_locked_balance_base)?;
+        let storage_stake = Balance::from(self.current_storage_usage)
+            .saturating_mul(self.context.storage_byte_cost);
+        let locked_for_storage = storage_stake
+            .saturating_sub(self.current_account_locked_balance)
+            .min(self.current_account_balance);
+        self.memory_set_u128(balance_ptr, locked_for_storage)
+    }
+
+    fn check_storage_staking_available(&self, method_name: &str) -> Result<()> {
+        if !cfg!(feature = "protocol_feature_storage_staking_host_functions")
+            || self.current_protocol_version < STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION
+        {
+            return Err(HostError::HostFunctionNotAvailable {
+                method_name: method_name.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
     // #################
     // # Economics API #
     // #################
@@ -1113,6 +1195,194 @@ impl<'a> VMLogic<'a> {
         self.checked_push_promise(Promise::Receipt(new_receipt_idx))
     }
 
+    /// Creates a new promise towards given `account_id` without any actions attached to it. The
+    /// receipt is stored in the state and is sent no earlier than the block with the given
+    /// `block_height`. Actions are attached to the scheduled promise as to any other batch promise,
+    /// but it can not be returned with `promise_return`.
+    ///
+    /// # Errors
+    ///
+    /// * If `account_id_len + account_id_ptr` points outside the memory of the guest or host
+    /// returns `MemoryAccessViolation`.
+    /// * If called as view function returns `ProhibitedInView`.
+    /// * If the total number of promises exceeds `max_promises_per_function_call_action` limit
+    ///   returns `NumPromisesExceeded`.
+    ///
+    /// # Returns
+    ///
+    /// Index of the new promise that uniquely identifies it within the current execution of the
+    /// method.
+    ///
+    /// # Cost
+    ///
+    /// `burnt_gas := base + cost of reading and decoding the account id + dispatch cost of the receipt`.
+    /// `used_gas := burnt_gas + exec cost of the receipt`.
+    pub fn promise_batch_create_at_height(
+        &mut self,
+        account_id_len: u64,
+        account_id_ptr: u64,
+        block_height: u64,
+    ) -> Result<u64> {
+        self.internal_promise_batch_create_scheduled(
+            "promise_batch_create_at_height",
+            account_id_len,
+            account_id_ptr,
+            ReceiptSchedule::BlockHeight(block_height),
+        )
+    }
+
+    /// Same as `promise_batch_create_at_height`, but the receipt is sent no earlier than the block
+    /// with the given `block_timestamp` in nanoseconds.
+    ///
+    /// # Errors
+    ///
+    /// * If `account_id_len + account_id_ptr` points outside the memory of the guest or host
+    /// returns `MemoryAccessViolation`.
+    /// * If called as view function returns `ProhibitedInView`.
+    /// * If the total number of promises exceeds `max_promises_per_function_call_action` limit
+    ///   returns `NumPromisesExceeded`.
+    ///
+    /// # Returns
+    ///
+    /// Index of the new promise that uniquely identifies it within the current execution of the
+    /// method.
+    ///
+    /// # Cost
+    ///
+    /// `burnt_gas := base + cost of reading and decoding the account id + dispatch cost of the receipt`.
+    /// `used_gas := burnt_gas + exec cost of the receipt`.
+    pub fn promise_batch_create_at_timestamp(
+        &mut self,
+        account_id_len: u64,
+        account_id_ptr: u64,
+        block_timestamp: u64,
+    ) -> Result<u64> {
+        self.internal_promise_batch_create_scheduled(
+            "promise_batch_create_at_timestamp",
+            account_id_len,
+            account_id_ptr,
+            ReceiptSchedule::BlockTimestamp(block_timestamp),
+        )
+    }
+
+    fn internal_promise_batch_create_scheduled(
+        &mut self,
+        method_name: &str,
+        account_id_len: u64,
+        account_id_ptr: u64,
+        schedule: ReceiptSchedule,
+    ) -> Result<u64> {
+        self.gas_counter.pay_base(base)?;
+        if !cfg!(feature = "protocol_feature_scheduled_receipts")
+            || self.current_protocol_version < SCHEDULED_RECEIPTS_PROTOCOL_VERSION
+        {
+            return Err(HostError::HostFunctionNotAvailable {
+                method_name: method_name.to_string(),
+            }
+            .into());
+        }
+        if self.context.is_view {
+            return Err(HostError::ProhibitedInView { method_name: method_name.to_string() }.into());
+        }
+        let account_id = self.read_and_parse_account_id(account_id_ptr, account_id_len)?;
+        let sir = account_id == self.context.current_account_id;
+        self.pay_gas_for_new_receipt(sir, &[])?;
+        let (new_receipt_idx, receipt_id) =
+            self.ext.create_scheduled_receipt(account_id.clone(), schedule)?;
+        self.receipt_to_account.insert(new_receipt_idx, account_id);
+        self.scheduled_receipts.insert(new_receipt_idx, receipt_id);
+
+        self.checked_push_promise(Promise::Receipt(new_receipt_idx))
+    }
+
+    /// Writes the ID of the scheduled receipt created by the promise `promise_idx` into the
+    /// register. The ID can later be used to cancel the receipt with `promise_scheduled_cancel`.
+    ///
+    /// # Errors
+    ///
+    /// * If `promise_idx` does not correspond to an existing scheduled promise returns
+    ///   `InvalidPromiseIndex`.
+    /// * If called as view function returns `ProhibitedInView`.
+    ///
+    /// # Cost
+    ///
+    /// `base + write_register_base + write_register_byte * num_bytes`
+    pub fn promise_scheduled_receipt_id(
+        &mut self,
+        promise_idx: u64,
+        register_id: u64,
+    ) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        if !cfg!(feature = "protocol_feature_scheduled_receipts")
+            || self.current_protocol_version < SCHEDULED_RECEIPTS_PROTOCOL_VERSION
+        {
+            return Err(HostError::HostFunctionNotAvailable {
+                method_name: "promise_scheduled_receipt_id".to_string(),
+            }
+            .into());
+        }
+        if self.context.is_view {
+            return Err(HostError::ProhibitedInView {
+                method_name: "promise_scheduled_receipt_id".to_string(),
+            }
+            .into());
+        }
+        let receipt_id = match self.promises.get(promise_idx as usize) {
+            Some(Promise::Receipt(receipt_idx)) => self.scheduled_receipts.get(receipt_idx),
+            _ => None,
+        }
+        .ok_or(HostError::InvalidPromiseIndex { promise_idx })?
+        .clone();
+        self.internal_write_register(register_id, receipt_id)
+    }
+
+    /// Cancels the receipt with the given ID that was scheduled by the current account and is
+    /// not yet due. The attached deposit and the prepaid gas of the receipt are refunded.
+    ///
+    /// # Errors
+    ///
+    /// * If `receipt_id_len + receipt_id_ptr` points outside the memory of the guest or host
+    /// returns `MemoryAccessViolation`.
+    /// * If the given bytes are not a valid receipt ID returns `InvalidReceiptId`.
+    /// * If called as view function returns `ProhibitedInView`.
+    ///
+    /// # Returns
+    ///
+    /// * If the receipt was cancelled returns `1`;
+    /// * If there is no such scheduled receipt or it's already due returns `0`.
+    ///
+    /// # Cost
+    ///
+    /// `base + storage_remove_base + cost of reading the receipt id`
+    pub fn promise_scheduled_cancel(
+        &mut self,
+        receipt_id_len: u64,
+        receipt_id_ptr: u64,
+    ) -> Result<u64> {
+        self.gas_counter.pay_base(base)?;
+        if !cfg!(feature = "protocol_feature_scheduled_receipts")
+            || self.current_protocol_version < SCHEDULED_RECEIPTS_PROTOCOL_VERSION
+        {
+            return Err(HostError::HostFunctionNotAvailable {
+                method_name: "promise_scheduled_cancel".to_string(),
+            }
+            .into());
+        }
+        if self.context.is_view {
+            return Err(HostError::ProhibitedInView {
+                method_name: "promise_scheduled_cancel".to_string(),
+            }
+            .into());
+        }
+        self.gas_counter.pay_base(storage_remove_base)?;
+        let receipt_id = self.get_vec_from_memory_or_register(receipt_id_ptr, receipt_id_len)?;
+        if self.ext.cancel_scheduled_receipt(&receipt_id)? {
+            Ok(1)
+        } else {
+            Ok(0)
+        }
+    }
+
     /// Creates a new promise towards given `account_id` without any actions attached, that is
     /// executed after promise pointed by `promise_idx` is complete.
     ///
@@ -1766,6 +2036,9 @@ impl<'a> VMLogic<'a> {
             .ok_or(HostError::InvalidPromiseIndex { promise_idx })?
         {
             Promise::Receipt(receipt_idx) => {
+                if self.scheduled_receipts.contains_key(receipt_idx) {
+                    return Err(HostError::CannotReturnScheduledPromise.into());
+                }
                 self.return_data = ReturnData::ReceiptIndex(*receipt_idx);
                 Ok(())
             }
@@ -1,4 +1,4 @@
-use crate::types::{AccountId, Balance, Gas, PublicKey};
+use crate::types::{AccountId, Balance, Gas, PublicKey, ReceiptSchedule};
 use crate::{External, ValuePtr};
 use near_vm_errors::HostError;
 use serde::{Deserialize, Serialize};
@@ -10,6 +10,8 @@ pub struct MockedExternal {
     pub fake_trie: HashMap<Vec<u8>, Vec<u8>>,
     receipts: Vec<Receipt>,
     pub validators: HashMap<AccountId, Balance>,
+    /// IDs of the scheduled receipts that are stored and can be cancelled.
+    pub scheduled_receipts: HashMap<Vec<u8>, ReceiptSchedule>,
 }
 
 pub struct MockedValuePtr {
@@ -79,6 +81,25 @@ impl External for MockedExternal {
         Ok(res)
     }
 
+    fn create_scheduled_receipt(
+        &mut self,
+        receiver_id: String,
+        schedule: ReceiptSchedule,
+    ) -> Result<(u64, Vec<u8>)> {
+        let receipt_index = self.create_receipt(vec![], receiver_id)?;
+        let mut receipt_id = vec![0; 32];
+        receipt_id[..8].copy_from_slice(&receipt_index.to_le_bytes());
+        self.scheduled_receipts.insert(receipt_id.clone(), schedule);
+        Ok((receipt_index, receipt_id))
+    }
+
+    fn cancel_scheduled_receipt(&mut self, receipt_id: &[u8]) -> Result<bool> {
+        if receipt_id.len() != 32 {
+            return Err(HostError::InvalidReceiptId.into());
+        }
+        Ok(self.scheduled_receipts.remove(receipt_id).is_some())
+    }
+
     fn append_action_create_account(&mut self, receipt_index: u64) -> Result<()> {
         self.receipts.get_mut(receipt_index as usize).unwrap().actions.push(Action::CreateAccount);
         Ok(())
@@ -15,6 +15,13 @@ pub type IteratorIndex = u64;
 pub type StorageUsage = u64;
 pub type ProtocolVersion = u32;
 
+/// Protocol version of `ProtocolFeature::ScheduledReceipts` in `near-primitives`, which enables
+/// the `promise_batch_create_at_*` host functions.
+pub const SCHEDULED_RECEIPTS_PROTOCOL_VERSION: ProtocolVersion = 45;
+
+/// Protocol version of `ProtocolFeature::StorageStakingHostFunctions` in `near-primitives`.
+pub const STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION: ProtocolVersion = 46;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub enum ReturnData {
     /// Method returned some value or data.
@@ -29,6 +36,15 @@ pub enum ReturnData {
     None,
 }
 
+/// Describes when a scheduled receipt becomes due and is sent to its receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceiptSchedule {
+    /// The receipt is sent in the first block with at least the given height.
+    BlockHeight(BlockHeight),
+    /// The receipt is sent in the first block with at least the given timestamp in nanoseconds.
+    BlockTimestamp(u64),
+}
+
 /// When there is a callback attached to one or more contract calls the execution results of these
 /// calls are available to the contract invoked through the callback.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -13,6 +13,7 @@ pub fn get_context(input: Vec<u8>, is_view: bool) -> VMContext {
         epoch_height: 0,
         account_balance: 100,
         storage_usage: 0,
+        storage_byte_cost: 0,
         account_locked_balance: 0,
         attached_deposit: 10,
         prepaid_gas: 10_u64.pow(14),
@@ -771,3 +771,25 @@ fn test_contract_size_limit() {
         .into())
     );
 }
+
+#[test]
+#[cfg(feature = "protocol_feature_storage_staking_host_functions")]
+fn test_storage_staking_host_functions_costs() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut logic = logic_builder.build(get_context(vec![], false));
+    let buf = [0u8; std::mem::size_of::<u128>()];
+
+    logic.storage_usage_delta(0).expect("storage usage delta ok");
+    logic.storage_byte_cost(buf.as_ptr() as _).expect("storage byte cost ok");
+    logic.storage_locked_balance(buf.as_ptr() as _).expect("storage locked balance ok");
+    assert_costs(map! {
+        ExtCosts::base: 3,
+        ExtCosts::storage_usage_delta_base: 1,
+        ExtCosts::storage_byte_cost_base: 1,
+        ExtCosts::storage_locked_balance_base: 1,
+        ExtCosts::write_register_base: 1,
+        ExtCosts::write_register_byte: 8,
+        ExtCosts::write_memory_base: 2,
+        ExtCosts::write_memory_byte: 32,
+    });
+}
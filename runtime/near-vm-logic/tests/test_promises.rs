@@ -4,6 +4,7 @@ mod vm_logic_builder;
 
 use fixtures::get_context;
 use helpers::*;
+use near_vm_errors::HostError;
 use near_vm_logic::types::PromiseResult;
 use serde_json;
 use vm_logic_builder::VMLogicBuilder;
@@ -422,3 +423,60 @@ fn test_promise_batch_then() {
         &expected.to_string()
     );
 }
+
+#[test]
+#[cfg(feature = "protocol_feature_scheduled_receipts")]
+fn test_promise_batch_create_scheduled() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut logic = logic_builder.build(get_context(vec![], false));
+
+    let account_id = b"rick.test";
+    let index = logic
+        .promise_batch_create_at_height(account_id.len() as u64, account_id.as_ptr() as _, 100)
+        .expect("should create a scheduled promise");
+    promise_batch_action_function_call(&mut logic, index, 0, 0)
+        .expect("should add an action to scheduled receipt");
+    assert_eq!(
+        logic.promise_return(index),
+        Err(HostError::CannotReturnScheduledPromise.into()),
+        "scheduled promise can not be returned"
+    );
+
+    let normal = logic
+        .promise_batch_create(account_id.len() as u64, account_id.as_ptr() as _)
+        .expect("should create a promise");
+    logic
+        .promise_scheduled_receipt_id(normal, 0)
+        .expect_err("shouldn't accept non-scheduled promise index");
+
+    logic.promise_scheduled_receipt_id(index, 0).expect("should write the receipt id");
+    let receipt_id = [0u8; 32];
+    logic.read_register(0, receipt_id.as_ptr() as u64).unwrap();
+    assert_eq!(logic.promise_scheduled_cancel(32, receipt_id.as_ptr() as _), Ok(1));
+    assert_eq!(logic.promise_scheduled_cancel(32, receipt_id.as_ptr() as _), Ok(0));
+    assert_eq!(
+        logic.promise_scheduled_cancel(3, receipt_id.as_ptr() as _),
+        Err(HostError::InvalidReceiptId.into())
+    );
+}
+
+#[test]
+fn test_promise_batch_create_scheduled_old_protocol() {
+    let mut logic_builder = VMLogicBuilder::default();
+    logic_builder.current_protocol_version =
+        near_vm_logic::types::SCHEDULED_RECEIPTS_PROTOCOL_VERSION - 1;
+    let mut logic = logic_builder.build(get_context(vec![], false));
+
+    let account_id = b"rick.test";
+    assert_eq!(
+        logic.promise_batch_create_at_timestamp(
+            account_id.len() as u64,
+            account_id.as_ptr() as _,
+            100
+        ),
+        Err(HostError::HostFunctionNotAvailable {
+            method_name: "promise_batch_create_at_timestamp".to_string()
+        }
+        .into())
+    );
+}
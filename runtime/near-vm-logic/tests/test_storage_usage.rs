@@ -2,6 +2,7 @@ mod fixtures;
 mod vm_logic_builder;
 
 use crate::fixtures::get_context;
+use near_vm_logic::HostError;
 use vm_logic_builder::VMLogicBuilder;
 
 #[test]
@@ -46,3 +47,92 @@ fn test_storage_remove() {
 
     assert_eq!(logic.storage_usage().unwrap(), 0u64);
 }
+
+#[test]
+#[cfg(feature = "protocol_feature_storage_staking_host_functions")]
+fn test_storage_usage_delta() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let data_record_cost = logic_builder.fees_config.storage_usage_config.num_extra_bytes_record;
+    let key = b"foo";
+    let val = b"bar";
+    let record_size = data_record_cost + (key.len() + val.len()) as u64;
+
+    let storage_usage_delta = |logic: &mut near_vm_logic::VMLogic| {
+        let buf = [0u8; std::mem::size_of::<i64>()];
+        logic.storage_usage_delta(1).expect("storage usage delta ok");
+        logic.read_register(1, buf.as_ptr() as _).expect("read register ok");
+        i64::from_le_bytes(buf)
+    };
+
+    let mut context = get_context(vec![], false);
+    context.storage_usage = 100;
+    let mut logic = logic_builder.build(context.clone());
+    assert_eq!(storage_usage_delta(&mut logic), 0);
+    logic
+        .storage_write(key.len() as _, key.as_ptr() as _, val.len() as _, val.as_ptr() as _, 0)
+        .expect("storage write ok");
+    assert_eq!(storage_usage_delta(&mut logic), record_size as i64);
+
+    // The record is kept by the mocked external, so a following call can release it.
+    context.storage_usage = 100 + record_size;
+    let mut logic = logic_builder.build(context);
+    logic.storage_remove(key.len() as _, key.as_ptr() as _, 0).expect("storage remove ok");
+    assert_eq!(storage_usage_delta(&mut logic), -(record_size as i64));
+}
+
+#[test]
+#[cfg(feature = "protocol_feature_storage_staking_host_functions")]
+fn test_storage_locked_balance() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut context = get_context(vec![], false);
+    context.account_balance = 10_000;
+    context.account_locked_balance = 500;
+    context.attached_deposit = 0;
+    context.storage_usage = 100;
+    context.storage_byte_cost = 10;
+    let mut logic = logic_builder.build(context);
+    let buf = [0u8; std::mem::size_of::<u128>()];
+
+    logic.storage_byte_cost(buf.as_ptr() as _).expect("storage byte cost ok");
+    assert_eq!(u128::from_le_bytes(buf), 10);
+
+    // 100 bytes cost 1000 tokens, 500 of which are covered by the locked balance.
+    logic.storage_locked_balance(buf.as_ptr() as _).expect("storage locked balance ok");
+    assert_eq!(u128::from_le_bytes(buf), 500);
+
+    let key = b"foo";
+    let val = b"bar";
+    logic
+        .storage_write(key.len() as _, key.as_ptr() as _, val.len() as _, val.as_ptr() as _, 0)
+        .expect("storage write ok");
+    let storage_usage = logic.storage_usage().unwrap() as u128;
+    logic.storage_locked_balance(buf.as_ptr() as _).expect("storage locked balance ok");
+    assert_eq!(u128::from_le_bytes(buf), storage_usage * 10 - 500);
+}
+
+#[test]
+fn test_storage_staking_host_functions_old_protocol() {
+    let mut logic_builder = VMLogicBuilder::default();
+    logic_builder.current_protocol_version =
+        near_vm_logic::types::STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION - 1;
+    let mut logic = logic_builder.build(get_context(vec![], false));
+    let buf = [0u8; std::mem::size_of::<u128>()];
+
+    assert_eq!(
+        logic.storage_usage_delta(0),
+        Err(HostError::HostFunctionNotAvailable { method_name: "storage_usage_delta".to_string() }
+            .into())
+    );
+    assert_eq!(
+        logic.storage_byte_cost(buf.as_ptr() as _),
+        Err(HostError::HostFunctionNotAvailable { method_name: "storage_byte_cost".to_string() }
+            .into())
+    );
+    assert_eq!(
+        logic.storage_locked_balance(buf.as_ptr() as _),
+        Err(HostError::HostFunctionNotAvailable {
+            method_name: "storage_locked_balance".to_string()
+        }
+        .into())
+    );
+}
@@ -28,6 +28,10 @@ fn test_prohibited_view_methods() {
     test_prohibited!(promise_and, 0, 0);
     test_prohibited!(promise_batch_create, 0, 0);
     test_prohibited!(promise_batch_then, 0, 0, 0);
+    test_prohibited!(promise_batch_create_at_height, 0, 0, 0);
+    test_prohibited!(promise_batch_create_at_timestamp, 0, 0, 0);
+    test_prohibited!(promise_scheduled_receipt_id, 0, 0);
+    test_prohibited!(promise_scheduled_cancel, 0, 0);
     test_prohibited!(promise_batch_action_create_account, 0);
     test_prohibited!(promise_batch_action_deploy_contract, 0, 0, 0);
     test_prohibited!(promise_batch_action_function_call, 0, 0, 0, 0, 0, 0, 0);
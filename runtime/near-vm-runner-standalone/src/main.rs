@@ -83,6 +83,7 @@ fn default_vm_context() -> VMContext {
         account_balance: 10u128.pow(25),
         account_locked_balance: 0,
         storage_usage: 100,
+        storage_byte_cost: 0,
         attached_deposit: 0,
         prepaid_gas: 10u64.pow(18),
         random_seed: vec![0, 1, 2],
@@ -32,6 +32,7 @@ fn setup(
         account_balance: 0,
         account_locked_balance: 0,
         storage_usage: 0,
+        storage_byte_cost: 0,
         attached_deposit: 0,
         prepaid_gas: 10u64.pow(15),
         random_seed: vec![0, 1, 2],
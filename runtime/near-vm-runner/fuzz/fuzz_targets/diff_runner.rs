@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use near_vm_logic::VMKind;
+use near_vm_runner_fuzz::{find_entry_point, run_method, ArbitraryModule};
+
+fuzz_target!(|module: ArbitraryModule| {
+    let code = module.to_bytes();
+    let method_name = match find_entry_point(&code) {
+        Some(method_name) => method_name,
+        None => return,
+    };
+    let wasmer = run_method(&code, &method_name, VMKind::Wasmer);
+    let wasmtime = run_method(&code, &method_name, VMKind::Wasmtime);
+    assert_eq!(wasmer, wasmtime, "Wasmer and Wasmtime diverged on method {}", method_name);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use near_vm_logic::VMConfig;
+use near_vm_runner::prepare::prepare_contract;
+use near_vm_runner_fuzz::ArbitraryModule;
+
+fuzz_target!(|module: ArbitraryModule| {
+    let code = module.to_bytes();
+    let config = VMConfig::default();
+    if let Ok(prepared) = prepare_contract(&code, &config) {
+        // Instrumentation must be deterministic and must always produce a module that passes
+        // `prepare_contract` checks again.
+        assert_eq!(prepare_contract(&code, &config), Ok(prepared.clone()));
+        assert!(prepare_contract(&prepared, &config).is_ok());
+    }
+});
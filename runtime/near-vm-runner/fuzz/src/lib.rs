@@ -0,0 +1,307 @@
+//! Helpers shared by the `near-vm-runner` fuzz targets.
+//!
+//! Modules are produced with `wasm-smith`, so every input the fuzzer feeds us is a valid Wasm
+//! module and the targets spend their time in `prepare_contract` and the VMs rather than in the
+//! deserializer.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use arbitrary::Arbitrary;
+use parity_wasm::elements::{self, Instruction, Internal, Section, Type, ValueType};
+use wasm_smith::{Config, ConfiguredModule};
+
+use near_runtime_fees::RuntimeFeesConfig;
+use near_vm_logic::mocks::mock_external::MockedExternal;
+use near_vm_logic::types::ProtocolVersion;
+use near_vm_logic::{VMConfig, VMContext, VMKind, VMOutcome};
+use near_vm_runner::{run_vm, VMError};
+
+pub const LATEST_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::MAX;
+
+/// Host functions that generated modules may import from `env`: the name, the number of `i64`
+/// arguments and whether an `i64` is returned.
+const HOST_FUNCTIONS: &[(&str, usize, bool)] = &[
+    ("read_register", 2, false),
+    ("register_len", 1, true),
+    ("write_register", 3, false),
+    ("current_account_id", 1, false),
+    ("signer_account_pk", 1, false),
+    ("input", 1, false),
+    ("block_index", 0, true),
+    ("storage_usage", 0, true),
+    ("account_balance", 1, false),
+    ("attached_deposit", 1, false),
+    ("prepaid_gas", 0, true),
+    ("used_gas", 0, true),
+    ("random_seed", 1, false),
+    ("sha256", 3, false),
+    ("keccak256", 3, false),
+    ("value_return", 2, false),
+    ("log_utf8", 2, false),
+    ("log_utf16", 2, false),
+    ("panic_utf8", 2, false),
+    ("promise_batch_create", 2, true),
+    ("promise_batch_action_transfer", 2, false),
+    ("promise_results_count", 0, true),
+    ("promise_result", 2, true),
+    ("promise_return", 1, false),
+    ("storage_write", 5, true),
+    ("storage_read", 3, true),
+    ("storage_remove", 3, true),
+    ("storage_has_key", 2, true),
+    ("storage_iter_prefix", 2, true),
+    ("storage_iter_range", 4, true),
+    ("storage_iter_next", 3, true),
+    ("validator_total_stake", 1, false),
+];
+
+/// `wasm-smith` configuration that produces modules resembling NEAR contracts, kept small so that
+/// every run stays cheap. `wasm-smith` names its imports arbitrarily, which would never link, so
+/// it generates none; the `env` host functions are imported by `ArbitraryModule` instead, and the
+/// memory import is added by `prepare_contract`.
+#[derive(Arbitrary, Clone, Debug, Default)]
+pub struct ContractConfig;
+
+impl Config for ContractConfig {
+    fn max_imports(&self) -> usize {
+        0
+    }
+
+    fn max_memories(&self) -> u32 {
+        1
+    }
+
+    fn max_memory_pages(&self) -> u32 {
+        VMConfig::default().limit_config.max_memory_pages
+    }
+
+    fn max_tables(&self) -> u32 {
+        1
+    }
+
+    fn max_funcs(&self) -> usize {
+        32
+    }
+
+    fn max_instructions(&self) -> usize {
+        256
+    }
+
+    fn max_exports(&self) -> usize {
+        16
+    }
+}
+
+/// Call of a host function injected at the start of one of the generated functions. Arguments
+/// are kept small so that pointers and lengths often land inside the first memory page.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct HostCall {
+    pub function: u8,
+    pub target: u8,
+    pub args: Vec<u16>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct ArbitraryModule {
+    pub module: ConfiguredModule<ContractConfig>,
+    pub host_calls: Vec<HostCall>,
+}
+
+impl ArbitraryModule {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let code = self.module.to_bytes();
+        if self.host_calls.is_empty() {
+            return code;
+        }
+        match elements::deserialize_buffer(&code) {
+            Ok(module) => {
+                elements::serialize(inject_host_calls(module, &self.host_calls)).unwrap_or(code)
+            }
+            Err(_) => code,
+        }
+    }
+}
+
+/// Imports the host functions used by `calls` from `env` and calls them at the start of the
+/// target functions, so that the VMs run `VMLogic` and not only the generated arithmetic.
+fn inject_host_calls(mut module: elements::Module, calls: &[HostCall]) -> elements::Module {
+    let num_bodies = module.code_section().map_or(0, |code| code.bodies().len());
+    if num_bodies == 0 {
+        return module;
+    }
+    let mut hosts = vec![];
+    for call in calls {
+        let host = call.function as usize % HOST_FUNCTIONS.len();
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+
+    if module.type_section().is_none() {
+        module.sections_mut().insert(0, Section::Type(elements::TypeSection::with_types(vec![])));
+    }
+    if module.import_section().is_none() {
+        let pos = module
+            .sections()
+            .iter()
+            .position(|section| matches!(section, Section::Type(_)))
+            .unwrap();
+        module
+            .sections_mut()
+            .insert(pos + 1, Section::Import(elements::ImportSection::with_entries(vec![])));
+    }
+    // New imports go after the existing function imports, shifting the indices of all the
+    // functions defined in the module.
+    let num_imported = module.import_count(elements::ImportCountType::Function) as u32;
+    let num_hosts = hosts.len() as u32;
+    let mut imports = vec![];
+    for host in hosts.iter() {
+        let (name, num_args, returns) = HOST_FUNCTIONS[*host];
+        let types = module.type_section_mut().unwrap().types_mut();
+        types.push(Type::Function(elements::FunctionType::new(
+            vec![ValueType::I64; num_args],
+            if returns { Some(ValueType::I64) } else { None },
+        )));
+        let type_idx = types.len() as u32 - 1;
+        imports.push(elements::ImportEntry::new(
+            "env".to_string(),
+            name.to_string(),
+            elements::External::Function(type_idx),
+        ));
+    }
+    module.import_section_mut().unwrap().entries_mut().extend(imports);
+
+    let shift = |idx: &mut u32| {
+        if *idx >= num_imported {
+            *idx += num_hosts;
+        }
+    };
+    for section in module.sections_mut() {
+        match section {
+            Section::Code(code) => {
+                for body in code.bodies_mut() {
+                    for instruction in body.code_mut().elements_mut() {
+                        if let Instruction::Call(idx) = instruction {
+                            shift(idx);
+                        }
+                    }
+                }
+            }
+            Section::Export(exports) => {
+                for export in exports.entries_mut() {
+                    if let Internal::Function(idx) = export.internal_mut() {
+                        shift(idx);
+                    }
+                }
+            }
+            Section::Element(elements) => {
+                for segment in elements.entries_mut() {
+                    segment.members_mut().iter_mut().for_each(shift);
+                }
+            }
+            Section::Start(idx) => shift(idx),
+            _ => {}
+        }
+    }
+
+    let bodies = module.code_section_mut().unwrap().bodies_mut();
+    for call in calls {
+        let host = call.function as usize % HOST_FUNCTIONS.len();
+        let (_, num_args, returns) = HOST_FUNCTIONS[host];
+        let host_idx = num_imported + hosts.iter().position(|h| *h == host).unwrap() as u32;
+        let mut injected: Vec<_> = (0..num_args)
+            .map(|i| Instruction::I64Const(call.args.get(i).copied().unwrap_or(0) as i64))
+            .collect();
+        injected.push(Instruction::Call(host_idx));
+        if returns {
+            injected.push(Instruction::Drop);
+        }
+        let body = &mut bodies[call.target as usize % num_bodies];
+        body.code_mut().elements_mut().splice(0..0, injected);
+    }
+    module
+}
+
+/// Returns the name of the first exported function that takes no arguments and returns nothing,
+/// i.e. the first export that the runtime is able to call as a contract method.
+pub fn find_entry_point(code: &[u8]) -> Option<String> {
+    let module: elements::Module = elements::deserialize_buffer(code).ok()?;
+    let types = module.type_section().map(elements::TypeSection::types).unwrap_or(&[]);
+    let functions =
+        module.function_section().map(elements::FunctionSection::entries).unwrap_or(&[]);
+    let imported_functions = module.import_count(elements::ImportCountType::Function);
+    for export in module.export_section()?.entries() {
+        let func_idx = match export.internal() {
+            Internal::Function(idx) => *idx as usize,
+            _ => continue,
+        };
+        let type_idx = match func_idx.checked_sub(imported_functions) {
+            Some(idx) => functions.get(idx)?.type_ref(),
+            None => continue,
+        };
+        let Type::Function(func_type) = types.get(type_idx as usize)?;
+        if func_type.params().is_empty() && func_type.return_type().is_none() {
+            return Some(export.field().to_string());
+        }
+    }
+    None
+}
+
+pub fn create_context(input: Vec<u8>) -> VMContext {
+    VMContext {
+        current_account_id: "alice".to_owned(),
+        signer_account_id: "bob".to_owned(),
+        signer_account_pk: vec![0, 1, 2],
+        predecessor_account_id: "carol".to_owned(),
+        input,
+        block_index: 10,
+        block_timestamp: 42,
+        epoch_height: 1,
+        account_balance: 2u128,
+        account_locked_balance: 0,
+        storage_usage: 12,
+        storage_byte_cost: 0,
+        attached_deposit: 2u128,
+        prepaid_gas: 10u64.pow(14),
+        random_seed: vec![0, 1, 2],
+        is_view: false,
+        output_data_receivers: vec![],
+    }
+}
+
+/// Result of a single method call together with the state the call left behind.
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+    pub outcome: Option<VMOutcome>,
+    pub error: Option<VMError>,
+    pub trie: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+pub fn run_method(code: &[u8], method_name: &str, vm_kind: VMKind) -> RunResult {
+    let mut fake_external = MockedExternal::new();
+    let context = create_context(vec![]);
+    let config = VMConfig::default();
+    let fees = RuntimeFeesConfig::default();
+    let promise_results = vec![];
+
+    let mut hash = DefaultHasher::new();
+    code.hash(&mut hash);
+    let code_hash = hash.finish().to_le_bytes().to_vec();
+    let (outcome, error) = run_vm(
+        code_hash,
+        code,
+        method_name.as_bytes(),
+        &mut fake_external,
+        context,
+        &config,
+        &fees,
+        &promise_results,
+        vm_kind,
+        LATEST_PROTOCOL_VERSION,
+        None,
+    );
+    let mut trie: Vec<_> = fake_external.fake_trie.into_iter().collect();
+    trie.sort();
+    RunResult { outcome, error, trie }
+}
@@ -1,3 +1,4 @@
+use near_vm_logic::types::ProtocolVersion;
 use near_vm_logic::VMLogic;
 
 use std::ffi::c_void;
@@ -23,8 +24,19 @@ macro_rules! rust2wasm {
     };
 }
 
+// Imports marked with a protocol feature are only registered once the feature is enabled, so
+// before that contracts importing them fail to link, the same way as before they were added.
+macro_rules! is_import_enabled {
+    ($protocol_version:expr) => {
+        true
+    };
+    ($protocol_version:expr, $feature_name:tt, $feature:ident) => {
+        near_primitives::checked_feature!($feature_name, $feature, $protocol_version)
+    };
+}
+
 macro_rules! wrapped_imports {
-        ( $( $func:ident < [ $( $arg_name:ident : $arg_type:ident ),* ] -> [ $( $returns:ident ),* ] >, )* ) => {
+        ( $( $(#[$feature_name:tt, $feature:ident])? $func:ident < [ $( $arg_name:ident : $arg_type:ident ),* ] -> [ $( $returns:ident ),* ] >, )* ) => {
             pub mod wasmer_ext {
             use near_vm_logic::VMLogic;
             use wasmer_runtime::Ctx;
@@ -76,22 +88,26 @@ macro_rules! wrapped_imports {
             )*
             }
 
-            pub(crate) fn build_wasmer(memory: wasmer_runtime::memory::Memory, logic: &mut VMLogic<'_>) ->
-                wasmer_runtime::ImportObject {
+            pub(crate) fn build_wasmer(
+                memory: wasmer_runtime::memory::Memory,
+                logic: &mut VMLogic<'_>,
+                protocol_version: ProtocolVersion,
+            ) -> wasmer_runtime::ImportObject {
                 let raw_ptr = logic as *mut _ as *mut c_void;
                 let import_reference = ImportReference(raw_ptr);
-                wasmer_runtime::imports! {
-                    move || {
-                        let dtor = (|_: *mut c_void| {}) as fn(*mut c_void);
-                        (import_reference.0, dtor)
-                    },
-                    "env" => {
-                        "memory" => memory,
-                        $(
-                            stringify!($func) => wasmer_runtime::func!(wasmer_ext::$func),
-                        )*
-                    },
-                }
+                let mut import_object = wasmer_runtime::ImportObject::new_with_data(move || {
+                    let dtor = (|_: *mut c_void| {}) as fn(*mut c_void);
+                    (import_reference.0, dtor)
+                });
+                let mut ns = wasmer_runtime_core::import::Namespace::new();
+                ns.insert("memory", memory);
+                $(
+                    if is_import_enabled!(protocol_version $(, $feature_name, $feature)?) {
+                        ns.insert(stringify!($func), wasmer_runtime::func!(wasmer_ext::$func));
+                    }
+                )*
+                import_object.register("env", ns);
+                import_object
             }
 
             #[cfg(feature = "wasmtime_vm")]
@@ -99,6 +115,7 @@ macro_rules! wrapped_imports {
                     linker: &mut wasmtime::Linker,
                     memory: wasmtime::Memory,
                     raw_logic: *mut c_void,
+                    protocol_version: ProtocolVersion,
              ) {
                 wasmtime_ext::CALLER_CONTEXT.with(|caller_context| {
                     unsafe {
@@ -108,9 +125,11 @@ macro_rules! wrapped_imports {
                 linker.define("env", "memory", memory).
                     expect("cannot define memory");
                 $(
-                   linker.func("env", stringify!($func), wasmtime_ext::$func).
-                    expect("cannot link external");
-                  )*
+                    if is_import_enabled!(protocol_version $(, $feature_name, $feature)?) {
+                        linker.func("env", stringify!($func), wasmtime_ext::$func).
+                            expect("cannot link external");
+                    }
+                )*
             }
 
             #[cfg(feature = "wasmtime_vm")]
@@ -142,6 +161,9 @@ wrapped_imports! {
     block_timestamp<[] -> [u64]>,
     epoch_height<[] -> [u64]>,
     storage_usage<[] -> [u64]>,
+    #["protocol_feature_storage_staking_host_functions", StorageStakingHostFunctions] storage_usage_delta<[register_id: u64] -> []>,
+    #["protocol_feature_storage_staking_host_functions", StorageStakingHostFunctions] storage_byte_cost<[balance_ptr: u64] -> []>,
+    #["protocol_feature_storage_staking_host_functions", StorageStakingHostFunctions] storage_locked_balance<[balance_ptr: u64] -> []>,
     // #################
     // # Economics API #
     // #################
@@ -193,6 +215,18 @@ wrapped_imports! {
     promise_and<[promise_idx_ptr: u64, promise_idx_count: u64] -> [u64]>,
     promise_batch_create<[account_id_len: u64, account_id_ptr: u64] -> [u64]>,
     promise_batch_then<[promise_index: u64, account_id_len: u64, account_id_ptr: u64] -> [u64]>,
+    #["protocol_feature_scheduled_receipts", ScheduledReceipts] promise_batch_create_at_height<[
+        account_id_len: u64,
+        account_id_ptr: u64,
+        block_height: u64
+    ] -> [u64]>,
+    #["protocol_feature_scheduled_receipts", ScheduledReceipts] promise_batch_create_at_timestamp<[
+        account_id_len: u64,
+        account_id_ptr: u64,
+        block_timestamp: u64
+    ] -> [u64]>,
+    #["protocol_feature_scheduled_receipts", ScheduledReceipts] promise_scheduled_receipt_id<[promise_index: u64, register_id: u64] -> []>,
+    #["protocol_feature_scheduled_receipts", ScheduledReceipts] promise_scheduled_cancel<[receipt_id_len: u64, receipt_id_ptr: u64] -> [u64]>,
     // #######################
     // # Promise API actions #
     // #######################
@@ -10,6 +10,7 @@ mod wasmtime_runner;
 pub use cache::CompiledContractCache;
 pub use near_vm_errors::VMError;
 pub use runner::compile_module;
+pub use runner::is_vm_kind_supported;
 pub use runner::run;
 pub use runner::run_vm;
 pub use runner::run_vm_profiled;
@@ -145,6 +145,15 @@ pub fn run_vm_profiled<'a>(
     }
 }
 
+/// Whether `vm_kind` was compiled into this build, i.e. whether `run_vm` can run it without
+/// panicking.
+pub fn is_vm_kind_supported(vm_kind: VMKind) -> bool {
+    match vm_kind {
+        VMKind::Wasmer => true,
+        VMKind::Wasmtime => cfg!(feature = "wasmtime_vm"),
+    }
+}
+
 pub fn with_vm_variants(runner: fn(VMKind) -> ()) {
     runner(VMKind::Wasmer);
     #[cfg(feature = "wasmtime_vm")]
@@ -156,7 +156,7 @@ pub mod wasmtime_runner {
         // Unfortunately, due to the Wasmtime implementation we have to do tricks with the
         // lifetimes of the logic instance and pass raw pointers here.
         let raw_logic = &mut logic as *mut _ as *mut c_void;
-        imports::link_wasmtime(&mut linker, memory_copy, raw_logic);
+        imports::link_wasmtime(&mut linker, memory_copy, raw_logic, current_protocol_version);
         let func_name = match str::from_utf8(method_name) {
             Ok(name) => name,
             Err(_) => {
@@ -4,7 +4,10 @@ use near_vm_runner::{with_vm_variants, VMError};
 
 pub mod test_utils;
 
-use self::test_utils::{make_simple_contract_call_vm, make_simple_contract_call_with_gas_vm};
+use self::test_utils::{
+    make_simple_contract_call_vm, make_simple_contract_call_with_gas_vm,
+    make_simple_contract_call_with_protocol_version_vm,
+};
 
 fn vm_outcome_with_gas(gas: u64) -> VMOutcome {
     VMOutcome {
@@ -525,3 +528,71 @@ fn test_external_call_error() {
         );
     });
 }
+
+fn scheduled_receipt_import_contract() -> Vec<u8> {
+    wabt::wat2wasm(
+        r#"
+            (module
+              (type (;0;) (func (param i64 i64 i64) (result i64)))
+              (type (;1;) (func))
+              (import "env" "promise_batch_create_at_height" (func (;0;) (type 0)))
+              (func (;1;) (type 1))
+              (export "hello" (func 1))
+            )"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_scheduled_receipt_import_before_protocol_feature() {
+    // Contracts importing the scheduled receipts API fail to link before the protocol feature,
+    // the same way as before the API was added.
+    with_vm_variants(|vm_kind: VMKind| {
+        let result = make_simple_contract_call_with_protocol_version_vm(
+            &scheduled_receipt_import_contract(),
+            b"hello",
+            10u64.pow(14),
+            near_vm_logic::types::SCHEDULED_RECEIPTS_PROTOCOL_VERSION - 1,
+            vm_kind,
+        );
+        match result.1 {
+            Some(VMError::FunctionCallError(FunctionCallError::LinkError { msg })) => {
+                assert!(msg.contains("promise_batch_create_at_height"), "{}", msg);
+            }
+            other => panic!("expected a link error, got {:?}", other),
+        }
+    });
+}
+
+fn storage_staking_import_contract() -> Vec<u8> {
+    wabt::wat2wasm(
+        r#"
+            (module
+              (type (;0;) (func (param i64)))
+              (type (;1;) (func))
+              (import "env" "storage_byte_cost" (func (;0;) (type 0)))
+              (func (;1;) (type 1))
+              (export "hello" (func 1))
+            )"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_storage_staking_import_before_protocol_feature() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let result = make_simple_contract_call_with_protocol_version_vm(
+            &storage_staking_import_contract(),
+            b"hello",
+            10u64.pow(14),
+            near_vm_logic::types::STORAGE_STAKING_HOST_FUNCTIONS_PROTOCOL_VERSION - 1,
+            vm_kind,
+        );
+        match result.1 {
+            Some(VMError::FunctionCallError(FunctionCallError::LinkError { msg })) => {
+                assert!(msg.contains("storage_byte_cost"), "{}", msg);
+            }
+            other => panic!("expected a link error, got {:?}", other),
+        }
+    });
+}
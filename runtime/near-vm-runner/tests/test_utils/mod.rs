@@ -29,6 +29,7 @@ pub fn create_context(input: Vec<u8>) -> VMContext {
         account_balance: 2u128,
         account_locked_balance: 0,
         storage_usage: 12,
+        storage_byte_cost: 0,
         attached_deposit: 2u128,
         prepaid_gas: 10_u64.pow(14),
         random_seed: vec![0, 1, 2],
@@ -42,6 +43,22 @@ pub fn make_simple_contract_call_with_gas_vm(
     method_name: &[u8],
     prepaid_gas: u64,
     vm_kind: VMKind,
+) -> (Option<VMOutcome>, Option<VMError>) {
+    make_simple_contract_call_with_protocol_version_vm(
+        code,
+        method_name,
+        prepaid_gas,
+        LATEST_PROTOCOL_VERSION,
+        vm_kind,
+    )
+}
+
+pub fn make_simple_contract_call_with_protocol_version_vm(
+    code: &[u8],
+    method_name: &[u8],
+    prepaid_gas: u64,
+    protocol_version: ProtocolVersion,
+    vm_kind: VMKind,
 ) -> (Option<VMOutcome>, Option<VMError>) {
     let mut fake_external = MockedExternal::new();
     let mut context = create_context(vec![]);
@@ -64,7 +81,7 @@ pub fn make_simple_contract_call_with_gas_vm(
         &fees,
         &promise_results,
         vm_kind,
-        LATEST_PROTOCOL_VERSION,
+        protocol_version,
         None,
     )
 }
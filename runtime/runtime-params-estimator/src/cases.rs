@@ -602,6 +602,8 @@ fn get_runtime_fees_config(measurement: &Measurements) -> RuntimeFeesConfig {
             },
             delete_key_cost: measured_to_fee(metric, measured[&ActionDeleteKey]),
             delete_account_cost: measured_to_fee(metric, measured[&ActionDeleteAccount]),
+            // Delegate actions are not measured yet.
+            delegate_cost: RuntimeFeesConfig::default().action_creation_config.delegate_cost,
         },
         ..Default::default()
     }
@@ -672,6 +674,10 @@ fn get_ext_costs_config(measurement: &Measurements, config: &Config) -> ExtCosts
         // TODO: accurately price host functions that expose validator information.
         validator_stake_base: 303944908800,
         validator_total_stake_base: 303944908800,
+        // TODO: accurately price host functions that expose storage staking information.
+        storage_usage_delta_base: 88256037,
+        storage_byte_cost_base: 88256037,
+        storage_locked_balance_base: 88256037,
     }
 }
 
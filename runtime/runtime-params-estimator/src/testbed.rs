@@ -79,6 +79,7 @@ impl RuntimeTestbed {
         let apply_state = ApplyState {
             // Put each runtime into a separate shard.
             block_index: 0,
+            shard_id: 0,
             // Epoch length is long enough to avoid corner cases.
             last_block_hash: Default::default(),
             epoch_id: Default::default(),
@@ -90,6 +91,8 @@ impl RuntimeTestbed {
             current_protocol_version: PROTOCOL_VERSION,
             config: Arc::new(runtime_config),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
+            differential_vm: None,
+            congestion_info: Default::default(),
         };
         Self {
             workdir,
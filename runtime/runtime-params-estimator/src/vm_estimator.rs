@@ -34,6 +34,7 @@ fn create_context(input: Vec<u8>) -> VMContext {
         account_balance: 2u128,
         account_locked_balance: 1u128,
         storage_usage: 12,
+        storage_byte_cost: 0,
         attached_deposit: 2u128,
         prepaid_gas: 10_u64.pow(18),
         random_seed: vec![0, 1, 2],
@@ -215,6 +215,7 @@ impl RuntimeStandalone {
     pub fn produce_block(&mut self) -> Result<(), RuntimeError> {
         let apply_state = ApplyState {
             block_index: self.cur_block.block_height,
+            shard_id: 0,
             epoch_height: self.cur_block.epoch_height,
             gas_price: self.cur_block.gas_price,
             block_timestamp: self.cur_block.block_timestamp,
@@ -227,6 +228,8 @@ impl RuntimeStandalone {
             config: self.runtime_config.clone(),
             // TODO: shall we use compiled contracts cache in standalone runtime?
             cache: None,
+            differential_vm: None,
+            congestion_info: Default::default(),
         };
 
         let apply_result = self.runtime.apply(
@@ -316,6 +319,7 @@ impl RuntimeStandalone {
             &mut logs,
             self.epoch_info_provider.as_ref(),
             PROTOCOL_VERSION,
+            &self.runtime_config,
         )?;
         Ok((result, logs))
     }
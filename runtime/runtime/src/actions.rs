@@ -3,31 +3,42 @@ use std::sync::Arc;
 use borsh::{BorshDeserialize, BorshSerialize};
 use log::debug;
 
-use near_primitives::account::{AccessKey, AccessKeyPermission, Account};
+use near_primitives::account::{AccessKey, AccessKeyPermission, Account, ChargedDeposit};
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
-use near_primitives::receipt::{ActionReceipt, Receipt};
+use near_primitives::receipt::{ActionReceipt, DataReceipt, Receipt, ReceiptEnum, ReceiptSchedule};
 use near_primitives::transaction::{
     Action, AddKeyAction, DeleteAccountAction, DeleteKeyAction, DeployContractAction,
-    FunctionCallAction, StakeAction, TransferAction,
+    DeployContractWithAbiAction, FunctionCallAction, SignedDelegateAction, StakeAction,
+    TransferAction, UpgradeContractAction,
 };
-use near_primitives::types::{AccountId, EpochInfoProvider, ValidatorStake};
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{AccountId, Balance, EpochInfoProvider, StorageUsage, ValidatorStake};
 use near_primitives::utils::{
     create_random_seed, is_valid_account_id, is_valid_sub_account_id, is_valid_top_level_account_id,
 };
-use near_runtime_fees::RuntimeFeesConfig;
+use near_runtime_fees::{RuntimeFeesConfig, StorageUsageConfig};
 use near_runtime_utils::is_account_id_64_len_hex;
 use near_store::{
-    get_access_key, get_code, remove_access_key, remove_account, set_access_key, set_code,
+    get_access_key, get_charged_deposits, get_code, get_contract_abi, get_scheduled_receipt,
+    get_scheduled_receipt_ids, has_contract_data, remove_access_key, remove_account,
+    remove_account_without_data, remove_contract_abi, remove_scheduled_receipt, set_access_key,
+    set_charged_deposits, set_code, set_contract_abi, set_deleted_account, set_scheduled_receipt,
     StorageError, TrieUpdate,
 };
-use near_vm_logic::types::PromiseResult;
-use near_vm_logic::VMContext;
+use near_vm_logic::types::{PromiseResult, ReceiptIndex};
+use near_vm_logic::{ReturnData, VMContext, VMKind};
 
-use crate::config::{safe_add_gas, RuntimeConfig};
+use crate::balance_checker::receipt_cost;
+use crate::config::{
+    safe_add_gas, total_deposit, total_prepaid_exec_fees, total_prepaid_gas, RuntimeConfig,
+};
 use crate::ext::RuntimeExt;
+use crate::verifier::{validate_receipt, verify_delegate_action};
+use crate::vm_diff::{report_differences, VMExecution};
 use crate::{ActionResult, ApplyState};
 use near_crypto::PublicKey;
+use near_primitives::checked_feature;
 use near_primitives::errors::{ActionError, ActionErrorKind, ExternalError, RuntimeError};
 use near_primitives::version::{ProtocolVersion, IMPLICIT_ACCOUNT_CREATION_PROTOCOL_VERSION};
 use near_runtime_configs::AccountCreationConfig;
@@ -86,18 +97,6 @@ pub(crate) fn action_function_call(
         .into());
     }
 
-    let mut runtime_ext = RuntimeExt::new(
-        state_update,
-        account_id,
-        &action_receipt.signer_id,
-        &action_receipt.signer_public_key,
-        action_receipt.gas_price,
-        action_hash,
-        &apply_state.epoch_id,
-        &apply_state.last_block_hash,
-        epoch_info_provider,
-        apply_state.current_protocol_version,
-    );
     // Output data receipts are ignored if the function call is not the last action in the batch.
     let output_data_receivers: Vec<_> = if is_last_action {
         action_receipt.output_data_receivers.iter().map(|r| r.receiver_id.clone()).collect()
@@ -125,6 +124,7 @@ pub(crate) fn action_function_call(
         account_balance: account.amount,
         account_locked_balance: account.locked,
         storage_usage: account.storage_usage,
+        storage_byte_cost: config.storage_amount_per_byte,
         attached_deposit: function_call.deposit,
         prepaid_gas: function_call.gas,
         random_seed,
@@ -132,6 +132,53 @@ pub(crate) fn action_function_call(
         output_data_receivers,
     };
 
+    let differential_execution = apply_state.differential_vm.map(|vm_kind| {
+        VMExecution::isolated(vm_kind, state_update, |state_update| {
+            let mut runtime_ext = RuntimeExt::new(
+                state_update,
+                account_id,
+                &action_receipt.signer_id,
+                &action_receipt.signer_public_key,
+                action_receipt.gas_price,
+                action_hash,
+                &apply_state.epoch_id,
+                &apply_state.last_block_hash,
+                apply_state.block_index,
+                apply_state.block_timestamp,
+                epoch_info_provider,
+                apply_state.current_protocol_version,
+            );
+            let (outcome, err) = near_vm_runner::run_vm(
+                code.hash.as_ref().to_vec(),
+                &code.code,
+                function_call.method_name.as_bytes(),
+                &mut runtime_ext,
+                context.clone(),
+                &config.wasm_config,
+                &config.transaction_costs,
+                promise_results,
+                vm_kind,
+                apply_state.current_protocol_version,
+                None,
+            );
+            (outcome, err, runtime_ext.into_receipts(account_id))
+        })
+    });
+
+    let mut runtime_ext = RuntimeExt::new(
+        state_update,
+        account_id,
+        &action_receipt.signer_id,
+        &action_receipt.signer_public_key,
+        action_receipt.gas_price,
+        action_hash,
+        &apply_state.epoch_id,
+        &apply_state.last_block_hash,
+        apply_state.block_index,
+        apply_state.block_timestamp,
+        epoch_info_provider,
+        apply_state.current_protocol_version,
+    );
     let (outcome, err) = near_vm_runner::run(
         code.hash.as_ref().to_vec(),
         &code.code,
@@ -144,6 +191,24 @@ pub(crate) fn action_function_call(
         apply_state.current_protocol_version,
         cache,
     );
+    let (scheduled_receipts, cancelled_scheduled_receipts) = runtime_ext.take_scheduled_receipts();
+    let new_receipts = runtime_ext.into_receipts(account_id);
+    if let Some(differential_execution) = differential_execution {
+        let execution = VMExecution {
+            vm_kind: VMKind::default(),
+            outcome: outcome.clone(),
+            error: err.clone(),
+            receipts: new_receipts.clone(),
+            state_changes: state_update.prospective(),
+        };
+        report_differences(
+            account_id,
+            &function_call.method_name,
+            action_hash,
+            &execution,
+            &differential_execution,
+        );
+    }
     let execution_succeeded = match err {
         Some(VMError::FunctionCallError(err)) => {
             result.result = Err(ActionErrorKind::FunctionCallError(err).into());
@@ -185,7 +250,20 @@ pub(crate) fn action_function_call(
             account.amount = outcome.balance;
             account.storage_usage = outcome.storage_usage;
             result.result = Ok(outcome.return_data);
-            result.new_receipts.extend(runtime_ext.into_receipts(account_id));
+            if scheduled_receipts.is_empty() && cancelled_scheduled_receipts.is_empty() {
+                result.new_receipts.extend(new_receipts);
+            } else {
+                apply_scheduled_receipts(
+                    state_update,
+                    apply_state,
+                    account,
+                    account_id,
+                    new_receipts,
+                    scheduled_receipts,
+                    cancelled_scheduled_receipts,
+                    result,
+                )?;
+            }
         }
     } else {
         assert!(!execution_succeeded, "Outcome should always be available if execution succeeded")
@@ -193,6 +271,135 @@ pub(crate) fn action_function_call(
     Ok(())
 }
 
+/// Returns the storage usage of a scheduled receipt, which is charged from the account that
+/// scheduled it. It covers both the receipt and the index record.
+pub(crate) fn scheduled_receipt_storage_usage(
+    storage_usage_config: &StorageUsageConfig,
+    schedule: ReceiptSchedule,
+    receipt: &Receipt,
+) -> StorageUsage {
+    let index_key = TrieKey::ScheduledReceiptIndex {
+        account_id: receipt.predecessor_id.clone(),
+        receipt_id: receipt.receipt_id,
+    };
+    let receipt_key = TrieKey::ScheduledReceipt { schedule, receipt_id: receipt.receipt_id };
+    let schedule_len =
+        schedule.try_to_vec().expect("Borsh serializer is not expected to ever fail");
+    let receipt_len = receipt.try_to_vec().expect("Borsh serializer is not expected to ever fail");
+    storage_usage_config.num_extra_bytes_record * 2
+        + (index_key.len() + schedule_len.len() + receipt_key.len() + receipt_len.len()) as u64
+}
+
+/// Moves the receipts scheduled by a successful function call from the new receipts into the
+/// state, and refunds the scheduled receipts that were cancelled by it.
+fn apply_scheduled_receipts(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    account: &mut Account,
+    account_id: &AccountId,
+    new_receipts: Vec<Receipt>,
+    scheduled_receipts: Vec<(ReceiptIndex, ReceiptSchedule, CryptoHash)>,
+    cancelled_scheduled_receipts: Vec<Receipt>,
+    result: &mut ActionResult,
+) -> Result<(), RuntimeError> {
+    let storage_usage_config = &apply_state.config.transaction_costs.storage_usage_config;
+    // Scheduled receipts can't be returned, so the returned receipt index only has to be shifted
+    // by the number of scheduled receipts created before it.
+    if let Ok(ReturnData::ReceiptIndex(ref mut receipt_index)) = result.result {
+        let num_scheduled_before =
+            scheduled_receipts.iter().filter(|(index, _, _)| *index < *receipt_index).count();
+        *receipt_index -= num_scheduled_before as ReceiptIndex;
+    }
+    let mut scheduled_receipts = scheduled_receipts.into_iter().peekable();
+    for (receipt_index, mut new_receipt) in new_receipts.into_iter().enumerate() {
+        let (schedule, receipt_id) = match scheduled_receipts.peek() {
+            Some((index, schedule, receipt_id)) if *index == receipt_index as ReceiptIndex => {
+                (*schedule, *receipt_id)
+            }
+            _ => {
+                result.new_receipts.push(new_receipt);
+                continue;
+            }
+        };
+        scheduled_receipts.next();
+        new_receipt.receipt_id = receipt_id;
+        if let Err(e) = validate_receipt(&apply_state.config.wasm_config.limit_config, &new_receipt)
+        {
+            result.result = Err(ActionErrorKind::NewReceiptValidationError(e).into());
+            return Ok(());
+        }
+        account.storage_usage = account
+            .storage_usage
+            .checked_add(scheduled_receipt_storage_usage(
+                storage_usage_config,
+                schedule,
+                &new_receipt,
+            ))
+            .ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Storage usage integer overflow for account {}",
+                    account_id
+                ))
+            })?;
+        set_scheduled_receipt(state_update, schedule, &new_receipt);
+        result.new_scheduled_receipts.push(new_receipt);
+    }
+
+    for receipt in cancelled_scheduled_receipts {
+        // The schedule doesn't affect the size of the key, so any schedule can be used here.
+        account.storage_usage =
+            account.storage_usage.saturating_sub(scheduled_receipt_storage_usage(
+                storage_usage_config,
+                ReceiptSchedule::BlockHeight(0),
+                &receipt,
+            ));
+        refund_cancelled_scheduled_receipt(apply_state, account_id, account_id, receipt, result)?;
+    }
+    Ok(())
+}
+
+/// Refunds the full cost of a cancelled scheduled receipt: the attached deposits to
+/// `deposit_receiver_id` and the prepaid gas to the signer. Receipts that depend on the cancelled
+/// receipt receive a failed result.
+fn refund_cancelled_scheduled_receipt(
+    apply_state: &ApplyState,
+    account_id: &AccountId,
+    deposit_receiver_id: &AccountId,
+    receipt: Receipt,
+    result: &mut ActionResult,
+) -> Result<(), RuntimeError> {
+    let action_receipt = match &receipt.receipt {
+        ReceiptEnum::Action(action_receipt) => action_receipt,
+        ReceiptEnum::Data(_) => unreachable!("scheduled receipt should be an action receipt"),
+    };
+    let deposit_refund = total_deposit(&action_receipt.actions)?;
+    let gas_refund = receipt_cost(
+        &apply_state.config.transaction_costs,
+        &receipt,
+        apply_state.current_protocol_version,
+    )? - deposit_refund;
+    if deposit_refund > 0 {
+        result.new_receipts.push(Receipt::new_balance_refund(deposit_receiver_id, deposit_refund));
+    }
+    if gas_refund > 0 {
+        result.new_receipts.push(Receipt::new_gas_refund(
+            &action_receipt.signer_id,
+            gas_refund,
+            action_receipt.signer_public_key.clone(),
+        ));
+    }
+    result.new_receipts.extend(action_receipt.output_data_receivers.iter().map(|data_receiver| {
+        Receipt {
+            predecessor_id: account_id.clone(),
+            receiver_id: data_receiver.receiver_id.clone(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Data(DataReceipt { data_id: data_receiver.data_id, data: None }),
+        }
+    }));
+    result.cancelled_scheduled_receipts.push(receipt);
+    Ok(())
+}
+
 pub(crate) fn action_stake(
     account: &mut Account,
     result: &mut ActionResult,
@@ -255,15 +462,20 @@ pub(crate) fn try_refund_allowance(
 ) -> Result<(), StorageError> {
     if let Some(mut access_key) = get_access_key(state_update, account_id, public_key)? {
         let mut updated = false;
-        if let AccessKeyPermission::FunctionCall(function_call_permission) =
-            &mut access_key.permission
-        {
-            if let Some(allowance) = function_call_permission.allowance.as_mut() {
-                let new_allowance = allowance.saturating_add(transfer.deposit);
-                if new_allowance > *allowance {
-                    *allowance = new_allowance;
-                    updated = true;
-                }
+        let allowance = match &mut access_key.permission {
+            AccessKeyPermission::FunctionCall(function_call_permission) => {
+                function_call_permission.allowance.as_mut()
+            }
+            AccessKeyPermission::ExtendedFunctionCall(function_call_permission) => {
+                function_call_permission.allowance.as_mut()
+            }
+            AccessKeyPermission::FullAccess => None,
+        };
+        if let Some(allowance) = allowance {
+            let new_allowance = allowance.saturating_add(transfer.deposit);
+            if new_allowance > *allowance {
+                *allowance = new_allowance;
+                updated = true;
             }
         }
         if updated {
@@ -273,6 +485,75 @@ pub(crate) fn try_refund_allowance(
     Ok(())
 }
 
+/// The maximum number of charged deposits stored for an account. Once the limit is reached, the
+/// oldest charged deposit is forgotten and a later refund of it doesn't refund the deposit
+/// allowance. Most charged deposits are never refunded, because their function calls succeed.
+const MAX_CHARGED_DEPOSITS_PER_ACCOUNT: usize = 100;
+
+/// Records a deposit charged from the deposit allowance of the given access key, so the deposit
+/// refund receipt with the given ID can refund the deposit allowance.
+pub(crate) fn record_charged_deposit(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+    public_key: &PublicKey,
+    refund_receipt_id: CryptoHash,
+    amount: Balance,
+) -> Result<(), StorageError> {
+    let mut charged_deposits = get_charged_deposits(state_update, account_id)?;
+    if charged_deposits.len() >= MAX_CHARGED_DEPOSITS_PER_ACCOUNT {
+        charged_deposits.remove(0);
+    }
+    charged_deposits.push(ChargedDeposit {
+        refund_receipt_id,
+        public_key: public_key.clone(),
+        amount,
+    });
+    set_charged_deposits(state_update, account_id.clone(), charged_deposits);
+    Ok(())
+}
+
+/// Tries to refund the deposit allowance of an extended function call access key, the same best
+/// effort way as `try_refund_allowance`. Only the deposits recorded by `record_charged_deposit`
+/// for the given refund receipt are refunded, because deposits attached by contracts are not
+/// charged from the deposit allowance.
+pub(crate) fn try_refund_deposit_allowance(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+    public_key: &PublicKey,
+    refund_receipt_id: &CryptoHash,
+    transfer: &TransferAction,
+) -> Result<(), StorageError> {
+    let mut charged_deposits = get_charged_deposits(state_update, account_id)?;
+    let charged_deposit = match charged_deposits.iter().position(|charged_deposit| {
+        &charged_deposit.refund_receipt_id == refund_receipt_id
+            && &charged_deposit.public_key == public_key
+    }) {
+        Some(index) => charged_deposits.remove(index),
+        None => return Ok(()),
+    };
+    set_charged_deposits(state_update, account_id.clone(), charged_deposits);
+    let refund = charged_deposit.amount.min(transfer.deposit);
+    if let Some(mut access_key) = get_access_key(state_update, account_id, public_key)? {
+        if let AccessKeyPermission::ExtendedFunctionCall(function_call_permission) =
+            &mut access_key.permission
+        {
+            if let Some(deposit_allowance) = function_call_permission.deposit_allowance.as_mut() {
+                let new_deposit_allowance = deposit_allowance.saturating_add(refund);
+                if new_deposit_allowance > *deposit_allowance {
+                    *deposit_allowance = new_deposit_allowance;
+                    set_access_key(
+                        state_update,
+                        account_id.clone(),
+                        public_key.clone(),
+                        &access_key,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn action_transfer(
     account: &mut Account,
     transfer: &TransferAction,
@@ -374,6 +655,7 @@ pub(crate) fn action_deploy_contract(
     account: &mut Account,
     account_id: &AccountId,
     deploy_contract: &DeployContractAction,
+    current_protocol_version: ProtocolVersion,
 ) -> Result<(), StorageError> {
     let code = ContractCode::new(deploy_contract.code.clone(), None);
     let prev_code = get_code(state_update, account_id, Some(account.code_hash))?;
@@ -394,18 +676,199 @@ pub(crate) fn action_deploy_contract(
         })?;
     account.code_hash = code.get_hash();
     set_code(state_update, account_id.clone(), &code);
+    if checked_feature!("protocol_feature_contract_abi", ContractAbi, current_protocol_version) {
+        // The stored ABI describes the previous code.
+        update_contract_abi(state_update, account, account_id, None)?;
+    }
+    Ok(())
+}
+
+/// Deploys the code and stores the ABI of the new contract.
+pub(crate) fn action_deploy_contract_with_abi(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    account_id: &AccountId,
+    deploy_contract_with_abi: &DeployContractWithAbiAction,
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), StorageError> {
+    action_deploy_contract(
+        state_update,
+        account,
+        account_id,
+        &DeployContractAction { code: deploy_contract_with_abi.code.clone() },
+        current_protocol_version,
+    )?;
+    update_contract_abi(
+        state_update,
+        account,
+        account_id,
+        Some(deploy_contract_with_abi.abi.clone()),
+    )
+}
+
+/// Replaces the ABI stored for the account, or removes it if `abi` is `None`, and updates the
+/// storage usage of the account accordingly.
+fn update_contract_abi(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    account_id: &AccountId,
+    abi: Option<Vec<u8>>,
+) -> Result<(), StorageError> {
+    let prev_abi_length = get_contract_abi(state_update, account_id)?.map(|abi| abi.len() as u64);
+    account.storage_usage = account
+        .storage_usage
+        .checked_sub(prev_abi_length.unwrap_or_default())
+        .ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Storage usage integer underflow for account {}",
+                account_id
+            ))
+        })?;
+    match abi {
+        Some(abi) => {
+            account.storage_usage =
+                account.storage_usage.checked_add(abi.len() as u64).ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Storage usage integer overflow for account {}",
+                        account_id
+                    ))
+                })?;
+            set_contract_abi(state_update, account_id.clone(), abi);
+        }
+        None => {
+            if prev_abi_length.is_some() {
+                remove_contract_abi(state_update, account_id.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deploys the new code and calls the migration method of the new code on top of the existing
+/// contract state. The gas of the migration is accounted in `result`. If the migration fails, the
+/// action fails and the whole receipt, including the deployed code, is reverted.
+pub(crate) fn action_upgrade_contract(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    account: &mut Account,
+    receipt: &Receipt,
+    action_receipt: &ActionReceipt,
+    promise_results: &[PromiseResult],
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    upgrade_contract: &UpgradeContractAction,
+    action_hash: &CryptoHash,
+    config: &RuntimeConfig,
+    is_last_action: bool,
+    epoch_info_provider: &dyn EpochInfoProvider,
+) -> Result<(), RuntimeError> {
+    action_deploy_contract(
+        state_update,
+        account,
+        account_id,
+        &DeployContractAction { code: upgrade_contract.code.clone() },
+        apply_state.current_protocol_version,
+    )?;
+    let migration = FunctionCallAction {
+        method_name: upgrade_contract.method_name.clone(),
+        args: upgrade_contract.args.clone(),
+        gas: upgrade_contract.gas,
+        deposit: 0,
+    };
+    action_function_call(
+        state_update,
+        apply_state,
+        account,
+        receipt,
+        action_receipt,
+        promise_results,
+        result,
+        account_id,
+        &migration,
+        action_hash,
+        config,
+        is_last_action,
+        epoch_info_provider,
+    )
+}
+
+/// Verifies the delegate action and creates a receipt with the delegated actions on behalf of its
+/// sender. The sender pays for the deposits of the delegated actions, while the gas for the new
+/// receipt was prepaid by the signer of the transaction (the relayer).
+pub(crate) fn action_delegate(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    account: &mut Account,
+    action_receipt: &ActionReceipt,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    signed_delegate_action: &SignedDelegateAction,
+) -> Result<(), RuntimeError> {
+    if let Err(err) = verify_delegate_action(
+        state_update,
+        signed_delegate_action,
+        account_id,
+        apply_state.block_index,
+    )? {
+        result.result = Err(err.into());
+        return Ok(());
+    }
+    let delegate_action = &signed_delegate_action.delegate_action;
+
+    let deposit = total_deposit(&delegate_action.actions)?;
+    account.amount = match account.amount.checked_sub(deposit) {
+        Some(amount) => amount,
+        None => {
+            result.result = Err(ActionErrorKind::DelegateActionNotEnoughBalance {
+                sender_id: account_id.clone(),
+                balance: account.amount,
+                cost: deposit,
+            }
+            .into());
+            return Ok(());
+        }
+    };
+
+    // The relayer has prepaid the execution of the new receipt as part of this action.
+    let transaction_costs = &apply_state.config.transaction_costs;
+    let mut required_gas = safe_add_gas(
+        transaction_costs.action_receipt_creation_config.exec_fee(),
+        total_prepaid_exec_fees(
+            transaction_costs,
+            &delegate_action.actions,
+            &delegate_action.receiver_id,
+            apply_state.current_protocol_version,
+        )?,
+    )?;
+    required_gas = safe_add_gas(required_gas, total_prepaid_gas(&delegate_action.actions)?)?;
+    result.gas_used = safe_add_gas(result.gas_used, required_gas)?;
+
+    result.new_receipts.push(Receipt {
+        predecessor_id: delegate_action.sender_id.clone(),
+        receiver_id: delegate_action.receiver_id.clone(),
+        receipt_id: CryptoHash::default(),
+        receipt: ReceiptEnum::Action(ActionReceipt {
+            signer_id: action_receipt.signer_id.clone(),
+            signer_public_key: action_receipt.signer_public_key.clone(),
+            gas_price: action_receipt.gas_price,
+            output_data_receivers: vec![],
+            input_data_ids: vec![],
+            actions: delegate_action.actions.clone(),
+        }),
+    });
     Ok(())
 }
 
 pub(crate) fn action_delete_account(
     state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
     account: &mut Option<Account>,
     actor_id: &mut AccountId,
     receipt: &Receipt,
     result: &mut ActionResult,
     account_id: &AccountId,
     delete_account: &DeleteAccountAction,
-) -> Result<(), StorageError> {
+) -> Result<(), RuntimeError> {
     // We use current amount as a pay out to beneficiary.
     let account_balance = account.as_ref().unwrap().amount;
     if account_balance > 0 {
@@ -413,7 +876,44 @@ pub(crate) fn action_delete_account(
             .new_receipts
             .push(Receipt::new_balance_refund(&delete_account.beneficiary_id, account_balance));
     }
-    remove_account(state_update, account_id)?;
+    if checked_feature!(
+        "protocol_feature_scheduled_receipts",
+        ScheduledReceipts,
+        apply_state.current_protocol_version
+    ) {
+        // The receipts scheduled by the account are cancelled, so the index of the deleted
+        // account doesn't outlive it. Their deposits are paid out to the beneficiary as well.
+        for receipt_id in get_scheduled_receipt_ids(state_update, account_id)? {
+            let (schedule, receipt) = get_scheduled_receipt(state_update, account_id, receipt_id)?
+                .ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Scheduled receipt {} should be in the state",
+                        receipt_id
+                    ))
+                })?;
+            remove_scheduled_receipt(state_update, schedule, account_id, receipt_id);
+            refund_cancelled_scheduled_receipt(
+                apply_state,
+                account_id,
+                &delete_account.beneficiary_id,
+                receipt,
+                result,
+            )?;
+        }
+    }
+    if checked_feature!(
+        "protocol_feature_lazy_account_deletion",
+        LazyAccountDeletion,
+        apply_state.current_protocol_version
+    ) {
+        // The contract data can be arbitrarily large, so it's removed by the following chunks.
+        remove_account_without_data(state_update, account_id)?;
+        if has_contract_data(state_update, account_id)? {
+            set_deleted_account(state_update, account_id.clone(), apply_state.block_index);
+        }
+    } else {
+        remove_account(state_update, account_id)?;
+    }
     *actor_id = receipt.predecessor_id.clone();
     *account = None;
     Ok(())
@@ -501,7 +1001,12 @@ pub(crate) fn check_actor_permissions(
     account_id: &AccountId,
 ) -> Result<(), ActionError> {
     match action {
-        Action::DeployContract(_) | Action::Stake(_) | Action::AddKey(_) | Action::DeleteKey(_) => {
+        Action::DeployContract(_)
+        | Action::DeployContractWithAbi(_)
+        | Action::UpgradeContract(_)
+        | Action::Stake(_)
+        | Action::AddKey(_)
+        | Action::DeleteKey(_) => {
             if actor_id != account_id {
                 return Err(ActionErrorKind::ActorNoPermission {
                     account_id: actor_id.clone(),
@@ -526,7 +1031,10 @@ pub(crate) fn check_actor_permissions(
                 .into());
             }
         }
-        Action::CreateAccount(_) | Action::FunctionCall(_) | Action::Transfer(_) => (),
+        Action::CreateAccount(_)
+        | Action::FunctionCall(_)
+        | Action::Transfer(_)
+        | Action::Delegate(_) => (),
     };
     Ok(())
 }
@@ -591,11 +1099,14 @@ pub(crate) fn check_account_existence(
             }
         }
         Action::DeployContract(_)
+        | Action::DeployContractWithAbi(_)
+        | Action::UpgradeContract(_)
         | Action::FunctionCall(_)
         | Action::Stake(_)
         | Action::AddKey(_)
         | Action::DeleteKey(_)
-        | Action::DeleteAccount(_) => {
+        | Action::DeleteAccount(_)
+        | Action::Delegate(_) => {
             if account.is_none() {
                 return Err(ActionErrorKind::AccountDoesNotExist {
                     account_id: account_id.clone(),
@@ -709,4 +1220,40 @@ mod tests {
             test_action_create_account(account_id.clone(), predecessor_id.clone(), 0);
         assert!(action_result.result.is_ok());
     }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_contract_abi")]
+    fn test_deploy_contract_with_abi() {
+        use near_primitives::test_utils::account_new;
+        use near_store::test_utils::create_tries;
+
+        let tries = create_tries();
+        let mut state_update = tries.new_trie_update(0, CryptoHash::default());
+        let account_id = AccountId::from("alice.near");
+        let mut account = account_new(100, CryptoHash::default());
+        let storage_usage = account.storage_usage;
+
+        action_deploy_contract_with_abi(
+            &mut state_update,
+            &mut account,
+            &account_id,
+            &DeployContractWithAbiAction { code: vec![1, 2, 3], abi: vec![4, 5] },
+            near_primitives::version::PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(get_contract_abi(&state_update, &account_id).unwrap(), Some(vec![4, 5]));
+        assert_eq!(account.storage_usage, storage_usage + 5);
+
+        // Deploying the code without ABI removes the ABI of the previous code.
+        action_deploy_contract(
+            &mut state_update,
+            &mut account,
+            &account_id,
+            &DeployContractAction { code: vec![1, 2, 3, 4] },
+            near_primitives::version::PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(get_contract_abi(&state_update, &account_id).unwrap(), None);
+        assert_eq!(account.storage_usage, storage_usage + 4);
+    }
 }
@@ -5,7 +5,10 @@ use near_primitives::types::{
     AccountId, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, MerkleHash, ShardId,
 };
 use near_primitives::version::ProtocolVersion;
-use near_primitives::views::ViewStateResult;
+use near_primitives::views::{
+    CallerContext, ContractAbiView, StateOverrides, StorageStakingView, StorageUsageView,
+    ViewStateResult,
+};
 
 /// Adapter for querying runtime.
 pub trait ViewRuntimeAdapter {
@@ -28,6 +31,8 @@ pub trait ViewRuntimeAdapter {
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        state_overrides: &StateOverrides,
+        caller: Option<&CallerContext>,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
@@ -55,4 +60,27 @@ pub trait ViewRuntimeAdapter {
         account_id: &AccountId,
         prefix: &[u8],
     ) -> Result<ViewStateResult, Box<dyn std::error::Error>>;
+
+    fn view_storage_usage(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+        prefix_len: usize,
+        max_keys: Option<u64>,
+    ) -> Result<StorageUsageView, Box<dyn std::error::Error>>;
+
+    fn view_storage_staking(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+    ) -> Result<StorageStakingView, Box<dyn std::error::Error>>;
+
+    fn view_contract_abi(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+    ) -> Result<ContractAbiView, Box<dyn std::error::Error>>;
 }
@@ -1,7 +1,7 @@
 use crate::safe_add_balance_apply;
 
 use crate::config::{
-    safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit, total_exec_fees,
+    safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit, total_prepaid_exec_fees,
     total_prepaid_gas,
 };
 use crate::{ApplyStats, DelayedReceiptIndices, ValidatorAccountsUpdate};
@@ -18,6 +18,46 @@ use near_runtime_fees::RuntimeFeesConfig;
 use near_store::{get, get_account, get_postponed_receipt, TrieUpdate};
 use std::collections::HashSet;
 
+/// Returns the balance that is held by the receipt: the attached deposits and, unless the receipt
+/// is a refund, the prepaid gas.
+pub(crate) fn receipt_cost(
+    transaction_costs: &RuntimeFeesConfig,
+    receipt: &Receipt,
+    current_protocol_version: ProtocolVersion,
+) -> Result<Balance, IntegerOverflowError> {
+    Ok(match &receipt.receipt {
+        ReceiptEnum::Action(action_receipt) => {
+            let mut total_cost = total_deposit(&action_receipt.actions)?;
+            if receipt.predecessor_id != system_account() {
+                let mut total_gas = safe_add_gas(
+                    transaction_costs.action_receipt_creation_config.exec_fee(),
+                    total_prepaid_exec_fees(
+                        transaction_costs,
+                        &action_receipt.actions,
+                        &receipt.receiver_id,
+                        current_protocol_version,
+                    )?,
+                )?;
+                total_gas = safe_add_gas(total_gas, total_prepaid_gas(&action_receipt.actions)?)?;
+                let total_gas_cost = safe_gas_to_balance(action_receipt.gas_price, total_gas)?;
+                total_cost = safe_add_balance(total_cost, total_gas_cost)?;
+            }
+            total_cost
+        }
+        ReceiptEnum::Data(_) => 0,
+    })
+}
+
+pub(crate) fn receipts_cost(
+    transaction_costs: &RuntimeFeesConfig,
+    receipts: &[Receipt],
+    current_protocol_version: ProtocolVersion,
+) -> Result<Balance, IntegerOverflowError> {
+    receipts.iter().try_fold(0u128, |res, receipt| {
+        safe_add_balance(res, receipt_cost(transaction_costs, receipt, current_protocol_version)?)
+    })
+}
+
 pub(crate) fn check_balance(
     transaction_costs: &RuntimeFeesConfig,
     initial_state: &TrieUpdate,
@@ -99,36 +139,10 @@ pub(crate) fn check_balance(
     let final_accounts_balance = total_accounts_balance(&final_state)?;
     // Receipts
     let receipt_cost = |receipt: &Receipt| -> Result<Balance, IntegerOverflowError> {
-        Ok(match &receipt.receipt {
-            ReceiptEnum::Action(action_receipt) => {
-                let mut total_cost = total_deposit(&action_receipt.actions)?;
-                if receipt.predecessor_id != system_account() {
-                    let mut total_gas = safe_add_gas(
-                        transaction_costs.action_receipt_creation_config.exec_fee(),
-                        total_exec_fees(
-                            transaction_costs,
-                            &action_receipt.actions,
-                            &receipt.receiver_id,
-                            current_protocol_version,
-                        )?,
-                    )?;
-                    total_gas =
-                        safe_add_gas(total_gas, total_prepaid_gas(&action_receipt.actions)?)?;
-                    let total_gas_cost = safe_gas_to_balance(action_receipt.gas_price, total_gas)?;
-                    total_cost = safe_add_balance(total_cost, total_gas_cost)?;
-                }
-                total_cost
-            }
-            ReceiptEnum::Data(_) => 0,
-        })
+        receipt_cost(transaction_costs, receipt, current_protocol_version)
     };
     let receipts_cost = |receipts: &[Receipt]| -> Result<Balance, IntegerOverflowError> {
-        receipts
-            .iter()
-            .map(receipt_cost)
-            .collect::<Result<Vec<Balance>, IntegerOverflowError>>()?
-            .into_iter()
-            .try_fold(0u128, |res, balance| safe_add_balance(res, balance))
+        receipts_cost(transaction_costs, receipts, current_protocol_version)
     };
     let incoming_receipts_balance = receipts_cost(incoming_receipts)?;
     let outgoing_receipts_balance = receipts_cost(outgoing_receipts)?;
@@ -185,13 +199,15 @@ pub(crate) fn check_balance(
         initial_accounts_balance,
         incoming_receipts_balance,
         processed_delayed_receipts_balance,
-        initial_postponed_receipts_balance
+        initial_postponed_receipts_balance,
+        stats.released_scheduled_receipts_balance
     );
     let final_balance = safe_add_balance_apply!(
         final_accounts_balance,
         outgoing_receipts_balance,
         new_delayed_receipts_balance,
         final_postponed_receipts_balance,
+        stats.new_scheduled_receipts_balance,
         stats.tx_burnt_amount,
         stats.slashed_burnt_amount,
         stats.other_burnt_amount
@@ -204,11 +220,13 @@ pub(crate) fn check_balance(
             incoming_receipts_balance,
             processed_delayed_receipts_balance,
             initial_postponed_receipts_balance,
+            released_scheduled_receipts_balance: stats.released_scheduled_receipts_balance,
             // Outputs
             final_accounts_balance,
             outgoing_receipts_balance,
             new_delayed_receipts_balance,
             final_postponed_receipts_balance,
+            new_scheduled_receipts_balance: stats.new_scheduled_receipts_balance,
             tx_burnt_amount: stats.tx_burnt_amount,
             slashed_burnt_amount: stats.slashed_burnt_amount,
             other_burnt_amount: stats.other_burnt_amount,
@@ -387,6 +405,8 @@ mod tests {
                 gas_deficit_amount: 0,
                 other_burnt_amount: 0,
                 slashed_burnt_amount: 0,
+                new_scheduled_receipts_balance: 0,
+                released_scheduled_receipts_balance: 0,
             },
             PROTOCOL_VERSION,
         )
@@ -1,8 +1,9 @@
 //! Settings of the parameters of the runtime.
-use near_primitives::account::AccessKeyPermission;
+use near_primitives::account::{AccessKeyPermission, ExtendedFunctionCallPermission};
 use near_primitives::errors::IntegerOverflowError;
 use near_primitives::transaction::{
-    Action, AddKeyAction, DeployContractAction, FunctionCallAction, Transaction,
+    Action, AddKeyAction, DeployContractAction, DeployContractWithAbiAction, FunctionCallAction,
+    Transaction, UpgradeContractAction,
 };
 use near_primitives::types::{AccountId, Balance, Gas};
 use near_runtime_fees::RuntimeFeesConfig;
@@ -125,15 +126,66 @@ pub fn total_send_fees(
                 AccessKeyPermission::FullAccess => {
                     cfg.add_key_cost.full_access_cost.send_fee(sender_is_receiver)
                 }
+                AccessKeyPermission::ExtendedFunctionCall(call_perm) => {
+                    let num_bytes = extended_function_call_permission_bytes(call_perm);
+                    cfg.add_key_cost.function_call_cost.send_fee(sender_is_receiver)
+                        + num_bytes
+                            * cfg
+                                .add_key_cost
+                                .function_call_cost_per_byte
+                                .send_fee(sender_is_receiver)
+                }
             },
             DeleteKey(_) => cfg.delete_key_cost.send_fee(sender_is_receiver),
             DeleteAccount(_) => cfg.delete_account_cost.send_fee(sender_is_receiver),
+            UpgradeContract(UpgradeContractAction { code, method_name, args, .. }) => {
+                // Upgrade is charged as a deploy followed by a function call.
+                let code_bytes = code.len() as u64;
+                let call_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
+                cfg.deploy_contract_cost.send_fee(sender_is_receiver)
+                    + cfg.deploy_contract_cost_per_byte.send_fee(sender_is_receiver) * code_bytes
+                    + cfg.function_call_cost.send_fee(sender_is_receiver)
+                    + cfg.function_call_cost_per_byte.send_fee(sender_is_receiver) * call_bytes
+            }
+            Delegate(signed_delegate_action) => {
+                // The relayer also pays for sending the receipt with the delegated actions.
+                let delegate_action = &signed_delegate_action.delegate_action;
+                let delegate_sender_is_receiver =
+                    delegate_action.sender_id == delegate_action.receiver_id;
+                cfg.delegate_cost.send_fee(sender_is_receiver)
+                    + config.action_receipt_creation_config.send_fee(delegate_sender_is_receiver)
+                    + total_send_fees(
+                        config,
+                        delegate_sender_is_receiver,
+                        &delegate_action.actions,
+                        &delegate_action.receiver_id,
+                        current_protocol_version,
+                    )?
+            }
+            DeployContractWithAbi(DeployContractWithAbiAction { code, abi }) => {
+                // The ABI is charged as a part of the deployed code.
+                let num_bytes = code.len() as u64 + abi.len() as u64;
+                cfg.deploy_contract_cost.send_fee(sender_is_receiver)
+                    + cfg.deploy_contract_cost_per_byte.send_fee(sender_is_receiver) * num_bytes
+            }
         };
         result = safe_add_gas(result, delta)?;
     }
     Ok(result)
 }
 
+/// Number of bytes of the method names and the receiver ids of the extended function call
+/// permission that are charged per byte when the access key is added.
+fn extended_function_call_permission_bytes(call_perm: &ExtendedFunctionCallPermission) -> u64 {
+    call_perm
+        .method_names
+        .iter()
+        .chain(call_perm.receiver_ids.iter())
+        // Account for null-terminating characters.
+        .map(|name| name.as_bytes().len() as u64 + 1)
+        .sum::<u64>()
+}
+
 pub fn exec_fee(
     config: &RuntimeFeesConfig,
     action: &Action,
@@ -179,9 +231,28 @@ pub fn exec_fee(
                     + num_bytes * cfg.add_key_cost.function_call_cost_per_byte.exec_fee()
             }
             AccessKeyPermission::FullAccess => cfg.add_key_cost.full_access_cost.exec_fee(),
+            AccessKeyPermission::ExtendedFunctionCall(call_perm) => {
+                let num_bytes = extended_function_call_permission_bytes(call_perm);
+                cfg.add_key_cost.function_call_cost.exec_fee()
+                    + num_bytes * cfg.add_key_cost.function_call_cost_per_byte.exec_fee()
+            }
         },
         DeleteKey(_) => cfg.delete_key_cost.exec_fee(),
         DeleteAccount(_) => cfg.delete_account_cost.exec_fee(),
+        UpgradeContract(UpgradeContractAction { code, method_name, args, .. }) => {
+            let code_bytes = code.len() as u64;
+            let call_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
+            cfg.deploy_contract_cost.exec_fee()
+                + cfg.deploy_contract_cost_per_byte.exec_fee() * code_bytes
+                + cfg.function_call_cost.exec_fee()
+                + cfg.function_call_cost_per_byte.exec_fee() * call_bytes
+        }
+        Delegate(_) => cfg.delegate_cost.exec_fee(),
+        DeployContractWithAbi(DeployContractWithAbiAction { code, abi }) => {
+            let num_bytes = code.len() as u64 + abi.len() as u64;
+            cfg.deploy_contract_cost.exec_fee()
+                + cfg.deploy_contract_cost_per_byte.exec_fee() * num_bytes
+        }
     }
 }
 /// Returns transaction costs for a given transaction.
@@ -227,7 +298,7 @@ pub fn tx_cost(
         safe_add_gas(prepaid_gas, config.action_receipt_creation_config.exec_fee())?;
     gas_remaining = safe_add_gas(
         gas_remaining,
-        total_exec_fees(
+        total_prepaid_exec_fees(
             &config,
             &transaction.actions,
             &transaction.receiver_id,
@@ -255,6 +326,35 @@ pub fn total_exec_fees(
     }
     Ok(result)
 }
+/// Total sum of gas that has to be prepaid for the execution of the given actions. Unlike
+/// `total_exec_fees` it includes the execution of the receipts created by delegate actions.
+pub fn total_prepaid_exec_fees(
+    config: &RuntimeFeesConfig,
+    actions: &[Action],
+    receiver_id: &AccountId,
+    current_protocol_version: ProtocolVersion,
+) -> Result<Gas, IntegerOverflowError> {
+    let mut result = 0;
+    for action in actions {
+        let mut delta = exec_fee(&config, action, receiver_id, current_protocol_version);
+        if let Action::Delegate(signed_delegate_action) = action {
+            let delegate_action = &signed_delegate_action.delegate_action;
+            delta = safe_add_gas(delta, config.action_receipt_creation_config.exec_fee())?;
+            delta = safe_add_gas(
+                delta,
+                total_prepaid_exec_fees(
+                    config,
+                    &delegate_action.actions,
+                    &delegate_action.receiver_id,
+                    current_protocol_version,
+                )?,
+            )?;
+        }
+        result = safe_add_gas(result, delta)?;
+    }
+    Ok(result)
+}
+
 /// Get the total sum of deposits for given actions.
 pub fn total_deposit(actions: &[Action]) -> Result<Balance, IntegerOverflowError> {
     let mut total_balance: Balance = 0;
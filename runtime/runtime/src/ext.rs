@@ -3,22 +3,30 @@ use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
 use near_primitives::errors::{ExternalError, StorageError};
 use near_primitives::hash::CryptoHash;
-use near_primitives::receipt::{ActionReceipt, DataReceiver, Receipt, ReceiptEnum};
+use near_primitives::receipt::{
+    ActionReceipt, DataReceiver, Receipt, ReceiptEnum, ReceiptSchedule,
+};
 use near_primitives::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
     DeployContractAction, FunctionCallAction, StakeAction, TransferAction,
 };
 use near_primitives::trie_key::TrieKey;
-use near_primitives::types::{AccountId, Balance, EpochId, EpochInfoProvider};
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochId, EpochInfoProvider};
 use near_primitives::utils::create_data_id;
 use near_primitives::version::ProtocolVersion;
-use near_store::{TrieUpdate, TrieUpdateValuePtr};
+use near_store::{get_scheduled_receipt, remove_scheduled_receipt, TrieUpdate, TrieUpdateValuePtr};
+use near_vm_logic::types::{ReceiptIndex, ReceiptSchedule as VMReceiptSchedule};
 use near_vm_logic::{External, HostError, VMLogicError, ValuePtr};
+use std::convert::TryFrom;
 
 pub struct RuntimeExt<'a> {
     trie_update: &'a mut TrieUpdate,
     account_id: &'a AccountId,
     action_receipts: Vec<(AccountId, ActionReceipt)>,
+    /// Indices of the receipts that are scheduled, their schedules and the receipt IDs.
+    scheduled_receipts: Vec<(ReceiptIndex, ReceiptSchedule, CryptoHash)>,
+    /// Previously scheduled receipts that were cancelled and removed from the state.
+    cancelled_scheduled_receipts: Vec<Receipt>,
     signer_id: &'a AccountId,
     signer_public_key: &'a PublicKey,
     gas_price: Balance,
@@ -26,6 +34,10 @@ pub struct RuntimeExt<'a> {
     data_count: u64,
     epoch_id: &'a EpochId,
     last_block_hash: &'a CryptoHash,
+    /// The height and the timestamp of the current block, scheduled receipts that are due at
+    /// them can't be cancelled anymore.
+    block_height: BlockHeight,
+    block_timestamp: u64,
     epoch_info_provider: &'a dyn EpochInfoProvider,
     current_protocol_version: ProtocolVersion,
 }
@@ -52,6 +64,8 @@ impl<'a> RuntimeExt<'a> {
         action_hash: &'a CryptoHash,
         epoch_id: &'a EpochId,
         last_block_hash: &'a CryptoHash,
+        block_height: BlockHeight,
+        block_timestamp: u64,
         epoch_info_provider: &'a dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
     ) -> Self {
@@ -59,6 +73,8 @@ impl<'a> RuntimeExt<'a> {
             trie_update,
             account_id,
             action_receipts: vec![],
+            scheduled_receipts: vec![],
+            cancelled_scheduled_receipts: vec![],
             signer_id,
             signer_public_key,
             gas_price,
@@ -66,6 +82,8 @@ impl<'a> RuntimeExt<'a> {
             data_count: 0,
             epoch_id,
             last_block_hash,
+            block_height,
+            block_timestamp,
             epoch_info_provider,
             current_protocol_version,
         }
@@ -98,6 +116,17 @@ impl<'a> RuntimeExt<'a> {
             .collect()
     }
 
+    /// Takes the receipts scheduled during the execution with their receipt indices, and the
+    /// scheduled receipts that were cancelled.
+    pub fn take_scheduled_receipts(
+        &mut self,
+    ) -> (Vec<(ReceiptIndex, ReceiptSchedule, CryptoHash)>, Vec<Receipt>) {
+        (
+            std::mem::take(&mut self.scheduled_receipts),
+            std::mem::take(&mut self.cancelled_scheduled_receipts),
+        )
+    }
+
     fn append_action(&mut self, receipt_index: u64, action: Action) {
         self.action_receipts
             .get_mut(receipt_index as usize)
@@ -169,6 +198,43 @@ impl<'a> External for RuntimeExt<'a> {
         Ok(new_receipt_index)
     }
 
+    fn create_scheduled_receipt(
+        &mut self,
+        receiver_id: AccountId,
+        schedule: VMReceiptSchedule,
+    ) -> ExtResult<(ReceiptIndex, Vec<u8>)> {
+        let new_receipt_index = self.create_receipt(vec![], receiver_id)?;
+        let receipt_id = self.new_data_id();
+        let schedule = match schedule {
+            VMReceiptSchedule::BlockHeight(block_height) => {
+                ReceiptSchedule::BlockHeight(block_height)
+            }
+            VMReceiptSchedule::BlockTimestamp(block_timestamp) => {
+                ReceiptSchedule::BlockTimestamp(block_timestamp)
+            }
+        };
+        self.scheduled_receipts.push((new_receipt_index, schedule, receipt_id));
+        Ok((new_receipt_index, receipt_id.as_ref().to_vec()))
+    }
+
+    fn cancel_scheduled_receipt(&mut self, receipt_id: &[u8]) -> ExtResult<bool> {
+        let receipt_id =
+            CryptoHash::try_from(receipt_id).map_err(|_| HostError::InvalidReceiptId)?;
+        let (schedule, receipt) =
+            match get_scheduled_receipt(self.trie_update, self.account_id, receipt_id)
+                .map_err(wrap_storage_error)?
+            {
+                Some(scheduled_receipt) => scheduled_receipt,
+                None => return Ok(false),
+            };
+        if schedule.is_due(self.block_height, self.block_timestamp) {
+            return Ok(false);
+        }
+        remove_scheduled_receipt(self.trie_update, schedule, self.account_id, receipt_id);
+        self.cancelled_scheduled_receipts.push(receipt);
+        Ok(true)
+    }
+
     fn append_action_create_account(&mut self, receipt_index: u64) -> ExtResult<()> {
         self.append_action(receipt_index, Action::CreateAccount(CreateAccountAction {}));
         Ok(())
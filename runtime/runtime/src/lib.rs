@@ -10,41 +10,49 @@ use near_primitives::contract::ContractCode;
 use near_primitives::errors::{ActionError, ActionErrorKind, RuntimeError, TxExecutionError};
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{
-    ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData,
+    ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceiptSchedule,
+    ReceivedData,
 };
+use near_primitives::sharding::CongestionInfo;
 use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{
     Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, LogEntry, SignedTransaction,
 };
-use near_primitives::trie_key::TrieKey;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{
     AccountId, Balance, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, Gas, MerkleHash,
     RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot, ValidatorStake,
 };
 use near_primitives::utils::{
-    create_action_hash, create_receipt_id_from_receipt, create_receipt_id_from_transaction,
-    system_account,
+    create_action_hash, create_deposit_refund_receipt_id, create_receipt_id_from_receipt,
+    create_receipt_id_from_transaction, system_account,
 };
 use near_runtime_configs::get_insufficient_storage_stake;
 use near_store::{
-    get, get_account, get_postponed_receipt, get_received_data, remove_postponed_receipt, set,
-    set_access_key, set_account, set_code, set_postponed_receipt, set_received_data,
-    PartialStorage, ShardTries, StorageError, StoreUpdate, Trie, TrieChanges, TrieUpdate,
+    get, get_account, get_deleted_account, get_postponed_receipt, get_received_data,
+    remove_contract_data, remove_deleted_account, remove_postponed_receipt,
+    remove_scheduled_receipt, set, set_access_key, set_account, set_code, set_contract_abi,
+    set_postponed_receipt, set_received_data, set_scheduled_receipt, PartialStorage, ShardTries,
+    StorageError, StoreUpdate, Trie, TrieChanges, TrieUpdate,
 };
 use near_vm_logic::types::PromiseResult;
-use near_vm_logic::ReturnData;
+use near_vm_logic::{ReturnData, VMKind};
+pub use near_vm_runner::is_vm_kind_supported;
 use near_vm_runner::CompiledContractCache;
 #[cfg(feature = "costs_counting")]
 pub use near_vm_runner::EXT_COSTS_COUNTER;
 
 use crate::actions::*;
-use crate::balance_checker::check_balance;
+use crate::balance_checker::{check_balance, receipts_cost};
 use crate::config::{
-    exec_fee, safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit, total_exec_fees,
-    total_prepaid_gas, RuntimeConfig,
+    exec_fee, safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit,
+    total_prepaid_exec_fees, total_prepaid_gas, RuntimeConfig,
 };
 use crate::verifier::validate_receipt;
-pub use crate::verifier::{validate_transaction, verify_and_charge_transaction};
+pub use crate::verifier::{
+    check_receiver_congestion, validate_transaction, verify_and_charge_transaction,
+};
+use near_primitives::checked_feature;
 use near_primitives::version::{ProtocolVersion, IMPLICIT_ACCOUNT_CREATION_PROTOCOL_VERSION};
 use near_runtime_fees::RuntimeFeesConfig;
 use std::borrow::Borrow;
@@ -60,14 +68,30 @@ pub mod ext;
 mod metrics;
 pub mod state_viewer;
 mod verifier;
+mod vm_diff;
 
 const EXPECT_ACCOUNT_EXISTS: &str = "account exists, checked above";
 
+/// The maximum number of due scheduled receipts of each schedule kind that are released by a
+/// single chunk. The remaining due receipts are released by the following chunks.
+const MAX_RELEASED_SCHEDULED_RECEIPTS: usize = 100;
+
+/// The maximum amount of gas that a single chunk spends on removing the contract data of the
+/// deleted accounts. The remaining records are removed by the following chunks.
+const MAX_DELETED_ACCOUNTS_CLEANUP_GAS: Gas = 50 * 10u64.pow(12);
+
+/// The size of the delayed receipts queue at which a shard is considered congested. Every chunk
+/// sends at most `CONGESTED_DELAYED_RECEIPTS_THRESHOLD - delayed_receipts_count` receipts to
+/// a shard, the remaining outgoing receipts are buffered in the state of the sender's shard.
+const CONGESTED_DELAYED_RECEIPTS_THRESHOLD: u64 = 1000;
+
 #[derive(Debug)]
 pub struct ApplyState {
     /// Currently building block height.
     // TODO #1903 pub block_height: BlockHeight,
     pub block_index: BlockHeight,
+    /// The shard that is being applied.
+    pub shard_id: ShardId,
     /// Prev block hash
     pub last_block_hash: CryptoHash,
     /// Current epoch id
@@ -89,6 +113,12 @@ pub struct ApplyState {
     pub config: Arc<RuntimeConfig>,
     /// Cache for compiled contracts.
     pub cache: Option<Arc<dyn CompiledContractCache>>,
+    /// If set, every function call is also executed on the given VM and the differences with the
+    /// default VM are reported. Results of the extra execution are discarded.
+    pub differential_vm: Option<VMKind>,
+    /// Sizes of the delayed receipts queues of all shards as of the previous block. It's used to
+    /// throttle outgoing receipts to congested shards.
+    pub congestion_info: CongestionInfo,
 }
 
 /// Contains information to update validators accounts at the first block of a new epoch.
@@ -116,6 +146,8 @@ pub struct VerificationResult {
     pub receipt_gas_price: Balance,
     /// The balance that was burnt to convert the transaction into a receipt and send it.
     pub burnt_amount: Balance,
+    /// The deposit that was charged from the deposit allowance of the access key.
+    pub charged_deposit: Balance,
 }
 
 #[derive(Debug, Default)]
@@ -126,6 +158,10 @@ pub struct ApplyStats {
     /// This is a negative amount. This amount was not charged from the account that issued
     /// the transaction. It's likely due to the delayed queue of the receipts.
     pub gas_deficit_amount: Balance,
+    /// The balance held by the receipts that were scheduled and stored in the state.
+    pub new_scheduled_receipts_balance: Balance,
+    /// The balance held by the scheduled receipts that were released or cancelled.
+    pub released_scheduled_receipts_balance: Balance,
 }
 
 pub struct ApplyResult {
@@ -148,6 +184,10 @@ pub struct ActionResult {
     pub logs: Vec<LogEntry>,
     pub new_receipts: Vec<Receipt>,
     pub validator_proposals: Vec<ValidatorStake>,
+    /// Receipts that were scheduled and stored in the state.
+    pub new_scheduled_receipts: Vec<Receipt>,
+    /// Previously scheduled receipts that were cancelled and removed from the state.
+    pub cancelled_scheduled_receipts: Vec<Receipt>,
 }
 
 impl ActionResult {
@@ -174,9 +214,13 @@ impl ActionResult {
         if self.result.is_ok() {
             self.new_receipts.append(&mut next_result.new_receipts);
             self.validator_proposals.append(&mut next_result.validator_proposals);
+            self.new_scheduled_receipts.append(&mut next_result.new_scheduled_receipts);
+            self.cancelled_scheduled_receipts.append(&mut next_result.cancelled_scheduled_receipts);
         } else {
             self.new_receipts.clear();
             self.validator_proposals.clear();
+            self.new_scheduled_receipts.clear();
+            self.cancelled_scheduled_receipts.clear();
         }
         Ok(())
     }
@@ -192,6 +236,8 @@ impl Default for ActionResult {
             logs: vec![],
             new_receipts: vec![],
             validator_proposals: vec![],
+            new_scheduled_receipts: vec![],
+            cancelled_scheduled_receipts: vec![],
         }
     }
 }
@@ -240,19 +286,31 @@ impl Runtime {
             apply_state.gas_price,
             signed_transaction,
             true,
+            Some(apply_state.block_index),
             apply_state.current_protocol_version,
         ) {
             Ok(verification_result) => {
                 near_metrics::inc_counter(&metrics::TRANSACTION_PROCESSED_SUCCESSFULLY_TOTAL);
-                state_update.commit(StateChangeCause::TransactionProcessing {
-                    tx_hash: signed_transaction.get_hash(),
-                });
                 let transaction = &signed_transaction.transaction;
                 let receipt_id = create_receipt_id_from_transaction(
                     apply_state.current_protocol_version,
                     &signed_transaction,
                     &apply_state.last_block_hash,
                 );
+                if verification_result.charged_deposit > 0 {
+                    // Only the deposits charged here refund the deposit allowance, see
+                    // `generate_refund_receipts`.
+                    record_charged_deposit(
+                        state_update,
+                        &transaction.signer_id,
+                        &transaction.public_key,
+                        create_deposit_refund_receipt_id(&receipt_id),
+                        verification_result.charged_deposit,
+                    )?;
+                }
+                state_update.commit(StateChangeCause::TransactionProcessing {
+                    tx_hash: signed_transaction.get_hash(),
+                });
                 let receipt = Receipt {
                     predecessor_id: transaction.signer_id.clone(),
                     receiver_id: transaction.receiver_id.clone(),
@@ -328,6 +386,22 @@ impl Runtime {
             result.result = Err(e);
             return Ok(result);
         }
+        // The account is being created, it's not allowed while the contract data of the deleted
+        // account with the same ID is still in the state.
+        if account.is_none()
+            && checked_feature!(
+                "protocol_feature_lazy_account_deletion",
+                LazyAccountDeletion,
+                apply_state.current_protocol_version
+            )
+            && get_deleted_account(state_update, account_id)?.is_some()
+        {
+            result.result = Err(ActionErrorKind::DeletedAccountCleanupInProgress {
+                account_id: account_id.clone(),
+            }
+            .into());
+            return Ok(result);
+        }
         // Permission validation
         if let Err(e) = check_actor_permissions(action, account, &actor_id, account_id) {
             result.result = Err(e);
@@ -353,6 +427,47 @@ impl Runtime {
                     account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
                     &account_id,
                     deploy_contract,
+                    apply_state.current_protocol_version,
+                )?;
+            }
+            Action::DeployContractWithAbi(deploy_contract_with_abi) => {
+                near_metrics::inc_counter(&metrics::ACTION_DEPLOY_CONTRACT_WITH_ABI_TOTAL);
+                action_deploy_contract_with_abi(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &account_id,
+                    deploy_contract_with_abi,
+                    apply_state.current_protocol_version,
+                )?;
+            }
+            Action::UpgradeContract(upgrade_contract) => {
+                near_metrics::inc_counter(&metrics::ACTION_UPGRADE_CONTRACT_TOTAL);
+                action_upgrade_contract(
+                    state_update,
+                    apply_state,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    receipt,
+                    action_receipt,
+                    promise_results,
+                    &mut result,
+                    account_id,
+                    upgrade_contract,
+                    action_hash,
+                    &apply_state.config,
+                    action_index + 1 == actions.len(),
+                    epoch_info_provider,
+                )?;
+            }
+            Action::Delegate(signed_delegate_action) => {
+                near_metrics::inc_counter(&metrics::ACTION_DELEGATE_TOTAL);
+                action_delegate(
+                    state_update,
+                    apply_state,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    action_receipt,
+                    &mut result,
+                    account_id,
+                    signed_delegate_action,
                 )?;
             }
             Action::FunctionCall(function_call) => {
@@ -386,6 +501,16 @@ impl Runtime {
                             transfer,
                         )?;
                     }
+                    // Check if this is a deposit refund, then try to refund the deposit allowance.
+                    if receipt.is_deposit_refund() {
+                        try_refund_deposit_allowance(
+                            state_update,
+                            &receipt.receiver_id,
+                            &action_receipt.signer_public_key,
+                            &receipt.receipt_id,
+                            transfer,
+                        )?;
+                    }
                 } else {
                     // Implicit account creation
                     debug_assert!(
@@ -440,6 +565,7 @@ impl Runtime {
                 near_metrics::inc_counter(&metrics::ACTION_DELETE_ACCOUNT_TOTAL);
                 action_delete_account(
                     state_update,
+                    apply_state,
                     account,
                     actor_id,
                     receipt,
@@ -590,6 +716,25 @@ impl Runtime {
         // Moving validator proposals
         validator_proposals.append(&mut result.validator_proposals);
 
+        // Accounting the balance of the scheduled receipts that were stored or removed. Both lists
+        // are empty if the execution failed.
+        stats.new_scheduled_receipts_balance = safe_add_balance(
+            stats.new_scheduled_receipts_balance,
+            receipts_cost(
+                &apply_state.config.transaction_costs,
+                &result.new_scheduled_receipts,
+                apply_state.current_protocol_version,
+            )?,
+        )?;
+        stats.released_scheduled_receipts_balance = safe_add_balance(
+            stats.released_scheduled_receipts_balance,
+            receipts_cost(
+                &apply_state.config.transaction_costs,
+                &result.cancelled_scheduled_receipts,
+                apply_state.current_protocol_version,
+            )?,
+        )?;
+
         // Committing or rolling back state.
         match &result.result {
             Ok(_) => {
@@ -680,12 +825,18 @@ impl Runtime {
             .into_iter()
             .enumerate()
             .filter_map(|(receipt_index, mut new_receipt)| {
-                let receipt_id = create_receipt_id_from_receipt(
-                    apply_state.current_protocol_version,
-                    &receipt,
-                    &apply_state.last_block_hash,
-                    receipt_index,
-                );
+                // The deposit refund has the ID that was recorded with the charged deposit when
+                // the transaction was converted into this receipt.
+                let receipt_id = if new_receipt.is_deposit_refund() {
+                    create_deposit_refund_receipt_id(&receipt.receipt_id)
+                } else {
+                    create_receipt_id_from_receipt(
+                        apply_state.current_protocol_version,
+                        &receipt,
+                        &apply_state.last_block_hash,
+                        receipt_index,
+                    )
+                };
 
                 new_receipt.receipt_id = receipt_id;
                 let is_action = match &new_receipt.receipt {
@@ -742,7 +893,7 @@ impl Runtime {
         let total_deposit = total_deposit(&action_receipt.actions)?;
         let prepaid_gas = total_prepaid_gas(&action_receipt.actions)?;
         let exec_gas = safe_add_gas(
-            total_exec_fees(
+            total_prepaid_exec_fees(
                 &transaction_costs,
                 &action_receipt.actions,
                 &receipt.receiver_id,
@@ -786,9 +937,25 @@ impl Runtime {
             )?;
         }
         if deposit_refund > 0 {
-            result
-                .new_receipts
-                .push(Receipt::new_balance_refund(&receipt.predecessor_id, deposit_refund));
+            // Deposits attached directly by the signer refund the deposit allowance of the access
+            // key if it was charged when the transaction was verified. The deposit refund receipt
+            // only carries the access key, the charged deposit is matched by the receipt ID.
+            if checked_feature!(
+                "protocol_feature_extended_function_call_access_key",
+                ExtendedFunctionCallAccessKey,
+                current_protocol_version
+            ) && receipt.predecessor_id == action_receipt.signer_id
+            {
+                result.new_receipts.push(Receipt::new_deposit_refund(
+                    &receipt.predecessor_id,
+                    deposit_refund,
+                    action_receipt.signer_public_key.clone(),
+                ));
+            } else {
+                result
+                    .new_receipts
+                    .push(Receipt::new_balance_refund(&receipt.predecessor_id, deposit_refund));
+            }
         }
         if gas_balance_refund > 0 {
             // Gas refunds refund the allowance of the access key, so if the key exists on the
@@ -1106,10 +1273,31 @@ impl Runtime {
         }
 
         let mut outgoing_receipts = Vec::new();
+        let mut cleanup_gas_burnt = 0;
+        if checked_feature!(
+            "protocol_feature_scheduled_receipts",
+            ScheduledReceipts,
+            apply_state.current_protocol_version
+        ) {
+            self.release_scheduled_receipts(
+                &mut state_update,
+                apply_state,
+                &mut outgoing_receipts,
+                &mut stats,
+            )?;
+        }
+        if checked_feature!(
+            "protocol_feature_lazy_account_deletion",
+            LazyAccountDeletion,
+            apply_state.current_protocol_version
+        ) {
+            cleanup_gas_burnt = self.cleanup_deleted_accounts(&mut state_update, apply_state)?;
+        }
         let mut validator_proposals = vec![];
         let mut local_receipts = vec![];
         let mut outcomes = vec![];
-        let mut total_gas_burnt = 0;
+        // The cleanup of the deleted accounts counts toward the gas limit of the chunk.
+        let mut total_gas_burnt = cleanup_gas_burnt;
 
         for signed_transaction in transactions {
             let (receipt, outcome_with_id) = self.process_transaction(
@@ -1231,6 +1419,25 @@ impl Runtime {
 
         state_update.commit(StateChangeCause::UpdatedDelayedReceipts);
 
+        near_metrics::set_gauge_vec(
+            &metrics::DELAYED_RECEIPTS_COUNT,
+            &[&apply_state.shard_id.to_string()],
+            (delayed_receipts_indices.next_available_index - delayed_receipts_indices.first_index)
+                as i64,
+        );
+
+        // Buffering only moves receipts between the outgoing receipts and the state, so it's done
+        // after the balance check.
+        let outgoing_receipts = if checked_feature!(
+            "protocol_feature_congestion_control",
+            CongestionControl,
+            apply_state.current_protocol_version
+        ) {
+            self.buffer_outgoing_receipts(&mut state_update, apply_state, outgoing_receipts)?
+        } else {
+            outgoing_receipts
+        };
+
         let (trie_changes, state_changes) = state_update.finalize()?;
 
         // Dedup proposals from the same account.
@@ -1258,6 +1465,269 @@ impl Runtime {
         })
     }
 
+    /// Removes the scheduled receipts that are due at the current block from the state and sends
+    /// them to their receivers. The storage they occupied is released from the accounts that
+    /// scheduled them. Same as for the delayed receipts, receipts stop being released once the
+    /// gas they need to be executed reaches the gas limit of the chunk, the remaining due receipts
+    /// are released by the following chunks.
+    fn release_scheduled_receipts(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        outgoing_receipts: &mut Vec<Receipt>,
+        stats: &mut ApplyStats,
+    ) -> Result<(), RuntimeError> {
+        let gas_limit = apply_state.gas_limit.unwrap_or(Gas::max_value());
+        let mut released_gas: Gas = 0;
+        let mut released_receipts = vec![];
+        for schedule_kind in &[ReceiptSchedule::BlockHeight(0), ReceiptSchedule::BlockTimestamp(0)]
+        {
+            if released_gas >= gas_limit {
+                break;
+            }
+            let prefix = trie_key_parsers::get_raw_prefix_for_scheduled_receipts(schedule_kind);
+            let mut due_receipts = vec![];
+            for raw_key in state_update.iter(&prefix)? {
+                let key =
+                    trie_key_parsers::parse_trie_key_scheduled_receipt_from_raw_key(&raw_key?)
+                        .map_err(|_| {
+                            StorageError::StorageInconsistentState(
+                                "Can't parse the key of a scheduled receipt".to_string(),
+                            )
+                        })?;
+                // Keys are ordered by the due block height or timestamp.
+                match &key {
+                    TrieKey::ScheduledReceipt { schedule, .. }
+                        if schedule
+                            .is_due(apply_state.block_index, apply_state.block_timestamp) =>
+                    {
+                        due_receipts.push(key)
+                    }
+                    _ => break,
+                }
+                if due_receipts.len() >= MAX_RELEASED_SCHEDULED_RECEIPTS {
+                    break;
+                }
+            }
+            for key in due_receipts {
+                if released_gas >= gas_limit {
+                    break;
+                }
+                let receipt: Receipt = get(state_update, &key)?.ok_or_else(|| {
+                    StorageError::StorageInconsistentState(
+                        "Scheduled receipt should be in the state".to_string(),
+                    )
+                })?;
+                if let ReceiptEnum::Action(action_receipt) = &receipt.receipt {
+                    released_gas = safe_add_gas(
+                        released_gas,
+                        safe_add_gas(
+                            total_prepaid_gas(&action_receipt.actions)?,
+                            total_prepaid_exec_fees(
+                                &apply_state.config.transaction_costs,
+                                &action_receipt.actions,
+                                &receipt.receiver_id,
+                                apply_state.current_protocol_version,
+                            )?,
+                        )?,
+                    )?;
+                }
+                let schedule = match key {
+                    TrieKey::ScheduledReceipt { schedule, .. } => schedule,
+                    _ => unreachable!(),
+                };
+                remove_scheduled_receipt(
+                    state_update,
+                    schedule,
+                    &receipt.predecessor_id,
+                    receipt.receipt_id,
+                );
+                if let Some(mut account) = get_account(state_update, &receipt.predecessor_id)? {
+                    account.storage_usage =
+                        account.storage_usage.saturating_sub(scheduled_receipt_storage_usage(
+                            &apply_state.config.transaction_costs.storage_usage_config,
+                            schedule,
+                            &receipt,
+                        ));
+                    set_account(state_update, receipt.predecessor_id.clone(), &account);
+                }
+                released_receipts.push(receipt);
+            }
+        }
+        if !released_receipts.is_empty() {
+            near_metrics::inc_counter_by(
+                &metrics::SCHEDULED_RECEIPTS_RELEASED_TOTAL,
+                released_receipts.len() as i64,
+            );
+            stats.released_scheduled_receipts_balance = safe_add_balance(
+                stats.released_scheduled_receipts_balance,
+                receipts_cost(
+                    &apply_state.config.transaction_costs,
+                    &released_receipts,
+                    apply_state.current_protocol_version,
+                )?,
+            )?;
+            state_update.commit(StateChangeCause::ReleasedScheduledReceipts);
+            outgoing_receipts.extend(released_receipts);
+        }
+        Ok(())
+    }
+
+    /// Removes the contract data left in the state by the deleted accounts. Removing a record
+    /// costs the same gas as `storage_remove` of its key, and a chunk spends at most
+    /// `MAX_DELETED_ACCOUNTS_CLEANUP_GAS`, but no more than its gas limit. Once all the data of
+    /// an account is removed, the account ID can be used again. Returns the burnt gas.
+    fn cleanup_deleted_accounts(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+    ) -> Result<Gas, StorageError> {
+        let ext_costs = &apply_state.config.wasm_config.ext_costs;
+        let remove_gas = |key_len: usize| {
+            ext_costs
+                .storage_remove_base
+                .saturating_add(ext_costs.storage_remove_key_byte.saturating_mul(key_len as Gas))
+        };
+        let gas_limit =
+            apply_state.gas_limit.unwrap_or(Gas::max_value()).min(MAX_DELETED_ACCOUNTS_CLEANUP_GAS);
+        let prefix = trie_key_parsers::get_raw_prefix_for_deleted_accounts();
+        let mut gas_burnt: Gas = 0;
+        let mut num_removed = 0;
+        let mut has_deleted_accounts = false;
+        loop {
+            // The accounts that are cleaned up are removed from the state, so the first remaining
+            // key is always the next account to clean up.
+            let raw_key = match state_update.iter(&prefix)?.next() {
+                Some(raw_key) => raw_key?,
+                None => break,
+            };
+            has_deleted_accounts = true;
+            let account_id = trie_key_parsers::parse_account_id_from_deleted_account_key(&raw_key)
+                .map_err(|_| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse the key of a deleted account".to_string(),
+                    )
+                })?;
+            let is_removed = remove_contract_data(state_update, &account_id, |key| {
+                let new_gas_burnt = gas_burnt.saturating_add(remove_gas(key.len()));
+                if new_gas_burnt > gas_limit {
+                    return false;
+                }
+                gas_burnt = new_gas_burnt;
+                num_removed += 1;
+                true
+            })?;
+            let new_gas_burnt = gas_burnt.saturating_add(remove_gas(raw_key.len()));
+            if !is_removed || new_gas_burnt > gas_limit {
+                break;
+            }
+            gas_burnt = new_gas_burnt;
+            remove_deleted_account(state_update, account_id);
+        }
+        if !has_deleted_accounts {
+            return Ok(0);
+        }
+        near_metrics::inc_counter_by(&metrics::DELETED_ACCOUNT_DATA_REMOVED_TOTAL, num_removed);
+        state_update.commit(StateChangeCause::DeletedAccountsCleanup);
+        Ok(gas_burnt)
+    }
+
+    /// Throttles the outgoing receipts sent to congested shards. For every other shard, the
+    /// previously buffered receipts are forwarded first while the receiver shard has capacity,
+    /// then the new outgoing receipts. The receipts that exceed the capacity are buffered in the
+    /// state in the same order.
+    fn buffer_outgoing_receipts(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        outgoing_receipts: Vec<Receipt>,
+    ) -> Result<Vec<Receipt>, StorageError> {
+        let congestion_info = &apply_state.congestion_info;
+        if congestion_info.delayed_receipts_counts.is_empty() {
+            return Ok(outgoing_receipts);
+        }
+        let shard_layout = &congestion_info.shard_layout;
+        let mut receipts_to_send = vec![];
+        let mut receipts_by_shard: HashMap<ShardId, Vec<Receipt>> = HashMap::new();
+        for receipt in outgoing_receipts {
+            let receiver_shard_id = shard_layout.account_id_to_shard_id(&receipt.receiver_id);
+            if receiver_shard_id == apply_state.shard_id {
+                receipts_to_send.push(receipt);
+            } else {
+                receipts_by_shard.entry(receiver_shard_id).or_default().push(receipt);
+            }
+        }
+        for receiver_shard_id in shard_layout.shard_ids() {
+            if receiver_shard_id == apply_state.shard_id {
+                continue;
+            }
+            let mut capacity = CONGESTED_DELAYED_RECEIPTS_THRESHOLD
+                .saturating_sub(congestion_info.delayed_receipts_count(receiver_shard_id));
+            let indices_key = TrieKey::BufferedReceiptIndices { receiver_shard_id };
+            let mut buffered_receipts_indices: DelayedReceiptIndices =
+                get(state_update, &indices_key)?.unwrap_or_default();
+            let initial_buffered_receipts_indices = buffered_receipts_indices.clone();
+            while capacity > 0
+                && buffered_receipts_indices.first_index
+                    < buffered_receipts_indices.next_available_index
+            {
+                let key = TrieKey::BufferedReceipt {
+                    receiver_shard_id,
+                    index: buffered_receipts_indices.first_index,
+                };
+                let receipt: Receipt = get(state_update, &key)?.ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Buffered receipt #{} for shard {} should be in the state",
+                        buffered_receipts_indices.first_index, receiver_shard_id
+                    ))
+                })?;
+                state_update.remove(key);
+                // Math checked above: first_index is less than next_available_index
+                buffered_receipts_indices.first_index += 1;
+                capacity -= 1;
+                receipts_to_send.push(receipt);
+            }
+            for receipt in receipts_by_shard.remove(&receiver_shard_id).unwrap_or_default() {
+                if capacity > 0
+                    && buffered_receipts_indices.first_index
+                        == buffered_receipts_indices.next_available_index
+                {
+                    capacity -= 1;
+                    receipts_to_send.push(receipt);
+                } else {
+                    set(
+                        state_update,
+                        TrieKey::BufferedReceipt {
+                            receiver_shard_id,
+                            index: buffered_receipts_indices.next_available_index,
+                        },
+                        &receipt,
+                    );
+                    buffered_receipts_indices.next_available_index = buffered_receipts_indices
+                        .next_available_index
+                        .checked_add(1)
+                        .ok_or_else(|| {
+                            StorageError::StorageInconsistentState(
+                                "Next available index for buffered receipt exceeded the integer limit"
+                                    .to_string(),
+                            )
+                        })?;
+                }
+            }
+            if buffered_receipts_indices != initial_buffered_receipts_indices {
+                set(state_update, indices_key, &buffered_receipts_indices);
+            }
+            near_metrics::set_gauge_vec(
+                &metrics::BUFFERED_RECEIPTS_COUNT,
+                &[&apply_state.shard_id.to_string(), &receiver_shard_id.to_string()],
+                (buffered_receipts_indices.next_available_index
+                    - buffered_receipts_indices.first_index) as i64,
+            );
+        }
+        state_update.commit(StateChangeCause::UpdatedBufferedReceipts);
+        Ok(receipts_to_send)
+    }
+
     // Adds the given receipt into the end of the delayed receipt queue in the state.
     fn delay_receipt(
         state_update: &mut TrieUpdate,
@@ -1312,6 +1782,13 @@ impl Runtime {
                 StateRecord::PostponedReceipt(_) => None,
                 StateRecord::ReceivedData { .. } => None,
                 StateRecord::DelayedReceipt(_) => None,
+                StateRecord::ScheduledReceipt { schedule, receipt } => Some((
+                    receipt.predecessor_id.clone(),
+                    scheduled_receipt_storage_usage(config, *schedule, receipt),
+                )),
+                StateRecord::ContractAbi { account_id, abi } => {
+                    Some((account_id.clone(), abi.len() as u64))
+                }
             };
             if let Some((account, storage_usage)) = account_and_storage {
                 *result.entry(account).or_default() += storage_usage;
@@ -1370,6 +1847,12 @@ impl Runtime {
                     )
                     .unwrap();
                 }
+                StateRecord::ScheduledReceipt { schedule, receipt } => {
+                    set_scheduled_receipt(&mut state_update, schedule, &receipt);
+                }
+                StateRecord::ContractAbi { account_id, abi } => {
+                    set_contract_abi(&mut state_update, account_id, abi);
+                }
             }
         }
         for (account_id, storage_usage) in self.compute_storage_usage(records, &config) {
@@ -1442,14 +1925,18 @@ mod tests {
     use super::*;
 
     use crate::cache::StoreCompiledContractCache;
+    use crate::config::total_exec_fees;
     use near_crypto::{InMemorySigner, KeyType, Signer};
     use near_primitives::errors::ReceiptValidationError;
     use near_primitives::hash::hash;
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
     use near_primitives::transaction::{FunctionCallAction, TransferAction};
     use near_primitives::types::MerkleHash;
+    use near_primitives::utils::account_id_to_shard_id;
     use near_primitives::version::PROTOCOL_VERSION;
     use near_store::test_utils::create_tries;
+    use near_store::{get_access_key, get_charged_deposits};
     use std::sync::Arc;
     use testlib::runtime_utils::{alice_account, bob_account};
 
@@ -1521,6 +2008,7 @@ mod tests {
 
         let apply_state = ApplyState {
             block_index: 0,
+            shard_id: 0,
             last_block_hash: Default::default(),
             epoch_id: Default::default(),
             epoch_height: 0,
@@ -1531,6 +2019,8 @@ mod tests {
             current_protocol_version: PROTOCOL_VERSION,
             config: Arc::new(RuntimeConfig::default()),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
+            differential_vm: None,
+            congestion_info: Default::default(),
         };
 
         (runtime, tries, root, apply_state, signer, MockEpochInfoProvider::default())
@@ -2213,4 +2703,914 @@ mod tests {
         // Burnt all the fees + all prepaid gas.
         assert_eq!(result.stats.tx_burnt_amount, total_receipt_cost);
     }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn test_apply_delegate_action() {
+        use near_primitives::transaction::{DelegateAction, SignedDelegateAction};
+
+        let initial_balance = to_yocto(1_000_000);
+        let deposit = to_yocto(10);
+        let (runtime, tries, root, apply_state, relayer_signer, epoch_info_provider) =
+            setup_runtime(initial_balance, 0, 10u64.pow(15));
+
+        // Bob only has the balance for the storage and the deposit, the gas is paid by Alice.
+        let storage_balance = to_yocto(1);
+        let sender_signer = InMemorySigner::from_seed(&bob_account(), KeyType::ED25519, "bob");
+        let mut state_update = tries.new_trie_update(0, root);
+        set_account(
+            &mut state_update,
+            bob_account(),
+            &account_new(storage_balance + deposit, hash(&[])),
+        );
+        set_access_key(
+            &mut state_update,
+            bob_account(),
+            sender_signer.public_key(),
+            &AccessKey::full_access(),
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let delegate_action = DelegateAction {
+            sender_id: bob_account(),
+            receiver_id: alice_account(),
+            actions: vec![Action::Transfer(TransferAction { deposit })],
+            nonce: 1,
+            max_block_height: 100,
+            public_key: sender_signer.public_key(),
+        };
+        let signature = sender_signer.sign(delegate_action.get_hash().as_ref());
+        let transaction = SignedTransaction::from_actions(
+            1,
+            alice_account(),
+            bob_account(),
+            &*relayer_signer,
+            vec![Action::Delegate(SignedDelegateAction::new(signature, delegate_action))],
+            CryptoHash::default(),
+        );
+
+        // The transaction, the delegate action and the delegated transfer are each applied in a
+        // separate chunk.
+        let mut root = root;
+        let mut receipts = vec![];
+        let mut transactions = vec![transaction];
+        for _ in 0..3 {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &transactions,
+                    &epoch_info_provider,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+            receipts = apply_result.outgoing_receipts;
+            transactions = vec![];
+        }
+
+        let state_update = tries.new_trie_update(0, root);
+        let sender = get_account(&state_update, &bob_account()).unwrap().unwrap();
+        assert_eq!(sender.amount, storage_balance);
+        let access_key = get_access_key(&state_update, &bob_account(), &sender_signer.public_key())
+            .unwrap()
+            .unwrap();
+        assert_eq!(access_key.nonce, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_apply_failed_receipt_refunds_deposit_allowance() {
+        use near_primitives::account::{AccessKeyPermission, ExtendedFunctionCallPermission};
+
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let mut state_update = tries.new_trie_update(0, root);
+        set_access_key(
+            &mut state_update,
+            alice_account(),
+            signer.public_key(),
+            &AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::ExtendedFunctionCall(
+                    ExtendedFunctionCallPermission {
+                        allowance: None,
+                        receiver_ids: vec![bob_account()],
+                        method_names: vec![],
+                        max_deposit_per_call: Some(100),
+                        deposit_allowance: Some(150),
+                        expires_at: None,
+                    },
+                ),
+            },
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        // Bob doesn't exist, so the function call fails and the deposit is refunded.
+        let transaction = SignedTransaction::from_actions(
+            1,
+            alice_account(),
+            bob_account(),
+            &*signer,
+            vec![Action::FunctionCall(FunctionCallAction {
+                method_name: "hello".to_string(),
+                args: vec![],
+                gas: 10u64.pow(12),
+                deposit: 100,
+            })],
+            CryptoHash::default(),
+        );
+        let deposit_allowance = |root| {
+            let state_update = tries.new_trie_update(0, root);
+            match get_access_key(&state_update, &alice_account(), &signer.public_key())
+                .unwrap()
+                .unwrap()
+                .permission
+            {
+                AccessKeyPermission::ExtendedFunctionCall(permission) => {
+                    permission.deposit_allowance
+                }
+                permission => panic!("unexpected permission {:?}", permission),
+            }
+        };
+
+        // The transaction, the failed function call and the refunds are each applied in a
+        // separate chunk.
+        let mut root = root;
+        let mut receipts = vec![];
+        let mut transactions = vec![transaction];
+        for expected_deposit_allowance in &[50, 50, 150] {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &transactions,
+                    &epoch_info_provider,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+            receipts = apply_result.outgoing_receipts;
+            transactions = vec![];
+            assert_eq!(deposit_allowance(root), Some(*expected_deposit_allowance));
+        }
+        let state_update = tries.new_trie_update(0, root);
+        assert_eq!(get_charged_deposits(&state_update, &alice_account()).unwrap(), vec![]);
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_apply_failed_contract_deposit_keeps_deposit_allowance() {
+        use near_primitives::account::{AccessKeyPermission, ExtendedFunctionCallPermission};
+
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let mut state_update = tries.new_trie_update(0, root);
+        set_access_key(
+            &mut state_update,
+            alice_account(),
+            signer.public_key(),
+            &AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::ExtendedFunctionCall(
+                    ExtendedFunctionCallPermission {
+                        allowance: None,
+                        receiver_ids: vec![alice_account()],
+                        method_names: vec![],
+                        max_deposit_per_call: Some(100),
+                        deposit_allowance: Some(150),
+                        expires_at: None,
+                    },
+                ),
+            },
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        // The contract of Alice, called with the access key, attaches a deposit from the balance
+        // of Alice to a function call to Bob. Bob doesn't exist, so the function call fails.
+        let receipts = vec![Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: bob_account(),
+            receipt_id: hash(b"contract_deposit"),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: signer.public_key(),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(FunctionCallAction {
+                    method_name: "hello".to_string(),
+                    args: vec![],
+                    gas: 10u64.pow(12),
+                    deposit: 100,
+                })],
+            }),
+        }];
+        let deposit_allowance = |root| {
+            let state_update = tries.new_trie_update(0, root);
+            match get_access_key(&state_update, &alice_account(), &signer.public_key())
+                .unwrap()
+                .unwrap()
+                .permission
+            {
+                AccessKeyPermission::ExtendedFunctionCall(permission) => {
+                    permission.deposit_allowance
+                }
+                permission => panic!("unexpected permission {:?}", permission),
+            }
+        };
+
+        // The deposit wasn't charged from the deposit allowance, so the deposit refund doesn't
+        // increase it.
+        let mut root = root;
+        let mut receipts = receipts;
+        for _ in 0..2 {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &[],
+                    &epoch_info_provider,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+            receipts = apply_result.outgoing_receipts;
+            assert_eq!(deposit_allowance(root), Some(150));
+        }
+        assert!(receipts.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_contract_upgrade")]
+    fn test_apply_upgrade_contract_failed_migration() {
+        use near_primitives::transaction::UpgradeContractAction;
+
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let old_code = ContractCode::new(b"old code".to_vec(), None);
+        let data_key = TrieKey::ContractData { account_id: alice_account(), key: b"b".to_vec() };
+        let mut state_update = tries.new_trie_update(0, root);
+        let mut account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        account.code_hash = old_code.get_hash();
+        set_account(&mut state_update, alice_account(), &account);
+        set_code(&mut state_update, alice_account(), &old_code);
+        state_update.set(data_key.clone(), b"old".to_vec());
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        // The migration overwrites the key `b` first and then runs out of gas.
+        let mut args = 0u64.to_le_bytes().to_vec();
+        args.extend_from_slice(&100_000u64.to_le_bytes());
+        let receipts = vec![Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: alice_account(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::UpgradeContract(UpgradeContractAction {
+                    code: include_bytes!("../../near-vm-runner/tests/res/test_contract_rs.wasm")
+                        .to_vec(),
+                    method_name: "insert_strings".to_string(),
+                    args,
+                    gas: 10u64.pow(13),
+                })],
+            }),
+        }];
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert!(matches!(apply_result.outcomes[0].outcome.status, ExecutionStatus::Failure(_)));
+
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, root);
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.code_hash, old_code.get_hash());
+        let code = near_store::get_code(&state_update, &alice_account(), None).unwrap().unwrap();
+        assert_eq!(code.code, old_code.code);
+        assert_eq!(state_update.get(&data_key).unwrap(), Some(b"old".to_vec()));
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn test_apply_releases_due_scheduled_receipts() {
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let receipts: Vec<Receipt> = generate_receipts(to_yocto(1), 3)
+            .into_iter()
+            .map(|mut receipt| {
+                receipt.predecessor_id = alice_account();
+                receipt
+            })
+            .collect();
+        let schedules = vec![
+            ReceiptSchedule::BlockHeight(10),
+            ReceiptSchedule::BlockTimestamp(100),
+            ReceiptSchedule::BlockHeight(11),
+        ];
+
+        let mut state_update = tries.new_trie_update(0, root);
+        let mut account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        let initial_storage_usage = account.storage_usage;
+        for (schedule, receipt) in schedules.iter().zip(receipts.iter()) {
+            set_scheduled_receipt(&mut state_update, *schedule, receipt);
+            account.storage_usage += scheduled_receipt_storage_usage(
+                &apply_state.config.transaction_costs.storage_usage_config,
+                *schedule,
+                receipt,
+            );
+        }
+        set_account(&mut state_update, alice_account(), &account);
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        apply_state.block_index = 10;
+        apply_state.block_timestamp = 100;
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outgoing_receipts, receipts[..2].to_vec());
+        assert_eq!(
+            apply_result.stats.released_scheduled_receipts_balance,
+            receipts_cost(
+                &apply_state.config.transaction_costs,
+                &receipts[..2],
+                apply_state.current_protocol_version,
+            )
+            .unwrap()
+        );
+
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, root);
+        for receipt in &receipts[..2] {
+            assert!(near_store::get_scheduled_receipt(
+                &state_update,
+                &alice_account(),
+                receipt.receipt_id
+            )
+            .unwrap()
+            .is_none());
+        }
+        assert!(near_store::get_scheduled_receipt(
+            &state_update,
+            &alice_account(),
+            receipts[2].receipt_id
+        )
+        .unwrap()
+        .is_some());
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(
+            account.storage_usage,
+            initial_storage_usage
+                + scheduled_receipt_storage_usage(
+                    &apply_state.config.transaction_costs.storage_usage_config,
+                    schedules[2],
+                    &receipts[2],
+                )
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn test_apply_scheduled_receipts_bounded_by_gas_limit() {
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let receipts: Vec<Receipt> = generate_receipts(to_yocto(1), 3)
+            .into_iter()
+            .map(|mut receipt| {
+                receipt.predecessor_id = alice_account();
+                receipt
+            })
+            .collect();
+        let mut state_update = tries.new_trie_update(0, root);
+        for (i, receipt) in receipts.iter().enumerate() {
+            set_scheduled_receipt(
+                &mut state_update,
+                ReceiptSchedule::BlockHeight(i as u64),
+                receipt,
+            );
+        }
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, mut root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        // Any released receipt uses up the gas limit, so every chunk releases a single receipt.
+        apply_state.block_index = 10;
+        apply_state.gas_limit = Some(1);
+        for receipt in &receipts {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &[],
+                    &[],
+                    &epoch_info_provider,
+                )
+                .unwrap();
+            assert_eq!(apply_result.outgoing_receipts, vec![receipt.clone()]);
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+        }
+    }
+
+    /// Contract that schedules a transfer of 1000 yoctoNEAR to `bob.near` at the height 100 and
+    /// stores its receipt ID under the key `id`, and then cancels it. The cancellation panics if
+    /// the receipt can't be cancelled.
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn scheduling_contract() -> ContractCode {
+        let code = wabt::wat2wasm(
+            r#"
+            (module
+              (import "env" "promise_batch_create_at_height"
+                (func $create_at_height (param i64 i64 i64) (result i64)))
+              (import "env" "promise_batch_action_transfer" (func $transfer (param i64 i64)))
+              (import "env" "promise_scheduled_receipt_id" (func $receipt_id (param i64 i64)))
+              (import "env" "promise_scheduled_cancel" (func $cancel (param i64 i64) (result i64)))
+              (import "env" "storage_write"
+                (func $storage_write (param i64 i64 i64 i64 i64) (result i64)))
+              (import "env" "storage_read" (func $storage_read (param i64 i64 i64) (result i64)))
+              (memory 1)
+              (data (i32.const 0) "bob.near")
+              (data (i32.const 16) "\e8\03")
+              (data (i32.const 32) "id")
+              (func (export "schedule")
+                (local $promise i64)
+                (local.set $promise
+                  (call $create_at_height (i64.const 8) (i64.const 0) (i64.const 100)))
+                (call $transfer (local.get $promise) (i64.const 16))
+                (call $receipt_id (local.get $promise) (i64.const 0))
+                (drop (call $storage_write
+                  (i64.const 2) (i64.const 32) (i64.const -1) (i64.const 0) (i64.const 1))))
+              (func (export "cancel")
+                (drop (call $storage_read (i64.const 2) (i64.const 32) (i64.const 0)))
+                (if (i64.eqz (call $cancel (i64.const -1) (i64.const 0)))
+                  (then unreachable))))"#,
+        )
+        .unwrap();
+        ContractCode::new(code, None)
+    }
+
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn function_call_receipt(receipt_id: CryptoHash, method_name: &str) -> Receipt {
+        Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: alice_account(),
+            receipt_id,
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(FunctionCallAction {
+                    method_name: method_name.to_string(),
+                    args: vec![],
+                    gas: 10u64.pow(13),
+                    deposit: 0,
+                })],
+            }),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn test_apply_schedule_and_cancel_receipt_from_contract() {
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let code = scheduling_contract();
+        let mut state_update = tries.new_trie_update(0, root);
+        let mut account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        account.code_hash = code.get_hash();
+        set_account(&mut state_update, alice_account(), &account);
+        set_code(&mut state_update, alice_account(), &code);
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let apply = |root, apply_state: &ApplyState, receipts: &[Receipt]| {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    apply_state,
+                    receipts,
+                    &[],
+                    &epoch_info_provider,
+                )
+                .unwrap();
+            let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            (apply_result, root)
+        };
+
+        // The scheduled transfer is stored in the state and its storage is charged to Alice.
+        let (apply_result, root) =
+            apply(root, &apply_state, &[function_call_receipt(hash(b"schedule 1"), "schedule")]);
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        let state_update = tries.new_trie_update(0, root);
+        let scheduled_receipt_ids =
+            near_store::get_scheduled_receipt_ids(&state_update, &alice_account()).unwrap();
+        assert_eq!(scheduled_receipt_ids.len(), 1);
+        let account_after_schedule = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert!(account_after_schedule.storage_usage > account.storage_usage);
+
+        // Cancelling it removes it from the state and refunds the deposit to Alice.
+        apply_state.block_index = 1;
+        let (apply_result, root) =
+            apply(root, &apply_state, &[function_call_receipt(hash(b"cancel 1"), "cancel")]);
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        let state_update = tries.new_trie_update(0, root);
+        assert!(near_store::get_scheduled_receipt_ids(&state_update, &alice_account())
+            .unwrap()
+            .is_empty());
+        assert!(apply_result
+            .outgoing_receipts
+            .contains(&Receipt::new_balance_refund(&alice_account(), 1000)));
+        let account_after_cancel = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert!(account_after_cancel.storage_usage < account_after_schedule.storage_usage);
+
+        // A receipt that is due can't be cancelled anymore, even if it's not released yet. The
+        // receipt due at the height 50 uses up the gas limit of the chunk, so the scheduled
+        // transfer due at the height 100 is kept in the state.
+        apply_state.block_index = 2;
+        let (_, root) =
+            apply(root, &apply_state, &[function_call_receipt(hash(b"schedule 2"), "schedule")]);
+        let mut expensive_receipt = function_call_receipt(hash(b"expensive"), "schedule");
+        expensive_receipt.predecessor_id = bob_account();
+        expensive_receipt.receiver_id = bob_account();
+        let mut state_update = tries.new_trie_update(0, root);
+        set_scheduled_receipt(
+            &mut state_update,
+            ReceiptSchedule::BlockHeight(50),
+            &expensive_receipt,
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        apply_state.block_index = 100;
+        apply_state.gas_limit = Some(10u64.pow(13));
+        let (apply_result, root) =
+            apply(root, &apply_state, &[function_call_receipt(hash(b"cancel 2"), "cancel")]);
+        assert_eq!(apply_result.outgoing_receipts[0], expensive_receipt);
+        assert!(matches!(apply_result.outcomes[0].outcome.status, ExecutionStatus::Failure(_)));
+        let state_update = tries.new_trie_update(0, root);
+        assert_eq!(
+            near_store::get_scheduled_receipt_ids(&state_update, &alice_account()).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_scheduled_receipts")]
+    fn test_delete_account_cancels_scheduled_receipts() {
+        use near_primitives::transaction::DeleteAccountAction;
+
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let scheduled_receipt = generate_receipts(to_yocto(1), 1)
+            .into_iter()
+            .map(|mut receipt| {
+                receipt.predecessor_id = alice_account();
+                receipt
+            })
+            .next()
+            .unwrap();
+        let mut state_update = tries.new_trie_update(0, root);
+        set_scheduled_receipt(
+            &mut state_update,
+            ReceiptSchedule::BlockHeight(100),
+            &scheduled_receipt,
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let receipts = vec![Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: alice_account(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::DeleteAccount(DeleteAccountAction {
+                    beneficiary_id: bob_account(),
+                })],
+            }),
+        }];
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        // The deposit of the scheduled receipt is paid out to the beneficiary.
+        assert!(apply_result
+            .outgoing_receipts
+            .contains(&Receipt::new_balance_refund(&bob_account(), to_yocto(1))));
+
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, root);
+        assert!(near_store::get_scheduled_receipt_ids(&state_update, &alice_account())
+            .unwrap()
+            .is_empty());
+        assert!(near_store::get_scheduled_receipt(
+            &state_update,
+            &alice_account(),
+            scheduled_receipt.receipt_id
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn test_buffer_outgoing_receipts_to_congested_shard() {
+        let (runtime, tries, root, mut apply_state, _, _) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let receiver_id = (0..)
+            .map(|i| format!("receiver{}", i))
+            .find(|account_id| account_id_to_shard_id(account_id, 2) == 1)
+            .unwrap();
+        let receipts: Vec<Receipt> = generate_receipts(to_yocto(1), 6)
+            .into_iter()
+            .map(|mut receipt| {
+                receipt.receiver_id = receiver_id.clone();
+                receipt
+            })
+            .collect();
+        let mut state_update = tries.new_trie_update(0, root);
+
+        // The receiver shard can accept only 2 more receipts.
+        apply_state.congestion_info = CongestionInfo {
+            delayed_receipts_counts: vec![0, CONGESTED_DELAYED_RECEIPTS_THRESHOLD - 2],
+            shard_layout: ShardLayout::v0(2, 0),
+        };
+        let sent_receipts = runtime
+            .buffer_outgoing_receipts(&mut state_update, &apply_state, receipts[..5].to_vec())
+            .unwrap();
+        assert_eq!(sent_receipts, receipts[..2].to_vec());
+        let indices: DelayedReceiptIndices =
+            get(&state_update, &TrieKey::BufferedReceiptIndices { receiver_shard_id: 1 })
+                .unwrap()
+                .unwrap();
+        assert_eq!(indices.next_available_index - indices.first_index, 3);
+
+        // New receipts are buffered behind the previous ones while the shard is congested.
+        apply_state.congestion_info.delayed_receipts_counts[1] =
+            CONGESTED_DELAYED_RECEIPTS_THRESHOLD;
+        let sent_receipts = runtime
+            .buffer_outgoing_receipts(&mut state_update, &apply_state, vec![receipts[5].clone()])
+            .unwrap();
+        assert!(sent_receipts.is_empty());
+
+        // Once the congestion is resolved, the buffered receipts are sent in the original order.
+        apply_state.congestion_info.delayed_receipts_counts[1] = 0;
+        let sent_receipts =
+            runtime.buffer_outgoing_receipts(&mut state_update, &apply_state, vec![]).unwrap();
+        assert_eq!(sent_receipts, receipts[2..].to_vec());
+        let indices: DelayedReceiptIndices =
+            get(&state_update, &TrieKey::BufferedReceiptIndices { receiver_shard_id: 1 })
+                .unwrap()
+                .unwrap();
+        assert_eq!(indices.first_index, indices.next_available_index);
+    }
+
+    /// The number of contract data records with 8 byte keys that a chunk removes from the state of
+    /// the deleted accounts.
+    fn max_removed_deleted_account_data(apply_state: &ApplyState) -> usize {
+        let ext_costs = &apply_state.config.wasm_config.ext_costs;
+        let remove_gas = ext_costs.storage_remove_base + ext_costs.storage_remove_key_byte * 8;
+        (MAX_DELETED_ACCOUNTS_CLEANUP_GAS / remove_gas) as usize
+    }
+
+    #[test]
+    fn test_cleanup_deleted_accounts() {
+        let (runtime, tries, root, apply_state, _, _) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let account_id = alice_account();
+        let mut state_update = tries.new_trie_update(0, root);
+        let num_records = max_removed_deleted_account_data(&apply_state) + 10;
+        for i in 0..num_records {
+            state_update.set(
+                TrieKey::ContractData {
+                    account_id: account_id.clone(),
+                    key: (i as u64).to_le_bytes().to_vec(),
+                },
+                vec![1],
+            );
+        }
+        near_store::set_deleted_account(&mut state_update, account_id.clone(), 1);
+        state_update.commit(StateChangeCause::InitialState);
+
+        // The first chunk removes the data up to the gas limit and keeps the account marked
+        // deleted.
+        let gas_burnt = runtime.cleanup_deleted_accounts(&mut state_update, &apply_state).unwrap();
+        assert!(gas_burnt > 0 && gas_burnt <= MAX_DELETED_ACCOUNTS_CLEANUP_GAS);
+        assert!(near_store::has_contract_data(&state_update, &account_id).unwrap());
+        assert_eq!(get_deleted_account(&state_update, &account_id).unwrap(), Some(1));
+
+        // The second chunk removes the rest of the data and the account can be created again.
+        let gas_burnt = runtime.cleanup_deleted_accounts(&mut state_update, &apply_state).unwrap();
+        assert!(gas_burnt > 0 && gas_burnt < MAX_DELETED_ACCOUNTS_CLEANUP_GAS);
+        assert!(!near_store::has_contract_data(&state_update, &account_id).unwrap());
+        assert_eq!(get_deleted_account(&state_update, &account_id).unwrap(), None);
+
+        // Without deleted accounts no gas is burnt.
+        assert_eq!(runtime.cleanup_deleted_accounts(&mut state_update, &apply_state).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_cleanup_deleted_accounts_respects_chunk_gas_limit() {
+        let (runtime, tries, root, mut apply_state, _, _) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let ext_costs = &apply_state.config.wasm_config.ext_costs;
+        let remove_gas = ext_costs.storage_remove_base + ext_costs.storage_remove_key_byte * 8;
+        // The gas limit of the chunk allows to remove only 5 records.
+        apply_state.gas_limit = Some(5 * remove_gas + 1);
+        let account_id = alice_account();
+        let mut state_update = tries.new_trie_update(0, root);
+        for i in 0..10u64 {
+            state_update.set(
+                TrieKey::ContractData {
+                    account_id: account_id.clone(),
+                    key: i.to_le_bytes().to_vec(),
+                },
+                vec![1],
+            );
+        }
+        near_store::set_deleted_account(&mut state_update, account_id.clone(), 1);
+        state_update.commit(StateChangeCause::InitialState);
+
+        let gas_burnt = runtime.cleanup_deleted_accounts(&mut state_update, &apply_state).unwrap();
+        assert_eq!(gas_burnt, 5 * remove_gas);
+        let num_records = state_update
+            .iter(&trie_key_parsers::get_raw_prefix_for_contract_data(&account_id, &[]))
+            .unwrap()
+            .count();
+        assert_eq!(num_records, 5);
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_lazy_account_deletion")]
+    fn test_delete_account_with_contract_data() {
+        use near_primitives::errors::{ActionError, ActionErrorKind, TxExecutionError};
+        use near_primitives::transaction::{CreateAccountAction, DeleteAccountAction};
+
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        apply_state.block_index = 5;
+        let account_id = alice_account();
+        let mut state_update = tries.new_trie_update(0, root);
+        // More data than two chunks can remove.
+        for i in 0..2 * max_removed_deleted_account_data(&apply_state) + 10 {
+            state_update.set(
+                TrieKey::ContractData {
+                    account_id: account_id.clone(),
+                    key: (i as u64).to_le_bytes().to_vec(),
+                },
+                vec![1],
+            );
+        }
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let action_receipt = |predecessor_id: AccountId, actions: Vec<Action>| Receipt {
+            predecessor_id: predecessor_id.clone(),
+            receiver_id: account_id.clone(),
+            receipt_id: hash(&actions.try_to_vec().unwrap()),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: predecessor_id,
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions,
+            }),
+        };
+
+        // The account is removed right away, but it's marked deleted while its data remains.
+        let receipts = vec![action_receipt(
+            account_id.clone(),
+            vec![Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob_account() })],
+        )];
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, root);
+        assert!(get_account(&state_update, &account_id).unwrap().is_none());
+        assert_eq!(get_deleted_account(&state_update, &account_id).unwrap(), Some(5));
+        assert!(near_store::has_contract_data(&state_update, &account_id).unwrap());
+
+        // The account can't be created again until all its data is removed.
+        let receipts = vec![action_receipt(
+            bob_account(),
+            vec![
+                Action::CreateAccount(CreateAccountAction {}),
+                Action::Transfer(TransferAction { deposit: to_yocto(1) }),
+            ],
+        )];
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert_eq!(
+            apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::DeletedAccountCleanupInProgress {
+                    account_id: account_id.clone()
+                },
+            }))
+        );
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, root);
+        assert!(get_account(&state_update, &account_id).unwrap().is_none());
+        assert_eq!(get_deleted_account(&state_update, &account_id).unwrap(), Some(5));
+    }
 }
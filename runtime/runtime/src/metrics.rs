@@ -1,4 +1,4 @@
-use near_metrics::{try_create_int_counter, IntCounter};
+use near_metrics::{try_create_int_counter, try_create_int_gauge_vec, IntCounter, IntGaugeVec};
 
 lazy_static::lazy_static! {
     pub static ref ACTION_CREATE_ACCOUNT_TOTAL: near_metrics::Result<IntCounter> =
@@ -11,6 +11,21 @@ lazy_static::lazy_static! {
             "near_action_deploy_contract_total",
             "The number of DeployContract actions called since starting this node"
         );
+    pub static ref ACTION_DEPLOY_CONTRACT_WITH_ABI_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_deploy_contract_with_abi_total",
+            "The number of DeployContractWithAbi actions called since starting this node"
+        );
+    pub static ref ACTION_UPGRADE_CONTRACT_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_upgrade_contract_total",
+            "The number of UpgradeContract actions called since starting this node"
+        );
+    pub static ref ACTION_DELEGATE_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_delegate_total",
+            "The number of Delegate actions called since starting this node"
+        );
     pub static ref ACTION_FUNCTION_CALL_TOTAL: near_metrics::Result<IntCounter> =
         try_create_int_counter(
             "near_action_function_call_total",
@@ -53,4 +68,36 @@ lazy_static::lazy_static! {
             "near_transaction_processed_failed_total",
             "The number of transactions processed and failed since starting this node"
         );
+    pub static ref SCHEDULED_RECEIPTS_RELEASED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_scheduled_receipts_released_total",
+            "The number of scheduled receipts released since starting this node"
+        );
+    pub static ref DELETED_ACCOUNT_DATA_REMOVED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_deleted_account_data_removed_total",
+            "The number of contract data records of deleted accounts removed since starting this node"
+        );
+    pub static ref VM_DIFF_FUNCTION_CALL_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_vm_diff_function_call_total",
+            "The number of function calls executed on both VMs in differential execution mode"
+        );
+    pub static ref VM_DIFF_FUNCTION_CALL_DIVERGED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_vm_diff_function_call_diverged_total",
+            "The number of function calls whose results differed between VMs in differential execution mode"
+        );
+    pub static ref DELAYED_RECEIPTS_COUNT: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_delayed_receipts_count",
+            "The number of receipts in the delayed receipts queue of a shard",
+            &["shard_id"]
+        );
+    pub static ref BUFFERED_RECEIPTS_COUNT: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_buffered_receipts_count",
+            "The number of outgoing receipts of a shard buffered for a congested receiver shard",
+            &["shard_id", "receiver_shard_id"]
+        );
 }
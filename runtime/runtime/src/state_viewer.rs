@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::str;
+use std::sync::Arc;
 use std::time::Instant;
 
 use borsh::BorshSerialize;
@@ -6,21 +8,33 @@ use log::debug;
 
 use near_crypto::{KeyType, PublicKey};
 use near_primitives::account::{AccessKey, Account};
+use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::to_base64;
-use near_primitives::trie_key::trie_key_parsers;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::EpochHeight;
 use near_primitives::types::{AccountId, BlockHeight, EpochId, EpochInfoProvider};
 use near_primitives::utils::is_valid_account_id;
-use near_primitives::views::{StateItem, ViewStateResult};
-use near_runtime_fees::RuntimeFeesConfig;
-use near_store::{get_access_key, get_account, TrieUpdate};
+use near_primitives::views::{
+    CallerContext, ContractAbiView, StateItem, StateOverrides, StoragePrefixUsageView,
+    StorageStakingView, StorageUsageView, ViewStateResult,
+};
+use near_runtime_configs::{get_storage_locked_balance, get_storage_stake, RuntimeConfig};
+use near_runtime_fees::{RuntimeFeesConfig, StorageUsageConfig};
+use near_store::{get_access_key, get_account, get_code, get_contract_abi, TrieUpdate};
 use near_vm_logic::{ReturnData, VMConfig, VMContext};
 
 use crate::actions::get_code_with_cache;
 use crate::ext::RuntimeExt;
 use near_primitives::version::ProtocolVersion;
 
+/// Number of leading key bytes used to group contract data when no prefix length is requested.
+pub const DEFAULT_STORAGE_USAGE_PREFIX_LEN: usize = 1;
+
+/// Maximum number of access keys and contract data records scanned to answer a storage usage
+/// query, so that a single RPC request can't iterate over an arbitrarily large account.
+pub const MAX_STORAGE_USAGE_QUERY_KEYS: u64 = 10_000;
+
 pub struct TrieViewer {}
 
 impl TrieViewer {
@@ -84,7 +98,160 @@ impl TrieViewer {
         Ok(ViewStateResult { values, proof: vec![] })
     }
 
+    /// Computes the storage usage breakdown of the account. Contract data is grouped by the first
+    /// `prefix_len` bytes of the keys. At most `max_keys` records are scanned, the result is
+    /// marked as truncated if the account has more.
+    pub fn view_storage_usage(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        prefix_len: usize,
+        max_keys: Option<u64>,
+        storage_usage_config: &StorageUsageConfig,
+    ) -> Result<StorageUsageView, Box<dyn std::error::Error>> {
+        let account = self.view_account(state_update, account_id)?;
+        let code = get_code(state_update, account_id, Some(account.code_hash))?
+            .map(|code| code.code.len() as u64)
+            .unwrap_or_default();
+        let abi = get_contract_abi(state_update, account_id)?
+            .map(|abi| abi.len() as u64)
+            .unwrap_or_default();
+
+        let mut num_scanned_keys = 0;
+        let mut truncated = false;
+        let mut num_access_keys = 0;
+        let mut access_keys = 0;
+        let query = trie_key_parsers::get_raw_prefix_for_access_keys(account_id);
+        let mut iter = state_update.trie.iter(&state_update.get_root())?;
+        iter.seek(&query)?;
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(&query) {
+                break;
+            }
+            if max_keys.map_or(false, |max_keys| num_scanned_keys >= max_keys) {
+                truncated = true;
+                break;
+            }
+            num_scanned_keys += 1;
+            num_access_keys += 1;
+            access_keys += storage_usage_config.num_extra_bytes_record
+                + (key.len() - query.len()) as u64
+                + value.len() as u64;
+        }
+
+        let mut data: BTreeMap<Vec<u8>, StoragePrefixUsageView> = BTreeMap::new();
+        let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, &[]);
+        let mut iter = state_update.trie.iter(&state_update.get_root())?;
+        iter.seek(&query)?;
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(&query) {
+                break;
+            }
+            if max_keys.map_or(false, |max_keys| num_scanned_keys >= max_keys) {
+                truncated = true;
+                break;
+            }
+            num_scanned_keys += 1;
+            let data_key = &key[query.len()..];
+            let prefix = data_key[..prefix_len.min(data_key.len())].to_vec();
+            let usage = data.entry(prefix.clone()).or_insert_with(|| StoragePrefixUsageView {
+                prefix: prefix.into(),
+                num_keys: 0,
+                storage_usage: 0,
+            });
+            usage.num_keys += 1;
+            usage.storage_usage += storage_usage_config.num_extra_bytes_record
+                + data_key.len() as u64
+                + value.len() as u64;
+        }
+
+        Ok(StorageUsageView {
+            storage_usage: account.storage_usage,
+            account: storage_usage_config.num_bytes_account,
+            code,
+            abi,
+            num_access_keys,
+            access_keys,
+            data: data.into_iter().map(|(_, usage)| usage).collect(),
+            truncated,
+        })
+    }
+
+    /// Splits the balance of the account between the storage stake and the free balance.
+    pub fn view_storage_staking(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        runtime_config: &RuntimeConfig,
+    ) -> Result<StorageStakingView, Box<dyn std::error::Error>> {
+        let account = self.view_account(state_update, account_id)?;
+        let storage_stake = get_storage_stake(&account, runtime_config)?;
+        let locked_for_storage = get_storage_locked_balance(&account, runtime_config)?;
+        Ok(StorageStakingView {
+            storage_usage: account.storage_usage,
+            storage_byte_cost: runtime_config.storage_amount_per_byte,
+            storage_stake,
+            amount: account.amount,
+            locked: account.locked,
+            locked_for_storage,
+            free_balance: account.amount - locked_for_storage,
+        })
+    }
+
+    /// Returns the ABI stored with the contract deployed on the account.
+    pub fn view_contract_abi(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+    ) -> Result<ContractAbiView, Box<dyn std::error::Error>> {
+        self.view_account(state_update, account_id)?;
+        let abi = get_contract_abi(state_update, account_id)?.ok_or_else(|| {
+            format!("contract ABI for account {} does not exist while viewing", account_id)
+        })?;
+        Ok(ContractAbiView::new(abi))
+    }
+
     pub fn call_function(
+        &self,
+        state_update: TrieUpdate,
+        block_height: BlockHeight,
+        block_timestamp: u64,
+        last_block_hash: &CryptoHash,
+        epoch_height: EpochHeight,
+        epoch_id: &EpochId,
+        contract_id: &AccountId,
+        method_name: &str,
+        args: &[u8],
+        logs: &mut Vec<String>,
+        epoch_info_provider: &dyn EpochInfoProvider,
+        current_protocol_version: ProtocolVersion,
+        runtime_config: &RuntimeConfig,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.call_function_with_overrides(
+            state_update,
+            block_height,
+            block_timestamp,
+            last_block_hash,
+            epoch_height,
+            epoch_id,
+            contract_id,
+            method_name,
+            args,
+            &StateOverrides::default(),
+            None,
+            logs,
+            epoch_info_provider,
+            current_protocol_version,
+            runtime_config,
+        )
+    }
+
+    /// Same as `call_function`, but applies `state_overrides` to the contract account first and,
+    /// if `caller` is given, runs the method as a regular function call from that caller.
+    /// All changes are made to the given `state_update` only and are never committed.
+    pub fn call_function_with_overrides(
         &self,
         mut state_update: TrieUpdate,
         block_height: BlockHeight,
@@ -95,22 +262,74 @@ impl TrieViewer {
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        state_overrides: &StateOverrides,
+        caller: Option<&CallerContext>,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
+        runtime_config: &RuntimeConfig,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let now = Instant::now();
         if !is_valid_account_id(contract_id) {
             return Err(format!("Contract ID {:?} is not valid", contract_id).into());
         }
+        if let Some(caller) = caller {
+            if !is_valid_account_id(&caller.predecessor_account_id) {
+                return Err(format!(
+                    "Predecessor ID {:?} is not valid",
+                    caller.predecessor_account_id
+                )
+                .into());
+            }
+        }
         let root = state_update.get_root();
-        let account = get_account(&state_update, contract_id)?
+        let mut account = get_account(&state_update, contract_id)?
             .ok_or_else(|| format!("Account {:?} doesn't exist", contract_id))?;
-        let code = get_code_with_cache(&state_update, contract_id, &account)?.ok_or_else(|| {
-            format!("cannot find contract code for account {}", contract_id.clone())
+        let code = match &state_overrides.code {
+            Some(code) => Arc::new(ContractCode::new(code.clone(), None)),
+            None => {
+                get_code_with_cache(&state_update, contract_id, &account)?.ok_or_else(|| {
+                    format!("cannot find contract code for account {}", contract_id.clone())
+                })?
+            }
+        };
+        if let Some(balance) = state_overrides.balance {
+            account.amount = balance;
+        }
+        // Overridden keys are accounted in the storage usage the same way `storage_write` and
+        // `storage_remove` would do it.
+        let num_extra_bytes_record =
+            runtime_config.transaction_costs.storage_usage_config.num_extra_bytes_record;
+        for item in &state_overrides.state {
+            let key: &[u8] = item.key.as_ref();
+            let trie_key =
+                TrieKey::ContractData { account_id: contract_id.clone(), key: key.to_vec() };
+            if let Some(old_value) = state_update.get(&trie_key)? {
+                account.storage_usage = account.storage_usage.saturating_sub(
+                    key.len() as u64 + old_value.len() as u64 + num_extra_bytes_record,
+                );
+            }
+            match &item.value {
+                Some(value) => {
+                    account.storage_usage = account
+                        .storage_usage
+                        .checked_add(key.len() as u64 + value.len() as u64 + num_extra_bytes_record)
+                        .ok_or_else(|| "Storage usage integer overflow".to_string())?;
+                    state_update.set(trie_key, value.clone())
+                }
+                None => state_update.remove(trie_key),
+            }
+        }
+        // TODO(#1015): Add ability to pass public key
+        let (originator_id, attached_deposit, is_view) = match caller {
+            Some(caller) => (&caller.predecessor_account_id, caller.attached_deposit, false),
+            None => (contract_id, 0, true),
+        };
+        account.amount = account.amount.checked_add(attached_deposit).ok_or_else(|| {
+            "Account balance integer overflow during function call deposit".to_string()
         })?;
-        // TODO(#1015): Add ability to pass public key and originator_id
-        let originator_id = contract_id;
+        let vm_config = VMConfig::default();
+        let prepaid_gas = if is_view { 0 } else { vm_config.limit_config.max_gas_burnt };
         let public_key = PublicKey::empty(KeyType::ED25519);
         let (outcome, err) = {
             let empty_hash = CryptoHash::default();
@@ -123,6 +342,8 @@ impl TrieViewer {
                 &empty_hash,
                 epoch_id,
                 last_block_hash,
+                block_height,
+                block_timestamp,
                 epoch_info_provider,
                 current_protocol_version,
             );
@@ -139,10 +360,11 @@ impl TrieViewer {
                 account_balance: account.amount,
                 account_locked_balance: account.locked,
                 storage_usage: account.storage_usage,
-                attached_deposit: 0,
-                prepaid_gas: 0,
+                storage_byte_cost: runtime_config.storage_amount_per_byte,
+                attached_deposit,
+                prepaid_gas,
                 random_seed: root.as_ref().into(),
-                is_view: true,
+                is_view,
                 output_data_receivers: vec![],
             };
 
@@ -152,7 +374,7 @@ impl TrieViewer {
                 method_name.as_bytes(),
                 &mut runtime_ext,
                 context,
-                &VMConfig::default(),
+                &vm_config,
                 &RuntimeFeesConfig::default(),
                 &[],
                 current_protocol_version,
@@ -188,7 +410,9 @@ impl TrieViewer {
 mod tests {
     use near_primitives::trie_key::TrieKey;
     use near_primitives::types::StateChangeCause;
-    use near_primitives::views::StateItem;
+    use near_primitives::views::{
+        ContractMethodAbiView, ContractMethodKindView, StateItem, StateItemOverride,
+    };
     use testlib::runtime_utils::{
         alice_account, encode_int, get_runtime_and_trie, get_test_trie_viewer,
     };
@@ -215,6 +439,7 @@ mod tests {
             &mut logs,
             &MockEpochInfoProvider::default(),
             PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
         );
 
         assert_eq!(result.unwrap(), encode_int(10));
@@ -238,6 +463,7 @@ mod tests {
             &mut logs,
             &MockEpochInfoProvider::default(),
             PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
         );
 
         let err = result.unwrap_err();
@@ -265,6 +491,7 @@ mod tests {
             &mut logs,
             &MockEpochInfoProvider::default(),
             PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
         );
         let err = result.unwrap_err();
         assert!(
@@ -273,6 +500,156 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_simulated_call_with_caller_can_change_storage() {
+        let (viewer, root) = get_test_trie_viewer();
+
+        let mut logs = vec![];
+        let caller =
+            CallerContext { predecessor_account_id: alice_account(), attached_deposit: 10 };
+        let result = viewer.call_function_with_overrides(
+            root,
+            1,
+            1,
+            &CryptoHash::default(),
+            0,
+            &EpochId::default(),
+            &AccountId::from("test.contract"),
+            "run_test_with_storage_change",
+            &[],
+            &StateOverrides::default(),
+            Some(&caller),
+            &mut logs,
+            &MockEpochInfoProvider::default(),
+            PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
+        );
+        assert!(result.is_ok(), "Simulated call failed: {:?}", result);
+    }
+
+    fn call_with_overrides(
+        state_update: TrieUpdate,
+        method_name: &str,
+        args: &[u8],
+        overrides: &StateOverrides,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        TrieViewer::new().call_function_with_overrides(
+            state_update,
+            1,
+            1,
+            &CryptoHash::default(),
+            0,
+            &EpochId::default(),
+            &AccountId::from("test.contract"),
+            method_name,
+            args,
+            overrides,
+            None,
+            &mut vec![],
+            &MockEpochInfoProvider::default(),
+            PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_view_call_with_balance_override() {
+        let (_, root) = get_test_trie_viewer();
+        let overrides = StateOverrides { balance: Some(123), ..Default::default() };
+        let result = call_with_overrides(root, "ext_account_balance", &[], &overrides);
+        assert_eq!(result.unwrap(), 123u128.to_le_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_view_call_with_state_override_is_not_persisted() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let key = 10u64.to_le_bytes().to_vec();
+        let overrides = StateOverrides {
+            state: vec![StateItemOverride {
+                key: key.clone().into(),
+                value: Some(42u64.to_le_bytes().to_vec()),
+            }],
+            ..Default::default()
+        };
+        let result =
+            call_with_overrides(tries.new_trie_update(0, root), "read_value", &key, &overrides);
+        assert_eq!(result.unwrap(), 42u64.to_le_bytes().to_vec());
+
+        // The overridden value is gone for the next call on the same state root.
+        let result = call_with_overrides(
+            tries.new_trie_update(0, root),
+            "read_value",
+            &key,
+            &StateOverrides::default(),
+        );
+        assert_eq!(result.unwrap(), Vec::<u8>::new());
+        let state_update = tries.new_trie_update(0, root);
+        assert_eq!(
+            state_update
+                .get(&TrieKey::ContractData { account_id: "test.contract".to_string(), key })
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_view_call_state_override_changes_storage_usage() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let storage_usage = |overrides: &StateOverrides| {
+            let result = call_with_overrides(
+                tries.new_trie_update(0, root),
+                "ext_storage_usage",
+                &[],
+                overrides,
+            )
+            .unwrap();
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&result);
+            u64::from_le_bytes(bytes)
+        };
+        let initial = storage_usage(&StateOverrides::default());
+        let overrides = StateOverrides {
+            state: vec![StateItemOverride {
+                key: b"key".to_vec().into(),
+                value: Some(b"value".to_vec()),
+            }],
+            ..Default::default()
+        };
+        let num_extra_bytes_record =
+            RuntimeFeesConfig::default().storage_usage_config.num_extra_bytes_record;
+        assert_eq!(storage_usage(&overrides), initial + 3 + 5 + num_extra_bytes_record);
+    }
+
+    #[test]
+    fn test_view_call_with_invalid_code_override() {
+        let (viewer, root) = get_test_trie_viewer();
+
+        let mut logs = vec![];
+        let overrides = StateOverrides { code: Some(vec![1, 2, 3]), ..Default::default() };
+        let result = viewer.call_function_with_overrides(
+            root,
+            1,
+            1,
+            &CryptoHash::default(),
+            0,
+            &EpochId::default(),
+            &AccountId::from("test.contract"),
+            "run_test",
+            &[],
+            &overrides,
+            None,
+            &mut logs,
+            &MockEpochInfoProvider::default(),
+            PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
+        );
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("CompilationError"),
+            format!("Got different error that doesn't match: {}", err)
+        );
+    }
+
     #[test]
     fn test_view_call_with_args() {
         let (viewer, root) = get_test_trie_viewer();
@@ -291,6 +668,7 @@ mod tests {
             &mut logs,
             &MockEpochInfoProvider::default(),
             PROTOCOL_VERSION,
+            &RuntimeConfig::default(),
         );
         assert_eq!(view_call_result.unwrap(), 3u64.to_le_bytes().to_vec());
     }
@@ -352,6 +730,142 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_view_storage_usage() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let mut state_update = tries.new_trie_update(0, root);
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: b"a1".to_vec() },
+            b"123".to_vec(),
+        );
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: b"a2".to_vec() },
+            b"45".to_vec(),
+        );
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: b"b".to_vec() },
+            b"6".to_vec(),
+        );
+        state_update.set(
+            TrieKey::ContractData { account_id: "alina".to_string(), key: b"a1".to_vec() },
+            b"321".to_vec(),
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (db_changes, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        db_changes.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, new_root);
+        let config = StorageUsageConfig { num_bytes_account: 100, num_extra_bytes_record: 40 };
+        let result = TrieViewer::new()
+            .view_storage_usage(&state_update, &alice_account(), 1, None, &config)
+            .unwrap();
+        assert_eq!(result.account, 100);
+        // The full access key of the test account: the public key with its type and the nonce
+        // with the permission.
+        assert_eq!(result.num_access_keys, 1);
+        assert_eq!(result.access_keys, 40 + 33 + 9);
+        assert!(!result.truncated);
+        assert_eq!(
+            result.data,
+            vec![
+                StoragePrefixUsageView {
+                    prefix: b"a".to_vec().into(),
+                    num_keys: 2,
+                    storage_usage: 40 + 2 + 3 + 40 + 2 + 2,
+                },
+                StoragePrefixUsageView {
+                    prefix: b"b".to_vec().into(),
+                    num_keys: 1,
+                    storage_usage: 40 + 1 + 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_view_storage_usage_truncated() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let mut state_update = tries.new_trie_update(0, root);
+        for key in &[b"a", b"b", b"c"] {
+            state_update.set(
+                TrieKey::ContractData { account_id: alice_account(), key: key.to_vec() },
+                b"1".to_vec(),
+            );
+        }
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (db_changes, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        db_changes.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, new_root);
+        let config = StorageUsageConfig { num_bytes_account: 100, num_extra_bytes_record: 40 };
+        // The access key and the first contract data record fit in the limit.
+        let result = TrieViewer::new()
+            .view_storage_usage(&state_update, &alice_account(), 1, Some(2), &config)
+            .unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.num_access_keys, 1);
+        assert_eq!(
+            result.data,
+            vec![StoragePrefixUsageView {
+                prefix: b"a".to_vec().into(),
+                num_keys: 1,
+                storage_usage: 40 + 1 + 1,
+            }]
+        );
+
+        let result = TrieViewer::new()
+            .view_storage_usage(&state_update, &alice_account(), 1, Some(4), &config)
+            .unwrap();
+        assert!(!result.truncated);
+        assert_eq!(result.data.len(), 3);
+    }
+
+    #[test]
+    fn test_view_storage_staking() {
+        let (viewer, state_update) = get_test_trie_viewer();
+        let account = viewer.view_account(&state_update, &alice_account()).unwrap();
+
+        let mut config = RuntimeConfig::free();
+        let result = viewer.view_storage_staking(&state_update, &alice_account(), &config).unwrap();
+        assert_eq!(result.storage_stake, 0);
+        assert_eq!(result.locked_for_storage, 0);
+        assert_eq!(result.free_balance, account.amount);
+
+        config.storage_amount_per_byte = account.amount + account.locked;
+        let result = viewer.view_storage_staking(&state_update, &alice_account(), &config).unwrap();
+        assert_eq!(result.storage_usage, account.storage_usage);
+        assert_eq!(result.locked_for_storage, account.amount);
+        assert_eq!(result.free_balance, 0);
+    }
+
+    #[test]
+    fn test_view_contract_abi() {
+        let (viewer, mut state_update) = get_test_trie_viewer();
+        viewer
+            .view_contract_abi(&state_update, &alice_account())
+            .expect_err("the account has no ABI");
+
+        let abi = br#"{"methods": [{"name": "get_num", "kind": "view"}]}"#.to_vec();
+        state_update.set(TrieKey::ContractAbi { account_id: alice_account() }, abi.clone());
+        let result = viewer.view_contract_abi(&state_update, &alice_account()).unwrap();
+        assert_eq!(result.abi, abi);
+        assert_eq!(
+            result.methods,
+            Some(vec![ContractMethodAbiView {
+                name: "get_num".to_string(),
+                kind: ContractMethodKindView::View,
+                args_schema: None,
+                result_schema: None,
+            }])
+        );
+
+        state_update.set(TrieKey::ContractAbi { account_id: alice_account() }, vec![0, 1, 2]);
+        let result = viewer.view_contract_abi(&state_update, &alice_account()).unwrap();
+        assert_eq!(result.methods, None);
+    }
+
     #[test]
     fn test_log_when_panic() {
         let (viewer, root) = get_test_trie_viewer();
@@ -371,6 +885,7 @@ mod tests {
                 &mut logs,
                 &MockEpochInfoProvider::default(),
                 PROTOCOL_VERSION,
+                &RuntimeConfig::default(),
             )
             .unwrap_err();
 
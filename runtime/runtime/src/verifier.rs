@@ -1,14 +1,19 @@
 use near_crypto::key_conversion::is_valid_staking_key;
-use near_primitives::account::AccessKeyPermission;
+use near_crypto::PublicKey;
+use near_primitives::account::{AccessKey, AccessKeyPermission};
+use near_primitives::checked_feature;
 use near_primitives::errors::{
-    ActionsValidationError, InvalidAccessKeyError, InvalidTxError, ReceiptValidationError,
-    RuntimeError,
+    ActionErrorKind, ActionsValidationError, InvalidAccessKeyError, InvalidTxError,
+    ReceiptValidationError, RuntimeError,
 };
 use near_primitives::receipt::{ActionReceipt, DataReceipt, Receipt, ReceiptEnum};
+use near_primitives::sharding::CongestionInfo;
 use near_primitives::transaction::{
-    Action, AddKeyAction, DeleteAccountAction, DeployContractAction, FunctionCallAction,
-    SignedTransaction, StakeAction,
+    Action, AddKeyAction, DeleteAccountAction, DeployContractAction, DeployContractWithAbiAction,
+    FunctionCallAction, SignedDelegateAction, SignedTransaction, StakeAction,
+    UpgradeContractAction,
 };
+use near_primitives::types::{AccountId, BlockHeight};
 use near_primitives::utils::is_valid_account_id;
 use near_primitives::version::ProtocolVersion;
 use near_runtime_configs::get_insufficient_storage_stake;
@@ -18,8 +23,8 @@ use near_store::{
 use near_vm_logic::types::Balance;
 use near_vm_logic::VMLimitConfig;
 
-use crate::config::{total_prepaid_gas, tx_cost, RuntimeConfig, TransactionCost};
-use crate::VerificationResult;
+use crate::config::{total_deposit, total_prepaid_gas, tx_cost, RuntimeConfig, TransactionCost};
+use crate::{VerificationResult, CONGESTED_DELAYED_RECEIPTS_THRESHOLD};
 
 /// Validates the transaction without using the state. It allows any node to validate a
 /// transaction before forwarding it to the node that tracks the `signer_id` account.
@@ -50,6 +55,8 @@ pub fn validate_transaction(
         return Err(InvalidTxError::InvalidSignature.into());
     }
 
+    check_actions_supported(&transaction.actions, current_protocol_version)
+        .map_err(|e| InvalidTxError::ActionsValidation(e))?;
     validate_actions(&config.wasm_config.limit_config, &transaction.actions)
         .map_err(|e| InvalidTxError::ActionsValidation(e))?;
 
@@ -65,14 +72,43 @@ pub fn validate_transaction(
     .map_err(|_| InvalidTxError::CostOverflow.into())
 }
 
+/// Rejects the transaction if the shard of its receiver is congested, i.e. its delayed receipts
+/// queue reached the threshold. It's used to filter transactions before they get into a chunk,
+/// the receipts that are already created are throttled by the runtime instead.
+pub fn check_receiver_congestion(
+    signed_transaction: &SignedTransaction,
+    congestion_info: &CongestionInfo,
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), InvalidTxError> {
+    if !checked_feature!(
+        "protocol_feature_congestion_control",
+        CongestionControl,
+        current_protocol_version
+    ) || congestion_info.delayed_receipts_counts.is_empty()
+    {
+        return Ok(());
+    }
+    let shard_id = congestion_info
+        .shard_layout
+        .account_id_to_shard_id(&signed_transaction.transaction.receiver_id);
+    let delayed_receipts_count = congestion_info.delayed_receipts_count(shard_id);
+    if delayed_receipts_count >= CONGESTED_DELAYED_RECEIPTS_THRESHOLD {
+        return Err(InvalidTxError::ReceiverShardCongested { shard_id, delayed_receipts_count });
+    }
+    Ok(())
+}
+
 /// Verifies the signed transaction on top of given state, charges transaction fees
 /// and balances, and updates the state for the used account and access keys.
+/// `block_height` is the height at which the transaction is going to be applied. It's used to
+/// check the expiration of the access key, `None` skips this check.
 pub fn verify_and_charge_transaction(
     config: &RuntimeConfig,
     state_update: &mut TrieUpdate,
     gas_price: Balance,
     signed_transaction: &SignedTransaction,
     verify_signature: bool,
+    block_height: Option<BlockHeight>,
     current_protocol_version: ProtocolVersion,
 ) -> Result<VerificationResult, RuntimeError> {
     let TransactionCost { gas_burnt, gas_remaining, receipt_gas_price, total_cost, burnt_amount } =
@@ -122,19 +158,26 @@ pub fn verify_and_charge_transaction(
             cost: total_cost,
         })?;
 
-    if let AccessKeyPermission::FunctionCall(ref mut function_call_permission) =
-        access_key.permission
-    {
-        if let Some(ref mut allowance) = function_call_permission.allowance {
-            *allowance = allowance.checked_sub(total_cost).ok_or_else(|| {
-                InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::NotEnoughAllowance {
-                    account_id: signer_id.clone(),
-                    public_key: transaction.public_key.clone(),
-                    allowance: *allowance,
-                    cost: total_cost,
-                })
-            })?;
+    let allowance = match access_key.permission {
+        AccessKeyPermission::FunctionCall(ref mut function_call_permission) => {
+            function_call_permission.allowance.as_mut()
+        }
+        AccessKeyPermission::ExtendedFunctionCall(ref mut function_call_permission) => {
+            function_call_permission.allowance.as_mut()
         }
+        AccessKeyPermission::FullAccess => None,
+    };
+    if let Some(allowance) = allowance {
+        // The allowance only pays for gas, the deposits are charged from the deposit allowance.
+        let gas_cost = total_cost - total_deposit(&transaction.actions)?;
+        *allowance = allowance.checked_sub(gas_cost).ok_or_else(|| {
+            InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::NotEnoughAllowance {
+                account_id: signer_id.clone(),
+                public_key: transaction.public_key.clone(),
+                allowance: *allowance,
+                cost: gas_cost,
+            })
+        })?;
     }
 
     match get_insufficient_storage_stake(&signer, &config) {
@@ -151,54 +194,213 @@ pub fn verify_and_charge_transaction(
         }
     };
 
-    if let AccessKeyPermission::FunctionCall(ref function_call_permission) = access_key.permission {
-        if transaction.actions.len() != 1 {
-            return Err(InvalidTxError::InvalidAccessKeyError(
-                InvalidAccessKeyError::RequiresFullAccess,
-            )
-            .into());
-        }
-        if let Some(Action::FunctionCall(ref function_call)) = transaction.actions.get(0) {
+    check_access_key_permission(
+        &access_key,
+        &transaction.receiver_id,
+        &transaction.actions,
+        block_height,
+    )
+    .map_err(InvalidTxError::InvalidAccessKeyError)?;
+
+    let charged_deposit = charge_deposit_allowance(
+        &mut access_key,
+        signer_id,
+        &transaction.public_key,
+        &transaction.actions,
+    )
+    .map_err(InvalidTxError::InvalidAccessKeyError)?;
+
+    set_access_key(state_update, signer_id.clone(), transaction.public_key.clone(), &access_key);
+    set_account(state_update, signer_id.clone(), &signer);
+
+    Ok(VerificationResult {
+        gas_burnt,
+        gas_remaining,
+        receipt_gas_price,
+        burnt_amount,
+        charged_deposit,
+    })
+}
+
+/// Checks that the access key allows to sign the given actions for the given receiver. A function
+/// call access key can only sign a single function call without a deposit to its receiver and one
+/// of its method names. An extended function call access key can only sign a single function call
+/// to one of its receivers and method names, with a deposit up to its limit and before it expires.
+pub(crate) fn check_access_key_permission(
+    access_key: &AccessKey,
+    receiver_id: &AccountId,
+    actions: &[Action],
+    block_height: Option<BlockHeight>,
+) -> Result<(), InvalidAccessKeyError> {
+    match access_key.permission {
+        AccessKeyPermission::FunctionCall(ref function_call_permission) => {
+            let function_call = get_single_function_call(actions)?;
             if function_call.deposit > 0 {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::DepositWithFunctionCall,
-                )
-                .into());
+                return Err(InvalidAccessKeyError::DepositWithFunctionCall);
             }
-            if transaction.receiver_id != function_call_permission.receiver_id {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::ReceiverMismatch {
-                        tx_receiver: transaction.receiver_id.clone(),
-                        ak_receiver: function_call_permission.receiver_id.clone(),
-                    },
-                )
-                .into());
+            if receiver_id != &function_call_permission.receiver_id {
+                return Err(InvalidAccessKeyError::ReceiverMismatch {
+                    tx_receiver: receiver_id.clone(),
+                    ak_receiver: function_call_permission.receiver_id.clone(),
+                });
             }
-            if !function_call_permission.method_names.is_empty()
-                && function_call_permission
-                    .method_names
-                    .iter()
-                    .all(|method_name| &function_call.method_name != method_name)
+            check_method_name(&function_call_permission.method_names, function_call)
+        }
+        AccessKeyPermission::ExtendedFunctionCall(ref function_call_permission) => {
+            if let (Some(expires_at), Some(block_height)) =
+                (function_call_permission.expires_at, block_height)
             {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::MethodNameMismatch {
-                        method_name: function_call.method_name.clone(),
-                    },
-                )
-                .into());
+                if block_height > expires_at {
+                    return Err(InvalidAccessKeyError::AccessKeyExpired {
+                        expires_at,
+                        block_height,
+                    });
+                }
             }
-        } else {
-            return Err(InvalidTxError::InvalidAccessKeyError(
-                InvalidAccessKeyError::RequiresFullAccess,
-            )
-            .into());
+            let function_call = get_single_function_call(actions)?;
+            if function_call.deposit > 0 {
+                match function_call_permission.max_deposit_per_call {
+                    None => return Err(InvalidAccessKeyError::DepositWithFunctionCall),
+                    Some(limit) if function_call.deposit > limit => {
+                        return Err(InvalidAccessKeyError::DepositExceedsLimit {
+                            deposit: function_call.deposit,
+                            limit,
+                        });
+                    }
+                    Some(_) => {}
+                }
+            }
+            if !function_call_permission.receiver_ids.contains(receiver_id) {
+                return Err(InvalidAccessKeyError::ReceiverNotAllowed {
+                    tx_receiver: receiver_id.clone(),
+                });
+            }
+            check_method_name(&function_call_permission.method_names, function_call)
         }
-    };
+        AccessKeyPermission::FullAccess => Ok(()),
+    }
+}
 
-    set_access_key(state_update, signer_id.clone(), transaction.public_key.clone(), &access_key);
-    set_account(state_update, signer_id.clone(), &signer);
+/// Decreases the deposit allowance of an extended function call access key by the deposits
+/// attached to the given actions. Returns the charged amount, which is zero if the access key
+/// doesn't limit the total deposits.
+fn charge_deposit_allowance(
+    access_key: &mut AccessKey,
+    account_id: &AccountId,
+    public_key: &PublicKey,
+    actions: &[Action],
+) -> Result<Balance, InvalidAccessKeyError> {
+    if let AccessKeyPermission::ExtendedFunctionCall(ref mut function_call_permission) =
+        access_key.permission
+    {
+        if let Some(ref mut deposit_allowance) = function_call_permission.deposit_allowance {
+            let deposit = total_deposit(actions).unwrap_or(Balance::max_value());
+            *deposit_allowance = deposit_allowance.checked_sub(deposit).ok_or_else(|| {
+                InvalidAccessKeyError::NotEnoughDepositAllowance {
+                    account_id: account_id.clone(),
+                    public_key: public_key.clone(),
+                    deposit_allowance: *deposit_allowance,
+                    deposit,
+                }
+            })?;
+            return Ok(deposit);
+        }
+    }
+    Ok(0)
+}
+
+/// Returns the only function call action, function call access keys can't sign anything else.
+fn get_single_function_call(
+    actions: &[Action],
+) -> Result<&FunctionCallAction, InvalidAccessKeyError> {
+    match actions {
+        [Action::FunctionCall(function_call)] => Ok(function_call),
+        _ => Err(InvalidAccessKeyError::RequiresFullAccess),
+    }
+}
+
+/// Checks that the method name is allowed, an empty list allows any method name.
+fn check_method_name(
+    method_names: &[String],
+    function_call: &FunctionCallAction,
+) -> Result<(), InvalidAccessKeyError> {
+    if !method_names.is_empty()
+        && method_names.iter().all(|method_name| &function_call.method_name != method_name)
+    {
+        return Err(InvalidAccessKeyError::MethodNameMismatch {
+            method_name: function_call.method_name.clone(),
+        });
+    }
+    Ok(())
+}
 
-    Ok(VerificationResult { gas_burnt, gas_remaining, receipt_gas_price, burnt_amount })
+/// Verifies the signed delegate action on top of the state of its sender and updates the nonce of
+/// the sender's access key. The caller is responsible for charging the sender for the deposits.
+pub(crate) fn verify_delegate_action(
+    state_update: &mut TrieUpdate,
+    signed_delegate_action: &SignedDelegateAction,
+    receiver_id: &AccountId,
+    block_height: BlockHeight,
+) -> Result<Result<(), ActionErrorKind>, StorageError> {
+    let delegate_action = &signed_delegate_action.delegate_action;
+    if !signed_delegate_action.verify() {
+        return Ok(Err(ActionErrorKind::DelegateActionInvalidSignature));
+    }
+    if block_height > delegate_action.max_block_height {
+        return Ok(Err(ActionErrorKind::DelegateActionExpired));
+    }
+    if &delegate_action.sender_id != receiver_id {
+        return Ok(Err(ActionErrorKind::DelegateActionSenderDoesNotMatchTxReceiver {
+            sender_id: delegate_action.sender_id.clone(),
+            receiver_id: receiver_id.clone(),
+        }));
+    }
+    let mut access_key = match get_access_key(
+        state_update,
+        &delegate_action.sender_id,
+        &delegate_action.public_key,
+    )? {
+        Some(access_key) => access_key,
+        None => {
+            return Ok(Err(ActionErrorKind::DelegateActionAccessKeyError(
+                InvalidAccessKeyError::AccessKeyNotFound {
+                    account_id: delegate_action.sender_id.clone(),
+                    public_key: delegate_action.public_key.clone(),
+                },
+            )));
+        }
+    };
+    if delegate_action.nonce <= access_key.nonce {
+        return Ok(Err(ActionErrorKind::DelegateActionInvalidNonce {
+            delegate_nonce: delegate_action.nonce,
+            ak_nonce: access_key.nonce,
+        }));
+    }
+    if let Err(err) = check_access_key_permission(
+        &access_key,
+        &delegate_action.receiver_id,
+        &delegate_action.actions,
+        Some(block_height),
+    )
+    .and_then(|_| {
+        charge_deposit_allowance(
+            &mut access_key,
+            &delegate_action.sender_id,
+            &delegate_action.public_key,
+            &delegate_action.actions,
+        )
+    }) {
+        return Ok(Err(ActionErrorKind::DelegateActionAccessKeyError(err)));
+    }
+
+    access_key.nonce = delegate_action.nonce;
+    set_access_key(
+        state_update,
+        delegate_action.sender_id.clone(),
+        delegate_action.public_key.clone(),
+        &access_key,
+    );
+    Ok(Ok(()))
 }
 
 /// Validates a given receipt. Checks validity of the predecessor and receiver account IDs and
@@ -268,6 +470,68 @@ fn validate_data_receipt(
     Ok(())
 }
 
+/// Checks that every given action, including the actions inside delegate actions, is enabled in
+/// the given protocol version.
+pub(crate) fn check_actions_supported(
+    actions: &[Action],
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), ActionsValidationError> {
+    for action in actions {
+        let (protocol_feature, is_supported) = match action {
+            Action::UpgradeContract(_) => (
+                "UpgradeContract",
+                checked_feature!(
+                    "protocol_feature_contract_upgrade",
+                    ContractUpgrade,
+                    current_protocol_version
+                ),
+            ),
+            Action::Delegate(_) => (
+                "Delegate",
+                checked_feature!(
+                    "protocol_feature_delegate_action",
+                    DelegateAction,
+                    current_protocol_version
+                ),
+            ),
+            Action::DeployContractWithAbi(_) => (
+                "DeployContractWithAbi",
+                checked_feature!(
+                    "protocol_feature_contract_abi",
+                    ContractAbi,
+                    current_protocol_version
+                ),
+            ),
+            Action::AddKey(AddKeyAction {
+                access_key:
+                    AccessKey { permission: AccessKeyPermission::ExtendedFunctionCall(_), .. },
+                ..
+            }) => (
+                "ExtendedFunctionCallAccessKey",
+                checked_feature!(
+                    "protocol_feature_extended_function_call_access_key",
+                    ExtendedFunctionCallAccessKey,
+                    current_protocol_version
+                ),
+            ),
+            _ => continue,
+        };
+        if !is_supported {
+            return Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: protocol_feature.to_string(),
+                version: current_protocol_version,
+            });
+        }
+        if let Action::Delegate(signed_delegate_action) = action {
+            check_actions_supported(
+                &signed_delegate_action.delegate_action.actions,
+                current_protocol_version,
+            )?;
+        }
+    }
+    Ok(())
+}
+
 /// Validates given actions. Checks limits and validates `account_id` if applicable.
 /// Checks that the total number of actions doesn't exceed the limit.
 /// Validates each individual action.
@@ -319,6 +583,11 @@ pub fn validate_action(
         Action::AddKey(a) => validate_add_key_action(limit_config, a),
         Action::DeleteKey(_) => Ok(()),
         Action::DeleteAccount(a) => validate_delete_account_action(a),
+        Action::UpgradeContract(a) => validate_upgrade_contract_action(limit_config, a),
+        Action::Delegate(a) => validate_delegate_action(limit_config, a),
+        Action::DeployContractWithAbi(a) => {
+            validate_deploy_contract_with_abi_action(limit_config, a)
+        }
     }
 }
 
@@ -337,6 +606,29 @@ fn validate_deploy_contract_action(
     Ok(())
 }
 
+/// Validates `DeployContractWithAbiAction`. Checks the code against the `DeployContractAction`
+/// limits. The ABI is limited by the same maximum size as the contract code.
+fn validate_deploy_contract_with_abi_action(
+    limit_config: &VMLimitConfig,
+    action: &DeployContractWithAbiAction,
+) -> Result<(), ActionsValidationError> {
+    if action.code.len() as u64 > limit_config.max_contract_size {
+        return Err(ActionsValidationError::ContractSizeExceeded {
+            size: action.code.len() as u64,
+            limit: limit_config.max_contract_size,
+        });
+    }
+
+    if action.abi.len() as u64 > limit_config.max_contract_size {
+        return Err(ActionsValidationError::ContractAbiSizeExceeded {
+            size: action.abi.len() as u64,
+            limit: limit_config.max_contract_size,
+        });
+    }
+
+    Ok(())
+}
+
 /// Validates `FunctionCallAction`. Checks that the method name length doesn't exceed the limit and
 /// the length of the arguments doesn't exceed the limit.
 fn validate_function_call_action(
@@ -364,6 +656,58 @@ fn validate_function_call_action(
     Ok(())
 }
 
+/// Validates `UpgradeContractAction`. Checks the new code against the `DeployContractAction`
+/// limits and the migration call against the `FunctionCallAction` limits.
+fn validate_upgrade_contract_action(
+    limit_config: &VMLimitConfig,
+    action: &UpgradeContractAction,
+) -> Result<(), ActionsValidationError> {
+    if action.code.len() as u64 > limit_config.max_contract_size {
+        return Err(ActionsValidationError::ContractSizeExceeded {
+            size: action.code.len() as u64,
+            limit: limit_config.max_contract_size,
+        });
+    }
+
+    if action.gas == 0 {
+        return Err(ActionsValidationError::FunctionCallZeroAttachedGas);
+    }
+
+    if action.method_name.len() as u64 > limit_config.max_length_method_name {
+        return Err(ActionsValidationError::FunctionCallMethodNameLengthExceeded {
+            length: action.method_name.len() as u64,
+            limit: limit_config.max_length_method_name,
+        });
+    }
+
+    if action.args.len() as u64 > limit_config.max_arguments_length {
+        return Err(ActionsValidationError::FunctionCallArgumentsLengthExceeded {
+            length: action.args.len() as u64,
+            limit: limit_config.max_arguments_length,
+        });
+    }
+
+    Ok(())
+}
+
+/// Validates `SignedDelegateAction`. Checks that the delegated actions don't contain other
+/// delegate actions and validates them the same way as the actions of a transaction.
+fn validate_delegate_action(
+    limit_config: &VMLimitConfig,
+    action: &SignedDelegateAction,
+) -> Result<(), ActionsValidationError> {
+    let actions = &action.delegate_action.actions;
+    if actions.iter().any(|action| matches!(action, Action::Delegate(_))) {
+        return Err(ActionsValidationError::NestedDelegateAction);
+    }
+    if !is_valid_account_id(&action.delegate_action.receiver_id) {
+        return Err(ActionsValidationError::InvalidAccountId {
+            account_id: action.delegate_action.receiver_id.clone(),
+        });
+    }
+    validate_actions(limit_config, actions)
+}
+
 /// Validates `StakeAction`. Checks that the `public_key` is a valid staking key.
 fn validate_stake_action(action: &StakeAction) -> Result<(), ActionsValidationError> {
     if !is_valid_staking_key(&action.public_key) {
@@ -375,38 +719,46 @@ fn validate_stake_action(action: &StakeAction) -> Result<(), ActionsValidationEr
     Ok(())
 }
 
-/// Validates `AddKeyAction`. If the access key permission is `FunctionCall` checks that the
-/// `receiver_id` is a valid account ID, checks the total number of bytes of the method names
-/// doesn't exceed the limit and every method name length doesn't exceed the limit.
+/// Validates `AddKeyAction`. If the access key permission is `FunctionCall` or
+/// `ExtendedFunctionCall` checks that the receiver IDs are valid account IDs, checks the total
+/// number of bytes of the method names doesn't exceed the limit and every method name length
+/// doesn't exceed the limit.
 fn validate_add_key_action(
     limit_config: &VMLimitConfig,
     action: &AddKeyAction,
 ) -> Result<(), ActionsValidationError> {
-    if let AccessKeyPermission::FunctionCall(fc) = &action.access_key.permission {
-        if !is_valid_account_id(&fc.receiver_id) {
+    let (receiver_ids, method_names) = match &action.access_key.permission {
+        AccessKeyPermission::FunctionCall(fc) => {
+            (std::slice::from_ref(&fc.receiver_id), &fc.method_names)
+        }
+        AccessKeyPermission::ExtendedFunctionCall(fc) => (&fc.receiver_ids[..], &fc.method_names),
+        AccessKeyPermission::FullAccess => return Ok(()),
+    };
+    for receiver_id in receiver_ids {
+        if !is_valid_account_id(receiver_id) {
             return Err(ActionsValidationError::InvalidAccountId {
-                account_id: fc.receiver_id.clone(),
+                account_id: receiver_id.clone(),
             });
         }
-        // Checking method name length limits
-        let mut total_number_of_bytes = 0;
-        for method_name in &fc.method_names {
-            let length = method_name.len() as u64;
-            if length > limit_config.max_length_method_name {
-                return Err(ActionsValidationError::AddKeyMethodNameLengthExceeded {
-                    length,
-                    limit: limit_config.max_length_method_name,
-                });
-            }
-            // Adding terminating character to the total number of bytes
-            total_number_of_bytes += length + 1;
-        }
-        if total_number_of_bytes > limit_config.max_number_bytes_method_names {
-            return Err(ActionsValidationError::AddKeyMethodNamesNumberOfBytesExceeded {
-                total_number_of_bytes,
-                limit: limit_config.max_number_bytes_method_names,
+    }
+    // Checking method name length limits
+    let mut total_number_of_bytes = 0;
+    for method_name in method_names {
+        let length = method_name.len() as u64;
+        if length > limit_config.max_length_method_name {
+            return Err(ActionsValidationError::AddKeyMethodNameLengthExceeded {
+                length,
+                limit: limit_config.max_length_method_name,
             });
         }
+        // Adding terminating character to the total number of bytes
+        total_number_of_bytes += length + 1;
+    }
+    if total_number_of_bytes > limit_config.max_number_bytes_method_names {
+        return Err(ActionsValidationError::AddKeyMethodNamesNumberOfBytesExceeded {
+            total_number_of_bytes,
+            limit: limit_config.max_number_bytes_method_names,
+        });
     }
 
     Ok(())
@@ -435,10 +787,10 @@ mod tests {
     use near_primitives::receipt::DataReceiver;
     use near_primitives::test_utils::account_new;
     use near_primitives::transaction::{
-        CreateAccountAction, DeleteKeyAction, StakeAction, TransferAction,
+        CreateAccountAction, DelegateAction, DeleteKeyAction, StakeAction, TransferAction,
     };
     use near_primitives::types::{AccountId, Balance, MerkleHash, StateChangeCause};
-    use near_primitives::version::PROTOCOL_VERSION;
+    use near_primitives::version::{PROTOCOL_VERSION, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
     use near_store::test_utils::create_tries;
     use testlib::runtime_utils::{alice_account, bob_account, eve_dot_alice_account};
 
@@ -509,6 +861,7 @@ mod tests {
                 gas_price,
                 &signed_transaction,
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -541,6 +894,7 @@ mod tests {
             gas_price,
             &transaction,
             true,
+            None,
             PROTOCOL_VERSION,
         )
         .expect("valid transaction");
@@ -662,6 +1016,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 false,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -728,6 +1083,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -760,6 +1116,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -808,6 +1165,7 @@ mod tests {
                 CryptoHash::default(),
             ),
             true,
+            None,
             PROTOCOL_VERSION,
         )
         .expect_err("expected an error");
@@ -859,6 +1217,7 @@ mod tests {
                 CryptoHash::default(),
             ),
             true,
+            None,
             PROTOCOL_VERSION,
         )
         .expect_err("expected an error");
@@ -900,6 +1259,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -950,6 +1310,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -972,6 +1333,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -994,6 +1356,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1038,6 +1401,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1085,6 +1449,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1129,6 +1494,7 @@ mod tests {
                     CryptoHash::default(),
                 ),
                 true,
+                None,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1138,6 +1504,202 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn extended_function_call_access_key() -> AccessKey {
+        use near_primitives::account::ExtendedFunctionCallPermission;
+
+        AccessKey {
+            nonce: 0,
+            permission: AccessKeyPermission::ExtendedFunctionCall(ExtendedFunctionCallPermission {
+                allowance: None,
+                receiver_ids: vec![bob_account(), eve_dot_alice_account()],
+                method_names: vec![],
+                max_deposit_per_call: Some(100),
+                deposit_allowance: Some(150),
+                expires_at: Some(10),
+            }),
+        }
+    }
+
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn function_call_with_deposit(
+        nonce: u64,
+        receiver_id: AccountId,
+        signer: &dyn Signer,
+        deposit: Balance,
+    ) -> SignedTransaction {
+        SignedTransaction::from_actions(
+            nonce,
+            alice_account(),
+            receiver_id,
+            signer,
+            vec![Action::FunctionCall(FunctionCallAction {
+                method_name: "hello".to_string(),
+                args: b"abc".to_vec(),
+                gas: 100,
+                deposit,
+            })],
+            CryptoHash::default(),
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_validate_transaction_extended_function_call_deposit() {
+        let config = RuntimeConfig::default();
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(extended_function_call_access_key()));
+
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &function_call_with_deposit(1, eve_dot_alice_account(), &*signer, 100),
+            true,
+            Some(10),
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+        let access_key =
+            get_access_key(&state_update, &alice_account(), &signer.public_key()).unwrap().unwrap();
+        match access_key.permission {
+            AccessKeyPermission::ExtendedFunctionCall(permission) => {
+                assert_eq!(permission.deposit_allowance, Some(50));
+            }
+            permission => panic!("unexpected permission {:?}", permission),
+        }
+
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &function_call_with_deposit(2, bob_account(), &*signer, 101),
+                true,
+                Some(10),
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::DepositExceedsLimit { deposit: 101, limit: 100 },
+            )),
+        );
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &function_call_with_deposit(2, bob_account(), &*signer, 100),
+                true,
+                Some(10),
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::NotEnoughDepositAllowance {
+                    account_id: alice_account(),
+                    public_key: signer.public_key(),
+                    deposit_allowance: 50,
+                    deposit: 100,
+                },
+            )),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_validate_transaction_extended_function_call_allowance_excludes_deposit() {
+        let config = RuntimeConfig::default();
+        let mut access_key = extended_function_call_access_key();
+        if let AccessKeyPermission::ExtendedFunctionCall(ref mut permission) = access_key.permission
+        {
+            permission.allowance = Some(NEAR_BASE);
+        }
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(access_key));
+
+        let transaction = function_call_with_deposit(1, bob_account(), &*signer, 100);
+        let TransactionCost { total_cost, .. } = tx_cost(
+            &config.transaction_costs,
+            &transaction.transaction,
+            gas_price,
+            false,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &transaction,
+            true,
+            Some(10),
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.amount, TESTING_INIT_BALANCE - total_cost);
+        let access_key =
+            get_access_key(&state_update, &alice_account(), &signer.public_key()).unwrap().unwrap();
+        match access_key.permission {
+            AccessKeyPermission::ExtendedFunctionCall(permission) => {
+                // The deposit is only charged from the deposit allowance.
+                assert_eq!(permission.allowance, Some(NEAR_BASE - (total_cost - 100)));
+                assert_eq!(permission.deposit_allowance, Some(50));
+            }
+            permission => panic!("unexpected permission {:?}", permission),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_validate_transaction_extended_function_call_invalid_receiver() {
+        let config = RuntimeConfig::default();
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(extended_function_call_access_key()));
+
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &function_call_with_deposit(1, "carol.near".to_string(), &*signer, 0),
+                true,
+                Some(10),
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::ReceiverNotAllowed { tx_receiver: "carol.near".to_string() },
+            )),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_extended_function_call_access_key")]
+    fn test_validate_transaction_extended_function_call_expired() {
+        let config = RuntimeConfig::default();
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(extended_function_call_access_key()));
+
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &function_call_with_deposit(1, bob_account(), &*signer, 0),
+                true,
+                Some(11),
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::AccessKeyExpired { expires_at: 10, block_height: 11 },
+            )),
+        );
+    }
+
     // Receipts
 
     #[test]
@@ -1446,6 +2008,294 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validate_action_invalid_upgrade_contract_zero_gas() {
+        assert_eq!(
+            validate_action(
+                &VMLimitConfig::default(),
+                &Action::UpgradeContract(UpgradeContractAction {
+                    code: vec![1, 2, 3],
+                    method_name: "migrate".to_string(),
+                    args: vec![],
+                    gas: 0,
+                }),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::FunctionCallZeroAttachedGas,
+        );
+    }
+
+    #[test]
+    fn test_validate_action_nested_delegate_action() {
+        let signer = InMemorySigner::from_seed(&alice_account(), KeyType::ED25519, "alice");
+        let delegate_action = |actions| DelegateAction {
+            sender_id: alice_account(),
+            receiver_id: bob_account(),
+            actions,
+            nonce: 1,
+            max_block_height: 100,
+            public_key: signer.public_key(),
+        };
+        let sign = |delegate_action: DelegateAction| {
+            let signature = signer.sign(delegate_action.get_hash().as_ref());
+            Action::Delegate(SignedDelegateAction::new(signature, delegate_action))
+        };
+        let inner = sign(delegate_action(vec![Action::Transfer(TransferAction { deposit: 1 })]));
+        validate_action(&VMLimitConfig::default(), &inner).expect("valid action");
+        assert_eq!(
+            validate_action(&VMLimitConfig::default(), &sign(delegate_action(vec![inner])))
+                .expect_err("expected an error"),
+            ActionsValidationError::NestedDelegateAction,
+        );
+    }
+
+    fn sign_delegate_action(
+        signer: &dyn Signer,
+        nonce: u64,
+        actions: Vec<Action>,
+    ) -> SignedDelegateAction {
+        let delegate_action = DelegateAction {
+            sender_id: alice_account(),
+            receiver_id: bob_account(),
+            actions,
+            nonce,
+            max_block_height: 100,
+            public_key: signer.public_key(),
+        };
+        let signature = signer.sign(delegate_action.get_hash().as_ref());
+        SignedDelegateAction::new(signature, delegate_action)
+    }
+
+    fn transfer(deposit: Balance) -> Vec<Action> {
+        vec![Action::Transfer(TransferAction { deposit })]
+    }
+
+    #[test]
+    fn test_verify_delegate_action_replayed_nonce() {
+        let (signer, mut state_update, _) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+        let signed_delegate_action = sign_delegate_action(&*signer, 1, transfer(10));
+        verify_delegate_action(&mut state_update, &signed_delegate_action, &alice_account(), 10)
+            .unwrap()
+            .expect("valid delegate action");
+        let access_key =
+            get_access_key(&state_update, &alice_account(), &signer.public_key()).unwrap().unwrap();
+        assert_eq!(access_key.nonce, 1);
+
+        assert_eq!(
+            verify_delegate_action(
+                &mut state_update,
+                &signed_delegate_action,
+                &alice_account(),
+                10
+            )
+            .unwrap(),
+            Err(ActionErrorKind::DelegateActionInvalidNonce { delegate_nonce: 1, ak_nonce: 1 }),
+        );
+    }
+
+    #[test]
+    fn test_verify_delegate_action_expired() {
+        let (signer, mut state_update, _) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+        let signed_delegate_action = sign_delegate_action(&*signer, 1, transfer(10));
+        assert_eq!(
+            verify_delegate_action(
+                &mut state_update,
+                &signed_delegate_action,
+                &alice_account(),
+                101
+            )
+            .unwrap(),
+            Err(ActionErrorKind::DelegateActionExpired),
+        );
+    }
+
+    #[test]
+    fn test_verify_delegate_action_invalid_signature() {
+        let (signer, mut state_update, _) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+        let mut signed_delegate_action = sign_delegate_action(&*signer, 1, transfer(10));
+        // The signature doesn't cover the changed deposit.
+        signed_delegate_action.delegate_action.actions = transfer(1_000);
+        assert_eq!(
+            verify_delegate_action(
+                &mut state_update,
+                &signed_delegate_action,
+                &alice_account(),
+                10
+            )
+            .unwrap(),
+            Err(ActionErrorKind::DelegateActionInvalidSignature),
+        );
+
+        let other_signer = InMemorySigner::from_seed(&bob_account(), KeyType::ED25519, "bob");
+        let mut signed_delegate_action = sign_delegate_action(&*signer, 1, transfer(10));
+        signed_delegate_action.signature =
+            other_signer.sign(signed_delegate_action.delegate_action.get_hash().as_ref());
+        assert_eq!(
+            verify_delegate_action(
+                &mut state_update,
+                &signed_delegate_action,
+                &alice_account(),
+                10
+            )
+            .unwrap(),
+            Err(ActionErrorKind::DelegateActionInvalidSignature),
+        );
+    }
+
+    #[test]
+    fn test_verify_delegate_action_sender_does_not_match_receiver() {
+        let (signer, mut state_update, _) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+        let signed_delegate_action = sign_delegate_action(&*signer, 1, transfer(10));
+        assert_eq!(
+            verify_delegate_action(&mut state_update, &signed_delegate_action, &bob_account(), 10)
+                .unwrap(),
+            Err(ActionErrorKind::DelegateActionSenderDoesNotMatchTxReceiver {
+                sender_id: alice_account(),
+                receiver_id: bob_account(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_verify_delegate_action_function_call_access_key() {
+        let (signer, mut state_update, _) = setup_common(
+            TESTING_INIT_BALANCE,
+            0,
+            Some(AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                    allowance: None,
+                    receiver_id: bob_account(),
+                    method_names: vec!["hello".to_string()],
+                }),
+            }),
+        );
+        let function_call = |method_name: &str, deposit| {
+            vec![Action::FunctionCall(FunctionCallAction {
+                method_name: method_name.to_string(),
+                args: vec![],
+                gas: 100,
+                deposit,
+            })]
+        };
+        let mut verify = |nonce, actions| {
+            verify_delegate_action(
+                &mut state_update,
+                &sign_delegate_action(&*signer, nonce, actions),
+                &alice_account(),
+                10,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            verify(1, transfer(10)),
+            Err(ActionErrorKind::DelegateActionAccessKeyError(
+                InvalidAccessKeyError::RequiresFullAccess
+            )),
+        );
+        assert_eq!(
+            verify(1, function_call("hello", 10)),
+            Err(ActionErrorKind::DelegateActionAccessKeyError(
+                InvalidAccessKeyError::DepositWithFunctionCall
+            )),
+        );
+        assert_eq!(
+            verify(1, function_call("bye", 0)),
+            Err(ActionErrorKind::DelegateActionAccessKeyError(
+                InvalidAccessKeyError::MethodNameMismatch { method_name: "bye".to_string() }
+            )),
+        );
+        verify(1, function_call("hello", 0)).expect("allowed function call");
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn test_delegate_action_inner_actions_must_be_supported() {
+        use near_primitives::version::{ProtocolFeature, PROTOCOL_FEATURES_TO_VERSION_MAPPING};
+
+        let signer = InMemorySigner::from_seed(&alice_account(), KeyType::ED25519, "alice");
+        let version = PROTOCOL_FEATURES_TO_VERSION_MAPPING[&ProtocolFeature::DelegateAction];
+        let actions = vec![Action::Delegate(sign_delegate_action(&signer, 1, transfer(10)))];
+        check_actions_supported(&actions, version).expect("supported action");
+
+        // `DeployContractWithAbi` is introduced after delegate actions.
+        let actions = vec![Action::Delegate(sign_delegate_action(
+            &signer,
+            1,
+            vec![Action::DeployContractWithAbi(DeployContractWithAbiAction {
+                code: vec![1, 2, 3],
+                abi: b"{}".to_vec(),
+            })],
+        ))];
+        assert_eq!(
+            check_actions_supported(&actions, version).expect_err("expected an error"),
+            ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "DeployContractWithAbi".to_string(),
+                version,
+            },
+        );
+    }
+
+    #[test]
+    fn test_upgrade_contract_not_supported_before_protocol_upgrade() {
+        let actions = vec![Action::UpgradeContract(UpgradeContractAction {
+            code: vec![1, 2, 3],
+            method_name: "migrate".to_string(),
+            args: vec![],
+            gas: 100,
+        })];
+        assert_eq!(
+            check_actions_supported(&actions, SHARD_CHUNK_HEADER_UPGRADE_VERSION)
+                .expect_err("expected an error"),
+            ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "UpgradeContract".to_string(),
+                version: SHARD_CHUNK_HEADER_UPGRADE_VERSION,
+            },
+        );
+        #[cfg(feature = "protocol_feature_contract_upgrade")]
+        check_actions_supported(&actions, PROTOCOL_VERSION).expect("supported action");
+    }
+
+    #[test]
+    fn test_validate_action_invalid_contract_abi_size() {
+        let mut limit_config = VMLimitConfig::default();
+        limit_config.max_contract_size = 5;
+        assert_eq!(
+            validate_action(
+                &limit_config,
+                &Action::DeployContractWithAbi(DeployContractWithAbiAction {
+                    code: vec![1, 2, 3],
+                    abi: vec![1, 2, 3, 4, 5, 6],
+                }),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::ContractAbiSizeExceeded { size: 6, limit: 5 },
+        );
+    }
+
+    #[test]
+    fn test_deploy_contract_with_abi_not_supported_before_protocol_upgrade() {
+        let actions = vec![Action::DeployContractWithAbi(DeployContractWithAbiAction {
+            code: vec![1, 2, 3],
+            abi: b"{}".to_vec(),
+        })];
+        assert_eq!(
+            check_actions_supported(&actions, SHARD_CHUNK_HEADER_UPGRADE_VERSION)
+                .expect_err("expected an error"),
+            ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "DeployContractWithAbi".to_string(),
+                version: SHARD_CHUNK_HEADER_UPGRADE_VERSION,
+            },
+        );
+        #[cfg(feature = "protocol_feature_contract_abi")]
+        check_actions_supported(&actions, PROTOCOL_VERSION).expect("supported action");
+    }
+
     #[test]
     fn test_validate_action_valid_transfer() {
         validate_action(
@@ -1532,4 +2382,40 @@ mod tests {
         )
         .expect("valid action");
     }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    fn test_check_receiver_congestion() {
+        use near_primitives::shard_layout::ShardLayout;
+
+        let signer = InMemorySigner::from_seed(&alice_account(), KeyType::ED25519, "alice");
+        let transaction = SignedTransaction::send_money(
+            1,
+            alice_account(),
+            bob_account(),
+            &signer,
+            100,
+            CryptoHash::default(),
+        );
+        // "bob.near" is on the second shard of the layout.
+        let mut congestion_info = CongestionInfo {
+            delayed_receipts_counts: vec![CONGESTED_DELAYED_RECEIPTS_THRESHOLD, 0],
+            shard_layout: ShardLayout::v1(vec!["b".to_string()], None, 0),
+        };
+        check_receiver_congestion(&transaction, &congestion_info, PROTOCOL_VERSION)
+            .expect("receiver shard isn't congested");
+
+        congestion_info.delayed_receipts_counts[1] = CONGESTED_DELAYED_RECEIPTS_THRESHOLD;
+        assert_eq!(
+            check_receiver_congestion(&transaction, &congestion_info, PROTOCOL_VERSION),
+            Err(InvalidTxError::ReceiverShardCongested {
+                shard_id: 1,
+                delayed_receipts_count: CONGESTED_DELAYED_RECEIPTS_THRESHOLD,
+            })
+        );
+
+        // Without the congestion info, e.g. right after the shards are split, nothing is rejected.
+        check_receiver_congestion(&transaction, &CongestionInfo::default(), PROTOCOL_VERSION)
+            .expect("congestion is unknown");
+    }
 }
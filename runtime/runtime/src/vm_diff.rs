@@ -0,0 +1,209 @@
+//! Differential execution of function calls.
+//!
+//! When `ApplyState::differential_vm` is set, every function call is additionally executed on the
+//! given VM against the same state, and any difference with the result of the default VM is
+//! reported. The result of the differential run is always discarded, so enabling this mode never
+//! changes the outcome of applying a chunk.
+
+use log::warn;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::types::AccountId;
+use near_store::{TrieUpdate, TrieUpdates};
+use near_vm_logic::{VMKind, VMOutcome};
+use near_vm_runner::VMError;
+
+use crate::metrics;
+
+/// Everything a single function call execution produced.
+pub(crate) struct VMExecution {
+    pub vm_kind: VMKind,
+    pub outcome: Option<VMOutcome>,
+    pub error: Option<VMError>,
+    pub receipts: Vec<Receipt>,
+    /// Uncommitted state changes of the receipt after the call.
+    pub state_changes: TrieUpdates,
+}
+
+impl VMExecution {
+    /// Runs `execute` on top of `state_update` and reverts all the changes it made afterwards.
+    pub fn isolated<F>(vm_kind: VMKind, state_update: &mut TrieUpdate, execute: F) -> Self
+    where
+        F: FnOnce(&mut TrieUpdate) -> (Option<VMOutcome>, Option<VMError>, Vec<Receipt>),
+    {
+        let initial_changes = state_update.prospective();
+        let (outcome, error, receipts) = execute(state_update);
+        let state_changes = state_update.prospective();
+        state_update.set_prospective(initial_changes);
+        VMExecution { vm_kind, outcome, error, receipts, state_changes }
+    }
+
+    /// Returns human readable descriptions of every difference between two executions.
+    pub fn differences(&self, other: &VMExecution) -> Vec<String> {
+        let mut differences = vec![];
+        let mut compare = |what: &str, left: String, right: String| {
+            if left != right {
+                differences.push(format!(
+                    "{}: {:?} = {}, {:?} = {}",
+                    what, self.vm_kind, left, other.vm_kind, right
+                ));
+            }
+        };
+        match (&self.outcome, &other.outcome) {
+            (Some(left), Some(right)) => {
+                compare(
+                    "return value",
+                    format!("{:?}", left.return_data),
+                    format!("{:?}", right.return_data),
+                );
+                compare("gas burnt", left.burnt_gas.to_string(), right.burnt_gas.to_string());
+                compare("gas used", left.used_gas.to_string(), right.used_gas.to_string());
+                compare("balance", left.balance.to_string(), right.balance.to_string());
+                compare(
+                    "storage usage",
+                    left.storage_usage.to_string(),
+                    right.storage_usage.to_string(),
+                );
+                compare("logs", format!("{:?}", left.logs), format!("{:?}", right.logs));
+            }
+            (left, right) => {
+                compare("outcome", format!("{:?}", left), format!("{:?}", right));
+            }
+        }
+        compare("error", format!("{:?}", self.error), format!("{:?}", other.error));
+        compare("receipts", format!("{:?}", self.receipts), format!("{:?}", other.receipts));
+        compare(
+            "state changes",
+            format!("{:?}", self.state_changes.values().collect::<Vec<_>>()),
+            format!("{:?}", other.state_changes.values().collect::<Vec<_>>()),
+        );
+        differences
+    }
+}
+
+/// Compares the executions of the same function call and logs every divergence.
+pub(crate) fn report_differences(
+    account_id: &AccountId,
+    method_name: &str,
+    action_hash: &CryptoHash,
+    expected: &VMExecution,
+    actual: &VMExecution,
+) {
+    near_metrics::inc_counter(&metrics::VM_DIFF_FUNCTION_CALL_TOTAL);
+    let differences = expected.differences(actual);
+    if differences.is_empty() {
+        return;
+    }
+    near_metrics::inc_counter(&metrics::VM_DIFF_FUNCTION_CALL_DIVERGED_TOTAL);
+    warn!(
+        target: "vm_diff",
+        "Function call {}.{} (action {}) diverged between VMs:\n{}",
+        account_id,
+        method_name,
+        action_hash,
+        differences.join("\n")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_runtime_fees::RuntimeFeesConfig;
+    use near_store::test_utils::create_tries;
+    use near_vm_logic::mocks::mock_external::MockedExternal;
+    use near_vm_logic::{VMConfig, VMContext};
+    use near_vm_runner::with_vm_variants;
+
+    use super::*;
+
+    const TEST_CONTRACT: &[u8] =
+        include_bytes!("../../near-vm-runner/tests/res/test_contract_rs.wasm");
+
+    fn create_context(input: &[u64]) -> VMContext {
+        VMContext {
+            current_account_id: "alice".to_string(),
+            signer_account_id: "bob".to_string(),
+            signer_account_pk: vec![0, 1, 2],
+            predecessor_account_id: "carol".to_string(),
+            input: input.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect(),
+            block_index: 10,
+            block_timestamp: 42,
+            epoch_height: 1,
+            account_balance: 10u128.pow(24),
+            account_locked_balance: 0,
+            storage_usage: 100,
+            storage_byte_cost: 0,
+            attached_deposit: 0,
+            prepaid_gas: 10u64.pow(14),
+            random_seed: vec![0, 1, 2],
+            is_view: false,
+            output_data_receivers: vec![],
+        }
+    }
+
+    /// Runs `write_key_value` of the test contract and writes the contract storage it produced
+    /// into `state_update`, the way `RuntimeExt` would.
+    fn write_key_value(
+        vm_kind: VMKind,
+        input: &[u64],
+        state_update: &mut TrieUpdate,
+    ) -> VMExecution {
+        VMExecution::isolated(vm_kind, state_update, |state_update| {
+            let mut external = MockedExternal::new();
+            let (outcome, error) = near_vm_runner::run_vm(
+                vec![],
+                TEST_CONTRACT,
+                b"write_key_value",
+                &mut external,
+                create_context(input),
+                &VMConfig::default(),
+                &RuntimeFeesConfig::default(),
+                &[],
+                vm_kind,
+                PROTOCOL_VERSION,
+                None,
+            );
+            for (key, value) in external.fake_trie {
+                state_update
+                    .set(TrieKey::ContractData { account_id: "alice".to_string(), key }, value);
+            }
+            (outcome, error, vec![])
+        })
+    }
+
+    #[test]
+    fn test_isolated_execution_reverts_state() {
+        let tries = create_tries();
+        let mut state_update = tries.new_trie_update(0, CryptoHash::default());
+        state_update.set(
+            TrieKey::ContractData { account_id: "alice".to_string(), key: b"existing".to_vec() },
+            b"value".to_vec(),
+        );
+        let initial_changes = state_update.prospective();
+
+        let execution = write_key_value(VMKind::Wasmer, &[10, 20], &mut state_update);
+        assert!(execution.error.is_none());
+        assert_eq!(state_update.prospective(), initial_changes);
+        assert_eq!(execution.state_changes.len(), initial_changes.len() + 1);
+    }
+
+    #[test]
+    fn test_differences_between_vms() {
+        with_vm_variants(|vm_kind: VMKind| {
+            let tries = create_tries();
+            let mut state_update = tries.new_trie_update(0, CryptoHash::default());
+            let expected = write_key_value(VMKind::Wasmer, &[10, 20], &mut state_update);
+
+            let same = write_key_value(vm_kind, &[10, 20], &mut state_update);
+            assert_eq!(expected.differences(&same), Vec::<String>::new());
+
+            // The other VM writing a different value is reported as a divergence.
+            let diverged = write_key_value(vm_kind, &[10, 21], &mut state_update);
+            let differences = expected.differences(&diverged);
+            assert_eq!(differences.len(), 1);
+            assert!(differences[0].starts_with("state changes"), "{:?}", differences);
+        });
+    }
+}
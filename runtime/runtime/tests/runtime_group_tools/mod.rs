@@ -58,6 +58,7 @@ impl StandaloneRuntime {
 
         let apply_state = ApplyState {
             block_index: 0,
+            shard_id: 0,
             last_block_hash: Default::default(),
             epoch_id: Default::default(),
             epoch_height: 0,
@@ -68,6 +69,8 @@ impl StandaloneRuntime {
             current_protocol_version: PROTOCOL_VERSION,
             config: Arc::new(runtime_config),
             cache: None,
+            differential_vm: None,
+            congestion_info: Default::default(),
         };
 
         Self {
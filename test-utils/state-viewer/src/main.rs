@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -12,12 +13,17 @@ use near_logger_utils::init_integration_logger;
 use near_network::peer_store::PeerStore;
 use near_primitives::block::BlockHeader;
 use near_primitives::hash::CryptoHash;
-use near_primitives::serialize::to_base;
+use near_primitives::serialize::{to_base, to_base64};
+use near_primitives::sharding::CongestionInfo;
 use near_primitives::state_record::StateRecord;
-use near_primitives::types::{BlockHeight, ChunkExtra, ShardId, StateRoot};
+use near_primitives::syncing::{get_num_state_parts, get_state_part_file_path};
+use near_primitives::types::{AccountId, BlockHeight, ChunkExtra, ShardId, StateRoot};
 use near_store::test_utils::create_test_store;
 use near_store::{create_store, Store, TrieIterator};
+use near_vm_logic::VMKind;
 use neard::{get_default_home, get_store_path, load_config, NearConfig, NightshadeRuntime};
+use node_runtime::adapter::ViewRuntimeAdapter;
+use node_runtime::state_viewer::DEFAULT_STORAGE_USAGE_PREFIX_LEN;
 use state_dump::state_dump;
 
 mod state_dump;
@@ -199,6 +205,7 @@ fn apply_block_at_height(
     near_config: &NearConfig,
     height: BlockHeight,
     shard_id: ShardId,
+    differential_vm: Option<VMKind>,
 ) {
     let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
     let runtime = NightshadeRuntime::new(
@@ -207,7 +214,8 @@ fn apply_block_at_height(
         &near_config.genesis,
         near_config.client_config.tracked_accounts.clone(),
         near_config.client_config.tracked_shards.clone(),
-    );
+    )
+    .with_differential_vm(differential_vm);
     let block_hash = chain_store.get_block_hash_by_height(height).unwrap();
     let block = chain_store.get_block(&block_hash).unwrap().clone();
     assert_eq!(block.chunks()[shard_id as usize].height_included(), height);
@@ -240,6 +248,10 @@ fn apply_block_at_height(
             chunk_inner.gas_limit,
             &block.header().challenges_result(),
             *block.header().random_value(),
+            &CongestionInfo::from_chunk_headers(
+                prev_block.chunks().iter(),
+                runtime.get_shard_layout(prev_block.header().epoch_id()).unwrap(),
+            ),
         )
         .unwrap();
     let (outcome_root, _) = ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
@@ -329,6 +341,97 @@ fn view_chain(
     }
 }
 
+fn view_storage_usage(
+    store: Arc<Store>,
+    home_dir: &Path,
+    near_config: &NearConfig,
+    account_id: AccountId,
+    prefix_len: usize,
+) {
+    let (runtime, state_roots, header) = load_trie(store, home_dir, near_config);
+    let shard_id = runtime.account_id_to_shard_id(&account_id, header.epoch_id()).unwrap();
+    let usage = runtime
+        .view_storage_usage(shard_id, state_roots[shard_id as usize], &account_id, prefix_len, None)
+        .unwrap();
+    println!("account {} at block height {}", account_id, header.height());
+    println!("recorded storage usage: {}", usage.storage_usage);
+    println!("account record: {}", usage.account);
+    println!("contract code: {}", usage.code);
+    println!("contract ABI: {}", usage.abi);
+    println!("access keys: {} in {} keys", usage.access_keys, usage.num_access_keys);
+    for prefix_usage in usage.data {
+        println!(
+            "data prefix {:?}: {} in {} keys",
+            to_base64(prefix_usage.prefix.as_ref()),
+            prefix_usage.storage_usage,
+            prefix_usage.num_keys
+        );
+    }
+}
+
+/// Dumps the state parts of all shards for the given epoch sync hash, or for the first block of
+/// the epoch of the head, in the layout expected by the state sync from `StatePartsSource`.
+fn dump_state_parts(
+    store: Arc<Store>,
+    home_dir: &Path,
+    near_config: &NearConfig,
+    sync_hash: Option<CryptoHash>,
+    output_dir: &Path,
+) {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let runtime = NightshadeRuntime::new(
+        &home_dir,
+        store,
+        &near_config.genesis,
+        near_config.client_config.tracked_accounts.clone(),
+        near_config.client_config.tracked_shards.clone(),
+    );
+    let sync_hash = match sync_hash {
+        Some(sync_hash) => sync_hash,
+        None => {
+            let mut header =
+                chain_store.get_block_header(&chain_store.head().unwrap().last_block_hash).unwrap();
+            let epoch_id = header.epoch_id().clone();
+            loop {
+                let prev_header = chain_store.get_block_header(header.prev_hash()).unwrap();
+                if prev_header.epoch_id() != &epoch_id {
+                    break *header.hash();
+                }
+                header = prev_header;
+            }
+        }
+    };
+    let sync_block = chain_store.get_block(&sync_hash).unwrap().clone();
+    let sync_prev_block = chain_store.get_block(sync_block.header().prev_hash()).unwrap().clone();
+    assert_ne!(
+        sync_block.header().epoch_id(),
+        sync_prev_block.header().epoch_id(),
+        "sync hash {} is not the first block of the epoch",
+        sync_hash
+    );
+    for (shard_id, chunk) in sync_prev_block.chunks().iter().enumerate() {
+        let shard_id = shard_id as ShardId;
+        let state_root = chunk.prev_state_root();
+        let state_root_node = runtime.get_state_root_node(shard_id, &state_root).unwrap();
+        let num_parts = get_num_state_parts(state_root_node.memory_usage);
+        for part_id in 0..num_parts {
+            let part =
+                runtime.obtain_state_part(shard_id, &state_root, part_id, num_parts).unwrap();
+            let path =
+                output_dir.join(get_state_part_file_path(&sync_hash, shard_id, part_id, num_parts));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &part).unwrap();
+        }
+        println!(
+            "Saved {} parts of state {} of shard {} into {}",
+            num_parts,
+            state_root,
+            shard_id,
+            output_dir.display()
+        );
+    }
+}
+
 fn check_block_chunk_existence(store: Arc<Store>, near_config: &NearConfig) {
     let genesis_height = near_config.genesis.config.genesis_height;
     let mut chain_store = ChainStore::new(store.clone(), genesis_height);
@@ -428,6 +531,13 @@ fn main() {
                         .help("Id of the shard to apply")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("differential_vm")
+                        .long("differential_vm")
+                        .possible_values(&["wasmer", "wasmtime"])
+                        .help("Also execute function calls on this VM and report differences")
+                        .takes_value(true),
+                )
                 .help("apply block at some height for shard"),
         )
         .subcommand(
@@ -452,6 +562,40 @@ fn main() {
                 )
                 .help("View head of the storage"),
         )
+        .subcommand(
+            SubCommand::with_name("storage_usage")
+                .arg(
+                    Arg::with_name("account_id")
+                        .long("account_id")
+                        .required(true)
+                        .help("Account to compute the storage usage breakdown for")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("prefix_len")
+                        .long("prefix_len")
+                        .help("Number of leading key bytes used to group the contract data")
+                        .takes_value(true),
+                )
+                .help("Storage usage of the account grouped by contract data key prefix"),
+        )
+        .subcommand(
+            SubCommand::with_name("dump_state_parts")
+                .arg(
+                    Arg::with_name("sync_hash")
+                        .long("sync_hash")
+                        .help("Hash of the first block of the epoch, defaults to the current epoch")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output_dir")
+                        .long("output_dir")
+                        .required(true)
+                        .help("Directory to save the state parts into")
+                        .takes_value(true),
+                )
+                .help("Dump the state parts for state sync from a directory or HTTP server"),
+        )
         .subcommand(
             SubCommand::with_name("check_block")
                 .help("Check whether the node has all the blocks up to its head"),
@@ -523,7 +667,12 @@ fn main() {
             let height = args.value_of("height").map(|s| s.parse::<u64>().unwrap()).unwrap();
             let shard_id =
                 args.value_of("shard_id").map(|s| s.parse::<u64>().unwrap()).unwrap_or_default();
-            apply_block_at_height(store, home_dir, &near_config, height, shard_id);
+            let differential_vm = args.value_of("differential_vm").map(|vm| match vm {
+                "wasmer" => VMKind::Wasmer,
+                "wasmtime" => VMKind::Wasmtime,
+                _ => unreachable!(),
+            });
+            apply_block_at_height(store, home_dir, &near_config, height, shard_id, differential_vm);
         }
         ("view_chain", Some(args)) => {
             let height = args.value_of("height").map(|s| s.parse::<u64>().unwrap());
@@ -531,6 +680,19 @@ fn main() {
             let view_chunks = args.is_present("chunk");
             view_chain(store, &near_config, height, view_block, view_chunks);
         }
+        ("storage_usage", Some(args)) => {
+            let account_id = args.value_of("account_id").map(AccountId::from).unwrap();
+            let prefix_len = args
+                .value_of("prefix_len")
+                .map(|s| s.parse::<usize>().unwrap())
+                .unwrap_or(DEFAULT_STORAGE_USAGE_PREFIX_LEN);
+            view_storage_usage(store, home_dir, &near_config, account_id, prefix_len);
+        }
+        ("dump_state_parts", Some(args)) => {
+            let sync_hash = args.value_of("sync_hash").map(|s| CryptoHash::try_from(s).unwrap());
+            let output_dir = args.value_of("output_dir").map(Path::new).unwrap();
+            dump_state_parts(store, home_dir, &near_config, sync_hash, output_dir);
+        }
         ("check_block", Some(_)) => {
             check_block_chunk_existence(store, &near_config);
         }
@@ -126,6 +126,7 @@ impl RuntimeUser {
     fn apply_state(&self) -> ApplyState {
         ApplyState {
             block_index: 0,
+            shard_id: 0,
             last_block_hash: Default::default(),
             block_timestamp: 0,
             epoch_height: 0,
@@ -136,6 +137,8 @@ impl RuntimeUser {
             current_protocol_version: PROTOCOL_VERSION,
             config: self.runtime_config.clone(),
             cache: None,
+            differential_vm: None,
+            congestion_info: Default::default(),
         }
     }
 
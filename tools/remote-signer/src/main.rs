@@ -0,0 +1,91 @@
+//! Reference remote signer. Keeps the validator key in this process and signs the requests of a
+//! node that has `remote_signer_socket` set in its config, so that the secret key never gets into
+//! the memory of the node. Every request is signed as is, the node is responsible for not asking
+//! to sign anything conflicting.
+
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use clap::{App, Arg};
+use log::{info, warn, LevelFilter};
+
+use near_primitives::remote_signer::serve_remote_signer_connection;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+
+/// Creates the directory of the socket, or checks an existing one, so that only the user running
+/// the signer can get to the socket. Otherwise other users could connect in the moment between
+/// binding the socket and restricting its permissions.
+fn prepare_socket_dir(dir: &Path) {
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .expect("Failed to create the socket directory");
+    }
+    let mode = fs::metadata(dir).expect("Failed to read the socket directory").permissions().mode();
+    if mode & 0o077 != 0 {
+        panic!(
+            "Socket directory {} must only be accessible by its owner, run `chmod 700` on it",
+            dir.display()
+        );
+    }
+}
+
+fn main() {
+    env_logger::Builder::new().filter(None, LevelFilter::Info).init();
+    let matches = App::new("NEAR remote signer")
+        .about("Signs blocks, chunks, approvals and challenges for a validator node over a Unix socket")
+        .arg(
+            Arg::with_name("key-file")
+                .long("key-file")
+                .required(true)
+                .takes_value(true)
+                .help("Validator key file"),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .long("socket")
+                .required(true)
+                .takes_value(true)
+                .help("Path of the Unix socket to listen on, in a directory only accessible by this user"),
+        )
+        .get_matches();
+
+    let signer: Arc<dyn ValidatorSigner> = Arc::new(InMemoryValidatorSigner::from_file(Path::new(
+        matches.value_of("key-file").unwrap(),
+    )));
+    let socket_path = Path::new(matches.value_of("socket").unwrap());
+    let socket_dir = match socket_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    prepare_socket_dir(socket_dir);
+    if socket_path.exists() {
+        fs::remove_file(socket_path).expect("Failed to remove the old socket");
+    }
+    let listener = UnixListener::bind(socket_path).expect("Failed to listen on the socket");
+    // Only the user running the signer may connect to it.
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))
+        .expect("Failed to set the socket permissions");
+    info!("Signing for {} on {}", signer.validator_id(), socket_path.display());
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                info!("Node connected");
+                let signer = signer.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve_remote_signer_connection(&*signer, stream) {
+                        warn!("Connection to the node failed: {}", err);
+                    }
+                });
+            }
+            Err(err) => warn!("Failed to accept a connection: {}", err),
+        }
+    }
+}